@@ -0,0 +1,54 @@
+//! Throughput of `scan_directory` on a large synthetic tree, the one
+//! `ss bench generate` writes with default options, and the memory the
+//! resulting index takes up.
+//!
+//! Run with `cargo bench --bench scan`; set `SCAN_BENCH_FILES` to change the
+//! tree size (default 100000 files).
+
+use sonic_search::fixture::{self, FixtureOptions};
+use sonic_search::{Index, memory, scanner};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+
+fn main() {
+    let files: usize = std::env::var("SCAN_BENCH_FILES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(100_000);
+    let tree = tempfile::tempdir().expect("create temporary tree");
+    let options = FixtureOptions {
+        files,
+        ..Default::default()
+    };
+    fixture::generate(tree.path(), &options).expect("generate tree");
+
+    // The first walk warms the page cache and is left out of the timings
+    scanner::scan_directory(tree.path()).expect("scan tree");
+    let mut timings: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let scan = scanner::scan_directory(tree.path()).expect("scan tree");
+            assert_eq!(scan.file_count, files);
+            start.elapsed()
+        })
+        .collect();
+    timings.sort();
+    let median = timings[RUNS / 2];
+    println!(
+        "scan {} files: median {:.1} ms over {} runs, {:.0} files/s",
+        files,
+        median.as_secs_f64() * 1000.0,
+        RUNS,
+        files as f64 / median.as_secs_f64()
+    );
+
+    let index = Index::from_scan(scanner::scan_directory(tree.path()).expect("scan tree"));
+    let peak = memory::peak_rss().map_or("unknown".to_string(), scanner::format_size);
+    println!(
+        "index in memory: {} ({} bytes per file), peak RSS {}",
+        scanner::format_size(index.heap_size() as u64),
+        index.heap_size() / files.max(1),
+        peak
+    );
+}
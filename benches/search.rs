@@ -0,0 +1,50 @@
+//! Query time of a broad fuzzy search over a large in-memory index, ranking
+//! every match against keeping only the best few with `--limit`.
+//!
+//! Run with `cargo bench --bench search`; set `SEARCH_BENCH_FILES` to change
+//! the index size (default 1000000 files).
+
+use sonic_search::SearchOptions;
+use sonic_search::fixture::NameStyle;
+use sonic_search::testing::IndexBuilder;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+/// Matches nearly every generated name
+const QUERY: &str = "e";
+
+fn main() {
+    let files: usize = std::env::var("SEARCH_BENCH_FILES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1_000_000);
+    let index = IndexBuilder::new("/bench")
+        .random_files(files, NameStyle::Mixed)
+        .build();
+
+    for limit in [None, Some(20)] {
+        let options = SearchOptions {
+            limit,
+            ..Default::default()
+        };
+        let mut matches = 0;
+        let mut timings: Vec<Duration> = (0..RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                matches = index.search(QUERY, &options).expect("search").len();
+                start.elapsed()
+            })
+            .collect();
+        timings.sort();
+        let limit = limit.map_or("none".to_string(), |limit| limit.to_string());
+        println!(
+            "search {:?} in {} entries, limit {}: {} results, median {:.1} ms over {} runs",
+            QUERY,
+            index.entries.len(),
+            limit,
+            matches,
+            timings[RUNS / 2].as_secs_f64() * 1000.0,
+            RUNS
+        );
+    }
+}
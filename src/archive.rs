@@ -0,0 +1,321 @@
+use crate::normalize;
+use crate::scanner::FileEntry;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Separates the path of an archive from that of a file inside it, as in
+/// `data.zip!/2024/jan.csv`
+pub const SEPARATOR: &str = "!/";
+
+/// Archive formats this build reads; zip and gzip need the `archives` feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Tar,
+    #[cfg(feature = "archives")]
+    TarGz,
+    #[cfg(feature = "archives")]
+    Zip,
+}
+
+fn format(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+    match () {
+        _ if name.ends_with(".tar") => Some(Format::Tar),
+        #[cfg(feature = "archives")]
+        _ if name.ends_with(".tar.gz") || name.ends_with(".tgz") => Some(Format::TarGz),
+        #[cfg(feature = "archives")]
+        _ if name.ends_with(".zip") => Some(Format::Zip),
+        _ => None,
+    }
+}
+
+/// Whether `path` names an archive whose files `scan --archives` indexes
+pub fn is_archive(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+/// The path of the file `inner` inside the archive at `archive`
+pub fn member_path(archive: &Path, inner: &str) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(SEPARATOR);
+    path.push(inner);
+    PathBuf::from(path)
+}
+
+/// The archive and the path inside it that `path` names, when it's the
+/// path of a file inside an archive
+pub fn split(path: &Path) -> Option<(&Path, &str)> {
+    let text = path.to_str()?;
+    text.match_indices(SEPARATOR).find_map(|(i, _)| {
+        let archive = Path::new(&text[..i]);
+        is_archive(archive).then(|| (archive, &text[i + SEPARATOR.len()..]))
+    })
+}
+
+/// The contents of the file at `path`, which may be inside an archive.
+///
+/// Reading from a tar archive means reading it up to that file, and from a
+/// gzipped one decompressing it that far too.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    read_within(path, |_| false).map(Option::unwrap_or_default)
+}
+
+/// Like [`read`], but `None` for a file `too_large` says to skip. Files in
+/// archives are told by the size the archive lists, before any of them is
+/// decompressed.
+pub fn read_within(path: &Path, too_large: impl Fn(u64) -> bool) -> Result<Option<Vec<u8>>> {
+    let Some((archive, inner)) = split(path) else {
+        let len = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len();
+        if too_large(len) {
+            return Ok(None);
+        }
+        let contents =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        return Ok(Some(contents));
+    };
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let missing = || anyhow::anyhow!("No {} in {}", inner, archive.display());
+    match format(archive) {
+        Some(Format::Tar) => {
+            let archive = tar::Archive::new(BufReader::new(file));
+            read_tar(archive, inner, too_large)?.ok_or_else(missing)
+        }
+        #[cfg(feature = "archives")]
+        Some(Format::TarGz) => {
+            let decoder = flate2::read::GzDecoder::new(BufReader::new(file));
+            read_tar(tar::Archive::new(decoder), inner, too_large)?.ok_or_else(missing)
+        }
+        #[cfg(feature = "archives")]
+        Some(Format::Zip) => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))?;
+            let mut member = zip.by_name(inner).map_err(|e| match e {
+                zip::result::ZipError::FileNotFound => missing(),
+                e => e.into(),
+            })?;
+            if too_large(member.size()) {
+                return Ok(None);
+            }
+            let mut contents = Vec::with_capacity(member.size() as usize);
+            member.read_to_end(&mut contents)?;
+            Ok(Some(contents))
+        }
+        None => Err(missing()),
+    }
+}
+
+/// The member `inner` of a tar archive: `None` if it isn't there, and
+/// `Some(None)` if it's one `too_large` says to skip
+fn read_tar(
+    mut archive: tar::Archive<impl Read>,
+    inner: &str,
+    too_large: impl Fn(u64) -> bool,
+) -> Result<Option<Option<Vec<u8>>>> {
+    for member in archive.entries()? {
+        let mut member = member?;
+        if member.path()?.to_string_lossy() == inner {
+            if too_large(member.size()) {
+                return Ok(Some(None));
+            }
+            let mut contents = Vec::with_capacity(member.size() as usize);
+            member.read_to_end(&mut contents)?;
+            return Ok(Some(Some(contents)));
+        }
+    }
+    Ok(None)
+}
+
+/// Entries for the files inside the archive at `path`, named by
+/// [`member_path`], skipping those `too_large` says to
+pub fn members(path: &Path, too_large: impl Fn(u64) -> bool) -> Result<Vec<FileEntry>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut members = Vec::new();
+    let mut add = |inner: &str, size: u64, modified: Option<SystemTime>| {
+        if !too_large(size) {
+            members.push(member_entry(path, inner, size, modified));
+        }
+    };
+    match format(path) {
+        Some(Format::Tar) => list_tar(tar::Archive::new(BufReader::new(file)), &mut add)?,
+        #[cfg(feature = "archives")]
+        Some(Format::TarGz) => {
+            let decoder = flate2::read::GzDecoder::new(BufReader::new(file));
+            list_tar(tar::Archive::new(decoder), &mut add)?
+        }
+        #[cfg(feature = "archives")]
+        Some(Format::Zip) => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))?;
+            for i in 0..zip.len() {
+                let member = zip.by_index_raw(i)?;
+                let Ok(name) = member.name() else {
+                    continue;
+                };
+                // Names climbing out of the archive, like `../x`, can't be read back
+                if !member.is_file() || member.enclosed_name().is_none() {
+                    continue;
+                }
+                let modified = member.last_modified().and_then(zip_time);
+                add(&name, member.size(), modified);
+            }
+        }
+        None => anyhow::bail!("Not an archive: {}", path.display()),
+    }
+    Ok(members)
+}
+
+fn list_tar(
+    mut archive: tar::Archive<impl Read>,
+    add: &mut impl FnMut(&str, u64, Option<SystemTime>),
+) -> Result<()> {
+    for member in archive.entries()? {
+        let member = member?;
+        let header = member.header();
+        if !header.entry_type().is_file() {
+            continue;
+        }
+        let modified = header
+            .mtime()
+            .ok()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        add(&member.path()?.to_string_lossy(), header.size()?, modified);
+    }
+    Ok(())
+}
+
+/// A zip timestamp, which is in local time
+#[cfg(feature = "archives")]
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    use chrono::TimeZone;
+    let naive = chrono::NaiveDate::from_ymd_opt(
+        time.year().into(),
+        time.month().into(),
+        time.day().into(),
+    )?
+    .and_hms_opt(
+        time.hour().into(),
+        time.minute().into(),
+        time.second().into(),
+    )?;
+    Some(chrono::Local.from_local_datetime(&naive).earliest()?.into())
+}
+
+fn member_entry(archive: &Path, inner: &str, size: u64, modified: Option<SystemTime>) -> FileEntry {
+    let name = inner.rsplit('/').next().unwrap_or(inner).to_string();
+    FileEntry {
+        path: member_path(archive, inner),
+        folded_name: normalize::folded_name(&name),
+        name,
+        size,
+        is_dir: false,
+        is_symlink: false,
+        modified,
+        project: None,
+        owners: None,
+        vendored: false,
+        hash: None,
+        repo: None,
+        git_status: Default::default(),
+        mime: None,
+        attributes: Default::default(),
+        device: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_bytes(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mtime(1_700_000_000);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_paths() {
+        let path = member_path(Path::new("/data/q3!.tar"), "2024/jan.csv");
+        assert_eq!(path, Path::new("/data/q3!.tar!/2024/jan.csv"));
+        assert_eq!(
+            split(&path),
+            Some((Path::new("/data/q3!.tar"), "2024/jan.csv"))
+        );
+        assert_eq!(split(Path::new("/data/notes!/a.txt")), None);
+        assert!(is_archive(Path::new("backup.TAR")));
+        assert!(!is_archive(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn test_tar_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("sales.tar");
+        std::fs::write(
+            &archive,
+            tar_bytes(&[
+                ("2024/jan.csv", "region,total\n"),
+                ("big.bin", "0123456789abcdefghij"),
+            ]),
+        )
+        .unwrap();
+
+        let members = members(&archive, |size| size > 16).unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].name, "jan.csv");
+        assert_eq!(members[0].size, 13);
+        assert_eq!(
+            members[0].modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(read(&members[0].path).unwrap(), b"region,total\n");
+        assert!(read(&member_path(&archive, "missing.txt")).is_err());
+        let big = member_path(&archive, "big.bin");
+        assert_eq!(read_within(&big, |size| size > 16).unwrap(), None);
+        assert_eq!(read_within(&big, |_| false).unwrap().unwrap().len(), 20);
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn test_zip_and_gzip_members() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let zipped = dir.path().join("dataset.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zipped).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        writer.add_directory("docs/", options).unwrap();
+        writer.start_file("docs/readme.md", options).unwrap();
+        writer.write_all(b"# Dataset\n").unwrap();
+        writer.finish().unwrap();
+
+        let members = members(&zipped, |_| false).unwrap();
+        let names: Vec<&str> = members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["readme.md"]);
+        assert_eq!(read(&members[0].path).unwrap(), b"# Dataset\n");
+
+        let gzipped = dir.path().join("logs.tar.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&gzipped).unwrap(),
+            flate2::Compression::fast(),
+        );
+        encoder
+            .write_all(&tar_bytes(&[("app.log", "ERROR disk full\n")]))
+            .unwrap();
+        encoder.finish().unwrap();
+        let path = member_path(&gzipped, "app.log");
+        assert_eq!(super::members(&gzipped, |_| false).unwrap()[0].path, path);
+        assert_eq!(read(&path).unwrap(), b"ERROR disk full\n");
+    }
+}
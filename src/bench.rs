@@ -0,0 +1,275 @@
+//! `ss bench run`: how long scanning, loading and searching take on one
+//! machine and filesystem, measured the same way everywhere so reports
+//! from different setups can be set side by side.
+//!
+//! Every measurement is taken `runs` times after one untimed run that warms
+//! the page cache, and reported as its median, fastest and slowest.
+
+use crate::fixture::{self, FixtureOptions};
+use crate::grep::GrepOptions;
+use crate::index::{Index, MappedSnapshot};
+use crate::mounts::MountTable;
+use crate::scanner;
+use crate::search::{Filters, SearchOptions};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// What [`run`] measures
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Timed runs of each measurement
+    pub runs: usize,
+    /// Walk the indexed root again, as `ss scan` would
+    pub scan: bool,
+    /// Name searches to time; when empty, a broad query and the name of
+    /// one indexed file
+    pub queries: Vec<String>,
+    /// Content searches to time
+    pub grep: Vec<String>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            runs: 5,
+            scan: true,
+            queries: Vec::new(),
+            grep: Vec::new(),
+        }
+    }
+}
+
+/// How long something took over several runs, in microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Timing {
+    pub runs: usize,
+    pub median_us: u64,
+    pub min_us: u64,
+    pub max_us: u64,
+}
+
+impl Timing {
+    /// Time `runs` calls of `work`, after one untimed call
+    fn measure<T>(runs: usize, mut work: impl FnMut() -> Result<T>) -> Result<(Self, T)> {
+        let mut last = work()?;
+        let mut timings: Vec<Duration> = Vec::with_capacity(runs);
+        for _ in 0..runs.max(1) {
+            let start = Instant::now();
+            last = work()?;
+            timings.push(start.elapsed());
+        }
+        timings.sort();
+        let micros = |duration: Duration| duration.as_micros() as u64;
+        let timing = Timing {
+            runs: timings.len(),
+            median_us: micros(timings[timings.len() / 2]),
+            min_us: micros(timings[0]),
+            max_us: micros(timings[timings.len() - 1]),
+        };
+        Ok((timing, last))
+    }
+
+    pub fn median(&self) -> Duration {
+        Duration::from_micros(self.median_us)
+    }
+
+    /// How many of `count` things a second the median run got through
+    pub fn per_second(&self, count: usize) -> f64 {
+        count as f64 / self.median().as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/// A walk of the indexed root
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanTiming {
+    pub files: usize,
+    pub directories: usize,
+    pub timing: Timing,
+}
+
+/// A name or content search
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryTiming {
+    pub query: String,
+    /// Matching files
+    pub results: usize,
+    pub timing: Timing,
+}
+
+/// Everything [`run`] measured, and the setup it measured it on
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub threads: usize,
+    /// Type of the filesystem the root is on, where the platform says
+    pub filesystem: Option<String>,
+    pub index_dir: PathBuf,
+    pub root: PathBuf,
+    pub generation: u64,
+    pub entries: usize,
+    pub scan: Option<ScanTiming>,
+    /// Loading the whole generation, with its content index if it has one
+    pub load: Timing,
+    /// Opening the generation's entries mapped, as `find` does
+    pub open_mapped: Timing,
+    pub queries: Vec<QueryTiming>,
+    pub grep: Vec<QueryTiming>,
+}
+
+/// Measure the index stored in `index_dir` and the tree it covers
+pub fn run(index_dir: &Path, options: &BenchOptions) -> Result<Report> {
+    let runs = options.runs;
+    let (load, index) = Timing::measure(runs, || {
+        let mut index = Index::load(index_dir)?;
+        index.load_content(index_dir)?;
+        Ok(index)
+    })?;
+    let (open_mapped, _) = Timing::measure(runs, || Ok(MappedSnapshot::open(index_dir)?))?;
+    let root = index.meta.root.clone();
+
+    let scan = match options.scan {
+        true => {
+            let scan_options = &index.meta.scan_options;
+            let (timing, scan) = Timing::measure(runs, || {
+                Ok(scanner::scan_directory_with(
+                    &root,
+                    scan_options,
+                    None,
+                    None,
+                )?)
+            })?;
+            Some(ScanTiming {
+                files: scan.file_count,
+                directories: scan.dir_count,
+                timing,
+            })
+        }
+        false => None,
+    };
+
+    let queries = match options.queries.is_empty() {
+        true => default_queries(&index),
+        false => options.queries.clone(),
+    };
+    let search_options = SearchOptions::default();
+    let queries = queries
+        .into_iter()
+        .map(|query| {
+            let (timing, results) =
+                Timing::measure(runs, || Ok(index.search(&query, &search_options)?))?;
+            Ok(QueryTiming {
+                query,
+                results: results.len(),
+                timing,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let grep_options = GrepOptions::default();
+    let grep = options
+        .grep
+        .iter()
+        .map(|pattern| {
+            let (timing, results) = Timing::measure(runs, || {
+                Ok(index.grep(pattern, &grep_options, &Filters::default())?)
+            })?;
+            Ok(QueryTiming {
+                query: pattern.clone(),
+                results: results.len(),
+                timing,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Report {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        threads: rayon::current_num_threads(),
+        filesystem: MountTable::read()
+            .containing(&root)
+            .map(|mount| mount.fstype.clone()),
+        index_dir: index_dir.to_path_buf(),
+        root,
+        generation: index.meta.generation,
+        entries: index.entries.len(),
+        scan,
+        load,
+        open_mapped,
+        queries,
+        grep,
+    })
+}
+
+/// A single letter nearly every name contains, and the name of the file
+/// halfway through the index, so reports from different trees stay alike
+fn default_queries(index: &Index) -> Vec<String> {
+    let mut queries = vec!["e".to_string()];
+    let files: Vec<_> = index.entries.iter().filter(|entry| !entry.is_dir).collect();
+    if let Some(stem) = files
+        .get(files.len() / 2)
+        .and_then(|entry| entry.path.file_stem())
+    {
+        queries.push(stem.to_string_lossy().into_owned());
+    }
+    queries
+}
+
+/// A synthetic tree and an index of it, both removed when dropped
+pub struct Synthetic {
+    _tree: tempfile::TempDir,
+    index_dir: tempfile::TempDir,
+}
+
+impl Synthetic {
+    /// Generate a tree of `files` files as `ss bench generate` does with
+    /// default options, and scan it
+    pub fn generate(files: usize) -> Result<Self> {
+        let tree = tempfile::tempdir()?;
+        let options = FixtureOptions {
+            files,
+            ..Default::default()
+        };
+        fixture::generate(tree.path(), &options)?;
+        let index_dir = tempfile::tempdir()?;
+        Index::from_scan(scanner::scan_directory(tree.path())?).save(index_dir.path())?;
+        Ok(Synthetic {
+            _tree: tree,
+            index_dir,
+        })
+    }
+
+    pub fn index_dir(&self) -> &Path {
+        self.index_dir.path()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_on_synthetic_tree() {
+        let synthetic = Synthetic::generate(200).unwrap();
+        let options = BenchOptions {
+            runs: 3,
+            grep: vec!["anything".to_string()],
+            ..Default::default()
+        };
+        let report = run(synthetic.index_dir(), &options).unwrap();
+        assert_eq!(report.load.runs, 3);
+        assert!(report.load.min_us <= report.load.median_us);
+        assert!(report.load.median_us <= report.load.max_us);
+        assert_eq!(report.scan.unwrap().files, 200);
+        assert_eq!(report.queries.len(), 2);
+        assert!(
+            report.queries[1].results >= 1,
+            "an indexed file's own name finds it"
+        );
+        assert_eq!(report.grep[0].results, 0);
+    }
+}
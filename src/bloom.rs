@@ -0,0 +1,212 @@
+use crate::cjk;
+use crate::engine::Engine;
+use crate::normalize;
+use crate::query::{Field, Query};
+use crate::search::{EntryFields, SearchOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Share of lookups for absent keys that a filter answers "maybe" to
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// A Bloom filter over 64-bit key hashes: a compact set that can say a key
+/// is certainly absent, or only that it may be present
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bloom {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl Bloom {
+    /// A filter holding `keys`, sized for about [`FALSE_POSITIVE_RATE`]
+    pub fn from_keys(keys: &HashSet<u64>) -> Self {
+        let count = keys.len().max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-count * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as usize;
+        let hashes = ((bits as f64 / count) * ln2).round().clamp(1.0, 16.0) as u32;
+        let mut bloom = Bloom {
+            bits: vec![0; bits.div_ceil(64).max(1)],
+            hashes,
+        };
+        for &key in keys {
+            for bit in bloom.positions(key) {
+                bloom.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        bloom
+    }
+
+    /// Whether `key` may have been added; `false` is always right
+    pub fn contains(&self, key: u64) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Bits `key` sets, by double hashing
+    fn positions(&self, key: u64) -> impl Iterator<Item = usize> + use<> {
+        let len = (self.bits.len() * 64) as u64;
+        let step = mix(key) | 1;
+        (0..u64::from(self.hashes))
+            .map(move |i| (key.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+}
+
+/// The FNV-1a hash of `tag` followed by `text`, which unlike the standard
+/// library's hasher stays the same across builds
+fn key(tag: u8, text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in std::iter::once(&tag).chain(text.as_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    mix(hash)
+}
+
+/// Spread the bits of `hash` with the splitmix64 finalizer
+fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+const CHAR: u8 = b'c';
+const PAIR: u8 = b'p';
+const TRIGRAM: u8 = b't';
+const EXTENSION: u8 = b'e';
+
+/// What the names of one index generation contain, so a query that can't
+/// match any of them skips the generation without reading its entries.
+///
+/// It records, lowercased, the characters of each name, every pair of them
+/// appearing in that order, its trigrams and the extensions in use. A fuzzy
+/// term can only match a name holding each pair of its adjacent letters in
+/// order, and `name:` text one holding its trigrams.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameFilter {
+    bloom: Bloom,
+}
+
+impl NameFilter {
+    /// Build the filter for `entries`
+    pub fn build<'a, E: EntryFields + 'a>(entries: impl IntoIterator<Item = &'a E>) -> Self {
+        let _span = crate::profile::span("bloom");
+        let mut keys = HashSet::new();
+        for entry in entries {
+            // Names are compared folded by default and in NFC with exact accents
+            let folded = entry.folded_name().unwrap_or(entry.name());
+            let nfc = normalize::nfc(entry.name());
+            for name in [folded, &nfc] {
+                name_keys(&name.to_lowercase(), &mut keys);
+            }
+            if let Some(ext) = entry.path().extension() {
+                keys.insert(key(EXTENSION, &ext.to_string_lossy().to_lowercase()));
+            }
+        }
+        NameFilter {
+            bloom: Bloom::from_keys(&keys),
+        }
+    }
+
+    /// Whether `query` may match some entry under `options`. `false` means
+    /// it certainly matches none.
+    pub fn may_match(&self, query: &Query, options: &SearchOptions) -> bool {
+        match query {
+            // A regex's text says little about the names it matches
+            Query::Term(_) if options.engine == Engine::Regex => true,
+            Query::Term(term) if !options.full_path && !term.contains('/') => {
+                let term = options.normalize(term).to_lowercase();
+                let chars: Vec<char> = term.chars().collect();
+                // CJK terms match by adjacent pairs, and needn't have all of them
+                let ordered = cjk::has_cjk(&term)
+                    || chars
+                        .windows(2)
+                        .all(|pair| self.has(PAIR, &pair.iter().collect::<String>()));
+                chars.iter().all(|c| self.has(CHAR, &c.to_string())) && ordered
+            }
+            Query::Field(Field::Name(text)) => {
+                let text = options.normalize(text).to_lowercase();
+                let chars: Vec<char> = text.chars().collect();
+                chars.iter().all(|c| self.has(CHAR, &c.to_string()))
+                    && chars
+                        .windows(3)
+                        .all(|trigram| self.has(TRIGRAM, &trigram.iter().collect::<String>()))
+            }
+            Query::Field(Field::Extension(ext)) => self.has(EXTENSION, ext),
+            Query::And(parts) => parts.iter().all(|part| self.may_match(part, options)),
+            Query::Or(parts) => parts.iter().any(|part| self.may_match(part, options)),
+            // Paths, types, sizes and negations aren't recorded
+            _ => true,
+        }
+    }
+
+    fn has(&self, tag: u8, text: &str) -> bool {
+        self.bloom.contains(key(tag, text))
+    }
+}
+
+/// Add the keys of one lowercased name to `keys`
+fn name_keys(name: &str, keys: &mut HashSet<u64>) {
+    let chars: Vec<char> = name.chars().collect();
+    let mut seen = HashSet::new();
+    for (i, &first) in chars.iter().enumerate() {
+        if !seen.insert(first) {
+            // Every pair starting here also starts at its first occurrence
+            continue;
+        }
+        keys.insert(key(CHAR, &first.to_string()));
+        let mut following = HashSet::new();
+        for &second in &chars[i + 1..] {
+            if following.insert(second) {
+                keys.insert(key(PAIR, &String::from_iter([first, second])));
+            }
+        }
+    }
+    for trigram in chars.windows(3) {
+        keys.insert(key(TRIGRAM, &trigram.iter().collect::<String>()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::FileEntry;
+    use std::path::PathBuf;
+
+    fn entry(name: &str) -> FileEntry {
+        FileEntry::new(PathBuf::from("/root").join(name), false, 0, None)
+    }
+
+    #[test]
+    fn test_bloom_has_no_false_negatives() {
+        let keys: HashSet<u64> = (0..1000).map(|i| key(b'x', &i.to_string())).collect();
+        let bloom = Bloom::from_keys(&keys);
+        assert!(keys.iter().all(|&k| bloom.contains(k)));
+        let false_positives = (1000..11000)
+            .filter(|i| bloom.contains(key(b'x', &i.to_string())))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let filter = NameFilter::build(&[entry("Budget-2024.xlsx"), entry("résumé.pdf")]);
+        let may = |query: &str| {
+            filter.may_match(&Query::parse(query).unwrap(), &SearchOptions::default())
+        };
+        assert!(may("budget"));
+        assert!(may("BDG24"));
+        assert!(may("resume"));
+        assert!(may("ext:pdf name:get-"));
+        assert!(may("zzz OR bud"));
+        assert!(may("NOT zzz"));
+        assert!(may("path:anything"));
+        // Letters out of order, one no name holds, or an unused extension
+        assert!(!may("tegdub"));
+        assert!(!may("budgetq"));
+        assert!(!may("ext:rs"));
+        assert!(!may("name:dgb"));
+        assert!(!may("budget zzz"));
+    }
+}
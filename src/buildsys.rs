@@ -0,0 +1,170 @@
+//! Output directories of build systems, left out of scans: `target/` next
+//! to a `Cargo.toml`, `build/` next to a `CMakeLists.txt` or a Gradle build
+//! script, and Bazel's `bazel-*` links next to its workspace file.
+//!
+//! Only directories beside a build file count, so a `build/` holding
+//! sources of its own is still indexed.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A build system whose output directories can be told apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildSystem {
+    Cargo,
+    CMake,
+    Bazel,
+    Gradle,
+}
+
+impl BuildSystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildSystem::Cargo => "Cargo",
+            BuildSystem::CMake => "CMake",
+            BuildSystem::Bazel => "Bazel",
+            BuildSystem::Gradle => "Gradle",
+        }
+    }
+}
+
+/// Each build system, the files that mark a directory as built by it, and
+/// the names of the outputs it writes there; `*` at the end of a name
+/// matches any rest. CMake's `build/` is only a convention, but it's the
+/// one nearly every project follows.
+const KNOWN: &[(BuildSystem, &[&str], &[&str])] = &[
+    (BuildSystem::Cargo, &["Cargo.toml"], &["target"]),
+    (
+        BuildSystem::CMake,
+        &["CMakeLists.txt"],
+        &["build", "cmake-build-*"],
+    ),
+    (
+        BuildSystem::Bazel,
+        &["WORKSPACE", "WORKSPACE.bazel", "MODULE.bazel"],
+        &["bazel-*"],
+    ),
+    (
+        BuildSystem::Gradle,
+        &[
+            "build.gradle",
+            "build.gradle.kts",
+            "settings.gradle",
+            "settings.gradle.kts",
+        ],
+        &["build", ".gradle"],
+    ),
+];
+
+/// Which build systems' outputs a scan leaves out; all of them unless
+/// turned off, e.g. with `[skip_build_outputs] gradle = false` in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildSystems {
+    pub cargo: bool,
+    pub cmake: bool,
+    pub bazel: bool,
+    pub gradle: bool,
+}
+
+impl Default for BuildSystems {
+    fn default() -> Self {
+        BuildSystems {
+            cargo: true,
+            cmake: true,
+            bazel: true,
+            gradle: true,
+        }
+    }
+}
+
+impl BuildSystems {
+    /// Leave every build output in
+    pub const NONE: BuildSystems = BuildSystems {
+        cargo: false,
+        cmake: false,
+        bazel: false,
+        gradle: false,
+    };
+
+    pub fn contains(&self, system: BuildSystem) -> bool {
+        match system {
+            BuildSystem::Cargo => self.cargo,
+            BuildSystem::CMake => self.cmake,
+            BuildSystem::Bazel => self.bazel,
+            BuildSystem::Gradle => self.gradle,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// The build system among these that wrote the directory at `dir`, if
+    /// it's the output of one. Looks for the build file next to it, so it
+    /// only touches the disk for directories with an output's name.
+    pub fn output_of(&self, dir: &Path) -> Option<BuildSystem> {
+        let name = dir.file_name()?.to_str()?;
+        let parent = dir.parent()?;
+        KNOWN
+            .iter()
+            .filter(|(system, _, outputs)| {
+                self.contains(*system) && outputs.iter().any(|output| matches(output, name))
+            })
+            .find(|(_, markers, _)| markers.iter().any(|marker| parent.join(marker).is_file()))
+            .map(|(system, _, _)| *system)
+    }
+}
+
+/// Whether `name` is the output name `pattern`
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.len() > prefix.len() && name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_output_of() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        for file in [
+            "app/Cargo.toml",
+            "app/target/debug/app",
+            "native/CMakeLists.txt",
+            "native/build/Makefile",
+            "mono/MODULE.bazel",
+            "mono/bazel-out/x",
+            "android/build.gradle.kts",
+            "android/build/outputs/app.apk",
+            "docs/build/index.md",
+            "docs/target/notes.md",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let all = BuildSystems::default();
+        let output = |dir: &str| all.output_of(&root.join(dir));
+        assert_eq!(output("app/target"), Some(BuildSystem::Cargo));
+        assert_eq!(output("native/build"), Some(BuildSystem::CMake));
+        assert_eq!(output("mono/bazel-out"), Some(BuildSystem::Bazel));
+        assert_eq!(output("android/build"), Some(BuildSystem::Gradle));
+        // Without a build file beside them, they're just directories
+        assert_eq!(output("docs/build"), None);
+        assert_eq!(output("docs/target"), None);
+        assert_eq!(output("app/target/debug"), None);
+
+        let no_gradle = BuildSystems {
+            gradle: false,
+            ..Default::default()
+        };
+        assert_eq!(no_gradle.output_of(&root.join("android/build")), None);
+        assert_eq!(BuildSystems::NONE.output_of(&root.join("app/target")), None);
+    }
+}
@@ -0,0 +1,201 @@
+use fuzzy_matcher::FuzzyMatcher;
+
+/// Points for each pair of adjacent pattern characters found in a name
+const PAIR_SCORE: i64 = 16;
+/// Points for the whole pattern appearing in a name as it is
+const CONTIGUOUS_BONUS: i64 = 32;
+
+/// Whether `c` is a Chinese, Japanese or Korean character: a Han
+/// ideograph, kana or Hangul
+pub fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        // Hangul Jamo
+        '\u{1100}'..='\u{11FF}'
+            // Hiragana and Katakana
+            | '\u{3040}'..='\u{30FF}'
+            // Hangul compatibility Jamo
+            | '\u{3130}'..='\u{318F}'
+            // Katakana phonetic extensions
+            | '\u{31F0}'..='\u{31FF}'
+            // Han, with extension A
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            // Hangul syllables
+            | '\u{AC00}'..='\u{D7AF}'
+            // Han compatibility ideographs
+            | '\u{F900}'..='\u{FAFF}'
+            // Halfwidth Katakana
+            | '\u{FF66}'..='\u{FF9F}'
+            // Han extensions B to H
+            | '\u{20000}'..='\u{323AF}'
+    )
+}
+
+/// Whether `text` has CJK characters, which a [`BigramMatcher`] matches
+/// better than a subsequence matcher does
+pub fn has_cjk(text: &str) -> bool {
+    text.chars().any(is_cjk)
+}
+
+/// Matches names by the pairs of adjacent characters they share with the
+/// pattern, for languages written without spaces between words. A
+/// subsequence matcher lets a Chinese or Japanese pattern match characters
+/// scattered all over a name, which rarely has anything to do with it.
+///
+/// Every character of the pattern must appear in the name, and at least
+/// half of its pairs, so `会议记录` matches `会议_记录.docx` but not
+/// `会计记事本议录`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BigramMatcher {
+    ignore_case: bool,
+}
+
+impl BigramMatcher {
+    pub fn new(ignore_case: bool) -> Self {
+        BigramMatcher { ignore_case }
+    }
+
+    fn chars(&self, text: &str) -> Vec<char> {
+        text.chars()
+            .map(|c| match self.ignore_case {
+                // One character for each, so indices stay those of `text`
+                true => c.to_lowercase().next().unwrap_or(c),
+                false => c,
+            })
+            .collect()
+    }
+}
+
+impl FuzzyMatcher for BigramMatcher {
+    fn fuzzy_indices(&self, choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+        let text = self.chars(choice);
+        let pattern = self.chars(pattern);
+        if pattern.is_empty() {
+            return Some((0, Vec::new()));
+        }
+        if !pattern.iter().all(|c| text.contains(c)) {
+            return None;
+        }
+        if let [c] = pattern[..] {
+            let position = text.iter().position(|&found| found == c)?;
+            return Some((PAIR_SCORE, vec![position]));
+        }
+        let mut indices = Vec::new();
+        let mut found = 0;
+        for pair in pattern.windows(2) {
+            if let Some(start) = text.windows(2).position(|window| window == pair) {
+                indices.extend([start, start + 1]);
+                found += 1;
+            }
+        }
+        let pairs = pattern.len() - 1;
+        if found < pairs.div_ceil(2) {
+            return None;
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        let contiguous = text
+            .windows(pattern.len())
+            .any(|window| window == pattern.as_slice());
+        let score = found as i64 * PAIR_SCORE + if contiguous { CONTIGUOUS_BONUS } else { 0 };
+        Some((score, indices))
+    }
+}
+
+/// The Latin letters a Chinese character or Japanese kana is read as:
+/// pinyin without tones, as in Mandarin, for Han characters, kanji
+/// included, and Hepburn romaji for kana.
+///
+/// Kana are read one at a time, so `きょう` reads as `kiyou` rather than
+/// `kyou`, which a fuzzy search for `kyou` still finds.
+#[cfg(feature = "romanize")]
+pub fn romanize(c: char) -> Option<&'static str> {
+    use pinyin::ToPinyin;
+    romaji(c).or_else(|| c.to_pinyin().map(|pinyin| pinyin.plain()))
+}
+
+/// Hiragana from `ぁ` to `ゖ`, in Unicode order, in romaji; katakana come
+/// 0x60 later in the same order
+#[cfg(feature = "romanize")]
+const KANA: [&str; 86] = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o", //
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go", //
+    "sa", "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo", //
+    "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to", "do", //
+    "na", "ni", "nu", "ne", "no", //
+    "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu", "he", "be", "pe", "ho", "bo",
+    "po", //
+    "ma", "mi", "mu", "me", "mo", //
+    "ya", "ya", "yu", "yu", "yo", "yo", //
+    "ra", "ri", "ru", "re", "ro", //
+    "wa", "wa", "i", "e", "o", "n", "vu", "ka", "ke",
+];
+
+#[cfg(feature = "romanize")]
+fn romaji(c: char) -> Option<&'static str> {
+    let hiragana = match c {
+        'ァ'..='ヶ' => c as u32 - 0x60,
+        'ぁ'..='ゖ' => c as u32,
+        _ => return None,
+    };
+    KANA.get((hiragana - 'ぁ' as u32) as usize).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(choice: &str, pattern: &str) -> Option<i64> {
+        BigramMatcher::new(true).fuzzy_match(choice, pattern)
+    }
+
+    #[test]
+    fn test_bigram_matching() {
+        assert!(has_cjk("2024年度报告.pdf"));
+        assert!(has_cjk("カタログ"));
+        assert!(has_cjk("보고서"));
+        assert!(!has_cjk("report.pdf"));
+
+        let matcher = BigramMatcher::new(true);
+        assert_eq!(
+            matcher.fuzzy_indices("会议_记录.docx", "会议记录"),
+            Some((2 * PAIR_SCORE, vec![0, 1, 3, 4]))
+        );
+        assert_eq!(score("会计记事本议录", "会议记录"), None);
+        // A subsequence matcher would take any name with these characters
+        assert_eq!(score("东南京", "东京"), None);
+        assert_eq!(
+            score("东京.txt", "东京"),
+            Some(PAIR_SCORE + CONTIGUOUS_BONUS)
+        );
+        assert_eq!(
+            score("報告書 2024.PDF", "2024.pdf報告"),
+            Some(8 * PAIR_SCORE)
+        );
+        assert_eq!(
+            matcher.fuzzy_indices("新しい資料", "資"),
+            Some((PAIR_SCORE, vec![3]))
+        );
+        assert!(
+            score("年度报告.pdf", "年度报告").unwrap()
+                > score("年度_报告.pdf", "年度报告").unwrap()
+        );
+        assert_eq!(
+            BigramMatcher::new(false).fuzzy_match("東京.TXT", "東京.txt"),
+            None
+        );
+    }
+
+    #[cfg(feature = "romanize")]
+    #[test]
+    fn test_romanization() {
+        assert_eq!(romanize('北'), Some("bei"));
+        assert_eq!(romanize('京'), Some("jing"));
+        assert_eq!(romanize('す'), Some("su"));
+        assert_eq!(romanize('シ'), Some("shi"));
+        assert_eq!(romanize('ん'), Some("n"));
+        assert_eq!(romanize('ヶ'), Some("ke"));
+        assert_eq!(romanize('a'), None);
+    }
+}
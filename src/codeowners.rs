@@ -0,0 +1,193 @@
+use crate::scanner::FileEntry;
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Places GitHub and GitLab look for the CODEOWNERS file, in order
+const LOCATIONS: [&str; 3] = ["CODEOWNERS", ".github/CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A parsed CODEOWNERS file.
+///
+/// As on GitHub, the last rule matching a path decides its owners.
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+    /// Owner list of every rule, in file order
+    rules: Vec<Vec<String>>,
+    /// Globs of every rule; a match reports the rule's position
+    globs: GlobSet,
+    /// Rule each glob belongs to (a rule can expand to several globs)
+    glob_rules: Vec<usize>,
+}
+
+impl CodeOwners {
+    /// Find and parse the CODEOWNERS file of the tree rooted at `root`
+    pub fn load(root: &Path) -> Result<Option<Self>> {
+        for location in LOCATIONS {
+            let path = root.join(location);
+            if let Ok(contents) = fs::read_to_string(&path) {
+                return Ok(Some(Self::parse(&contents)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parse CODEOWNERS syntax: `pattern @owner @org/team user@example.com`.
+    ///
+    /// Lines without owners are kept, since they un-assign a path that an
+    /// earlier rule matched. GitLab-style `[Section]` headers are skipped.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        let mut builder = GlobSetBuilder::new();
+        let mut glob_rules = Vec::new();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            let Ok(globs) = pattern_globs(pattern) else {
+                // GitHub ignores rules it can't parse; so do we
+                continue;
+            };
+            for glob in globs {
+                builder.add(glob);
+                glob_rules.push(rules.len());
+            }
+            rules.push(fields.map(str::to_string).collect());
+        }
+        Ok(CodeOwners {
+            rules,
+            globs: builder.build()?,
+            glob_rules,
+        })
+    }
+
+    /// Owners of `relative` (a path relative to the root), if any rule assigns some
+    pub fn owners_of(&self, relative: &Path) -> Option<&[String]> {
+        let rule = self
+            .globs
+            .matches(relative)
+            .into_iter()
+            .map(|glob| self.glob_rules[glob])
+            .max()?;
+        let owners = &self.rules[rule];
+        (!owners.is_empty()).then_some(owners.as_slice())
+    }
+}
+
+/// Translate one gitignore-style CODEOWNERS pattern into globs over
+/// root-relative paths
+fn pattern_globs(pattern: &str) -> Result<Vec<globset::Glob>> {
+    let anchored = pattern.starts_with('/') || pattern.trim_end_matches('/').contains('/');
+    let body = pattern.trim_start_matches('/');
+    let dir_only = body.ends_with('/');
+    let body = body.trim_end_matches('/');
+    if body.is_empty() {
+        anyhow::bail!("empty pattern");
+    }
+    let base = if anchored {
+        body.to_string()
+    } else {
+        format!("**/{}", body)
+    };
+
+    // A pattern naming a directory owns everything beneath it
+    let mut globs = vec![format!("{}/**", base)];
+    if !dir_only {
+        globs.push(base);
+    }
+    globs
+        .into_iter()
+        .map(|glob| Ok(GlobBuilder::new(&glob).literal_separator(true).build()?))
+        .collect()
+}
+
+/// Assign owners to every entry under `root`.
+///
+/// Distinct owner lists are stored once in the returned table; each entry
+/// refers to its list by position.
+pub fn assign(root: &Path, entries: &mut [FileEntry]) -> Vec<Vec<String>> {
+    let Ok(Some(codeowners)) = CodeOwners::load(root) else {
+        for entry in entries.iter_mut() {
+            entry.owners = None;
+        }
+        return Vec::new();
+    };
+    let mut table: Vec<Vec<String>> = Vec::new();
+    let mut ids: HashMap<Vec<String>, u32> = HashMap::new();
+    for entry in entries.iter_mut() {
+        let relative: PathBuf = entry
+            .path
+            .strip_prefix(root)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        entry.owners = codeowners.owners_of(&relative).map(|owners| {
+            *ids.entry(owners.to_vec()).or_insert_with(|| {
+                table.push(owners.to_vec());
+                table.len() as u32 - 1
+            })
+        });
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+# Default owners
+*                   @acme/everyone
+*.rs                @acme/rust
+/docs/              @acme/docs docs@example.com
+src/generated/      # no owner: un-assigned
+apps/web/**/*.ts    @acme/web
+
+[Legacy]
+/legacy/ @old-team
+"#;
+
+    #[test]
+    fn test_last_matching_rule_wins() {
+        let owners = CodeOwners::parse(SAMPLE).unwrap();
+        let of = |path: &str| owners.owners_of(Path::new(path)).map(|o| o.join(" "));
+        assert_eq!(of("README.md").as_deref(), Some("@acme/everyone"));
+        assert_eq!(of("src/deep/main.rs").as_deref(), Some("@acme/rust"));
+        assert_eq!(
+            of("docs/guide/intro.md").as_deref(),
+            Some("@acme/docs docs@example.com")
+        );
+        // Anchored: a nested docs/ directory isn't matched
+        assert_eq!(of("src/docs/notes.md").as_deref(), Some("@acme/everyone"));
+        assert_eq!(of("src/generated/api.rs"), None);
+        assert_eq!(of("apps/web/ui/button.ts").as_deref(), Some("@acme/web"));
+        assert_eq!(of("legacy/old.c").as_deref(), Some("@old-team"));
+    }
+
+    #[test]
+    fn test_assign_interns_owner_lists() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join(".github")).unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join(".github/CODEOWNERS"), "*.rs @rust\n").unwrap();
+        for name in ["src/a.rs", "src/b.rs", "notes.txt"] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let scan = crate::scanner::scan_directory(root.path()).unwrap();
+        let mut entries = scan.files;
+        let table = assign(&scan.root, &mut entries);
+        assert_eq!(table, vec![vec!["@rust".to_string()]]);
+        let owned = entries.iter().filter(|e| e.owners == Some(0)).count();
+        assert_eq!(owned, 2);
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.name == "notes.txt" && e.owners.is_none())
+        );
+    }
+}
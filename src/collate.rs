@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use icu_collator::options::CollatorOptions;
+use icu_collator::preferences::CollationNumericOrdering;
+use icu_collator::{CollatorBorrowed, CollatorPreferences};
+use icu_locale_core::Locale;
+use std::cmp::Ordering;
+
+/// Orders names by the Unicode Collation Algorithm as tailored for a
+/// locale, so `é` sorts with `e`, `å` after `z` in Swedish and `file2`
+/// before `file10`
+pub struct Collator {
+    collator: CollatorBorrowed<'static>,
+}
+
+impl Collator {
+    /// A collator for a BCP 47 or POSIX locale name, such as `sv-SE` or
+    /// `de_DE.UTF-8`; `C` and `POSIX` get the root collation
+    pub fn new(name: &str) -> Result<Self> {
+        let locale = match bcp47(name) {
+            Some(tag) => {
+                Locale::try_from_str(&tag).with_context(|| format!("Invalid locale '{}'", name))?
+            }
+            None => Locale::UNKNOWN,
+        };
+        let mut prefs = CollatorPreferences::from(&locale);
+        prefs.numeric_ordering = Some(CollationNumericOrdering::True);
+        let collator = CollatorBorrowed::try_new(prefs, CollatorOptions::default())
+            .with_context(|| format!("No collation for locale '{}'", name))?;
+        Ok(Collator { collator })
+    }
+
+    /// A collator for the locale collation is configured for in the
+    /// environment, by `LC_ALL`, `LC_COLLATE` or `LANG` as on POSIX; an
+    /// unrecognized one gets the root collation
+    pub fn system() -> Result<Self> {
+        match system_locale() {
+            Some(name) => Self::new(&name).or_else(|_| Self::new("C")),
+            None => Self::new("C"),
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator.compare(a, b)
+    }
+}
+
+/// The first of `LC_ALL`, `LC_COLLATE` and `LANG` that is set
+pub fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// `name` as a BCP 47 tag, without a POSIX name's encoding and modifier;
+/// `None` for the `C` and `POSIX` locales
+fn bcp47(name: &str) -> Option<String> {
+    let language = name.split(['.', '@']).next().unwrap_or(name);
+    match language {
+        "" | "C" | "POSIX" => None,
+        _ => Some(language.replace('_', "-")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted<'a>(collator: &Collator, names: &[&'a str]) -> Vec<&'a str> {
+        let mut names = names.to_vec();
+        names.sort_by(|a, b| collator.compare(a, b));
+        names
+    }
+
+    #[test]
+    fn test_collation() {
+        assert_eq!(bcp47("sv_SE.UTF-8@euro").as_deref(), Some("sv-SE"));
+        assert_eq!(bcp47("C.UTF-8"), None);
+
+        let root = Collator::new("C").unwrap();
+        assert_eq!(
+            sorted(&root, &["Zebra.txt", "été.txt", "apple.txt", "Eve.txt"]),
+            ["apple.txt", "été.txt", "Eve.txt", "Zebra.txt"]
+        );
+        assert_eq!(
+            sorted(&root, &["file10.txt", "file2.txt", "file1.txt"]),
+            ["file1.txt", "file2.txt", "file10.txt"]
+        );
+
+        let swedish = Collator::new("sv_SE.UTF-8").unwrap();
+        assert_eq!(
+            sorted(&swedish, &["öl.txt", "zon.txt", "åsa.txt", "ara.txt"]),
+            ["ara.txt", "zon.txt", "åsa.txt", "öl.txt"]
+        );
+        assert!(Collator::new("not a locale!").is_err());
+    }
+}
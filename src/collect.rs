@@ -0,0 +1,208 @@
+use crate::scanner::FileEntry;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Archive formats `ss collect` writes, chosen by the output's extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// Plain `.tar`
+    Tar,
+    /// Zstandard-compressed `.tar.zst` (or `.tzst`)
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Pick the format from the name of the archive to write
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Ok(Self::TarZst)
+        } else if name.ends_with(".tar") {
+            Ok(Self::Tar)
+        } else {
+            anyhow::bail!(
+                "Unsupported archive type: {}; use a .tar or .tar.zst file name",
+                path.display()
+            )
+        }
+    }
+}
+
+/// Safety limits guarding against archiving far more than intended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_files: usize,
+    /// Total size in bytes of the files before compression
+    pub max_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_files: 10_000,
+            max_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// Fail if archiving `entries` would exceed a limit
+    pub fn check(&self, entries: &[&FileEntry]) -> Result<()> {
+        if entries.len() > self.max_files {
+            anyhow::bail!(
+                "{} files match, more than the limit of {}; narrow the query or raise --max-files",
+                entries.len(),
+                self.max_files
+            );
+        }
+        let size: u64 = entries.iter().map(|entry| entry.size).sum();
+        if size > self.max_size {
+            anyhow::bail!(
+                "The matches total {} bytes, more than the limit of {}; narrow the query or raise --max-size",
+                size,
+                self.max_size
+            );
+        }
+        Ok(())
+    }
+}
+
+/// What [`write_archive`] put into the archive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub files: usize,
+    /// Total size in bytes of the archived files before compression
+    pub size: u64,
+    /// Files that couldn't be read, e.g. because they were deleted since the scan
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Archive `entries` into `out`, storing each under its path relative to
+/// `root`.
+///
+/// The archive is written to a temporary file next to `out` and only moved
+/// into place once complete, so a failure never leaves a truncated archive.
+pub fn write_archive(
+    root: &Path,
+    entries: &[&FileEntry],
+    out: &Path,
+    format: ArchiveFormat,
+) -> Result<Report> {
+    let dir = match out.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let temp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create an archive in {}", dir.display()))?;
+    let out_path = dunce::canonicalize(dir)?.join(out.file_name().unwrap_or_default());
+
+    let report = match format {
+        ArchiveFormat::Tar => append_entries(temp.as_file(), root, entries, &out_path)?.1,
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::Encoder::new(temp.as_file(), 0)?;
+            let (encoder, report) = append_entries(encoder, root, entries, &out_path)?;
+            encoder.finish()?;
+            report
+        }
+    };
+    temp.persist(out)
+        .with_context(|| format!("Failed to write {}", out.display()))?;
+    Ok(report)
+}
+
+fn append_entries<W: Write>(
+    writer: W,
+    root: &Path,
+    entries: &[&FileEntry],
+    out: &Path,
+) -> Result<(W, Report)> {
+    let mut builder = tar::Builder::new(writer);
+    let mut report = Report::default();
+    for entry in entries {
+        // An archive written inside the tree must not swallow its own path
+        if entry.is_dir || entry.path == out {
+            continue;
+        }
+        let name = entry
+            .path
+            .strip_prefix(root)
+            .unwrap_or(Path::new(&entry.name));
+        match File::open(&entry.path) {
+            Ok(mut file) => {
+                builder
+                    .append_file(name, &mut file)
+                    .with_context(|| format!("Failed to archive {}", entry.path.display()))?;
+                report.files += 1;
+                report.size += file.metadata()?.len();
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                report.skipped.push(entry.path.clone());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", entry.path.display()));
+            }
+        }
+    }
+    Ok((builder.into_inner()?, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+    use std::fs;
+
+    #[test]
+    fn test_archive_keeps_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dunce::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/budget.md"), "# Budget").unwrap();
+        fs::write(root.join("budget.txt"), "42").unwrap();
+        let scan = scanner::scan_directory(&root).unwrap();
+        let mut entries: Vec<&FileEntry> = scan.files.iter().collect();
+        let gone = scanner::entry_for_file(&root.join("budget.txt")).map(|entry| FileEntry {
+            path: root.join("gone.txt"),
+            ..entry
+        });
+        entries.push(gone.as_ref().unwrap());
+
+        assert!(Limits::default().check(&entries).is_ok());
+        let tight = Limits {
+            max_files: 1,
+            ..Default::default()
+        };
+        assert!(tight.check(&entries).is_err());
+
+        for name in ["out.tar", "out.tar.zst"] {
+            let out = root.join(name);
+            let format = ArchiveFormat::from_path(&out).unwrap();
+            let report = write_archive(&root, &entries, &out, format).unwrap();
+            assert_eq!(report.files, 2);
+            assert_eq!(report.size, 10);
+            assert_eq!(report.skipped, vec![root.join("gone.txt")]);
+
+            let file = File::open(&out).unwrap();
+            let reader: Box<dyn io::Read> = match format {
+                ArchiveFormat::Tar => Box::new(file),
+                ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file).unwrap()),
+            };
+            let mut names: Vec<PathBuf> = tar::Archive::new(reader)
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().into_owned())
+                .collect();
+            names.sort();
+            assert_eq!(
+                names,
+                vec![PathBuf::from("budget.txt"), PathBuf::from("docs/budget.md")]
+            );
+        }
+        assert!(ArchiveFormat::from_path(Path::new("out.zip")).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::scanner::FileEntry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+/// How the names in a [`NameCollision`] differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionKind {
+    /// Only in letter case, like `README.md` and `readme.md`
+    Case,
+    /// Only in Unicode normalization, like a precomposed `é` and an `e`
+    /// followed by a combining accent
+    Normalization,
+}
+
+impl CollisionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CollisionKind::Case => "case",
+            CollisionKind::Normalization => "normalization",
+        }
+    }
+}
+
+/// Names in one directory that a case-insensitive or normalizing filesystem
+/// would treat as the same file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameCollision {
+    pub dir: PathBuf,
+    pub kind: CollisionKind,
+    /// The colliding names, sorted
+    pub names: Vec<String>,
+}
+
+/// Find names that collide case-insensitively or after Unicode
+/// normalization within the same directory, sorted by directory.
+///
+/// Directories are compared too, as the components of the entries' paths
+/// below `root`.
+pub fn find_collisions(root: &Path, entries: &[&FileEntry]) -> Vec<NameCollision> {
+    let mut children: HashMap<&Path, BTreeSet<String>> = HashMap::new();
+    for entry in entries.iter().filter(|entry| entry.path.starts_with(root)) {
+        for path in entry.path.ancestors().take_while(|path| *path != root) {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                break;
+            };
+            // Once a directory is known, so are the ones above it
+            let name = name.to_string_lossy().to_string();
+            if !children.entry(parent).or_default().insert(name) {
+                break;
+            }
+        }
+    }
+
+    let mut collisions = Vec::new();
+    for (dir, names) in children {
+        let mut by_key: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for name in names {
+            by_key.entry(fold(&name)).or_default().push(name);
+        }
+        for names in by_key.into_values().filter(|names| names.len() > 1) {
+            let normalized: BTreeSet<String> = names.iter().map(|n| n.nfc().collect()).collect();
+            let kind = match normalized.len() {
+                1 => CollisionKind::Normalization,
+                _ => CollisionKind::Case,
+            };
+            collisions.push(NameCollision {
+                dir: dir.to_path_buf(),
+                kind,
+                names,
+            });
+        }
+    }
+    collisions.sort_by(|a, b| a.dir.cmp(&b.dir).then_with(|| a.names.cmp(&b.names)));
+    collisions
+}
+
+/// The Unicode normalization form `name` is in, which tells names that look
+/// the same apart: `NFC`, `NFD` (as older macOS filesystems store names) or
+/// `mixed`
+pub fn normalization_form(name: &str) -> &'static str {
+    if unicode_normalization::is_nfc(name) {
+        "NFC"
+    } else if unicode_normalization::is_nfd(name) {
+        "NFD"
+    } else {
+        "mixed"
+    }
+}
+
+/// The form of `name` that colliding names share
+fn fold(name: &str) -> String {
+    name.nfc().collect::<String>().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_collisions() {
+        let root = Path::new("/root");
+        let paths = [
+            "README.md",
+            "readme.md",
+            "Docs/guide.md",
+            "docs/index.md",
+            "docs/index.MD",
+            "caf\u{e9}.txt",
+            "cafe\u{301}.txt",
+            "other/README.md",
+        ];
+        let entries: Vec<FileEntry> = paths
+            .iter()
+            .map(|path| FileEntry {
+                path: root.join(path),
+                name: String::new(),
+                size: 1,
+                is_dir: false,
+                is_symlink: false,
+                modified: None,
+                project: None,
+                owners: None,
+                vendored: false,
+                hash: None,
+                repo: None,
+                git_status: Default::default(),
+                mime: None,
+                attributes: Default::default(),
+                device: None,
+                folded_name: None,
+            })
+            .collect();
+        let entries: Vec<&FileEntry> = entries.iter().collect();
+
+        let collisions = find_collisions(root, &entries);
+        let found: Vec<(&Path, CollisionKind, Vec<&str>)> = collisions
+            .iter()
+            .map(|c| {
+                let names = c.names.iter().map(String::as_str).collect();
+                (c.dir.as_path(), c.kind, names)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (root, CollisionKind::Case, vec!["Docs", "docs"]),
+                (root, CollisionKind::Case, vec!["README.md", "readme.md"]),
+                (
+                    root,
+                    CollisionKind::Normalization,
+                    vec!["cafe\u{301}.txt", "caf\u{e9}.txt"]
+                ),
+                (
+                    &root.join("docs"),
+                    CollisionKind::Case,
+                    vec!["index.MD", "index.md"]
+                ),
+            ]
+        );
+        assert_eq!(normalization_form("caf\u{e9}"), "NFC");
+        assert_eq!(normalization_form("cafe\u{301}"), "NFD");
+    }
+}
@@ -0,0 +1,608 @@
+//! Other tools' command lines answered from the index instead of walking
+//! the disk: the flags of [fd](https://github.com/sharkdp/fd) that scripts
+//! use most for `ss compat fd`, and mlocate's patterns for `ss locate`.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use globset::{GlobBuilder, GlobMatcher, GlobSet};
+use regex::{Regex, RegexBuilder};
+use sonic_search::scanner::{FileEntry, ScanOptions};
+use sonic_search::{CaseMode, Filters, archive, units};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The name to install `ss` under, or link it to, to run it as `ss compat fd`
+const FD_ALIAS: &str = "ssfd";
+
+/// The command line of `ss compat fd`, spelled the way fd spells it
+#[derive(Debug, Parser)]
+#[command(
+    name = "ss compat fd",
+    no_binary_name = true,
+    disable_version_flag = true
+)]
+pub struct FdArgs {
+    /// Regex matched against file names; every entry when left out
+    pub pattern: Option<String>,
+    /// Directories to search, relative to the cwd (defaults to the cwd)
+    pub paths: Vec<PathBuf>,
+    /// Include hidden files and directories, if the index has them
+    #[arg(short = 'H', long)]
+    pub hidden: bool,
+    /// Include ignored files, if the index has them
+    #[arg(short = 'I', long)]
+    pub no_ignore: bool,
+    /// Same as -HI
+    #[arg(short = 'u', long)]
+    pub unrestricted: bool,
+    /// Match case-sensitively
+    #[arg(short = 's', long, overrides_with = "ignore_case")]
+    pub case_sensitive: bool,
+    /// Match ignoring case
+    #[arg(short = 'i', long, overrides_with = "case_sensitive")]
+    pub ignore_case: bool,
+    /// Take the pattern as a glob instead of a regex
+    #[arg(short = 'g', long, conflicts_with = "fixed_strings")]
+    pub glob: bool,
+    /// Take the pattern as literal text instead of a regex
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+    /// Match the pattern against the absolute path instead of the name
+    #[arg(short = 'p', long)]
+    pub full_path: bool,
+    /// Only list entries with this extension; repeat for several
+    #[arg(short = 'e', long = "extension", value_name = "EXT")]
+    pub extensions: Vec<String>,
+    /// Only list entries of this type: f(ile), d(irectory), l (symlink),
+    /// x (executable) or e(mpty); repeat for several
+    #[arg(short = 't', long = "type", value_name = "TYPE")]
+    pub types: Vec<String>,
+    /// Leave out entries matching this glob, and everything inside them
+    #[arg(short = 'E', long = "exclude", value_name = "GLOB")]
+    pub excludes: Vec<String>,
+    /// Only descend this many levels below each search path
+    #[arg(short = 'd', long, visible_alias = "maxdepth", value_name = "DEPTH")]
+    pub max_depth: Option<usize>,
+    /// Only list entries at least this many levels below a search path
+    #[arg(long, value_name = "DEPTH")]
+    pub min_depth: Option<usize>,
+    /// Only list entries exactly this many levels below a search path
+    #[arg(long, value_name = "DEPTH", conflicts_with_all = ["max_depth", "min_depth"])]
+    pub exact_depth: Option<usize>,
+    /// Only list files of this size, such as +10M or -1K (binary units)
+    #[arg(short = 'S', long = "size", value_name = "SIZE", value_parser = units::parse_size_filter)]
+    pub sizes: Vec<units::SizeFilter>,
+    /// Only list entries changed within this long (2w) or since this date
+    #[arg(long, visible_alias = "change-newer-than", value_parser = units::parse_time)]
+    pub changed_within: Option<std::time::SystemTime>,
+    /// Only list entries changed before this long ago or this date
+    #[arg(long, visible_alias = "change-older-than", value_parser = units::parse_time)]
+    pub changed_before: Option<std::time::SystemTime>,
+    /// Print absolute paths
+    #[arg(short = 'a', long)]
+    pub absolute_path: bool,
+    /// End each path with a NUL byte instead of a newline
+    #[arg(short = '0', long)]
+    pub print0: bool,
+    /// Stop after this many results
+    #[arg(long, value_name = "COUNT")]
+    pub max_results: Option<usize>,
+    /// Stop after the first result, like --max-results=1
+    #[arg(short = '1', conflicts_with = "max_results")]
+    pub one: bool,
+}
+
+/// The fd entry types the index can tell apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FdType {
+    File,
+    Directory,
+    Symlink,
+    Executable,
+    Empty,
+}
+
+impl FdType {
+    fn parse(input: &str) -> Result<Self> {
+        Ok(match input {
+            "f" | "file" => FdType::File,
+            "d" | "dir" | "directory" => FdType::Directory,
+            "l" | "symlink" => FdType::Symlink,
+            "x" | "executable" => FdType::Executable,
+            "e" | "empty" => FdType::Empty,
+            "s" | "socket" | "p" | "pipe" | "b" | "block-device" | "c" | "char-device" => {
+                anyhow::bail!(
+                    "The index holds no sockets, pipes or devices (-t {})",
+                    input
+                )
+            }
+            other => anyhow::bail!("Unknown type '{}' (expected f, d, l, x or e)", other),
+        })
+    }
+
+    fn matches(self, entry: &FileEntry) -> bool {
+        match self {
+            FdType::File => !entry.is_dir && !entry.is_symlink,
+            FdType::Directory => entry.is_dir && !entry.is_symlink,
+            FdType::Symlink => entry.is_symlink,
+            FdType::Executable => {
+                !entry.is_dir && entry.attributes.mode.is_some_and(|mode| mode & 0o111 != 0)
+            }
+            // Whether a directory is empty isn't stored, so ask the disk
+            FdType::Empty if entry.is_dir => {
+                std::fs::read_dir(&entry.path).is_ok_and(|mut children| children.next().is_none())
+            }
+            FdType::Empty => entry.size == 0,
+        }
+    }
+}
+
+/// What the pattern is matched as
+#[derive(Debug)]
+enum Pattern {
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+/// A directory searched, as given and as found in the index
+#[derive(Debug)]
+struct SearchPath {
+    shown: PathBuf,
+    absolute: PathBuf,
+}
+
+/// An fd command line turned into filters for the index and the checks
+/// those can't express
+#[derive(Debug)]
+pub struct FdSearch {
+    /// Narrow the entries before [`FdSearch::matches`] sees them
+    pub filters: Filters,
+    pattern: Option<Pattern>,
+    full_path: bool,
+    paths: Vec<SearchPath>,
+    types: Vec<FdType>,
+    excludes: GlobSet,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    absolute: bool,
+    /// Stop after this many results
+    pub limit: Option<usize>,
+    /// End each path with a NUL byte
+    pub print0: bool,
+    /// Whether hidden entries are listed, as with -H
+    pub hidden: bool,
+    /// Whether -I asked for ignored entries
+    pub no_ignore: bool,
+}
+
+impl FdSearch {
+    /// Translate `args`, resolving relative search paths against `cwd`
+    pub fn new(args: FdArgs, cwd: &Path) -> Result<Self> {
+        let case = match (args.case_sensitive, args.ignore_case) {
+            (true, _) => CaseMode::Sensitive,
+            (_, true) => CaseMode::Insensitive,
+            _ => CaseMode::Smart,
+        };
+        let pattern = match args.pattern.as_deref() {
+            None | Some("") => None,
+            Some(pattern) => {
+                let ignore_case = case.ignores_case(pattern);
+                Some(match args.glob {
+                    true => Pattern::Glob(
+                        GlobBuilder::new(pattern)
+                            .case_insensitive(ignore_case)
+                            .literal_separator(true)
+                            .build()
+                            .with_context(|| format!("Invalid glob '{}'", pattern))?
+                            .compile_matcher(),
+                    ),
+                    false => {
+                        let source = match args.fixed_strings {
+                            true => regex::escape(pattern),
+                            false => pattern.to_string(),
+                        };
+                        Pattern::Regex(
+                            RegexBuilder::new(&source)
+                                .case_insensitive(ignore_case)
+                                .build()
+                                .with_context(|| format!("Invalid regex '{}'", pattern))?,
+                        )
+                    }
+                })
+            }
+        };
+        let given = match args.paths.is_empty() {
+            true => vec![PathBuf::from(".")],
+            false => args.paths,
+        };
+        let mut paths = Vec::new();
+        for shown in given {
+            let absolute = dunce::canonicalize(cwd.join(&shown))
+                .with_context(|| format!("Can't search {}", shown.display()))?;
+            paths.push(SearchPath { shown, absolute });
+        }
+        let types = args
+            .types
+            .iter()
+            .map(|kind| FdType::parse(kind))
+            .collect::<Result<Vec<_>>>()?;
+        let mut excludes = globset::GlobSetBuilder::new();
+        for exclude in &args.excludes {
+            excludes.add(
+                globset::Glob::new(exclude)
+                    .with_context(|| format!("Invalid exclude glob '{}'", exclude))?,
+            );
+        }
+        let (min_depth, max_depth) = match args.exact_depth {
+            Some(depth) => (depth, Some(depth)),
+            None => (args.min_depth.unwrap_or(0), args.max_depth),
+        };
+        Ok(FdSearch {
+            filters: Filters {
+                extensions: args.extensions,
+                sizes: args.sizes,
+                modified_after: args.changed_within,
+                modified_before: args.changed_before,
+                // fd doesn't know vendor directories are special
+                include_vendored: true,
+                ..Default::default()
+            },
+            pattern,
+            full_path: args.full_path,
+            paths,
+            types,
+            excludes: excludes.build()?,
+            min_depth,
+            max_depth,
+            absolute: args.absolute_path,
+            limit: if args.one { Some(1) } else { args.max_results },
+            print0: args.print0,
+            hidden: args.hidden || args.unrestricted,
+            no_ignore: args.no_ignore || args.unrestricted,
+        })
+    }
+
+    /// Directories the search covers, to look up the index around
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.paths.iter().map(|path| path.absolute.as_path())
+    }
+
+    /// Why the index scanned with `options` may be missing entries fd
+    /// would list, one line each
+    pub fn notes(&self, options: &ScanOptions) -> Vec<String> {
+        let mut notes = Vec::new();
+        if self.hidden && !options.hidden {
+            notes.push(
+                "the index has no hidden files; rescan with `ss scan --hidden` to include them"
+                    .to_string(),
+            );
+        }
+        if self.no_ignore && !options.no_ignore {
+            notes.push(
+                "the index has no ignored files; rescan with `ss scan --no-ignore` to include them"
+                    .to_string(),
+            );
+        }
+        notes
+    }
+
+    /// The path fd would print for `entry`, or `None` if it wouldn't list it
+    pub fn matches(&self, entry: &FileEntry) -> Option<PathBuf> {
+        if archive::split(&entry.path).is_some() {
+            return None;
+        }
+        let (root, relative) = self.paths.iter().find_map(|root| {
+            let relative = entry.path.strip_prefix(&root.absolute).ok()?;
+            (!relative.as_os_str().is_empty()).then_some((root, relative))
+        })?;
+        let depth = relative.components().count();
+        if depth < self.min_depth || self.max_depth.is_some_and(|max| depth > max) {
+            return None;
+        }
+        let hidden = |name: &std::ffi::OsStr| name.to_string_lossy().starts_with('.');
+        if !self.hidden && relative.iter().any(hidden) {
+            return None;
+        }
+        if !self.excludes.is_empty()
+            && relative
+                .ancestors()
+                .filter(|a| !a.as_os_str().is_empty())
+                .any(|a| {
+                    self.excludes.is_match(a)
+                        || a.file_name()
+                            .is_some_and(|name| self.excludes.is_match(name))
+                })
+        {
+            return None;
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|kind| kind.matches(entry)) {
+            return None;
+        }
+        let haystack = match self.full_path {
+            true => entry.path.to_string_lossy(),
+            false => entry.name.as_str().into(),
+        };
+        let found = match &self.pattern {
+            None => true,
+            Some(Pattern::Regex(regex)) => regex.is_match(&haystack),
+            Some(Pattern::Glob(glob)) => glob.is_match(haystack.as_ref()),
+        };
+        if !found {
+            return None;
+        }
+        Some(match self.absolute || root.shown.is_absolute() {
+            true => entry.path.clone(),
+            false if root.shown == Path::new(".") => relative.to_path_buf(),
+            false => root.shown.join(relative),
+        })
+    }
+}
+
+/// The patterns of `ss locate`, matched the way mlocate matches them
+#[derive(Debug)]
+pub struct Locate {
+    patterns: Vec<LocatePattern>,
+    basename: bool,
+    all: bool,
+}
+
+#[derive(Debug)]
+enum LocatePattern {
+    Substring { text: String, ignore_case: bool },
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Locate {
+    /// `patterns` are found anywhere in the path unless they hold `*`, `?`
+    /// or `[`, in which case they're globs the whole path must match; with
+    /// `regex` they're all regexes, as `regexps` always are. Entries must
+    /// match one of them, or every one with `all`, and only their name is
+    /// looked at with `basename`.
+    pub fn new(
+        patterns: &[String],
+        regexps: &[String],
+        regex: bool,
+        ignore_case: bool,
+        basename: bool,
+        all: bool,
+    ) -> Result<Self> {
+        let build_regex = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .with_context(|| format!("Invalid regex '{}'", pattern))
+                .map(LocatePattern::Regex)
+        };
+        let mut compiled = Vec::new();
+        for pattern in patterns {
+            compiled.push(match regex {
+                true => build_regex(pattern)?,
+                false if pattern.contains(['*', '?', '[']) => LocatePattern::Glob(
+                    GlobBuilder::new(pattern)
+                        .case_insensitive(ignore_case)
+                        .backslash_escape(true)
+                        .build()
+                        .with_context(|| format!("Invalid pattern '{}'", pattern))?
+                        .compile_matcher(),
+                ),
+                false => LocatePattern::Substring {
+                    text: match ignore_case {
+                        true => pattern.to_lowercase(),
+                        false => pattern.clone(),
+                    },
+                    ignore_case,
+                },
+            });
+        }
+        for pattern in regexps {
+            compiled.push(build_regex(pattern)?);
+        }
+        Ok(Locate {
+            patterns: compiled,
+            basename,
+            all,
+        })
+    }
+
+    pub fn matches(&self, entry: &FileEntry) -> bool {
+        if archive::split(&entry.path).is_some() {
+            return false;
+        }
+        let haystack = match self.basename {
+            true => entry.name.as_str().into(),
+            false => entry.path.to_string_lossy(),
+        };
+        let found = |pattern: &LocatePattern| match pattern {
+            LocatePattern::Substring { text, ignore_case } => match ignore_case {
+                true => haystack.to_lowercase().contains(text.as_str()),
+                false => haystack.contains(text.as_str()),
+            },
+            LocatePattern::Glob(glob) => glob.is_match(haystack.as_ref()),
+            LocatePattern::Regex(regex) => regex.is_match(&haystack),
+        };
+        match self.all {
+            true => self.patterns.iter().all(found),
+            false => self.patterns.iter().any(found),
+        }
+    }
+}
+
+/// The gitignore-style excludes for updatedb's space-separated
+/// `prunepaths`, absolute paths of which those outside `root` are dropped,
+/// and `prunenames`, names left out wherever they are
+pub fn prune_excludes(root: &Path, prunepaths: &[String], prunenames: &[String]) -> Vec<String> {
+    let paths = prunepaths
+        .iter()
+        .flat_map(|list| list.split_whitespace())
+        .filter_map(|path| {
+            let relative = Path::new(path).strip_prefix(root).ok()?;
+            let relative = relative.to_str()?.trim_end_matches('/');
+            (!relative.is_empty()).then(|| format!("/{}", relative))
+        });
+    let names = prunenames
+        .iter()
+        .flat_map(|list| list.split_whitespace())
+        .map(str::to_string);
+    paths.chain(names).collect()
+}
+
+/// `args` with `ss compat fd --` put in front when `ss` was started as
+/// [`FD_ALIAS`]
+pub fn expand_alias(mut args: Vec<OsString>) -> Vec<OsString> {
+    let invoked_as = args
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|stem| stem == FD_ALIAS);
+    if invoked_as {
+        let translated = ["compat", "fd", "--"].map(OsString::from);
+        args.splice(1..1, translated);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_search::testing::IndexBuilder;
+
+    fn fd(root: &Path, args: &[&str]) -> Vec<String> {
+        let args = FdArgs::try_parse_from(args).unwrap();
+        let search = FdSearch::new(args, root).unwrap();
+        let index = IndexBuilder::new(root)
+            .spec(
+                "src/main.rs
+                 src/Lib.rs
+                 src/bin/tool.rs
+                 .github/ci.yml
+                 docs/guide.md  2K
+                 docs/.draft.md
+                 target/debug/app.d",
+            )
+            .unwrap()
+            .build();
+        index
+            .entries
+            .iter()
+            .filter_map(|entry| search.matches(entry))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_fd_translation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dunce::canonicalize(dir.path()).unwrap();
+        for sub in ["src/bin", "docs"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+
+        assert_eq!(fd(&root, &["main"]), ["src/main.rs"]);
+        // Smart case: an uppercase letter makes the pattern case-sensitive
+        assert_eq!(fd(&root, &["lib"]), ["src/Lib.rs"]);
+        assert!(fd(&root, &["LIB"]).is_empty());
+        assert_eq!(fd(&root, &["-i", "LIB"]), ["src/Lib.rs"]);
+        assert_eq!(fd(&root, &[r"^t.*\.rs$"]), ["src/bin/tool.rs"]);
+        assert_eq!(fd(&root, &["-g", "*.md"]), ["docs/guide.md"]);
+        assert_eq!(
+            fd(&root, &["-H", "-g", "*.md"]),
+            ["docs/.draft.md", "docs/guide.md"]
+        );
+        assert_eq!(fd(&root, &["-td", "-d", "1"]), ["docs", "src", "target"]);
+        assert_eq!(
+            fd(&root, &[".", "src", "--exact-depth", "2"]),
+            ["src/bin/tool.rs"]
+        );
+        assert_eq!(
+            fd(&root, &["-E", "target", "-E", "src", "-tf"]),
+            ["docs/guide.md"]
+        );
+
+        let args = FdArgs::try_parse_from(["-e", "rs", "-S", "+1K", "-1"]).unwrap();
+        let search = FdSearch::new(args, &root).unwrap();
+        assert_eq!(search.filters.extensions, ["rs"]);
+        assert_eq!(search.filters.sizes.len(), 1);
+        assert_eq!(search.limit, Some(1));
+
+        let args = FdArgs::try_parse_from(["-u"]).unwrap();
+        let notes = FdSearch::new(args, &root)
+            .unwrap()
+            .notes(&ScanOptions::default());
+        assert_eq!(notes.len(), 2);
+        let args = FdArgs::try_parse_from(["-t", "p"]).unwrap();
+        assert!(FdSearch::new(args, &root).is_err());
+    }
+
+    #[test]
+    fn test_locate_patterns() {
+        let index = IndexBuilder::new("/srv")
+            .spec(
+                "etc/nginx/nginx.conf
+                 etc/Hosts
+                 home/ada/notes.txt
+                 home/ada/backup.zip",
+            )
+            .unwrap()
+            .build();
+        let found = |patterns: &[&str], regex, ignore_case, basename, all| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            let locate = Locate::new(&patterns, &[], regex, ignore_case, basename, all).unwrap();
+            let paths: Vec<&str> = index
+                .entries
+                .iter()
+                .filter(|entry| locate.matches(entry))
+                .map(|entry| entry.path.to_str().unwrap().trim_start_matches("/srv/"))
+                .collect();
+            paths.join(" ")
+        };
+        // A plain pattern is found anywhere in the path, directories included
+        assert_eq!(
+            found(&["ada"], false, false, false, false),
+            "home/ada home/ada/backup.zip home/ada/notes.txt"
+        );
+        assert_eq!(found(&["hosts"], false, false, false, false), "");
+        assert_eq!(found(&["hosts"], false, true, false, false), "etc/Hosts");
+        // A glob must match the whole path, with `*` crossing directories
+        assert_eq!(
+            found(&["*.conf"], false, false, false, false),
+            "etc/nginx/nginx.conf"
+        );
+        assert_eq!(found(&["nginx*"], false, false, false, false), "");
+        assert_eq!(
+            found(&["nginx*"], false, false, true, false),
+            "etc/nginx etc/nginx/nginx.conf"
+        );
+        assert_eq!(
+            found(&["zip", "notes"], false, false, false, false),
+            "home/ada/backup.zip home/ada/notes.txt"
+        );
+        assert_eq!(
+            found(&["ada", "notes"], false, false, false, true),
+            "home/ada/notes.txt"
+        );
+        assert_eq!(
+            found(&["/e.c/[a-z]+$"], true, false, false, false),
+            "etc/nginx"
+        );
+
+        let excludes = prune_excludes(
+            Path::new("/srv"),
+            &["/tmp /srv/home/ada/ /srv".to_string()],
+            &[".git node_modules".to_string()],
+        );
+        assert_eq!(excludes, ["/home/ada", ".git", "node_modules"]);
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let args = |list: &[&str]| list.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            expand_alias(args(&["/usr/bin/ssfd", "-e", "rs"])),
+            args(&["/usr/bin/ssfd", "compat", "fd", "--", "-e", "rs"])
+        );
+        assert_eq!(
+            expand_alias(args(&["ss", "find", "x"])),
+            args(&["ss", "find", "x"])
+        );
+    }
+}
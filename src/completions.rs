@@ -0,0 +1,131 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use clap_complete::{CompleteEnv, Shell};
+use sonic_search::search::EntryFields;
+use sonic_search::{MappedSnapshot, workspace};
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+
+/// Environment variable the dynamic scripts set when calling back into `ss`
+const COMPLETE_ENV: &str = "COMPLETE";
+
+/// How to load the scripts, shown in `ss completions --help`
+pub const INSTALL_HINT: &str = "Load completions on shell startup, e.g.:
+  bash:  source <(ss completions bash --dynamic)
+  zsh:   source <(ss completions zsh --dynamic)
+  fish:  ss completions fish --dynamic | source
+  powershell:  ss completions powershell --dynamic | Out-String | Invoke-Expression";
+
+/// Most names offered for a query, so huge indexes don't flood the shell
+const MAX_CANDIDATES: usize = 200;
+
+/// Answer a completion request from a dynamic script and exit, if this run
+/// is one; otherwise return and let the command run
+pub fn handle_request(command: fn() -> clap::Command) {
+    CompleteEnv::with_factory(command)
+        .var(COMPLETE_ENV)
+        .complete();
+}
+
+/// Write the completion script for `shell` to `out`. A static script knows
+/// the commands and flags; a dynamic one asks `ss` on every completion, so
+/// it can also offer indexed names for the `find` query.
+pub fn write_script(
+    command: &mut clap::Command,
+    shell: Shell,
+    dynamic: bool,
+    out: &mut impl Write,
+) -> Result<()> {
+    let name = command.get_name().to_string();
+    if !dynamic {
+        clap_complete::generate(shell, command, &name, out);
+        return Ok(());
+    }
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .with_context(|| format!("No dynamic completions for {}", shell))?;
+    completer.write_registration(COMPLETE_ENV, &name, &name, &name, out)?;
+    Ok(())
+}
+
+/// Names in the index `find` would search from here that start with
+/// `current`, for completing its query
+pub fn indexed_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Ok(cwd) = std::env::current_dir() else {
+        return Vec::new();
+    };
+    let index_dir = Config::load()
+        .ok()
+        .and_then(|config| config.index_dir)
+        .unwrap_or_else(|| workspace::locate(&cwd).index_dir);
+    names_in(&index_dir, &current.to_string_lossy())
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Distinct names in the index at `index_dir` starting with `prefix`,
+/// ignoring case unless it has an uppercase letter, in order
+fn names_in(index_dir: &Path, prefix: &str) -> Vec<String> {
+    let Ok(snapshot) = MappedSnapshot::open(index_dir) else {
+        return Vec::new();
+    };
+    let ignore_case = !prefix.chars().any(char::is_uppercase);
+    let lowercase = prefix.to_lowercase();
+    let mut names = BTreeSet::new();
+    for entry in snapshot.entries().iter() {
+        let name = entry.name();
+        let matches = match ignore_case {
+            true => name.to_lowercase().starts_with(&lowercase),
+            false => name.starts_with(prefix),
+        };
+        if matches {
+            names.insert(name.to_string());
+        }
+    }
+    names.into_iter().take(MAX_CANDIDATES).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_search::{Index, scanner};
+    use std::fs;
+
+    #[test]
+    fn test_names_in_index() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        for name in ["Budget.xlsx", "budget-2024.csv", "notes.md"] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let mut index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        index.save(index_dir.path()).unwrap();
+
+        assert_eq!(
+            names_in(index_dir.path(), "bud"),
+            ["Budget.xlsx", "budget-2024.csv"]
+        );
+        assert_eq!(names_in(index_dir.path(), "Bud"), ["Budget.xlsx"]);
+        assert!(names_in(index_dir.path(), "zzz").is_empty());
+        assert!(names_in(root.path(), "bud").is_empty());
+    }
+
+    #[test]
+    fn test_scripts() {
+        let command = clap::Command::new("ss").subcommand(clap::Command::new("find"));
+        let script = |shell: Shell, dynamic: bool| {
+            let mut out = Vec::new();
+            write_script(&mut command.clone(), shell, dynamic, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(script(Shell::Bash, false).contains("find"));
+        assert!(script(Shell::Fish, true).contains("COMPLETE"));
+        assert!(script(Shell::Zsh, true).contains("COMPLETE"));
+    }
+}
@@ -0,0 +1,608 @@
+use crate::output::ColorChoice;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sonic_search::buildsys::BuildSystems;
+use sonic_search::{RankWeights, suggest, units};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variable overriding the config file location
+pub const CONFIG_ENV: &str = "SONIC_SEARCH_CONFIG";
+
+/// Global ignore file, kept beside the config file
+pub const GLOBAL_IGNORE_FILE: &str = "ignore";
+
+/// Maximum alias nesting before we assume the aliases form a cycle
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// The config this run was started with, see [`Config::install`]
+static INSTALLED: OnceLock<Config> = OnceLock::new();
+
+/// An option the top-level command takes before the subcommand, such as
+/// `--output json` or `--no-pager`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalOption {
+    /// As written, `--output` or `-q`
+    pub name: String,
+    /// Whether the argument after it is its value
+    pub takes_value: bool,
+}
+
+/// Where the subcommand is, or would be, in `args`: after the program name
+/// and any global options with their values
+fn command_position(args: &[OsString], globals: &[GlobalOption]) -> usize {
+    let mut position = 1;
+    while let Some(arg) = args.get(position).and_then(|a| a.to_str()) {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, _)) if name.starts_with("--") => (name, true),
+            _ => (arg, false),
+        };
+        let Some(global) = globals.iter().find(|global| global.name == name) else {
+            break;
+        };
+        position += match global.takes_value && !inline_value {
+            true => 2,
+            false => 1,
+        };
+    }
+    position.min(args.len())
+}
+
+/// User configuration loaded from `config.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// User-defined command aliases, e.g. `rs = "find --ext rs"`
+    pub aliases: BTreeMap<String, AliasValue>,
+    /// Subcommand run when the first argument isn't a command, e.g. `ss budget`
+    pub default_command: DefaultCommand,
+    /// What hyperlinks in results open, as for `--hyperlink-format`, e.g.
+    /// `vscode` or `idea://open?file={path}&line={line}`
+    pub hyperlink_format: Option<String>,
+    /// How `find` weighs recency, depth and word matches against the fuzzy
+    /// score, e.g. `[ranking] recency = 0`
+    pub ranking: RankWeights,
+    /// Index used by commands run without `--index-dir`, instead of the
+    /// one found from the current directory; `~/` means the home directory
+    pub index_dir: Option<PathBuf>,
+    /// Whether to highlight matches, as for `--color`
+    pub color: Option<ColorChoice>,
+    /// Filters applied to every search unless the same flag is given
+    pub filters: FilterDefaults,
+    /// Build outputs `ss scan` leaves out, by build system, e.g.
+    /// `[skip_build_outputs] gradle = false` to index Gradle's `build/`
+    pub skip_build_outputs: BuildSystems,
+    /// Cap on each index directory, as for `scan --max-index-size`, e.g.
+    /// `max_index_size = "2G"`
+    pub max_index_size: Option<String>,
+    /// Defaults for `ss daemon`, e.g. `[daemon] reindex = "03:00"`
+    pub daemon: DaemonDefaults,
+}
+
+/// Default values for the flags of `ss daemon`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonDefaults {
+    /// As for `--reindex`, e.g. `6h` or `03:00,15:00`
+    pub reindex: Option<String>,
+    /// As for `--full-reindex`
+    pub full_reindex: bool,
+}
+
+/// Default values for the filter flags of `find`, `grep` and friends, e.g.
+/// `[filters] ext = ["rs", "toml"]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterDefaults {
+    /// As for `--glob`
+    pub glob: Vec<String>,
+    /// As for `--ext`
+    pub ext: Vec<String>,
+    /// As for `--include-vendored`
+    pub include_vendored: bool,
+    /// As for `--where`
+    #[serde(rename = "where")]
+    pub where_query: Option<String>,
+}
+
+/// Subcommands that can serve as the default for bare `ss <query>`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultCommand {
+    #[default]
+    Find,
+    Grep,
+}
+
+impl DefaultCommand {
+    /// Subcommand name inserted into the argument list
+    pub fn name(self) -> &'static str {
+        match self {
+            DefaultCommand::Find => "find",
+            DefaultCommand::Grep => "grep",
+        }
+    }
+}
+
+/// An alias body, written either as a string or as a list of arguments
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum AliasValue {
+    Line(String),
+    Args(Vec<String>),
+}
+
+impl AliasValue {
+    /// Arguments the alias expands to
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            AliasValue::Line(line) => line.split_whitespace().map(str::to_string).collect(),
+            AliasValue::Args(args) => args.clone(),
+        }
+    }
+}
+
+impl Config {
+    /// Default location of the config file, honouring `$SONIC_SEARCH_CONFIG`
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Some(PathBuf::from(path));
+        }
+        dirs::config_dir().map(|dir| dir.join("sonic-search").join("config.toml"))
+    }
+
+    /// The global ignore file beside the default config file, whose
+    /// gitignore-style patterns no scan indexes
+    pub fn global_ignore_path() -> Option<PathBuf> {
+        Self::default_path()?
+            .parent()
+            .map(|dir| dir.join(GLOBAL_IGNORE_FILE))
+    }
+
+    /// Load the config from its default location; a missing file is not an error
+    pub fn load() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.is_file() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config from a specific file
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
+        config.index_dir = config.index_dir.map(|dir| expand_home(&dir));
+        Ok(config)
+    }
+
+    /// The parsed `max_index_size`, if one is set
+    pub fn max_index_size(&self) -> Result<Option<u64>> {
+        self.max_index_size
+            .as_deref()
+            .map(units::parse_size)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid max_index_size in the config: {}", e))
+    }
+
+    /// The file named by `--config` in raw command-line arguments, which
+    /// has to be known before they're parsed
+    pub fn path_from_args(args: &[OsString]) -> Option<PathBuf> {
+        let mut args = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            }
+            if arg == "--config" {
+                return args.next().map(|path| PathBuf::from(path.as_ref()));
+            }
+            if let Some(path) = arg.strip_prefix("--config=") {
+                return Some(PathBuf::from(path));
+            }
+        }
+        None
+    }
+
+    /// Make this the config [`Config::get`] returns for the rest of the run
+    pub fn install(self) -> &'static Config {
+        INSTALLED.get_or_init(|| self)
+    }
+
+    /// The config installed at startup, or the defaults if there is none
+    pub fn get() -> &'static Config {
+        static DEFAULT: OnceLock<Config> = OnceLock::new();
+        INSTALLED
+            .get()
+            .unwrap_or_else(|| DEFAULT.get_or_init(Config::default))
+    }
+
+    /// Rewrite raw command-line arguments before clap sees them: expand
+    /// aliases, then fall back to the default command for `ss <query>`.
+    /// Each looks past the `globals` given before the subcommand.
+    pub fn prepare_args(
+        &self,
+        args: Vec<OsString>,
+        builtins: &[String],
+        globals: &[GlobalOption],
+    ) -> Result<Vec<OsString>> {
+        let args = self.expand_aliases(args, builtins, globals)?;
+        self.check_command_typo(&args, builtins, globals)?;
+        Ok(self.insert_default_command(args, builtins, globals))
+    }
+
+    /// Reject a first argument that looks like a mistyped command.
+    ///
+    /// A lone unknown word is a query for the default command (`ss budget`),
+    /// but one followed by more arguments (`ss scna . -i idx`) can't be, so
+    /// the closest command or alias is suggested instead.
+    pub fn check_command_typo(
+        &self,
+        args: &[OsString],
+        builtins: &[String],
+        globals: &[GlobalOption],
+    ) -> Result<()> {
+        let position = command_position(args, globals);
+        let Some(first) = args.get(position).and_then(|a| a.to_str()) else {
+            return Ok(());
+        };
+        if args.len() < position + 2
+            || first.starts_with('-')
+            || builtins.iter().any(|b| b == first)
+        {
+            return Ok(());
+        }
+        let known = builtins
+            .iter()
+            .chain(self.aliases.keys())
+            .map(String::as_str);
+        match suggest::closest(first, known) {
+            Some(command) => anyhow::bail!(
+                "Unknown command '{}'. Did you mean '{}'? (use `ss {} {}` to search for it)",
+                first,
+                command,
+                self.default_command.name(),
+                first
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Insert the default subcommand when the first argument isn't one.
+    ///
+    /// Top-level `--help`/`--version` and an empty command line are left
+    /// alone so clap can handle them.
+    pub fn insert_default_command(
+        &self,
+        mut args: Vec<OsString>,
+        builtins: &[String],
+        globals: &[GlobalOption],
+    ) -> Vec<OsString> {
+        let position = command_position(&args, globals);
+        let Some(first) = args.get(position).map(|a| a.to_string_lossy().into_owned()) else {
+            return args;
+        };
+        let top_level_flag = matches!(first.as_str(), "-h" | "--help" | "-V" | "--version");
+        if top_level_flag || builtins.contains(&first) {
+            return args;
+        }
+        args.insert(position, OsString::from(self.default_command.name()));
+        args
+    }
+
+    /// Replace a user-defined alias in the subcommand position of `args`.
+    ///
+    /// `args[0]` is the program name, and `globals` may come before the
+    /// subcommand. Built-in subcommand names always win over aliases so a
+    /// config file can't break the standard commands.
+    pub fn expand_aliases(
+        &self,
+        mut args: Vec<OsString>,
+        builtins: &[String],
+        globals: &[GlobalOption],
+    ) -> Result<Vec<OsString>> {
+        let position = command_position(&args, globals);
+        let mut seen: Vec<String> = Vec::new();
+        loop {
+            let Some(first) = args
+                .get(position)
+                .and_then(|a| a.to_str())
+                .map(str::to_string)
+            else {
+                return Ok(args);
+            };
+            if builtins.contains(&first) {
+                return Ok(args);
+            }
+            let Some(alias) = self.aliases.get(&first) else {
+                return Ok(args);
+            };
+            if seen.contains(&first) || seen.len() >= MAX_ALIAS_DEPTH {
+                seen.push(first);
+                anyhow::bail!("Alias cycle detected: {}", seen.join(" -> "));
+            }
+            let expansion = alias.args();
+            if expansion.is_empty() {
+                anyhow::bail!("Alias '{}' is empty", first);
+            }
+            seen.push(first);
+
+            let rest = args.split_off(position + 1);
+            args.truncate(position);
+            args.extend(expansion.into_iter().map(OsString::from));
+            args.extend(rest);
+        }
+    }
+}
+
+/// `path` with a leading `~` replaced by the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os_args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    fn builtins() -> Vec<String> {
+        vec!["find".to_string(), "grep".to_string(), "scan".to_string()]
+    }
+
+    fn globals() -> Vec<GlobalOption> {
+        let option = |name: &str, takes_value| GlobalOption {
+            name: name.to_string(),
+            takes_value,
+        };
+        vec![option("--output", true), option("--no-pager", false)]
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let config: Config = toml::from_str(
+            r#"
+            [aliases]
+            rs = "find --ext rs"
+            todo = ["grep", "TODO|FIXME"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.aliases["rs"].args(), vec!["find", "--ext", "rs"]);
+        assert_eq!(config.aliases["todo"].args(), vec!["grep", "TODO|FIXME"]);
+    }
+
+    #[test]
+    fn test_expand_alias_keeps_trailing_args() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("rs".into(), AliasValue::Line("find --ext rs".into()));
+        let expanded = config
+            .expand_aliases(os_args(&["ss", "rs", "main"]), &builtins(), &globals())
+            .unwrap();
+        assert_eq!(expanded, os_args(&["ss", "find", "--ext", "rs", "main"]));
+    }
+
+    #[test]
+    fn test_builtins_win_over_aliases() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("find".into(), AliasValue::Line("grep".into()));
+        let expanded = config
+            .expand_aliases(os_args(&["ss", "find", "x"]), &builtins(), &globals())
+            .unwrap();
+        assert_eq!(expanded, os_args(&["ss", "find", "x"]));
+    }
+
+    #[test]
+    fn test_nested_aliases_and_cycles() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("a".into(), AliasValue::Line("b --x".into()));
+        config
+            .aliases
+            .insert("b".into(), AliasValue::Line("find".into()));
+        let expanded = config
+            .expand_aliases(os_args(&["ss", "a", "q"]), &builtins(), &globals())
+            .unwrap();
+        assert_eq!(expanded, os_args(&["ss", "find", "--x", "q"]));
+
+        config
+            .aliases
+            .insert("b".into(), AliasValue::Line("a".into()));
+        let err = config
+            .expand_aliases(os_args(&["ss", "a"]), &builtins(), &globals())
+            .unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn test_default_command_insertion() {
+        let config = Config::default();
+        assert_eq!(
+            config
+                .prepare_args(os_args(&["ss", "budget"]), &builtins(), &globals())
+                .unwrap(),
+            os_args(&["ss", "find", "budget"])
+        );
+        assert_eq!(
+            config
+                .prepare_args(
+                    os_args(&["ss", "--index-dir", "x", "q"]),
+                    &builtins(),
+                    &globals()
+                )
+                .unwrap(),
+            os_args(&["ss", "find", "--index-dir", "x", "q"])
+        );
+        for untouched in [
+            &["ss"][..],
+            &["ss", "--help"],
+            &["ss", "-V"],
+            &["ss", "grep", "x"],
+        ] {
+            assert_eq!(
+                config
+                    .prepare_args(os_args(untouched), &builtins(), &globals())
+                    .unwrap(),
+                os_args(untouched)
+            );
+        }
+    }
+
+    #[test]
+    fn test_global_options_before_the_command() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("rs".into(), AliasValue::Line("find --ext rs".into()));
+        let prepare = |args: &[&str]| config.prepare_args(os_args(args), &builtins(), &globals());
+        for given in [
+            &["ss", "--output", "json", "grep", "x"][..],
+            &["ss", "--output=json", "--no-pager", "find", "x"],
+            &["ss", "--no-pager"],
+        ] {
+            assert_eq!(prepare(given).unwrap(), os_args(given));
+        }
+        assert_eq!(
+            prepare(&["ss", "--output", "json", "budget"]).unwrap(),
+            os_args(&["ss", "--output", "json", "find", "budget"])
+        );
+        assert_eq!(
+            prepare(&["ss", "--no-pager", "rs", "main"]).unwrap(),
+            os_args(&["ss", "--no-pager", "find", "--ext", "rs", "main"])
+        );
+        let err = prepare(&["ss", "--no-pager", "scna", ".", "-i", "idx"]).unwrap_err();
+        assert!(err.to_string().contains("Did you mean 'scan'?"), "{}", err);
+    }
+
+    #[test]
+    fn test_command_typos_are_suggested() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("todos".into(), AliasValue::Line("grep TODO".into()));
+
+        let err = config
+            .prepare_args(
+                os_args(&["ss", "scna", ".", "-i", "idx"]),
+                &builtins(),
+                &globals(),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Did you mean 'scan'?"), "{}", err);
+        let err = config
+            .prepare_args(
+                os_args(&["ss", "todo", "--ignore-case"]),
+                &builtins(),
+                &globals(),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Did you mean 'todos'?"), "{}", err);
+
+        // A single word is still a query, as is anything unlike a command
+        assert_eq!(
+            config
+                .prepare_args(os_args(&["ss", "scna"]), &builtins(), &globals())
+                .unwrap(),
+            os_args(&["ss", "find", "scna"])
+        );
+        assert!(
+            config
+                .prepare_args(
+                    os_args(&["ss", "budget", "-i", "idx"]),
+                    &builtins(),
+                    &globals()
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_default_command_is_configurable() {
+        let config: Config = toml::from_str("default_command = \"grep\"").unwrap();
+        assert_eq!(config.default_command, DefaultCommand::Grep);
+        assert_eq!(
+            config
+                .prepare_args(os_args(&["ss", "TODO"]), &builtins(), &globals())
+                .unwrap(),
+            os_args(&["ss", "grep", "TODO"])
+        );
+        // Aliases are expanded before the default applies
+        let mut config = config;
+        config
+            .aliases
+            .insert("rs".into(), AliasValue::Line("find --ext rs".into()));
+        assert_eq!(
+            config
+                .prepare_args(os_args(&["ss", "rs", "main"]), &builtins(), &globals())
+                .unwrap(),
+            os_args(&["ss", "find", "--ext", "rs", "main"])
+        );
+        assert!(toml::from_str::<Config>("default_command = \"scan\"").is_err());
+    }
+
+    #[test]
+    fn test_ranking_weights() {
+        let config: Config = toml::from_str("[ranking]\nrecency = 0\ndepth = 10\n").unwrap();
+        assert_eq!(config.ranking.recency, 0);
+        assert_eq!(config.ranking.depth, 10);
+        assert_eq!(config.ranking.stem, RankWeights::default().stem);
+        assert!(toml::from_str::<Config>("[ranking]\nage = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_defaults_and_config_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "index_dir = \"~/indexes/main\"\ncolor = \"never\"\n\
+             [filters]\next = [\"rs\"]\nwhere = \"NOT path:tests\"\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(config.index_dir, Some(home.join("indexes/main")));
+        }
+        assert_eq!(config.color, Some(ColorChoice::Never));
+        assert_eq!(config.filters.ext, ["rs"]);
+        assert_eq!(
+            config.filters.where_query.as_deref(),
+            Some("NOT path:tests")
+        );
+
+        let path_of = |args: &[&str]| Config::path_from_args(&os_args(args));
+        assert_eq!(
+            path_of(&["ss", "find", "x", "--config", "a.toml"]),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            path_of(&["ss", "--config=b.toml", "grep", "x"]),
+            Some(PathBuf::from("b.toml"))
+        );
+        assert_eq!(path_of(&["ss", "grep", "--", "--config"]), None);
+    }
+
+    #[test]
+    fn test_load_from_rejects_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[aliases]\nrs = \"find\"\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().aliases.len(), 1);
+
+        fs::write(&path, "colour = true\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+    }
+}
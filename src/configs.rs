@@ -0,0 +1,200 @@
+//! Configuration files among the indexed ones, recognized by a built-in
+//! list of where applications keep their settings, for `ss configs`.
+
+use crate::scanner::FileEntry;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+/// Where an application keeps its configuration, as path patterns matched
+/// against the end of a file's path.
+///
+/// `*` in a name matches any run of characters, and a trailing `/*` matches
+/// everything below the directory, however deep. The first application
+/// whose pattern matches claims the file.
+const KNOWN: &[(&str, &[&str])] = &[
+    ("Git", &[".gitconfig", ".gitignore_global", ".config/git/*"]),
+    (
+        "Bash",
+        &[
+            ".bashrc",
+            ".bash_profile",
+            ".bash_aliases",
+            ".bash_logout",
+            ".profile",
+            ".inputrc",
+        ],
+    ),
+    (
+        "Zsh",
+        &[".zshrc", ".zshenv", ".zprofile", ".zlogin", ".p10k.zsh"],
+    ),
+    ("Fish", &[".config/fish/*"]),
+    ("Vim", &[".vimrc", ".gvimrc", ".vim/vimrc"]),
+    ("Neovim", &[".config/nvim/*"]),
+    (
+        "Emacs",
+        &[
+            ".emacs",
+            ".emacs.d/init.el",
+            ".emacs.d/early-init.el",
+            ".config/emacs/*",
+        ],
+    ),
+    ("tmux", &[".tmux.conf", ".config/tmux/*"]),
+    (
+        "SSH",
+        &[".ssh/config", ".ssh/authorized_keys", ".ssh/known_hosts"],
+    ),
+    ("GnuPG", &[".gnupg/gpg.conf", ".gnupg/gpg-agent.conf"]),
+    (
+        "VS Code",
+        &[".vscode/*", "Code/User/*.json", ".config/Code/User/*.json"],
+    ),
+    ("JetBrains IDEs", &[".idea/*"]),
+    ("EditorConfig", &[".editorconfig"]),
+    ("npm", &[".npmrc", ".yarnrc", ".yarnrc.yml"]),
+    (
+        "Cargo",
+        &[
+            ".cargo/config.toml",
+            ".cargo/config",
+            ".cargo/credentials.toml",
+        ],
+    ),
+    ("pip", &[".pypirc", "pip.conf", "pip.ini"]),
+    ("Docker", &[".docker/config.json"]),
+    ("Kubernetes", &[".kube/config"]),
+    ("AWS", &[".aws/config", ".aws/credentials"]),
+    ("Starship", &[".config/starship.toml"]),
+    ("Alacritty", &[".alacritty.toml", ".config/alacritty/*"]),
+    ("kitty", &[".config/kitty/*"]),
+    ("WezTerm", &[".wezterm.lua", ".config/wezterm/*"]),
+    ("Homebrew", &["Brewfile"]),
+    ("curl", &[".curlrc"]),
+    ("Wget", &[".wgetrc"]),
+    ("Mercurial", &[".hgrc"]),
+    (
+        "X11",
+        &[".Xresources", ".Xdefaults", ".xinitrc", ".xprofile"],
+    ),
+    ("nginx", &["etc/nginx/*"]),
+    ("systemd", &["etc/systemd/*", ".config/systemd/*"]),
+    ("cron", &["etc/crontab", "etc/cron.d/*"]),
+    (
+        "System",
+        &[
+            "etc/hosts",
+            "etc/fstab",
+            "etc/passwd",
+            "etc/group",
+            "etc/*.conf",
+        ],
+    ),
+    // Run-control files of anything else
+    ("Other", &[".*rc"]),
+];
+
+/// The application whose configuration the file at `path` is, if it's one
+pub fn application(path: &Path) -> Option<&'static str> {
+    let names: Vec<&str> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    KNOWN
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|pattern| matches(pattern, &names)))
+        .map(|(app, _)| *app)
+}
+
+/// Every application in the built-in list
+pub fn applications() -> impl Iterator<Item = &'static str> {
+    KNOWN.iter().map(|(app, _)| *app)
+}
+
+/// The files among `entries` that are known configuration files, by
+/// application, each sorted by path
+pub fn group<'a>(entries: &[&'a FileEntry]) -> BTreeMap<&'static str, Vec<&'a FileEntry>> {
+    let mut groups: BTreeMap<&str, Vec<&FileEntry>> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        if let Some(app) = application(&entry.path) {
+            groups.entry(app).or_default().push(entry);
+        }
+    }
+    for files in groups.values_mut() {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups
+}
+
+/// Whether the path made of `names` matches `pattern`
+fn matches(pattern: &str, names: &[&str]) -> bool {
+    if let Some(dir) = pattern.strip_suffix("/*") {
+        let dir: Vec<&str> = dir.split('/').collect();
+        // Below the directory: it's somewhere among the parents
+        let parents = &names[..names.len().saturating_sub(1)];
+        return parents
+            .windows(dir.len())
+            .any(|window| window.iter().zip(&dir).all(|(name, p)| glob(p, name)));
+    }
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    names.len() >= pattern.len()
+        && names[names.len() - pattern.len()..]
+            .iter()
+            .zip(&pattern)
+            .all(|(name, p)| glob(p, name))
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any characters
+fn glob(pattern: &str, name: &str) -> bool {
+    let Some((prefix, suffix)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_application() {
+        let app = |path: &str| application(Path::new(path));
+        assert_eq!(app("/home/ana/.gitconfig"), Some("Git"));
+        assert_eq!(
+            app("/home/ana/.config/nvim/lua/plugins.lua"),
+            Some("Neovim")
+        );
+        assert_eq!(
+            app("/home/ana/work/app/.vscode/settings.json"),
+            Some("VS Code")
+        );
+        assert_eq!(app("/etc/nginx/sites-enabled/default"), Some("nginx"));
+        assert_eq!(app("/etc/resolv.conf"), Some("System"));
+        assert_eq!(app("/home/ana/.sqliterc"), Some("Other"));
+        // Only what's below the directory, and only whole names
+        assert_eq!(app("/home/ana/.config/nvim"), None);
+        assert_eq!(app("/home/ana/my.gitconfig"), None);
+        assert_eq!(app("/home/ana/.rc"), None);
+        assert_eq!(app("/etc/nginx.conf.d/x"), None);
+    }
+
+    #[test]
+    fn test_group() {
+        let index = IndexBuilder::new("/home/ana")
+            .file(".zshrc", 10)
+            .file(".ssh/known_hosts", 20)
+            .file(".ssh/id_ed25519", 30)
+            .file(".ssh/config", 40)
+            .file("notes.txt", 50)
+            .build();
+        let entries: Vec<&FileEntry> = index.entries.iter().collect();
+        let groups = group(&entries);
+        assert_eq!(groups.keys().copied().collect::<Vec<_>>(), ["SSH", "Zsh"]);
+        let ssh: Vec<&str> = groups["SSH"].iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(ssh, ["config", "known_hosts"]);
+    }
+}
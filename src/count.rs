@@ -0,0 +1,176 @@
+use crate::grep;
+use crate::index::Index;
+use crate::scanner::FileEntry;
+use crate::trigram::DEFAULT_MAX_CONTENT_SIZE;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+/// What `count` groups files by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Lowercased file extension
+    Extension,
+    /// Containing sub-project
+    Project,
+    /// Top-level directory below the root
+    Directory,
+}
+
+/// Line counts of text files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LineCount {
+    pub lines: u64,
+    pub blank: u64,
+}
+
+impl LineCount {
+    /// Lines that aren't blank
+    pub fn code(&self) -> u64 {
+        self.lines - self.blank
+    }
+}
+
+/// Totals for one group of files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CountRow {
+    /// Extension, project name or directory; empty for files without one
+    pub key: String,
+    pub files: usize,
+    pub size: u64,
+    /// Line counts, when requested; binary and oversized files count as
+    /// files but contribute no lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineCount>,
+}
+
+/// Count the lines of `contents`, or `None` if it looks binary
+pub fn count_lines(contents: &[u8]) -> Option<LineCount> {
+    if grep::is_binary(contents) {
+        return None;
+    }
+    let mut count = LineCount::default();
+    let body = contents.strip_suffix(b"\n").unwrap_or(contents);
+    if contents.is_empty() {
+        return Some(count);
+    }
+    for line in body.split(|&b| b == b'\n') {
+        count.lines += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            count.blank += 1;
+        }
+    }
+    Some(count)
+}
+
+/// Group `entries` of `index` by `by`, largest groups first. Files are read
+/// in parallel to count lines only when `lines` is set.
+pub fn count(index: &Index, entries: &[&FileEntry], by: GroupBy, lines: bool) -> Vec<CountRow> {
+    let counted: Vec<(String, &FileEntry, Option<LineCount>)> = entries
+        .par_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|&entry| {
+            let lines = (lines && entry.size <= DEFAULT_MAX_CONTENT_SIZE)
+                .then(|| std::fs::read(&entry.path).ok())
+                .flatten()
+                .and_then(|contents| count_lines(&contents));
+            (group_key(index, entry, by), entry, lines)
+        })
+        .collect();
+
+    let mut rows: HashMap<String, CountRow> = HashMap::new();
+    for (key, entry, file_lines) in counted {
+        let row = rows.entry(key.clone()).or_insert_with(|| CountRow {
+            key,
+            files: 0,
+            size: 0,
+            lines: lines.then(LineCount::default),
+        });
+        row.files += 1;
+        row.size += entry.size;
+        if let (Some(total), Some(file)) = (&mut row.lines, file_lines) {
+            total.lines += file.lines;
+            total.blank += file.blank;
+        }
+    }
+    let mut rows: Vec<CountRow> = rows.into_values().collect();
+    rows.sort_by(|a, b| {
+        let weight = |row: &CountRow| (row.lines.map_or(0, |l| l.lines), row.size);
+        weight(b).cmp(&weight(a)).then_with(|| a.key.cmp(&b.key))
+    });
+    rows
+}
+
+fn group_key(index: &Index, entry: &FileEntry, by: GroupBy) -> String {
+    match by {
+        GroupBy::Extension => Path::new(&entry.name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+        GroupBy::Project => entry
+            .project
+            .and_then(|id| index.projects.get(id as usize))
+            .map(|project| project.name.clone())
+            .unwrap_or_default(),
+        GroupBy::Directory => {
+            let relative = entry
+                .path
+                .strip_prefix(&index.meta.root)
+                .unwrap_or(&entry.path);
+            let mut components = relative.components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(dir)), Some(_)) => dir.to_string_lossy().into_owned(),
+                _ => String::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+    use std::fs;
+
+    #[test]
+    fn test_count_lines() {
+        assert_eq!(count_lines(b""), Some(LineCount { lines: 0, blank: 0 }));
+        assert_eq!(
+            count_lines(b"a\n\n  \nb"),
+            Some(LineCount { lines: 4, blank: 2 })
+        );
+        assert_eq!(count_lines(b"a\nb\n").unwrap().code(), 2);
+        assert_eq!(count_lines(b"\0\x01"), None);
+    }
+
+    #[test]
+    fn test_count_groups() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join("src/main.rs"), "fn main() {\n\n}\n").unwrap();
+        fs::write(root.path().join("src/lib.RS"), "pub mod a;\n").unwrap();
+        fs::write(root.path().join("README.md"), "# Title\n").unwrap();
+        fs::write(root.path().join("logo.png"), b"\x89PNG\0\0").unwrap();
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let entries: Vec<&FileEntry> = index.entries.iter().collect();
+
+        let rows = count(&index, &entries, GroupBy::Extension, true);
+        let summary: Vec<(&str, usize, u64, u64)> = rows
+            .iter()
+            .map(|r| {
+                let lines = r.lines.unwrap();
+                (r.key.as_str(), r.files, lines.lines, lines.code())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("rs", 2, 4, 3), ("md", 1, 1, 1), ("png", 1, 0, 0)]
+        );
+
+        let rows = count(&index, &entries, GroupBy::Directory, false);
+        let keys: Vec<(&str, usize)> = rows.iter().map(|r| (r.key.as_str(), r.files)).collect();
+        assert_eq!(keys, vec![("src", 2), ("", 2)]);
+        assert!(rows.iter().all(|r| r.lines.is_none()));
+    }
+}
@@ -0,0 +1,545 @@
+use crate::entrypoint;
+use crate::grep::{self, FileMatches, GrepOptions};
+use crate::index::{Index, Snapshot};
+use crate::scanner::FileEntry;
+use crate::search::{EntryFields, Filters, SearchOptions};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+/// Name of the socket `ss daemon` listens on inside the index directory
+pub const SOCKET_FILE: &str = "daemon.sock";
+
+/// Socket of the daemon serving the index in `index_dir`
+#[cfg(not(windows))]
+pub fn socket_path(index_dir: &Path) -> PathBuf {
+    index_dir.join(SOCKET_FILE)
+}
+
+/// Named pipe of the daemon serving the index in `index_dir`, which can't
+/// live inside it: its name is the local pipe namespace's, told apart by a
+/// hash of the directory
+#[cfg(windows)]
+pub fn socket_path(index_dir: &Path) -> PathBuf {
+    let dir = dunce::canonicalize(index_dir).unwrap_or_else(|_| index_dir.to_path_buf());
+    let key = dir.to_string_lossy().to_lowercase();
+    let hash = blake3::hash(key.as_bytes()).to_hex();
+    PathBuf::from(format!(r"\\.\pipe\sonic-search-{}", &hash[..16]))
+}
+
+/// A query sent to the daemon, answered with one [`Response`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Request {
+    Find {
+        query: String,
+        options: SearchOptions,
+    },
+    Grep {
+        query: String,
+        options: GrepOptions,
+        filters: Filters,
+    },
+}
+
+/// A matched entry, with the details `find` and `grep` print resolved
+/// against the index it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hit {
+    pub entry: FileEntry,
+    /// Name of the sub-project containing the file
+    pub project: Option<String>,
+    /// CODEOWNERS owners of the file
+    pub owners: Vec<String>,
+    /// README or entry point of a matched directory, from [`entrypoint`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<PathBuf>,
+}
+
+impl Hit {
+    pub fn new(index: &Index, entry: &FileEntry) -> Self {
+        Self {
+            entry: entry.clone(),
+            project: entry
+                .project
+                .map(|id| index.projects[id as usize].name.clone()),
+            owners: index.owners_of(entry).to_vec(),
+            entry_point: None,
+        }
+    }
+
+    /// Look up the entry point of each directory among `hits` in `entries`,
+    /// the entries of the index they came from
+    pub fn attach_entry_points<'a, E: EntryFields>(
+        hits: impl Iterator<Item = &'a mut Hit>,
+        entries: impl Iterator<Item = E>,
+    ) {
+        let mut dirs: Vec<&mut Hit> = hits.filter(|hit| hit.entry.is_dir).collect();
+        if dirs.is_empty() {
+            return;
+        }
+        let _span = crate::profile::span("entry-points");
+        let paths: Vec<PathBuf> = dirs.iter().map(|hit| hit.entry.path.clone()).collect();
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        let mut found = entrypoint::find(&paths, entries);
+        for hit in &mut dirs {
+            hit.entry_point = found.remove(&hit.entry.path);
+        }
+    }
+}
+
+/// The daemon's answer to a [`Request`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    /// Matches of a `Find` and their scores, best first
+    Found {
+        root: PathBuf,
+        generation: u64,
+        matches: Vec<(Hit, i64)>,
+    },
+    /// Matches of a `Grep`, one element per file
+    Grepped {
+        root: PathBuf,
+        generation: u64,
+        files: Vec<(Hit, FileMatches)>,
+        /// Number of files searched
+        searched: usize,
+    },
+    Error(String),
+}
+
+/// Answers requests from the index in one directory, reloading it whenever
+/// a newer generation is saved
+pub struct Server {
+    index_dir: PathBuf,
+    snapshot: Mutex<Arc<Snapshot>>,
+}
+
+impl Server {
+    pub fn open(index_dir: &Path) -> Result<Self> {
+        Ok(Self {
+            index_dir: index_dir.to_path_buf(),
+            snapshot: Mutex::new(Arc::new(open_snapshot(index_dir)?)),
+        })
+    }
+
+    /// Directory of the index being served
+    pub fn index_dir(&self) -> &Path {
+        &self.index_dir
+    }
+
+    /// Generation of the index currently being served
+    pub fn generation(&self) -> u64 {
+        self.snapshot.lock().unwrap().generation()
+    }
+
+    /// Answer `request`, reporting failures as [`Response::Error`]
+    pub fn answer(&self, request: &Request) -> Response {
+        self.try_answer(request)
+            .unwrap_or_else(|e| Response::Error(format!("{:#}", e)))
+    }
+
+    fn try_answer(&self, request: &Request) -> Result<Response> {
+        let snapshot = self.snapshot()?;
+        let root = snapshot.meta.root.clone();
+        let generation = snapshot.generation();
+        match request {
+            Request::Find { query, options } => {
+                let mut matches: Vec<(Hit, i64)> = snapshot
+                    .search(query, options)?
+                    .into_iter()
+                    .map(|m| (Hit::new(&snapshot, m.entry), m.score))
+                    .collect();
+                Hit::attach_entry_points(
+                    matches.iter_mut().map(|(hit, _)| hit),
+                    snapshot.entries.iter(),
+                );
+                Ok(Response::Found {
+                    root,
+                    generation,
+                    matches,
+                })
+            }
+            Request::Grep {
+                query,
+                options,
+                filters,
+            } => {
+                let pattern = grep::build_pattern(query, options)?;
+                let filter = snapshot.entry_filter(filters)?;
+                let candidates: Vec<&FileEntry> = snapshot
+                    .grep_candidates(query, options)
+                    .unwrap_or_else(|| snapshot.files().collect())
+                    .into_iter()
+                    .filter(|entry| filter.matches(entry))
+                    .collect();
+                let paths: Vec<PathBuf> =
+                    candidates.iter().map(|entry| entry.path.clone()).collect();
+                let entries: HashMap<&Path, &FileEntry> = candidates
+                    .into_iter()
+                    .map(|entry| (entry.path.as_path(), entry))
+                    .collect();
+                let searched = paths.len();
+                let files = grep::search_files(&paths, &pattern, options)
+                    .into_iter()
+                    .map(|file| (Hit::new(&snapshot, entries[file.path.as_path()]), file))
+                    .collect();
+                Ok(Response::Grepped {
+                    root,
+                    generation,
+                    files,
+                    searched,
+                })
+            }
+        }
+    }
+
+    /// The snapshot to answer from, reopened if a newer generation was saved
+    pub fn snapshot(&self) -> Result<Arc<Snapshot>> {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if Index::current_generation(&self.index_dir)? != Some(snapshot.generation()) {
+            *snapshot = Arc::new(open_snapshot(&self.index_dir)?);
+        }
+        Ok(Arc::clone(&snapshot))
+    }
+}
+
+fn open_snapshot(index_dir: &Path) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::open(index_dir)?;
+    snapshot.load_content(index_dir)?;
+    Ok(snapshot)
+}
+
+/// Answer the single request a client sends, as a line of JSON, with one
+/// line of JSON
+fn handle(server: &Server, reader: impl Read, writer: impl Write) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line)?;
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => server.answer(&request),
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+    let mut writer = BufWriter::new(writer);
+    serde_json::to_writer(&mut writer, &response)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Send `request` to a daemon over a connection and read its response
+fn exchange(reader: impl Read, mut writer: impl Write, request: &Request) -> Result<Response> {
+    serde_json::to_writer(&mut writer, request)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line)?;
+    serde_json::from_str(&line).context("Invalid response from the daemon")
+}
+
+#[cfg(unix)]
+mod socket {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    /// Serve `server` on its index directory's socket until `stop` is set,
+    /// calling `ready` once clients can connect
+    pub fn serve(server: &Server, stop: &AtomicBool, ready: impl FnOnce(&Path)) -> Result<()> {
+        let path = socket_path(&server.index_dir);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("A daemon is already serving {}", server.index_dir.display());
+            }
+            // Left behind by a daemon that was killed
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        // Poll so `stop` is noticed without a connection arriving
+        listener.set_nonblocking(true)?;
+        ready(&path);
+        let result = std::thread::scope(|scope| {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        stream.set_nonblocking(false)?;
+                        scope.spawn(move || handle(server, &stream, &stream));
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(())
+        });
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    /// Send `request` to the daemon serving `index_dir`, or return `None`
+    /// when no daemon is listening
+    pub fn request(index_dir: &Path, request: &Request) -> Result<Option<Response>> {
+        let Ok(stream) = UnixStream::connect(socket_path(index_dir)) else {
+            return Ok(None);
+        };
+        exchange(&stream, &stream, request).map(Some)
+    }
+
+    /// Whether a daemon is serving the index in `index_dir`
+    pub fn is_running(index_dir: &Path) -> bool {
+        UnixStream::connect(socket_path(index_dir)).is_ok()
+    }
+}
+
+#[cfg(unix)]
+pub use socket::{is_running, request, serve};
+
+/// A named pipe per index directory, polled like the Unix socket: each
+/// instance is created non-blocking so `stop` is noticed between clients,
+/// and switched to blocking once one connects
+#[cfg(windows)]
+mod pipe {
+    use super::*;
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{
+        ERROR_NO_DATA, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, ERROR_PIPE_LISTENING, HANDLE,
+        INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_NOWAIT, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        SetNamedPipeHandleState,
+    };
+
+    /// Bytes each direction of a pipe instance buffers
+    const BUFFER_LEN: u32 = 64 * 1024;
+
+    /// Serve `server` on its index directory's pipe until `stop` is set,
+    /// calling `ready` once clients can connect
+    pub fn serve(server: &Server, stop: &AtomicBool, ready: impl FnOnce(&Path)) -> Result<()> {
+        let path = socket_path(&server.index_dir);
+        // Only one process may create the first instance of a pipe name
+        let mut pipe = create(&path, true).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => {
+                anyhow::anyhow!("A daemon is already serving {}", server.index_dir.display())
+            }
+            _ => anyhow::Error::new(e).context(format!("Failed to listen on {}", path.display())),
+        })?;
+        ready(&path);
+        std::thread::scope(|scope| {
+            while !stop.load(Ordering::Relaxed) {
+                // SAFETY: `pipe` is a valid pipe handle this loop owns
+                if unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } == 0 {
+                    let error = io::Error::last_os_error();
+                    match error.raw_os_error().map(|code| code as u32) {
+                        Some(ERROR_PIPE_CONNECTED) => {}
+                        Some(ERROR_PIPE_LISTENING) => {
+                            std::thread::sleep(Duration::from_millis(50));
+                            continue;
+                        }
+                        // A client that connected and left before being served
+                        Some(ERROR_NO_DATA) => {
+                            // SAFETY: as above
+                            unsafe { DisconnectNamedPipe(pipe) };
+                            continue;
+                        }
+                        _ => {
+                            close(pipe);
+                            return Err(error.into());
+                        }
+                    }
+                }
+                let mode = PIPE_READMODE_BYTE | PIPE_WAIT;
+                // SAFETY: as above, with `mode` outliving the call
+                if unsafe {
+                    SetNamedPipeHandleState(pipe, &mode, std::ptr::null(), std::ptr::null())
+                } == 0
+                {
+                    let error = io::Error::last_os_error();
+                    close(pipe);
+                    return Err(error.into());
+                }
+                // SAFETY: the handle is valid and owned by the file from here on
+                let file = unsafe { File::from_raw_handle(pipe) };
+                scope.spawn(move || {
+                    let answered = handle(server, &file, &file);
+                    // Closing before the client has read it would discard the response
+                    let _ = file.sync_all();
+                    answered
+                });
+                pipe = create(&path, false)?;
+            }
+            close(pipe);
+            Ok(())
+        })
+    }
+
+    /// Send `request` to the daemon serving `index_dir`, or return `None`
+    /// when no daemon is listening
+    pub fn request(index_dir: &Path, request: &Request) -> Result<Option<Response>> {
+        let Some(file) = connect(index_dir)? else {
+            return Ok(None);
+        };
+        exchange(&file, &file, request).map(Some)
+    }
+
+    /// Whether a daemon is serving the index in `index_dir`
+    pub fn is_running(index_dir: &Path) -> bool {
+        connect(index_dir).is_ok_and(|file| file.is_some())
+    }
+
+    /// The client end of the pipe serving `index_dir`, waiting out a
+    /// daemon busy setting up the next instance; `None` when there's none
+    fn connect(index_dir: &Path) -> io::Result<Option<File>> {
+        let path = socket_path(index_dir);
+        for _ in 0..40 {
+            match File::options().read(true).write(true).open(&path) {
+                Ok(file) => return Ok(Some(file)),
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                    std::thread::sleep(Duration::from_millis(25));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// A new, non-blocking instance of the pipe at `path`, refused to
+    /// clients on other machines
+    fn create(path: &Path, first: bool) -> io::Result<HANDLE> {
+        let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        let pipe_mode =
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_NOWAIT | PIPE_REJECT_REMOTE_CLIENTS;
+        // SAFETY: `name` is NUL-terminated and outlives the call
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                pipe_mode,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_LEN,
+                BUFFER_LEN,
+                0,
+                std::ptr::null(),
+            )
+        };
+        match pipe == INVALID_HANDLE_VALUE {
+            true => Err(io::Error::last_os_error()),
+            false => Ok(pipe),
+        }
+    }
+
+    fn close(pipe: HANDLE) {
+        // SAFETY: `pipe` is valid and not used again
+        drop(unsafe { File::from_raw_handle(pipe) });
+    }
+}
+
+#[cfg(windows)]
+pub use pipe::{is_running, request, serve};
+
+#[cfg(not(any(unix, windows)))]
+pub fn is_running(_: &Path) -> bool {
+    false
+}
+
+/// Serving needs Unix domain sockets or Windows named pipes
+#[cfg(not(any(unix, windows)))]
+pub fn serve(_: &Server, _: &AtomicBool, _: impl FnOnce(&Path)) -> Result<()> {
+    anyhow::bail!("`ss daemon` needs Unix domain sockets or named pipes, which this platform lacks")
+}
+
+/// Without a daemon every request is answered by loading the index locally
+#[cfg(not(any(unix, windows)))]
+pub fn request(_: &Path, _: &Request) -> Result<Option<Response>> {
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::scanner;
+    use std::fs;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[test]
+    fn test_daemon_answers_and_reloads() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("budget.txt"), "total = 42").unwrap();
+        let mut index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        index.save(index_dir.path()).unwrap();
+
+        let find = Request::Find {
+            query: "budget".to_string(),
+            options: SearchOptions::default(),
+        };
+        assert!(request(index_dir.path(), &find).unwrap().is_none());
+
+        let server = Server::open(index_dir.path()).unwrap();
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let (ready, listening) = std::sync::mpsc::channel();
+            let serving = scope.spawn(|| serve(&server, &stop, move |_| ready.send(()).unwrap()));
+            listening.recv_timeout(Duration::from_secs(10)).unwrap();
+            let Some(Response::Found { matches, .. }) = request(index_dir.path(), &find).unwrap()
+            else {
+                panic!("expected find results");
+            };
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].0.entry.name, "budget.txt");
+
+            // A newer generation is picked up without restarting
+            fs::write(root.path().join("budget.md"), "total = 7").unwrap();
+            index.update().unwrap();
+            index.save(index_dir.path()).unwrap();
+            let grep = Request::Grep {
+                query: "total".to_string(),
+                options: GrepOptions::default(),
+                filters: Filters::default(),
+            };
+            let Some(Response::Grepped {
+                files, generation, ..
+            }) = request(index_dir.path(), &grep).unwrap()
+            else {
+                panic!("expected grep results");
+            };
+            assert_eq!(files.len(), 2);
+            assert_eq!(generation, server.generation());
+
+            let invalid = Request::Grep {
+                query: "(".to_string(),
+                options: GrepOptions::default(),
+                filters: Filters::default(),
+            };
+            let response = request(index_dir.path(), &invalid).unwrap();
+            assert!(matches!(response, Some(Response::Error(_))));
+
+            let second = serve(&server, &stop, |_| panic!("a second daemon must not start"));
+            assert!(second.is_err());
+            stop.store(true, Ordering::Relaxed);
+            serving.join().unwrap().unwrap();
+        });
+        assert!(!socket_path(index_dir.path()).exists());
+    }
+}
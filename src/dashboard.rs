@@ -0,0 +1,356 @@
+use crate::picker::TerminalGuard;
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+use sonic_search::search::EntryFields;
+use sonic_search::{Index, MappedSnapshot, daemon, scanner, units, watch};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often the dashboard re-reads every index without a key press
+const REFRESH: Duration = Duration::from_secs(2);
+
+/// Number of recent messages listed below the table
+const MESSAGES: usize = 5;
+
+/// What a key press asks the dashboard to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Redraw,
+    /// Re-read the status of every index
+    Refresh,
+    /// Update the selected index from its root
+    Rescan,
+    /// Remove the superseded generations of the selected index
+    Compact,
+    Quit,
+}
+
+/// The state of one index as the dashboard shows it
+#[derive(Debug)]
+struct IndexStatus {
+    index_dir: PathBuf,
+    /// Details from the current generation, or why it couldn't be opened
+    summary: std::result::Result<Summary, String>,
+    daemon: bool,
+    watched: bool,
+    /// Bytes taken by generations older than the current one
+    superseded: u64,
+}
+
+#[derive(Debug)]
+struct Summary {
+    root: PathBuf,
+    generation: u64,
+    files: usize,
+    /// Seconds since the index was last updated
+    age_secs: u64,
+    partial: bool,
+}
+
+impl IndexStatus {
+    fn read(index_dir: &Path) -> Self {
+        let summary = MappedSnapshot::open(index_dir)
+            .map(|snapshot| {
+                let meta = &snapshot.index().meta;
+                Summary {
+                    root: meta.root.clone(),
+                    generation: snapshot.generation(),
+                    files: snapshot.entries().iter().filter(|e| !e.is_dir()).count(),
+                    age_secs: meta.updated_at.elapsed().unwrap_or_default().as_secs(),
+                    partial: meta.partial,
+                }
+            })
+            .map_err(|e| format!("{:#}", e));
+        IndexStatus {
+            index_dir: index_dir.to_path_buf(),
+            summary,
+            daemon: daemon::is_running(index_dir),
+            watched: watch::is_watched(index_dir),
+            superseded: Index::superseded_size(index_dir).unwrap_or(0),
+        }
+    }
+
+    /// The root, or the index directory when the index can't be read
+    fn name(&self) -> String {
+        match &self.summary {
+            Ok(summary) => summary.root.display().to_string(),
+            Err(_) => self.index_dir.display().to_string(),
+        }
+    }
+}
+
+/// Selection and recent messages, kept apart from the terminal so key
+/// handling can be tested
+#[derive(Debug, Default)]
+struct DashboardState {
+    selected: usize,
+    /// Outcomes of quick actions, newest last
+    messages: VecDeque<String>,
+}
+
+impl DashboardState {
+    fn handle_key(&mut self, key: KeyEvent, index_count: usize) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Action::Quit,
+            KeyCode::Char('c') if ctrl => Action::Quit,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1, index_count),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1, index_count),
+            KeyCode::Char('r') if index_count > 0 => Action::Rescan,
+            KeyCode::Char('c') if index_count > 0 => Action::Compact,
+            KeyCode::Char('g') | KeyCode::F(5) => Action::Refresh,
+            _ => Action::Redraw,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize, index_count: usize) -> Action {
+        if index_count > 0 {
+            self.selected = self
+                .selected
+                .saturating_add_signed(delta)
+                .min(index_count - 1);
+        }
+        Action::Redraw
+    }
+
+    fn note(&mut self, message: String) {
+        if self.messages.len() == MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+}
+
+/// Show the health of every index in `index_dirs` until the user quits,
+/// rescanning or compacting the selected one on request.
+///
+/// The screen is drawn on stderr, like the picker's.
+pub fn run(index_dirs: &[PathBuf]) -> Result<()> {
+    let _guard = TerminalGuard::enter()?;
+    let mut state = DashboardState::default();
+    let mut statuses = read_all(index_dirs);
+    loop {
+        draw(&state, &statuses)?;
+        if !event::poll(REFRESH)? {
+            statuses = read_all(index_dirs);
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let action = state.handle_key(key, statuses.len());
+        let selected = statuses.get(state.selected);
+        match (action, selected) {
+            (Action::Quit, _) => return Ok(()),
+            (Action::Redraw, _) => continue,
+            (Action::Refresh, _) => {}
+            (Action::Rescan, Some(status)) => {
+                let name = status.name();
+                state.note(format!("⏳ Rescanning {}…", name));
+                draw(&state, &statuses)?;
+                state.messages.pop_back();
+                let message = match rescan(&status.index_dir) {
+                    Ok(message) => format!("🔄 {}: {}", name, message),
+                    Err(e) => format!("⚠️  {}: {:#}", name, e),
+                };
+                state.note(message);
+            }
+            (Action::Compact, Some(status)) => {
+                let message = match Index::compact(&status.index_dir) {
+                    Ok(freed) => format!(
+                        "🧹 {}: freed {}",
+                        status.name(),
+                        scanner::format_size(freed)
+                    ),
+                    Err(e) => format!("⚠️  {}: {:#}", status.name(), e),
+                };
+                state.note(message);
+            }
+            (Action::Rescan | Action::Compact, None) => {}
+        }
+        statuses = read_all(index_dirs);
+    }
+}
+
+fn read_all(index_dirs: &[PathBuf]) -> Vec<IndexStatus> {
+    index_dirs
+        .iter()
+        .map(|dir| IndexStatus::read(dir))
+        .collect()
+}
+
+/// Update the index in `index_dir` from its root, describing what changed
+fn rescan(index_dir: &Path) -> Result<String> {
+    let mut index = Index::load(index_dir)?;
+    index.load_content(index_dir)?;
+    let report = index.update_in(index_dir)?;
+    let generation = index.save(index_dir)?;
+    Ok(format!(
+        "+{} ~{} -{} (generation {})",
+        report.added, report.modified, report.removed, generation
+    ))
+}
+
+fn draw(state: &DashboardState, statuses: &[IndexStatus]) -> Result<()> {
+    let mut out = io::stderr().lock();
+    let (width, height) = match terminal::size()? {
+        // Some pseudo-terminals report no size at all
+        (0, _) | (_, 0) => (80, 24),
+        size => size,
+    };
+    let width = width as usize;
+    // Everything but the root column, which gets the rest of the width
+    const COLUMNS: usize = 6 + 10 + 9 + 8 + 7 + 6 + 10;
+    let root_width = width.saturating_sub(COLUMNS + 2).max(12);
+    let fit = |text: &str, limit: usize| -> String {
+        let count = text.chars().count();
+        if count <= limit {
+            return text.to_string();
+        }
+        // Keep the end of long paths, which tells roots apart
+        let tail: String = text.chars().skip(count + 1 - limit).collect();
+        format!("…{}", tail)
+    };
+
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        SetAttribute(Attribute::Bold),
+        Print(format!(
+            "  {:<root_width$}{:>6}{:>10}{:>9}{:>8}{:>7}{:>6}{:>10}",
+            "ROOT", "GEN", "FILES", "UPDATED", "STATE", "DAEMON", "WATCH", "OLD GENS"
+        )),
+        SetAttribute(Attribute::Reset),
+    )?;
+    let rows = (height as usize).saturating_sub(MESSAGES + 4);
+    let first = state.selected.saturating_sub(rows.saturating_sub(1));
+    for (row, status) in statuses.iter().enumerate().skip(first).take(rows) {
+        let yes_no = |on: bool| if on { "yes" } else { "no" };
+        let name = fit(&status.name(), root_width.saturating_sub(1));
+        let line = match &status.summary {
+            Ok(summary) => format!(
+                "{:<root_width$}{:>6}{:>10}{:>9}{:>8}{:>7}{:>6}{:>10}",
+                name,
+                summary.generation,
+                summary.files,
+                units::format_age(summary.age_secs),
+                if summary.partial { "partial" } else { "ok" },
+                yes_no(status.daemon),
+                yes_no(status.watched),
+                scanner::format_size(status.superseded),
+            ),
+            Err(e) => format!("{:<root_width$}  {}", name, e),
+        };
+        let line: String = line.chars().take(width.saturating_sub(2)).collect();
+        queue!(out, cursor::MoveTo(0, (row - first + 1) as u16))?;
+        if row == state.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", line)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(format!("  {}", line)))?;
+        }
+    }
+    if statuses.is_empty() {
+        queue!(
+            out,
+            cursor::MoveTo(0, 1),
+            Print("  No indexes yet; create one with `ss scan <dir>`")
+        )?;
+    }
+
+    let bottom = height.saturating_sub(1);
+    let messages_top = bottom.saturating_sub(state.messages.len() as u16 + 1);
+    for (i, message) in state.messages.iter().enumerate() {
+        let line: String = message.chars().take(width.saturating_sub(2)).collect();
+        queue!(
+            out,
+            cursor::MoveTo(0, messages_top + i as u16),
+            Print(format!("  {}", line))
+        )?;
+    }
+    queue!(
+        out,
+        cursor::MoveTo(0, bottom),
+        SetAttribute(Attribute::Dim),
+        Print("  ↑/↓ select · r rescan · c compact · g refresh · q quit"),
+        SetAttribute(Attribute::Reset),
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_keys_and_messages() {
+        let mut state = DashboardState::default();
+        assert_eq!(state.handle_key(key(KeyCode::Down), 3), Action::Redraw);
+        state.handle_key(key(KeyCode::Char('j')), 3);
+        state.handle_key(key(KeyCode::Char('j')), 3);
+        assert_eq!(state.selected, 2);
+        state.handle_key(key(KeyCode::Up), 3);
+        assert_eq!(state.selected, 1);
+        assert_eq!(state.handle_key(key(KeyCode::Char('r')), 3), Action::Rescan);
+        assert_eq!(
+            state.handle_key(key(KeyCode::Char('c')), 3),
+            Action::Compact
+        );
+        assert_eq!(state.handle_key(key(KeyCode::Char('r')), 0), Action::Redraw);
+        assert_eq!(state.handle_key(key(KeyCode::F(5)), 0), Action::Refresh);
+        assert_eq!(state.handle_key(key(KeyCode::Char('q')), 0), Action::Quit);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(state.handle_key(ctrl_c, 3), Action::Quit);
+
+        for i in 0..MESSAGES + 2 {
+            state.note(i.to_string());
+        }
+        assert_eq!(state.messages.len(), MESSAGES);
+        assert_eq!(state.messages.back().unwrap(), &(MESSAGES + 1).to_string());
+    }
+
+    #[test]
+    fn test_index_status() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let mut index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        index.save(index_dir.path()).unwrap();
+        index.save(index_dir.path()).unwrap();
+
+        let status = IndexStatus::read(index_dir.path());
+        let summary = status.summary.as_ref().unwrap();
+        assert_eq!((summary.generation, summary.files), (2, 1));
+        assert!(!summary.partial);
+        assert!(!status.daemon && !status.watched);
+        assert!(status.superseded > 0);
+        assert_eq!(status.name(), index.meta.root.display().to_string());
+
+        assert!(
+            rescan(index_dir.path())
+                .unwrap()
+                .ends_with("(generation 3)")
+        );
+        let missing = IndexStatus::read(&index_dir.path().join("missing"));
+        assert!(missing.summary.is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::scanner::FileEntry;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How an entry differs between two snapshots of an index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Larger than before
+    Grown,
+    /// Smaller than before
+    Shrunk,
+    /// The same size, with a new modification time or content hash
+    Modified,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Grown => "grown",
+            ChangeKind::Shrunk => "shrunk",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+/// An entry that differs between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Size in the older snapshot, unless the entry was added
+    pub old_size: Option<u64>,
+    /// Size in the newer snapshot, unless the entry was removed
+    pub new_size: Option<u64>,
+    /// Modification time in the newer snapshot, or the older one for a
+    /// removed entry
+    pub modified: Option<SystemTime>,
+}
+
+impl Change {
+    /// Bytes gained, negative when the entry shrank or was removed
+    pub fn growth(&self) -> i64 {
+        self.new_size.unwrap_or(0) as i64 - self.old_size.unwrap_or(0) as i64
+    }
+}
+
+/// The entries that differ between the `old` and `new` snapshots, sorted
+/// by path.
+///
+/// Directories only count as added or removed, since their modification
+/// time changes with whatever is added to or removed from them. Files
+/// hashed in both snapshots are compared by hash rather than by mtime, so
+/// a touched file isn't reported.
+pub fn compare<'a>(
+    old: impl IntoIterator<Item = &'a FileEntry>,
+    new: impl IntoIterator<Item = &'a FileEntry>,
+) -> Vec<Change> {
+    let mut before: HashMap<&Path, &FileEntry> = old
+        .into_iter()
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    let mut changes = Vec::new();
+    for entry in new {
+        match before.remove(entry.path.as_path()) {
+            None => changes.push(added(entry)),
+            // Replaced by something of another type
+            Some(previous) if previous.is_dir != entry.is_dir => {
+                changes.push(removed(previous));
+                changes.push(added(entry));
+            }
+            Some(previous) => {
+                if let Some(kind) = difference(previous, entry) {
+                    changes.push(Change {
+                        kind,
+                        path: entry.path.clone(),
+                        is_dir: entry.is_dir,
+                        old_size: Some(previous.size),
+                        new_size: Some(entry.size),
+                        modified: entry.modified,
+                    });
+                }
+            }
+        }
+    }
+    changes.extend(before.into_values().map(removed));
+    changes.sort_by(|a, b| a.path.cmp(&b.path).then(a.kind.cmp(&b.kind)));
+    changes
+}
+
+/// How the file `previous` became `entry`, if it changed
+fn difference(previous: &FileEntry, entry: &FileEntry) -> Option<ChangeKind> {
+    if entry.is_dir {
+        return None;
+    }
+    match entry.size.cmp(&previous.size) {
+        Ordering::Greater => Some(ChangeKind::Grown),
+        Ordering::Less => Some(ChangeKind::Shrunk),
+        Ordering::Equal => {
+            let rewritten = match (&previous.hash, &entry.hash) {
+                (Some(before), Some(after)) => before != after,
+                _ => previous.modified != entry.modified,
+            };
+            rewritten.then_some(ChangeKind::Modified)
+        }
+    }
+}
+
+fn added(entry: &FileEntry) -> Change {
+    Change {
+        kind: ChangeKind::Added,
+        path: entry.path.clone(),
+        is_dir: entry.is_dir,
+        old_size: None,
+        new_size: Some(entry.size),
+        modified: entry.modified,
+    }
+}
+
+fn removed(entry: &FileEntry) -> Change {
+    Change {
+        kind: ChangeKind::Removed,
+        path: entry.path.clone(),
+        is_dir: entry.is_dir,
+        old_size: Some(entry.size),
+        new_size: None,
+        modified: entry.modified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn entry(path: &str, size: u64, secs: u64, hash: Option<u8>) -> FileEntry {
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(secs));
+        FileEntry {
+            hash: hash.map(|byte| [byte; 32]),
+            ..FileEntry::new(path, path.ends_with("dir"), size, modified)
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = [
+            entry("/r/dir", 0, 1, None),
+            entry("/r/app.log", 100, 1, None),
+            entry("/r/cache.db", 800, 1, None),
+            entry("/r/config.toml", 50, 1, None),
+            entry("/r/notes.txt", 10, 1, Some(7)),
+            entry("/r/old.txt", 30, 1, None),
+            entry("/r/same.txt", 5, 1, None),
+        ];
+        let new = [
+            entry("/r/dir", 0, 2, None),
+            entry("/r/app.log", 900, 2, None),
+            entry("/r/cache.db", 200, 2, None),
+            entry("/r/config.toml", 50, 2, None),
+            // Touched, but hashed the same
+            entry("/r/notes.txt", 10, 2, Some(7)),
+            entry("/r/same.txt", 5, 1, None),
+            entry("/r/new.rs", 70, 2, None),
+        ];
+        let changes = compare(&old, &new);
+        let kinds: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|change| (change.path.to_str().unwrap(), change.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("/r/app.log", ChangeKind::Grown),
+                ("/r/cache.db", ChangeKind::Shrunk),
+                ("/r/config.toml", ChangeKind::Modified),
+                ("/r/new.rs", ChangeKind::Added),
+                ("/r/old.txt", ChangeKind::Removed),
+            ]
+        );
+        assert_eq!(changes[0].growth(), 800);
+        assert_eq!((changes[3].old_size, changes[3].new_size), (None, Some(70)));
+        assert_eq!((changes[4].old_size, changes[4].new_size), (Some(30), None));
+        assert_eq!(changes[4].growth(), -30);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::archive;
+use crate::scanner::FileEntry;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Number and total size of the indexed files below one directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirUsage {
+    pub path: PathBuf,
+    /// Levels below the directory the report starts from
+    pub depth: usize,
+    pub files: usize,
+    pub size: u64,
+}
+
+/// How much the files among `entries` take up in `top` and each directory
+/// below it down to `depth` levels, in the order of a tree: every directory
+/// followed by its subdirectories, biggest first. With `limit`, only that
+/// many of the biggest subdirectories of each are listed.
+///
+/// Files inside archives are left out, since the archive itself counts.
+pub fn usage<'a>(
+    entries: impl IntoIterator<Item = &'a FileEntry>,
+    top: &Path,
+    depth: usize,
+    limit: Option<usize>,
+) -> Vec<DirUsage> {
+    let mut totals: HashMap<&Path, (usize, u64)> = HashMap::new();
+    for entry in entries {
+        if entry.is_dir || archive::split(&entry.path).is_some() {
+            continue;
+        }
+        let Ok(relative) = entry.path.strip_prefix(top) else {
+            continue;
+        };
+        let levels = relative.components().count().saturating_sub(1);
+        // The file's ancestors from `top` down to `depth`
+        let skipped = levels.saturating_sub(depth);
+        for dir in entry
+            .path
+            .ancestors()
+            .skip(1 + skipped)
+            .take(levels + 1 - skipped)
+        {
+            let total = totals.entry(dir).or_default();
+            total.0 += 1;
+            total.1 += entry.size;
+        }
+    }
+
+    let mut children: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for &dir in totals.keys() {
+        if dir != top
+            && let Some(parent) = dir.parent()
+        {
+            children.entry(parent).or_default().push(dir);
+        }
+    }
+    let mut rows = Vec::with_capacity(totals.len());
+    let mut pending = vec![(top, 0)];
+    while let Some((dir, level)) = pending.pop() {
+        let Some(&(files, size)) = totals.get(dir) else {
+            continue;
+        };
+        rows.push(DirUsage {
+            path: dir.to_path_buf(),
+            depth: level,
+            files,
+            size,
+        });
+        let mut below = children.remove(dir).unwrap_or_default();
+        below.sort_by(|a, b| totals[b].1.cmp(&totals[a].1).then_with(|| a.cmp(b)));
+        below.truncate(limit.unwrap_or(usize::MAX));
+        // Pushed smallest first, so the biggest is listed next
+        pending.extend(below.into_iter().rev().map(|child| (child, level + 1)));
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> FileEntry {
+        FileEntry::new(path, false, size, None)
+    }
+
+    #[test]
+    fn test_usage_tree() {
+        let entries = [
+            file("/r/notes.txt", 5),
+            file("/r/photos/2023/a.jpg", 300),
+            file("/r/photos/2024/b.jpg", 500),
+            file("/r/photos/2024/trip/c.jpg", 200),
+            file("/r/src/main.rs", 40),
+            file("/r/src/lib.rs", 60),
+            file("/r/backup.zip", 900),
+            file("/r/backup.zip!/old/d.txt", 4000),
+            file("/elsewhere/e.txt", 1),
+        ];
+        let rows = usage(&entries, Path::new("/r"), 2, None);
+        let tree: Vec<(&str, usize, usize, u64)> = rows
+            .iter()
+            .map(|row| (row.path.to_str().unwrap(), row.depth, row.files, row.size))
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                ("/r", 0, 7, 2005),
+                ("/r/photos", 1, 3, 1000),
+                // trip/ is deeper than 2 levels but counts towards 2024/
+                ("/r/photos/2024", 2, 2, 700),
+                ("/r/photos/2023", 2, 1, 300),
+                ("/r/src", 1, 2, 100),
+            ]
+        );
+
+        let rows = usage(&entries, Path::new("/r/photos"), 5, Some(1));
+        let paths: Vec<&str> = rows.iter().map(|row| row.path.to_str().unwrap()).collect();
+        assert_eq!(
+            paths,
+            ["/r/photos", "/r/photos/2024", "/r/photos/2024/trip"]
+        );
+        assert!(usage(&entries, Path::new("/nowhere"), 1, None).is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::scanner::{self, FileEntry};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Bytes hashed in the quick pass that weeds out files differing early on
+const PREFIX_LEN: u64 = 4096;
+
+/// Files with identical contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Size of each file in bytes
+    pub size: u64,
+    /// BLAKE3 hash of the contents, in hex
+    pub hash: String,
+    /// The identical files, sorted
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Bytes freed by keeping only one of the files
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// BLAKE3 hash of the contents of `path`, in hex
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    Ok(scanner::hash_hex(&scanner::content_hash(path)?))
+}
+
+fn hash_prefix(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?.take(PREFIX_LEN))?;
+    Ok(hasher.finalize())
+}
+
+/// Find the files among `entries` whose contents are identical, most
+/// reclaimable space first.
+///
+/// Only files sharing a size are read: first their leading bytes, then in
+/// full for those that still agree, in parallel. Hashes stored in the index
+/// are used instead of reading files in full. Empty files and files that
+/// can't be read are skipped.
+pub fn find_duplicates(entries: &[&FileEntry]) -> Vec<DuplicateGroup> {
+    let mut by_size: HashMap<u64, Vec<&FileEntry>> = HashMap::new();
+    for entry in entries.iter().filter(|e| !e.is_dir && e.size > 0) {
+        by_size.entry(entry.size).or_default().push(entry);
+    }
+    let candidates: Vec<&FileEntry> = by_size
+        .into_values()
+        .filter(|entries| entries.len() > 1)
+        .flatten()
+        .collect();
+
+    // Stored hashes make the quick pass pointless
+    let (hashed, unhashed): (Vec<&FileEntry>, Vec<&FileEntry>) = candidates
+        .into_iter()
+        .partition(|entry| entry.hash.is_some());
+    let prefixes: Vec<((u64, blake3::Hash), &FileEntry)> = unhashed
+        .par_iter()
+        .filter_map(|&entry| Some(((entry.size, hash_prefix(&entry.path).ok()?), entry)))
+        .collect();
+    let candidates: Vec<&FileEntry> = group(prefixes)
+        .into_iter()
+        .flat_map(|(_, entries)| entries)
+        .chain(hashed)
+        .collect();
+
+    let hashes: Vec<((u64, [u8; 32]), &FileEntry)> = candidates
+        .par_iter()
+        .filter_map(|&entry| {
+            let hash = match entry.hash {
+                Some(hash) => hash,
+                None => scanner::content_hash(&entry.path).ok()?,
+            };
+            Some(((entry.size, hash), entry))
+        })
+        .collect();
+    let mut groups: Vec<DuplicateGroup> = group(hashes)
+        .into_iter()
+        .map(|((size, hash), entries)| {
+            let mut paths: Vec<PathBuf> = entries.iter().map(|e| e.path.clone()).collect();
+            paths.sort();
+            DuplicateGroup {
+                size,
+                hash: scanner::hash_hex(&hash),
+                paths,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.reclaimable()
+            .cmp(&a.reclaimable())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    groups
+}
+
+/// The indexed files with the same contents as one file, found by
+/// [`copies_of`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Copies {
+    pub size: u64,
+    /// BLAKE3 hash of the contents, in hex
+    pub hash: String,
+    /// The copies, sorted
+    pub copies: Vec<FoundCopy>,
+}
+
+/// One file found by [`copies_of`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundCopy {
+    pub path: PathBuf,
+    /// Its contents were read to compare them, rather than trusting the
+    /// hash stored in the index
+    pub verified: bool,
+}
+
+/// Find the files among `entries` whose contents are those of `original`,
+/// leaving `original` itself out.
+///
+/// Only files of the same size are compared, by the hash stored in the
+/// index where there is one; with `verify`, or without a stored hash, they
+/// are read in full, in parallel. Files that can't be read are skipped.
+pub fn copies_of(original: &Path, entries: &[&FileEntry], verify: bool) -> io::Result<Copies> {
+    let size = original.metadata()?.len();
+    let hash = scanner::content_hash(original)?;
+    let original = dunce::canonicalize(original)?;
+    let mut copies: Vec<FoundCopy> = entries
+        .par_iter()
+        .filter(|entry| !entry.is_dir && entry.size == size && entry.path != original)
+        .filter_map(|entry| {
+            let (found, verified) = match entry.hash {
+                Some(stored) if !verify => (stored, false),
+                _ => (scanner::content_hash(&entry.path).ok()?, true),
+            };
+            (found == hash).then(|| FoundCopy {
+                path: entry.path.clone(),
+                verified,
+            })
+        })
+        .collect();
+    copies.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Copies {
+        size,
+        hash: scanner::hash_hex(&hash),
+        copies,
+    })
+}
+
+/// Group entries by key, keeping only keys shared by several entries
+fn group<K: std::hash::Hash + Eq>(keyed: Vec<(K, &FileEntry)>) -> Vec<(K, Vec<&FileEntry>)> {
+    let mut groups: HashMap<K, Vec<&FileEntry>> = HashMap::new();
+    for (key, entry) in keyed {
+        groups.entry(key).or_default().push(entry);
+    }
+    groups
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+    use std::fs;
+
+    #[test]
+    fn test_find_duplicates() {
+        let root = tempfile::tempdir().unwrap();
+        let long = "x".repeat(PREFIX_LEN as usize);
+        let files = [
+            ("a.txt", "same contents".to_string()),
+            ("sub/b.txt", "same contents".to_string()),
+            ("sub/c.txt", "same contents".to_string()),
+            ("other.txt", "diff contents".to_string()),
+            ("long1.bin", format!("{}1", long)),
+            ("long2.bin", format!("{}2", long)),
+            ("empty1", String::new()),
+            ("empty2", String::new()),
+        ];
+        for (name, body) in &files {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+        let scan = scanner::scan_directory(root.path()).unwrap();
+        let entries: Vec<&FileEntry> = scan.files.iter().collect();
+
+        let groups = find_duplicates(&entries);
+        assert_eq!(groups.len(), 1);
+        let names: Vec<&str> = groups[0]
+            .paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(groups[0].reclaimable(), 2 * 13);
+        assert_eq!(groups[0].hash, hash_file(&groups[0].paths[0]).unwrap());
+
+        // Stored hashes are trusted without reading the files again
+        let mut stored = scan.files.clone();
+        for entry in &mut stored {
+            entry.hash = Some([entry.name.len() as u8; 32]);
+        }
+        let entries: Vec<&FileEntry> = stored.iter().collect();
+        let groups = find_duplicates(&entries);
+        assert_eq!(groups.len(), 2);
+        // long1.bin and long2.bin differ, but their made-up hashes don't
+        assert_eq!(groups[0].size, PREFIX_LEN + 1);
+    }
+
+    #[test]
+    fn test_copies_of() {
+        let root = tempfile::tempdir().unwrap();
+        for (name, body) in [
+            ("asset.psd", "layers"),
+            ("backup/asset.psd", "layers"),
+            ("old/asset-v1.psd", "layers"),
+            ("other.psd", "flat pixels"),
+        ] {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+        let mut scan = scanner::scan_directory(root.path()).unwrap();
+        let original = root.path().join("asset.psd");
+
+        let entries: Vec<&FileEntry> = scan.files.iter().collect();
+        let found = copies_of(&original, &entries, false).unwrap();
+        assert_eq!(found.hash, hash_file(&original).unwrap());
+        let names: Vec<String> = found
+            .copies
+            .iter()
+            .map(|copy| {
+                copy.path
+                    .strip_prefix(root.path())
+                    .unwrap()
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, ["backup/asset.psd", "old/asset-v1.psd"]);
+        assert!(found.copies.iter().all(|copy| copy.verified));
+
+        // A stored hash is trusted unless asked to verify it
+        fs::write(root.path().join("backup/asset.psd"), "LAYERS").unwrap();
+        let stored = scanner::content_hash(&original).unwrap();
+        for entry in &mut scan.files {
+            entry.hash = Some(stored);
+        }
+        let entries: Vec<&FileEntry> = scan.files.iter().collect();
+        let found = copies_of(&original, &entries, false).unwrap();
+        assert_eq!(found.copies.len(), 2);
+        assert!(!found.copies[0].verified);
+        let found = copies_of(&original, &entries, true).unwrap();
+        assert_eq!(found.copies.len(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! How query terms match names, for `find --matcher`: skim's fuzzy
+//! subsequences by default, or exact substrings, prefixes or regular
+//! expressions when a script wants to know exactly what it gets. With the
+//! `aligned-matcher` feature, subsequences can instead be scored by their
+//! best alignment rather than the first, favouring word and path
+//! boundaries.
+//!
+//! Each engine is a [`FuzzyMatcher`], like the CJK [`crate::cjk::BigramMatcher`],
+//! so scoring, bonuses and highlighting work the same whichever is chosen.
+
+use crate::error::Error;
+use crate::search::CaseMode;
+use anyhow::{Context, Result};
+use fuzzy_matcher::FuzzyMatcher;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Score of each matched character, in line with skim's scores so the
+/// ranking bonuses weigh the same
+const CHAR_SCORE: i64 = 16;
+/// Extra score for a match at the very start of the name or path
+const START_BONUS: i64 = 32;
+
+/// A way of matching query terms
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// The term's characters in order, anywhere in the name
+    #[default]
+    Fuzzy,
+    /// The term as a substring of the name
+    Exact,
+    /// Names starting with the term
+    Prefix,
+    /// The term as a regular expression searched for in the name
+    Regex,
+    /// The term's characters in order, at the places scoring best
+    #[cfg(feature = "aligned-matcher")]
+    Aligned,
+}
+
+impl Engine {
+    /// A matcher for `term` honouring `case`. Fails for a regex that
+    /// doesn't compile.
+    pub(crate) fn matcher(self, term: &str, case: CaseMode) -> Result<Box<dyn FuzzyMatcher>> {
+        let ignore_case = case.ignores_case(term);
+        Ok(match self {
+            Engine::Fuzzy => case.matcher(term),
+            Engine::Exact => Box::new(Substring {
+                ignore_case,
+                prefix: false,
+            }),
+            Engine::Prefix => Box::new(Substring {
+                ignore_case,
+                prefix: true,
+            }),
+            Engine::Regex => Box::new(RegexMatcher::new(term, ignore_case)?),
+            #[cfg(feature = "aligned-matcher")]
+            Engine::Aligned => Box::new(aligned::Aligned { ignore_case }),
+        })
+    }
+}
+
+/// Matches the pattern as a whole, anywhere or only at the start
+struct Substring {
+    ignore_case: bool,
+    prefix: bool,
+}
+
+impl Substring {
+    fn chars(&self, text: &str) -> Vec<char> {
+        text.chars()
+            .map(|c| match self.ignore_case {
+                // One character for each, so indices stay those of `text`
+                true => c.to_lowercase().next().unwrap_or(c),
+                false => c,
+            })
+            .collect()
+    }
+}
+
+impl FuzzyMatcher for Substring {
+    fn fuzzy_indices(&self, choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+        let text = self.chars(choice);
+        let pattern = self.chars(pattern);
+        let start = match self.prefix {
+            true => text.starts_with(&pattern).then_some(0)?,
+            false if pattern.is_empty() => 0,
+            false => text
+                .windows(pattern.len())
+                .position(|window| window == pattern.as_slice())?,
+        };
+        let bonus = if start == 0 { START_BONUS } else { 0 };
+        let score = pattern.len() as i64 * CHAR_SCORE + bonus;
+        Some((score, (start..start + pattern.len()).collect()))
+    }
+}
+
+/// Matches a regular expression compiled once for the term; the pattern
+/// passed with each name is that term, so it's ignored
+struct RegexMatcher {
+    regex: Regex,
+}
+
+impl RegexMatcher {
+    fn new(term: &str, ignore_case: bool) -> Result<Self> {
+        let regex = RegexBuilder::new(term)
+            .case_insensitive(ignore_case)
+            .build()
+            .with_context(|| Error::query("--matcher regex", term))?;
+        Ok(RegexMatcher { regex })
+    }
+}
+
+impl FuzzyMatcher for RegexMatcher {
+    fn fuzzy_indices(&self, choice: &str, _pattern: &str) -> Option<(i64, Vec<usize>)> {
+        let found = self.regex.find(choice)?;
+        let start = choice[..found.start()].chars().count();
+        let len = found.as_str().chars().count();
+        let bonus = if start == 0 { START_BONUS } else { 0 };
+        let score = len as i64 * CHAR_SCORE + bonus;
+        Some((score, (start..start + len).collect()))
+    }
+}
+
+#[cfg(feature = "aligned-matcher")]
+mod aligned {
+    use super::FuzzyMatcher;
+
+    // A gap costs less than a boundary is worth, so a match spread over
+    // word starts beats one bunched mid-word
+    const SCORE_MATCH: i64 = 16;
+    const PENALTY_GAP_START: i64 = 3;
+    const PENALTY_GAP_EXTENSION: i64 = 1;
+    const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+    const BONUS_BOUNDARY_WHITE: i64 = BONUS_BOUNDARY + 2;
+    const BONUS_BOUNDARY_DELIMITER: i64 = BONUS_BOUNDARY + 1;
+    const BONUS_NON_WORD: i64 = BONUS_BOUNDARY;
+    const BONUS_CAMEL123: i64 = BONUS_BOUNDARY - PENALTY_GAP_START;
+    const BONUS_CONSECUTIVE: i64 = PENALTY_GAP_START + PENALTY_GAP_EXTENSION;
+    const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Class {
+        White,
+        NonWord,
+        Delimiter,
+        Lower,
+        Upper,
+        Number,
+    }
+
+    fn class(c: char) -> Class {
+        match c {
+            _ if c.is_whitespace() => Class::White,
+            '/' | '\\' | ',' | ':' | ';' | '|' => Class::Delimiter,
+            _ if c.is_numeric() => Class::Number,
+            _ if c.is_uppercase() => Class::Upper,
+            _ if c.is_alphabetic() => Class::Lower,
+            _ => Class::NonWord,
+        }
+    }
+
+    /// What matching a character of class `current` after one of class
+    /// `prev` is worth on top of [`SCORE_MATCH`]
+    fn bonus(prev: Class, current: Class) -> i64 {
+        match current {
+            Class::White => BONUS_BOUNDARY_WHITE,
+            Class::NonWord | Class::Delimiter => BONUS_NON_WORD,
+            _ => match prev {
+                Class::White => BONUS_BOUNDARY_WHITE,
+                Class::Delimiter => BONUS_BOUNDARY_DELIMITER,
+                Class::NonWord => BONUS_BOUNDARY,
+                Class::Lower if current == Class::Upper => BONUS_CAMEL123,
+                _ if current == Class::Number && prev != Class::Number => BONUS_CAMEL123,
+                _ => 0,
+            },
+        }
+    }
+
+    /// A matched cell of the alignment: its score, the bonus its run of
+    /// consecutive matches started with, and where the previous
+    /// character of the term matched
+    #[derive(Clone, Copy)]
+    struct Cell {
+        score: i64,
+        first_bonus: i64,
+        from: usize,
+    }
+
+    /// The best-scoring alignment of the term in the name, found by
+    /// dynamic programming over every place each character could match
+    pub(super) struct Aligned {
+        pub(super) ignore_case: bool,
+    }
+
+    impl Aligned {
+        fn fold(&self, c: char) -> char {
+            match self.ignore_case {
+                true => c.to_lowercase().next().unwrap_or(c),
+                false => c,
+            }
+        }
+    }
+
+    impl FuzzyMatcher for Aligned {
+        fn fuzzy_indices(&self, choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+            let text: Vec<char> = choice.chars().collect();
+            let needle: Vec<char> = pattern.chars().map(|c| self.fold(c)).collect();
+            if needle.is_empty() {
+                return Some((0, Vec::new()));
+            }
+            let folded: Vec<char> = text.iter().map(|&c| self.fold(c)).collect();
+            let mut rest = folded.iter();
+            if !needle.iter().all(|c| rest.any(|t| t == c)) {
+                return None;
+            }
+            let mut prev = Class::White;
+            let bonuses: Vec<i64> = text
+                .iter()
+                .map(|&c| {
+                    let current = class(c);
+                    let bonus = bonus(prev, current);
+                    prev = current;
+                    bonus
+                })
+                .collect();
+
+            let mut rows: Vec<Vec<Option<Cell>>> = Vec::with_capacity(needle.len());
+            for &wanted in &needle {
+                let above = rows.last();
+                let mut row = vec![None; text.len()];
+                // Best cell of the row above to reach the next column from
+                // across a gap, with what the gap has cost so far
+                let mut gap: Option<(i64, usize)> = None;
+                for j in 0..text.len() {
+                    if let Some(above) = above
+                        && j >= 2
+                    {
+                        gap = gap.map(|(score, from)| (score - PENALTY_GAP_EXTENSION, from));
+                        if let Some(cell) = above[j - 2] {
+                            let opened = cell.score - PENALTY_GAP_START;
+                            if gap.is_none_or(|(score, _)| opened >= score) {
+                                gap = Some((opened, j - 2));
+                            }
+                        }
+                    }
+                    if folded[j] != wanted {
+                        continue;
+                    }
+                    let bonus = bonuses[j];
+                    let Some(above) = above else {
+                        row[j] = Some(Cell {
+                            score: SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER,
+                            first_bonus: bonus,
+                            from: j,
+                        });
+                        continue;
+                    };
+                    let across = gap.map(|(score, from)| Cell {
+                        score: score + SCORE_MATCH + bonus,
+                        first_bonus: bonus,
+                        from,
+                    });
+                    let consecutive = j.checked_sub(1).and_then(|k| above[k]).map(|cell| {
+                        let first_bonus = match bonus >= BONUS_BOUNDARY {
+                            true => bonus,
+                            false => cell.first_bonus,
+                        };
+                        let run = bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+                        Cell {
+                            score: cell.score + SCORE_MATCH + run,
+                            first_bonus,
+                            from: j - 1,
+                        }
+                    });
+                    row[j] = match (consecutive, across) {
+                        (Some(a), Some(b)) if b.score > a.score => Some(b),
+                        (Some(a), _) => Some(a),
+                        (None, b) => b,
+                    };
+                }
+                rows.push(row);
+            }
+
+            let (mut j, best) = rows
+                .last()?
+                .iter()
+                .enumerate()
+                .filter_map(|(j, cell)| Some((j, (*cell)?)))
+                .max_by_key(|(_, cell)| cell.score)?;
+            let mut indices = vec![0; needle.len()];
+            for i in (0..needle.len()).rev() {
+                indices[i] = j;
+                j = rows[i][j]?.from;
+            }
+            Some((best.score, indices))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(engine: Engine, choice: &str, term: &str) -> Option<i64> {
+        let matcher = engine.matcher(term, CaseMode::Smart).unwrap();
+        matcher.fuzzy_match(choice, term)
+    }
+
+    #[test]
+    fn test_engines() {
+        assert!(score(Engine::Fuzzy, "q3-report.pdf", "qrpt").is_some());
+        assert!(score(Engine::Exact, "q3-report.pdf", "qrpt").is_none());
+        assert!(score(Engine::Exact, "q3-Report.pdf", "report").is_some());
+        assert!(score(Engine::Exact, "q3-Report.pdf", "Report").is_some());
+        // Smart case: an uppercase letter makes the term case-sensitive
+        assert!(score(Engine::Exact, "q3-report.pdf", "Report").is_none());
+        assert!(score(Engine::Prefix, "report.pdf", "rep").is_some());
+        assert!(score(Engine::Prefix, "q3-report.pdf", "rep").is_none());
+        assert!(score(Engine::Regex, "q3-report.pdf", r"^q\d-").is_some());
+        assert!(score(Engine::Regex, "report.pdf", r"^q\d-").is_none());
+        // Matches at the start rank above the same match further in
+        assert!(
+            score(Engine::Exact, "report.pdf", "rep") > score(Engine::Exact, "q3-report", "rep")
+        );
+
+        let matcher = Engine::Regex.matcher("p.f", CaseMode::Smart).unwrap();
+        assert_eq!(
+            matcher.fuzzy_indices("résumé.pdf", "p.f"),
+            Some((3 * CHAR_SCORE, vec![7, 8, 9]))
+        );
+        assert!(Engine::Regex.matcher("[a-", CaseMode::Smart).is_err());
+    }
+
+    #[cfg(feature = "aligned-matcher")]
+    #[test]
+    fn test_aligned() {
+        assert!(score(Engine::Aligned, "q3-report.pdf", "qrpt").is_some());
+        assert!(score(Engine::Aligned, "q3-report.pdf", "qx").is_none());
+        // The best alignment wins, not the first: `fb` at the word starts
+        // of `foo_bar` rather than the `f` of `fab`
+        let matcher = Engine::Aligned.matcher("fb", CaseMode::Smart).unwrap();
+        assert_eq!(
+            matcher.fuzzy_indices("fab/foo_bar", "fb").unwrap().1,
+            vec![4, 8]
+        );
+        assert!(
+            score(Engine::Aligned, "src/main.rs", "main")
+                > score(Engine::Aligned, "domains.rs", "main")
+        );
+        assert!(score(Engine::Aligned, "Report.pdf", "report").is_some());
+        assert!(score(Engine::Aligned, "report.pdf", "Report").is_none());
+    }
+}
@@ -0,0 +1,107 @@
+//! The file to open in a directory `find` matched: its README, or else the
+//! entry point of the code in it, shown as a secondary hit under the
+//! directory.
+
+use crate::search::EntryFields;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Entry points below a directory, the most telling first. Names are
+/// compared ignoring case, so `readme.md` counts as a README.
+const ENTRY_POINTS: &[&str] = &[
+    "README.md",
+    "README.rst",
+    "README.txt",
+    "README",
+    "main.rs",
+    "lib.rs",
+    "index.ts",
+    "index.js",
+    "main.py",
+    "__main__.py",
+    "__init__.py",
+    "main.go",
+    "index.html",
+    "src/main.rs",
+    "src/lib.rs",
+    "src/index.ts",
+    "src/index.js",
+    "src/main.py",
+];
+
+/// Where the file at `relative`, below a directory, ranks among the entry
+/// points of the directory
+fn rank(relative: &Path) -> Option<usize> {
+    let names: Option<Vec<&str>> = relative.iter().map(|name| name.to_str()).collect();
+    let relative = names?.join("/");
+    ENTRY_POINTS
+        .iter()
+        .position(|known| known.eq_ignore_ascii_case(&relative))
+}
+
+/// The entry point of each of `dirs` found among `entries`, by directory
+pub fn find<E: EntryFields>(
+    dirs: &[&Path],
+    entries: impl Iterator<Item = E>,
+) -> HashMap<PathBuf, PathBuf> {
+    if dirs.is_empty() {
+        return HashMap::new();
+    }
+    let dirs: HashSet<&Path> = dirs.iter().copied().collect();
+    let mut best: HashMap<&Path, (usize, PathBuf)> = HashMap::new();
+    for entry in entries.filter(|entry| !entry.is_dir()) {
+        let path = entry.path();
+        // Directly in the directory, or in its `src`
+        let candidates = path.ancestors().skip(1).take(2);
+        for dir in candidates {
+            let Some(dir) = dirs.get(dir).copied() else {
+                continue;
+            };
+            let Some(rank) = path.strip_prefix(dir).ok().and_then(rank) else {
+                continue;
+            };
+            if best.get(dir).is_none_or(|(known, _)| rank < *known) {
+                best.insert(dir, (rank, path.to_path_buf()));
+            }
+        }
+    }
+    best.into_iter()
+        .map(|(dir, (_, path))| (dir.to_path_buf(), path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_find_entry_points() {
+        let index = IndexBuilder::new("/work")
+            .dir("app")
+            .file("app/src/main.rs", 10)
+            .file("app/readme.md", 10)
+            .dir("tool")
+            .file("tool/src/main.rs", 10)
+            .file("tool/src/cli.rs", 10)
+            .dir("docs")
+            .file("docs/guide.md", 10)
+            .file("docs/deep/README.md", 10)
+            .build();
+        let dirs = [
+            Path::new("/work/app"),
+            Path::new("/work/tool"),
+            Path::new("/work/docs"),
+        ];
+        let found = find(&dirs, index.entries.iter());
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[Path::new("/work/app")],
+            Path::new("/work/app/readme.md")
+        );
+        assert_eq!(
+            found[Path::new("/work/tool")],
+            Path::new("/work/tool/src/main.rs")
+        );
+    }
+}
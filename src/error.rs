@@ -0,0 +1,148 @@
+//! Errors a caller can tell apart. The types the crate root re-exports
+//! ([`Index`](crate::Index), [`Snapshot`](crate::Snapshot),
+//! [`Query`](crate::Query) and the scanner, search and grep functions behind
+//! them) return this module's [`Result`]; the rest of the crate returns
+//! [`anyhow::Result`], which converts into it as [`Error::Other`]. Failures
+//! a user can act on carry an [`Error`] somewhere in their chain, and
+//! [`ErrorKind::of`] says which kind any error is:
+//!
+//! ```
+//! use sonic_search::error::ErrorKind;
+//! use sonic_search::query::Query;
+//!
+//! let error = Query::parse("(budget").unwrap_err();
+//! assert_eq!(error.kind(), ErrorKind::Query);
+//! ```
+
+use crate::selfcheck::Finding;
+use std::io;
+use std::path::PathBuf;
+
+/// What the crate's main entry points return
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Like [`anyhow::bail!`], for functions returning either [`Result`]
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(anyhow::anyhow!($($arg)*).into())
+    };
+}
+pub(crate) use bail;
+
+/// A failure caused by what was asked for or by the index read, rather than
+/// by the system
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A query, search pattern or glob that doesn't parse; what's wrong
+    /// with it is the next cause in the chain
+    #[error("Invalid {what}: {input}")]
+    Query { what: &'static str, input: String },
+    /// An index file that isn't one or is damaged
+    #[error("{problem}: {}", path.display())]
+    IndexFormat {
+        problem: &'static str,
+        path: PathBuf,
+    },
+    /// Reading or writing a file failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Anything else, or one of the above with context added on the way up;
+    /// [`Error::kind`] looks through it
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl Error {
+    pub(crate) fn query(what: &'static str, input: &str) -> Self {
+        Error::Query {
+            what,
+            input: input.to_string(),
+        }
+    }
+
+    pub(crate) fn index_format(problem: &'static str, path: &std::path::Path) -> Self {
+        Error::IndexFormat {
+            problem,
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Query { .. } => ErrorKind::Query,
+            Error::IndexFormat { .. } => ErrorKind::IndexFormat,
+            Error::Io(_) => ErrorKind::Io,
+            Error::Other(error) => ErrorKind::of(error),
+        }
+    }
+}
+
+/// What went wrong, broadly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Something asked for doesn't parse, see [`Error::Query`]
+    Query,
+    /// The index is damaged, or was written in another format version and
+    /// needs migrating; see [`Error::IndexFormat`] and [`Finding`]
+    IndexFormat,
+    /// Reading or writing a file failed, see [`Error::Io`]
+    Io,
+    /// Anything else
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of `error`, from the outermost cause that tells: an
+    /// [`Error`], a [`Finding`] about the index, or an I/O error
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<Error>() {
+            return error.kind();
+        }
+        if error.downcast_ref::<Finding>().is_some() {
+            return ErrorKind::IndexFormat;
+        }
+        match error.chain().any(|cause| cause.is::<io::Error>()) {
+            true => ErrorKind::Io,
+            false => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::path::Path;
+
+    #[test]
+    fn test_kind_of() {
+        let query = anyhow::anyhow!("unexpected ')'")
+            .context(Error::query("query", "a)"))
+            .context("Failed to search");
+        assert_eq!(ErrorKind::of(&query), ErrorKind::Query);
+        assert_eq!(query.root_cause().to_string(), "unexpected ')'");
+
+        let damaged: anyhow::Error =
+            Error::index_format("Index file is corrupt", Path::new("x")).into();
+        assert_eq!(damaged.to_string(), "Index file is corrupt: x");
+        assert_eq!(ErrorKind::of(&damaged), ErrorKind::IndexFormat);
+        let outdated = anyhow::Error::from(Finding::format_mismatch(Path::new("idx"), 1))
+            .context("Failed to load");
+        assert_eq!(ErrorKind::of(&outdated), ErrorKind::IndexFormat);
+
+        let missing = std::fs::read("/nonexistent/file").context("Failed to read");
+        assert_eq!(ErrorKind::of(&missing.unwrap_err()), ErrorKind::Io);
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("nope")), ErrorKind::Other);
+
+        // Typed errors keep their kind however they're wrapped
+        let io: Error = std::fs::read("/nonexistent/file").unwrap_err().into();
+        assert_eq!(io.kind(), ErrorKind::Io);
+        let wrapped: Error = query.into();
+        assert_eq!(wrapped.kind(), ErrorKind::Query);
+        assert_eq!(wrapped.to_string(), "Failed to search");
+        assert_eq!(
+            ErrorKind::of(&anyhow::Error::from(wrapped)),
+            ErrorKind::Query
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+/// Placeholder replaced by the quoted path, or paths, of the matches
+pub const PLACEHOLDER: &str = "{path}";
+
+/// A command that runs `line` through the platform's shell
+#[cfg(windows)]
+pub fn shell(line: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    // Passed on verbatim: escaping it as an argument would add backslashes
+    // cmd doesn't read. `/S` has cmd strip just the outer quotes.
+    let mut command = Command::new("cmd");
+    command.raw_arg(format!("/S /C \"{}\"", line));
+    command
+}
+
+/// A command that runs `line` through the platform's shell
+#[cfg(not(windows))]
+pub fn shell(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+/// `path` quoted so the shell passes it on as a single argument
+pub fn quote(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if cfg!(windows) {
+        quote_cmd(&text)
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+/// `text` quoted for cmd.exe: first as the program reading it splits its
+/// command line, then with cmd's own metacharacters, the quotes included,
+/// escaped with `^`, so a name like `a&calc.txt` can't run a command
+pub fn quote_cmd(text: &str) -> String {
+    let mut arg = String::from('"');
+    let mut backslashes = 0;
+    for c in text.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            // Backslashes only escape when a quote follows them
+            '"' => arg.extend(std::iter::repeat_n('\\', backslashes * 2 + 1)),
+            _ => arg.extend(std::iter::repeat_n('\\', backslashes)),
+        }
+        arg.push(c);
+        backslashes = 0;
+    }
+    arg.extend(std::iter::repeat_n('\\', backslashes * 2));
+    arg.push('"');
+
+    let mut escaped = String::with_capacity(arg.len() * 2);
+    for c in arg.chars() {
+        if matches!(c, '(' | ')' | '%' | '!' | '^' | '"' | '<' | '>' | '&' | '|') {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `template` with [`PLACEHOLDER`] replaced by the quoted `paths`, or
+/// with them appended when it has none, as `fd -x` does
+pub fn command_line(template: &str, paths: &[&Path]) -> String {
+    let quoted: Vec<String> = paths.iter().map(|path| quote(path)).collect();
+    let quoted = quoted.join(" ");
+    match template.contains(PLACEHOLDER) {
+        true => template.replace(PLACEHOLDER, &quoted),
+        false => format!("{} {}", template, quoted),
+    }
+}
+
+/// Run `template` once per path, at most `jobs` at a time, and return how
+/// many failed. Each command's output is written out whole once it
+/// finishes, so that of parallel commands doesn't interleave.
+pub fn run_each(template: &str, paths: &[&Path], jobs: NonZeroUsize) -> Result<usize> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.get())
+        .build()?;
+    let failed = pool.install(|| {
+        paths
+            .par_iter()
+            .filter(|&&path| {
+                let line = command_line(template, &[path]);
+                let output = shell(&line).env("SS_MATCH_PATH", path).output();
+                match output {
+                    Ok(output) => {
+                        let _ = std::io::stdout().lock().write_all(&output.stdout);
+                        let _ = std::io::stderr().lock().write_all(&output.stderr);
+                        if !output.status.success() {
+                            eprintln!("⚠️  `{}` failed with {}", line, output.status);
+                        }
+                        !output.status.success()
+                    }
+                    Err(e) => {
+                        eprintln!("⚠️  Failed to run `{}`: {}", line, e);
+                        true
+                    }
+                }
+            })
+            .count()
+    });
+    Ok(failed)
+}
+
+/// Run `template` once with every path
+pub fn run_batch(template: &str, paths: &[&Path]) -> Result<ExitStatus> {
+    let line = command_line(template, paths);
+    shell(&line)
+        .status()
+        .with_context(|| format!("Failed to run `{}`", template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line() {
+        let paths = [Path::new("/a b/it's.txt"), Path::new("/c.txt")];
+        if cfg!(unix) {
+            assert_eq!(
+                command_line("code {path}", &paths[..1]),
+                r"code '/a b/it'\''s.txt'"
+            );
+            assert_eq!(
+                command_line("wc -l", &paths),
+                "wc -l '/a b/it'\\''s.txt' '/c.txt'"
+            );
+        }
+        assert!(command_line("cat {path} | wc", &paths[1..]).ends_with(" | wc"));
+    }
+
+    #[test]
+    fn test_quote_cmd() {
+        assert_eq!(quote_cmd(r"C:\a b\x.txt"), r#"^"C:\a b\x.txt^""#);
+        assert_eq!(quote_cmd("a&calc.txt"), r#"^"a^&calc.txt^""#);
+        assert_eq!(
+            quote_cmd(r#"say "hi"%PATH%"#),
+            r#"^"say \^"hi\^"^%PATH^%^""#
+        );
+        assert_eq!(quote_cmd(r"dir\"), r#"^"dir\\^""#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<std::path::PathBuf> = ["a", "b c", "d"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        let paths: Vec<&Path> = paths.iter().map(|path| path.as_path()).collect();
+        let two = NonZeroUsize::new(2).unwrap();
+        assert_eq!(run_each("touch", &paths, two).unwrap(), 0);
+        assert!(paths.iter().all(|path| path.exists()));
+        assert_eq!(run_each("test -d {path}", &paths, two).unwrap(), 3);
+        assert!(run_batch("rm {path}", &paths).unwrap().success());
+        assert!(!paths.iter().any(|path| path.exists()));
+    }
+}
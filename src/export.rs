@@ -0,0 +1,302 @@
+//! Dump every entry of an index with its metadata as a table, for analysis
+//! in tools such as pandas or DuckDB.
+
+use crate::filetype;
+use crate::git;
+use crate::index::Index;
+use crate::parquet::{self, Column, Values};
+use crate::perms;
+use crate::scanner;
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Formats an index can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Comma-separated values with a header row
+    Csv,
+    /// A JSON array of objects
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    Parquet,
+}
+
+impl Format {
+    /// The format a file name's extension asks for
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "parquet" | "pq" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// One entry with everything the index knows about it, flattened
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Row<'a> {
+    pub path: String,
+    pub name: &'a str,
+    /// Lowercased, without the dot
+    pub extension: Option<String>,
+    pub size: u64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// Modification time, in RFC 3339 UTC
+    pub modified: Option<String>,
+    /// Name of the sub-project containing the entry
+    pub project: Option<&'a str>,
+    /// CODEOWNERS owners, separated by spaces
+    pub owners: Option<String>,
+    pub vendored: bool,
+    /// BLAKE3 hash, for indexes scanned with `--hash`
+    pub hash: Option<String>,
+    pub mime: Option<&'static str>,
+    /// `untracked`, `tracked` or `modified`
+    pub git_status: &'static str,
+    /// Work tree of the git repository holding the entry
+    pub repo: Option<String>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    /// Permission bits in octal, such as `644`
+    pub mode: Option<String>,
+    pub readonly: bool,
+    pub hidden: bool,
+    /// Modification time in milliseconds since the Unix epoch, which
+    /// Parquet stores instead of [`Row::modified`]
+    #[serde(skip)]
+    pub modified_ms: Option<i64>,
+}
+
+/// Column names, in the order every format writes them
+pub const COLUMNS: [&str; 19] = [
+    "path",
+    "name",
+    "extension",
+    "size",
+    "is_dir",
+    "is_symlink",
+    "modified",
+    "project",
+    "owners",
+    "vendored",
+    "hash",
+    "mime",
+    "git_status",
+    "repo",
+    "user",
+    "group",
+    "mode",
+    "readonly",
+    "hidden",
+];
+
+/// A row for every entry of `index`, in index order
+pub fn rows(index: &Index) -> Vec<Row<'_>> {
+    index
+        .entries
+        .iter()
+        .map(|entry| {
+            let owners = index.owners_of(entry);
+            let attributes = &entry.attributes;
+            Row {
+                path: entry.path.display().to_string(),
+                name: &entry.name,
+                extension: (!entry.is_dir)
+                    .then(|| Path::new(&entry.name).extension())
+                    .flatten()
+                    .map(|extension| extension.to_string_lossy().to_lowercase()),
+                size: entry.size,
+                is_dir: entry.is_dir,
+                is_symlink: entry.is_symlink,
+                modified: entry.modified.map(|time| {
+                    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+                }),
+                project: entry
+                    .project
+                    .and_then(|id| index.projects.get(id as usize))
+                    .map(|project| project.name.as_str()),
+                owners: (!owners.is_empty()).then(|| owners.join(" ")),
+                vendored: entry.vendored,
+                hash: entry.hash.as_ref().map(scanner::hash_hex),
+                mime: entry.mime.and_then(filetype::mime_name),
+                git_status: match entry.git_status {
+                    git::Status::Untracked => "untracked",
+                    git::Status::Tracked => "tracked",
+                    git::Status::Modified => "modified",
+                },
+                repo: entry
+                    .repo
+                    .and_then(|id| index.repos.get(id as usize))
+                    .map(|repo| repo.display().to_string()),
+                user: attributes.uid.map(perms::user_label),
+                group: attributes.gid.map(perms::group_label),
+                mode: attributes.mode.map(|mode| format!("{:03o}", mode)),
+                readonly: attributes.readonly,
+                hidden: attributes.hidden,
+                modified_ms: entry.modified.map(unix_millis),
+            }
+        })
+        .collect()
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(before) => -(before.duration().as_millis() as i64),
+    }
+}
+
+/// Write `rows` to `out` as `format`
+pub fn write(out: &mut impl Write, format: Format, rows: &[Row]) -> Result<()> {
+    match format {
+        Format::Csv => write_csv(out, rows)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut *out, row)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Parquet => parquet::write(&mut *out, &columns(rows))?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, rows: &[Row]) -> Result<()> {
+    writeln!(out, "{}", COLUMNS.join(","))?;
+    for row in rows {
+        let fields: [String; 19] = [
+            csv_field(&row.path),
+            csv_field(row.name),
+            csv_field(row.extension.as_deref().unwrap_or("")),
+            row.size.to_string(),
+            row.is_dir.to_string(),
+            row.is_symlink.to_string(),
+            row.modified.clone().unwrap_or_default(),
+            csv_field(row.project.unwrap_or("")),
+            csv_field(row.owners.as_deref().unwrap_or("")),
+            row.vendored.to_string(),
+            row.hash.clone().unwrap_or_default(),
+            row.mime.unwrap_or("").to_string(),
+            row.git_status.to_string(),
+            csv_field(row.repo.as_deref().unwrap_or("")),
+            csv_field(row.user.as_deref().unwrap_or("")),
+            csv_field(row.group.as_deref().unwrap_or("")),
+            row.mode.clone().unwrap_or_default(),
+            row.readonly.to_string(),
+            row.hidden.to_string(),
+        ];
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// `text` quoted if it holds a comma, a quote or a line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// The rows as a table of columns, named as in [`COLUMNS`]
+fn columns(rows: &[Row]) -> Vec<Column> {
+    let text = |get: fn(&Row) -> Option<String>| Values::Utf8(rows.iter().map(get).collect());
+    let flag = |get: fn(&Row) -> bool| Values::Bool(rows.iter().map(get).collect());
+    let values = [
+        text(|row| Some(row.path.clone())),
+        text(|row| Some(row.name.to_string())),
+        text(|row| row.extension.clone()),
+        Values::Int64(rows.iter().map(|row| Some(row.size as i64)).collect()),
+        flag(|row| row.is_dir),
+        flag(|row| row.is_symlink),
+        Values::Timestamp(rows.iter().map(|row| row.modified_ms).collect()),
+        text(|row| row.project.map(str::to_string)),
+        text(|row| row.owners.clone()),
+        flag(|row| row.vendored),
+        text(|row| row.hash.clone()),
+        text(|row| row.mime.map(str::to_string)),
+        text(|row| Some(row.git_status.to_string())),
+        text(|row| row.repo.clone()),
+        text(|row| row.user.clone()),
+        text(|row| row.group.clone()),
+        text(|row| row.mode.clone()),
+        flag(|row| row.readonly),
+        flag(|row| row.hidden),
+    ];
+    COLUMNS
+        .into_iter()
+        .zip(values)
+        .map(|(name, values)| Column { name, values })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::ProjectKind;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_export_formats() {
+        let index = IndexBuilder::new("/work")
+            .spec(
+                "src/main.rs       2K  2024-03-01
+                 docs/q1, final.md 40",
+            )
+            .unwrap()
+            .project("", "app", ProjectKind::Cargo)
+            .build();
+        let rows = rows(&index);
+        let main = rows.iter().find(|row| row.name == "main.rs").unwrap();
+        assert_eq!(main.extension.as_deref(), Some("rs"));
+        assert_eq!(main.size, 2048);
+        assert_eq!(main.modified.as_deref(), Some("2024-03-01T00:00:00.000Z"));
+        assert_eq!(main.modified_ms, Some(1_709_251_200_000));
+        assert_eq!(main.project, Some("app"));
+        let docs = rows.iter().find(|row| row.name == "docs").unwrap();
+        assert_eq!(docs.extension, None);
+
+        let mut csv = Vec::new();
+        write(&mut csv, Format::Csv, &rows).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next().unwrap().split(',').count(), COLUMNS.len());
+        assert!(csv.contains("\"/work/docs/q1, final.md\",\"q1, final.md\",md,40,false"));
+
+        let mut ndjson = Vec::new();
+        write(&mut ndjson, Format::Ndjson, &rows).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_slice(ndjson.split(|&b| b == b'\n').next().unwrap()).unwrap();
+        let keys: Vec<&str> = first
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys.len(), COLUMNS.len());
+        assert!(COLUMNS.iter().all(|column| keys.contains(column)));
+
+        let columns = columns(&rows);
+        assert_eq!(columns.len(), COLUMNS.len());
+        assert_eq!(
+            Format::from_path(Path::new("files.PARQUET")),
+            Some(Format::Parquet)
+        );
+        assert_eq!(Format::from_path(Path::new("files.txt")), None);
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Pulls searchable text out of a file format that isn't plain text, so
+/// `scan --content` can index it and grep can search it like any text file
+pub trait ContentExtractor: Send + Sync {
+    /// Lowercase extensions of the files it reads
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// The text of a file with these `contents`, one paragraph per line
+    fn extract(&self, contents: &[u8]) -> Result<String>;
+}
+
+/// The extractors this build has, by the `office` and `pdf` features
+pub const EXTRACTORS: &[&dyn ContentExtractor] = &[
+    #[cfg(feature = "office")]
+    &office::Docx,
+    #[cfg(feature = "office")]
+    &office::Odt,
+    #[cfg(feature = "pdf")]
+    &pdf::Pdf,
+];
+
+/// The extractor among [`EXTRACTORS`] for `path`, by its extension
+pub fn for_path(path: &Path) -> Option<&'static dyn ContentExtractor> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    EXTRACTORS
+        .iter()
+        .copied()
+        .find(|extractor| extractor.extensions().contains(&ext.as_str()))
+}
+
+/// The character data of an XML document, with entities decoded and
+/// `markup` giving the text written for elements by name: `/p` for where a
+/// `p` element ends, `tab` for where one starts or stands empty
+#[cfg_attr(not(feature = "office"), allow(dead_code))]
+fn xml_text(xml: &str, markup: &[(&str, &str)]) -> String {
+    let mut text = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        decode_entities(&rest[..start], &mut text);
+        rest = &rest[start + 1..];
+        let end = match rest.strip_prefix("!--") {
+            Some(comment) => comment.find("-->").map(|end| end + 6),
+            None => rest.find('>').map(|end| end + 1),
+        };
+        let Some(end) = end else {
+            return text;
+        };
+        let tag = &rest[..end - 1];
+        let name_end = tag
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c.is_whitespace() || c == '/')
+            .map_or(tag.len(), |(i, _)| i);
+        let name = &tag[..name_end];
+        if let Some((_, written)) = markup.iter().find(|(element, _)| *element == name) {
+            text.push_str(written);
+        }
+        rest = &rest[end..];
+    }
+    decode_entities(rest, &mut text);
+    text
+}
+
+/// Append `data` to `text` with XML's character and entity references
+/// replaced; unknown ones are kept as written
+fn decode_entities(data: &str, text: &mut String) {
+    let mut rest = data;
+    while let Some(start) = rest.find('&') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                reference => {
+                    let code = match reference.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => reference.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                text.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+}
+
+/// Word processor files, which are zip archives of XML parts
+#[cfg(feature = "office")]
+mod office {
+    use super::{ContentExtractor, xml_text};
+    use anyhow::{Context, Result};
+    use std::io::{Cursor, Read};
+
+    /// The text of the XML part `name` of the zip archive in `contents`
+    fn part_text(contents: &[u8], name: &str, markup: &[(&str, &str)]) -> Result<String> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(contents))?;
+        let mut xml = String::new();
+        archive
+            .by_name(name)
+            .with_context(|| format!("No {} in the archive", name))?
+            .read_to_string(&mut xml)?;
+        Ok(xml_text(&xml, markup))
+    }
+
+    /// Office Open XML documents, as Word writes them
+    pub struct Docx;
+
+    impl ContentExtractor for Docx {
+        fn extensions(&self) -> &'static [&'static str] {
+            &["docx", "docm"]
+        }
+
+        fn extract(&self, contents: &[u8]) -> Result<String> {
+            part_text(
+                contents,
+                "word/document.xml",
+                &[("/w:p", "\n"), ("w:tab", "\t"), ("w:br", "\n")],
+            )
+        }
+    }
+
+    /// OpenDocument text, as LibreOffice writes it
+    pub struct Odt;
+
+    impl ContentExtractor for Odt {
+        fn extensions(&self) -> &'static [&'static str] {
+            &["odt"]
+        }
+
+        fn extract(&self, contents: &[u8]) -> Result<String> {
+            part_text(
+                contents,
+                "content.xml",
+                &[
+                    ("/text:p", "\n"),
+                    ("/text:h", "\n"),
+                    ("text:tab", "\t"),
+                    ("text:line-break", "\n"),
+                    ("text:s", " "),
+                ],
+            )
+        }
+    }
+}
+
+#[cfg(feature = "pdf")]
+mod pdf {
+    use super::ContentExtractor;
+    use anyhow::Result;
+
+    /// PDF documents with a text layer; scanned pages without one have no text
+    pub struct Pdf;
+
+    impl ContentExtractor for Pdf {
+        fn extensions(&self) -> &'static [&'static str] {
+            &["pdf"]
+        }
+
+        fn extract(&self, contents: &[u8]) -> Result<String> {
+            // The parser panics on some malformed files, which mustn't abort a scan
+            std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(contents))
+                .map_err(|_| anyhow::anyhow!("Unreadable PDF"))?
+                .map_err(|e| anyhow::anyhow!("Unreadable PDF: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_text() {
+        let xml = r#"<?xml version="1.0"?><doc><!-- a > b --><p>Fish &amp; chips<tab/>&#163;5</p><p a="1">Q&#x33;&nbsp;</p></doc>"#;
+        let markup = [("/p", "\n"), ("tab", "\t")];
+        assert_eq!(xml_text(xml, &markup), "Fish & chips\t£5\nQ3&nbsp;\n");
+        assert_eq!(xml_text("a<b", &markup), "a");
+    }
+
+    #[cfg(feature = "office")]
+    #[test]
+    fn test_office_documents() {
+        use std::io::Write;
+
+        let archive = |name: &str, xml: &str| {
+            let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            writer.start_file(name, options).unwrap();
+            writer.write_all(xml.as_bytes()).unwrap();
+            writer.finish().unwrap().into_inner()
+        };
+        let docx = archive(
+            "word/document.xml",
+            "<w:document><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:tab/><w:t>report</w:t></w:r></w:p>\
+             <w:p><w:r><w:t xml:space=\"preserve\">Net &lt;profit&gt;</w:t></w:r></w:p></w:body></w:document>",
+        );
+        let extractor = for_path(Path::new("Q3 Report.DOCX")).unwrap();
+        assert_eq!(
+            extractor.extract(&docx).unwrap(),
+            "Quarterly\treport\nNet <profit>\n"
+        );
+
+        let odt = archive(
+            "content.xml",
+            "<office:text><text:h>Minutes</text:h><text:p>Present:<text:s/>all</text:p></office:text>",
+        );
+        let extractor = for_path(Path::new("minutes.odt")).unwrap();
+        assert_eq!(extractor.extract(&odt).unwrap(), "Minutes\nPresent: all\n");
+        assert!(extractor.extract(b"not a zip").is_err());
+        assert!(for_path(Path::new("notes.txt")).is_none());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_pdf() {
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R \
+             /Resources << /Font << /F1 5 0 R >> >> >>",
+            "<< /Length 41 >>\nstream\nBT /F1 12 Tf 72 720 Td (Net profit) Tj ET\nendstream",
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+        ];
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+        for offset in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", offset).bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .bytes(),
+        );
+
+        let extractor = for_path(Path::new("statement.pdf")).unwrap();
+        let text = extractor.extract(&pdf).unwrap();
+        assert!(text.contains("Net profit"), "{:?}", text);
+        assert!(extractor.extract(b"%PDF-1.4 garbage").is_err());
+    }
+}
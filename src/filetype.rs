@@ -0,0 +1,418 @@
+use crate::archive;
+use crate::scanner::FileEntry;
+use crate::suggest;
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of leading bytes [`sniff`] looks at
+const SNIFF_LEN: usize = 512;
+
+/// MIME types entries can be tagged with, and the extensions that give
+/// each away. An entry stores the position of its type, so the table is
+/// only ever appended to.
+pub const MIME_TYPES: &[(&str, &[&str])] = &[
+    ("text/plain", &["txt", "text", "log"]),
+    ("text/markdown", &["md", "markdown"]),
+    ("text/html", &["html", "htm"]),
+    ("text/css", &["css", "scss"]),
+    ("text/csv", &["csv", "tsv"]),
+    ("text/x-rust", &["rs"]),
+    ("text/x-python", &["py", "pyi"]),
+    ("text/javascript", &["js", "mjs", "cjs", "jsx"]),
+    ("text/x-typescript", &["ts", "tsx", "mts"]),
+    ("text/x-go", &["go"]),
+    ("text/x-c", &["c", "h"]),
+    ("text/x-c++", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]),
+    ("text/x-java", &["java"]),
+    ("text/x-kotlin", &["kt", "kts"]),
+    ("text/x-ruby", &["rb"]),
+    ("text/x-shellscript", &["sh", "bash", "zsh"]),
+    ("text/x-swift", &["swift"]),
+    ("application/json", &["json"]),
+    ("application/toml", &["toml"]),
+    ("application/yaml", &["yaml", "yml"]),
+    ("application/xml", &["xml"]),
+    ("application/sql", &["sql"]),
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    ("image/svg+xml", &["svg"]),
+    ("image/heic", &["heic", "heif"]),
+    ("image/bmp", &["bmp"]),
+    ("image/tiff", &["tif", "tiff"]),
+    ("audio/mpeg", &["mp3"]),
+    ("audio/flac", &["flac"]),
+    ("audio/wav", &["wav"]),
+    ("audio/ogg", &["ogg", "oga"]),
+    ("audio/mp4", &["m4a"]),
+    ("video/mp4", &["mp4", "m4v"]),
+    ("video/quicktime", &["mov"]),
+    ("video/webm", &["webm"]),
+    ("video/x-matroska", &["mkv"]),
+    ("application/pdf", &["pdf"]),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        &["docx"],
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        &["xlsx"],
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        &["pptx"],
+    ),
+    ("application/vnd.oasis.opendocument.text", &["odt"]),
+    ("application/vnd.oasis.opendocument.spreadsheet", &["ods"]),
+    ("application/rtf", &["rtf"]),
+    ("application/epub+zip", &["epub"]),
+    ("application/zip", &["zip", "jar"]),
+    ("application/x-tar", &["tar"]),
+    ("application/gzip", &["gz", "tgz"]),
+    ("application/x-7z-compressed", &["7z"]),
+    ("application/zstd", &["zst"]),
+    ("application/x-xz", &["xz"]),
+    ("application/vnd.sqlite3", &["sqlite", "sqlite3", "db"]),
+    ("application/x-elf", &[]),
+    ("application/wasm", &["wasm"]),
+];
+
+/// Leading bytes that give a file's type away, for files whose extension
+/// doesn't. The `ftyp` box of MP4, QuickTime and HEIF files and the WebP
+/// tag come at an offset, so they're checked separately.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"BM", "image/bmp"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\xfd7zXZ\0", "application/x-xz"),
+    (b"SQLite format 3\0", "application/vnd.sqlite3"),
+    (b"\x7fELF", "application/x-elf"),
+    (b"\0asm", "application/wasm"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    (b"{\\rtf", "application/rtf"),
+];
+
+/// Named file types for `--type`, like ripgrep's: each matches file names
+/// against globs
+pub const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("kotlin", &["*.kt", "*.kts"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("sql", &["*.sql"]),
+    ("swift", &["*.swift"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Broad categories for `--type`, each made of named [`TYPES`] and MIME
+/// patterns, so sniffed files without an extension fall in them too
+pub const CATEGORIES: &[(&str, &[&str], &[&str])] = &[
+    (
+        "code",
+        &[
+            "c", "cpp", "go", "java", "js", "kotlin", "make", "py", "ruby", "rust", "sh", "swift",
+            "ts",
+        ],
+        &[],
+    ),
+    ("config", &["json", "toml", "yaml"], &["application/xml"]),
+    (
+        "document",
+        &["md"],
+        &[
+            "text/plain",
+            "application/pdf",
+            "application/rtf",
+            "application/epub+zip",
+            "application/vnd.*",
+        ],
+    ),
+    ("image", &[], &["image/*"]),
+    ("audio", &[], &["audio/*"]),
+    ("video", &[], &["video/*"]),
+    (
+        "archive",
+        &[],
+        &[
+            "application/zip",
+            "application/x-tar",
+            "application/gzip",
+            "application/x-7z-compressed",
+            "application/zstd",
+            "application/x-xz",
+        ],
+    ),
+];
+
+/// The MIME type stored as `id`
+pub fn mime_name(id: u32) -> Option<&'static str> {
+    MIME_TYPES.get(id as usize).map(|(mime, _)| *mime)
+}
+
+fn mime_id(mime: &str) -> Option<u32> {
+    MIME_TYPES
+        .iter()
+        .position(|(known, _)| *known == mime)
+        .map(|id| id as u32)
+}
+
+/// The MIME type of a file named `name`, by its extension
+pub fn from_extension(name: &str) -> Option<u32> {
+    let (_, ext) = name.rsplit_once('.')?;
+    let ext = ext.to_ascii_lowercase();
+    MIME_TYPES
+        .iter()
+        .position(|(_, extensions)| extensions.contains(&ext.as_str()))
+        .map(|id| id as u32)
+}
+
+/// The MIME type of `contents`, the leading bytes of a file, by its magic
+/// bytes; text without NUL bytes is `text/plain`, or a shell script when
+/// it starts with `#!`
+pub fn sniff(contents: &[u8]) -> Option<u32> {
+    let at = |offset: usize, tag: &[u8]| contents.get(offset..offset + tag.len()) == Some(tag);
+    let mime = if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| at(0, magic)) {
+        mime
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        "image/webp"
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        "audio/wav"
+    } else if at(4, b"ftyp") {
+        match contents.get(8..12) {
+            Some(b"qt  ") => "video/quicktime",
+            Some(b"heic" | b"heix" | b"mif1") => "image/heic",
+            Some(b"M4A ") => "audio/mp4",
+            _ => "video/mp4",
+        }
+    } else if contents.len() > 262 && at(257, b"ustar") {
+        "application/x-tar"
+    } else if !contents.is_empty() && !contents.contains(&0) && is_text(contents) {
+        match contents.starts_with(b"#!") {
+            true => "text/x-shellscript",
+            false => "text/plain",
+        }
+    } else {
+        return None;
+    };
+    mime_id(mime)
+}
+
+/// Whether `contents` is UTF-8, allowing for a character cut off at the end
+fn is_text(contents: &[u8]) -> bool {
+    match std::str::from_utf8(contents) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+fn sniff_file(path: &Path) -> Option<u32> {
+    let mut contents = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut contents)
+        .ok()?;
+    sniff(&contents)
+}
+
+/// Tag the files among `entries` that have no MIME type yet with the one
+/// their extension gives away, and with `sniff` the rest by reading their
+/// first bytes. Files inside archives are only tagged by extension.
+pub fn assign(entries: &mut [FileEntry], sniff: bool) {
+    entries
+        .par_iter_mut()
+        .filter(|entry| !entry.is_dir && entry.mime.is_none())
+        .for_each(|entry| {
+            entry.mime = from_extension(&entry.name).or_else(|| {
+                (sniff && archive::split(&entry.path).is_none())
+                    .then(|| sniff_file(&entry.path))
+                    .flatten()
+            });
+        });
+}
+
+/// Every name `--type` accepts, categories first
+pub fn type_names() -> impl Iterator<Item = &'static str> {
+    let categories = CATEGORIES.iter().map(|(name, _, _)| *name);
+    categories.chain(TYPES.iter().map(|(name, _)| *name))
+}
+
+/// Whether `name` is a type or category `--type` accepts
+pub fn is_type(name: &str) -> bool {
+    type_names().any(|known| known == name)
+}
+
+/// `--type` and `--mime` filters compiled into something entries can be
+/// matched against
+#[derive(Debug, Clone)]
+pub struct TypeMatcher {
+    /// Globs of the named types, matched against file names
+    globs: GlobSet,
+    /// MIME patterns from categories
+    type_mimes: Vec<String>,
+    /// MIME patterns from `--mime`, every one of which must match
+    mimes: Vec<String>,
+}
+
+impl TypeMatcher {
+    /// Compile `types`, names from [`type_names`], and `mimes`, MIME types
+    /// such as `image/png` or patterns such as `image/*`; `None` when
+    /// neither restricts anything
+    pub fn new(types: &[String], mimes: &[String]) -> Result<Option<Self>> {
+        if types.is_empty() && mimes.is_empty() {
+            return Ok(None);
+        }
+        let mut globs = GlobSetBuilder::new();
+        let mut type_mimes = Vec::new();
+        let add_type = |name: &str, globs: &mut GlobSetBuilder| -> Result<()> {
+            let Some((_, patterns)) = TYPES.iter().find(|(known, _)| *known == name) else {
+                match suggest::closest(name, type_names()) {
+                    Some(known) => {
+                        anyhow::bail!("Unknown file type '{}'. Did you mean '{}'?", name, known)
+                    }
+                    None => anyhow::bail!(
+                        "Unknown file type '{}' (known types: {})",
+                        name,
+                        type_names().collect::<Vec<_>>().join(", ")
+                    ),
+                }
+            };
+            for pattern in *patterns {
+                globs.add(Glob::new(pattern)?);
+            }
+            Ok(())
+        };
+        for name in types {
+            let name = name.to_ascii_lowercase();
+            match CATEGORIES.iter().find(|(known, _, _)| *known == name) {
+                Some((_, members, patterns)) => {
+                    for member in *members {
+                        add_type(member, &mut globs)?;
+                    }
+                    type_mimes.extend(patterns.iter().map(|pattern| pattern.to_string()));
+                }
+                None => add_type(&name, &mut globs)?,
+            }
+        }
+        Ok(Some(TypeMatcher {
+            globs: globs.build()?,
+            type_mimes,
+            mimes: mimes.iter().map(|mime| mime.to_ascii_lowercase()).collect(),
+        }))
+    }
+
+    /// Whether a file named `name` with MIME type `mime` passes. Without
+    /// types, every file does; without MIME patterns too.
+    pub fn matches(&self, name: &str, mime: Option<&str>) -> bool {
+        let typed = self.globs.is_empty() && self.type_mimes.is_empty()
+            || self.globs.is_match(name)
+            || mime.is_some_and(|mime| self.type_mimes.iter().any(|p| mime_matches(p, mime)));
+        typed
+            && (self.mimes.is_empty()
+                || mime.is_some_and(|mime| self.mimes.iter().any(|p| mime_matches(p, mime))))
+    }
+}
+
+/// Whether `mime` is `pattern`, or starts with what comes before a
+/// trailing `*` in it
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => mime.starts_with(prefix),
+        None => pattern == mime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_detection() {
+        let name = |id: Option<u32>| id.and_then(mime_name);
+        assert_eq!(name(from_extension("Photo.JPG")), Some("image/jpeg"));
+        assert_eq!(name(from_extension("lib.rs")), Some("text/x-rust"));
+        assert_eq!(from_extension("Makefile"), None);
+
+        assert_eq!(name(sniff(b"\x89PNG\r\n\x1a\n\0\0")), Some("image/png"));
+        assert_eq!(
+            name(sniff(b"\0\0\0\x18ftypqt  \0\0")),
+            Some("video/quicktime")
+        );
+        assert_eq!(name(sniff(b"RIFF\0\0\0\0WEBPVP8 ")), Some("image/webp"));
+        assert_eq!(
+            name(sniff(b"#!/bin/sh\necho hi\n")),
+            Some("text/x-shellscript")
+        );
+        // A multi-byte character cut off by the sample is still text
+        assert_eq!(
+            name(sniff("notes: é".as_bytes().split_last().unwrap().1)),
+            Some("text/plain")
+        );
+        assert_eq!(sniff(b"\x01\x02\0\x03"), None);
+        assert_eq!(sniff(b""), None);
+        assert!(MIME_TYPES.iter().all(|(mime, _)| mime_id(mime).is_some()));
+        assert!(SIGNATURES.iter().all(|(_, mime)| mime_id(mime).is_some()));
+    }
+
+    #[test]
+    fn test_type_matcher() {
+        let matcher = |types: &[&str], mimes: &[&str]| {
+            let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+            let mimes: Vec<String> = mimes.iter().map(|m| m.to_string()).collect();
+            TypeMatcher::new(&types, &mimes).unwrap().unwrap()
+        };
+        let code = matcher(&["code"], &[]);
+        assert!(code.matches("main.rs", Some("text/x-rust")));
+        assert!(code.matches("Makefile", None));
+        assert!(!code.matches("photo.png", Some("image/png")));
+
+        // Sniffed files without an extension fall into categories by MIME
+        let images = matcher(&["image"], &[]);
+        assert!(images.matches("IMG_0001", Some("image/jpeg")));
+        assert!(!images.matches("IMG_0001", None));
+
+        let mime = matcher(&[], &["image/*"]);
+        assert!(mime.matches("a.png", Some("image/png")));
+        assert!(!mime.matches("a.pdf", Some("application/pdf")));
+        assert!(matcher(&["rust", "py"], &["text/*"]).matches("x.py", Some("text/x-python")));
+
+        assert!(TypeMatcher::new(&[], &[]).unwrap().is_none());
+        let typo = TypeMatcher::new(&["rsut".to_string()], &[]).unwrap_err();
+        assert_eq!(
+            typo.to_string(),
+            "Unknown file type 'rsut'. Did you mean 'rust'?"
+        );
+        let members = CATEGORIES.iter().flat_map(|(_, members, _)| members.iter());
+        assert!(members.copied().all(is_type));
+    }
+}
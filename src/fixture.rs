@@ -0,0 +1,392 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// 2024-01-01 00:00 UTC; files are dated up to three years before it, so
+/// the same seed gives the same tree whenever it's generated
+pub(crate) const NEWEST_MTIME: u64 = 1_704_067_200;
+pub(crate) const MTIME_SPREAD: u64 = 3 * 365 * 86_400;
+
+pub(crate) const DIR_WORDS: &[&str] = &[
+    "src", "docs", "assets", "tests", "build", "archive", "projects", "photos", "music", "2023",
+    "2024", "backup", "shared", "work", "personal", "old", "misc", "data", "scripts", "clients",
+];
+const CODE_WORDS: &[&str] = &[
+    "main", "lib", "parser", "lexer", "config", "utils", "handler", "client", "server", "router",
+    "model", "schema", "index", "test", "mod", "error", "cache", "buffer", "stream", "token",
+];
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "go", "c", "h", "java", "toml", "json", "yaml", "md",
+];
+const DOCUMENT_WORDS: &[&str] = &[
+    "report",
+    "budget",
+    "invoice",
+    "notes",
+    "meeting",
+    "minutes",
+    "proposal",
+    "contract",
+    "summary",
+    "plan",
+    "draft",
+    "review",
+    "résumé",
+    "Москва",
+    "東京",
+    "会议记录",
+];
+const DOCUMENT_EXTENSIONS: &[&str] = &["pdf", "docx", "xlsx", "txt", "md", "odt", "csv", "pptx"];
+const MEDIA_WORDS: &[&str] = &[
+    "holiday", "beach", "birthday", "wedding", "concert", "trip", "family", "sunset", "party",
+    "album",
+];
+const MEDIA_EXTENSIONS: &[&str] = &["jpg", "png", "heic", "mp3", "mp4", "mov", "flac", "gif"];
+
+/// What the files of a fixture tree are named like
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameStyle {
+    /// Source files, like `lexer_utils.rs`
+    Code,
+    /// Office documents, like `Budget_2023-final.xlsx`, some with accented,
+    /// Cyrillic or CJK names
+    Documents,
+    /// Photos and recordings, like `IMG_0412.jpg`
+    Media,
+    /// A third of each
+    #[default]
+    Mixed,
+}
+
+/// What the files of a fixture tree hold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Contents {
+    #[default]
+    Empty,
+    /// Lines of words, with a `TODO` now and then, for code and documents,
+    /// and binary noise for media
+    Text,
+}
+
+/// The shape of a generated tree
+#[derive(Debug, Clone)]
+pub struct FixtureOptions {
+    pub files: usize,
+    /// How deep directories nest below the root
+    pub depth: usize,
+    /// Files per directory on average
+    pub files_per_dir: usize,
+    pub names: NameStyle,
+    pub contents: Contents,
+    /// Largest file written with [`Contents::Text`]; most are much smaller
+    pub max_size: u64,
+    /// Trees generated with the same options and seed are identical
+    pub seed: u64,
+}
+
+impl Default for FixtureOptions {
+    fn default() -> Self {
+        FixtureOptions {
+            files: 100_000,
+            depth: 8,
+            files_per_dir: 50,
+            names: NameStyle::default(),
+            contents: Contents::default(),
+            max_size: 4096,
+            seed: 0,
+        }
+    }
+}
+
+/// What [`generate`] created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fixture {
+    pub files: usize,
+    /// Directories below the root
+    pub dirs: usize,
+    pub bytes: u64,
+}
+
+/// A small, seedable pseudo-random generator (SplitMix64), so fixtures
+/// don't depend on a random number crate or differ between its versions
+#[derive(Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+
+    pub(crate) fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Create a tree of `options.files` files under `out`, which must not
+/// exist yet or be empty.
+///
+/// Directories are grown one at a time below a random existing one that
+/// isn't already `depth` deep, and each file lands in a random directory,
+/// which leaves a few crowded directories and many sparse ones, much like
+/// real trees. Directories are filled in parallel, each from a seed of its
+/// own, so the tree doesn't depend on thread scheduling.
+pub fn generate(out: &Path, options: &FixtureOptions) -> Result<Fixture> {
+    let occupied = fs::read_dir(out).is_ok_and(|mut entries| entries.next().is_some());
+    if occupied {
+        anyhow::bail!("{} already exists and isn't empty", out.display());
+    }
+    let mut rng = Rng::new(options.seed);
+    let dirs = grow_dirs(&mut rng, options);
+    let mut counts = vec![0usize; dirs.len()];
+    for _ in 0..options.files {
+        counts[rng.below(dirs.len() as u64) as usize] += 1;
+    }
+    for dir in &dirs {
+        let path = out.join(dir);
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+    }
+    let bytes = dirs
+        .par_iter()
+        .zip(&counts)
+        .enumerate()
+        .map(|(i, (dir, &count))| {
+            let seed = options.seed ^ (i as u64 + 1).wrapping_mul(0x2545_f491_4f6c_dd1d);
+            fill_dir(&out.join(dir), count, &mut Rng::new(seed), options)
+        })
+        .sum::<Result<u64>>()?;
+    Ok(Fixture {
+        files: options.files,
+        dirs: dirs.len() - 1,
+        bytes,
+    })
+}
+
+/// Relative paths of the directories of a tree, the root first
+fn grow_dirs(rng: &mut Rng, options: &FixtureOptions) -> Vec<PathBuf> {
+    let wanted = options.files.div_ceil(options.files_per_dir.max(1)).max(1);
+    let mut dirs = vec![PathBuf::new()];
+    // Indices of directories that may still get subdirectories
+    let mut open: Vec<usize> = Vec::new();
+    if options.depth > 0 {
+        open.push(0);
+    }
+    let mut taken = HashSet::new();
+    while dirs.len() < wanted && !open.is_empty() {
+        let parent = open[rng.below(open.len() as u64) as usize];
+        let word = rng.pick(DIR_WORDS);
+        let mut path = dirs[parent].join(word);
+        let mut n = 2;
+        while !taken.insert(path.clone()) {
+            path = dirs[parent].join(format!("{}-{}", word, n));
+            n += 1;
+        }
+        if path.components().count() < options.depth {
+            open.push(dirs.len());
+        }
+        dirs.push(path);
+    }
+    dirs
+}
+
+/// Write `count` files into `dir`, returning the bytes written
+fn fill_dir(dir: &Path, count: usize, rng: &mut Rng, options: &FixtureOptions) -> Result<u64> {
+    let mut names = HashSet::new();
+    let mut bytes = 0;
+    for _ in 0..count {
+        let style = match options.names {
+            NameStyle::Mixed => {
+                [NameStyle::Code, NameStyle::Documents, NameStyle::Media][rng.below(3) as usize]
+            }
+            style => style,
+        };
+        let (stem, extension) = file_name(rng, style);
+        let mut name = format!("{}.{}", stem, extension);
+        let mut n = 2;
+        // Names differing only in case would collide on some filesystems
+        while !names.insert(name.to_lowercase()) {
+            name = format!("{}-{}.{}", stem, n, extension);
+            n += 1;
+        }
+        let path = dir.join(&name);
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        if options.contents == Contents::Text {
+            // Squaring skews sizes towards the small end
+            let fraction = rng.below(1 << 16) as f64 / (1 << 16) as f64;
+            let size = (options.max_size as f64 * fraction * fraction) as u64;
+            bytes += write_contents(&mut writer, rng, style, size)?;
+        }
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        let mtime = NEWEST_MTIME - rng.below(MTIME_SPREAD);
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+    }
+    Ok(bytes)
+}
+
+/// A stem and an extension for a file named in `style`
+pub(crate) fn file_name(rng: &mut Rng, style: NameStyle) -> (String, &'static str) {
+    match style {
+        NameStyle::Code => {
+            let first = rng.pick(CODE_WORDS);
+            let stem = match rng.below(3) {
+                0 => first.to_string(),
+                1 => format!("{}_{}", first, rng.pick(CODE_WORDS)),
+                _ => {
+                    let second = rng.pick(CODE_WORDS);
+                    let (head, tail) = second.split_at(1);
+                    format!("{}{}{}", first, head.to_uppercase(), tail)
+                }
+            };
+            (stem, rng.pick(CODE_EXTENSIONS))
+        }
+        NameStyle::Documents | NameStyle::Mixed => {
+            let word = rng.pick(DOCUMENT_WORDS);
+            let mut stem = match rng.chance(30) {
+                true => capitalized(word),
+                false => word.to_string(),
+            };
+            if rng.chance(50) {
+                stem.push_str(&format!("_{}", 2015 + rng.below(10)));
+            }
+            if rng.chance(20) {
+                stem.push_str(rng.pick(&["-final", "-v2", " (copy)", "-draft"]));
+            }
+            (stem, rng.pick(DOCUMENT_EXTENSIONS))
+        }
+        NameStyle::Media => {
+            let stem = match rng.below(3) {
+                0 => format!("IMG_{:04}", rng.below(10_000)),
+                1 => format!("DSC{:05}", rng.below(100_000)),
+                _ => format!("{}-{:03}", rng.pick(MEDIA_WORDS), rng.below(1000)),
+            };
+            (stem, rng.pick(MEDIA_EXTENSIONS))
+        }
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Write about `size` bytes to `writer`: text for code and documents, noise
+/// with NUL bytes in it for media
+fn write_contents(
+    writer: &mut impl Write,
+    rng: &mut Rng,
+    style: NameStyle,
+    size: u64,
+) -> Result<u64> {
+    if style == NameStyle::Media {
+        let noise: Vec<u8> = (0..size)
+            .map(|i| if i % 64 == 0 { 0 } else { rng.next() as u8 })
+            .collect();
+        writer.write_all(&noise)?;
+        return Ok(size);
+    }
+    let words = match style {
+        NameStyle::Code => CODE_WORDS,
+        _ => DOCUMENT_WORDS,
+    };
+    let mut written = 0;
+    while written < size {
+        let mut line = match rng.chance(5) {
+            true => "TODO".to_string(),
+            false => rng.pick(words).to_string(),
+        };
+        for _ in 0..rng.below(10) {
+            line.push(' ');
+            line.push_str(rng.pick(words));
+        }
+        line.push('\n');
+        writer.write_all(line.as_bytes())?;
+        written += line.len() as u64;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    /// Every file below `root` with its size and mtime, sorted
+    fn listing(root: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+        let mut files = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let entry = entry.unwrap();
+                let metadata = entry.metadata().unwrap();
+                match metadata.is_dir() {
+                    true => pending.push(entry.path()),
+                    false => files.push((
+                        entry.path().strip_prefix(root).unwrap().to_path_buf(),
+                        metadata.len(),
+                        metadata.modified().unwrap(),
+                    )),
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let options = FixtureOptions {
+            files: 300,
+            depth: 3,
+            files_per_dir: 10,
+            contents: Contents::Text,
+            max_size: 512,
+            seed: 7,
+            ..Default::default()
+        };
+        let first = tempfile::tempdir().unwrap();
+        let fixture = generate(first.path(), &options).unwrap();
+        assert_eq!(fixture.files, 300);
+        assert!(fixture.dirs > 1);
+        let files = listing(first.path());
+        assert_eq!(files.len(), 300);
+        assert_eq!(files.iter().map(|f| f.1).sum::<u64>(), fixture.bytes);
+        assert!(files.iter().all(|f| f.0.components().count() <= 4));
+
+        let second = tempfile::tempdir().unwrap();
+        assert_eq!(generate(second.path(), &options).unwrap(), fixture);
+        assert_eq!(listing(second.path()), files);
+
+        let reseeded = tempfile::tempdir().unwrap();
+        let options = FixtureOptions { seed: 8, ..options };
+        generate(reseeded.path(), &options).unwrap();
+        assert_ne!(listing(reseeded.path()), files);
+        // Never written over
+        assert!(generate(first.path(), &options).is_err());
+    }
+}
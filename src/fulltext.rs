@@ -0,0 +1,540 @@
+//! Ranked full-text search of file contents with tantivy, for documents
+//! where the trigram index's exact substrings fall short: words are
+//! stemmed in the language a document is written in, results come most
+//! relevant first (BM25), and queries take `AND`, `OR`, `-word` and
+//! `"phrases"`. Source files are split into words by [`tokenize`], so a
+//! query for `getUserName` finds `get_user_name` too.
+//!
+//! Each generation keeps its index in a directory of its own next to its
+//! other files. A new generation starts from links to the files of the one
+//! before and only reads the files that changed since.
+
+use crate::archive;
+use crate::error::Error;
+use crate::extract;
+use crate::grep;
+use crate::lang;
+use crate::mapped;
+use crate::scanner::FileEntry;
+use crate::tokenize::{self, TokenOptions};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{
+    Field, INDEXED, IndexRecordOption, STORED, Schema, TantivyDocument, TextFieldIndexing,
+    TextOptions, Value,
+};
+use tantivy::tokenizer::{
+    Language, LowerCaser, PreTokenizedStream, PreTokenizedString, RemoveLongFilter,
+    SimpleTokenizer, Stemmer, TextAnalyzer, Token, Tokenizer,
+};
+use tantivy::{IndexWriter, Term};
+
+/// Languages whose words are indexed by their stem, by ISO 639-1 code;
+/// documents in any other language, and code, are indexed word by word
+const STEMMED: &[(&str, Language)] = &[
+    ("ar", Language::Arabic),
+    ("da", Language::Danish),
+    ("de", Language::German),
+    ("el", Language::Greek),
+    ("en", Language::English),
+    ("es", Language::Spanish),
+    ("fi", Language::Finnish),
+    ("fr", Language::French),
+    ("hu", Language::Hungarian),
+    ("it", Language::Italian),
+    ("nb", Language::Norwegian),
+    ("nl", Language::Dutch),
+    ("pt", Language::Portuguese),
+    ("ro", Language::Romanian),
+    ("ru", Language::Russian),
+    ("sv", Language::Swedish),
+    ("ta", Language::Tamil),
+    ("tr", Language::Turkish),
+];
+
+/// Field and tokenizer of text in no stemmed language
+const PLAIN: &str = "text";
+
+/// Field and tokenizer of source code, split into words by [`tokenize`]
+const CODE: &str = "code";
+
+/// Words longer than this many bytes, such as base64 blobs, aren't indexed
+const MAX_WORD_LEN: usize = 40;
+
+/// Threads adding documents, each buffering this many bytes before it
+/// writes a segment
+const WRITER_THREADS: usize = 4;
+const WRITER_MEMORY: usize = 32 * 1024 * 1024;
+
+/// Files read at a time, which bounds how much text is held before it's
+/// handed to the writer
+const BATCH_LEN: usize = 256;
+
+/// A file matching a full-text query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub path: PathBuf,
+    /// BM25 relevance; higher is better
+    pub score: f32,
+}
+
+/// The fields every document has
+struct Fields {
+    path: Field,
+    size: Field,
+    /// Modification time in nanoseconds since the Unix epoch, to tell
+    /// whether the document is still current
+    modified: Field,
+    plain: Field,
+    code: Field,
+    /// The field of each of [`STEMMED`], in the same order
+    stemmed: Vec<Field>,
+}
+
+impl Fields {
+    fn of(schema: &Schema) -> Result<Self> {
+        let field = |name: &str| {
+            schema
+                .get_field(name)
+                .with_context(|| format!("The full-text index has no `{}` field", name))
+        };
+        Ok(Fields {
+            path: field("path")?,
+            size: field("size")?,
+            modified: field("modified")?,
+            plain: field(PLAIN)?,
+            code: field(CODE)?,
+            stemmed: STEMMED
+                .iter()
+                .map(|(code, _)| field(&stemmed_name(code)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn text(&self) -> Vec<Field> {
+        let mut fields = vec![self.plain, self.code];
+        fields.extend(&self.stemmed);
+        fields
+    }
+
+    /// The field for text written in `language`
+    fn for_language(&self, language: Option<&str>) -> Field {
+        language
+            .and_then(|language| STEMMED.iter().position(|(code, _)| *code == language))
+            .map_or(self.plain, |position| self.stemmed[position])
+    }
+}
+
+fn stemmed_name(code: &str) -> String {
+    format!("{}_{}", PLAIN, code)
+}
+
+fn schema() -> Schema {
+    let text = |tokenizer: &str| {
+        let indexing = TextFieldIndexing::default()
+            .set_tokenizer(tokenizer)
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+        TextOptions::default().set_indexing_options(indexing)
+    };
+    let mut builder = Schema::builder();
+    builder.add_bytes_field("path", INDEXED | STORED);
+    builder.add_u64_field("size", STORED);
+    builder.add_i64_field("modified", STORED);
+    builder.add_text_field(PLAIN, text(PLAIN));
+    builder.add_text_field(CODE, text(CODE));
+    for (code, _) in STEMMED {
+        let name = stemmed_name(code);
+        builder.add_text_field(&name, text(&name));
+    }
+    builder.build()
+}
+
+/// Register the tokenizers the schema names, which tantivy doesn't store
+fn register_tokenizers(index: &tantivy::Index) {
+    let tokenizers = index.tokenizers();
+    let words = || {
+        TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(MAX_WORD_LEN))
+            .filter(LowerCaser)
+    };
+    tokenizers.register(PLAIN, words().build());
+    let code = TextAnalyzer::builder(CodeTokenizer)
+        .filter(RemoveLongFilter::limit(MAX_WORD_LEN))
+        .build();
+    tokenizers.register(CODE, code);
+    for &(code, language) in STEMMED {
+        let analyzer = words().filter(Stemmer::new(language)).build();
+        tokenizers.register(&stemmed_name(code), analyzer);
+    }
+}
+
+/// Splits queries on the code field as [`tokenize::words`] splits source,
+/// so `getUserName` looks for the words `get user name` in a row
+#[derive(Clone)]
+struct CodeTokenizer;
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = PreTokenizedStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> PreTokenizedStream {
+        pre_tokenized(text.to_string(), tokenize::words(text)).into()
+    }
+}
+
+/// `text` split into `tokens`, as the code field indexes it
+fn pre_tokenized(text: String, tokens: Vec<tokenize::Token>) -> PreTokenizedString {
+    let tokens = tokens
+        .into_iter()
+        .filter(|token| token.text.len() <= MAX_WORD_LEN)
+        .enumerate()
+        .map(|(position, token)| Token {
+            offset_from: token.span.start,
+            offset_to: token.span.end,
+            position,
+            text: token.text,
+            position_length: 1,
+        })
+        .collect();
+    PreTokenizedString { text, tokens }
+}
+
+/// What an index's commit records of how it split source code: documents
+/// are only carried over from an index that split them the same way
+fn payload(options: TokenOptions) -> &'static str {
+    match options.strip_comments {
+        true => "code:strip-comments",
+        false => "code",
+    }
+}
+
+fn open(path: &Path) -> Result<(tantivy::Index, Fields)> {
+    let index = tantivy::Index::open_in_dir(path)
+        .with_context(|| format!("Failed to open the full-text index at {}", path.display()))?;
+    register_tokenizers(&index);
+    let fields = Fields::of(&index.schema())?;
+    Ok((index, fields))
+}
+
+/// Build the full-text index of the files among `entries` in the directory
+/// `path`, skipping those over `max_content_size` bytes. The documents of
+/// files unchanged since the index at `previous` was built are carried
+/// over from it rather than read again, if it split source code by the same
+/// `options`.
+pub fn build(
+    path: &Path,
+    previous: Option<&Path>,
+    entries: &[FileEntry],
+    max_content_size: u64,
+    options: TokenOptions,
+) -> Result<()> {
+    // A generation that was set aside may have left one behind
+    if path.exists() {
+        fs::remove_dir_all(path)?;
+    }
+    fs::create_dir_all(path)?;
+    let previous = previous.filter(|previous| {
+        previous.join("meta.json").is_file()
+            && tantivy::Index::open_in_dir(previous)
+                .and_then(|index| index.load_metas())
+                .is_ok_and(|metas| metas.payload.as_deref() == Some(payload(options)))
+    });
+    let index = match previous {
+        Some(previous) => {
+            link_files(previous, path)?;
+            tantivy::Index::open_in_dir(path)?
+        }
+        None => tantivy::Index::create_in_dir(path, schema())?,
+    };
+    register_tokenizers(&index);
+    let fields = Fields::of(&index.schema())?;
+    let mut writer: IndexWriter =
+        index.writer_with_num_threads(WRITER_THREADS, WRITER_THREADS * WRITER_MEMORY)?;
+
+    let current: HashMap<Cow<[u8]>, (u64, i64)> = entries
+        .iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| (mapped::path_bytes(&entry.path), version(entry)))
+        .collect();
+    let mut kept = HashSet::new();
+    let searcher = index.reader()?.searcher();
+    for segment in searcher.segment_readers() {
+        let store = segment.get_store_reader(1)?;
+        for doc in store.iter::<TantivyDocument>(segment.alive_bitset()) {
+            let doc = doc?;
+            let path = doc
+                .get_first(fields.path)
+                .and_then(|value| value.as_bytes());
+            let size = doc.get_first(fields.size).and_then(|value| value.as_u64());
+            let modified = doc
+                .get_first(fields.modified)
+                .and_then(|value| value.as_i64());
+            let Some(path) = path else { continue };
+            let unchanged = match (size, modified) {
+                (Some(size), Some(modified)) => current.get(path) == Some(&(size, modified)),
+                _ => false,
+            };
+            match unchanged {
+                true => {
+                    kept.insert(path.to_vec());
+                }
+                false => {
+                    writer.delete_term(Term::from_field_bytes(fields.path, path));
+                }
+            }
+        }
+    }
+
+    let fresh: Vec<&FileEntry> = entries
+        .iter()
+        .filter(|entry| !entry.is_dir && !kept.contains(&*mapped::path_bytes(&entry.path)))
+        .collect();
+    for batch in fresh.chunks(BATCH_LEN) {
+        let documents: Vec<TantivyDocument> = batch
+            .par_iter()
+            .map(|entry| document(&fields, entry, max_content_size, options))
+            .collect();
+        for document in documents {
+            writer.add_document(document)?;
+        }
+    }
+    let mut commit = writer.prepare_commit()?;
+    commit.set_payload(payload(options));
+    commit.commit()?;
+    writer.wait_merging_threads()?;
+    Ok(())
+}
+
+/// Hard-link the files of the index at `from` into the directory `to`,
+/// copying those that can't be linked. Tantivy never changes a file once
+/// written, so both can go their own way from there.
+fn link_files(from: &Path, to: &Path) -> Result<()> {
+    for file in fs::read_dir(from)? {
+        let file = file?;
+        let name = file.file_name();
+        // Lock files belong to whoever holds them
+        if name.to_string_lossy().starts_with(".tantivy") || !file.file_type()?.is_file() {
+            continue;
+        }
+        let target = to.join(&name);
+        if fs::hard_link(file.path(), &target).is_err() {
+            fs::copy(file.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// What tells whether a file changed since its document was added
+fn version(entry: &FileEntry) -> (u64, i64) {
+    let modified = entry.modified.map_or(i64::MIN, |time| {
+        let (secs, nanos) = mapped::epoch_offset(time);
+        secs.saturating_mul(1_000_000_000)
+            .saturating_add(nanos.into())
+    });
+    (entry.size, modified)
+}
+
+/// The document of `entry`, without text if it's too large, binary or
+/// can't be read, so it isn't read again until it changes
+fn document(
+    fields: &Fields,
+    entry: &FileEntry,
+    max_size: u64,
+    options: TokenOptions,
+) -> TantivyDocument {
+    let mut document = TantivyDocument::default();
+    let (size, modified) = version(entry);
+    document.add_bytes(fields.path, &mapped::path_bytes(&entry.path));
+    document.add_u64(fields.size, size);
+    document.add_i64(fields.modified, modified);
+    if let Some((text, language)) = text_of(entry, max_size) {
+        match tokenize::for_path(&entry.path) {
+            Some(tokenizer) => {
+                let tokens = tokenizer.tokens(&text, options);
+                document.add_pre_tokenized_text(fields.code, pre_tokenized(text, tokens));
+            }
+            None => document.add_text(fields.for_language(language), text),
+        }
+    }
+    document
+}
+
+/// The text of `entry`, and the language it's written in if it's a prose
+/// document
+fn text_of(entry: &FileEntry, max_size: u64) -> Option<(String, Option<&'static str>)> {
+    if entry.size > max_size {
+        return None;
+    }
+    let contents = archive::read(&entry.path).ok()?;
+    let (text, document) = match extract::for_path(&entry.path) {
+        Some(extractor) => (extractor.extract(&contents).ok()?, true),
+        None => {
+            if grep::is_binary(&contents) {
+                return None;
+            }
+            let text = String::from_utf8_lossy(&contents).into_owned();
+            (text, lang::is_document(&entry.path))
+        }
+    };
+    let language = document.then(|| lang::detect(text.as_bytes())).flatten();
+    Some((text, language))
+}
+
+/// Up to `limit` files of the full-text index at `path` that match `query`
+/// and that `keep` accepts, most relevant first
+pub fn search(
+    path: &Path,
+    query: &str,
+    limit: usize,
+    keep: impl Fn(&Path) -> bool,
+) -> Result<Vec<Hit>> {
+    let (index, fields) = open(path)?;
+    let parser = QueryParser::for_index(&index, fields.text());
+    let query = parser
+        .parse_query(query)
+        .map_err(|e| anyhow::anyhow!("{}", e).context(Error::query("full-text query", query)))?;
+    let searcher = index.reader()?.searcher();
+    let limit = limit.max(1);
+    // Fetch more until enough pass `keep` or every match has been seen
+    let mut wanted = limit;
+    loop {
+        let top = searcher.search(&query, &TopDocs::with_limit(wanted).order_by_score())?;
+        let exhausted = top.len() < wanted;
+        let mut hits = Vec::new();
+        for (score, address) in top {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let Some(bytes) = doc
+                .get_first(fields.path)
+                .and_then(|value| value.as_bytes())
+            else {
+                continue;
+            };
+            let path = mapped::path_from_bytes(bytes);
+            if keep(path) {
+                hits.push(Hit {
+                    path: path.to_path_buf(),
+                    score,
+                });
+                if hits.len() == limit {
+                    return Ok(hits);
+                }
+            }
+        }
+        if exhausted {
+            return Ok(hits);
+        }
+        wanted = wanted.saturating_mul(4);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_build_and_search() {
+        let root = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| fs::write(root.path().join(name), text).unwrap();
+        write(
+            "outage.md",
+            "The connections to the database were timing out. Every request failed \
+             with an error after the timeout, so we restarted the service and the \
+             errors went away.",
+        );
+        write("notes.txt", "An error, but nothing slow");
+        write("main.rs", "fn main() { let timeout = 3; }");
+        fs::write(root.path().join("blob.bin"), b"timeout\0error").unwrap();
+        let entries = scanner::scan_directory(root.path()).unwrap().files;
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("gen-1.ftx");
+        build(&first, None, &entries, u64::MAX, TokenOptions::default()).unwrap();
+
+        let names = |hits: Vec<Hit>| -> Vec<String> {
+            hits.iter()
+                .map(|hit| hit.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        let all = |_: &Path| true;
+        let hits = search(&first, "error AND timeout", 10, all).unwrap();
+        assert_eq!(names(hits), ["outage.md"]);
+        // The English document is stemmed: `connection` finds `connections`
+        let hits = search(&first, "connection", 10, all).unwrap();
+        assert_eq!(names(hits), ["outage.md"]);
+        let hits = search(&first, "\"the timeout\"", 10, all).unwrap();
+        assert_eq!(names(hits), ["outage.md"]);
+        assert!(
+            search(&first, "\"timeout the\"", 10, all)
+                .unwrap()
+                .is_empty()
+        );
+        let hits = search(&first, "timeout", 10, all).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].score >= hits[1].score);
+        let hits = search(&first, "timeout", 10, |path| path.ends_with("main.rs")).unwrap();
+        assert_eq!(names(hits), ["main.rs"]);
+        assert!(search(&first, "AND (", 10, all).is_err());
+
+        // The next build only reads what changed
+        write("notes.txt", "Timeout, with a delay");
+        let entries = scanner::scan_directory(root.path()).unwrap().files;
+        let second = dir.path().join("gen-2.ftx");
+        build(
+            &second,
+            Some(&first),
+            &entries,
+            u64::MAX,
+            TokenOptions::default(),
+        )
+        .unwrap();
+        let hits = search(&second, "delay", 10, all).unwrap();
+        assert_eq!(names(hits), ["notes.txt"]);
+        assert_eq!(search(&second, "timeout", 10, all).unwrap().len(), 3);
+        // The first generation still answers as it was built
+        assert_eq!(search(&first, "timeout", 10, all).unwrap().len(), 2);
+        assert!(search(&first, "delay", 10, all).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_code_identifiers() {
+        let root = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| fs::write(root.path().join(name), text).unwrap();
+        write(
+            "user.rs",
+            "fn get_user_name() {} // falls back to fetchAccount",
+        );
+        write("user.py", "def getUserName():\n    return name");
+        write("notes.md", "To get the user name, ask.");
+        let entries = scanner::scan_directory(root.path()).unwrap().files;
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("gen-1.ftx");
+        build(&first, None, &entries, u64::MAX, TokenOptions::default()).unwrap();
+
+        let names = |path: &Path, query: &str| -> Vec<String> {
+            let mut names: Vec<String> = search(path, query, 10, |_| true)
+                .unwrap()
+                .iter()
+                .map(|hit| hit.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&first, "getUserName"), ["user.py", "user.rs"]);
+        assert_eq!(names(&first, "get_user_name"), ["user.py", "user.rs"]);
+        assert_eq!(names(&first, "fetch_account"), ["user.rs"]);
+
+        // Stripping comments rebuilds documents rather than carrying them over
+        let second = dir.path().join("gen-2.ftx");
+        let strip = TokenOptions {
+            strip_comments: true,
+        };
+        build(&second, Some(&first), &entries, u64::MAX, strip).unwrap();
+        assert!(names(&second, "fetchAccount").is_empty());
+        assert_eq!(names(&second, "getUserName"), ["user.py", "user.rs"]);
+    }
+}
@@ -0,0 +1,476 @@
+use crate::grep::FileMatches;
+use crate::scanner::FileEntry;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Separates the fields of a commit header in `git log` output
+const FIELD_SEP: char = '\x1f';
+
+/// The most recent commit that touched a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastCommit {
+    pub author: String,
+    pub email: String,
+    pub time: SystemTime,
+}
+
+/// Root of the git work tree containing `dir`, or `None` outside a repository
+/// or when git isn't installed
+pub fn toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8(output.stdout).ok()?;
+    dunce::canonicalize(path.trim_end()).ok()
+}
+
+/// What git says about an indexed file, as of the last scan or update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    /// Not tracked, or not in a repository at all
+    #[default]
+    Untracked,
+    Tracked,
+    /// Tracked, with changes not committed yet, staged or not
+    Modified,
+}
+
+/// Work trees of the git repositories holding `entries`: the one `root` is
+/// in, if any, and every indexed directory with a `.git` of its own, such
+/// as a nested clone or a submodule. Sorted.
+pub fn repositories(root: &Path, entries: &[FileEntry]) -> Vec<PathBuf> {
+    let mut repos: Vec<PathBuf> = entries
+        .par_iter()
+        .filter(|entry| entry.is_dir && entry.path.join(".git").exists())
+        .map(|entry| entry.path.clone())
+        .collect();
+    repos.extend(toplevel(root));
+    repos.sort();
+    repos.dedup();
+    repos
+}
+
+/// Tag every entry with the innermost repository holding it and its status
+/// there, returning the repositories git could read, which the ids refer to
+pub fn assign(root: &Path, entries: &mut [FileEntry]) -> Vec<PathBuf> {
+    let found: Vec<(PathBuf, HashMap<PathBuf, Status>)> = repositories(root, entries)
+        .into_par_iter()
+        .filter_map(|repo| {
+            let statuses = statuses(&repo).ok()?;
+            Some((repo, statuses))
+        })
+        .collect();
+    let ids: HashMap<&Path, u32> = found
+        .iter()
+        .enumerate()
+        .map(|(id, (repo, _))| (repo.as_path(), id as u32))
+        .collect();
+    for entry in entries.iter_mut() {
+        entry.repo = entry
+            .path
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| ids.get(dir).copied());
+        entry.git_status = entry
+            .repo
+            .and_then(|id| found[id as usize].1.get(&entry.path))
+            .copied()
+            .unwrap_or_default();
+    }
+    found.into_iter().map(|(repo, _)| repo).collect()
+}
+
+/// The status of every tracked file in the work tree at `repo`
+fn statuses(repo: &Path) -> Result<HashMap<PathBuf, Status>> {
+    let tracked = run_z(repo, &["ls-files", "-z"])?;
+    let mut statuses: HashMap<PathBuf, Status> = tracked
+        .iter()
+        .map(|path| (repo.join(path), Status::Tracked))
+        .collect();
+    let changes = run_z(
+        repo,
+        &["status", "--porcelain=v1", "-z", "--untracked-files=no"],
+    )?;
+    let mut fields = changes.iter();
+    while let Some(field) = fields.next() {
+        let (Some(code), Some(path)) = (field.get(..2), field.get(3..)) else {
+            continue;
+        };
+        // A rename or copy is followed by the path it came from
+        if code.contains(['R', 'C']) {
+            fields.next();
+        }
+        statuses.insert(repo.join(path), Status::Modified);
+    }
+    Ok(statuses)
+}
+
+/// The NUL-separated fields git prints for `args`, run in `repo`
+fn run_z(repo: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed in {}", args[0], repo.display());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Find the last commit touching each of `paths`.
+///
+/// History is read newest first and reading stops as soon as every path has
+/// been seen, so recently changed files are cheap to look up. Paths outside
+/// the repository or never committed are missing from the result, which is
+/// empty when `repo` isn't in a git repository at all.
+pub fn last_commits(repo: &Path, paths: &[&Path]) -> Result<HashMap<PathBuf, LastCommit>> {
+    let mut found = HashMap::new();
+    let Some(top) = toplevel(repo) else {
+        return Ok(found);
+    };
+    let mut wanted: HashSet<String> = paths
+        .iter()
+        .filter_map(|path| path.strip_prefix(&top).ok())
+        .map(git_path)
+        .collect();
+    if wanted.is_empty() {
+        return Ok(found);
+    }
+
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(&top)
+        .args(["-c", "core.quotePath=false", "log", "--no-renames"])
+        .arg(format!("--format={0}%an{0}%ae{0}%at", FIELD_SEP))
+        .arg("--name-only")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run git")?;
+    let stdout = child.stdout.take().context("Failed to read git output")?;
+
+    let mut commit: Option<LastCommit> = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if let Some(header) = line.strip_prefix(FIELD_SEP) {
+            commit = parse_header(header);
+        } else if let Some(commit) = &commit
+            && wanted.remove(&line)
+        {
+            found.insert(top.join(&line), commit.clone());
+            if wanted.is_empty() {
+                break;
+            }
+        }
+    }
+    // Stop git early once everything was found; its exit status doesn't matter
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(found)
+}
+
+/// Who last changed a line, according to `git blame`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub author: String,
+    pub email: String,
+    pub time: SystemTime,
+}
+
+/// Blame every line of `path`, in order. Uncommitted lines are attributed to
+/// "Not Committed Yet" at the current time, as git reports them.
+pub fn blame(path: &Path) -> Result<Vec<BlameLine>> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["blame", "--line-porcelain", "--"])
+        .arg(path)
+        .stderr(Stdio::null())
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git blame failed for {}", path.display());
+    }
+    Ok(parse_line_porcelain(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn parse_line_porcelain(output: &str) -> Vec<BlameLine> {
+    let mut lines = Vec::new();
+    let (mut author, mut email, mut time) = (String::new(), String::new(), 0);
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            // The line's content ends its record
+            lines.push(BlameLine {
+                author: std::mem::take(&mut author),
+                email: std::mem::take(&mut email),
+                time: UNIX_EPOCH + Duration::from_secs(time),
+            });
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = name.to_string();
+        } else if let Some(mail) = line.strip_prefix("author-mail ") {
+            email = mail.trim_matches(['<', '>']).to_string();
+        } else if let Some(secs) = line.strip_prefix("author-time ") {
+            time = secs.parse().unwrap_or(0);
+        }
+    }
+    lines
+}
+
+/// Restricts grep matches to lines last changed by an author or within a
+/// time range
+#[derive(Debug, Clone, Default)]
+pub struct BlameFilter {
+    /// Case-insensitive substring of the author's name or email
+    pub author: Option<String>,
+    /// Only lines last changed at or after this time
+    pub since: Option<SystemTime>,
+}
+
+impl BlameFilter {
+    pub fn is_empty(&self) -> bool {
+        self.author.is_none() && self.since.is_none()
+    }
+
+    pub fn matches(&self, line: &BlameLine) -> bool {
+        if let Some(author) = &self.author {
+            let author = author.to_lowercase();
+            if !line.author.to_lowercase().contains(&author)
+                && !line.email.to_lowercase().contains(&author)
+            {
+                return false;
+            }
+        }
+        self.since.is_none_or(|since| line.time >= since)
+    }
+
+    /// Drop the matches on lines the filter rejects, and files left with none.
+    ///
+    /// Files git can't blame, such as untracked ones, have no author and
+    /// are dropped entirely.
+    pub fn retain(&self, results: &mut Vec<FileMatches>) {
+        if self.is_empty() {
+            return;
+        }
+        results.retain_mut(|file| {
+            let Ok(blame) = blame(&file.path) else {
+                return false;
+            };
+            file.matches.retain(|m| {
+                blame
+                    .get(m.line_number.wrapping_sub(1))
+                    .is_some_and(|line| self.matches(line))
+            });
+            !file.matches.is_empty()
+        });
+    }
+}
+
+/// `author␟email␟timestamp`
+fn parse_header(header: &str) -> Option<LastCommit> {
+    let mut fields = header.split(FIELD_SEP);
+    let author = fields.next()?.to_string();
+    let email = fields.next()?.to_string();
+    let secs: u64 = fields.next()?.parse().ok()?;
+    Some(LastCommit {
+        author,
+        email,
+        time: UNIX_EPOCH + Duration::from_secs(secs),
+    })
+}
+
+/// A relative path as git prints it, with `/` separators
+fn git_path(relative: &Path) -> String {
+    let path = relative.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '/' {
+        path.into_owned()
+    } else {
+        path.replace(std::path::MAIN_SEPARATOR, "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=Ada", "-c", "user.email=ada@example.com"])
+            .args(args)
+            .env("GIT_COMMITTER_DATE", "2024-01-02T00:00:00Z")
+            .env("GIT_AUTHOR_DATE", "2024-01-02T00:00:00Z")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_last_commits() {
+        if Command::new("git").arg("--version").output().is_err() {
+            return; // git isn't installed
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = dunce::canonicalize(tmp.path()).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::write(repo.join("src/lib.rs"), "needle\n").unwrap();
+        fs::write(repo.join("untracked.txt"), "").unwrap();
+        git(&repo, &["add", "src/lib.rs"]);
+        git(&repo, &["commit", "-q", "-m", "init"]);
+
+        let lib = repo.join("src/lib.rs");
+        let untracked = repo.join("untracked.txt");
+        let commits = last_commits(&repo, &[&lib, &untracked]).unwrap();
+        assert_eq!(commits.len(), 1);
+        let commit = &commits[&lib];
+        assert_eq!(commit.author, "Ada");
+        assert_eq!(commit.email, "ada@example.com");
+        assert_eq!(commit.time, UNIX_EPOCH + Duration::from_secs(1_704_153_600));
+
+        let outside = tempfile::tempdir().unwrap();
+        assert!(last_commits(outside.path(), &[&lib]).unwrap().is_empty());
+
+        assert_eq!(blame(&lib).unwrap()[0].author, "Ada");
+        let options = crate::GrepOptions::default();
+        let pattern = crate::grep::build_pattern("needle", &options).unwrap();
+        let search = || crate::grep::search_files(std::slice::from_ref(&lib), &pattern, &options);
+        let mut results = search();
+        BlameFilter {
+            author: Some("ada".into()),
+            since: None,
+        }
+        .retain(&mut results);
+        assert_eq!(results.len(), 1);
+        let mut results = search();
+        BlameFilter {
+            author: Some("grace".into()),
+            since: None,
+        }
+        .retain(&mut results);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_git_filters() {
+        if Command::new("git").arg("--version").output().is_err() {
+            return; // git isn't installed
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        let lib = root.join("libs/json");
+        fs::create_dir_all(&lib).unwrap();
+        git(&root, &["init", "-q"]);
+        git(&lib, &["init", "-q"]);
+        for (name, body) in [
+            ("config.toml", "a"),
+            ("config.old", "a"),
+            ("config.tmp", "a"),
+            ("libs/json/config.rs", "a"),
+        ] {
+            fs::write(root.join(name), body).unwrap();
+        }
+        git(&root, &["add", "config.toml", "config.old"]);
+        git(&root, &["commit", "-q", "-m", "init"]);
+        git(&lib, &["add", "config.rs"]);
+        git(&lib, &["commit", "-q", "-m", "init"]);
+        fs::write(root.join("config.toml"), "b").unwrap();
+
+        let index = crate::Index::from_scan(crate::scanner::scan_directory(&root).unwrap());
+        assert_eq!(index.repos, [root.clone(), lib.clone()]);
+        let found = |filters: crate::search::Filters| {
+            let options = crate::SearchOptions {
+                filters,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("config", &options)
+                .unwrap()
+                .iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        let tracked = found(crate::search::Filters {
+            git_tracked: true,
+            ..Default::default()
+        });
+        assert_eq!(tracked, ["config.old", "config.rs", "config.toml"]);
+        let modified = found(crate::search::Filters {
+            git_modified: true,
+            ..Default::default()
+        });
+        assert_eq!(modified, ["config.toml"]);
+        let in_lib = found(crate::search::Filters {
+            repos: vec!["libs/json".into()],
+            ..Default::default()
+        });
+        assert_eq!(in_lib, ["config.rs"]);
+        assert!(index.repo_ids(&["nope".into()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_porcelain() {
+        let output = "\
+1f2e3d4c 1 1 2
+author Ada Lovelace
+author-mail <ada@example.com>
+author-time 1704153600
+summary first
+\tfn main() {
+1f2e3d4c 2 2
+author Charles Babbage
+author-mail <cb@example.com>
+author-time 1600000000
+\t}
+";
+        let lines = parse_line_porcelain(output);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].author, "Ada Lovelace");
+        assert_eq!(lines[0].email, "ada@example.com");
+
+        let by_ada = BlameFilter {
+            author: Some("ADA".into()),
+            since: None,
+        };
+        assert!(by_ada.matches(&lines[0]) && !by_ada.matches(&lines[1]));
+        let by_email = BlameFilter {
+            author: Some("cb@".into()),
+            since: None,
+        };
+        assert!(by_email.matches(&lines[1]));
+        let recent = BlameFilter {
+            author: None,
+            since: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        };
+        assert!(recent.matches(&lines[0]) && !recent.matches(&lines[1]));
+    }
+}
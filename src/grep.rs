@@ -0,0 +1,814 @@
+use crate::archive;
+use crate::error::{Error, Result};
+use crate::extract;
+use crate::tokenize;
+use anyhow::Context;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::ops::{ControlFlow, Range};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Files at least this large are memory-mapped instead of read into memory
+const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+/// Number of leading bytes inspected when deciding whether a file is binary
+pub(crate) const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+const HIGHLIGHT_START: &str = "\x1b[1;31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+/// Options controlling how the grep pattern is interpreted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GrepOptions {
+    /// Treat the pattern as a literal string instead of a regex
+    pub fixed_strings: bool,
+    /// Match case-insensitively
+    pub ignore_case: bool,
+    /// Skip files larger than this many bytes
+    pub max_filesize: Option<u64>,
+    /// Only search documents detected to be written in this language, an
+    /// ISO 639-1 code; needs a content index
+    pub doc_lang: Option<String>,
+    /// Lines to report around each match
+    #[serde(default)]
+    pub context: ContextLines,
+    /// Stop reading a file after this many matching lines
+    #[serde(default)]
+    pub max_count: Option<usize>,
+    /// Stop searching once this many matching lines are found in all
+    #[serde(default)]
+    pub max_total: Option<usize>,
+    /// Which patterns that are a single identifier of several words, such
+    /// as `getUserName`, match it in its other spellings too (see
+    /// [`identifier_words`])
+    #[serde(default)]
+    pub spellings: Spellings,
+}
+
+/// Which identifiers a pattern matching one also matches spelled otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Spellings {
+    /// Only the pattern as written
+    #[default]
+    Exact,
+    /// Patterns in camelCase or PascalCase, whose words only their case
+    /// tells apart, so none could have meant its separators
+    Joined,
+    /// Any identifier, `get_user_name` and `get-user-name` too
+    Any,
+}
+
+/// How many lines before and after each match `grep` reports with it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextLines {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl ContextLines {
+    pub fn is_empty(self) -> bool {
+        self.before == 0 && self.after == 0
+    }
+}
+
+/// A single matching line within a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineMatch {
+    /// 1-based line number
+    pub line_number: usize,
+    /// Line contents without the trailing newline
+    pub line: String,
+    /// Byte ranges of every match within `line`
+    pub spans: Vec<Range<usize>>,
+    /// Byte offset of the start of the line within the file
+    #[serde(default)]
+    pub offset: u64,
+    /// Context lines just before this one, never reaching back into the
+    /// previous match or its context
+    #[serde(default)]
+    pub before: Vec<String>,
+    /// Context lines just after this one, stopping short of the next match
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+impl LineMatch {
+    /// 1-based byte column of the first match, as editors expect
+    pub fn column(&self) -> usize {
+        self.spans.first().map_or(0, |span| span.start) + 1
+    }
+
+    /// Byte offset of the first match within the file
+    pub fn byte_offset(&self) -> u64 {
+        self.offset + self.spans.first().map_or(0, |span| span.start) as u64
+    }
+}
+
+/// All matches found in one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub matches: Vec<LineMatch>,
+}
+
+/// The words of `pattern` when it's a single identifier of several that
+/// [`GrepOptions::spellings`] lets match in another case style:
+/// `getUserName` matches `get_user_name`, `GetUserName` and `get-user-name`
+/// too. `None` for fixed strings and other patterns.
+pub fn identifier_words(pattern: &str, options: &GrepOptions) -> Option<Vec<String>> {
+    let separated = pattern.contains(['_', '-']);
+    let spelled = match options.spellings {
+        Spellings::Exact => false,
+        Spellings::Joined => !separated,
+        Spellings::Any => true,
+    };
+    if !spelled
+        || options.fixed_strings
+        || !pattern
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+    let words: Vec<String> = tokenize::words(pattern)
+        .into_iter()
+        .map(|token| token.text)
+        .collect();
+    (words.len() > 1).then_some(words)
+}
+
+/// Regex source for the user's pattern, escaping it for fixed-string searches
+/// and spelling out an identifier's other spellings: in any case when
+/// ignoring case, and otherwise only in the usual case styles
+pub fn pattern_source(pattern: &str, options: &GrepOptions) -> String {
+    if let Some(words) = identifier_words(pattern, options) {
+        let words: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+        if options.ignore_case {
+            return words.join("[_-]?");
+        }
+        let capitalized: Vec<String> = words.iter().map(|word| capitalize(word)).collect();
+        let mut spellings = vec![
+            regex::escape(pattern),
+            format!("{}{}", words[0], capitalized[1..].concat()),
+            capitalized.concat(),
+            words.join("_"),
+            words.join("_").to_uppercase(),
+            words.join("-"),
+        ];
+        spellings.sort();
+        spellings.dedup();
+        return format!("(?:{})", spellings.join("|"));
+    }
+    if options.fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    }
+}
+
+/// `word` with its first letter in upper case
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Compile the user's pattern according to `options`. `^` and `$` anchor
+/// at every line, even when the pattern runs over a whole file at once.
+pub fn build_pattern(pattern: &str, options: &GrepOptions) -> Result<Regex> {
+    let regex = RegexBuilder::new(&pattern_source(pattern, options))
+        .case_insensitive(options.ignore_case)
+        .multi_line(true)
+        .crlf(true)
+        .build()
+        .with_context(|| Error::query("search pattern", pattern))?;
+    Ok(regex)
+}
+
+/// Search a single file, returning `None` if it has no matches or is binary.
+/// Files inside archives are read from them, and documents [`extract`]
+/// reads are searched through their text.
+pub fn search_file(
+    path: &Path,
+    pattern: &Regex,
+    options: &GrepOptions,
+) -> Result<Option<FileMatches>> {
+    let too_large = |len: u64| options.max_filesize.is_some_and(|max| len > max);
+    let extractor = extract::for_path(path);
+    let mapped;
+    let buffer;
+    let contents: &[u8] = if archive::split(path).is_some() {
+        // Only the member is decompressed, and none too large to search
+        let Some(member) = archive::read_within(path, too_large)? else {
+            return Ok(None);
+        };
+        buffer = member;
+        &buffer
+    } else {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let len = file.metadata()?.len();
+        if len == 0 || too_large(len) {
+            return Ok(None);
+        }
+        if len >= MMAP_THRESHOLD && extractor.is_none() {
+            // SAFETY: the map is read-only and dropped before returning; a file
+            // truncated concurrently may fault, which is the usual mmap caveat
+            // accepted by grep-like tools.
+            mapped = unsafe { Mmap::map(&file) }
+                .with_context(|| format!("Failed to map {}", path.display()))?;
+            &mapped
+        } else {
+            buffer = std::fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            &buffer
+        }
+    };
+    let text;
+    let contents = match extractor {
+        Some(extractor) => {
+            text = extractor
+                .extract(contents)
+                .with_context(|| format!("Failed to extract the text of {}", path.display()))?;
+            text.as_bytes()
+        }
+        None => contents,
+    };
+
+    let matches = search_contents(contents, pattern, options);
+    if matches.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(FileMatches {
+        path: path.to_path_buf(),
+        matches,
+    }))
+}
+
+/// Matching lines of `contents`; binary contents never match
+pub fn search_bytes(contents: &[u8], pattern: &Regex) -> Vec<LineMatch> {
+    search_contents(contents, pattern, &GrepOptions::default())
+}
+
+/// Matching lines of `contents`, up to `options.max_count` of them, with
+/// `options.context` lines around each
+pub fn search_contents(contents: &[u8], pattern: &Regex, options: &GrepOptions) -> Vec<LineMatch> {
+    if is_binary(contents) || !pattern.is_match(contents) {
+        return Vec::new();
+    }
+    if contents.is_empty() {
+        return Vec::new();
+    }
+    // A final newline ends the last line rather than starting another
+    let body = contents.strip_suffix(b"\n").unwrap_or(contents);
+    let mut offset = 0;
+    let mut matches: Vec<LineMatch> = body
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter_map(|(i, raw)| {
+            let start = offset;
+            offset += raw.len() as u64 + 1;
+            let raw = strip_cr(raw);
+            let spans: Vec<Range<usize>> = pattern.find_iter(raw).map(|m| m.range()).collect();
+            if spans.is_empty() {
+                return None;
+            }
+            Some(LineMatch {
+                line_number: i + 1,
+                line: String::from_utf8_lossy(raw).into_owned(),
+                spans,
+                offset: start,
+                before: Vec::new(),
+                after: Vec::new(),
+            })
+        })
+        .take(options.max_count.unwrap_or(usize::MAX))
+        .collect();
+    if !options.context.is_empty() {
+        add_context(contents, &mut matches, options.context);
+    }
+    matches
+}
+
+fn strip_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Fill in the context lines of `matches`, found in `contents`
+fn add_context(contents: &[u8], matches: &mut [LineMatch], context: ContextLines) {
+    let contents = contents.strip_suffix(b"\n").unwrap_or(contents);
+    let lines: Vec<&[u8]> = contents.split(|&b| b == b'\n').map(strip_cr).collect();
+    let text = |range: Range<usize>| -> Vec<String> {
+        lines[range]
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect()
+    };
+    // Index of the first line no earlier match or context has covered
+    let mut covered = 0;
+    for k in 0..matches.len() {
+        let i = matches[k].line_number - 1;
+        let next = matches
+            .get(k + 1)
+            .map_or(lines.len(), |m| m.line_number - 1);
+        let end = (i + 1 + context.after).min(next).min(lines.len());
+        matches[k].before = text(i.saturating_sub(context.before).max(covered)..i);
+        matches[k].after = text((i + 1).min(end)..end);
+        covered = end;
+    }
+}
+
+/// `m` with every match of `pattern` in its line replaced by `template`,
+/// which may refer to capture groups as `$1` or `${name}`, as `regex`'s
+/// `replace_all` does. The spans are those of the replacements.
+pub fn replace_matches(m: &LineMatch, pattern: &Regex, template: &str) -> LineMatch {
+    let line = m.line.as_bytes();
+    let mut replaced = Vec::with_capacity(line.len());
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for captures in pattern.captures_iter(line) {
+        let whole = captures.get_match();
+        replaced.extend_from_slice(&line[last..whole.start()]);
+        let start = replaced.len();
+        captures.expand(template.as_bytes(), &mut replaced);
+        spans.push(start..replaced.len());
+        last = whole.end();
+    }
+    replaced.extend_from_slice(&line[last..]);
+    LineMatch {
+        line: String::from_utf8_lossy(&replaced).into_owned(),
+        spans,
+        ..m.clone()
+    }
+}
+
+/// Search many files in parallel, preserving the input order in the results.
+///
+/// Unreadable files (permission denied, vanished since indexing) are skipped.
+pub fn search_files(paths: &[PathBuf], pattern: &Regex, options: &GrepOptions) -> Vec<FileMatches> {
+    let _span = crate::profile::span("match");
+    let max_total = options.max_total.unwrap_or(usize::MAX);
+    let found = AtomicUsize::new(0);
+    let mut results: Vec<FileMatches> = paths
+        .par_iter()
+        .filter_map(|path| {
+            // Files already being searched finish, so there may be a few more
+            if found.load(Ordering::Relaxed) >= max_total {
+                return None;
+            }
+            let file = search_file(path, pattern, options).ok().flatten()?;
+            found.fetch_add(file.matches.len(), Ordering::Relaxed);
+            Some(file)
+        })
+        .collect();
+    if options.max_total.is_some() {
+        limit_lines(&mut results, max_total);
+    }
+    results
+}
+
+/// Search many files in parallel like [`search_files`], but hand each file
+/// with matches to `on_file` as soon as it's been searched, in the order
+/// they finish. Stops after `options.max_total` matching lines, or once
+/// `on_file` breaks.
+pub fn search_files_streaming(
+    paths: &[PathBuf],
+    pattern: &Regex,
+    options: &GrepOptions,
+    mut on_file: impl FnMut(FileMatches) -> ControlFlow<()>,
+) {
+    if options.max_total == Some(0) {
+        return;
+    }
+    let _span = crate::profile::span("match");
+    let stop = AtomicBool::new(false);
+    let (sender, files) = mpsc::channel();
+    std::thread::scope(|scope| {
+        let stop = &stop;
+        scope.spawn(move || {
+            paths.par_iter().for_each_with(sender, |sender, path| {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if let Some(file) = search_file(path, pattern, options).ok().flatten() {
+                    // The receiver only goes away once it's stopped listening
+                    let _ = sender.send(file);
+                }
+            });
+        });
+        let mut remaining = options.max_total.unwrap_or(usize::MAX);
+        for mut file in files {
+            file.matches.truncate(remaining);
+            remaining -= file.matches.len();
+            if on_file(file).is_break() || remaining == 0 {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+}
+
+/// Keep only the first `limit` matching lines, dropping files left empty
+pub fn limit_lines(results: &mut Vec<FileMatches>, limit: usize) {
+    let mut remaining = limit;
+    results.retain_mut(|file| {
+        file.matches.truncate(remaining);
+        remaining -= file.matches.len();
+        !file.matches.is_empty()
+    });
+}
+
+/// Heuristic used by most grep tools: a NUL byte near the start means binary
+pub(crate) fn is_binary(contents: &[u8]) -> bool {
+    let sniff = &contents[..contents.len().min(BINARY_SNIFF_LEN)];
+    sniff.contains(&0)
+}
+
+/// Render `line` with every span wrapped in ANSI highlight codes
+pub fn highlight(line: &str, spans: &[Range<usize>], color: bool) -> String {
+    if !color {
+        return line.to_string();
+    }
+    let mut out = String::with_capacity(line.len() + spans.len() * 12);
+    let mut last = 0;
+    for span in spans {
+        // Spans come from byte offsets; lossy UTF-8 conversion may have shifted
+        // boundaries, so fall back to plain text rather than slicing mid-char.
+        let (Some(before), Some(matched)) = (line.get(last..span.start), line.get(span.clone()))
+        else {
+            return line.to_string();
+        };
+        out.push_str(before);
+        out.push_str(HIGHLIGHT_START);
+        out.push_str(matched);
+        out.push_str(HIGHLIGHT_END);
+        last = span.end;
+    }
+    out.push_str(line.get(last..).unwrap_or_default());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_search_file_reports_lines_and_spans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "alpha\nbeta target\r\ngamma\ntarget target\n").unwrap();
+
+        let pattern = build_pattern("target", &GrepOptions::default()).unwrap();
+        let found = search_file(&path, &pattern, &GrepOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.matches.len(), 2);
+        assert_eq!(found.matches[0].line_number, 2);
+        assert_eq!(found.matches[0].line, "beta target");
+        assert_eq!(found.matches[0].spans, vec![5..11]);
+        assert_eq!(found.matches[1].spans, vec![0..6, 7..13]);
+        assert_eq!(found.matches[0].offset, 6);
+        assert_eq!(found.matches[0].column(), 6);
+        assert_eq!(found.matches[0].byte_offset(), 11);
+        assert_eq!(found.matches[1].byte_offset(), 25);
+    }
+
+    #[test]
+    fn test_context_lines_never_overlap() {
+        let pattern = build_pattern("match", &GrepOptions::default()).unwrap();
+        let contents = b"a\nb\nmatch 1\nc\nmatch 2\nd\ne\nf\ng\nmatch 3\r\nh\n";
+        let context = ContextLines {
+            before: 2,
+            after: 2,
+        };
+        let options = GrepOptions {
+            context,
+            ..Default::default()
+        };
+        let found = search_contents(contents, &pattern, &options);
+        let lines: Vec<(Vec<String>, Vec<String>)> = found
+            .iter()
+            .map(|m| (m.before.clone(), m.after.clone()))
+            .collect();
+        let strings =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|l| l.to_string()).collect() };
+        assert_eq!(
+            lines,
+            [
+                (strings(&["a", "b"]), strings(&["c"])),
+                (strings(&[]), strings(&["d", "e"])),
+                (strings(&["f", "g"]), strings(&["h"])),
+            ]
+        );
+        assert!(search_bytes(contents, &pattern)[0].before.is_empty());
+    }
+
+    #[test]
+    fn test_replace_matches_expands_captures() {
+        let pattern = build_pattern(r"import (?<module>\w+)", &GrepOptions::default()).unwrap();
+        let m = &search_bytes(b"import os; import sys", &pattern)[0];
+        let replaced = replace_matches(m, &pattern, "<${module}>");
+        assert_eq!(replaced.line, "<os>; <sys>");
+        assert_eq!(replaced.spans, vec![0..4, 6..11]);
+        assert_eq!(replace_matches(m, &pattern, "$1").line, "os; sys");
+        assert_eq!(replaced.line_number, m.line_number);
+    }
+
+    #[test]
+    fn test_trailing_newline_ends_the_last_line() {
+        let anything = build_pattern("", &GrepOptions::default()).unwrap();
+        let lines = |contents: &[u8]| -> Vec<usize> {
+            search_contents(contents, &anything, &GrepOptions::default())
+                .iter()
+                .map(|m| m.line_number)
+                .collect()
+        };
+        assert_eq!(lines(b"one\ntwo\n"), [1, 2]);
+        assert_eq!(lines(b"one\ntwo"), [1, 2]);
+        assert_eq!(lines(b"one\n\n"), [1, 2]);
+        assert!(lines(b"").is_empty());
+    }
+
+    #[test]
+    fn test_anchors_match_every_line() {
+        let lines = |pattern: &str, contents: &[u8]| -> Vec<usize> {
+            let pattern = build_pattern(pattern, &GrepOptions::default()).unwrap();
+            search_contents(contents, &pattern, &GrepOptions::default())
+                .iter()
+                .map(|m| m.line_number)
+                .collect()
+        };
+        assert_eq!(
+            lines(
+                "b$", b"a
+b
+ab
+"
+            ),
+            [2, 3]
+        );
+        assert_eq!(
+            lines(
+                "^b$", b"a
+b
+ab
+"
+            ),
+            [2]
+        );
+        assert_eq!(
+            lines(
+                "^b$", b"a
+b
+"
+            ),
+            [2]
+        );
+        assert_eq!(
+            lines(
+                "^$", b"a
+
+b
+"
+            ),
+            [2]
+        );
+    }
+
+    #[test]
+    fn test_search_file_skips_binary_and_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("blob.bin");
+        fs::write(&binary, b"target\0\x01\x02").unwrap();
+        let empty = dir.path().join("empty.txt");
+        fs::write(&empty, "").unwrap();
+
+        let pattern = build_pattern("target", &GrepOptions::default()).unwrap();
+        assert!(
+            search_file(&binary, &pattern, &GrepOptions::default())
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            search_file(&empty, &pattern, &GrepOptions::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_search_large_file_uses_mmap() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        let mut contents = "filler line\n".repeat(10_000);
+        contents.push_str("needle here\n");
+        fs::write(&path, contents).unwrap();
+
+        let pattern = build_pattern("needle", &GrepOptions::default()).unwrap();
+        let found = search_file(&path, &pattern, &GrepOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.matches.len(), 1);
+        assert_eq!(found.matches[0].line_number, 10_001);
+    }
+
+    #[test]
+    fn test_max_filesize_skips_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.js");
+        fs::write(&path, "needle ".repeat(100)).unwrap();
+
+        let pattern = build_pattern("needle", &GrepOptions::default()).unwrap();
+        let options = GrepOptions {
+            max_filesize: Some(100),
+            ..Default::default()
+        };
+        assert!(search_file(&path, &pattern, &options).unwrap().is_none());
+        let options = GrepOptions {
+            max_filesize: Some(1000),
+            ..Default::default()
+        };
+        assert!(search_file(&path, &pattern, &options).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_match_caps() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = ["a.js", "b.js", "c.js"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for path in &paths {
+            fs::write(path, "x\n".repeat(50)).unwrap();
+        }
+        let pattern = build_pattern("x", &GrepOptions::default()).unwrap();
+        let options = GrepOptions {
+            max_count: Some(3),
+            ..Default::default()
+        };
+        let results = search_files(&paths, &pattern, &options);
+        assert!(results.iter().all(|file| file.matches.len() == 3));
+        let options = GrepOptions {
+            max_count: Some(30),
+            max_total: Some(40),
+            ..Default::default()
+        };
+        let results = search_files(&paths, &pattern, &options);
+        let counts: Vec<usize> = results.iter().map(|file| file.matches.len()).collect();
+        assert_eq!(counts, [30, 10]);
+    }
+
+    #[test]
+    fn test_fixed_strings_and_ignore_case() {
+        let options = GrepOptions {
+            fixed_strings: true,
+            ignore_case: true,
+            ..Default::default()
+        };
+        let pattern = build_pattern("a.B", &options).unwrap();
+        assert!(pattern.is_match(b"xA.bx"));
+        assert!(!pattern.is_match(b"aXb"));
+        assert!(build_pattern("(unclosed", &GrepOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_identifier_spellings() {
+        let options = GrepOptions {
+            spellings: Spellings::Joined,
+            ..Default::default()
+        };
+        let pattern = build_pattern("getUserName", &options).unwrap();
+        for spelling in [
+            "getUserName",
+            "get_user_name",
+            "GetUserName",
+            "get-user-name",
+            "GET_USER_NAME",
+        ] {
+            assert!(pattern.is_match(spelling.as_bytes()), "{}", spelling);
+        }
+        // Case-sensitive, so only the usual case styles
+        assert!(!pattern.is_match(b"GETUSERNAME"));
+        assert!(!pattern.is_match(b"get_name"));
+        let ignore_case = GrepOptions {
+            ignore_case: true,
+            ..options.clone()
+        };
+        assert!(
+            build_pattern("getUserName", &ignore_case)
+                .unwrap()
+                .is_match(b"GETUSERNAME")
+        );
+        assert_eq!(identifier_words("user", &options), None);
+        assert_eq!(identifier_words("get.*name", &options), None);
+        let fixed = GrepOptions {
+            fixed_strings: true,
+            ..options.clone()
+        };
+        assert!(
+            !build_pattern("getUserName", &fixed)
+                .unwrap()
+                .is_match(b"get_user_name")
+        );
+        let plain = build_pattern("getUserName", &GrepOptions::default()).unwrap();
+        assert!(!plain.is_match(b"get_user_name"));
+
+        // Separators stay as written unless any spelling is asked for
+        assert_eq!(identifier_words("foo-bar", &options), None);
+        assert!(
+            !build_pattern("foo-bar", &options)
+                .unwrap()
+                .is_match(b"foobar")
+        );
+        let any = GrepOptions {
+            spellings: Spellings::Any,
+            ..options
+        };
+        let pattern = build_pattern("foo-bar", &any).unwrap();
+        assert!(pattern.is_match(b"fooBar"));
+        assert!(!pattern.is_match(b"foobar"));
+    }
+
+    #[test]
+    fn test_search_files_preserves_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..20)
+            .map(|i| {
+                let path = dir.path().join(format!("f{}.txt", i));
+                let body = if i % 2 == 0 { "hit" } else { "miss" };
+                fs::write(&path, body).unwrap();
+                path
+            })
+            .collect();
+
+        let pattern = build_pattern("hit", &GrepOptions::default()).unwrap();
+        let results = search_files(&paths, &pattern, &GrepOptions::default());
+        assert_eq!(results.len(), 10);
+        let expected: Vec<&PathBuf> = paths.iter().step_by(2).collect();
+        let actual: Vec<&PathBuf> = results.iter().map(|r| &r.path).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_search_files_streaming() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..20)
+            .map(|i| {
+                let path = dir.path().join(format!("f{}.txt", i));
+                fs::write(&path, if i % 2 == 0 { "hit\nhit\n" } else { "miss" }).unwrap();
+                path
+            })
+            .collect();
+        let pattern = build_pattern("hit", &GrepOptions::default()).unwrap();
+        let stream = |options: &GrepOptions, stop_after: usize| {
+            let mut found = Vec::new();
+            search_files_streaming(&paths, &pattern, options, |file| {
+                found.push(file);
+                match found.len() < stop_after {
+                    true => ControlFlow::Continue(()),
+                    false => ControlFlow::Break(()),
+                }
+            });
+            found
+        };
+        let mut all = stream(&GrepOptions::default(), usize::MAX);
+        all.sort_by(|a, b| a.path.cmp(&b.path));
+        let unordered = search_files(&paths, &pattern, &GrepOptions::default());
+        let mut expected = unordered.clone();
+        expected.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(all, expected);
+
+        assert_eq!(stream(&GrepOptions::default(), 3).len(), 3);
+        let capped = GrepOptions {
+            max_total: Some(3),
+            ..Default::default()
+        };
+        let lines: usize = stream(&capped, usize::MAX)
+            .iter()
+            .map(|file| file.matches.len())
+            .sum();
+        assert_eq!(lines, 3);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let spans = vec![2..8, 11..17];
+        assert_eq!(
+            highlight("a target b target", &spans, false),
+            "a target b target"
+        );
+        assert_eq!(
+            highlight("a target b target", &spans, true),
+            "a \x1b[1;31mtarget\x1b[0m b \x1b[1;31mtarget\x1b[0m"
+        );
+    }
+}
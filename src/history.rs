@@ -0,0 +1,312 @@
+use crate::git;
+use crate::grep::{self, GrepOptions, LineMatch};
+use crate::index::{read_versioned, write_versioned};
+use crate::trigram::{DEFAULT_MAX_CONTENT_SIZE, TrigramIndex};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Magic bytes identifying a history index file
+const HISTORY_MAGIC: &[u8; 4] = b"SSHX";
+
+/// File holding the history index inside the index directory
+const HISTORY_FILE: &str = "history.bin";
+
+/// A file version stored in git, identified by its blob id.
+///
+/// Identical contents share one blob however many commits and paths
+/// contain them, so each is indexed and searched once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryBlob {
+    /// Object id of the blob
+    pub oid: String,
+    /// A path the blob was committed under, relative to the repository root
+    pub path: String,
+}
+
+/// Trigram index over every blob reachable from any ref of a repository
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HistoryIndex {
+    pub blobs: Vec<HistoryBlob>,
+    content: TrigramIndex,
+}
+
+/// A commit that added or removed a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitRef {
+    pub id: String,
+    pub time: SystemTime,
+    pub summary: String,
+}
+
+/// Matches within one historical version of a file
+#[derive(Debug, Clone)]
+pub struct HistoryMatch {
+    pub blob: HistoryBlob,
+    pub matches: Vec<LineMatch>,
+}
+
+impl HistoryIndex {
+    pub fn path(index_dir: &Path) -> PathBuf {
+        index_dir.join(HISTORY_FILE)
+    }
+
+    /// Load the history index stored in `index_dir`, or an empty one if
+    /// there is none or it was written by another version
+    pub fn load(index_dir: &Path) -> Self {
+        read_versioned(&Self::path(index_dir), HISTORY_MAGIC).unwrap_or_default()
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        write_versioned(&Self::path(index_dir), HISTORY_MAGIC, self, false)
+    }
+
+    /// Index the blobs reachable from any ref of `repo` that aren't indexed
+    /// yet, returning how many were added
+    pub fn update(&mut self, repo: &Path) -> Result<usize> {
+        let top = git::toplevel(repo)
+            .with_context(|| format!("{} is not in a git repository", repo.display()))?;
+        let known: HashSet<&str> = self.blobs.iter().map(|b| b.oid.as_str()).collect();
+
+        // Every object with a path, newest commits first; trees are weeded out below
+        let listing = git_output(&top, &["rev-list", "--objects", "--all"])?;
+        let mut seen = HashSet::new();
+        let objects: Vec<(String, String)> = listing
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(oid, _)| !known.contains(oid) && seen.insert(*oid))
+            .map(|(oid, path)| (oid.to_string(), path.to_string()))
+            .collect();
+        drop(known);
+
+        let oids: Vec<&str> = objects.iter().map(|(oid, _)| oid.as_str()).collect();
+        let mut added = 0;
+        let mut batch = CatFile::spawn(&top, &oids)?;
+        for (oid, path) in &objects {
+            let Some((kind, contents)) = batch.next_object()? else {
+                break;
+            };
+            if kind != "blob" {
+                continue;
+            }
+            let id = self.blobs.len() as u32;
+            let indexable = contents.len() as u64 <= DEFAULT_MAX_CONTENT_SIZE;
+            self.content
+                .push(id, indexable.then_some(contents.as_slice()));
+            self.blobs.push(HistoryBlob {
+                oid: oid.clone(),
+                path: path.clone(),
+            });
+            added += 1;
+        }
+        batch.finish();
+        Ok(added)
+    }
+
+    /// Search every indexed blob of `repo` for `query`
+    pub fn grep(
+        &self,
+        repo: &Path,
+        query: &str,
+        options: &GrepOptions,
+    ) -> Result<Vec<HistoryMatch>> {
+        let pattern = grep::build_pattern(query, options)?;
+        let top = git::toplevel(repo)
+            .with_context(|| format!("{} is not in a git repository", repo.display()))?;
+        let source = grep::pattern_source(query, options);
+        let candidates = match grep::identifier_words(query, options) {
+            Some(words) => self.content.identifier_candidates(&words),
+            None => self.content.candidates(&source, options.ignore_case),
+        };
+        let candidates: Vec<&HistoryBlob> = match candidates {
+            Some(ids) => ids.into_iter().map(|id| &self.blobs[id as usize]).collect(),
+            None => self.blobs.iter().collect(),
+        };
+
+        let oids: Vec<&str> = candidates.iter().map(|blob| blob.oid.as_str()).collect();
+        let mut batch = CatFile::spawn(&top, &oids)?;
+        let mut results = Vec::new();
+        for blob in candidates {
+            let Some((_, contents)) = batch.next_object()? else {
+                break;
+            };
+            if options
+                .max_filesize
+                .is_some_and(|max| contents.len() as u64 > max)
+            {
+                continue;
+            }
+            let matches = grep::search_bytes(&contents, &pattern);
+            if !matches.is_empty() {
+                results.push(HistoryMatch {
+                    blob: blob.clone(),
+                    matches,
+                });
+            }
+        }
+        batch.finish();
+        Ok(results)
+    }
+}
+
+/// Commits that added or removed blob `oid`, oldest first
+pub fn blob_commits(repo: &Path, oid: &str) -> Result<Vec<CommitRef>> {
+    let find_object = format!("--find-object={}", oid);
+    let output = git_output(
+        repo,
+        &[
+            "log",
+            "--all",
+            "--reverse",
+            "--format=%H%x1f%ct%x1f%s",
+            &find_object,
+        ],
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\x1f');
+            let id = fields.next()?.to_string();
+            let secs: u64 = fields.next()?.parse().ok()?;
+            Some(CommitRef {
+                id,
+                time: UNIX_EPOCH + Duration::from_secs(secs),
+                summary: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed", args.first().unwrap_or(&""));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads object contents through one `git cat-file --batch` process
+struct CatFile {
+    child: Child,
+    stdout: BufReader<std::process::ChildStdout>,
+    writer: Option<std::thread::JoinHandle<()>>,
+}
+
+impl CatFile {
+    /// Start reading `oids`; objects come back in the same order
+    fn spawn(repo: &Path, oids: &[&str]) -> Result<Self> {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to run git")?;
+        let mut stdin = child.stdin.take().context("Failed to write to git")?;
+        let stdout = BufReader::new(child.stdout.take().context("Failed to read git output")?);
+        // Feed ids from another thread so a full stdout pipe can't deadlock us
+        let input: String = oids.iter().map(|oid| format!("{}\n", oid)).collect();
+        let writer = std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        Ok(CatFile {
+            child,
+            stdout,
+            writer: Some(writer),
+        })
+    }
+
+    /// Type and contents of the next object
+    fn next_object(&mut self) -> Result<Option<(String, Vec<u8>)>> {
+        let mut header = String::new();
+        if self.stdout.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        // `<oid> <type> <size>`, or `<oid> missing`
+        let mut fields = header.split_whitespace().skip(1);
+        let kind = fields.next().unwrap_or_default().to_string();
+        let Some(size) = fields.next().and_then(|size| size.parse::<usize>().ok()) else {
+            return Ok(Some((kind, Vec::new())));
+        };
+        let mut contents = vec![0; size + 1];
+        self.stdout.read_exact(&mut contents)?;
+        contents.pop(); // the newline after each object
+        Ok(Some((kind, contents)))
+    }
+
+    fn finish(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=Ada", "-c", "user.email=ada@example.com"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_history_finds_deleted_content() {
+        if Command::new("git").arg("--version").output().is_err() {
+            return; // git isn't installed
+        }
+        let repo = tempfile::tempdir().unwrap();
+        let repo = repo.path();
+        git(repo, &["init", "-q"]);
+        fs::write(repo.join("notes.txt"), "the secret_token is here\n").unwrap();
+        fs::write(repo.join("copy.txt"), "the secret_token is here\n").unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "add notes"]);
+        fs::write(repo.join("notes.txt"), "nothing to see\n").unwrap();
+        fs::remove_file(repo.join("copy.txt")).unwrap();
+        git(repo, &["commit", "-q", "-am", "scrub"]);
+
+        let mut history = HistoryIndex::default();
+        // Two distinct contents: the identical files share one blob
+        assert_eq!(history.update(repo).unwrap(), 2);
+        assert_eq!(history.update(repo).unwrap(), 0);
+
+        let index_dir = tempfile::tempdir().unwrap();
+        history.save(index_dir.path()).unwrap();
+        let history = HistoryIndex::load(index_dir.path());
+        assert_eq!(history.blobs.len(), 2);
+
+        let options = GrepOptions::default();
+        let results = history.grep(repo, "secret_token", &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matches[0].line_number, 1);
+
+        let commits = blob_commits(repo, &results[0].blob.oid).unwrap();
+        let summaries: Vec<&str> = commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["add notes", "scrub"]);
+        assert!(history.grep(repo, "absent", &options).unwrap().is_empty());
+    }
+}
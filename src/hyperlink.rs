@@ -0,0 +1,161 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use std::path::Path;
+
+/// Templates of the `--hyperlink-format` presets, by name
+const PRESETS: &[(&str, &str)] = &[
+    ("file", "file://{path}"),
+    ("vscode", "vscode://file{path}:{line}:{column}"),
+    (
+        "vscode-insiders",
+        "vscode-insiders://file{path}:{line}:{column}",
+    ),
+    ("vscodium", "vscodium://file{path}:{line}:{column}"),
+    ("cursor", "cursor://file{path}:{line}:{column}"),
+    ("idea", "idea://open?file={path}&line={line}"),
+    (
+        "textmate",
+        "txmt://open?url=file://{path}&line={line}&column={column}",
+    ),
+    (
+        "macvim",
+        "mvim://open?url=file://{path}&line={line}&column={column}",
+    ),
+];
+
+/// When to make paths in text output clickable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HyperlinkChoice {
+    /// Only when stdout is a terminal known to support OSC 8 hyperlinks
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl HyperlinkChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            HyperlinkChoice::Auto => std::io::stdout().is_terminal() && terminal_supports_links(),
+            HyperlinkChoice::Always => true,
+            HyperlinkChoice::Never => false,
+        }
+    }
+}
+
+/// Whether the terminal we're running in is one that renders OSC 8
+/// hyperlinks, going by the variables it sets
+fn terminal_supports_links() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    matches!(
+        var("TERM_PROGRAM").as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper"
+    ) || std::env::var_os("WT_SESSION").is_some()
+        || std::env::var_os("KONSOLE_VERSION").is_some()
+        || var("TERM") == "xterm-kitty"
+        // GNOME Terminal and other VTE terminals since 0.50
+        || var("VTE_VERSION").parse().is_ok_and(|version: u32| version >= 5000)
+}
+
+/// Where a hyperlinked path points, as a URI template with `{path}`,
+/// `{line}` and `{column}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperlinkFormat {
+    template: String,
+}
+
+impl Default for HyperlinkFormat {
+    fn default() -> Self {
+        HyperlinkFormat {
+            template: PRESETS[0].1.to_string(),
+        }
+    }
+}
+
+impl HyperlinkFormat {
+    /// Parse a preset such as `file` or `vscode`, or a template of our own
+    /// like `myeditor://open?path={path}&line={line}`
+    pub fn parse(text: &str) -> Result<Self> {
+        if let Some((_, template)) = PRESETS.iter().find(|(name, _)| *name == text) {
+            return Ok(HyperlinkFormat {
+                template: template.to_string(),
+            });
+        }
+        if !text.contains("{path}") {
+            let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            anyhow::bail!(
+                "Invalid hyperlink format '{}': expected one of {} or a template containing {{path}}",
+                text,
+                names.join(", ")
+            );
+        }
+        Ok(HyperlinkFormat {
+            template: text.to_string(),
+        })
+    }
+
+    /// The URI of `path`, at `line` when known and the first line otherwise
+    pub fn uri(&self, path: &Path, line: Option<usize>) -> String {
+        self.template
+            .replace("{path}", &encode_path(path))
+            .replace("{line}", &line.unwrap_or(1).to_string())
+            .replace("{column}", "1")
+    }
+
+    /// `label` wrapped in an OSC 8 escape that links it to `path`
+    pub fn link(&self, label: &str, path: &Path, line: Option<usize>) -> String {
+        format!(
+            "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
+            self.uri(path, line),
+            label
+        )
+    }
+}
+
+/// `path` as it appears in a URI: percent-encoded, with `/` separators and
+/// a leading `/` before Windows drive letters
+fn encode_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let path = path.replace(std::path::MAIN_SEPARATOR, "/");
+    let mut encoded = String::with_capacity(path.len());
+    if !path.starts_with('/') {
+        encoded.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyperlink_formats() {
+        let path = Path::new("/home/me/my notes/ünï.md");
+        let file = HyperlinkFormat::default();
+        assert_eq!(
+            file.uri(path, Some(3)),
+            "file:///home/me/my%20notes/%C3%BCn%C3%AF.md"
+        );
+        let vscode = HyperlinkFormat::parse("vscode").unwrap();
+        assert_eq!(
+            vscode.uri(Path::new("/src/main.rs"), Some(42)),
+            "vscode://file/src/main.rs:42:1"
+        );
+        let custom = HyperlinkFormat::parse("edit://{path}?l={line}").unwrap();
+        assert_eq!(custom.uri(Path::new("/a.rs"), None), "edit:///a.rs?l=1");
+        assert_eq!(
+            file.link("a.rs", Path::new("/a.rs"), None),
+            "\x1b]8;;file:///a.rs\x1b\\a.rs\x1b]8;;\x1b\\"
+        );
+        assert!(HyperlinkFormat::parse("emacs").is_err());
+    }
+}
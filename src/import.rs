@@ -0,0 +1,410 @@
+//! Turn the databases of other file search tools into index entries, so
+//! an index can start out from what `updatedb` or Everything already found.
+
+use crate::mapped;
+use crate::scanner::{self, FileEntry, ScanOptions, ScanResult};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Databases that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `plocate.db`, written by plocate's `updatedb`
+    Plocate,
+    /// `mlocate.db`, written by mlocate's `updatedb`
+    Mlocate,
+    /// A file list exported from Everything (`.efu`); its own `Everything.db`
+    /// is undocumented, but File > Export and `es -export-efu` write these
+    Everything,
+}
+
+/// Where `format` keeps its database by default
+pub fn default_path(format: Format) -> Option<PathBuf> {
+    match format {
+        Format::Plocate => Some(PathBuf::from("/var/lib/plocate/plocate.db")),
+        Format::Mlocate => Some(PathBuf::from("/var/lib/mlocate/mlocate.db")),
+        Format::Everything => None,
+    }
+}
+
+/// Read the entries listed in a database of `format`, as if a scan of
+/// `root` had found them; without `root`, the database's own root.
+///
+/// locate databases hold no sizes or modification times, so their files
+/// count as empty and undated until [`refresh`]ed or updated. The index
+/// they make includes hidden and ignored files, as `updatedb` does.
+pub fn import(format: Format, path: &Path, root: Option<&Path>) -> Result<ScanResult> {
+    let start = Instant::now();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let listed = match format {
+        Format::Plocate => parse_plocate(&bytes),
+        Format::Mlocate => parse_mlocate(&bytes),
+        Format::Everything => parse_efu(&String::from_utf8_lossy(&bytes)),
+    };
+    let listed =
+        listed.with_context(|| format!("Invalid {:?} database: {}", format, path.display()))?;
+    let root = match root {
+        Some(root) => root.to_path_buf(),
+        None => listed.root.unwrap_or_else(|| common_root(&listed.entries)),
+    };
+    let mut files: Vec<FileEntry> = listed
+        .entries
+        .into_iter()
+        .filter(|entry| entry.path != root && entry.path.starts_with(&root))
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files.dedup_by(|a, b| a.path == b.path);
+    let dir_count = files.iter().filter(|entry| entry.is_dir).count() + 1;
+    Ok(ScanResult {
+        root,
+        file_count: files.len() + 1 - dir_count,
+        dir_count,
+        total_size: files.iter().map(|entry| entry.size).sum(),
+        elapsed_ms: start.elapsed().as_millis(),
+        files,
+        options: ScanOptions {
+            hidden: true,
+            no_ignore: true,
+            ..Default::default()
+        },
+        partial: false,
+    })
+}
+
+/// Look up the size and modification time of every entry, dropping those
+/// that no longer exist
+pub fn refresh(scan: &mut ScanResult) {
+    scan.files = scan
+        .files
+        .par_iter()
+        .filter_map(|entry| scanner::entry_for_path(&entry.path))
+        .collect();
+    scan.dir_count = scan.files.iter().filter(|entry| entry.is_dir).count() + 1;
+    scan.file_count = scan.files.len() + 1 - scan.dir_count;
+    scan.total_size = scan.files.iter().map(|entry| entry.size).sum();
+}
+
+/// What a database lists, and the root it was built from if it records one
+struct Listed {
+    root: Option<PathBuf>,
+    entries: Vec<FileEntry>,
+}
+
+/// The deepest directory holding every entry
+fn common_root(entries: &[FileEntry]) -> PathBuf {
+    let mut paths = entries.iter().filter_map(|entry| entry.path.parent());
+    let Some(first) = paths.next() else {
+        return PathBuf::from("/");
+    };
+    let mut root = first.to_path_buf();
+    for path in paths {
+        while !path.starts_with(&root) && root.pop() {}
+    }
+    root
+}
+
+/// Entries for bare paths, telling directories by whether anything is
+/// listed inside them
+fn from_paths(paths: Vec<PathBuf>) -> Vec<FileEntry> {
+    let parents: HashSet<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    let dirs: Vec<bool> = paths
+        .iter()
+        .map(|path| parents.contains(path.as_path()))
+        .collect();
+    paths
+        .into_iter()
+        .zip(dirs)
+        .map(|(path, is_dir)| FileEntry::new(path, is_dir, 0, None))
+        .collect()
+}
+
+/// Split `bytes` at its first NUL, returning what comes before and after
+fn take_cstr(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let end = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .context("unterminated path")?;
+    Ok((&bytes[..end], &bytes[end + 1..]))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32> {
+    let field = bytes.get(at..at + 4).context("truncated header")?;
+    Ok(u32::from_le_bytes(field.try_into()?))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Result<u64> {
+    let field = bytes.get(at..at + 8).context("truncated header")?;
+    Ok(u64::from_le_bytes(field.try_into()?))
+}
+
+/// plocate's database: a header, a posting-list hash table this ignores,
+/// and the paths themselves, NUL-terminated in zstd-compressed blocks
+/// located by a table of offsets
+fn parse_plocate(bytes: &[u8]) -> Result<Listed> {
+    if !bytes.starts_with(b"\0plocate") {
+        anyhow::bail!("not a plocate database");
+    }
+    let version = u32_at(bytes, 8)?;
+    if version > 2 {
+        anyhow::bail!("unsupported version {}", version);
+    }
+    let blocks = u32_at(bytes, 20)? as usize;
+    let offsets_at = u64_at(bytes, 32)? as usize;
+    let dictionary = if version >= 1 {
+        let len = u32_at(bytes, 44)? as usize;
+        let at = u64_at(bytes, 48)? as usize;
+        bytes.get(at..at + len).context("truncated dictionary")?
+    } else {
+        &[]
+    };
+    let offsets = (0..=blocks)
+        .map(|block| u64_at(bytes, offsets_at + 8 * block).map(|offset| offset as usize))
+        .collect::<Result<Vec<usize>>>()?;
+    let mut paths = Vec::new();
+    let mut block = Vec::new();
+    for range in offsets.windows(2) {
+        let compressed = bytes.get(range[0]..range[1]).context("truncated block")?;
+        block.clear();
+        zstd::stream::read::Decoder::with_dictionary(compressed, dictionary)?
+            .read_to_end(&mut block)?;
+        paths.extend(
+            block
+                .split(|&b| b == 0)
+                .filter(|path| !path.is_empty())
+                .map(|path| mapped::path_from_bytes(path).to_path_buf()),
+        );
+    }
+    Ok(Listed {
+        root: None,
+        entries: from_paths(paths),
+    })
+}
+
+/// mlocate's database: a header naming the root, then every directory's
+/// time and path followed by the names inside it
+fn parse_mlocate(bytes: &[u8]) -> Result<Listed> {
+    let rest = bytes
+        .strip_prefix(b"\0mlocate")
+        .context("not an mlocate database")?;
+    let header = rest.get(..8).context("truncated header")?;
+    let conf_size = u32::from_be_bytes(header[..4].try_into()?) as usize;
+    if header[4] != 0 {
+        anyhow::bail!("unsupported version {}", header[4]);
+    }
+    let (root, rest) = take_cstr(&rest[8..])?;
+    let mut rest = rest.get(conf_size..).context("truncated configuration")?;
+
+    let mut dirs: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+    let mut files = Vec::new();
+    while !rest.is_empty() {
+        let header = rest.get(..16).context("truncated directory")?;
+        let secs = u64::from_be_bytes(header[..8].try_into()?);
+        let nanos = u32::from_be_bytes(header[8..12].try_into()?);
+        let (dir, mut names) = take_cstr(&rest[16..])?;
+        let dir = mapped::path_from_bytes(dir).to_path_buf();
+        let time = UNIX_EPOCH.checked_add(Duration::new(secs, nanos.min(999_999_999)));
+        dirs.insert(dir.clone(), time);
+        loop {
+            let (&kind, after) = names.split_first().context("truncated directory")?;
+            if kind == 2 {
+                rest = after;
+                break;
+            }
+            let (name, after) = take_cstr(after)?;
+            let path = dir.join(mapped::path_from_bytes(name));
+            match kind {
+                0 => files.push(path),
+                _ => {
+                    dirs.entry(path).or_insert(None);
+                }
+            }
+            names = after;
+        }
+    }
+    let mut entries: Vec<FileEntry> = files
+        .into_iter()
+        .map(|path| FileEntry::new(path, false, 0, None))
+        .collect();
+    entries.extend(
+        dirs.into_iter()
+            .map(|(path, time)| FileEntry::new(path, true, 0, time)),
+    );
+    Ok(Listed {
+        root: Some(mapped::path_from_bytes(root).to_path_buf()),
+        entries,
+    })
+}
+
+/// An Everything file list: CSV with a header naming the columns, of which
+/// `Filename` is the full path, `Size` is in bytes, times are FILETIMEs and
+/// `Attributes` are Windows file attributes
+fn parse_efu(text: &str) -> Result<Listed> {
+    const FILE_ATTRIBUTE_DIRECTORY: u64 = 0x10;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let header = split_csv(lines.next().context("empty file list")?)?;
+    let column = |name: &str| header.iter().position(|field| field == name);
+    let filename = column("Filename").context("no Filename column")?;
+    let (size, modified, attributes) = (
+        column("Size"),
+        column("Date Modified"),
+        column("Attributes"),
+    );
+
+    let mut rows = Vec::new();
+    for line in lines {
+        let fields = split_csv(line)?;
+        let field = |at: Option<usize>| {
+            at.and_then(|at| fields.get(at))
+                .and_then(|field| field.parse::<u64>().ok())
+        };
+        let path = PathBuf::from(fields.get(filename).context("row without a Filename")?);
+        let is_dir = field(attributes).map(|attributes| attributes & FILE_ATTRIBUTE_DIRECTORY != 0);
+        rows.push((
+            path,
+            is_dir,
+            field(size),
+            field(modified).and_then(from_filetime),
+        ));
+    }
+    let parents: HashSet<PathBuf> = rows
+        .iter()
+        .filter_map(|(path, ..)| path.parent().map(Path::to_path_buf))
+        .collect();
+    let entries = rows
+        .into_iter()
+        .map(|(path, is_dir, size, modified)| {
+            let is_dir = is_dir.unwrap_or_else(|| parents.contains(&path));
+            let size = if is_dir { 0 } else { size.unwrap_or(0) };
+            FileEntry::new(path, is_dir, size, modified)
+        })
+        .collect();
+    Ok(Listed {
+        root: None,
+        entries,
+    })
+}
+
+/// The fields of one CSV line, with quotes and doubled quotes undone
+fn split_csv(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        anyhow::bail!("unterminated quote in {:?}", line);
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// A Windows FILETIME, in 100 ns ticks since 1601
+fn from_filetime(ticks: u64) -> Option<SystemTime> {
+    const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+    let since_epoch = ticks.checked_sub(UNIX_EPOCH_TICKS)?;
+    UNIX_EPOCH.checked_add(Duration::from_nanos(since_epoch.saturating_mul(100)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(listed: &Listed) -> Vec<(String, bool)> {
+        let mut paths: Vec<(String, bool)> = listed
+            .entries
+            .iter()
+            .map(|entry| (entry.path.display().to_string(), entry.is_dir))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_parse_locate_databases() {
+        // The plocate layout, with a single block and no dictionary
+        let block =
+            zstd::bulk::compress(b"/srv\0/srv/app\0/srv/app/main.rs\0/srv/notes.txt\0", 3).unwrap();
+        let mut plocate = b"\0plocate".to_vec();
+        plocate.extend_from_slice(&1u32.to_le_bytes()); // version
+        plocate.extend_from_slice(&[0; 8]); // hash table size and extra slots
+        plocate.extend_from_slice(&1u32.to_le_bytes()); // blocks
+        plocate.extend_from_slice(&0u64.to_le_bytes()); // hash table offset
+        plocate.extend_from_slice(&64u64.to_le_bytes()); // offsets of the blocks
+        plocate.resize(64, 0);
+        let first = (64 + 16) as u64;
+        plocate.extend_from_slice(&first.to_le_bytes());
+        plocate.extend_from_slice(&(first + block.len() as u64).to_le_bytes());
+        plocate.extend_from_slice(&block);
+        let expected = [
+            ("/srv".to_string(), true),
+            ("/srv/app".to_string(), true),
+            ("/srv/app/main.rs".to_string(), false),
+            ("/srv/notes.txt".to_string(), false),
+        ];
+        assert_eq!(paths(&parse_plocate(&plocate).unwrap()), expected);
+        assert!(parse_plocate(b"\0mlocate").is_err());
+
+        let mut mlocate = b"\0mlocate".to_vec();
+        mlocate.extend_from_slice(&4u32.to_be_bytes());
+        mlocate.extend_from_slice(&[0, 0, 0, 0]);
+        mlocate.extend_from_slice(b"/srv\0conf");
+        for (dir, names) in [
+            ("/srv", &b"\x01app\0\x00notes.txt\0\x02"[..]),
+            ("/srv/app", &b"\x00main.rs\0\x02"[..]),
+        ] {
+            mlocate.extend_from_slice(&1_700_000_000u64.to_be_bytes());
+            mlocate.extend_from_slice(&[0; 8]);
+            mlocate.extend_from_slice(dir.as_bytes());
+            mlocate.push(0);
+            mlocate.extend_from_slice(names);
+        }
+        let listed = parse_mlocate(&mlocate).unwrap();
+        assert_eq!(listed.root, Some(PathBuf::from("/srv")));
+        assert_eq!(paths(&listed), expected);
+        assert!(mlocate.pop().is_some());
+        assert!(
+            parse_mlocate(&mlocate).is_err(),
+            "the last directory is cut short"
+        );
+    }
+
+    #[test]
+    fn test_parse_everything_list() {
+        let listed = parse_efu(
+            "Filename,Size,Date Modified,Date Created,Attributes
+             \"/d/Photos\",,133500000000000000,,16
+             \"/d/Photos/beach, \"\"summer\"\".jpg\",2048,133500000000000000,,32
+             /d/Photos/Trips/rome.jpg,10,,,",
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&listed),
+            [
+                ("/d/Photos".to_string(), true),
+                ("/d/Photos/Trips/rome.jpg".to_string(), false),
+                ("/d/Photos/beach, \"summer\".jpg".to_string(), false),
+            ]
+        );
+        let beach = &listed.entries[1];
+        assert_eq!(beach.size, 2048);
+        let modified = beach.modified.unwrap().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(modified.as_secs(), 1_705_526_400);
+        assert_eq!(common_root(&listed.entries), PathBuf::from("/d"));
+        assert!(parse_efu("Name,Size\nx,1").is_err());
+    }
+}
@@ -0,0 +1,2047 @@
+use crate::archive;
+use crate::bloom::NameFilter;
+use crate::codeowners;
+use crate::error::{Error, Result, bail};
+use crate::filetype;
+use crate::git;
+use crate::journal::{self, JournalCursor, JournalKind};
+use crate::mapped::MappedEntries;
+use crate::memory;
+use crate::normalize;
+use crate::profile;
+use crate::projects::{self, Project};
+use crate::quarantine;
+use crate::query::Query;
+use crate::quota;
+use crate::scanner::{self, FileEntry, ScanOptions, ScanResult};
+use crate::search::{self, SearchOptions};
+use crate::selfcheck::{self, Finding};
+use crate::storage;
+use crate::trigram::TrigramIndex;
+use crate::units;
+use crate::vendor;
+use crate::watch;
+use anyhow::Context;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Magic bytes at the start of every index file
+pub(crate) const INDEX_MAGIC: &[u8; 4] = b"SSIX";
+
+/// Magic bytes at the start of every content (trigram) index file
+const CONTENT_MAGIC: &[u8; 4] = b"SSTG";
+
+/// Magic bytes at the start of every name filter file
+const FILTER_MAGIC: &[u8; 4] = b"SSBF";
+
+/// Bumped whenever the on-disk layout of the index changes
+pub const INDEX_FORMAT_VERSION: u32 = 31;
+
+/// Flag in the header of an index file whose data is zstd-compressed
+pub(crate) const COMPRESSED: u32 = 1;
+
+/// File holding the number of the generation readers should open
+const CURRENT_FILE: &str = "CURRENT";
+
+/// File recording how the current generation was scanned as JSON, which
+/// other format versions can still read, for [`selfcheck::migrate`]
+const OPTIONS_FILE: &str = "scan-options.json";
+
+/// Directory holding pin files of generations that are being read
+const PIN_DIR: &str = "pins";
+
+/// Number of most recent generations kept on disk after a save, unless
+/// [`ScanOptions::keep_generations`] says otherwise
+const RETAINED_GENERATIONS: usize = 3;
+
+/// Pins older than this are assumed to belong to a crashed reader
+const PIN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// File inside the index directory that a process saving a generation
+/// keeps locked, so concurrent scans and updates take turns
+pub const WRITE_LOCK_FILE: &str = "write.lock";
+
+/// How long a save waits for another process's to finish before failing
+const WRITE_LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How often a waiting save checks whether the lock was released
+const WRITE_LOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Metadata describing a stored index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexMeta {
+    pub root: PathBuf,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub dir_count: usize,
+    /// Generation number assigned when the index was saved
+    pub generation: u64,
+    /// How the root was walked, reused by every update
+    pub scan_options: ScanOptions,
+    /// The scan that built the index was interrupted, so files may be
+    /// missing until the next update
+    pub partial: bool,
+    /// How far the OS change journal had got when the index was last
+    /// brought up to date, if the root is covered by one
+    pub journal: Option<JournalCursor>,
+}
+
+/// Number and total size of the indexed files sharing an extension
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ExtensionStats {
+    pub extension: String,
+    pub files: usize,
+    pub size: u64,
+}
+
+/// A persisted filename index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    pub meta: IndexMeta,
+    pub entries: Vec<FileEntry>,
+    /// Sub-projects detected from manifests in the tree, sorted by root
+    pub projects: Vec<Project>,
+    /// Distinct owner lists from CODEOWNERS, referenced by [`FileEntry::owners`]
+    pub owner_sets: Vec<Vec<String>>,
+    /// Work trees of the git repositories in the tree, referenced by
+    /// [`FileEntry::repo`]
+    pub repos: Vec<PathBuf>,
+    /// Trigram index over file contents, stored in its own file next to the
+    /// generation and only loaded on demand
+    #[serde(skip)]
+    pub content: Option<TrigramIndex>,
+}
+
+/// What a generation's `.bin` file holds; the entries live in a table of
+/// their own so they can be searched without decoding
+#[derive(Serialize)]
+struct Header<'a> {
+    meta: &'a IndexMeta,
+    projects: &'a [Project],
+    owner_sets: &'a [Vec<String>],
+    repos: &'a [PathBuf],
+    checksums: &'a Checksums,
+}
+
+#[derive(Deserialize)]
+struct StoredHeader {
+    meta: IndexMeta,
+    projects: Vec<Project>,
+    owner_sets: Vec<Vec<String>>,
+    repos: Vec<PathBuf>,
+    checksums: Checksums,
+}
+
+/// What a generation's header records about the files saved before it, so
+/// damage to them can be told apart from an intact generation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksums {
+    /// Number of records in the entry table
+    pub entry_count: u64,
+    /// BLAKE3 hashes of the entry table, unless its backend lets other
+    /// programs change it, the name filter and the content index, if there
+    /// is one
+    pub entries: Option<[u8; 32]>,
+    pub filter: [u8; 32],
+    pub content: Option<[u8; 32]>,
+}
+
+impl StoredHeader {
+    fn into_index(self, entries: Vec<FileEntry>) -> Index {
+        Index {
+            meta: self.meta,
+            entries,
+            projects: self.projects,
+            owner_sets: self.owner_sets,
+            repos: self.repos,
+            content: None,
+        }
+    }
+}
+
+/// Summary of what changed during an incremental update
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Unchanged entries whose owner, group or permissions changed
+    pub permissions_changed: usize,
+    pub elapsed_ms: u128,
+    /// The change journal the changes were read from, instead of walking
+    /// the root
+    pub journal: Option<JournalKind>,
+}
+
+impl UpdateReport {
+    /// Whether the update found any differences
+    pub fn has_changes(&self) -> bool {
+        self.added + self.modified + self.removed + self.permissions_changed > 0
+    }
+}
+
+impl Index {
+    /// Build a fresh index from a completed scan
+    pub fn from_scan(scan: ScanResult) -> Self {
+        let now = SystemTime::now();
+        let mut index = Index {
+            meta: IndexMeta {
+                root: scan.root,
+                created_at: now,
+                updated_at: now,
+                dir_count: scan.dir_count,
+                generation: 0,
+                scan_options: scan.options,
+                partial: scan.partial,
+                journal: None,
+            },
+            entries: scan.files,
+            projects: Vec::new(),
+            owner_sets: Vec::new(),
+            repos: Vec::new(),
+            content: None,
+        };
+        index.tag_entries();
+        index
+    }
+
+    /// Re-detect sub-projects, code owners, vendored code and git
+    /// repositories and tag every entry accordingly, transliterating names
+    /// and hashing files that have no content hash yet when the index does
+    /// so, and telling the MIME type of those that have none yet
+    fn tag_entries(&mut self) {
+        self.projects = projects::detect(&self.entries);
+        projects::assign(&mut self.entries, &self.projects);
+        self.owner_sets = codeowners::assign(&self.meta.root, &mut self.entries);
+        vendor::assign(&self.meta.root, &mut self.entries);
+        self.repos = git::assign(&self.meta.root, &mut self.entries);
+        filetype::assign(&mut self.entries, self.meta.scan_options.sniff_mime);
+        if self.meta.scan_options.transliterate {
+            self.entries
+                .par_iter_mut()
+                .filter(|entry| !entry.name.is_ascii())
+                .for_each(|entry| entry.folded_name = normalize::latin_name(&entry.name));
+        }
+        if self.meta.scan_options.hash {
+            self.entries
+                .par_iter_mut()
+                .filter(|entry| !entry.is_dir && entry.hash.is_none())
+                .for_each(|entry| entry.hash = scanner::content_hash(&entry.path).ok());
+        }
+    }
+
+    /// Owners of `entry` according to CODEOWNERS
+    pub fn owners_of(&self, entry: &FileEntry) -> &[String] {
+        entry
+            .owners
+            .and_then(|id| self.owner_sets.get(id as usize))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The indexed files, leaving out directories
+    pub fn files(&self) -> impl Iterator<Item = &FileEntry> {
+        self.entries.iter().filter(|entry| !entry.is_dir)
+    }
+
+    /// Number of indexed files, leaving out directories
+    pub fn file_count(&self) -> usize {
+        self.files().count()
+    }
+
+    /// Approximate bytes of memory the index takes up once loaded, including
+    /// its content index when that's loaded too
+    pub fn heap_size(&self) -> usize {
+        let entries: usize = self
+            .entries
+            .iter()
+            .map(|entry| {
+                entry.path.capacity()
+                    + entry.name.capacity()
+                    + entry.folded_name.as_ref().map_or(0, String::capacity)
+            })
+            .sum();
+        let projects: usize = self
+            .projects
+            .iter()
+            .map(|project| project.name.capacity() + project.root.capacity())
+            .sum();
+        let owners: usize = self
+            .owner_sets
+            .iter()
+            .map(|set| memory::vec_bytes(set) + set.iter().map(String::capacity).sum::<usize>())
+            .sum();
+        memory::vec_bytes(&self.entries)
+            + entries
+            + memory::vec_bytes(&self.projects)
+            + projects
+            + memory::vec_bytes(&self.owner_sets)
+            + owners
+            + memory::vec_bytes(&self.repos)
+            + self.repos.iter().map(PathBuf::capacity).sum::<usize>()
+            + self.content.as_ref().map_or(0, TrigramIndex::heap_size)
+    }
+
+    /// Build the trigram content index over all entries, within the memory
+    /// budget of the scan options
+    pub fn build_content(&mut self, max_content_size: u64) -> Result<()> {
+        let budget = self.meta.scan_options.content_memory;
+        let content = TrigramIndex::build(&self.entries, max_content_size, budget)
+            .context("Failed to build the content index")?;
+        self.content = Some(content);
+        Ok(())
+    }
+
+    /// Total size of all indexed files in bytes
+    pub fn total_size(&self) -> u64 {
+        self.files().map(|e| e.size).sum()
+    }
+
+    /// The `n` largest files, largest first
+    pub fn largest(&self, n: usize) -> Vec<&FileEntry> {
+        let mut files: Vec<&FileEntry> = self.files().collect();
+        files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        files.truncate(n);
+        files
+    }
+
+    /// The `n` most deeply nested files with their depth below the root,
+    /// deepest first
+    pub fn deepest(&self, n: usize) -> Vec<(&FileEntry, usize)> {
+        let mut files: Vec<(&FileEntry, usize)> = self
+            .files()
+            .map(|entry| {
+                let relative = entry
+                    .path
+                    .strip_prefix(&self.meta.root)
+                    .unwrap_or(&entry.path);
+                (entry, relative.components().count())
+            })
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.path.cmp(&b.0.path)));
+        files.truncate(n);
+        files
+    }
+
+    /// File count and total size per lowercased extension, largest total
+    /// first. Files without an extension are grouped under `""`.
+    pub fn extension_stats(&self) -> Vec<ExtensionStats> {
+        let mut by_extension: HashMap<String, ExtensionStats> = HashMap::new();
+        for entry in self.files() {
+            let extension = Path::new(&entry.name)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let stats = by_extension
+                .entry(extension.clone())
+                .or_insert_with(|| ExtensionStats {
+                    extension,
+                    ..Default::default()
+                });
+            stats.files += 1;
+            stats.size += entry.size;
+        }
+        let mut stats: Vec<ExtensionStats> = by_extension.into_values().collect();
+        stats.sort_by(|a, b| {
+            (b.size, b.files)
+                .cmp(&(a.size, a.files))
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+        stats
+    }
+
+    /// Path of the file storing `generation` inside `index_dir`
+    pub fn generation_path(index_dir: &Path, generation: u64) -> PathBuf {
+        index_dir.join(format!("gen-{:010}.bin", generation))
+    }
+
+    /// Path of the memory-mappable entry table of `generation` inside `index_dir`
+    pub fn entries_path(index_dir: &Path, generation: u64) -> PathBuf {
+        index_dir.join(format!("gen-{:010}.ent", generation))
+    }
+
+    /// Path of the content index belonging to `generation` inside `index_dir`
+    pub fn content_path(index_dir: &Path, generation: u64) -> PathBuf {
+        index_dir.join(format!("gen-{:010}.tri", generation))
+    }
+
+    /// Path of the directory holding the full-text index of `generation`
+    /// inside `index_dir`, for indexes scanned with `--fulltext`
+    pub fn fulltext_path(index_dir: &Path, generation: u64) -> PathBuf {
+        index_dir.join(format!("gen-{:010}.ftx", generation))
+    }
+
+    /// Path of the Bloom filter over the names of `generation` inside `index_dir`
+    pub fn filter_path(index_dir: &Path, generation: u64) -> PathBuf {
+        index_dir.join(format!("gen-{:010}.blm", generation))
+    }
+
+    /// Load the content index stored next to this generation, if one exists.
+    ///
+    /// Returns whether a content index is now available.
+    pub fn load_content(&mut self, index_dir: &Path) -> Result<bool> {
+        let _span = profile::span("load-content");
+        if self.content.is_none() {
+            let path = Self::content_path(index_dir, self.meta.generation);
+            if !path.is_file() {
+                return Ok(false);
+            }
+            match read_versioned(&path, CONTENT_MAGIC) {
+                Ok(content) => self.content = Some(content),
+                Err(e) if quarantine::is_damage(&e) => {
+                    let what = "content index (run `ss scan --content` to rebuild it)";
+                    quarantine::quarantine_part(index_dir, &path, what, &e);
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Generation that readers of `index_dir` currently see, if any
+    pub fn current_generation(index_dir: &Path) -> Result<Option<u64>> {
+        let path = index_dir.join(CURRENT_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let generation = contents
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::Error::new(Finding::pointer(index_dir)))?;
+                Ok(Some(generation))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                let error =
+                    anyhow::Error::new(e).context(format!("Failed to read {}", path.display()));
+                Err(error.into())
+            }
+        }
+    }
+
+    /// All generations still stored in `index_dir`, oldest first
+    pub fn generations(index_dir: &Path) -> Result<Vec<u64>> {
+        let mut generations = Vec::new();
+        let entries = match fs::read_dir(index_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(generations),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if let Some(number) = name
+                .strip_prefix("gen-")
+                .and_then(|rest| rest.strip_suffix(".bin"))
+                .and_then(|number| number.parse().ok())
+            {
+                generations.push(number);
+            }
+        }
+        generations.sort_unstable();
+        Ok(generations)
+    }
+
+    /// When each generation still stored in `index_dir` was last brought up
+    /// to date with the filesystem, oldest first
+    pub fn generation_times(index_dir: &Path) -> Result<Vec<(u64, SystemTime)>> {
+        Self::generations(index_dir)?
+            .into_iter()
+            .map(|generation| {
+                let path = Self::generation_path(index_dir, generation);
+                let header: StoredHeader = read_versioned(&path, INDEX_MAGIC)?;
+                Ok((generation, header.meta.updated_at))
+            })
+            .collect()
+    }
+
+    /// The newest generation in `index_dir` that was up to date as of
+    /// `time`, if one is still stored
+    pub fn generation_at(index_dir: &Path, time: SystemTime) -> Result<Option<u64>> {
+        Ok(Self::generation_times(index_dir)?
+            .into_iter()
+            .filter(|&(_, updated_at)| updated_at <= time)
+            .map(|(generation, _)| generation)
+            .next_back())
+    }
+
+    /// Bytes taken by generations older than the current one, which
+    /// [`Index::compact`] would reclaim unless a reader still has them pinned
+    pub fn superseded_size(index_dir: &Path) -> Result<u64> {
+        let current = Self::current_generation(index_dir)?;
+        Ok(Self::generations(index_dir)?
+            .into_iter()
+            .filter(|&generation| Some(generation) != current)
+            .map(|generation| generation_size(index_dir, generation))
+            .sum())
+    }
+
+    /// Remove every generation but the current one, keeping those readers
+    /// have pinned, and return the number of bytes freed
+    pub fn compact(index_dir: &Path) -> Result<u64> {
+        let current = Self::current_generation(index_dir)?;
+        let pinned = pinned_generations(index_dir);
+        let mut freed = 0;
+        for generation in Self::generations(index_dir)? {
+            if Some(generation) != current && !pinned.contains(&generation) {
+                freed += generation_size(index_dir, generation);
+                remove_generation(index_dir, generation);
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Make `generation` the one readers of `index_dir` see
+    pub fn set_current(index_dir: &Path, generation: u64) -> Result<()> {
+        let pointer = index_dir.join(CURRENT_FILE);
+        let tmp_pointer = index_dir.join(format!("{}.{}.tmp", CURRENT_FILE, std::process::id()));
+        fs::write(&tmp_pointer, generation.to_string())?;
+        fs::rename(&tmp_pointer, &pointer)
+            .with_context(|| format!("Failed to update {}", pointer.display()))?;
+        Ok(())
+    }
+
+    /// What the header of `generation` in `index_dir` records about the
+    /// rest of its files
+    pub fn checksums(index_dir: &Path, generation: u64) -> Result<Checksums> {
+        let path = Self::generation_path(index_dir, generation);
+        let header: StoredHeader = read_versioned(&path, INDEX_MAGIC)?;
+        Ok(header.checksums)
+    }
+
+    /// Whether an index has been stored in `index_dir`
+    pub fn exists(index_dir: &Path) -> bool {
+        match Self::current_generation(index_dir) {
+            Ok(Some(_)) => true,
+            Ok(None) => Self::generations(index_dir).is_ok_and(|stored| !stored.is_empty()),
+            // Opening it says what's wrong
+            Err(_) => true,
+        }
+    }
+
+    /// Load the current generation of the index stored in `index_dir`,
+    /// falling back on an older one if it's damaged (see [`quarantine`])
+    pub fn load(index_dir: &Path) -> Result<Self> {
+        loop {
+            let Some(generation) = Self::current_generation(index_dir)? else {
+                return Err(no_index(index_dir).into());
+            };
+            let loaded = load_generation(index_dir, generation);
+            match or_quarantine(index_dir, generation, loaded) {
+                Ok(Some(index)) => return Ok(index),
+                Ok(None) => {}
+                Err(_) if superseded(index_dir, generation) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Load a specific generation of the index stored in `index_dir`
+    pub fn load_generation(index_dir: &Path, generation: u64) -> Result<Self> {
+        Ok(load_generation(index_dir, generation)?)
+    }
+
+    /// Write the index into `index_dir` as a new generation and make it current.
+    ///
+    /// Generation files are never modified after being written, so readers
+    /// holding an older generation keep a consistent view while updates land.
+    /// Saves from several processes take turns, see [`WriteLock`].
+    pub fn save(&mut self, index_dir: &Path) -> Result<u64> {
+        let _lock = Self::lock_for_save(index_dir)?;
+        Ok(self.write_generation(index_dir)?)
+    }
+
+    /// Like [`Index::save`], but only over the generation this index was
+    /// loaded from: `None`, with nothing written, when another process saved
+    /// a newer one meanwhile, whose changes saving this one would undo
+    pub fn save_if_current(&mut self, index_dir: &Path) -> Result<Option<u64>> {
+        let _lock = Self::lock_for_save(index_dir)?;
+        if Self::current_generation(index_dir)? != Some(self.meta.generation) {
+            return Ok(None);
+        }
+        Ok(self.write_generation(index_dir).map(Some)?)
+    }
+
+    /// Bring this index, loaded from `index_dir`, up to date with
+    /// [`Index::update_in`] (or [`Index::update`] when `full`) and save it if
+    /// anything changed or a change journal started covering it. When
+    /// another process saved meanwhile, the update is redone on what it
+    /// saved, keeping this index's scan options, so neither loses changes.
+    pub fn update_and_save(
+        &mut self,
+        index_dir: &Path,
+        full: bool,
+    ) -> Result<(UpdateReport, Option<u64>)> {
+        loop {
+            let journal = self.meta.journal.map(|cursor| cursor.journal_id);
+            let report = match full {
+                true => self.update()?,
+                false => self.update_in(index_dir)?,
+            };
+            // A journal that just started covering the index is worth a
+            // save, so the next update can read from it
+            let tracked = self.meta.journal.map(|cursor| cursor.journal_id);
+            if !report.has_changes() && (tracked.is_none() || tracked == journal) {
+                return Ok((report, None));
+            }
+            if let Some(generation) = self.save_if_current(index_dir)? {
+                return Ok((report, Some(generation)));
+            }
+            let options = self.meta.scan_options.clone();
+            let content = self.content.is_some();
+            *self = Self::load(index_dir)?;
+            if self.meta.scan_options != options {
+                // The journal only knows what changed, not what the new
+                // options take in or leave out
+                self.meta.scan_options = options;
+                self.meta.journal = None;
+            }
+            if content {
+                self.load_content(index_dir)?;
+            }
+        }
+    }
+
+    fn lock_for_save(index_dir: &Path) -> anyhow::Result<WriteLock> {
+        fs::create_dir_all(index_dir).with_context(|| {
+            format!("Failed to create index directory: {}", index_dir.display())
+        })?;
+        Ok(WriteLock::acquire(index_dir)?)
+    }
+
+    /// Write this index as the next generation and make it current, with
+    /// the [`WriteLock`] held
+    fn write_generation(&mut self, index_dir: &Path) -> anyhow::Result<u64> {
+        let latest = Self::generations(index_dir)?.last().copied().unwrap_or(0);
+        let current = Self::current_generation(index_dir)?.unwrap_or(0);
+        let generation = latest.max(current) + 1;
+        self.meta.generation = generation;
+
+        // The content index goes first so the generation is complete once visible
+        let compress = self.meta.scan_options.compress;
+        let mut content_hash = None;
+        if let Some(content) = &self.content {
+            let path = Self::content_path(index_dir, generation);
+            write_versioned(&path, CONTENT_MAGIC, content, compress)?;
+            content_hash = Some(file_hash(&path)?);
+        }
+        #[cfg(feature = "fulltext")]
+        if self.meta.scan_options.fulltext {
+            let previous = Self::fulltext_path(index_dir, current);
+            crate::fulltext::build(
+                &Self::fulltext_path(index_dir, generation),
+                Some(&previous),
+                &self.entries,
+                crate::trigram::DEFAULT_MAX_CONTENT_SIZE,
+                crate::tokenize::TokenOptions {
+                    strip_comments: self.meta.scan_options.strip_comments,
+                },
+            )
+            .context("Failed to build the full-text index")?;
+        }
+        // Filters hash their keys evenly, so compressing them gains nothing
+        let filter_path = Self::filter_path(index_dir, generation);
+        write_versioned(
+            &filter_path,
+            FILTER_MAGIC,
+            &NameFilter::build(&self.entries),
+            false,
+        )?;
+        let storage = storage::storage(self.meta.scan_options.backend);
+        storage.write(index_dir, self)?;
+        let entries_path = storage.entries_path(index_dir, generation);
+        let checksums = Checksums {
+            entry_count: self.entries.len() as u64,
+            entries: match storage.is_editable() {
+                true => None,
+                false => Some(file_hash(&entries_path)?),
+            },
+            filter: file_hash(&filter_path)?,
+            content: content_hash,
+        };
+        let header = Header {
+            meta: &self.meta,
+            projects: &self.projects,
+            owner_sets: &self.owner_sets,
+            repos: &self.repos,
+            checksums: &checksums,
+        };
+        write_versioned(
+            &Self::generation_path(index_dir, generation),
+            INDEX_MAGIC,
+            &header,
+            compress,
+        )?;
+
+        Self::set_current(index_dir, generation)?;
+        let options = StoredOptions {
+            format_version: INDEX_FORMAT_VERSION,
+            scan_options: self.meta.scan_options.clone(),
+            content: self.content.as_ref().map(TrigramIndex::max_content_size),
+        };
+        options.write(index_dir)?;
+        let keep = match storage.keeps_history() {
+            true => self.meta.scan_options.keep_generations,
+            false => Some(1),
+        };
+        collect_garbage(index_dir, keep.unwrap_or(RETAINED_GENERATIONS))?;
+        if let Some(limit) = self.meta.scan_options.max_index_size {
+            let enforced = quota::enforce(index_dir, generation, limit)?;
+            for eviction in &enforced.evicted {
+                eprintln!(
+                    "🧹 Evicted {} ({}) to keep the index under {}",
+                    eviction.describe(),
+                    scanner::format_size(eviction.bytes),
+                    scanner::format_size(limit)
+                );
+            }
+            if enforced.usage > limit {
+                eprintln!(
+                    "⚠️  The index takes {}, over its cap of {}, with nothing left to evict",
+                    scanner::format_size(enforced.usage),
+                    scanner::format_size(limit)
+                );
+            }
+        }
+        Ok(generation)
+    }
+
+    /// Re-scan the indexed root and merge the differences into this index.
+    ///
+    /// Entries whose size and mtime are unchanged are carried over as-is, so
+    /// anything derived from a file's contents only has to be recomputed for
+    /// added or modified files. With content hashes, a file whose mtime
+    /// changed but whose hash didn't (e.g. after `touch` or a checkout) also
+    /// counts as unchanged.
+    pub fn update(&mut self) -> Result<UpdateReport> {
+        let scan =
+            scanner::scan_directory_with(&self.meta.root, &self.meta.scan_options, None, None)?;
+        let mut report = self.reconcile(scan.files)?;
+        report.elapsed_ms = scan.elapsed_ms;
+        self.meta.dir_count = scan.dir_count;
+        self.meta.partial = scan.partial;
+        Ok(report)
+    }
+
+    /// Like [`Index::update`], but ask the OS change journal what changed
+    /// since the last update first and only walk the root when it can't
+    /// tell, recording where the journal got to for next time. The index
+    /// lives in `index_dir`, which is never reported as changed.
+    pub fn update_in(&mut self, index_dir: &Path) -> Result<UpdateReport> {
+        let root = self.meta.root.clone();
+        if let Some(cursor) = self.meta.journal
+            && !self.meta.partial
+            && let Some((paths, next)) = journal::changes_since(&root, index_dir, &cursor)
+        {
+            let mut filter = watch::index_filter(self, index_dir)?;
+            let paths = watch::expand_paths(&mut filter, paths);
+            let mut report = self.apply_changes(&paths)?;
+            report.journal = Some(next.kind);
+            self.meta.journal = Some(next);
+            return Ok(report);
+        }
+        self.meta.journal = journal::current_cursor(&root, index_dir);
+        self.update()
+    }
+
+    /// Apply changes reported for individual paths, e.g. by a file watcher.
+    ///
+    /// Each path is re-examined: existing files and directories are added or
+    /// refreshed, while missing paths remove the entry and, for deleted
+    /// directories, every entry beneath them. Callers expand newly created
+    /// directories into what they contain. The files inside a changed
+    /// archive are listed again, when the index holds them.
+    pub fn apply_changes(&mut self, paths: &[PathBuf]) -> Result<UpdateReport> {
+        let start = Instant::now();
+        let options = &self.meta.scan_options;
+        let mut fresh: HashMap<PathBuf, FileEntry> = HashMap::new();
+        let mut gone: Vec<&Path> = Vec::new();
+        // Archives whose files found before are dropped unless still there
+        let mut relisted: Vec<&Path> = Vec::new();
+        for path in paths {
+            if options.archives && archive::is_archive(path) {
+                relisted.push(path);
+            }
+            match scanner::entry_for_path(path) {
+                // A file that grew past the size limit leaves the index
+                Some(entry) if options.too_large(entry.size) => gone.push(path),
+                Some(entry) => {
+                    if options.archives && archive::is_archive(path) {
+                        let members = archive::members(path, |size| options.too_large(size));
+                        for member in members.unwrap_or_default() {
+                            fresh.insert(member.path.clone(), member);
+                        }
+                    }
+                    fresh.insert(path.clone(), entry);
+                }
+                None if !path.exists() => gone.push(path),
+                None => {}
+            }
+        }
+
+        let mut next = Vec::with_capacity(self.entries.len() + fresh.len());
+        for entry in &self.entries {
+            if let Some(updated) = fresh.remove(&entry.path) {
+                next.push(updated);
+            } else if !gone.iter().any(|g| entry.path.starts_with(g))
+                && !archive::split(&entry.path).is_some_and(|(from, _)| relisted.contains(&from))
+            {
+                next.push(entry.clone());
+            }
+        }
+        let mut added: Vec<FileEntry> = fresh.into_values().collect();
+        added.sort_by(|a, b| a.path.cmp(&b.path));
+        next.extend(added);
+
+        let mut report = self.reconcile(next)?;
+        report.elapsed_ms = start.elapsed().as_millis();
+        Ok(report)
+    }
+
+    /// Drop the entries whose paths no longer exist, or whose archive
+    /// doesn't for files inside one, and return their paths. A broken
+    /// symlink still exists.
+    pub fn prune(&mut self) -> Result<Vec<PathBuf>> {
+        let missing: Vec<bool> = self
+            .entries
+            .par_iter()
+            .map(|entry| {
+                let path = archive::split(&entry.path).map_or(entry.path.as_path(), |(a, _)| a);
+                fs::symlink_metadata(path).is_err()
+            })
+            .collect();
+        let mut gone = Vec::new();
+        let mut kept = Vec::with_capacity(self.entries.len());
+        for (entry, missing) in self.entries.iter().zip(missing) {
+            match missing {
+                true => gone.push(entry.path.clone()),
+                false => kept.push(entry.clone()),
+            }
+        }
+        if !gone.is_empty() {
+            // Nothing was re-scanned, so the index is no more up to date
+            let updated_at = self.meta.updated_at;
+            self.reconcile(kept)?;
+            self.meta.updated_at = updated_at;
+        }
+        Ok(gone)
+    }
+
+    /// Replace the entry list with `next`, reusing unchanged entries and
+    /// keeping the content index in sync.
+    fn reconcile(&mut self, next: Vec<FileEntry>) -> anyhow::Result<UpdateReport> {
+        let mut report = UpdateReport::default();
+        let old_count = self.entries.len();
+        let mut previous: HashMap<PathBuf, (usize, FileEntry)> = self
+            .entries
+            .drain(..)
+            .enumerate()
+            .map(|(id, entry)| (entry.path.clone(), (id, entry)))
+            .collect();
+
+        // Files that were touched without changing size may still have the
+        // same contents; hashing tells for sure
+        let rehashed: HashMap<PathBuf, [u8; 32]> = next
+            .par_iter()
+            .filter(|fresh| {
+                previous.get(&fresh.path).is_some_and(|(_, old)| {
+                    old.hash.is_some() && old.size == fresh.size && old.modified != fresh.modified
+                })
+            })
+            .filter_map(|fresh| {
+                Some((fresh.path.clone(), scanner::content_hash(&fresh.path).ok()?))
+            })
+            .collect();
+
+        // Old id -> new id for entries whose contents can be reused
+        let mut mapping: Vec<Option<u32>> = vec![None; old_count];
+        let mut changed: Vec<u32> = Vec::new();
+        let mut entries = Vec::with_capacity(next.len());
+        for mut fresh in next {
+            let new_id = entries.len() as u32;
+            let hash = rehashed.get(&fresh.path).copied();
+            match previous.remove(&fresh.path) {
+                // A directory's mtime changes with what it holds, which
+                // gets counted on its own
+                Some((old_id, mut old))
+                    if old.is_dir == fresh.is_dir
+                        && (old.is_dir
+                            || old.size == fresh.size
+                                && (old.modified == fresh.modified
+                                    || hash.is_some() && hash == old.hash)) =>
+                {
+                    report.unchanged += 1;
+                    mapping[old_id] = Some(new_id);
+                    old.modified = fresh.modified;
+                    // chmod and chown leave the mtime alone
+                    if old.attributes != fresh.attributes {
+                        report.permissions_changed += 1;
+                        old.attributes = fresh.attributes;
+                    }
+                    old.device = fresh.device;
+                    entries.push(old);
+                }
+                Some(_) => {
+                    report.modified += 1;
+                    changed.push(new_id);
+                    fresh.hash = hash;
+                    entries.push(fresh);
+                }
+                None => {
+                    report.added += 1;
+                    changed.push(new_id);
+                    entries.push(fresh);
+                }
+            }
+        }
+        report.removed = previous.len();
+
+        self.entries = entries;
+        self.tag_entries();
+        if let Some(content) = &mut self.content {
+            content.remap(&mapping);
+            content
+                .add(
+                    &self.entries,
+                    &changed,
+                    self.meta.scan_options.content_memory,
+                )
+                .context("Failed to update the content index")?;
+        }
+        self.meta.updated_at = SystemTime::now();
+        Ok(report)
+    }
+}
+
+/// A read-only view of one index generation.
+///
+/// The generation is pinned for as long as the snapshot is alive, so saves
+/// from other processes (e.g. a watcher) won't garbage-collect it underneath
+/// a long-running query or export.
+#[derive(Debug)]
+pub struct Snapshot {
+    index: Index,
+    _pin: Pin,
+}
+
+impl Snapshot {
+    /// Open the current generation of the index in `index_dir`
+    pub fn open(index_dir: &Path) -> Result<Self> {
+        let (index, pin) = open_current(index_dir, |generation| {
+            load_generation(index_dir, generation)
+        })?;
+        Ok(Snapshot { index, _pin: pin })
+    }
+
+    /// Open a specific, still-retained generation of the index in `index_dir`
+    pub fn open_at(index_dir: &Path, generation: u64) -> Result<Self> {
+        let pin = Pin::acquire(index_dir, generation)?;
+        let index = Index::load_generation(index_dir, generation).with_context(|| {
+            let retained = Index::generations(index_dir).unwrap_or_default();
+            format!("Retained generations: {:?}", retained)
+        })?;
+        Ok(Snapshot { index, _pin: pin })
+    }
+
+    /// Generation this snapshot was read from
+    pub fn generation(&self) -> u64 {
+        self.index.meta.generation
+    }
+
+    /// Load the content index belonging to this snapshot's generation
+    pub fn load_content(&mut self, index_dir: &Path) -> Result<bool> {
+        self.index.load_content(index_dir)
+    }
+}
+
+impl Deref for Snapshot {
+    type Target = Index;
+
+    fn deref(&self) -> &Index {
+        &self.index
+    }
+}
+
+/// A read-only view of one index generation that leaves its entries in the
+/// memory-mapped entry table, so opening it costs about the same however
+/// large the index is.
+///
+/// It only answers name searches, decoding just the matches; use
+/// [`Snapshot`] for anything that needs every entry. A compressed index
+/// (`scan --compress`) has its table decompressed into memory instead.
+#[derive(Debug)]
+pub struct MappedSnapshot {
+    /// Everything but the entries, which stay empty
+    index: Index,
+    entries: MappedEntries,
+    /// What the names hold, absent for generations saved without one
+    filter: Option<NameFilter>,
+    _pin: Pin,
+}
+
+impl MappedSnapshot {
+    /// Open the current generation of the index in `index_dir`
+    pub fn open(index_dir: &Path) -> Result<Self> {
+        let ((index, entries), pin) = open_current(index_dir, |generation| {
+            open_generation(index_dir, generation)
+        })?;
+        Ok(MappedSnapshot {
+            filter: load_filter(index_dir, &index.meta)?,
+            index,
+            entries,
+            _pin: pin,
+        })
+    }
+
+    /// Open a specific, still-retained generation of the index in `index_dir`
+    pub fn open_at(index_dir: &Path, generation: u64) -> Result<Self> {
+        let pin = Pin::acquire(index_dir, generation)?;
+        let (index, entries) = open_generation(index_dir, generation).with_context(|| {
+            let retained = Index::generations(index_dir).unwrap_or_default();
+            format!("Retained generations: {:?}", retained)
+        })?;
+        Ok(MappedSnapshot {
+            filter: load_filter(index_dir, &index.meta)?,
+            index,
+            entries,
+            _pin: pin,
+        })
+    }
+
+    /// Generation this snapshot was read from
+    pub fn generation(&self) -> u64 {
+        self.index.meta.generation
+    }
+
+    /// Metadata, projects and code owners of the generation. Its `entries`
+    /// are empty; they're only reachable through [`MappedSnapshot::entries`].
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    pub fn entries(&self) -> &MappedEntries {
+        &self.entries
+    }
+
+    /// Like [`Index::search`], copying out only the entries that match.
+    /// Queries the generation's name filter rules out return at once.
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<(FileEntry, i64)>> {
+        let options = &*options.for_index(&self.index.meta);
+        let query = Query::parse(query)?;
+        let filter = self.index.entry_filter(&options.filters)?;
+        if !self.may_match(&query, options) {
+            return Ok(Vec::new());
+        }
+        let results = search::rank(
+            &self.entries,
+            &self.index.meta.root,
+            &query,
+            options,
+            &filter,
+        )?;
+        Ok(results
+            .into_iter()
+            .map(|(entry, score)| (entry.to_entry(), score))
+            .collect())
+    }
+}
+
+impl MappedSnapshot {
+    /// Whether `query` may match any entry of this generation, as far as
+    /// its name filter can tell
+    pub fn may_match(&self, query: &Query, options: &SearchOptions) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.may_match(query, options))
+    }
+}
+
+/// What [`Index::load_generation`] loads, with the error left for
+/// [`quarantine::is_damage`] to read
+fn load_generation(index_dir: &Path, generation: u64) -> anyhow::Result<Index> {
+    let (mut index, entries) = open_generation(index_dir, generation)?;
+    let _span = profile::span("decode");
+    index.entries = entries.to_vec();
+    Ok(index)
+}
+
+/// The name filter of the generation `meta` describes, if it was saved with
+/// one its entries can't have changed since
+fn load_filter(index_dir: &Path, meta: &IndexMeta) -> anyhow::Result<Option<NameFilter>> {
+    if storage::storage(meta.scan_options.backend).is_editable() {
+        return Ok(None);
+    }
+    let path = Index::filter_path(index_dir, meta.generation);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match read_versioned(&path, FILTER_MAGIC) {
+        Ok(filter) => Ok(Some(filter)),
+        Err(e) if quarantine::is_damage(&e) => {
+            quarantine::quarantine_part(index_dir, &path, "name filter", &e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The error for a directory holding no index
+fn no_index(index_dir: &Path) -> anyhow::Error {
+    if Index::generations(index_dir).is_ok_and(|generations| !generations.is_empty()) {
+        return Finding::pointer(index_dir).into();
+    }
+    anyhow::anyhow!(
+        "No index found at {} (run `ss scan` first)",
+        index_dir.display()
+    )
+}
+
+/// Read the header of `generation` and map its entry table. The entries of
+/// the index returned are left empty.
+fn open_generation(index_dir: &Path, generation: u64) -> anyhow::Result<(Index, MappedEntries)> {
+    let _span = profile::span("load");
+    selfcheck::check_generation(index_dir, generation)?;
+    let path = Index::generation_path(index_dir, generation);
+    let header: StoredHeader = read_versioned(&path, INDEX_MAGIC)?;
+    let mut index = header.into_index(Vec::new());
+    let entries = storage::storage(index.meta.scan_options.backend).read(index_dir, &mut index)?;
+    Ok((index, entries))
+}
+
+/// Open the current generation of the index in `index_dir` with `open`,
+/// pinned so it can't be garbage-collected while in use, and falling back
+/// on an older one if it's damaged
+fn open_current<T>(
+    index_dir: &Path,
+    open: impl Fn(u64) -> anyhow::Result<T>,
+) -> anyhow::Result<(T, Pin)> {
+    loop {
+        let (generation, pin) = pin_current(index_dir)?;
+        match or_quarantine(index_dir, generation, open(generation)) {
+            Ok(Some(value)) => return Ok((value, pin)),
+            Ok(None) => {}
+            Err(_) if superseded(index_dir, generation) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether a save made a newer generation than `generation` current, so
+/// failing to open `generation` (its files overwritten, as the SQLite
+/// backend does, or collected) is worth retrying at the new one
+fn superseded(index_dir: &Path, generation: u64) -> bool {
+    Index::current_generation(index_dir)
+        .ok()
+        .flatten()
+        .is_some_and(|current| current > generation)
+}
+
+/// The current generation of the index in `index_dir`, pinned
+fn pin_current(index_dir: &Path) -> anyhow::Result<(u64, Pin)> {
+    // A save may retire the generation between reading the pointer and
+    // pinning it; retry a few times before giving up.
+    for _ in 0..3 {
+        let Some(generation) = Index::current_generation(index_dir)? else {
+            break;
+        };
+        let pin = Pin::acquire(index_dir, generation)?;
+        if Index::generation_path(index_dir, generation).is_file() {
+            return Ok((generation, pin));
+        }
+    }
+    match Index::current_generation(index_dir)? {
+        Some(generation) => Ok((generation, Pin::none())),
+        None => Err(no_index(index_dir)),
+    }
+}
+
+/// What opening `generation` gave, or `None` if it's damaged and was set
+/// aside for readers to try the generation before it instead
+fn or_quarantine<T>(
+    index_dir: &Path,
+    generation: u64,
+    opened: anyhow::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    match opened {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if quarantine::is_damage(&e) => {
+            quarantine::recover(index_dir, generation, e)?;
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Marker file preventing a generation from being garbage-collected
+#[derive(Debug)]
+struct Pin {
+    path: Option<PathBuf>,
+}
+
+impl Pin {
+    fn acquire(index_dir: &Path, generation: u64) -> anyhow::Result<Self> {
+        let dir = index_dir.join(PIN_DIR);
+        fs::create_dir_all(&dir)?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let path = dir.join(format!(
+            "{:010}-{}-{}",
+            generation,
+            std::process::id(),
+            nanos
+        ));
+        File::create(&path).with_context(|| format!("Failed to pin generation {}", generation))?;
+        Ok(Pin { path: Some(path) })
+    }
+
+    fn none() -> Self {
+        Pin { path: None }
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The right to write generations to an index directory, held by one
+/// process at a time. The advisory lock goes with the file, so it's
+/// released when this is dropped or the process dies.
+#[derive(Debug)]
+pub struct WriteLock {
+    _file: File,
+}
+
+impl WriteLock {
+    /// Lock `index_dir` for writing, waiting for a process already writing
+    /// to it to finish, up to ten minutes
+    pub fn acquire(index_dir: &Path) -> Result<Self> {
+        Ok(Self::acquire_within(index_dir, WRITE_LOCK_TIMEOUT)?)
+    }
+
+    pub(crate) fn acquire_within(index_dir: &Path, timeout: Duration) -> anyhow::Result<Self> {
+        let path = index_dir.join(WRITE_LOCK_FILE);
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(WriteLock { _file: file }),
+                Err(fs::TryLockError::WouldBlock) if started.elapsed() >= timeout => {
+                    bail!(
+                        "Another process has been writing to the index at {} for over {}; try again once it's done",
+                        index_dir.display(),
+                        units::format_age(timeout.as_secs())
+                    )
+                }
+                Err(fs::TryLockError::WouldBlock) => {
+                    if !waiting {
+                        eprintln!(
+                            "⏳ Waiting for another process writing to {}",
+                            index_dir.display()
+                        );
+                        waiting = true;
+                    }
+                    std::thread::sleep(WRITE_LOCK_POLL);
+                }
+                Err(fs::TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+                }
+            }
+        }
+    }
+}
+
+/// How the current generation of an index was scanned, kept in a format
+/// that outlives [`INDEX_FORMAT_VERSION`] changes: fields a version doesn't
+/// know are skipped and those it misses take their defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct StoredOptions {
+    pub format_version: u32,
+    pub scan_options: ScanOptions,
+    /// Largest file the content index covered, if there was one
+    pub content: Option<u64>,
+}
+
+impl StoredOptions {
+    /// The options recorded in `index_dir`, if it has readable ones
+    pub fn read(index_dir: &Path) -> Option<Self> {
+        let bytes = fs::read(index_dir.join(OPTIONS_FILE)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn write(&self, index_dir: &Path) -> anyhow::Result<()> {
+        let path = index_dir.join(OPTIONS_FILE);
+        let tmp = index_dir.join(format!("{}.{}.tmp", OPTIONS_FILE, std::process::id()));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to update {}", path.display()))
+    }
+}
+
+/// Generations currently pinned by live readers; stale pins are removed
+pub(crate) fn pinned_generations(index_dir: &Path) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(index_dir.join(PIN_DIR)) else {
+        return Vec::new();
+    };
+    let mut pinned = Vec::new();
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > PIN_TTL);
+        if stale {
+            let _ = fs::remove_file(entry.path());
+            continue;
+        }
+        let name = entry.file_name();
+        if let Some(generation) = name
+            .to_string_lossy()
+            .split('-')
+            .next()
+            .and_then(|g| g.parse().ok())
+        {
+            pinned.push(generation);
+        }
+    }
+    pinned
+}
+
+/// Delete generations that are neither among the `keep` most recent nor
+/// pinned
+fn collect_garbage(index_dir: &Path, keep: usize) -> anyhow::Result<()> {
+    let generations = Index::generations(index_dir)?;
+    // The one just saved always stays
+    let keep = keep.max(1);
+    if generations.len() <= keep {
+        return Ok(());
+    }
+    let pinned = pinned_generations(index_dir);
+    let retired = &generations[..generations.len() - keep];
+    for generation in retired {
+        if !pinned.contains(generation) {
+            remove_generation(index_dir, *generation);
+        }
+    }
+    Ok(())
+}
+
+/// The BLAKE3 hash of the file at `path`
+pub(crate) fn file_hash(path: &Path) -> anyhow::Result<[u8; 32]> {
+    scanner::content_hash(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Paths of the files making up `generation`
+pub(crate) fn generation_files(index_dir: &Path, generation: u64) -> [PathBuf; 4] {
+    [
+        Index::generation_path(index_dir, generation),
+        Index::entries_path(index_dir, generation),
+        Index::content_path(index_dir, generation),
+        Index::filter_path(index_dir, generation),
+    ]
+}
+
+fn generation_size(index_dir: &Path, generation: u64) -> u64 {
+    generation_files(index_dir, generation)
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Delete every file of `generation`, whether or not a reader has it pinned
+pub(crate) fn remove_generation(index_dir: &Path, generation: u64) {
+    let _ = fs::remove_dir_all(Index::fulltext_path(index_dir, generation));
+    for path in generation_files(index_dir, generation) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Atomically write `value` to `path` behind a magic/version/flags header,
+/// zstd-compressing it if `compress` is set.
+///
+/// Data goes to a temporary file first so a failed write never clobbers a
+/// good file.
+pub(crate) fn write_versioned<T: Serialize>(
+    path: &Path,
+    magic: &[u8; 4],
+    value: &T,
+    compress: bool,
+) -> anyhow::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(magic)?;
+        writer.write_all(&INDEX_FORMAT_VERSION.to_le_bytes())?;
+        let flags = if compress { COMPRESSED } else { 0 };
+        writer.write_all(&flags.to_le_bytes())?;
+        if compress {
+            let mut encoder = zstd::Encoder::new(&mut writer, 0)?;
+            bincode::serialize_into(&mut encoder, value)?;
+            encoder.finish()?;
+        } else {
+            bincode::serialize_into(&mut writer, value)?;
+        }
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write index: {}", path.display()))
+}
+
+/// The error for an index file written with another format version
+pub(crate) fn format_mismatch(path: &Path, version: u32) -> anyhow::Error {
+    let index_dir = path.parent().unwrap_or(Path::new("."));
+    Finding::format_mismatch(index_dir, version).into()
+}
+
+/// Read a file written by [`write_versioned`], checking its header and
+/// decompressing it if needed
+pub(crate) fn read_versioned<T: DeserializeOwned>(
+    path: &Path,
+    magic: &[u8; 4],
+) -> anyhow::Result<T> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+
+    let mut found = [0u8; 4];
+    reader
+        .read_exact(&mut found)
+        .with_context(|| Error::index_format("Index file is truncated", path))?;
+    if &found != magic {
+        return Err(Error::index_format("Not a sonic-search index", path).into());
+    }
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != INDEX_FORMAT_VERSION {
+        return Err(format_mismatch(path, version));
+    }
+
+    let mut flags = [0u8; 4];
+    reader.read_exact(&mut flags)?;
+    let value = if u32::from_le_bytes(flags) & COMPRESSED != 0 {
+        bincode::deserialize_from(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        bincode::deserialize_from(reader)
+    };
+    value.with_context(|| Error::index_format("Failed to read index", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_index(dir: &Path) -> Index {
+        Index::from_scan(scanner::scan_directory(dir).unwrap())
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        fs::write(root.path().join("b.rs"), "bb").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut index = build_index(root.path());
+        assert_eq!(index.save(index_dir.path()).unwrap(), 1);
+        assert!(Index::exists(index_dir.path()));
+
+        let loaded = Index::load(index_dir.path()).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.meta.root, index.meta.root);
+        assert_eq!(loaded.total_size(), 3);
+    }
+
+    #[test]
+    fn test_prune_drops_missing_paths() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("old")).unwrap();
+        fs::write(root.path().join("old/draft.txt"), "d").unwrap();
+        fs::write(root.path().join("gone.txt"), "g").unwrap();
+        fs::write(root.path().join("kept.txt"), "k").unwrap();
+        let mut index = build_index(root.path());
+        let updated_at = index.meta.updated_at;
+
+        fs::remove_dir_all(root.path().join("old")).unwrap();
+        fs::remove_file(root.path().join("gone.txt")).unwrap();
+        // Not picked up, since prune doesn't scan
+        fs::write(root.path().join("new.txt"), "n").unwrap();
+        let mut pruned: Vec<String> = index
+            .prune()
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        pruned.sort();
+        assert_eq!(pruned, ["draft.txt", "gone.txt", "old"]);
+        let names: Vec<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["kept.txt"]);
+        assert_eq!(index.meta.updated_at, updated_at);
+        assert!(index.prune().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_partial_index_is_completed_by_update() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        fs::write(root.path().join("b.txt"), "b").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut scan = scanner::scan_directory(root.path()).unwrap();
+        scan.files.truncate(1);
+        scan.partial = true;
+        Index::from_scan(scan).save(index_dir.path()).unwrap();
+
+        let mut loaded = Index::load(index_dir.path()).unwrap();
+        assert!(loaded.meta.partial);
+        let report = loaded.update().unwrap();
+        assert_eq!(report.added, 1);
+        assert!(!loaded.meta.partial);
+        assert_eq!(loaded.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_compressed_index_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        for i in 0..200 {
+            let path = root.path().join(format!("report-{i:03}.txt"));
+            fs::write(path, "quarterly figures, nothing new\n".repeat(20)).unwrap();
+        }
+        let plain_dir = tempfile::tempdir().unwrap();
+        let compressed_dir = tempfile::tempdir().unwrap();
+        let mut index = build_index(root.path());
+        index
+            .build_content(crate::trigram::DEFAULT_MAX_CONTENT_SIZE)
+            .unwrap();
+        index.save(plain_dir.path()).unwrap();
+        index.meta.scan_options.compress = true;
+        index.save(compressed_dir.path()).unwrap();
+
+        let size = |dir: &Path| -> u64 {
+            [
+                Index::generation_path,
+                Index::entries_path,
+                Index::content_path,
+            ]
+            .iter()
+            .map(|path| fs::metadata(path(dir, 1)).unwrap().len())
+            .sum()
+        };
+        assert!(size(compressed_dir.path()) < size(plain_dir.path()));
+
+        let mut loaded = Index::load(compressed_dir.path()).unwrap();
+        assert!(loaded.meta.scan_options.compress);
+        assert_eq!(loaded.entries, index.entries);
+        assert!(loaded.load_content(compressed_dir.path()).unwrap());
+        let hits = loaded
+            .content
+            .as_ref()
+            .unwrap()
+            .candidates("quarterly", false);
+        assert_eq!(hits.unwrap().len(), 200);
+        let mapped = MappedSnapshot::open(compressed_dir.path()).unwrap();
+        let found = mapped
+            .search("report-042", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(found[0].0.name, "report-042.txt");
+    }
+
+    #[test]
+    fn test_breakdowns() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("a/b")).unwrap();
+        fs::write(root.path().join("a/b/deep.rs"), "12345").unwrap();
+        fs::write(root.path().join("big.RS"), "1234567890").unwrap();
+        fs::write(root.path().join("notes.md"), "1").unwrap();
+        fs::write(root.path().join("Makefile"), "12").unwrap();
+        let index = build_index(root.path());
+
+        let largest: Vec<&str> = index.largest(2).iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(largest, vec!["big.RS", "deep.rs"]);
+        let (deepest, depth) = index.deepest(1)[0];
+        assert_eq!((deepest.name.as_str(), depth), ("deep.rs", 3));
+
+        let stats = index.extension_stats();
+        let extensions: Vec<(&str, usize, u64)> = stats
+            .iter()
+            .map(|s| (s.extension.as_str(), s.files, s.size))
+            .collect();
+        assert_eq!(extensions, vec![("rs", 2, 15), ("", 1, 2), ("md", 1, 1)]);
+    }
+
+    #[test]
+    fn test_heap_size_counts_content_index() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("notes.md"), "budget and forecast").unwrap();
+        let mut index = build_index(root.path());
+        let entries = index.heap_size();
+        assert!(entries >= size_of::<FileEntry>() + "notes.md".len());
+        index.build_content(1024).unwrap();
+        assert!(index.heap_size() > entries);
+    }
+
+    #[test]
+    fn test_hashes_detect_touched_files() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("same.txt"), "abc").unwrap();
+        fs::write(root.path().join("edited.txt"), "abc").unwrap();
+        let options = ScanOptions {
+            hash: true,
+            ..Default::default()
+        };
+        let mut index = Index::from_scan(
+            scanner::scan_directory_with(root.path(), &options, None, None).unwrap(),
+        );
+        assert!(index.entries.iter().all(|e| e.hash.is_some()));
+
+        // Same size and new mtimes, but only one file's contents changed
+        let later = SystemTime::now() + Duration::from_secs(60);
+        for (name, body) in [("same.txt", "abc"), ("edited.txt", "xyz")] {
+            let path = root.path().join(name);
+            fs::write(&path, body).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        }
+        let report = index.update().unwrap();
+        assert_eq!((report.unchanged, report.modified), (1, 1));
+        let edited = index
+            .entries
+            .iter()
+            .find(|e| e.name == "edited.txt")
+            .unwrap();
+        assert_eq!(
+            edited.hash,
+            Some(scanner::content_hash(&edited.path).unwrap())
+        );
+        assert!(index.entries.iter().all(|e| e.modified == Some(later)));
+
+        let digest = scanner::hash_hex(&edited.hash.unwrap());
+        let filters = crate::Filters {
+            hash: Some(digest[..8].to_uppercase()),
+            ..Default::default()
+        };
+        let filter = index.entry_filter(&filters).unwrap();
+        let found: Vec<&str> = index
+            .entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(found, vec!["edited.txt"]);
+        let bad = crate::Filters {
+            hash: Some("xyz".into()),
+            ..Default::default()
+        };
+        assert!(index.entry_filter(&bad).is_err());
+    }
+
+    #[test]
+    fn test_load_missing_index() {
+        let index_dir = tempfile::tempdir().unwrap();
+        assert!(!Index::exists(index_dir.path()));
+        assert!(Index::load(index_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_foreign_file() {
+        let index_dir = tempfile::tempdir().unwrap();
+        fs::write(Index::generation_path(index_dir.path(), 1), b"not an index").unwrap();
+        fs::write(index_dir.path().join(CURRENT_FILE), "1").unwrap();
+        assert!(Index::load(index_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_save_retains_recent_generations() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut index = build_index(root.path());
+        for expected in 1..=5 {
+            assert_eq!(index.save(index_dir.path()).unwrap(), expected);
+        }
+        assert_eq!(
+            Index::current_generation(index_dir.path()).unwrap(),
+            Some(5)
+        );
+        assert_eq!(Index::generations(index_dir.path()).unwrap(), vec![3, 4, 5]);
+
+        let superseded = Index::superseded_size(index_dir.path()).unwrap();
+        assert!(superseded > 0);
+        assert_eq!(Index::compact(index_dir.path()).unwrap(), superseded);
+        assert_eq!(Index::generations(index_dir.path()).unwrap(), vec![5]);
+        assert_eq!(Index::superseded_size(index_dir.path()).unwrap(), 0);
+        assert_eq!(Index::load(index_dir.path()).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_save_if_current_refuses_a_stale_base() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        build_index(root.path()).save(index_dir.path()).unwrap();
+
+        let mut stale = Index::load(index_dir.path()).unwrap();
+        let mut newer = Index::load(index_dir.path()).unwrap();
+        assert_eq!(newer.save_if_current(index_dir.path()).unwrap(), Some(2));
+        assert_eq!(stale.save_if_current(index_dir.path()).unwrap(), None);
+        assert_eq!(
+            Index::current_generation(index_dir.path()).unwrap(),
+            Some(2)
+        );
+
+        fs::write(root.path().join("b.txt"), "b").unwrap();
+        let (report, saved) = stale.update_and_save(index_dir.path(), true).unwrap();
+        assert_eq!((report.added, saved), (1, Some(3)));
+        assert_eq!(Index::load(index_dir.path()).unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn test_keep_generations_and_times() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut index = build_index(root.path());
+        index.meta.scan_options.keep_generations = Some(4);
+        for day in 1..=6 {
+            index.meta.updated_at = UNIX_EPOCH + Duration::from_secs(day * 86_400);
+            index.save(index_dir.path()).unwrap();
+        }
+        let times = Index::generation_times(index_dir.path()).unwrap();
+        let generations: Vec<u64> = times.iter().map(|&(generation, _)| generation).collect();
+        assert_eq!(generations, [3, 4, 5, 6]);
+        let at =
+            |secs| Index::generation_at(index_dir.path(), UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(4 * 86_400 + 10).unwrap(), Some(4));
+        assert_eq!(at(100 * 86_400).unwrap(), Some(6));
+        assert_eq!(at(86_400).unwrap(), None);
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated_from_later_saves() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut index = build_index(root.path());
+        index.save(index_dir.path()).unwrap();
+        let snapshot = Snapshot::open(index_dir.path()).unwrap();
+        assert_eq!(snapshot.generation(), 1);
+
+        // Enough saves to retire generation 1 if it weren't pinned
+        fs::write(root.path().join("b.txt"), "b").unwrap();
+        index.update().unwrap();
+        for _ in 0..4 {
+            index.save(index_dir.path()).unwrap();
+        }
+        assert!(Index::generations(index_dir.path()).unwrap().contains(&1));
+        assert_eq!(snapshot.entries.len(), 1);
+
+        let reopened = Snapshot::open_at(index_dir.path(), 1).unwrap();
+        assert_eq!(reopened.entries.len(), 1);
+        assert_eq!(Snapshot::open(index_dir.path()).unwrap().entries.len(), 2);
+
+        // Once released, the next save collects it
+        drop(snapshot);
+        drop(reopened);
+        index.save(index_dir.path()).unwrap();
+        assert!(!Index::generations(index_dir.path()).unwrap().contains(&1));
+    }
+
+    #[test]
+    fn test_concurrent_saves_take_turns() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index = build_index(root.path());
+
+        let lock = WriteLock::acquire(index_dir.path()).unwrap();
+        let waited = WriteLock::acquire_within(index_dir.path(), Duration::ZERO);
+        assert!(waited.unwrap_err().to_string().contains("Another process"));
+        drop(lock);
+
+        let generations: Vec<u64> = std::thread::scope(|scope| {
+            let saves: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| index.clone().save(index_dir.path()).unwrap()))
+                .collect();
+            saves.into_iter().map(|save| save.join().unwrap()).collect()
+        });
+        let mut sorted = generations.clone();
+        sorted.sort();
+        assert_eq!(sorted, [1, 2, 3, 4]);
+        assert_eq!(
+            Index::current_generation(index_dir.path()).unwrap(),
+            Some(4)
+        );
+        assert_eq!(Index::load(index_dir.path()).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_mapped_snapshot_searches_in_place() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("docs")).unwrap();
+        fs::write(root.path().join("docs/budget.md"), "# Budget").unwrap();
+        fs::write(root.path().join("budget.txt"), "42").unwrap();
+        fs::write(root.path().join("notes.txt"), "").unwrap();
+        let mut index = build_index(root.path());
+        index.save(index_dir.path()).unwrap();
+
+        let mapped = MappedSnapshot::open(index_dir.path()).unwrap();
+        assert_eq!(mapped.generation(), 1);
+        assert!(mapped.index().entries.is_empty());
+        assert_eq!(mapped.entries().len(), index.entries.len());
+        let options = SearchOptions {
+            filters: search::Filters {
+                extensions: vec!["txt".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected: Vec<(FileEntry, i64)> = index
+            .search("budget", &options)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.entry.clone(), m.score))
+            .collect();
+        assert_eq!(expected.len(), 1);
+        assert_eq!(mapped.search("budget", &options).unwrap(), expected);
+        assert!(MappedSnapshot::open_at(index_dir.path(), 2).is_err());
+
+        // The name filter rules out queries no name can match
+        assert!(Index::filter_path(index_dir.path(), 1).is_file());
+        let any = SearchOptions::default();
+        assert!(mapped.may_match(&Query::parse("bdgt").unwrap(), &any));
+        assert!(!mapped.may_match(&Query::parse("ext:rs").unwrap(), &any));
+        assert!(mapped.search("zebra", &any).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_detects_changes() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("keep.txt"), "same").unwrap();
+        fs::write(root.path().join("edit.txt"), "old").unwrap();
+        fs::write(root.path().join("gone.txt"), "bye").unwrap();
+
+        let mut index = build_index(root.path());
+
+        fs::write(root.path().join("edit.txt"), "new and longer").unwrap();
+        fs::remove_file(root.path().join("gone.txt")).unwrap();
+        fs::write(root.path().join("fresh.txt"), "hi").unwrap();
+
+        let report = index.update().unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.modified, 1);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.unchanged, 1);
+        assert!(report.has_changes());
+
+        let mut names: Vec<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["edit.txt", "fresh.txt", "keep.txt"]);
+    }
+
+    #[test]
+    fn test_content_index_survives_save_and_update() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.txt"), "alpha needle").unwrap();
+        fs::write(root.path().join("b.txt"), "beta").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let mut index = build_index(root.path());
+        index
+            .build_content(crate::trigram::DEFAULT_MAX_CONTENT_SIZE)
+            .unwrap();
+        index.save(index_dir.path()).unwrap();
+
+        let mut loaded = Index::load(index_dir.path()).unwrap();
+        assert!(loaded.content.is_none());
+        assert!(loaded.load_content(index_dir.path()).unwrap());
+        let path_of = |index: &Index, ids: Vec<u32>| -> Vec<String> {
+            ids.into_iter()
+                .map(|id| index.entries[id as usize].name.clone())
+                .collect()
+        };
+        let hits = loaded.content.as_ref().unwrap().candidates("needle", false);
+        assert_eq!(path_of(&loaded, hits.unwrap()), vec!["a.txt"]);
+
+        // After an update the needle moves to a new file and ids are remapped
+        fs::write(root.path().join("a.txt"), "alpha only").unwrap();
+        fs::write(root.path().join("c.txt"), "gamma needle").unwrap();
+        loaded.update().unwrap();
+        let hits = loaded.content.as_ref().unwrap().candidates("needle", false);
+        assert_eq!(path_of(&loaded, hits.unwrap()), vec!["c.txt"]);
+
+        // Indexes scanned without --content simply have no content index
+        let mut plain = build_index(root.path());
+        plain.save(index_dir.path()).unwrap();
+        let mut plain = Index::load(index_dir.path()).unwrap();
+        assert!(!plain.load_content(index_dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_apply_changes_from_watcher_events() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("sub")).unwrap();
+        fs::write(root.path().join("keep.txt"), "keep").unwrap();
+        fs::write(root.path().join("edit.txt"), "old").unwrap();
+        fs::write(root.path().join("sub/inner.txt"), "inner").unwrap();
+
+        let mut index = build_index(root.path());
+        let root = index.meta.root.clone();
+
+        fs::write(root.join("edit.txt"), "new contents").unwrap();
+        fs::write(root.join("created.txt"), "hi").unwrap();
+        fs::remove_dir_all(root.join("sub")).unwrap();
+
+        let report = index
+            .apply_changes(&[
+                root.join("edit.txt"),
+                root.join("created.txt"),
+                root.join("sub"),
+            ])
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.modified, 1);
+        // The directory goes together with the file inside it
+        assert_eq!(report.removed, 2);
+        assert_eq!(report.unchanged, 1);
+
+        let mut names: Vec<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["created.txt", "edit.txt", "keep.txt"]);
+    }
+
+    #[test]
+    fn test_update_without_changes() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("only.txt"), "x").unwrap();
+
+        let mut index = build_index(root.path());
+        let report = index.update().unwrap();
+        assert!(!report.has_changes());
+        assert_eq!(report.unchanged, 1);
+
+        // A chmod leaves the mtime alone but still has to be saved
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = root.path().join("only.txt");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+            let report = index.update().unwrap();
+            assert_eq!((report.unchanged, report.permissions_changed), (1, 1));
+            assert!(report.has_changes());
+            assert_eq!(index.entries[0].attributes.mode, Some(0o600));
+        }
+    }
+
+    #[test]
+    fn test_update_reuses_scan_options() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("keep.txt"), "x").unwrap();
+        let options = ScanOptions {
+            excludes: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+        let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+        let mut index = Index::from_scan(scan);
+        let index_dir = tempfile::tempdir().unwrap();
+        index.save(index_dir.path()).unwrap();
+
+        fs::write(root.path().join("noise.log"), "y").unwrap();
+        let mut index = Index::load(index_dir.path()).unwrap();
+        assert_eq!(index.meta.scan_options, options);
+        let report = index.update().unwrap();
+        assert!(!report.has_changes());
+    }
+
+    #[test]
+    fn test_changes_respect_max_filesize() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("app.log");
+        fs::write(&path, "x").unwrap();
+        let options = ScanOptions {
+            max_filesize: Some(10),
+            ..Default::default()
+        };
+        let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+        let mut index = Index::from_scan(scan);
+        assert_eq!(index.entries.len(), 1);
+
+        // Growing past the limit drops the file like a fresh scan would
+        fs::write(&path, "x".repeat(100)).unwrap();
+        let report = index.apply_changes(std::slice::from_ref(&path)).unwrap();
+        assert_eq!(report.removed, 1);
+        assert!(index.entries.is_empty());
+    }
+
+    #[test]
+    fn test_archive_members() {
+        let root = tempfile::tempdir().unwrap();
+        let archive_path = root.path().join("exports.tar");
+        let write_archive = |files: &[(&str, &str)]| {
+            let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+            for (name, contents) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                builder
+                    .append_data(&mut header, name, contents.as_bytes())
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+        };
+        write_archive(&[("q1/sales.csv", "north,quarterly,100\n")]);
+        let options = ScanOptions {
+            archives: true,
+            ..Default::default()
+        };
+        let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+        let mut index = Index::from_scan(scan);
+        index.build_content(1 << 20).unwrap();
+        let member =
+            archive::member_path(&dunce::canonicalize(&archive_path).unwrap(), "q1/sales.csv");
+        let ids = index
+            .content
+            .as_ref()
+            .unwrap()
+            .candidates("quarterly", false);
+        let found: Vec<&Path> = ids
+            .unwrap()
+            .iter()
+            .map(|&id| index.entries[id as usize].path.as_path())
+            .collect();
+        assert_eq!(found, [member.as_path()]);
+
+        // A rewritten archive's files are listed again
+        write_archive(&[("q2/sales.csv", "south\n")]);
+        let changed = dunce::canonicalize(&archive_path).unwrap();
+        let report = index.apply_changes(&[changed]).unwrap();
+        assert_eq!((report.added, report.removed), (1, 1));
+        let names: Vec<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["exports.tar", "sales.csv"]);
+        assert!(index.entries[1].path.ends_with("exports.tar!/q2/sales.csv"));
+    }
+}
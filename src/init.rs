@@ -0,0 +1,315 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use sonic_search::scanner::{self, ScanOptions};
+use sonic_search::{Index, registry, trigram, workspace};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Build and dependency directories that are rarely worth searching
+pub const DEFAULT_EXCLUDES: [&str; 7] = [
+    "node_modules/",
+    "target/",
+    "build/",
+    "dist/",
+    "__pycache__/",
+    "venv/",
+    "*.tmp",
+];
+
+/// How often the progress line is redrawn during the initial scan
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Config written on first run, so later runs know setup already happened
+const CONFIG_TEMPLATE: &str = r#"# sonic-search configuration
+
+# Subcommand used for bare `ss <query>`: "find" or "grep"
+# default_command = "find"
+
+[aliases]
+# rs = "find --ext rs"
+# todo = ["grep", "TODO|FIXME"]
+"#;
+
+/// Everything the guided setup decided on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetupPlan {
+    pub root: PathBuf,
+    pub index_dir: PathBuf,
+    pub excludes: Vec<String>,
+    pub content: bool,
+}
+
+impl SetupPlan {
+    /// The plan used when every question is answered with its default
+    pub fn defaults(cwd: &Path) -> Self {
+        let root = suggested_roots(cwd).swap_remove(0);
+        SetupPlan {
+            index_dir: index_dir_for(&root, cwd),
+            root,
+            excludes: DEFAULT_EXCLUDES.iter().map(|p| p.to_string()).collect(),
+            content: false,
+        }
+    }
+}
+
+/// Whether this looks like the first time `ss` is used: no config file yet
+/// and no index in the default location
+pub fn is_first_run(config_path: Option<&Path>, index_dir: &Path) -> bool {
+    config_path.is_some_and(|path| !path.exists()) && !Index::exists(index_dir)
+}
+
+/// Directories offered as roots: the enclosing workspace and the current
+/// directory first, then the user's documents, desktop and downloads folders
+/// when they exist
+pub fn suggested_roots(cwd: &Path) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = workspace::find_workspace_root(cwd).into_iter().collect();
+    if !roots.iter().any(|root| root == cwd) {
+        roots.push(cwd.to_path_buf());
+    }
+    for dir in [
+        dirs::document_dir(),
+        dirs::desktop_dir(),
+        dirs::download_dir(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if dir.is_dir() && !roots.contains(&dir) {
+            roots.push(dir);
+        }
+    }
+    roots
+}
+
+/// Default index location for `root`, matching where searches from the
+/// workspace will look for it
+fn index_dir_for(root: &Path, cwd: &Path) -> PathBuf {
+    if root == cwd {
+        PathBuf::from(workspace::DEFAULT_INDEX_DIR)
+    } else {
+        root.join(workspace::DEFAULT_INDEX_DIR)
+    }
+}
+
+/// Ask the setup questions on `input`/`out`, falling back to defaults for
+/// empty answers (or end of input)
+pub fn prompt_plan(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    cwd: &Path,
+) -> Result<SetupPlan> {
+    let defaults = SetupPlan::defaults(cwd);
+    writeln!(
+        out,
+        "👋 Welcome to sonic-search! Let's set up your first index."
+    )?;
+
+    let roots = suggested_roots(cwd);
+    writeln!(out, "\nWhich directory should be indexed?")?;
+    for (i, root) in roots.iter().enumerate() {
+        writeln!(out, "  {}) {}", i + 1, root.display())?;
+    }
+    let answer = ask(input, out, "Pick a number or enter a path", "1")?;
+    let root = match answer.parse::<usize>() {
+        Ok(n) if (1..=roots.len()).contains(&n) => roots[n - 1].clone(),
+        _ => cwd.join(&answer),
+    };
+    if !root.is_dir() {
+        anyhow::bail!("Not a directory: {}", root.display());
+    }
+
+    let default_index = index_dir_for(&root, cwd);
+    let index_dir = PathBuf::from(ask(
+        input,
+        out,
+        "Where should the index be stored?",
+        &default_index.to_string_lossy(),
+    )?);
+
+    let default_excludes = defaults.excludes.join(", ");
+    let answer = ask(
+        input,
+        out,
+        "Exclude patterns (comma-separated, '-' for none)",
+        &default_excludes,
+    )?;
+    let excludes = if answer == "-" {
+        Vec::new()
+    } else {
+        answer
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    let answer = ask(
+        input,
+        out,
+        "Also index file contents to speed up grep?",
+        "n",
+    )?;
+    let content = matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes");
+
+    Ok(SetupPlan {
+        root,
+        index_dir,
+        excludes,
+        content,
+    })
+}
+
+/// Ask a yes/no question, with yes as the default
+pub fn confirm(input: &mut impl BufRead, out: &mut impl Write, question: &str) -> Result<bool> {
+    let answer = ask(input, out, question, "y")?;
+    Ok(matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Ask a yes/no question, with no as the default
+pub fn confirm_default_no(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+) -> Result<bool> {
+    let answer = ask(input, out, question, "n")?;
+    Ok(matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+fn ask(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+    default: &str,
+) -> Result<String> {
+    write!(out, "{} [{}]: ", question, default)?;
+    out.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let line = line.trim();
+    Ok(if line.is_empty() { default } else { line }.to_string())
+}
+
+/// Write the starter config file unless one already exists
+pub fn write_config(path: &Path) -> Result<bool> {
+    if path.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(path, CONFIG_TEMPLATE)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    Ok(true)
+}
+
+/// Run the initial scan for `plan`, redrawing a progress line on `progress_out`
+pub fn run_initial_scan(plan: &SetupPlan, progress_out: &mut (impl Write + Send)) -> Result<u64> {
+    let options = ScanOptions {
+        excludes: plan.excludes.clone(),
+        global_ignore: Config::global_ignore_path(),
+        ..Default::default()
+    };
+    let found = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let start = Instant::now();
+
+    let scan = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                let _ = write!(
+                    progress_out,
+                    "\r   Scanning… {} files ({} s)",
+                    found.load(Ordering::Relaxed),
+                    start.elapsed().as_secs()
+                );
+                let _ = progress_out.flush();
+                std::thread::sleep(PROGRESS_INTERVAL);
+            }
+            let _ = writeln!(
+                progress_out,
+                "\r   Scanned {} files in {} ms",
+                found.load(Ordering::Relaxed),
+                start.elapsed().as_millis()
+            );
+        });
+        let scan = scanner::scan_directory_with(&plan.root, &options, Some(&found), None);
+        done.store(true, Ordering::Relaxed);
+        scan
+    })?;
+
+    let mut index = Index::from_scan(scan);
+    if plan.content {
+        index.build_content(trigram::DEFAULT_MAX_CONTENT_SIZE)?;
+    }
+    let generation = index.save(&plan.index_dir)?;
+    registry::remember(&plan.index_dir, &index.meta.root);
+    Ok(generation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_prompt_defaults() {
+        let cwd = tempfile::tempdir().unwrap();
+        let mut out = Vec::new();
+        let plan = prompt_plan(&mut Cursor::new(""), &mut out, cwd.path()).unwrap();
+        assert_eq!(plan, SetupPlan::defaults(cwd.path()));
+        assert!(String::from_utf8(out).unwrap().contains("1) "));
+    }
+
+    #[test]
+    fn test_prompt_custom_answers() {
+        let cwd = tempfile::tempdir().unwrap();
+        fs::create_dir_all(cwd.path().join("notes")).unwrap();
+        let answers = "notes\n\nvendor/, *.bak\ny\n";
+        let plan = prompt_plan(&mut Cursor::new(answers), &mut Vec::new(), cwd.path()).unwrap();
+        assert_eq!(plan.root, cwd.path().join("notes"));
+        assert_eq!(plan.index_dir, cwd.path().join("notes/.sonic-search"));
+        assert_eq!(plan.excludes, vec!["vendor/", "*.bak"]);
+        assert!(plan.content);
+
+        let answers = "missing-dir\n";
+        assert!(prompt_plan(&mut Cursor::new(answers), &mut Vec::new(), cwd.path()).is_err());
+    }
+
+    #[test]
+    fn test_initial_scan_and_config() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("node_modules/pkg")).unwrap();
+        fs::write(root.path().join("node_modules/pkg/index.js"), "x").unwrap();
+        fs::write(root.path().join("readme.md"), "hello").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+
+        let plan = SetupPlan {
+            index_dir: index_dir.path().to_path_buf(),
+            content: true,
+            ..SetupPlan::defaults(root.path())
+        };
+        let mut progress = Vec::new();
+        assert_eq!(run_initial_scan(&plan, &mut progress).unwrap(), 1);
+        let mut index = Index::load(index_dir.path()).unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert!(index.load_content(index_dir.path()).unwrap());
+
+        let config = index_dir.path().join("config/config.toml");
+        assert!(is_first_run(
+            Some(&config),
+            &root.path().join(".sonic-search")
+        ));
+        assert!(write_config(&config).unwrap());
+        assert!(!write_config(&config).unwrap());
+        assert!(crate::config::Config::load_from(&config).is_ok());
+        assert!(!is_first_run(
+            Some(&config),
+            &root.path().join(".sonic-search")
+        ));
+    }
+}
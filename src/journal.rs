@@ -0,0 +1,755 @@
+//! Change journals kept by the OS, so an update can ask what changed since
+//! it last ran instead of walking the whole root.
+//!
+//! On Windows, NTFS keeps an update sequence number (USN) journal for every
+//! volume on its own. Linux keeps no such record, so `ss journal` runs a
+//! recorder that listens to fanotify and appends every changed path to a
+//! log in the index directory. Either way, the index stores a
+//! [`JournalCursor`] and [`changes_since`] reads everything after it.
+
+use crate::mapped;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::AtomicBool;
+
+/// Log of changed paths written by the fanotify recorder
+const LOG_FILE: &str = "journal.log";
+
+/// Held by the recorder for as long as it runs
+const LOCK_FILE: &str = "journal.lock";
+
+/// Start of the log, followed by the journal id as 8 little-endian bytes
+const LOG_MAGIC: &[u8; 4] = b"SSJ1";
+
+const HEADER_LEN: u64 = 12;
+
+/// Which change journal a cursor points into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalKind {
+    /// The NTFS update sequence number journal
+    Usn,
+    /// The log of `ss journal`, recorded through fanotify
+    Fanotify,
+}
+
+impl JournalKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            JournalKind::Usn => "USN",
+            JournalKind::Fanotify => "fanotify",
+        }
+    }
+}
+
+/// How far into a change journal an index has caught up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalCursor {
+    pub kind: JournalKind,
+    /// Identifies one run of the journal; positions in another mean nothing
+    pub journal_id: u64,
+    /// Where the next change will be read from
+    pub position: u64,
+}
+
+/// Where the journal covering `root` currently ends, if there is one to
+/// read from. Taken before walking the root, so nothing changed during the
+/// walk is missed later.
+pub fn current_cursor(root: &Path, index_dir: &Path) -> Option<JournalCursor> {
+    #[cfg(windows)]
+    if let Ok(cursor) = usn::current(root) {
+        return Some(cursor);
+    }
+    let _ = root;
+    if !is_recording(index_dir) {
+        return None;
+    }
+    let mut log = File::open(index_dir.join(LOG_FILE)).ok()?;
+    let journal_id = read_header(&mut log).ok()?;
+    Some(JournalCursor {
+        kind: JournalKind::Fanotify,
+        journal_id,
+        position: log.metadata().ok()?.len(),
+    })
+}
+
+/// Every path under `root` the journal saw change since `cursor`, along with
+/// the cursor to read from next time. `None` when the journal can't tell,
+/// e.g. because it was restarted or no longer reaches back that far, in
+/// which case the root has to be walked.
+pub fn changes_since(
+    root: &Path,
+    index_dir: &Path,
+    cursor: &JournalCursor,
+) -> Option<(BTreeSet<PathBuf>, JournalCursor)> {
+    match cursor.kind {
+        #[cfg(windows)]
+        JournalKind::Usn => usn::changes_since(root, cursor).ok().flatten(),
+        #[cfg(not(windows))]
+        JournalKind::Usn => None,
+        JournalKind::Fanotify => {
+            // Changes are only complete while the recorder has been running
+            // all along, which it can only vouch for while it still runs
+            if !is_recording(index_dir) {
+                return None;
+            }
+            let log = File::open(index_dir.join(LOG_FILE)).ok()?;
+            let (paths, position) = read_log(log, cursor.journal_id, cursor.position).ok()??;
+            let paths = paths.into_iter().filter(|path| path.starts_with(root));
+            let next = JournalCursor {
+                position,
+                ..*cursor
+            };
+            Some((paths.collect(), next))
+        }
+    }
+}
+
+/// Whether a recorder is running for the index in `index_dir`
+pub fn is_recording(index_dir: &Path) -> bool {
+    File::open(index_dir.join(LOCK_FILE))
+        .is_ok_and(|file| matches!(file.try_lock(), Err(std::fs::TryLockError::WouldBlock)))
+}
+
+fn read_header(log: &mut File) -> Result<u64> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    log.read_exact(&mut header)?;
+    if &header[..4] != LOG_MAGIC {
+        anyhow::bail!("Not a journal log");
+    }
+    Ok(u64::from_le_bytes(header[4..].try_into()?))
+}
+
+/// The paths logged from `position` on, if the log still belongs to
+/// `journal_id`, and the position after the last complete one
+fn read_log(mut log: File, journal_id: u64, position: u64) -> Result<Option<(Vec<PathBuf>, u64)>> {
+    if read_header(&mut log)? != journal_id || position < HEADER_LEN {
+        return Ok(None);
+    }
+    if position > log.metadata()?.len() {
+        return Ok(None);
+    }
+    // Read from one byte early to tell whether the cursor fell mid-path
+    log.seek(SeekFrom::Start(position - 1))?;
+    let mut bytes = Vec::new();
+    log.read_to_end(&mut bytes)?;
+    let mut records: &[u8] = &bytes[1..];
+    if position > HEADER_LEN && bytes[0] != 0 {
+        let skip = records
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(records.len(), |i| i + 1);
+        records = &records[skip..];
+    }
+    // The recorder may be halfway through writing the last one
+    let complete = records.iter().rposition(|&b| b == 0).map_or(0, |i| i + 1);
+    let read = (bytes.len() - 1 - records.len() + complete) as u64;
+    let paths = records[..complete]
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| mapped::path_from_bytes(path).to_path_buf())
+        .collect();
+    Ok(Some((paths, position + read)))
+}
+
+/// Record every change under `root` to the log in `index_dir` until `stop`
+/// is set, calling `ready` once changes are being recorded. Each run starts
+/// a new journal, so indexes only trust the log for changes made while
+/// this one ran.
+#[cfg(target_os = "linux")]
+pub fn record(
+    root: &Path,
+    index_dir: &Path,
+    stop: &AtomicBool,
+    ready: impl FnOnce(),
+) -> Result<()> {
+    fanotify::record(root, index_dir, stop, ready)
+}
+
+#[cfg(target_os = "linux")]
+mod fanotify {
+    use super::{HEADER_LEN, LOCK_FILE, LOG_FILE, LOG_MAGIC};
+    use anyhow::{Context, Result};
+    use std::collections::BTreeSet;
+    use std::ffi::{CString, OsStr};
+    use std::fs::{File, OpenOptions};
+    use std::io::{Error, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// How long a read waits for events before checking whether to stop
+    const POLL_MS: i32 = 100;
+
+    /// Past this size the log starts over as a new journal
+    const MAX_LOG_SIZE: u64 = 64 << 20;
+
+    const EVENTS: u64 = libc::FAN_CREATE
+        | libc::FAN_DELETE
+        | libc::FAN_MOVED_FROM
+        | libc::FAN_MOVED_TO
+        | libc::FAN_MODIFY
+        | libc::FAN_ATTRIB
+        | libc::FAN_ONDIR;
+
+    pub fn record(
+        root: &Path,
+        index_dir: &Path,
+        stop: &AtomicBool,
+        ready: impl FnOnce(),
+    ) -> Result<()> {
+        let lock = lock_journal(index_dir)?;
+        let excluded = dunce::canonicalize(index_dir)?;
+        let notify = init(root)?;
+        let mount =
+            File::open(root).with_context(|| format!("Failed to open {}", root.display()))?;
+        let mut log = Log::start(index_dir)?;
+        ready();
+
+        let mut buffer = vec![0u8; 64 * 1024];
+        while !stop.load(Ordering::Relaxed) {
+            let mut poll = libc::pollfd {
+                fd: notify.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll` is the one pollfd the count says
+            if unsafe { libc::poll(&mut poll, 1, POLL_MS) } <= 0 {
+                continue;
+            }
+            // SAFETY: the kernel writes at most `buffer.len()` bytes into it
+            let read =
+                unsafe { libc::read(notify.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+            if read <= 0 {
+                continue;
+            }
+            let mut paths = BTreeSet::new();
+            let overflowed = parse_events(&buffer[..read as usize], &mount, &mut paths);
+            paths.retain(|path| path.starts_with(root) && !path.starts_with(&excluded));
+            if overflowed || log.len >= MAX_LOG_SIZE {
+                // Changes were dropped, or the log grew too long; indexes
+                // that caught up with the old journal walk their root once
+                log = Log::start(index_dir)?;
+            }
+            log.append(&paths)?;
+        }
+        drop(lock);
+        Ok(())
+    }
+
+    fn lock_journal(index_dir: &Path) -> Result<File> {
+        let path = index_dir.join(LOCK_FILE);
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(file),
+            Err(std::fs::TryLockError::WouldBlock) => {
+                anyhow::bail!(
+                    "Another `ss journal` is already recording {}",
+                    index_dir.display()
+                )
+            }
+            Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// A fanotify group reporting changes on the whole filesystem holding `root`
+    pub(super) fn init(root: &Path) -> Result<OwnedFd> {
+        let flags = libc::FAN_CLASS_NOTIF
+            | libc::FAN_CLOEXEC
+            | libc::FAN_NONBLOCK
+            | libc::FAN_REPORT_DFID_NAME;
+        // SAFETY: it takes no pointers
+        let fd = unsafe { libc::fanotify_init(flags, libc::O_RDONLY as u32) };
+        if fd < 0 {
+            return Err(Error::last_os_error())
+                .context("Failed to start fanotify (it needs CAP_SYS_ADMIN and Linux 5.9)");
+        }
+        // SAFETY: `fd` was just opened, and nothing else owns it
+        let notify = unsafe { OwnedFd::from_raw_fd(fd) };
+        let path = CString::new(root.as_os_str().as_bytes())?;
+        // SAFETY: `path` is NUL-terminated and outlives the call
+        let marked = unsafe {
+            libc::fanotify_mark(
+                notify.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_FILESYSTEM,
+                EVENTS,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+            )
+        };
+        if marked < 0 {
+            return Err(Error::last_os_error())
+                .with_context(|| format!("Failed to watch the filesystem of {}", root.display()));
+        }
+        Ok(notify)
+    }
+
+    /// Collect the paths named by the events in `buffer`, returning whether
+    /// the kernel dropped any
+    pub(super) fn parse_events(buffer: &[u8], mount: &File, paths: &mut BTreeSet<PathBuf>) -> bool {
+        let mut overflowed = false;
+        let mut at = 0;
+        let metadata_len = size_of::<libc::fanotify_event_metadata>();
+        while at + metadata_len <= buffer.len() {
+            // SAFETY: the loop condition leaves a whole event's metadata at
+            // `at`, and it's plain data that any bytes are valid for
+            let event: libc::fanotify_event_metadata =
+                unsafe { std::ptr::read_unaligned(buffer[at..].as_ptr().cast()) };
+            let end = at + event.event_len as usize;
+            if event.event_len == 0 || end > buffer.len() {
+                break;
+            }
+            if event.mask & libc::FAN_Q_OVERFLOW != 0 {
+                overflowed = true;
+            }
+            let mut info = at + event.metadata_len as usize;
+            while info + size_of::<libc::fanotify_event_info_header>() <= end {
+                // SAFETY: as above, a whole header is left at `info`
+                let header: libc::fanotify_event_info_header =
+                    unsafe { std::ptr::read_unaligned(buffer[info..].as_ptr().cast()) };
+                if header.len == 0 {
+                    break;
+                }
+                let next = (info + header.len as usize).min(end);
+                if header.info_type == libc::FAN_EVENT_INFO_TYPE_DFID_NAME
+                    && let Some(path) = dir_entry(&buffer[info..next], mount)
+                {
+                    paths.insert(path);
+                }
+                info = next;
+            }
+            at = end;
+        }
+        overflowed
+    }
+
+    /// The path named by one directory handle and entry name record
+    pub(super) fn dir_entry(record: &[u8], mount: &File) -> Option<PathBuf> {
+        let handle_at = size_of::<libc::fanotify_event_info_fid>();
+        let handle_len = size_of::<libc::file_handle>();
+        // A truncated record may not hold a whole handle
+        if record.len() < handle_at + handle_len {
+            return None;
+        }
+        // SAFETY: checked just above that a whole `file_handle` follows
+        // `handle_at`, and it's plain data that any bytes are valid for
+        let handle: libc::file_handle =
+            unsafe { std::ptr::read_unaligned(record[handle_at..].as_ptr().cast()) };
+        let name_at = handle_at + handle_len + handle.handle_bytes as usize;
+        let name = record.get(name_at..)?;
+        let name = &name[..name.iter().position(|&b| b == 0)?];
+
+        // The handle has to sit in aligned memory for open_by_handle_at
+        let mut stored = vec![0u64; (name_at - handle_at).div_ceil(8)];
+        // SAFETY: `stored` holds at least `name_at - handle_at` bytes, and isn't
+        // used otherwise while `bytes` lives
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(stored.as_mut_ptr().cast::<u8>(), name_at - handle_at)
+        };
+        bytes.copy_from_slice(&record[handle_at..name_at]);
+        // SAFETY: `stored` holds the whole handle, its `handle_bytes` included,
+        // in memory aligned for it
+        let fd = unsafe {
+            libc::open_by_handle_at(
+                mount.as_raw_fd(),
+                stored.as_mut_ptr().cast(),
+                libc::O_PATH | libc::O_CLOEXEC,
+            )
+        };
+        // The directory may be gone already, in which case its own removal
+        // is reported against its parent
+        if fd < 0 {
+            return None;
+        }
+        // SAFETY: `fd` was just opened, and nothing else owns it
+        let dir = unsafe { OwnedFd::from_raw_fd(fd) };
+        let dir = std::fs::read_link(format!("/proc/self/fd/{}", dir.as_raw_fd())).ok()?;
+        Some(match name {
+            b"" | b"." => dir,
+            name => dir.join(OsStr::from_bytes(name)),
+        })
+    }
+
+    struct Log {
+        file: File,
+        len: u64,
+    }
+
+    impl Log {
+        /// Start the log over as a new journal
+        fn start(index_dir: &Path) -> Result<Log> {
+            let path = index_dir.join(LOG_FILE);
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let journal_id = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64);
+            file.write_all(LOG_MAGIC)?;
+            file.write_all(&journal_id.to_le_bytes())?;
+            Ok(Log {
+                file,
+                len: HEADER_LEN,
+            })
+        }
+
+        fn append(&mut self, paths: &BTreeSet<PathBuf>) -> Result<()> {
+            let mut records = Vec::new();
+            for path in paths {
+                records.extend_from_slice(path.as_os_str().as_bytes());
+                records.push(0);
+            }
+            // One write, so readers see whole paths but for the last one
+            self.file.write_all(&records)?;
+            self.len += records.len() as u64;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod usn {
+    use super::{JournalCursor, JournalKind};
+    use anyhow::{Context, Result};
+    use std::collections::{BTreeSet, HashMap};
+    use std::ffi::{OsStr, OsString};
+    use std::io::Error;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_READ, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_DESCRIPTOR, FILE_NAME_NORMALIZED,
+        FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FileIdType,
+        GetFinalPathNameByHandleW, GetVolumePathNameW, OPEN_EXISTING, OpenFileById,
+        VOLUME_NAME_DOS,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::{
+        FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0,
+        USN_JOURNAL_DATA_V0, USN_RECORD_V2,
+    };
+
+    const SHARE_ALL: u32 = FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE;
+
+    /// A handle closed when dropped
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle is open, and only this owns it
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain([0]).collect()
+    }
+
+    /// The volume holding `root`, opened to read its journal; this takes
+    /// administrator rights
+    fn open_volume(root: &Path) -> Result<Handle> {
+        let mut mount = [0u16; 261];
+        let path = wide(root.as_os_str());
+        // SAFETY: `path` is NUL-terminated, and `mount` as long as the length passed
+        if unsafe { GetVolumePathNameW(path.as_ptr(), mount.as_mut_ptr(), mount.len() as u32) } == 0
+        {
+            return Err(Error::last_os_error().into());
+        }
+        let mount = OsString::from_wide(&mount[..mount.iter().position(|&c| c == 0).unwrap_or(0)]);
+        let mount = mount.to_string_lossy();
+        let device = format!(r"\\.\{}", mount.trim_end_matches('\\'));
+        // SAFETY: the name is NUL-terminated and outlives the call
+        let handle = unsafe {
+            CreateFileW(
+                wide(OsStr::new(&device)).as_ptr(),
+                GENERIC_READ,
+                SHARE_ALL,
+                ptr::null(),
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(Error::last_os_error())
+                .with_context(|| format!("Failed to open {}", device));
+        }
+        Ok(Handle(handle))
+    }
+
+    fn query(volume: &Handle) -> Result<USN_JOURNAL_DATA_V0> {
+        // SAFETY: the struct is plain integers, which zero is valid for
+        let mut data: USN_JOURNAL_DATA_V0 = unsafe { std::mem::zeroed() };
+        let mut returned = 0;
+        // SAFETY: `data` is as large as the size passed, and outlives the call
+        let ok = unsafe {
+            DeviceIoControl(
+                volume.0,
+                FSCTL_QUERY_USN_JOURNAL,
+                ptr::null(),
+                0,
+                (&mut data as *mut USN_JOURNAL_DATA_V0).cast(),
+                size_of::<USN_JOURNAL_DATA_V0>() as u32,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(Error::last_os_error()).context("Failed to query the USN journal");
+        }
+        Ok(data)
+    }
+
+    pub fn current(root: &Path) -> Result<JournalCursor> {
+        let journal = query(&open_volume(root)?)?;
+        Ok(JournalCursor {
+            kind: JournalKind::Usn,
+            journal_id: journal.UsnJournalID,
+            position: journal.NextUsn as u64,
+        })
+    }
+
+    pub fn changes_since(
+        root: &Path,
+        cursor: &JournalCursor,
+    ) -> Result<Option<(BTreeSet<PathBuf>, JournalCursor)>> {
+        let volume = open_volume(root)?;
+        let journal = query(&volume)?;
+        // Recreated, or wrapped around past the cursor
+        if journal.UsnJournalID != cursor.journal_id || (cursor.position as i64) < journal.FirstUsn
+        {
+            return Ok(None);
+        }
+
+        let mut dirs: HashMap<u64, Option<PathBuf>> = HashMap::new();
+        let mut paths = BTreeSet::new();
+        let mut position = cursor.position as i64;
+        let mut buffer = vec![0u64; 8 * 1024];
+        let buffer_len = (buffer.len() * 8) as u32;
+        while position < journal.NextUsn {
+            let request = READ_USN_JOURNAL_DATA_V0 {
+                StartUsn: position,
+                ReasonMask: u32::MAX,
+                ReturnOnlyOnClose: 0,
+                Timeout: 0,
+                BytesToWaitFor: 0,
+                UsnJournalID: journal.UsnJournalID,
+            };
+            let mut returned = 0;
+            // SAFETY: `request` and `buffer` are as large as the sizes passed, and
+            // outlive the call
+            let ok = unsafe {
+                DeviceIoControl(
+                    volume.0,
+                    FSCTL_READ_USN_JOURNAL,
+                    (&request as *const READ_USN_JOURNAL_DATA_V0).cast(),
+                    size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+                    buffer.as_mut_ptr().cast(),
+                    buffer_len,
+                    &mut returned,
+                    ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(Error::last_os_error()).context("Failed to read the USN journal");
+            }
+            // SAFETY: the call filled `returned` bytes of `buffer`, which isn't
+            // written again while `bytes` lives
+            let bytes = unsafe {
+                std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), returned as usize)
+            };
+            let Some(next) = bytes.get(..8) else {
+                break;
+            };
+            let next = i64::from_le_bytes(next.try_into()?);
+            let mut at = 8;
+            while at + size_of::<USN_RECORD_V2>() <= bytes.len() {
+                // SAFETY: the loop condition leaves a whole record at `at`, and
+                // it's plain data that any bytes are valid for
+                let record: USN_RECORD_V2 =
+                    unsafe { ptr::read_unaligned(bytes[at..].as_ptr().cast()) };
+                if record.RecordLength == 0 {
+                    break;
+                }
+                if record.MajorVersion == 2 {
+                    let start = at + record.FileNameOffset as usize;
+                    let Some(name) = bytes.get(start..start + record.FileNameLength as usize)
+                    else {
+                        break;
+                    };
+                    let name: Vec<u16> = name
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                        .collect();
+                    let parent = dirs
+                        .entry(record.ParentFileReferenceNumber)
+                        .or_insert_with(|| path_by_id(&volume, record.ParentFileReferenceNumber));
+                    if let Some(parent) = parent {
+                        let path = parent.join(OsString::from_wide(&name));
+                        if path.starts_with(root) {
+                            paths.insert(path);
+                        }
+                    }
+                }
+                at += record.RecordLength as usize;
+            }
+            if next <= position {
+                break;
+            }
+            position = next;
+        }
+        let next = JournalCursor {
+            position: position as u64,
+            ..*cursor
+        };
+        Ok(Some((paths, next)))
+    }
+
+    /// Where the file with reference number `id` is now, if it still exists
+    fn path_by_id(volume: &Handle, id: u64) -> Option<PathBuf> {
+        // SAFETY: the struct and its union are plain integers, which zero is
+        // valid for
+        let mut descriptor: FILE_ID_DESCRIPTOR = unsafe { std::mem::zeroed() };
+        descriptor.dwSize = size_of::<FILE_ID_DESCRIPTOR>() as u32;
+        descriptor.Type = FileIdType;
+        descriptor.Anonymous.FileId = id as i64;
+        // SAFETY: `descriptor` is filled in, and outlives the call
+        let handle = unsafe {
+            OpenFileById(
+                volume.0,
+                &descriptor,
+                0,
+                SHARE_ALL,
+                ptr::null(),
+                FILE_FLAG_BACKUP_SEMANTICS,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let handle = Handle(handle);
+        let mut path = vec![0u16; 1024];
+        // SAFETY: `path` is as long as the length passed
+        let len = unsafe {
+            GetFinalPathNameByHandleW(
+                handle.0,
+                path.as_mut_ptr(),
+                path.len() as u32,
+                FILE_NAME_NORMALIZED | VOLUME_NAME_DOS,
+            )
+        } as usize;
+        if len == 0 || len >= path.len() {
+            return None;
+        }
+        let path = PathBuf::from(OsString::from_wide(&path[..len]));
+        Some(dunce::simplified(&path).to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_log(dir: &Path, journal_id: u64, records: &[u8]) {
+        let mut log = File::create(dir.join(LOG_FILE)).unwrap();
+        log.write_all(LOG_MAGIC).unwrap();
+        log.write_all(&journal_id.to_le_bytes()).unwrap();
+        log.write_all(records).unwrap();
+    }
+
+    #[test]
+    fn test_read_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = || File::open(dir.path().join(LOG_FILE)).unwrap();
+        write_log(dir.path(), 7, b"/r/a.txt\0/r/src/b.rs\0/r/half");
+
+        let (paths, position) = read_log(open(), 7, HEADER_LEN).unwrap().unwrap();
+        assert_eq!(
+            paths,
+            [PathBuf::from("/r/a.txt"), PathBuf::from("/r/src/b.rs")]
+        );
+        // The path still being written is left for next time
+        assert_eq!(position, HEADER_LEN + 21);
+        let (paths, _) = read_log(open(), 7, position).unwrap().unwrap();
+        assert!(paths.is_empty());
+
+        // A cursor taken mid-path skips to the next whole one
+        let (paths, _) = read_log(open(), 7, HEADER_LEN + 3).unwrap().unwrap();
+        assert_eq!(paths, [PathBuf::from("/r/src/b.rs")]);
+
+        // Another journal, or a log cut short, can't say what changed
+        assert!(read_log(open(), 8, HEADER_LEN).unwrap().is_none());
+        assert!(read_log(open(), 7, 1000).unwrap().is_none());
+
+        // Without a recorder running, the log isn't trusted
+        let cursor = JournalCursor {
+            kind: JournalKind::Fanotify,
+            journal_id: 7,
+            position: HEADER_LEN,
+        };
+        assert!(changes_since(Path::new("/r"), dir.path(), &cursor).is_none());
+        assert!(current_cursor(Path::new("/r"), dir.path()).is_none());
+        let lock = File::create(dir.path().join(LOCK_FILE)).unwrap();
+        lock.lock().unwrap();
+        let (paths, _) = changes_since(Path::new("/r/src"), dir.path(), &cursor).unwrap();
+        assert_eq!(paths, BTreeSet::from([PathBuf::from("/r/src/b.rs")]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_truncated_fanotify_records() {
+        let mount = File::open("/").unwrap();
+        let handle_at = size_of::<libc::fanotify_event_info_fid>();
+        for len in [0, handle_at, handle_at + 4] {
+            assert!(fanotify::dir_entry(&vec![0u8; len], &mount).is_none());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fanotify_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dunce::canonicalize(dir.path()).unwrap();
+        // Needs CAP_SYS_ADMIN, which tests don't usually run with
+        let Ok(notify) = fanotify::init(&root) else {
+            return;
+        };
+        let mount = File::open(&root).unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+
+        let mut paths = BTreeSet::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        for _ in 0..20 {
+            // SAFETY: the kernel writes at most `buffer.len()` bytes into it
+            let read = unsafe {
+                libc::read(
+                    std::os::fd::AsRawFd::as_raw_fd(&notify),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            if read > 0 {
+                fanotify::parse_events(&buffer[..read as usize], &mount, &mut paths);
+            }
+            if paths.contains(&root.join("src/main.rs")) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(paths.contains(&root.join("src")));
+        assert!(paths.contains(&root.join("src/main.rs")));
+    }
+}
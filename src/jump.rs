@@ -0,0 +1,354 @@
+use anyhow::{Context, Result};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the directory history inside the data directory
+pub const JUMP_FILE: &str = "jump.json";
+
+/// Once the ranks add up to more than this, they're all scaled down so old
+/// favourites fade and rarely visited directories are forgotten
+const MAX_TOTAL_RANK: f64 = 10_000.0;
+
+/// A directory the shell hook has seen, and how often
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visit {
+    pub path: PathBuf,
+    /// Number of visits, aged so it can drop below a whole visit
+    pub rank: f64,
+    pub last: SystemTime,
+}
+
+impl Visit {
+    /// The rank weighted by how recently the directory was visited
+    pub fn frecency(&self, now: SystemTime) -> f64 {
+        const HOUR: Duration = Duration::from_secs(60 * 60);
+        let age = now.duration_since(self.last).unwrap_or_default();
+        let weight = if age < HOUR {
+            4.0
+        } else if age < 24 * HOUR {
+            2.0
+        } else if age < 7 * 24 * HOUR {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * weight
+    }
+}
+
+/// Where rankings can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Zoxide,
+    Autojump,
+}
+
+/// Shells `ss jump --init` can print a hook for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Directories visited from the shell, for jumping back to them by a
+/// fragment of their path
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JumpHistory {
+    pub dirs: Vec<Visit>,
+}
+
+impl JumpHistory {
+    /// Load the history from `path`; a missing file is empty
+    pub fn load_from(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid directory history: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the history to `path`, replacing it atomically
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to update {}", path.display()))
+    }
+
+    /// Count a visit to `dir` at `now`
+    pub fn visit(&mut self, dir: &Path, now: SystemTime) {
+        self.merge(vec![Visit {
+            path: dir.to_path_buf(),
+            rank: 1.0,
+            last: now,
+        }]);
+    }
+
+    /// Add `visits`, e.g. imported from another tool, to the history, adding
+    /// up the ranks of directories it already knows
+    pub fn merge(&mut self, visits: Vec<Visit>) {
+        for visit in visits {
+            match self.dirs.iter_mut().find(|known| known.path == visit.path) {
+                Some(known) => {
+                    known.rank += visit.rank;
+                    known.last = known.last.max(visit.last);
+                }
+                None => self.dirs.push(visit),
+            }
+        }
+        let total: f64 = self.dirs.iter().map(|visit| visit.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            let scale = 0.9 * MAX_TOTAL_RANK / total;
+            for visit in &mut self.dirs {
+                visit.rank *= scale;
+            }
+            self.dirs.retain(|visit| visit.rank >= 1.0);
+        }
+    }
+
+    /// Directories matching every keyword, highest frecency first.
+    ///
+    /// Keywords must appear in the path in order, ignoring case. When no
+    /// directory matches that way, each keyword is fuzzy-matched anywhere in
+    /// the path instead.
+    pub fn ranked(&self, keywords: &[String], now: SystemTime) -> Vec<(&Visit, f64)> {
+        let keywords: Vec<String> = keywords.iter().map(|k| k.to_lowercase()).collect();
+        let mut ranked: Vec<(&Visit, f64)> = self
+            .dirs
+            .iter()
+            .filter(|visit| in_order(&visit.path.to_string_lossy().to_lowercase(), &keywords))
+            .map(|visit| (visit, visit.frecency(now)))
+            .collect();
+        if ranked.is_empty() && !keywords.is_empty() {
+            let matcher = SkimMatcherV2::default();
+            ranked = self
+                .dirs
+                .iter()
+                .filter(|visit| {
+                    let path = visit.path.to_string_lossy();
+                    keywords
+                        .iter()
+                        .all(|keyword| matcher.fuzzy_match(&path, keyword).is_some())
+                })
+                .map(|visit| (visit, visit.frecency(now)))
+                .collect();
+        }
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    }
+
+    /// The best directory matching `keywords` that still exists
+    pub fn best(&self, keywords: &[String], now: SystemTime) -> Option<&Path> {
+        self.ranked(keywords, now)
+            .into_iter()
+            .map(|(visit, _)| visit.path.as_path())
+            .find(|path| path.is_dir())
+    }
+}
+
+/// Whether `keywords` all occur in `path`, one after the other
+fn in_order(path: &str, keywords: &[String]) -> bool {
+    let mut rest = path;
+    keywords
+        .iter()
+        .all(|keyword| match rest.find(keyword.as_str()) {
+            Some(at) => {
+                rest = &rest[at + keyword.len()..];
+                true
+            }
+            None => false,
+        })
+}
+
+/// Where `source` keeps its database by default
+pub fn default_import_path(source: Source) -> Option<PathBuf> {
+    match source {
+        Source::Zoxide => match std::env::var_os("_ZO_DATA_DIR") {
+            Some(dir) => Some(PathBuf::from(dir).join("db.zo")),
+            None => dirs::data_dir().map(|dir| dir.join("zoxide").join("db.zo")),
+        },
+        Source::Autojump => dirs::data_dir().map(|dir| dir.join("autojump").join("autojump.txt")),
+    }
+}
+
+/// Read the visits recorded in a database of `source`
+pub fn import(source: Source, path: &Path) -> Result<Vec<Visit>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let visits = match source {
+        Source::Zoxide => parse_zoxide(&bytes),
+        Source::Autojump => parse_autojump(&String::from_utf8_lossy(&bytes)),
+    };
+    visits.with_context(|| format!("Invalid {:?} database: {}", source, path.display()))
+}
+
+/// zoxide's `db.zo`: a format version followed by bincode-encoded entries
+fn parse_zoxide(bytes: &[u8]) -> Result<Vec<Visit>> {
+    // Only version 3, used since zoxide 0.8, is understood
+    const VERSION: u32 = 3;
+    #[derive(Deserialize)]
+    struct Dir {
+        path: String,
+        rank: f64,
+        last_accessed: u64,
+    }
+    let version: u32 = bincode::deserialize(bytes)?;
+    if version != VERSION {
+        anyhow::bail!("unsupported version {}", version);
+    }
+    let (_, dirs): (u32, Vec<Dir>) = bincode::deserialize(bytes)?;
+    Ok(dirs
+        .into_iter()
+        .map(|dir| Visit {
+            path: PathBuf::from(dir.path),
+            rank: dir.rank,
+            last: UNIX_EPOCH + Duration::from_secs(dir.last_accessed),
+        })
+        .collect())
+}
+
+/// autojump's `autojump.txt`: one `weight<TAB>path` line per directory.
+/// It keeps no visit times, so every directory counts as visited long ago.
+fn parse_autojump(text: &str) -> Result<Vec<Visit>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (weight, path) = line
+                .split_once('\t')
+                .with_context(|| format!("line without a tab: {:?}", line))?;
+            Ok(Visit {
+                path: PathBuf::from(path),
+                rank: weight.trim().parse()?,
+                last: UNIX_EPOCH,
+            })
+        })
+        .collect()
+}
+
+/// Shell code that records every directory change with `ss jump --add` and
+/// defines `j <keywords>` to jump
+pub fn shell_hook(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"_ss_jump_hook() {
+  if [ "$PWD" != "$_SS_JUMP_LAST" ]; then
+    _SS_JUMP_LAST="$PWD"
+    command ss jump --add "$PWD" 2>/dev/null
+  fi
+}
+case ";${PROMPT_COMMAND-};" in
+  *";_ss_jump_hook;"*) ;;
+  *) PROMPT_COMMAND="_ss_jump_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+j() { local dir; dir="$(command ss jump "$@")" && cd "$dir"; }
+"#
+        }
+        Shell::Zsh => {
+            r#"_ss_jump_hook() { command ss jump --add "$PWD" 2>/dev/null; }
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd _ss_jump_hook
+j() { local dir; dir="$(command ss jump "$@")" && cd "$dir"; }
+"#
+        }
+        Shell::Fish => {
+            r#"function _ss_jump_hook --on-variable PWD
+    command ss jump --add "$PWD" 2>/dev/null
+end
+function j
+    set -l dir (command ss jump $argv); and cd $dir
+end
+"#
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visits_and_ranking() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("src/sonic-search");
+        let notes = dir.path().join("notes");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&notes).unwrap();
+        let now = SystemTime::now();
+        let last_month = now - Duration::from_secs(30 * 24 * 60 * 60);
+
+        let mut history = JumpHistory::default();
+        for _ in 0..3 {
+            history.visit(&notes, last_month);
+        }
+        history.visit(&project, now);
+        assert_eq!(history.dirs.len(), 2);
+        assert_eq!(history.dirs[0].rank, 3.0);
+        // One recent visit outweighs three old ones
+        let keywords: Vec<String> = Vec::new();
+        assert_eq!(history.ranked(&keywords, now)[0].0.path, project);
+
+        let words = |text: &str| -> Vec<String> { text.split(' ').map(str::to_string).collect() };
+        assert_eq!(
+            history.best(&words("SRC sonic"), now),
+            Some(project.as_path())
+        );
+        assert_eq!(
+            history.best(&words("sonic src"), now),
+            Some(project.as_path())
+        );
+        assert_eq!(history.best(&words("zzz"), now), None);
+        for (path, rank) in [("/home/ada/notes", 1.0), ("/home/ada/src", 5.0)] {
+            let visit = Visit {
+                path: PathBuf::from(path),
+                rank,
+                last: now,
+            };
+            let fuzzy = JumpHistory { dirs: vec![visit] };
+            let expected = usize::from(path.ends_with("notes"));
+            assert_eq!(fuzzy.ranked(&words("nts"), now).len(), expected);
+        }
+
+        let path = dir.path().join("data").join(JUMP_FILE);
+        history.save_to(&path).unwrap();
+        assert_eq!(JumpHistory::load_from(&path).unwrap(), history);
+
+        history.merge(vec![Visit {
+            path: notes.clone(),
+            rank: 20_000.0,
+            last: now,
+        }]);
+        assert!(history.dirs.iter().map(|v| v.rank).sum::<f64>() <= MAX_TOTAL_RANK);
+        assert_eq!(history.dirs.len(), 1);
+    }
+
+    #[test]
+    fn test_import_databases() {
+        let autojump = parse_autojump("12.5\t/home/ada/src\n3\t/tmp\n").unwrap();
+        assert_eq!(autojump[0].path, PathBuf::from("/home/ada/src"));
+        assert_eq!(autojump[1].rank, 3.0);
+        assert!(parse_autojump("no tab here").is_err());
+
+        let db = bincode::serialize(&(
+            3u32,
+            vec![("/home/ada/src".to_string(), 7.0f64, 1_700_000_000u64)],
+        ))
+        .unwrap();
+        let zoxide = parse_zoxide(&db).unwrap();
+        assert_eq!(zoxide[0].rank, 7.0);
+        assert_eq!(
+            zoxide[0].last,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        let old = bincode::serialize(&(2u32, Vec::<(String, f64, u64)>::new())).unwrap();
+        assert!(parse_zoxide(&old).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use std::path::Path;
+use whatlang::Lang;
+
+/// Number of leading bytes whose language is detected
+const SAMPLE_LEN: usize = 64 * 1024;
+
+/// Extensions of prose documents; code and data files aren't assigned a
+/// language even when they contain comments in one
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "adoc", "asciidoc", "htm", "html", "markdown", "md", "org", "rst", "tex", "text", "txt",
+];
+
+/// ISO 639-1 codes of the languages whatlang detects, by ISO 639-3 code
+const CODES: &[(&str, &str)] = &[
+    ("afr", "af"),
+    ("aka", "ak"),
+    ("amh", "am"),
+    ("ara", "ar"),
+    ("aze", "az"),
+    ("bel", "be"),
+    ("ben", "bn"),
+    ("bul", "bg"),
+    ("cat", "ca"),
+    ("ces", "cs"),
+    ("cmn", "zh"),
+    ("cym", "cy"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("epo", "eo"),
+    ("est", "et"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("guj", "gu"),
+    ("heb", "he"),
+    ("hin", "hi"),
+    ("hrv", "hr"),
+    ("hun", "hu"),
+    ("hye", "hy"),
+    ("ind", "id"),
+    ("ita", "it"),
+    ("jav", "jv"),
+    ("jpn", "ja"),
+    ("kan", "kn"),
+    ("kat", "ka"),
+    ("khm", "km"),
+    ("kor", "ko"),
+    ("lat", "la"),
+    ("lav", "lv"),
+    ("lit", "lt"),
+    ("mal", "ml"),
+    ("mar", "mr"),
+    ("mkd", "mk"),
+    ("mya", "my"),
+    ("nep", "ne"),
+    ("nld", "nl"),
+    ("nob", "nb"),
+    ("ori", "or"),
+    ("pan", "pa"),
+    ("pes", "fa"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("sin", "si"),
+    ("slk", "sk"),
+    ("slv", "sl"),
+    ("sna", "sn"),
+    ("spa", "es"),
+    ("srp", "sr"),
+    ("swe", "sv"),
+    ("tam", "ta"),
+    ("tel", "te"),
+    ("tgl", "tl"),
+    ("tha", "th"),
+    ("tuk", "tk"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("urd", "ur"),
+    ("uzb", "uz"),
+    ("vie", "vi"),
+    ("yid", "yi"),
+    ("zul", "zu"),
+];
+
+/// Whether `path` looks like a prose document: a known document extension,
+/// or no extension at all like `README`
+pub fn is_document(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_ascii_lowercase();
+            DOCUMENT_EXTENSIONS.contains(&ext.as_str())
+        }
+        None => true,
+    }
+}
+
+/// The ISO 639-1 code of the language `contents` are written in, when it
+/// can be told reliably
+pub fn detect(contents: &[u8]) -> Option<&'static str> {
+    let sample = &contents[..contents.len().min(SAMPLE_LEN)];
+    let info = whatlang::detect(&String::from_utf8_lossy(sample))?;
+    info.is_reliable().then(|| short_code(info.lang()))?
+}
+
+fn short_code(lang: Lang) -> Option<&'static str> {
+    CODES
+        .iter()
+        .find(|(long, _)| *long == lang.code())
+        .map(|(_, short)| *short)
+}
+
+/// Parse a language given as an ISO 639-1 or 639-3 code or an English name,
+/// such as `ja`, `jpn` or `Japanese`, into its ISO 639-1 code
+pub fn parse(text: &str) -> Result<&'static str> {
+    let text = text.to_ascii_lowercase();
+    let found = CODES.iter().find_map(|&(long, short)| {
+        let lang = Lang::from_code(long)?;
+        (text == short || text == long || text == lang.eng_name().to_ascii_lowercase())
+            .then_some(short)
+    });
+    match found {
+        Some(short) => Ok(short),
+        None => anyhow::bail!(
+            "Unknown language '{}': expected a code such as en, fr or ja",
+            text
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_parse() {
+        let english = "The quick brown fox jumps over the lazy dog. It was a bright cold \
+                       day in April, and the clocks were striking thirteen.";
+        assert_eq!(detect(english.as_bytes()), Some("en"));
+        let japanese = "吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。\
+                        何でも薄暗いじめじめした所でニャーニャー泣いていた事だけは記憶している。";
+        assert_eq!(detect(japanese.as_bytes()), Some("ja"));
+        assert_eq!(detect(b"x = 1"), None);
+
+        assert_eq!(parse("ja").unwrap(), "ja");
+        assert_eq!(parse("FRA").unwrap(), "fr");
+        assert_eq!(parse("German").unwrap(), "de");
+        assert!(parse("klingon").is_err());
+        // Every language whatlang knows has a short code
+        assert!(Lang::all().iter().all(|&lang| short_code(lang).is_some()));
+
+        assert!(is_document(Path::new("notes/Readme.MD")));
+        assert!(is_document(Path::new("LICENSE")));
+        assert!(!is_document(Path::new("src/main.rs")));
+    }
+}
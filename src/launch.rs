@@ -0,0 +1,220 @@
+//! `--open` and `--reveal` on search results: open a file in `$VISUAL` or
+//! `$EDITOR` at a matching line, or with the app the desktop opens it with,
+//! or show it in the file manager.
+
+use crate::exec;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Most results one search launches, so a broad query can't open hundreds
+/// of windows
+pub const MAX_LAUNCHED: usize = 20;
+
+/// What to do with each result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Open,
+    Reveal,
+}
+
+/// A result to launch, at a line of it for content matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target<'a> {
+    pub path: &'a Path,
+    pub line: Option<usize>,
+}
+
+/// Launch `action` on every target in turn, waiting for each launcher (and
+/// a terminal editor) to exit, and return how many failed. Revealing skips
+/// targets in a directory already shown.
+pub fn launch(action: Action, targets: &[Target]) -> Result<usize> {
+    if targets.len() > MAX_LAUNCHED {
+        anyhow::bail!(
+            "{} results to launch, more than {}; narrow the search or lower --limit",
+            targets.len(),
+            MAX_LAUNCHED
+        );
+    }
+    let mut revealed = Vec::new();
+    let mut failed = 0;
+    for target in targets {
+        let outcome = match action {
+            Action::Open => {
+                eprintln!("📂 Opening {}", describe(target));
+                open(target.path, target.line)
+            }
+            Action::Reveal => {
+                let parent = target.path.parent();
+                if revealed.contains(&parent) {
+                    continue;
+                }
+                revealed.push(parent);
+                eprintln!("📂 Revealing {}", target.path.display());
+                reveal(target.path)
+            }
+        };
+        if let Err(e) = outcome {
+            eprintln!("⚠️  {:#}", e);
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
+
+/// Open `path`: at `line` in `$VISUAL` or `$EDITOR` when one is set and
+/// there's a line to go to, and with the desktop's app for it otherwise
+pub fn open(path: &Path, line: Option<usize>) -> Result<()> {
+    match (editor(), line) {
+        (Some(editor), Some(_)) => run(exec::shell(&editor_line(&editor, path, line))),
+        _ => run_opener(open_command(path)),
+    }
+}
+
+/// Show `path` in the file manager: selected in Finder and Explorer, and as
+/// its directory opened elsewhere
+pub fn reveal(path: &Path) -> Result<()> {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        return run(command);
+    }
+    if cfg!(windows) {
+        // Explorer reads `/select,` and the path as one argument
+        let mut command = Command::new("explorer");
+        explorer_arg(&mut command, "/select,", path);
+        return run_opener(command);
+    }
+    run_opener(open_command(path.parent().unwrap_or(path)))
+}
+
+/// The editor the user chose, `$VISUAL` before `$EDITOR`
+pub fn editor() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+/// Shell command line that opens `path` in `editor`, which may carry its
+/// own arguments (`code --wait`), at `line` in the syntax that editor takes
+pub fn editor_line(editor: &str, path: &Path, line: Option<usize>) -> String {
+    let file = exec::quote(path);
+    let Some(line) = line else {
+        return format!("{} {}", editor, file);
+    };
+    let at_line = || exec::quote(Path::new(&format!("{}:{}", path.display(), line)));
+    let program = editor
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_stem())
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let args = match program.as_str() {
+        "vi" | "vim" | "nvim" | "gvim" | "mvim" | "view" | "nano" | "pico" | "emacs"
+        | "emacsclient" | "micro" | "kak" | "joe" | "jed" | "ne" | "mg" | "vis" => {
+            format!("+{} {}", line, file)
+        }
+        "code" | "code-insiders" | "codium" | "vscodium" | "cursor" | "windsurf" => {
+            format!("--goto {}", at_line())
+        }
+        "subl" | "sublime_text" | "zed" | "hx" | "helix" | "atom" | "lapce" => at_line(),
+        "idea" | "idea64" | "pycharm" | "clion" | "goland" | "webstorm" | "phpstorm"
+        | "rubymine" | "rider" | "studio" => format!("--line {} {}", line, file),
+        "mate" => format!("-l {} {}", line, file),
+        // An editor we don't know might take `+12` for a file name
+        _ => file,
+    };
+    format!("{} {}", editor, args)
+}
+
+/// The platform's command for opening `path` with its default app. None
+/// goes through a shell, which would read `&` or `%` in a file name.
+pub fn open_command(path: &Path) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("explorer");
+        explorer_arg(&mut command, "", path);
+        return command;
+    }
+    let mut command = match cfg!(target_os = "macos") {
+        true => Command::new("open"),
+        false => Command::new("xdg-open"),
+    };
+    command.arg(path);
+    command
+}
+
+/// Pass `path`, after `flags`, to Explorer quoted whatever it holds, since
+/// Explorer splits its arguments at commas too; a Windows path can't hold
+/// the quote itself
+#[cfg(windows)]
+fn explorer_arg(command: &mut Command, flags: &str, path: &Path) {
+    use std::os::windows::process::CommandExt;
+    command.raw_arg(format!("{}\"{}\"", flags, path.display()));
+}
+
+#[cfg(not(windows))]
+fn explorer_arg(command: &mut Command, flags: &str, path: &Path) {
+    command.arg(format!("{}{}", flags, path.display()));
+}
+
+fn describe(target: &Target) -> String {
+    match target.line {
+        Some(line) => format!("{}:{}", target.path.display(), line),
+        None => target.path.display().to_string(),
+    }
+}
+
+/// Run the command opening or revealing a file. Explorer exits with 1 even
+/// when it worked, so on Windows it's only started.
+fn run_opener(mut command: Command) -> Result<()> {
+    if cfg!(windows) {
+        let program = command.get_program().to_string_lossy().into_owned();
+        command
+            .spawn()
+            .with_context(|| format!("failed to run {}", program))?;
+        return Ok(());
+    }
+    run(command)
+}
+
+fn run(mut command: Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("{} failed with {}", program, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_line() {
+        if cfg!(windows) {
+            return;
+        }
+        let path = Path::new("/src/my app.rs");
+        let line = |editor: &str| editor_line(editor, path, Some(12));
+        assert_eq!(line("nvim"), "nvim +12 '/src/my app.rs'");
+        assert_eq!(
+            line("/usr/bin/vim -p"),
+            "/usr/bin/vim -p +12 '/src/my app.rs'"
+        );
+        assert_eq!(
+            line("code --wait"),
+            "code --wait --goto '/src/my app.rs:12'"
+        );
+        assert_eq!(line("subl"), "subl '/src/my app.rs:12'");
+        assert_eq!(line("idea"), "idea --line 12 '/src/my app.rs'");
+        assert_eq!(line("ed"), "ed '/src/my app.rs'");
+        assert_eq!(editor_line("vim", path, None), "vim '/src/my app.rs'");
+
+        let targets = [Target { path, line: None }; MAX_LAUNCHED + 1];
+        assert!(launch(Action::Reveal, &targets).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Sonic-Search: fast filename and content search over a persistent index.
+//!
+//! The `ss` command-line tool is a thin layer over this crate, so everything
+//! it does is available to other Rust programs:
+//!
+//! - [`scanner`] walks a directory tree, honouring `.gitignore` rules and
+//!   hidden files, and produces [`FileEntry`] records, optionally for the
+//!   files inside [`archive`]s too.
+//! - [`Index`] stores those entries on disk as immutable generations, keeps
+//!   them current with [`Index::update`], and is read through a pinned
+//!   [`Snapshot`].
+//! - [`Index::search`] fuzzy-matches file names and [`Index::grep`]
+//!   searches file contents, optionally narrowed by a trigram content index.
+//!   PDF and office documents are searched through their text, as
+//!   [`extract`] pulls it out.
+//! - [`Index`], [`Snapshot`], [`Query`] and the scanner, search and grep
+//!   functions behind them return [`error::Result`], whose [`Error`] tells
+//!   an invalid query, a damaged index and an I/O failure apart by its
+//!   [`Error::kind`]; the other modules return [`anyhow::Result`].
+//! - [`testing::IndexBuilder`] declares an index in memory, for tests of
+//!   code built on top of the crate.
+//!
+//! ```no_run
+//! use sonic_search::{Index, SearchOptions, scanner};
+//! use std::path::Path;
+//!
+//! let index_dir = Path::new(".sonic-search");
+//! let mut index = Index::from_scan(scanner::scan_directory(".")?);
+//! index.save(index_dir)?;
+//!
+//! for result in index.search("budget", &SearchOptions::default())? {
+//!     println!("{} ({})", result.entry.path.display(), result.score);
+//! }
+//! # Ok::<(), sonic_search::Error>(())
+//! ```
+
+pub mod archive;
+pub mod bench;
+pub mod bloom;
+pub mod buildsys;
+pub mod cjk;
+pub mod codeowners;
+pub mod collate;
+pub mod collect;
+pub mod collisions;
+pub mod configs;
+pub mod count;
+pub mod daemon;
+pub mod diff;
+pub mod du;
+pub mod dupes;
+pub mod engine;
+pub mod entrypoint;
+pub mod error;
+pub mod exec;
+pub mod export;
+pub mod extract;
+pub mod filetype;
+pub mod fixture;
+#[cfg(feature = "fulltext")]
+pub mod fulltext;
+pub mod git;
+pub mod grep;
+pub mod history;
+pub mod import;
+pub mod index;
+pub mod journal;
+pub mod jump;
+pub mod lang;
+pub mod launch;
+pub mod mapped;
+pub mod memory;
+pub mod mounts;
+pub mod normalize;
+pub mod parquet;
+pub mod perms;
+pub mod portability;
+pub mod profile;
+pub mod projects;
+pub mod quarantine;
+pub mod query;
+pub mod quota;
+pub mod registry;
+pub mod rename;
+pub mod replace;
+pub mod rerank;
+pub mod scanner;
+pub mod schedule;
+pub mod scrub;
+pub mod search;
+pub mod searches;
+pub mod selfcheck;
+pub mod sqlite;
+pub mod storage;
+pub mod suggest;
+pub mod tags;
+pub mod testing;
+pub mod tokenize;
+pub mod transfer;
+pub mod trigram;
+pub mod units;
+pub mod vendor;
+pub mod verify;
+pub mod watch;
+pub mod workspace;
+
+pub use error::{Error, ErrorKind};
+pub use grep::{FileMatches, GrepOptions, LineMatch};
+pub use index::{ExtensionStats, Index, IndexMeta, MappedSnapshot, Snapshot, UpdateReport};
+pub use query::Query;
+pub use scanner::{FileEntry, ScanOptions, ScanResult};
+pub use search::{
+    CaseMode, EntryFilter, EntryType, Filters, RankWeights, SearchOptions, SearchResult,
+};
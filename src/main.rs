@@ -1,9 +1,47 @@
-use super::scanner::{self, ScanResult};
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use std::time::Instant;
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
+mod compat;
+mod completions;
+mod config;
+#[cfg(feature = "tui")]
+mod dashboard;
+mod hyperlink;
+mod init;
+mod output;
+mod pager;
+#[cfg(feature = "tui")]
+mod picker;
+#[cfg(feature = "serve")]
+mod serve;
+mod template;
+
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCompleter;
+use config::Config;
+use hyperlink::{HyperlinkChoice, HyperlinkFormat};
+use output::{ColorChoice, OutputFormat};
+use sonic_search::buildsys::BuildSystems;
+use sonic_search::collate;
+use sonic_search::history::{self, HistoryIndex};
+use sonic_search::jump::{self, JumpHistory};
+use sonic_search::mounts::{Mount, MountTable};
+use sonic_search::registry::{self, Registry};
+use sonic_search::rename::{self, Rename};
+use sonic_search::replace;
+use sonic_search::schedule::{self, Schedule};
+use sonic_search::{
+    CaseMode, EntryFilter, FileEntry, Filters, Index, MappedSnapshot, RankWeights, SearchOptions,
+    Snapshot, bench, collect, collisions, configs, count, daemon, diff, du, dupes, engine, exec,
+    export, filetype, fixture, git, grep, import, journal, lang, launch, memory, perms,
+    portability, profile, quarantine, query, quota, rerank, scanner, scrub, search, searches,
+    selfcheck, storage, suggest, tags, transfer, trigram, units, verify, watch, workspace,
+};
+use std::collections::{BTreeSet, HashMap, HashSet, hash_map};
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command(name = "ss")]
@@ -12,137 +50,6758 @@ use fuzzy_matcher::FuzzyMatcher;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Output format for find, grep and stats results
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// Highlight matches in find and grep results [default: auto]
+    #[arg(long, global = true, value_enum)]
+    color: Option<ColorChoice>,
+    /// Show modification times in find results: relative, iso, or a strftime
+    /// format such as '%Y-%m-%d %H:%M'
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = units::parse_date_format)]
+    date_format: Option<units::DateFormat>,
+    /// Write dates in UTC instead of the local timezone
+    #[arg(long, global = true, requires = "date_format")]
+    utc: bool,
+    /// Make paths in find and grep results clickable (OSC 8 hyperlinks)
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = HyperlinkChoice::Auto)]
+    hyperlinks: HyperlinkChoice,
+    /// What hyperlinks open: file, vscode, vscode-insiders, vscodium, cursor,
+    /// idea, textmate, macvim, or a template such as 'myeditor://{path}:{line}'
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = HyperlinkFormat::parse)]
+    hyperlink_format: Option<HyperlinkFormat>,
+    /// Record how long each stage (walk, match, rank, print, ...) took into
+    /// this file, as folded stacks for flamegraph tools such as inferno
+    #[arg(long, global = true, value_name = "FILE")]
+    profile: Option<PathBuf>,
+    /// Never send long output through $SS_PAGER, $PAGER or less
+    #[arg(long, global = true)]
+    no_pager: bool,
+    /// End each path in find and grep results with a NUL byte instead of a
+    /// newline, for `xargs -0`; grep then lists the matching files
+    #[arg(long, global = true)]
+    print0: bool,
+    /// Print each find result or grep match as this template, e.g.
+    /// '{path}\t{size}\t{mtime}'. Fields: path, name, ext, size, mtime,
+    /// mode, user, group, score, line, column, offset, text and match
+    #[arg(
+        long = "format",
+        global = true,
+        value_name = "TEMPLATE",
+        value_parser = template::Template::parse,
+        conflicts_with = "output"
+    )]
+    format_template: Option<template::Template>,
+    /// Read settings from this file instead of
+    /// ~/.config/sonic-search/config.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Ranking weights from the config file
+    #[arg(skip)]
+    ranking: RankWeights,
+    /// The command line as given, for the search history
+    #[arg(skip)]
+    args: Vec<OsString>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Index a directory for searching
+    #[command(visible_alias = "s")]
+    Scan {
+        /// Directory to scan; several can be given with --name
+        #[arg(required = true, value_name = "PATH")]
+        paths: Vec<String>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Store the index of every path under this profile name, for `find --index <NAME>`
+        #[arg(long, conflicts_with = "index_dir")]
+        name: Option<String>,
+        /// Only re-process entries that changed since the stored index was built
+        #[arg(long)]
+        incremental: bool,
+        /// Also build a trigram index of file contents to speed up grep
+        #[arg(long)]
+        content: bool,
+        /// Files larger than this are left out of the content index
+        #[arg(long, value_parser = units::parse_size, default_value_t = trigram::DEFAULT_MAX_CONTENT_SIZE)]
+        max_content_size: u64,
+        /// Build the content index within roughly this much memory (e.g.
+        /// 2G), sorting postings in temporary files beyond it
+        #[arg(long, value_parser = units::parse_size, requires = "content")]
+        content_memory: Option<u64>,
+        /// Gitignore-style pattern to leave out of the index (repeatable)
+        #[arg(long = "exclude", value_name = "PATTERN")]
+        excludes: Vec<String>,
+        /// Store a content hash of every file, for `--hash` lookups and to
+        /// tell touched files from modified ones on update
+        #[arg(long)]
+        hash: bool,
+        /// Store the index zstd-compressed: smaller on disk, slower to open
+        #[arg(long)]
+        compress: bool,
+        /// Walk and index with this many threads (defaults to the number of CPUs)
+        #[arg(short = 'j', long, value_name = "N")]
+        threads: Option<NonZeroUsize>,
+        /// Descend at most this many directories below the root
+        #[arg(long, value_name = "DEPTH")]
+        max_depth: Option<usize>,
+        /// Leave files larger than this size out of the index (e.g. 100M)
+        #[arg(long, value_parser = units::parse_size)]
+        max_filesize: Option<u64>,
+        /// Index hidden files and directories too (but never `.git` or an index)
+        #[arg(long)]
+        hidden: bool,
+        /// Don't respect `.ignore`, `.gitignore` and `.sonic-ignore` files, git's
+        /// excludes or the global ignore file
+        #[arg(long)]
+        no_ignore: bool,
+        /// Don't respect `.gitignore` files or git's excludes, but still `.ignore`
+        /// and `.sonic-ignore` files
+        #[arg(long)]
+        no_git_ignore: bool,
+        /// Leave out what this gitignore-style file lists, relative to the root (repeatable)
+        #[arg(long = "ignore-file", value_name = "PATH")]
+        ignore_files: Vec<PathBuf>,
+        /// Follow symbolic links, indexing every directory once even if links loop
+        #[arg(short = 'L', long)]
+        follow_symlinks: bool,
+        /// Stay on the root's filesystem, leaving out whatever is mounted below it
+        #[arg(short = 'x', long)]
+        one_file_system: bool,
+        /// Leave out network (NFS, SMB), FUSE and kernel filesystems mounted below the root
+        #[arg(long)]
+        skip_network: bool,
+        /// Also index the files inside .zip, .tar and .tar.gz archives, as
+        /// `archive.zip!/inner/path`
+        #[arg(long, visible_alias = "index-archives")]
+        archives: bool,
+        /// Keep this many of the latest index generations for `ss diff` to
+        /// compare (defaults to 3)
+        #[arg(long, value_name = "N")]
+        keep_generations: Option<NonZeroUsize>,
+        /// Also match Cyrillic and Greek names by their Latin spelling, so
+        /// `moskva` finds `Москва.pdf` and `Москва` finds `moskva.pdf`
+        #[arg(long)]
+        transliterate: bool,
+        /// Tell the MIME type of files without a telling extension by their
+        /// first bytes, for `--mime` and `--type`
+        #[arg(long)]
+        sniff_mime: bool,
+        /// Where to keep the entries: the default binary format, or a
+        /// SQLite database (`index.sqlite`) other tools can query with SQL
+        #[arg(long, value_enum, value_name = "BACKEND")]
+        backend: Option<StorageBackend>,
+        /// Also keep a full-text index of the contents, for ranked word and
+        /// phrase queries with `ss grep --fulltext`
+        #[arg(long)]
+        fulltext: bool,
+        /// Leave comments out of the full-text index of source files
+        #[arg(long, requires = "fulltext")]
+        strip_comments: bool,
+        /// Index the output directories of Cargo, CMake, Bazel and Gradle
+        /// builds too, such as `target/` beside a `Cargo.toml`
+        #[arg(long)]
+        include_build_outputs: bool,
+        /// Keep the index directory within this size (e.g. 500M), evicting
+        /// older generations and then content indexes rather than failing
+        #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+        max_index_size: Option<u64>,
+    },
+    /// Guided first-time setup: choose a root and excludes, then scan it
+    Init {
+        /// Accept the default answer to every question
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Print a shell completion script
+    #[command(after_help = completions::INSTALL_HINT)]
+    Completions {
+        shell: clap_complete::Shell,
+        /// Ask `ss` on every completion, which also completes `find`
+        /// queries with indexed names
+        #[arg(long)]
+        dynamic: bool,
+    },
+    /// Incrementally refresh an existing index
+    Update {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Skip the update unless the index is older than this (e.g. 30m, 6h)
+        #[arg(long, value_parser = units::parse_duration)]
+        older_than: Option<Duration>,
+    },
+    /// Refresh the index quietly for `ss locate`, like updatedb: update it
+    /// if it exists, or scan --database-root into it
+    Updatedb {
+        /// Directory to index when there's no index yet, or to re-scan from
+        /// scratch when the index covers another one
+        #[arg(short = 'U', long, value_name = "PATH")]
+        database_root: Option<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short = 'o', long, value_name = "DIR")]
+        index_dir: Option<PathBuf>,
+        /// Space-separated absolute paths to leave out
+        #[arg(long, value_name = "PATHS")]
+        prunepaths: Vec<String>,
+        /// Space-separated file and directory names to leave out
+        #[arg(long, value_name = "NAMES")]
+        prunenames: Vec<String>,
+        /// Report what changed
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Build an index from the database of plocate, mlocate or Everything,
+    /// without walking the tree
+    Import {
+        /// Tool whose database is read
+        #[arg(value_enum)]
+        format: ImportFormat,
+        /// Database to read instead of the tool's default one; for
+        /// Everything, a file list exported as `.efu`
+        #[arg(value_name = "DB")]
+        database: Option<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Only import what's below this directory, which becomes the
+        /// index's root
+        #[arg(long, value_name = "DIR")]
+        root: Option<PathBuf>,
+        /// Look up the size and modification time of every path, which
+        /// locate databases don't record, dropping those that are gone
+        #[arg(long)]
+        stat: bool,
+    },
+    /// Write every indexed entry with its metadata as CSV, JSON or Parquet,
+    /// for pandas, DuckDB and the like
+    Export {
+        /// csv, json, ndjson or parquet; by default, what the extension of
+        /// --out says
+        #[arg(value_enum)]
+        format: Option<ExportFormat>,
+        /// File to write instead of standard output
+        #[arg(short = 'o', long = "out", value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// Drop entries whose paths no longer exist, without re-scanning
+    Prune {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// List the entries that would be dropped without saving
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the stored generations against their checksums and entry
+    /// counts, and look for orphaned files
+    Verify {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Point readers at the newest intact generation if theirs is
+        /// damaged, and delete damaged generations and orphaned files
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Rebuild an index written by another version of sonic-search in this
+    /// version's format, scanning its root again with the options it was
+    /// scanned with (same as `ss index migrate`)
+    Migrate {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// Keep the index up to date by watching the indexed root for changes
+    Watch {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// How long to batch filesystem events before applying them
+        #[arg(long, value_parser = units::parse_duration, default_value = "500ms")]
+        debounce: Duration,
+    },
+    /// Record changes under the indexed root so `update` can read them from
+    /// a journal instead of walking the root (Linux; Windows keeps a USN
+    /// journal of its own)
+    Journal {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// Keep the index in memory and answer `find` and `grep` from it over a
+    /// local socket, reloading when the index is updated
+    Daemon {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Also re-index on a schedule: every so often (e.g. 6h), or at
+        /// local times of day (e.g. 03:00,15:00)
+        #[arg(long, value_name = "SCHEDULE", value_parser = Schedule::parse)]
+        reindex: Option<Schedule>,
+        /// Walk the whole root when re-indexing, even where a change
+        /// journal could say what changed
+        #[arg(long)]
+        full_reindex: bool,
+    },
+    /// Answer `/search`, `/grep` and `/stats` requests with JSON over HTTP
+    Serve {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Port to listen on
+        #[arg(long, default_value_t = 7700)]
+        port: u16,
+        /// Address to listen on; use 0.0.0.0 to accept other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Require this token, as `Authorization: Bearer <token>` or `?token=`
+        #[arg(long)]
+        token: Option<String>,
+        /// Also serve a search page at `/` that searches as you type, for
+        /// using the index from a browser
+        #[arg(long)]
+        ui: bool,
+    },
+    /// Pick a file full-screen, or with --dashboard watch over every index
+    Tui {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Show the freshness, daemon and watcher of every known index, with
+        /// keys to rescan or compact them
+        #[arg(long)]
+        dashboard: bool,
+    },
+    /// Find files by name
+    #[command(visible_alias = "f")]
+    Find {
+        /// Search query
+        #[arg(
+            required_unless_present_any = ["interactive", "hash", "saved", "tags"],
+            add = ArgValueCompleter::new(completions::indexed_names)
+        )]
+        query: Option<String>,
+        /// Run the search saved under this name with `ss save-search`; more
+        /// options can be added to it
+        #[arg(long, value_name = "NAME", conflicts_with = "query")]
+        saved: Option<String>,
+        /// Pick a result in a live-updating terminal UI and print its path
+        #[arg(short = 'I', long)]
+        interactive: bool,
+        /// Path to the index directory (defaults to the workspace's index);
+        /// repeat to search several
+        #[arg(short, long)]
+        index_dir: Vec<PathBuf>,
+        /// Search every index of a profile created with `scan --name`
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["index_dir", "generation", "interactive"])]
+        index: Option<String>,
+        /// Search every index `ss scan` has built
+        #[arg(long, conflicts_with_all = ["index_dir", "index", "generation", "interactive"])]
+        all_indexes: bool,
+        /// Read a specific retained index generation instead of the current one
+        #[arg(long)]
+        generation: Option<u64>,
+        /// Match against the path relative to the index root, like fzf
+        /// (the default when the query contains '/')
+        #[arg(short = 'p', long)]
+        full_path: bool,
+        #[command(flatten)]
+        case: CaseArgs,
+        /// How query terms match names: fuzzily, as a substring, as a
+        /// prefix or as a regex, or with the `aligned-matcher` feature
+        /// fuzzily by the best alignment
+        #[arg(long, value_enum, value_name = "ENGINE", default_value_t = MatcherEngine::Fuzzy)]
+        matcher: MatcherEngine,
+        /// Only match accented letters with the same accents, instead of
+        /// letting `resume` find `résumé`
+        #[arg(long)]
+        exact_accents: bool,
+        /// Show who last committed each file, and when
+        #[arg(long)]
+        git: bool,
+        /// Only show files carrying this tag, from `ss tag add` (repeatable;
+        /// any of them)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Show a uniform random sample of this many matches instead of the
+        /// best ones
+        #[arg(long, value_name = "N", conflicts_with_all = ["limit", "interactive"])]
+        sample: Option<usize>,
+        /// Seed for --sample, to draw the same sample again
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+        /// Copy every match into this directory instead of listing them
+        #[arg(
+            long,
+            value_name = "DIR",
+            group = "transfer",
+            conflicts_with = "interactive"
+        )]
+        copy_to: Option<PathBuf>,
+        /// Move every match into this directory instead of listing them
+        #[arg(
+            long,
+            value_name = "DIR",
+            group = "transfer",
+            conflicts_with = "interactive"
+        )]
+        move_to: Option<PathBuf>,
+        /// What --copy-to and --move-to do when the destination name is taken
+        #[arg(long, value_enum, default_value_t = OnConflict::Skip, requires = "transfer")]
+        on_conflict: OnConflict,
+        /// Show what --copy-to or --move-to would do without touching any file
+        #[arg(long, requires = "transfer")]
+        dry_run: bool,
+        /// Set these permission bits, in octal such as 640, on every match (Unix)
+        #[arg(
+            long,
+            value_name = "MODE",
+            value_parser = perms::parse_mode,
+            conflicts_with_all = ["interactive", "transfer"]
+        )]
+        chmod: Option<u32>,
+        /// Give every match to this user, user:group or :group (Unix)
+        #[arg(
+            long,
+            value_name = "OWNER",
+            value_parser = perms::Owner::parse,
+            conflicts_with_all = ["interactive", "transfer"]
+        )]
+        chown: Option<perms::Owner>,
+        /// Set the modification time of every match to now
+        #[arg(long, conflicts_with_all = ["interactive", "transfer"])]
+        touch: bool,
+        /// Take away write permission from every match
+        #[arg(long, conflicts_with_all = ["interactive", "transfer", "chmod"])]
+        set_readonly: bool,
+        /// Clear the hidden attribute of every match (Windows and macOS)
+        #[arg(long, conflicts_with_all = ["interactive", "transfer"])]
+        clear_hidden: bool,
+        /// Change files without asking first
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Run this shell command for every match, with {path} replaced by
+        /// its quoted path or, without one, the path appended
+        #[arg(
+            long,
+            value_name = "COMMAND",
+            group = "exec_group",
+            conflicts_with_all = [
+                "interactive", "transfer", "chmod", "chown", "touch", "set_readonly", "clear_hidden"
+            ]
+        )]
+        exec: Option<String>,
+        /// Run this shell command once with every matched path, in place of
+        /// {path} or appended
+        #[arg(
+            long,
+            value_name = "COMMAND",
+            group = "exec_group",
+            conflicts_with_all = [
+                "interactive", "transfer", "chmod", "chown", "touch", "set_readonly", "clear_hidden"
+            ]
+        )]
+        exec_batch: Option<String>,
+        /// Run at most this many --exec commands at once [default: number of CPUs]
+        #[arg(long, value_name = "N", requires = "exec")]
+        jobs: Option<NonZeroUsize>,
+        /// Open the best match, or each one --limit allows, with the app the
+        /// desktop opens it with
+        #[arg(
+            long,
+            group = "launch",
+            conflicts_with_all = [
+                "interactive", "sample", "transfer", "exec_group", "chmod", "chown", "touch",
+                "set_readonly", "clear_hidden"
+            ]
+        )]
+        open: bool,
+        /// Show the best match, or each one --limit allows, in the file manager
+        #[arg(
+            long,
+            group = "launch",
+            conflicts_with_all = [
+                "interactive", "sample", "transfer", "exec_group", "chmod", "chown", "touch",
+                "set_readonly", "clear_hidden"
+            ]
+        )]
+        reveal: bool,
+        /// Reorder the best matches with this shell command: it reads them
+        /// as JSON lines, each with an `id`, and prints the ids back in the
+        /// order to show them (the query is in $SS_QUERY)
+        #[arg(
+            long,
+            value_name = "COMMAND",
+            conflicts_with_all = [
+                "interactive", "sample", "no_sort", "sort", "reverse", "transfer", "exec_group"
+            ]
+        )]
+        rerank_cmd: Option<String>,
+        /// How many of the best matches --rerank-cmd gets to reorder
+        #[arg(long, value_name = "N", default_value_t = 100, requires = "rerank_cmd")]
+        rerank_candidates: usize,
+        #[command(flatten)]
+        order: OrderArgs,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Print the query language `find` understands, with examples run
+    /// against an index when one is given
+    Syntax {
+        /// Run the examples against this index and show what they find
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Results to show for each example
+        #[arg(long, default_value_t = 3, requires = "index_dir")]
+        limit: usize,
+    },
+    /// List indexed paths containing a pattern, like mlocate's `locate`
+    Locate {
+        /// Text found anywhere in the path, or a glob with `*`, `?` or `[`
+        /// the whole path must match
+        #[arg(required_unless_present_any = ["regexp", "statistics"])]
+        patterns: Vec<String>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(
+            short = 'd',
+            long = "database",
+            visible_alias = "index-dir",
+            value_name = "DIR"
+        )]
+        index_dir: Option<PathBuf>,
+        /// Match ignoring case
+        #[arg(short, long)]
+        ignore_case: bool,
+        /// Match against the name only
+        #[arg(short, long, overrides_with = "wholename")]
+        basename: bool,
+        /// Match against the whole path (the default)
+        #[arg(short, long, overrides_with = "basename")]
+        wholename: bool,
+        /// Only list paths matching every pattern, instead of any
+        #[arg(short = 'A', long)]
+        all: bool,
+        /// Take every pattern as a regex
+        #[arg(long)]
+        regex: bool,
+        /// Also match this regex (repeatable)
+        #[arg(short, long, value_name = "REGEX")]
+        regexp: Vec<String>,
+        /// Only list paths that still exist
+        #[arg(short, long)]
+        existing: bool,
+        /// Print how many paths matched instead of listing them
+        #[arg(short, long)]
+        count: bool,
+        /// Stop after this many paths
+        #[arg(short, long, visible_short_alias = 'n', value_name = "N")]
+        limit: Option<usize>,
+        /// End each path with a NUL byte instead of a newline
+        #[arg(short = '0', long)]
+        null: bool,
+        /// Warn when the index is older than this many days
+        #[arg(long, value_name = "DAYS", default_value_t = LOCATE_MAX_AGE_DAYS)]
+        max_database_age: u64,
+        /// Don't warn about an old index
+        #[arg(short, long)]
+        quiet: bool,
+        /// Print the size of the index instead of searching it
+        #[arg(short = 'S', long)]
+        statistics: bool,
+    },
+    /// List the sub-projects (Cargo, npm, Bazel) detected in the index
+    Projects {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// Show index statistics
+    Stats {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Number of largest files, extensions and deepest paths to list
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Search inside file contents
+    #[command(visible_alias = "g")]
+    Grep {
+        /// Search pattern (regular expression)
+        query: String,
+        /// Path to the index directory (defaults to the workspace's index);
+        /// repeat to search several
+        #[arg(short, long)]
+        index_dir: Vec<PathBuf>,
+        /// Search every index of a profile created with `scan --name`
+        #[arg(long, value_name = "NAME", conflicts_with = "index_dir")]
+        index: Option<String>,
+        /// Search every index `ss scan` has built
+        #[arg(long, conflicts_with_all = ["index_dir", "index"])]
+        all_indexes: bool,
+        /// Treat the pattern as a literal string, matching an identifier
+        /// like `getUserName` only as it's spelled
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+        /// Match an identifier written with separators, like
+        /// `get_user_name`, in its other spellings too, as `getUserName` is
+        #[arg(long, conflicts_with = "fixed_strings")]
+        identifiers: bool,
+        #[command(flatten)]
+        case: CaseArgs,
+        /// Skip files larger than this size (e.g. 512K, 10M)
+        #[arg(long, value_parser = units::parse_size)]
+        max_filesize: Option<u64>,
+        /// Stop reading a file after this many matching lines
+        #[arg(short = 'm', long, value_name = "N")]
+        max_count: Option<usize>,
+        /// Stop searching once this many matching lines are found
+        #[arg(long, value_name = "N")]
+        max_total: Option<usize>,
+        /// Only search documents written in this language (e.g. en, fr, ja); needs `scan --content`
+        #[arg(long, value_name = "LANG", value_parser = lang::parse)]
+        doc_lang: Option<&'static str>,
+        /// Only report lines last changed by this author (name or email, per git blame)
+        #[arg(long, value_name = "AUTHOR")]
+        changed_by: Option<String>,
+        /// Only report lines last changed since this date (2024-01-01) or within this long (30d)
+        #[arg(long, value_name = "WHEN", value_parser = units::parse_time)]
+        since: Option<SystemTime>,
+        /// Search every version of every file in the repository's git history
+        #[arg(long, conflicts_with_all = ["changed_by", "since", "doc_lang"])]
+        history: bool,
+        /// Keep watching the tree and print matches in lines as they are written, like `tail -f`
+        #[arg(long, conflicts_with_all = ["changed_by", "since", "history", "doc_lang"])]
+        follow: bool,
+        /// Rank files by relevance to words, `AND`/`OR`, `-word` and
+        /// `"phrases"` in the query; needs `scan --fulltext`
+        #[arg(long, conflicts_with_all = ["fixed_strings", "changed_by", "since", "history", "follow"])]
+        fulltext: bool,
+        /// Open the first matching file, or each one --limit allows, at its
+        /// first match: in $VISUAL or $EDITOR when set, and with the
+        /// desktop's app for it otherwise
+        #[arg(long, group = "launch", conflicts_with_all = ["history", "follow", "fulltext"])]
+        open: bool,
+        /// Show the first matching file, or each one --limit allows, in the
+        /// file manager
+        #[arg(long, group = "launch", conflicts_with_all = ["history", "follow", "fulltext"])]
+        reveal: bool,
+        #[command(flatten)]
+        lines: LineArgs,
+        #[command(flatten)]
+        order: OrderArgs,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Wait until a pattern appears in the indexed tree, then run a command and exit
+    WatchFor {
+        /// Search pattern (regular expression)
+        pattern: String,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Shell command to run on the first match, which is passed in
+        /// SS_MATCH_PATH, SS_MATCH_LINE and SS_MATCH_TEXT
+        #[arg(long, value_name = "CMD")]
+        exec: Option<String>,
+        /// Fail if the pattern hasn't appeared after this long (e.g. 30s, 5m)
+        #[arg(long, value_parser = units::parse_duration)]
+        timeout: Option<Duration>,
+        /// Also match content already in the files, not just newly written lines
+        #[arg(long)]
+        existing: bool,
+        /// Treat the pattern as a literal string
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+        /// Match case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Count files, sizes and optionally lines per extension, project or directory
+    Count {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Also count lines, reading every text file
+        #[arg(long)]
+        lines: bool,
+        /// What to group files by
+        #[arg(long, value_enum, default_value_t = CountBy::Ext)]
+        by: CountBy,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Show how much the indexed files take up per directory, as a tree of
+    /// the biggest consumers
+    Du {
+        /// Directory to report on (defaults to the indexed root)
+        path: Option<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Levels of subdirectories to list
+        #[arg(short, long, default_value_t = 1)]
+        depth: usize,
+        /// List only this many of the biggest subdirectories of each directory
+        #[arg(short = 'n', long)]
+        top: Option<usize>,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Find indexed files with identical contents
+    Dupes {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Ignore files smaller than this size (e.g. 4K, 1M)
+        #[arg(long, value_parser = units::parse_size)]
+        min_size: Option<u64>,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// List the indexed files with the same contents as a file, e.g. stray
+    /// copies of a large asset
+    SameAs {
+        /// File to look for copies of; it needn't be indexed itself
+        file: PathBuf,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Read every candidate to compare it, instead of trusting the
+        /// hashes `scan --hash` stored
+        #[arg(long)]
+        verify: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Find names in the same directory that only differ in case or Unicode
+    /// normalization, which clash on case-insensitive filesystems
+    NameCollisions {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Check indexed paths for problems, such as names Windows can't store
+    Audit {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Flag paths over Windows' length limit, with characters NTFS doesn't
+        /// allow, ending in a dot or space, or using reserved device names
+        #[arg(long)]
+        portability: bool,
+        /// Longest path allowed below the root, for --portability
+        #[arg(long, value_name = "LEN", default_value_t = portability::WINDOWS_MAX_PATH)]
+        max_path: usize,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// List known configuration files (dotfiles, /etc configs, IDE
+    /// settings) by application, e.g. to audit a backup or a new machine
+    Configs {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Only list the files of this application (e.g. git, "vs code")
+        #[arg(long, value_name = "NAME")]
+        app: Option<String>,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Archive every file matching a query, keeping paths relative to the root
+    Collect {
+        /// Search query, matched like `find`
+        query: String,
+        /// Archive to write; the format follows the extension (.tar or .tar.zst)
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Match against the path relative to the index root, like fzf
+        /// (the default when the query contains '/')
+        #[arg(short = 'p', long)]
+        full_path: bool,
+        /// Refuse to archive more than this many files
+        #[arg(long, default_value_t = collect::Limits::default().max_files)]
+        max_files: usize,
+        /// Refuse to archive more than this much data (e.g. 500M, 2G)
+        #[arg(long, value_parser = units::parse_size, default_value = "1G")]
+        max_size: u64,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Rename files whose names match a regex, e.g. 'IMG_(\d+)\.jpeg' 'photo-$1.jpg'
+    Rename {
+        /// Regular expression matched against file names
+        #[arg(required_unless_present = "undo")]
+        pattern: Option<String>,
+        /// Replacement for the matched part of each name; $1 or ${name}
+        /// insert capture groups
+        #[arg(required_unless_present = "undo")]
+        template: Option<String>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Match case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+        /// Only print the renames that would be made
+        #[arg(long)]
+        dry_run: bool,
+        /// Revert the most recent rename
+        #[arg(long, conflicts_with_all = ["pattern", "template", "dry_run"])]
+        undo: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Replace every match of a pattern in the contents of indexed files
+    Replace {
+        /// Search pattern (regular expression)
+        pattern: String,
+        /// Replacement for each match; $1 or ${name} insert capture groups
+        replacement: String,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Treat the pattern and the replacement as literal strings
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+        /// Match case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+        /// Skip files larger than this size (e.g. 512K, 10M)
+        #[arg(long, value_parser = units::parse_size)]
+        max_filesize: Option<u64>,
+        /// Rewrite every line of changed files with this ending (lf or crlf)
+        /// instead of keeping each file's own
+        #[arg(long, value_parser = replace::LineEnding::parse)]
+        eol: Option<replace::LineEnding>,
+        /// Keep the modification times of rewritten files, so build tools
+        /// don't see them as changed
+        #[arg(long)]
+        preserve_mtime: bool,
+        /// Save each file's original contents beside it under its name with
+        /// this suffix added (`.orig` if none is given)
+        #[arg(long, value_name = "SUFFIX", num_args = 0..=1, default_missing_value = ".orig", require_equals = true, conflicts_with = "dry_run")]
+        backup: Option<String>,
+        /// Only print unified diffs of the changes, ready for `git apply`
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Report files added, removed, grown, shrunk or modified between two
+    /// stored generations of the index
+    Diff {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Compare from the generation that was current at this time (e.g.
+        /// 7d or 2024-01-01); defaults to the one before --to
+        #[arg(long, value_name = "TIME", value_parser = units::parse_time)]
+        from: Option<SystemTime>,
+        /// Compare to the generation that was current at this time;
+        /// defaults to the current one
+        #[arg(long, value_name = "TIME", value_parser = units::parse_time)]
+        to: Option<SystemTime>,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// List the files matched by only one of two queries
+    DiffResults {
+        /// First query
+        first: String,
+        /// Second query
+        second: String,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Compare files whose contents match the patterns, as `grep` does
+        #[arg(long)]
+        grep: bool,
+        /// Treat the patterns as literal strings (with --grep)
+        #[arg(short = 'F', long, requires = "grep")]
+        fixed_strings: bool,
+        /// Match case-insensitively (with --grep)
+        #[arg(long, requires = "grep")]
+        ignore_case: bool,
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Print the most frecent visited directory whose path contains the
+    /// keywords; `ss jump --init bash` sets up the shell side
+    Jump {
+        /// Fragments of the path, in order
+        keywords: Vec<String>,
+        /// Record a visit to this directory, as the shell hook does
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["keywords", "list", "import", "init"]
+        )]
+        add: Option<PathBuf>,
+        /// List the remembered directories matching the keywords, with
+        /// their frecency scores
+        #[arg(long)]
+        list: bool,
+        /// Add the rankings of another tool to the history
+        #[arg(long, value_enum, value_name = "TOOL", conflicts_with_all = ["keywords", "list", "init"])]
+        import: Option<JumpSource>,
+        /// Database to import instead of the tool's default one
+        #[arg(long, value_name = "FILE", requires = "import")]
+        from: Option<PathBuf>,
+        /// Print the hook recording directory changes and defining `j`
+        #[arg(long, value_enum, value_name = "SHELL", conflicts_with_all = ["keywords", "list"])]
+        init: Option<JumpShell>,
+    },
+    /// List the latest find and grep searches of an index, most recent first
+    History {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// List the searches saved with `ss save-search` instead
+        #[arg(long)]
+        saved: bool,
+        /// Show at most this many searches
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Forget the latest searches; saved ones are kept
+        #[arg(long, conflicts_with = "saved")]
+        clear: bool,
+    },
+    /// Save a find query and its options under a name, to run again with
+    /// `ss find --saved <name>`
+    SaveSearch {
+        /// Name to run it by
+        name: String,
+        /// Search query, as for `find`
+        #[arg(required_unless_present = "delete")]
+        query: Option<String>,
+        /// More `find` options to save with it, after `--`, e.g.
+        /// `-- --ext log --modified-within 7d`
+        #[arg(last = true, value_name = "FIND_OPTIONS")]
+        options: Vec<String>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Forget the search saved under this name
+        #[arg(long, conflicts_with_all = ["query", "options"])]
+        delete: bool,
+    },
+    /// Attach labels to indexed files, to pick them out with `find --tag`
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// Work on a stored index as a whole
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+    /// Tools for performance work
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommand,
+    },
+    /// Run other tools' command lines against the index
+    Compat {
+        #[command(subcommand)]
+        command: CompatCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// Write a copy of the index with every name hashed and no contents,
+    /// keeping the tree's shape and sizes, to share when reporting a bug
+    Scrub {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// Index directory to write the scrubbed copy to
+        #[arg(short = 'o', long = "out", value_name = "DIR")]
+        out: PathBuf,
+    },
+    /// Rebuild an index written by another version of sonic-search in this
+    /// version's format, by scanning its root again (same as `ss migrate`)
+    Migrate {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    /// Attach a tag to files
+    Add {
+        tag: String,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// Take a tag off files
+    Remove {
+        tag: String,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+    /// List the tags and how many files carry each, or the files with a tag
+    List {
+        tag: Option<String>,
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CompatCommand {
+    /// List files like `fd`, taking its flags after `--` (e.g. `ss compat fd
+    /// -- -e rs -d 2 main src`); also runs when `ss` is started as `ssfd`
+    Fd {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long)]
+        index_dir: Option<PathBuf>,
+        /// fd's pattern, paths and flags: -e, -t, -H, -I, -u, -d, -s, -i, -g,
+        /// -F, -p, -E, -S, -a, -0, -1 and friends
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "FD_ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BenchCommand {
+    /// Write a synthetic directory tree, the same every time for the same
+    /// options and seed, to scan and search in benchmarks
+    Generate {
+        /// Number of files (e.g. 5000, 250k, 1M)
+        #[arg(long, value_parser = units::parse_count, default_value = "100k")]
+        files: usize,
+        /// How deep directories nest
+        #[arg(long, default_value_t = 8)]
+        depth: usize,
+        /// Files per directory on average
+        #[arg(long, default_value_t = 50)]
+        files_per_dir: usize,
+        /// What files are named like
+        #[arg(long, value_enum, default_value_t = FixtureNames::Mixed)]
+        names: FixtureNames,
+        /// Fill files with text (binary noise for media) instead of leaving
+        /// them empty
+        #[arg(long)]
+        content: bool,
+        /// Largest file written with --content; most are much smaller
+        #[arg(long, value_parser = units::parse_size, default_value = "4K", requires = "content")]
+        max_size: u64,
+        /// Seed for the names, layout, sizes and dates
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Directory to create the tree in; must not exist or be empty
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Time scanning, loading and searching the index, for a report that
+    /// compares across machines and filesystems
+    Run {
+        /// Path to the index directory (defaults to the workspace's index)
+        #[arg(short, long, conflicts_with = "synthetic")]
+        index_dir: Option<PathBuf>,
+        /// Measure a synthetic tree of this many files instead, generated
+        /// as `bench generate` does and removed afterwards
+        #[arg(long, value_parser = units::parse_count, value_name = "FILES")]
+        synthetic: Option<usize>,
+        /// Timed runs of each measurement, after one to warm up
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        runs: u64,
+        /// Leave out walking the indexed root
+        #[arg(long)]
+        no_scan: bool,
+        /// Name search to time (repeatable); defaults to a broad query and
+        /// the name of one indexed file
+        #[arg(short, long = "query", value_name = "QUERY")]
+        queries: Vec<String>,
+        /// Content search to time (repeatable)
+        #[arg(short, long, value_name = "PATTERN")]
+        grep: Vec<String>,
+    },
+}
+
+impl Commands {
+    /// Whether the command lists results that can run to pages, which are
+    /// then shown through a pager. Commands that prompt, stream or run a
+    /// UI write straight to the terminal.
+    fn is_paged(&self) -> bool {
+        match self {
+            Commands::Find {
+                interactive,
+                copy_to,
+                move_to,
+                chmod,
+                chown,
+                touch,
+                set_readonly,
+                clear_hidden,
+                exec,
+                exec_batch,
+                open,
+                reveal,
+                ..
+            } => {
+                !interactive
+                    && !open
+                    && !reveal
+                    && exec.is_none()
+                    && exec_batch.is_none()
+                    && copy_to.is_none()
+                    && move_to.is_none()
+                    && chmod.is_none()
+                    && chown.is_none()
+                    && !touch
+                    && !set_readonly
+                    && !clear_hidden
+            }
+            Commands::Grep {
+                follow,
+                open,
+                reveal,
+                ..
+            } => !follow && !open && !reveal,
+            Commands::Replace { dry_run, .. } => *dry_run,
+            Commands::Stats { .. }
+            | Commands::Projects { .. }
+            | Commands::Count { .. }
+            | Commands::Locate { .. }
+            | Commands::Syntax { .. }
+            | Commands::Du { .. }
+            | Commands::Dupes { .. }
+            | Commands::SameAs { .. }
+            | Commands::NameCollisions { .. }
+            | Commands::Audit { .. }
+            | Commands::Configs { .. }
+            | Commands::Diff { .. }
+            | Commands::DiffResults { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// What `count` groups files by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CountBy {
+    /// File extension
+    Ext,
+    /// Sub-project (Cargo, npm, Bazel)
+    Project,
+    /// Top-level directory
+    Dir,
+}
+
+impl From<CountBy> for count::GroupBy {
+    fn from(by: CountBy) -> Self {
+        match by {
+            CountBy::Ext => count::GroupBy::Extension,
+            CountBy::Project => count::GroupBy::Project,
+            CountBy::Dir => count::GroupBy::Directory,
+        }
+    }
+}
+
+/// Tools whose directory rankings `jump --import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum JumpSource {
+    Zoxide,
+    Autojump,
+}
+
+impl From<JumpSource> for jump::Source {
+    fn from(source: JumpSource) -> Self {
+        match source {
+            JumpSource::Zoxide => jump::Source::Zoxide,
+            JumpSource::Autojump => jump::Source::Autojump,
+        }
+    }
+}
+
+/// Formats `export` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Parquet,
+}
+
+impl From<ExportFormat> for export::Format {
+    fn from(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Csv => export::Format::Csv,
+            ExportFormat::Json => export::Format::Json,
+            ExportFormat::Ndjson => export::Format::Ndjson,
+            ExportFormat::Parquet => export::Format::Parquet,
+        }
+    }
+}
+
+/// Storage backends `scan --backend` offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StorageBackend {
+    Binary,
+    Sqlite,
+}
+
+impl From<StorageBackend> for storage::Backend {
+    fn from(backend: StorageBackend) -> Self {
+        match backend {
+            StorageBackend::Binary => storage::Backend::Binary,
+            StorageBackend::Sqlite => storage::Backend::Sqlite,
+        }
+    }
+}
+
+/// Matching engines `find --matcher` offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MatcherEngine {
+    Fuzzy,
+    Exact,
+    Prefix,
+    Regex,
+    #[cfg(feature = "aligned-matcher")]
+    Aligned,
+}
+
+impl From<MatcherEngine> for engine::Engine {
+    fn from(matcher: MatcherEngine) -> Self {
+        match matcher {
+            MatcherEngine::Fuzzy => engine::Engine::Fuzzy,
+            MatcherEngine::Exact => engine::Engine::Exact,
+            MatcherEngine::Prefix => engine::Engine::Prefix,
+            MatcherEngine::Regex => engine::Engine::Regex,
+            #[cfg(feature = "aligned-matcher")]
+            MatcherEngine::Aligned => engine::Engine::Aligned,
+        }
+    }
+}
+
+/// Databases `import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ImportFormat {
+    Plocate,
+    Mlocate,
+    Everything,
+}
+
+impl From<ImportFormat> for import::Format {
+    fn from(format: ImportFormat) -> Self {
+        match format {
+            ImportFormat::Plocate => import::Format::Plocate,
+            ImportFormat::Mlocate => import::Format::Mlocate,
+            ImportFormat::Everything => import::Format::Everything,
+        }
+    }
+}
+
+/// Shells `jump --init` prints a hook for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum JumpShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl From<JumpShell> for jump::Shell {
+    fn from(shell: JumpShell) -> Self {
+        match shell {
+            JumpShell::Bash => jump::Shell::Bash,
+            JumpShell::Zsh => jump::Shell::Zsh,
+            JumpShell::Fish => jump::Shell::Fish,
+        }
+    }
+}
+
+/// Entry types `--type` picks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EntryKind {
+    /// Files, including links to files
+    #[value(name = "f", alias = "file")]
+    File,
+    /// Directories, including links to directories
+    #[value(name = "d", alias = "directory")]
+    Directory,
+    /// Symbolic links (indexed by `scan --follow-symlinks`)
+    #[value(name = "l", alias = "symlink")]
+    Symlink,
+}
+
+impl From<EntryKind> for sonic_search::EntryType {
+    fn from(kind: EntryKind) -> Self {
+        match kind {
+            EntryKind::File => sonic_search::EntryType::File,
+            EntryKind::Directory => sonic_search::EntryType::Directory,
+            EntryKind::Symlink => sonic_search::EntryType::Symlink,
+        }
+    }
+}
+
+/// What `--type` names: a kind of entry, or a file type such as `rust`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TypeArg {
+    Entry(EntryKind),
+    File(String),
+}
+
+fn parse_type(input: &str) -> Result<TypeArg, String> {
+    if let Ok(kind) = EntryKind::from_str(input, true) {
+        return Ok(TypeArg::Entry(kind));
+    }
+    let name = input.to_ascii_lowercase();
+    if filetype::is_type(&name) {
+        return Ok(TypeArg::File(name));
+    }
+    Err(match suggest::closest(input, filetype::type_names()) {
+        Some(known) => format!("unknown type '{}'. Did you mean '{}'?", input, known),
+        None => format!(
+            "unknown type '{}': expected f, d, l or one of {}",
+            input,
+            filetype::type_names().collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// What `find --copy-to` and `--move-to` do when the destination name is taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OnConflict {
+    /// Leave the match where it is
+    #[default]
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Use a free name such as 'photo (1).jpg'
+    Rename,
+}
+
+impl From<OnConflict> for transfer::Conflict {
+    fn from(policy: OnConflict) -> Self {
+        match policy {
+            OnConflict::Skip => transfer::Conflict::Skip,
+            OnConflict::Overwrite => transfer::Conflict::Overwrite,
+            OnConflict::Rename => transfer::Conflict::Rename,
+        }
+    }
+}
+
+/// How `find` and `grep` order their results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// Best fuzzy match first (for grep, index order)
+    #[default]
+    Score,
+    /// By file name, then path, A to Z
+    Name,
+    /// Largest first
+    Size,
+    /// Most recently modified first
+    Mtime,
+    /// Most recently committed first; uncommitted files last
+    LastCommit,
+}
+
+/// What `bench generate` names files like
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FixtureNames {
+    /// Source files, like `lexer_utils.rs`
+    Code,
+    /// Office documents, some with accented, Cyrillic or CJK names
+    Documents,
+    /// Photos and recordings, like `IMG_0412.jpg`
+    Media,
+    /// A third of each
+    Mixed,
+}
+
+impl From<FixtureNames> for fixture::NameStyle {
+    fn from(names: FixtureNames) -> Self {
+        match names {
+            FixtureNames::Code => fixture::NameStyle::Code,
+            FixtureNames::Documents => fixture::NameStyle::Documents,
+            FixtureNames::Media => fixture::NameStyle::Media,
+            FixtureNames::Mixed => fixture::NameStyle::Mixed,
+        }
+    }
+}
+
+/// How `--sort name` compares names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Collation {
+    /// By Unicode code point, so `Zebra` comes before `apple`
+    #[default]
+    Codepoint,
+    /// As is usual for the locale in LC_ALL, LC_COLLATE or LANG, ignoring
+    /// case and accents at first and with `file2` before `file10`
+    Locale,
+}
+
+/// Result ordering and limiting shared by `find` and `grep`
+#[derive(Args, Debug, Clone, Copy, Default)]
+struct OrderArgs {
+    /// Show at most this many results (for grep, matching lines)
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Order results by this key
+    #[arg(long, value_enum, default_value_t = SortOrder::Score)]
+    sort: SortOrder,
+    /// How to compare names for `--sort name`
+    #[arg(long, value_enum, default_value_t = Collation::Codepoint)]
+    collate: Collation,
+    /// Reverse the sort order
+    #[arg(long)]
+    reverse: bool,
+    /// Print results as they're found, in no particular order, instead of
+    /// waiting to sort them all
+    #[arg(long, conflicts_with_all = ["sort", "reverse", "collate"])]
+    no_sort: bool,
+}
+
+impl OrderArgs {
+    /// Whether results come out of the search already in order, so the
+    /// limit can be applied while searching
+    fn is_natural(&self) -> bool {
+        self.sort == SortOrder::Score && !self.reverse
+    }
+
+    /// Sort `items` by the file each refers to, stably so ties keep their
+    /// previous order. `commits` is only needed for `last-commit`.
+    fn sort<'e, T>(
+        &self,
+        items: &mut [T],
+        commits: &HashMap<PathBuf, git::LastCommit>,
+        entry: impl Fn(&T) -> &'e FileEntry,
+    ) -> Result<()> {
+        use std::cmp::Reverse;
+        match self.sort {
+            // Searches return results best first already
+            SortOrder::Score => {}
+            SortOrder::Name if self.collate == Collation::Locale => {
+                let collator = collate::Collator::system()?;
+                items.sort_by(|a, b| {
+                    let (a, b) = (entry(a), entry(b));
+                    collator
+                        .compare(&a.name, &b.name)
+                        .then_with(|| a.path.cmp(&b.path))
+                })
+            }
+            SortOrder::Name => items.sort_by(|a, b| {
+                let (a, b) = (entry(a), entry(b));
+                a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path))
+            }),
+            SortOrder::Size => items.sort_by_key(|item| Reverse(entry(item).size)),
+            SortOrder::Mtime => items.sort_by_key(|item| Reverse(entry(item).modified)),
+            SortOrder::LastCommit => items.sort_by_key(|item| {
+                Reverse(commits.get(&entry(item).path).map(|commit| commit.time))
+            }),
+        }
+        if self.reverse {
+            items.reverse();
+        }
+        Ok(())
+    }
+}
+
+/// How `find` orders and decorates its results
+#[derive(Debug, Clone, Default)]
+struct FindDisplay {
+    order: OrderArgs,
+    /// Reorder the best matches with an external command
+    rerank: Option<Rerank>,
+    /// Look up the last commit of every result
+    git: bool,
+    /// Highlight the matched characters
+    color: bool,
+    /// Show modification times, written this way
+    dates: Option<units::DateStyle>,
+    /// Link paths to the files, in this format
+    links: Option<HyperlinkFormat>,
+    listing: output::Listing,
+    /// Print each result as this template instead
+    template: Option<template::Template>,
+}
+
+/// An external command reordering `find` results, from `--rerank-cmd`
+#[derive(Debug, Clone)]
+struct Rerank {
+    command: String,
+    /// How many of the best matches it's given
+    candidates: usize,
+}
+
+/// How `grep` orders and decorates its results
+#[derive(Debug, Clone, Default)]
+struct GrepDisplay {
+    order: OrderArgs,
+    color: bool,
+    lines: LineArgs,
+    listing: output::Listing,
+    /// Print each match as this template instead
+    template: Option<template::Template>,
+    /// Link paths to the matching lines, in this format
+    links: Option<HyperlinkFormat>,
+    /// Open or reveal the matching files after printing them
+    launch: Option<launch::Action>,
+}
+
+/// How `grep` prints its matches
+#[derive(Args, Debug, Clone, Default)]
+struct LineArgs {
+    /// Print the number of each line; the default, except with -o
+    #[arg(short = 'n', long, overrides_with = "no_line_number")]
+    line_number: bool,
+    /// Leave out line numbers
+    #[arg(short = 'N', long)]
+    no_line_number: bool,
+    /// Print the 1-based byte column of the first match in each line
+    #[arg(long)]
+    column: bool,
+    /// Print the byte offset within the file of the first match in each line
+    #[arg(short = 'b', long)]
+    byte_offset: bool,
+    /// Print only the matched text, each match on its own line
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+    /// Leave out the `path:` before each line
+    #[arg(long)]
+    no_filename: bool,
+    /// Print every match replaced by this, where `$1` or `${name}` stand for
+    /// capture groups; files are left alone
+    #[arg(short = 'r', long, value_name = "TEMPLATE")]
+    replace: Option<String>,
+    /// Print each distinct line only once
+    #[arg(long)]
+    unique: bool,
+    /// Print only the number of matching lines in each file
+    #[arg(short = 'c', long, conflicts_with_all = ["only_matching", "files_with_matches"])]
+    count: bool,
+    /// Print only the paths of the matching files
+    #[arg(short = 'l', long, conflicts_with = "only_matching")]
+    files_with_matches: bool,
+    #[command(flatten)]
+    context: ContextArgs,
+}
+
+/// Lines `grep` shows around each match, as ripgrep does
+#[derive(Args, Debug, Clone, Copy, Default)]
+struct ContextArgs {
+    /// Show this many lines after each match
+    #[arg(short = 'A', long, value_name = "N")]
+    after_context: Option<usize>,
+    /// Show this many lines before each match
+    #[arg(short = 'B', long, value_name = "N")]
+    before_context: Option<usize>,
+    /// Show this many lines before and after each match
+    #[arg(short = 'C', long, value_name = "N")]
+    context: Option<usize>,
+}
+
+impl From<ContextArgs> for grep::ContextLines {
+    fn from(args: ContextArgs) -> Self {
+        grep::ContextLines {
+            before: args.before_context.or(args.context).unwrap_or(0),
+            after: args.after_context.or(args.context).unwrap_or(0),
+        }
+    }
+}
+
+/// What `grep` has printed so far, to leave out repeats and separate
+/// groups of context lines
+#[derive(Debug, Default)]
+struct Printed {
+    /// Lines printed, for `--unique`
+    seen: HashSet<String>,
+    /// Number of the last line printed from the current file
+    last_line: Option<usize>,
+    any: bool,
+}
+
+impl LineArgs {
+    /// The `path:` and `line:` fields before a line, joined by `separator`
+    fn prefix(&self, path: &str, line_number: usize, separator: char) -> String {
+        let mut prefix = String::new();
+        if !self.no_filename {
+            prefix.push_str(&format!("{}{}", path, separator));
+        }
+        let numbered = match self.only_matching {
+            true => self.line_number,
+            false => !self.no_line_number,
+        };
+        if numbered {
+            prefix.push_str(&format!("{}{}", line_number, separator));
+        }
+        prefix
+    }
+
+    /// The text lines to print for `m`, found by `pattern` in the file
+    /// labelled `path`
+    fn render(
+        &self,
+        path: &str,
+        m: &grep::LineMatch,
+        pattern: &regex::bytes::Regex,
+        color: bool,
+    ) -> Vec<String> {
+        let prefix = self.prefix(path, m.line_number, ':');
+        let shown = match &self.replace {
+            Some(template) => grep::replace_matches(m, pattern, template),
+            None => m.clone(),
+        };
+        if !self.only_matching {
+            let first = m.spans.first().map_or(0, |span| span.start);
+            return vec![format!(
+                "{}{}{}",
+                prefix,
+                self.positions(m, first),
+                grep::highlight(&shown.line, &shown.spans, color)
+            )];
+        }
+        // Positions are of the matches as found, before any replacement
+        m.spans
+            .iter()
+            .zip(&shown.spans)
+            .filter_map(|(found, span)| {
+                let text = shown.line.get(span.clone())?;
+                let whole = 0..text.len();
+                Some(format!(
+                    "{}{}{}",
+                    prefix,
+                    self.positions(m, found.start),
+                    grep::highlight(text, std::slice::from_ref(&whole), color)
+                ))
+            })
+            .collect()
+    }
+
+    /// Print `m` with its context lines, starting with `--` when they don't
+    /// continue what was printed before
+    fn print(
+        &self,
+        path: &str,
+        m: &grep::LineMatch,
+        pattern: &regex::bytes::Regex,
+        color: bool,
+        printed: &mut Printed,
+    ) {
+        let context = !self.only_matching && !grep::ContextLines::from(self.context).is_empty();
+        let first = m.line_number - m.before.len();
+        if context && printed.any && printed.last_line.is_none_or(|last| first > last + 1) {
+            println!("--");
+        }
+        if context {
+            for (i, line) in m.before.iter().enumerate() {
+                println!("{}{}", self.prefix(path, first + i, '-'), line);
+            }
+        }
+        for line in self.render(path, m, pattern, color) {
+            if !self.unique || printed.seen.insert(line.clone()) {
+                println!("{}", line);
+            }
+        }
+        if context {
+            for (i, line) in m.after.iter().enumerate() {
+                println!("{}{}", self.prefix(path, m.line_number + 1 + i, '-'), line);
+            }
+        }
+        printed.any = true;
+        printed.last_line = Some(m.line_number + m.after.len());
+    }
+
+    /// The `column:offset:` fields asked for, of the match at `start` in `m`
+    fn positions(&self, m: &grep::LineMatch, start: usize) -> String {
+        let mut prefix = String::new();
+        if self.column {
+            prefix.push_str(&format!("{}:", start + 1));
+        }
+        if self.byte_offset {
+            prefix.push_str(&format!("{}:", m.offset + start as u64));
+        }
+        prefix
+    }
+}
+
+/// Case matching shared by `find` and `grep`: smart case, ignoring case
+/// unless the query has an uppercase letter, when neither flag is given
+#[derive(Args, Debug, Clone, Copy, Default)]
+struct CaseArgs {
+    /// Match case exactly, even in an all-lowercase query
+    #[arg(long, conflicts_with = "ignore_case")]
+    case_sensitive: bool,
+    /// Match case-insensitively, even when the query has uppercase letters
+    #[arg(long)]
+    ignore_case: bool,
+}
+
+impl From<CaseArgs> for CaseMode {
+    fn from(args: CaseArgs) -> Self {
+        match (args.case_sensitive, args.ignore_case) {
+            (true, _) => CaseMode::Sensitive,
+            (_, true) => CaseMode::Insensitive,
+            _ => CaseMode::Smart,
+        }
+    }
+}
+
+/// Entry filters shared by `find` and `grep`
+#[derive(Args, Debug, Default)]
+struct FilterArgs {
+    /// Only search files matching this glob, e.g. '*.rs' or 'src/**' (repeatable)
+    #[arg(long = "glob", value_name = "GLOB")]
+    globs: Vec<String>,
+    /// Only search files with these extensions, e.g. rs,toml
+    #[arg(long = "ext", value_name = "EXT", value_delimiter = ',')]
+    extensions: Vec<String>,
+    /// Only search files inside this sub-project, by name or path (repeatable)
+    #[arg(long = "project", value_name = "NAME")]
+    projects: Vec<String>,
+    /// Only search files owned by this CODEOWNERS owner, e.g. @org/team (repeatable)
+    #[arg(long = "owner", value_name = "OWNER")]
+    owners: Vec<String>,
+    /// Only search files of this size: +10M (at least), -1k (at most) or exact (repeatable)
+    #[arg(long = "size", value_name = "SIZE", value_parser = units::parse_size_filter, allow_hyphen_values = true)]
+    sizes: Vec<units::SizeFilter>,
+    /// Only search files modified within this long (7d) or since this date (2024-01-01)
+    #[arg(long, value_name = "WHEN", value_parser = units::parse_time)]
+    modified_within: Option<SystemTime>,
+    /// Only search files modified before this date (2024-01-01) or longer ago than this (30d)
+    #[arg(long, value_name = "WHEN", value_parser = units::parse_time)]
+    modified_before: Option<SystemTime>,
+    /// Include files in git submodules and vendor directories (vendor/, third_party/, ...)
+    #[arg(long)]
+    include_vendored: bool,
+    /// Only files with this content hash, or a prefix of it (needs `scan --hash`)
+    #[arg(long, value_name = "DIGEST")]
+    hash: Option<String>,
+    /// Only entries owned by this user, by name or uid (repeatable, Unix)
+    #[arg(long = "user", value_name = "USER")]
+    users: Vec<String>,
+    /// Only entries whose group is this one, by name or gid (repeatable, Unix)
+    #[arg(long = "group", value_name = "GROUP")]
+    groups: Vec<String>,
+    /// Only entries whose owner isn't a known user, such as files of deleted accounts
+    #[arg(long)]
+    nouser: bool,
+    /// Only entries whose group isn't a known group
+    #[arg(long)]
+    nogroup: bool,
+    /// Only entries with these permission bits: 644 (exactly), -022 (all of them) or /111 (any)
+    #[arg(long = "perm", value_name = "MODE", value_parser = perms::PermFilter::parse, allow_hyphen_values = true)]
+    perms: Vec<perms::PermFilter>,
+    /// Only entries anyone may write to, like --perm -002
+    #[arg(long)]
+    world_writable: bool,
+    /// Only entries nobody may write to
+    #[arg(long)]
+    readonly: bool,
+    /// Only entries of this type: f (file), d (directory), l (symlink), a file type
+    /// such as rust or md, or code, config, document, image, audio, video or archive (repeatable)
+    #[arg(short = 't', long = "type", value_name = "TYPE", value_parser = parse_type)]
+    types: Vec<TypeArg>,
+    /// Only files of this MIME type, e.g. application/pdf or image/* (repeatable)
+    #[arg(long = "mime", value_name = "TYPE")]
+    mimes: Vec<String>,
+    /// Only search files git tracks, as of the last scan or update
+    #[arg(long)]
+    git_tracked: bool,
+    /// Only search tracked files with uncommitted changes, as of the last scan or update
+    #[arg(long)]
+    git_modified: bool,
+    /// Only search files inside this git repository, by path or name (repeatable)
+    #[arg(long = "repo", value_name = "PATH")]
+    repos: Vec<String>,
+    /// Only search files matching this query, e.g. 'ext:rs AND NOT path:tests'
+    #[arg(long = "where", value_name = "QUERY")]
+    where_query: Option<String>,
+}
+
+/// The filters given on the command line, falling back to the config
+/// file's `[filters]` for those that aren't
+impl From<FilterArgs> for Filters {
+    fn from(args: FilterArgs) -> Self {
+        let defaults = &Config::get().filters;
+        let or_default = |given: Vec<String>, default: &[String]| match given.is_empty() {
+            true => default.to_vec(),
+            false => given,
+        };
+        let (mut types, mut file_types) = (Vec::new(), Vec::new());
+        for arg in args.types {
+            match arg {
+                TypeArg::Entry(kind) => types.push(kind.into()),
+                TypeArg::File(name) => file_types.push(name),
+            }
+        }
+        Filters {
+            globs: or_default(args.globs, &defaults.glob),
+            extensions: or_default(args.extensions, &defaults.ext),
+            projects: args.projects,
+            owners: args.owners,
+            sizes: args.sizes,
+            modified_after: args.modified_within,
+            modified_before: args.modified_before,
+            include_vendored: args.include_vendored || defaults.include_vendored,
+            hash: args.hash,
+            users: args.users,
+            groups: args.groups,
+            no_user: args.nouser,
+            no_group: args.nogroup,
+            perms: match args.world_writable {
+                true => [args.perms, vec![perms::PermFilter::AllOf(0o002)]].concat(),
+                false => args.perms,
+            },
+            readonly: args.readonly,
+            types,
+            file_types,
+            mimes: args.mimes,
+            git_tracked: args.git_tracked,
+            git_modified: args.git_modified,
+            // Paths are given relative to where ss runs, and stored absolute
+            repos: args
+                .repos
+                .into_iter()
+                .map(|repo| match dunce::canonicalize(&repo) {
+                    Ok(path) => path.to_string_lossy().into_owned(),
+                    Err(_) => repo,
+                })
+                .collect(),
+            query: args.where_query.or_else(|| defaults.where_query.clone()),
+            tagged: None,
+        }
+    }
+}
+
+/// Exit like grep: 0 when something matched, 1 when nothing did and 2 on
+/// errors, see [`exit_code`]
+fn main() -> std::process::ExitCode {
+    // Dynamic completion scripts call back in here; nothing may print before
+    completions::handle_request(Cli::command);
+    match try_main() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            if !e.is::<NoMatches>() {
+                eprintln!("Error: {:?}", e);
+            }
+            std::process::ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// The status `ss` exits with after `error`: 1 when nothing matched and 2
+/// for any error, whatever its [`sonic_search::error::ErrorKind`], so
+/// scripts can rely on grep's statuses
+fn exit_code(error: &anyhow::Error) -> u8 {
+    match error.is::<NoMatches>() {
+        true => 1,
+        false => 2,
+    }
+}
+
+/// Returned by `find` and `grep` when nothing matched, so `ss` exits with
+/// status 1 without reporting an error
+#[derive(Debug)]
+struct NoMatches;
+
+impl std::fmt::Display for NoMatches {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("No matches")
+    }
+}
+
+impl std::error::Error for NoMatches {}
+
+fn try_main() -> Result<()> {
+    quarantine::enable_rescan();
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let config = match Config::path_from_args(&args) {
+        Some(path) => Config::load_from(&path)?,
+        None => Config::load()?,
+    }
+    .install();
+    let args = config.prepare_args(
+        compat::expand_alias(args),
+        &builtin_commands(),
+        &global_options(),
+    )?;
+    let mut cli = Cli::parse_from(&args);
+    if let Commands::Find {
+        saved: Some(name),
+        index_dir,
+        index,
+        all_indexes,
+        ..
+    } = &cli.command
+    {
+        let index_dirs = match (index, all_indexes) {
+            (Some(name), _) => Registry::load().profile(name)?.indexes.clone(),
+            (None, true) => Registry::load()
+                .indexes
+                .into_iter()
+                .map(|known| known.path)
+                .collect(),
+            (None, false) if index_dir.is_empty() => vec![resolve_index_dir(None)?],
+            (None, false) => index_dir.clone(),
+        };
+        let saved = saved_search(&index_dirs, name)?;
+        cli = Cli::parse_from(searches::splice_saved(&args, &saved));
+    }
+    cli.args = args;
+    if cli.hyperlink_format.is_none()
+        && let Some(format) = &config.hyperlink_format
+    {
+        let format = HyperlinkFormat::parse(format).context("Invalid config file")?;
+        cli.hyperlink_format = Some(format);
+    }
+    cli.color = cli.color.or(config.color);
+    cli.ranking = config.ranking;
+    run(cli)
+}
+
+/// Names and aliases of every built-in subcommand
+fn builtin_commands() -> Vec<String> {
+    let mut names = vec!["help".to_string()];
+    for command in Cli::command().get_subcommands() {
+        names.push(command.get_name().to_string());
+        names.extend(command.get_all_aliases().map(str::to_string));
+    }
+    names
+}
+
+/// The options `ss` takes before its subcommand, so aliases and the default
+/// command are found after them
+fn global_options() -> Vec<config::GlobalOption> {
+    let mut options = Vec::new();
+    for arg in Cli::command().get_arguments() {
+        let takes_value = arg.get_action().takes_values();
+        let longs = arg.get_long().map(|long| format!("--{}", long));
+        let shorts = arg.get_short().map(|short| format!("-{}", short));
+        options.extend(
+            longs
+                .into_iter()
+                .chain(shorts)
+                .map(|name| config::GlobalOption { name, takes_value }),
+        );
+    }
+    options
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let Some(profile_path) = cli.profile.clone() else {
+        return run_command(cli);
+    };
+    profile::enable();
+    let result = {
+        let _span = profile::span("ss");
+        run_command(cli)
+    };
+    profile::write(&profile_path)?;
+    eprintln!("⏱️  Profile written to {}", profile_path.display());
+    result
+}
+
+fn run_command(mut cli: Cli) -> Result<()> {
+    let args = std::mem::take(&mut cli.args);
+    let format = cli.output;
+    let color = cli.color.unwrap_or_default().enabled();
+    // A template replaces the decorated listing even in a terminal
+    let listing = match (&cli.format_template, output::Listing::detect(cli.print0)) {
+        (Some(_), output::Listing::Decorated) => output::Listing::Bare,
+        (_, listing) => listing,
+    };
+    let template = cli.format_template.clone();
+    let links = cli
+        .hyperlinks
+        .enabled()
+        .then(|| cli.hyperlink_format.unwrap_or_default());
+    let dates = cli.date_format.map(|format| units::DateStyle {
+        format,
+        utc: cli.utc,
+    });
+    // Started after colors are decided, which looks at the real stdout
+    let _pager = match format.is_text() && !cli.no_pager && cli.command.is_paged() {
+        true => pager::Pager::start(),
+        false => None,
+    };
+    match cli.command {
+        Commands::Scan {
+            paths,
+            index_dir,
+            name,
+            incremental,
+            content,
+            max_content_size,
+            content_memory,
+            excludes,
+            hash,
+            compress,
+            threads,
+            max_depth,
+            max_filesize,
+            hidden,
+            no_ignore,
+            no_git_ignore,
+            ignore_files,
+            follow_symlinks,
+            one_file_system,
+            skip_network,
+            archives,
+            keep_generations,
+            transliterate,
+            sniff_mime,
+            backend,
+            fulltext,
+            strip_comments,
+            include_build_outputs,
+            max_index_size,
+        } => {
+            if fulltext && !cfg!(feature = "fulltext") {
+                anyhow::bail!(
+                    "--fulltext isn't available: sonic-search was built without the `fulltext` feature"
+                );
+            }
+            // Stored absolute so updates find them from any directory
+            let ignore_files = ignore_files
+                .iter()
+                .map(|file| {
+                    dunce::canonicalize(file)
+                        .with_context(|| format!("Ignore file not found: {}", file.display()))
+                })
+                .collect::<Result<_>>()?;
+            let options = scanner::ScanOptions {
+                excludes,
+                hash,
+                compress,
+                threads: threads.map(NonZeroUsize::get),
+                max_depth,
+                max_filesize,
+                hidden,
+                no_ignore,
+                no_git_ignore,
+                ignore_files,
+                global_ignore: match no_ignore {
+                    true => None,
+                    false => Config::global_ignore_path(),
+                },
+                follow_symlinks,
+                one_file_system,
+                skip_network,
+                content_memory,
+                archives,
+                keep_generations: keep_generations.map(NonZeroUsize::get),
+                transliterate,
+                sniff_mime,
+                backend: backend.map(Into::into).unwrap_or_default(),
+                fulltext,
+                strip_comments,
+                skip_build_outputs: match include_build_outputs {
+                    true => BuildSystems::NONE,
+                    false => Config::get().skip_build_outputs,
+                },
+                max_index_size: match max_index_size {
+                    Some(size) => Some(size),
+                    None => Config::get().max_index_size()?,
+                },
+            };
+            limit_threads(options.threads);
+            let content = content.then_some(max_content_size);
+            if let Some(name) = name {
+                let data_dir = registry::data_dir()
+                    .context("No data directory to keep profiles in; set $SONIC_SEARCH_DATA_DIR")?;
+                return scan_profile(&name, &paths, &data_dir, &options, incremental, content);
+            }
+            let [path] = paths.as_slice() else {
+                anyhow::bail!("Scanning several roots needs --name to group them into a profile");
+            };
+            let index_dir = resolve_index_dir(index_dir)?;
+            scan_root(path, &index_dir, &options, incremental, content)
+        }
+        Commands::Init { yes } => setup(yes),
+        Commands::Completions { shell, dynamic } => {
+            completions::write_script(&mut Cli::command(), shell, dynamic, &mut std::io::stdout())
+        }
+        Commands::Update {
+            index_dir,
+            older_than,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let mut index = Index::load(&index_dir)?;
+            if let Some(max_age) = older_than {
+                let age = index.meta.updated_at.elapsed().unwrap_or_default();
+                if age < max_age {
+                    println!(
+                        "✅ Index was updated {} s ago; nothing to do.",
+                        age.as_secs()
+                    );
+                    return Ok(());
+                }
+            }
+            limit_threads(index.meta.scan_options.threads);
+            index.load_content(&index_dir)?;
+            update_index(index, &index_dir)
+        }
+        Commands::Bench {
+            command:
+                BenchCommand::Generate {
+                    files,
+                    depth,
+                    files_per_dir,
+                    names,
+                    content,
+                    max_size,
+                    seed,
+                    out,
+                },
+        } => {
+            let options = fixture::FixtureOptions {
+                files,
+                depth,
+                files_per_dir,
+                names: names.into(),
+                contents: match content {
+                    true => fixture::Contents::Text,
+                    false => fixture::Contents::Empty,
+                },
+                max_size,
+                seed,
+            };
+            let start = Instant::now();
+            let generated = fixture::generate(&out, &options)?;
+            println!(
+                "🏗️  Generated {} files in {} directories ({}) under {} in {:.1} s",
+                generated.files,
+                generated.dirs,
+                scanner::format_size(generated.bytes),
+                out.display(),
+                start.elapsed().as_secs_f64()
+            );
+            Ok(())
+        }
+        Commands::Bench {
+            command:
+                BenchCommand::Run {
+                    index_dir,
+                    synthetic,
+                    runs,
+                    no_scan,
+                    queries,
+                    grep,
+                },
+        } => {
+            let options = bench::BenchOptions {
+                runs: runs as usize,
+                scan: !no_scan,
+                queries,
+                grep,
+            };
+            let report = match synthetic {
+                Some(files) => {
+                    if format.is_text() {
+                        println!("🏗️  Generating a synthetic tree of {} files...", files);
+                    }
+                    let synthetic = bench::Synthetic::generate(files)?;
+                    bench::run(synthetic.index_dir(), &options)?
+                }
+                None => {
+                    let index_dir = resolve_index_dir(index_dir)?;
+                    require_index(&index_dir)?;
+                    bench::run(&index_dir, &options)?
+                }
+            };
+            show_bench(&report, format)
+        }
+        Commands::Compat {
+            command: CompatCommand::Fd { index_dir, args },
+        } => fd_compat(index_dir, args),
+        Commands::Index {
+            command: IndexCommand::Scrub { index_dir, out },
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            scrub_index(&index_dir, &out)
+        }
+        Commands::Tag { command } => match command {
+            TagCommand::Add {
+                tag,
+                paths,
+                index_dir,
+            } => tag_files(&resolve_index_dir(index_dir)?, &tag, &paths, true),
+            TagCommand::Remove {
+                tag,
+                paths,
+                index_dir,
+            } => tag_files(&resolve_index_dir(index_dir)?, &tag, &paths, false),
+            TagCommand::List { tag, index_dir } => {
+                list_tags(&resolve_index_dir(index_dir)?, tag.as_deref(), format)
+            }
+        },
+        Commands::Migrate { index_dir }
+        | Commands::Index {
+            command: IndexCommand::Migrate { index_dir },
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            migrate_index(&index_dir)
+        }
+        Commands::Updatedb {
+            database_root,
+            index_dir,
+            prunepaths,
+            prunenames,
+            verbose,
+        } => updatedb(
+            database_root,
+            &resolve_index_dir(index_dir)?,
+            &prunepaths,
+            &prunenames,
+            verbose,
+        ),
+        Commands::Import {
+            format,
+            database,
+            index_dir,
+            root,
+            stat,
+        } => import_database(
+            format.into(),
+            database,
+            &resolve_index_dir(index_dir)?,
+            root.as_deref(),
+            stat,
+        ),
+        Commands::Export {
+            format,
+            out,
+            index_dir,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            export_index(&index_dir, format.map(Into::into), out.as_deref())
+        }
+        Commands::Prune { index_dir, dry_run } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let mut index = Index::load(&index_dir)?;
+            index.load_content(&index_dir)?;
+            prune_index(index, &index_dir, dry_run)
+        }
+        Commands::Verify { index_dir, repair } => {
+            verify_index(&resolve_index_dir(index_dir)?, repair)
+        }
+        Commands::Watch {
+            index_dir,
+            debounce,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let root = Index::load(&index_dir)?.meta.root;
+            println!("👀 Watching {} (Ctrl-C to stop)", root.display());
+            let stop = std::sync::atomic::AtomicBool::new(false);
+            watch::watch(&index_dir, debounce, &stop, |report, generation| {
+                println!(
+                    "🔄 +{} ~{} -{} (generation {})",
+                    report.added, report.modified, report.removed, generation
+                );
+            })
+        }
+        Commands::Journal { index_dir } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            record_journal(&index_dir)
+        }
+        Commands::Daemon {
+            index_dir,
+            reindex,
+            full_reindex,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let defaults = &Config::get().daemon;
+            let reindex = match reindex {
+                Some(schedule) => Some(schedule),
+                None => defaults
+                    .reindex
+                    .as_deref()
+                    .map(Schedule::parse)
+                    .transpose()
+                    .map_err(|e| {
+                        anyhow::anyhow!("Invalid [daemon] reindex in the config: {}", e)
+                    })?,
+            };
+            let full_reindex = full_reindex || defaults.full_reindex;
+            run_daemon(&index_dir, reindex.as_ref(), full_reindex)
+        }
+        Commands::Serve {
+            index_dir,
+            port,
+            bind,
+            token,
+            ui,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let addr = format!("{}:{}", bind, port);
+            serve_http(&index_dir, &addr, token.as_deref(), ui)
+        }
+        Commands::Tui {
+            index_dir,
+            dashboard,
+        } => {
+            if dashboard {
+                return show_dashboard(dashboard_index_dirs(index_dir)?);
+            }
+            let index_dir = search_index_dir(index_dir)?;
+            pick_file("", &index_dir, None, &SearchOptions::default())
+        }
+        Commands::Find {
+            query,
+            saved: _,
+            interactive,
+            index_dir,
+            index,
+            all_indexes,
+            generation,
+            full_path,
+            case,
+            matcher,
+            exact_accents,
+            git,
+            tags,
+            sample,
+            seed,
+            copy_to,
+            move_to,
+            on_conflict,
+            dry_run,
+            chmod,
+            chown,
+            touch,
+            set_readonly,
+            clear_hidden,
+            yes,
+            exec,
+            exec_batch,
+            jobs,
+            open,
+            reveal,
+            rerank_cmd,
+            rerank_candidates,
+            order,
+            filters,
+        } => {
+            let index_dirs = search_index_dirs(index_dir, index.as_deref(), all_indexes)?;
+            remember_search(&index_dirs, &args);
+            if index_dirs.len() > 1 && (generation.is_some() || interactive || sample.is_some()) {
+                anyhow::bail!("--generation, --interactive and --sample need a single index");
+            }
+            let query = query.unwrap_or_default();
+            let mut filters: Filters = filters.into();
+            if !tags.is_empty() {
+                filters.tagged = Some(select_tagged(&index_dirs, &tags)?);
+            }
+            let sample = sample.map(|size| search::Sample {
+                size,
+                seed: seed.unwrap_or_else(random_seed),
+            });
+            let options = SearchOptions {
+                limit: order.limit,
+                filters,
+                full_path,
+                case: case.into(),
+                exact_accents,
+                transliterate: false,
+                weights: cli.ranking,
+                sample,
+                unsorted: order.no_sort,
+                engine: matcher.into(),
+            };
+            if interactive {
+                return pick_file(&query, &index_dirs[0], generation, &options);
+            }
+            let transfer = match (copy_to, move_to) {
+                (Some(dest), _) => Some((transfer::Mode::Copy, dest)),
+                (None, Some(dest)) => Some((transfer::Mode::Move, dest)),
+                (None, None) => None,
+            };
+            if let Some((mode, dest)) = transfer {
+                let request = TransferRequest {
+                    mode,
+                    dest,
+                    conflict: on_conflict.into(),
+                    dry_run,
+                };
+                return transfer_matches(
+                    &query,
+                    &index_dirs,
+                    generation,
+                    &options,
+                    request,
+                    format,
+                );
+            }
+            let command = match (exec, exec_batch) {
+                (Some(template), _) => Some(ExecRequest::Each {
+                    template,
+                    jobs: jobs.unwrap_or_else(|| {
+                        std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+                    }),
+                }),
+                (None, Some(template)) => Some(ExecRequest::Batch { template }),
+                (None, None) => None,
+            };
+            if let Some(request) = command {
+                return exec_matches(&query, &index_dirs, generation, &options, request);
+            }
+            if let Some(action) = launch_action(open, reveal) {
+                return launch_matches(&query, &index_dirs, generation, &options, action);
+            }
+            let change = perms::Change {
+                mode: chmod,
+                owner: chown,
+                touch,
+                readonly: set_readonly,
+                clear_hidden,
+            };
+            if !change.is_empty() {
+                return change_matches(
+                    &query,
+                    &index_dirs,
+                    generation,
+                    &options,
+                    &change,
+                    yes,
+                    format,
+                );
+            }
+            if format.is_text() && listing.is_decorated() {
+                println!("🔎 Searching for: {}", query);
+                if let Some(sample) = sample {
+                    println!(
+                        "   Sampling {} matches (--seed {})",
+                        sample.size, sample.seed
+                    );
+                }
+            }
+            let display = FindDisplay {
+                order,
+                rerank: rerank_cmd.map(|command| Rerank {
+                    command,
+                    candidates: rerank_candidates,
+                }),
+                git,
+                color,
+                dates,
+                links,
+                listing,
+                template,
+            };
+            find_files(&query, &index_dirs, generation, &options, display, format)?;
+            Ok(())
+        }
+        Commands::Syntax { index_dir, limit } => print_syntax(index_dir.as_deref(), limit),
+        Commands::Locate {
+            patterns,
+            index_dir,
+            ignore_case,
+            basename,
+            wholename: _,
+            all,
+            regex,
+            regexp,
+            existing,
+            count,
+            limit,
+            null,
+            max_database_age,
+            quiet,
+            statistics,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let snapshot = MappedSnapshot::open(&index_dir)?;
+            let options = SearchOptions {
+                filters: Filters {
+                    include_vendored: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let entries = snapshot.search("", &options)?;
+            if statistics {
+                return locate_statistics(&index_dir, &snapshot, &entries);
+            }
+            let max_age = Duration::from_secs(max_database_age * 86400);
+            let age = snapshot
+                .index()
+                .meta
+                .updated_at
+                .elapsed()
+                .unwrap_or_default();
+            if age > max_age && !quiet {
+                eprintln!(
+                    "⚠️  Warning: the index at {} is more than {} days old (actual age is {:.1} days); run `ss updatedb` to refresh it",
+                    index_dir.display(),
+                    max_database_age,
+                    age.as_secs_f64() / 86400.0
+                );
+            }
+            let locate =
+                compat::Locate::new(&patterns, &regexp, regex, ignore_case, basename, all)?;
+            let found = entries
+                .iter()
+                .map(|(entry, _)| entry)
+                .filter(|entry| locate.matches(entry))
+                .filter(|entry| !existing || entry.path.symlink_metadata().is_ok())
+                .take(limit.unwrap_or(usize::MAX));
+            let listing = match null {
+                true => output::Listing::Nul,
+                false => output::Listing::Bare,
+            };
+            let mut out = std::io::stdout().lock();
+            let mut matched = 0;
+            for entry in found {
+                matched += 1;
+                if !count {
+                    listing.write_path(&mut out, &entry.path)?;
+                }
+            }
+            if count {
+                println!("{}", matched);
+            }
+            match matched {
+                0 => Err(NoMatches.into()),
+                _ => Ok(()),
+            }
+        }
+        Commands::Projects { index_dir } => list_projects(&resolve_index_dir(index_dir)?, format),
+        Commands::Stats { index_dir, top } => {
+            show_stats(&resolve_index_dir(index_dir)?, top, format)?;
+            Ok(())
+        }
+        Commands::Grep {
+            query,
+            index_dir,
+            index,
+            all_indexes,
+            fixed_strings,
+            identifiers,
+            case,
+            max_filesize,
+            max_count,
+            max_total,
+            doc_lang,
+            changed_by,
+            since,
+            history,
+            follow,
+            fulltext,
+            open,
+            reveal,
+            lines,
+            order,
+            filters,
+        } => {
+            let index_dirs = search_index_dirs(index_dir, index.as_deref(), all_indexes)?;
+            remember_search(&index_dirs, &args);
+            if fulltext {
+                let limit = order.limit.unwrap_or(20);
+                return grep_fulltext(&query, &index_dirs, &filters.into(), limit, listing, format);
+            }
+            let options = grep::GrepOptions {
+                fixed_strings,
+                ignore_case: CaseMode::from(case).ignores_case(&query),
+                max_filesize,
+                doc_lang: doc_lang.map(str::to_string),
+                context: lines.context.into(),
+                max_count,
+                max_total,
+                spellings: match identifiers {
+                    true => grep::Spellings::Any,
+                    false => grep::Spellings::Joined,
+                },
+            };
+            if history || follow {
+                let [index_dir] = index_dirs.as_slice() else {
+                    anyhow::bail!("--history and --follow need a single index");
+                };
+                return match history {
+                    true => grep_history(&query, index_dir, &options, color, format),
+                    false => grep_follow(
+                        &query,
+                        index_dir,
+                        &options,
+                        &filters.into(),
+                        color,
+                        lines,
+                        format,
+                    ),
+                };
+            }
+            let blame = git::BlameFilter {
+                author: changed_by,
+                since,
+            };
+            grep_files(
+                &query,
+                &index_dirs,
+                &options,
+                &filters.into(),
+                &blame,
+                GrepDisplay {
+                    order,
+                    color,
+                    lines,
+                    listing,
+                    template,
+                    links,
+                    launch: launch_action(open, reveal),
+                },
+                format,
+            )
+        }
+        Commands::WatchFor {
+            pattern,
+            index_dir,
+            exec,
+            timeout,
+            existing,
+            fixed_strings,
+            ignore_case,
+            filters,
+        } => {
+            let options = grep::GrepOptions {
+                fixed_strings,
+                ignore_case,
+                ..Default::default()
+            };
+            let trigger = Trigger {
+                exec,
+                timeout,
+                existing,
+            };
+            watch_for(
+                &pattern,
+                &resolve_index_dir(index_dir)?,
+                &options,
+                &filters.into(),
+                trigger,
+                format,
+            )
+        }
+        Commands::Count {
+            index_dir,
+            lines,
+            by,
+            filters,
+        } => count_files(
+            &resolve_index_dir(index_dir)?,
+            lines,
+            by,
+            &filters.into(),
+            format,
+        ),
+        Commands::Du {
+            path,
+            index_dir,
+            depth,
+            top,
+            filters,
+        } => disk_usage(
+            &resolve_index_dir(index_dir)?,
+            path.as_deref(),
+            depth,
+            top,
+            &filters.into(),
+            format,
+        ),
+        Commands::Dupes {
+            index_dir,
+            min_size,
+            filters,
+        } => find_dupes(
+            &resolve_index_dir(index_dir)?,
+            min_size.unwrap_or(0),
+            &filters.into(),
+            format,
+        ),
+        Commands::SameAs {
+            file,
+            index_dir,
+            verify,
+            filters,
+        } => find_same_as(
+            &file,
+            &resolve_index_dir(index_dir)?,
+            verify,
+            &filters.into(),
+            format,
+        ),
+        Commands::NameCollisions { index_dir, filters } => {
+            find_name_collisions(&resolve_index_dir(index_dir)?, &filters.into(), format)
+        }
+        Commands::Audit {
+            index_dir,
+            portability,
+            max_path,
+            filters,
+        } => {
+            if !portability {
+                anyhow::bail!("Choose what to audit, e.g. --portability");
+            }
+            audit_portability(
+                &resolve_index_dir(index_dir)?,
+                max_path,
+                &filters.into(),
+                format,
+            )
+        }
+        Commands::Configs {
+            index_dir,
+            app,
+            filters,
+        } => list_configs(
+            &resolve_index_dir(index_dir)?,
+            app.as_deref(),
+            &filters.into(),
+            format,
+        ),
+        Commands::Rename {
+            pattern,
+            template,
+            index_dir,
+            ignore_case,
+            dry_run,
+            undo,
+            filters,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            if undo {
+                return undo_rename(&index_dir, format);
+            }
+            let pattern = regex::RegexBuilder::new(&pattern.unwrap_or_default())
+                .case_insensitive(ignore_case)
+                .build()?;
+            let template = template.unwrap_or_default();
+            rename_files(
+                &index_dir,
+                &pattern,
+                &template,
+                &filters.into(),
+                dry_run,
+                format,
+            )
+        }
+        Commands::Replace {
+            pattern,
+            replacement,
+            index_dir,
+            fixed_strings,
+            ignore_case,
+            max_filesize,
+            eol,
+            preserve_mtime,
+            backup,
+            dry_run,
+            filters,
+        } => {
+            if backup.as_deref() == Some("") {
+                anyhow::bail!("--backup needs a non-empty suffix");
+            }
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            let options = grep::GrepOptions {
+                fixed_strings,
+                ignore_case,
+                max_filesize,
+                ..Default::default()
+            };
+            let edit = Edit {
+                pattern: &pattern,
+                replacement: &replacement,
+                eol,
+                preserve_mtime,
+                backup: backup.as_deref(),
+                dry_run,
+            };
+            replace_matches(&index_dir, &edit, &options, &filters.into(), format)
+        }
+        Commands::Collect {
+            query,
+            out,
+            index_dir,
+            full_path,
+            max_files,
+            max_size,
+            filters,
+        } => {
+            let options = SearchOptions {
+                limit: None,
+                filters: filters.into(),
+                full_path,
+                case: CaseMode::default(),
+                exact_accents: false,
+                transliterate: false,
+                weights: cli.ranking,
+                sample: None,
+                unsorted: false,
+                engine: Default::default(),
+            };
+            let limits = collect::Limits {
+                max_files,
+                max_size,
+            };
+            collect_files(
+                &query,
+                &resolve_index_dir(index_dir)?,
+                &options,
+                &out,
+                limits,
+                format,
+            )
+        }
+        Commands::Diff {
+            index_dir,
+            from,
+            to,
+            filters,
+        } => diff_generations(
+            &resolve_index_dir(index_dir)?,
+            from,
+            to,
+            &filters.into(),
+            dates.as_ref(),
+            format,
+        ),
+        Commands::DiffResults {
+            first,
+            second,
+            index_dir,
+            grep,
+            fixed_strings,
+            ignore_case,
+            filters,
+        } => {
+            let index_dir = search_index_dir(index_dir)?;
+            offer_setup(&index_dir)?;
+            let grep = grep.then_some(grep::GrepOptions {
+                fixed_strings,
+                ignore_case,
+                ..Default::default()
+            });
+            diff_results(
+                [&first, &second],
+                &index_dir,
+                grep.as_ref(),
+                &filters.into(),
+                format,
+            )
+        }
+        Commands::History {
+            index_dir,
+            saved,
+            limit,
+            clear,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            match (clear, saved) {
+                (true, _) => {
+                    let mut searches = searches::Searches::load(&index_dir)?;
+                    searches.recent.clear();
+                    searches.save(&index_dir)
+                }
+                (false, true) => list_saved_searches(&index_dir, format),
+                (false, false) => list_recent_searches(&index_dir, limit, format),
+            }
+        }
+        Commands::SaveSearch {
+            name,
+            query,
+            options,
+            index_dir,
+            delete,
+        } => {
+            let index_dir = resolve_index_dir(index_dir)?;
+            require_index(&index_dir)?;
+            save_search(&index_dir, &name, query, options, delete)
+        }
+        Commands::Jump {
+            keywords,
+            add,
+            list,
+            import,
+            from,
+            init,
+        } => {
+            if let Some(shell) = init {
+                print!("{}", jump::shell_hook(shell.into()));
+                return Ok(());
+            }
+            let data_dir = registry::data_dir()
+                .context("No data directory to keep the history in; set $SONIC_SEARCH_DATA_DIR")?;
+            let path = data_dir.join(jump::JUMP_FILE);
+            if let Some(dir) = add {
+                let dir = dunce::canonicalize(&dir)
+                    .with_context(|| format!("No such directory: {}", dir.display()))?;
+                let mut history = JumpHistory::load_from(&path)?;
+                history.visit(&dir, SystemTime::now());
+                return history.save_to(&path);
+            }
+            if let Some(source) = import {
+                return import_jumps(&path, source.into(), from);
+            }
+            jump_to(&path, &keywords, list, format)
+        }
+    }
+}
+
+/// The arguments saved as `name` in the first of `index_dirs` that has it
+fn saved_search(index_dirs: &[PathBuf], name: &str) -> Result<Vec<String>> {
+    let mut first_error = None;
+    for index_dir in index_dirs {
+        match searches::Searches::load(index_dir)?.saved(name) {
+            Ok(args) => return Ok(args.to_vec()),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| anyhow::anyhow!("No index to look up '{}' in", name)))
+}
+
+/// Add the command line `args` to the history of every one of `index_dirs`.
+///
+/// Searching works without: an index directory that can't be written to,
+/// such as a shared one, just doesn't keep a history.
+fn remember_search(index_dirs: &[PathBuf], args: &[OsString]) {
+    let args: Vec<String> = args
+        .iter()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let now = SystemTime::now();
+    for index_dir in index_dirs.iter().filter(|dir| Index::exists(dir)) {
+        if let Ok(mut searches) = searches::Searches::load(index_dir) {
+            searches.record(args.clone(), now);
+            let _ = searches.save(index_dir);
+        }
+    }
+}
+
+/// Implements `save-search`
+fn save_search(
+    index_dir: &Path,
+    name: &str,
+    query: Option<String>,
+    options: Vec<String>,
+    delete: bool,
+) -> Result<()> {
+    let mut searches = searches::Searches::load(index_dir)?;
+    if delete {
+        searches.saved(name)?;
+        searches.saved.remove(name);
+        searches.save(index_dir)?;
+        println!("🗑️  Forgot the saved search '{}'", name);
+        return Ok(());
+    }
+    let args: Vec<String> = query.into_iter().chain(options).collect();
+    // Mistakes are reported now rather than when the search is run
+    let line = ["ss", "find"]
+        .into_iter()
+        .chain(args.iter().map(String::as_str));
+    let cli = Cli::try_parse_from(line).map_err(|e| {
+        let message = e.to_string();
+        anyhow::anyhow!(
+            "Not a valid search for `ss find`: {}",
+            message.trim_start_matches("error: ").trim_end()
+        )
+    })?;
+    if let Commands::Find { saved: Some(_), .. } = cli.command {
+        anyhow::bail!("A saved search can't run another one with --saved");
+    }
+    let replaced = searches.saved.insert(name.to_string(), args.clone());
+    searches.save(index_dir)?;
+    println!(
+        "💾 {} '{}': ss find {}",
+        match replaced {
+            Some(_) => "Updated",
+            None => "Saved",
+        },
+        name,
+        searches::command_line(&args)
+    );
+    println!("   Run it with `ss find --saved {}`", name);
+    Ok(())
+}
+
+/// Implements `history`
+fn list_recent_searches(index_dir: &Path, limit: usize, format: OutputFormat) -> Result<()> {
+    let searches = searches::Searches::load(index_dir)?;
+    let recent = &searches.recent[..searches.recent.len().min(limit)];
+    if !format.is_text() {
+        let records: Vec<_> = recent
+            .iter()
+            .map(|search| output::SearchRecord {
+                command_line: format!("ss {}", searches::command_line(&search.args)),
+                args: &search.args,
+                time: search
+                    .at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs()),
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    if recent.is_empty() {
+        println!("No searches of {} yet", index_dir.display());
+        return Ok(());
+    }
+    let now = SystemTime::now();
+    for search in recent {
+        let age = now.duration_since(search.at).unwrap_or_default().as_secs();
+        println!(
+            "{:>10}  ss {}",
+            format!("{} ago", units::format_age(age)),
+            searches::command_line(&search.args)
+        );
+    }
+    Ok(())
+}
+
+/// Implements `history --saved`
+fn list_saved_searches(index_dir: &Path, format: OutputFormat) -> Result<()> {
+    let searches = searches::Searches::load(index_dir)?;
+    if !format.is_text() {
+        let records: Vec<_> = searches
+            .saved
+            .iter()
+            .map(|(name, args)| output::SavedSearchRecord { name, args })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    if searches.saved.is_empty() {
+        println!("No saved searches; save one with `ss save-search <name> <query>`");
+        return Ok(());
+    }
+    let width = searches.saved.keys().map(|name| name.chars().count()).max();
+    for (name, args) in &searches.saved {
+        println!(
+            "{:<width$}  ss find {}",
+            name,
+            searches::command_line(args),
+            width = width.unwrap_or(0)
+        );
+    }
+    Ok(())
+}
+
+/// Implements `jump --import`
+fn import_jumps(path: &Path, source: jump::Source, from: Option<PathBuf>) -> Result<()> {
+    let database = from
+        .or_else(|| jump::default_import_path(source))
+        .context("No default database location on this platform; pass --from")?;
+    let visits = jump::import(source, &database)?;
+    let count = visits.len();
+    let mut history = JumpHistory::load_from(path)?;
+    history.merge(visits);
+    history.save_to(path)?;
+    println!(
+        "📥 Imported {} directories from {}",
+        count,
+        database.display()
+    );
+    Ok(())
+}
+
+/// Implements `jump`: print the best directory matching `keywords`, or list
+/// every match with `list`
+fn jump_to(path: &Path, keywords: &[String], list: bool, format: OutputFormat) -> Result<()> {
+    let history = JumpHistory::load_from(path)?;
+    let now = SystemTime::now();
+    if !list {
+        let dir = history
+            .best(keywords, now)
+            .with_context(|| format!("No visited directory matches '{}'", keywords.join(" ")))?;
+        println!("{}", dir.display());
+        return Ok(());
+    }
+    let ranked = history.ranked(keywords, now);
+    if format.is_text() {
+        for (visit, score) in &ranked {
+            println!("{:>8.1}  {}", score, visit.path.display());
+        }
+        return Ok(());
+    }
+    let records: Vec<output::JumpRecord> = ranked
+        .iter()
+        .map(|(visit, score)| output::JumpRecord {
+            path: &visit.path,
+            score: *score,
+            visits: visit.rank,
+            last_visit: output::unix_secs(visit.last),
+        })
+        .collect();
+    output::write_records(&mut std::io::stdout().lock(), format, &records)
+}
+
+/// Implements `grep --history`: search every blob reachable from the
+/// repository's refs, indexing new ones first
+fn grep_history(
+    query: &str,
+    index_dir: &Path,
+    options: &grep::GrepOptions,
+    color: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let start = Instant::now();
+    let repo = if Index::exists(index_dir) {
+        Snapshot::open(index_dir)?.meta.root.clone()
+    } else {
+        std::env::current_dir()?
+    };
+    std::fs::create_dir_all(index_dir)?;
+    let mut history = HistoryIndex::load(index_dir);
+    let added = history.update(&repo)?;
+    if added > 0 {
+        history.save(index_dir)?;
+        eprintln!("   Indexed {} new blobs from git history", added);
+    }
+
+    let results = history.grep(&repo, query, options)?;
+    let mut records: Vec<output::HistoryRecord> = Vec::new();
+    let mut line_count = 0;
+    for found in &results {
+        let commits = history::blob_commits(&repo, &found.blob.oid)?;
+        // The commit that introduced this version of the file
+        let commit = commits.first().map_or(&found.blob.oid[..7], |c| &c.id[..7]);
+        line_count += found.matches.len();
+        if format.is_text() {
+            for m in &found.matches {
+                println!(
+                    "{}:{}:{}:{}",
+                    commit,
+                    found.blob.path,
+                    m.line_number,
+                    grep::highlight(&m.line, &m.spans, color)
+                );
+            }
+            continue;
+        }
+        let commits: Vec<output::HistoryCommit> = commits
+            .iter()
+            .map(|c| output::HistoryCommit {
+                id: c.id.clone(),
+                time: output::unix_secs(c.time),
+                summary: c.summary.clone(),
+            })
+            .collect();
+        for m in &found.matches {
+            records.push(output::HistoryRecord {
+                blob: &found.blob.oid,
+                path: &found.blob.path,
+                commits: commits.clone(),
+                line_number: m.line_number,
+                line: &m.line,
+                spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+            });
+        }
+    }
+    output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+
+    eprintln!(
+        "Found {} matching lines in {} file versions ({} in history) in {} ms",
+        line_count,
+        results.len(),
+        history.blobs.len(),
+        start.elapsed().as_millis()
+    );
+    match line_count {
+        0 => Err(NoMatches.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Implements `find --interactive`: print the picked path, or nothing on cancel
+#[cfg(feature = "tui")]
+fn pick_file(
+    query: &str,
+    index_dir: &Path,
+    generation: Option<u64>,
+    options: &SearchOptions,
+) -> Result<()> {
+    if !std::io::stderr().is_terminal() {
+        anyhow::bail!("--interactive needs a terminal");
+    }
+    require_index(index_dir)?;
+    let index = match generation {
+        Some(generation) => Snapshot::open_at(index_dir, generation)?,
+        None => Snapshot::open(index_dir)?,
+    };
+    // Reject invalid filters before taking over the terminal
+    index.search("", options)?;
+    if let Some(path) = picker::pick(&index, options, query)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Implements the 'serve' command
+#[cfg(feature = "serve")]
+fn serve_http(index_dir: &Path, addr: &str, token: Option<&str>, ui: bool) -> Result<()> {
+    let server = daemon::Server::open(index_dir)?;
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    serve::serve(&server, addr, token, ui, &stop, |addr| {
+        println!(
+            "🌐 Serving generation {} on http://{} (Ctrl-C to stop)",
+            server.generation(),
+            addr
+        );
+        if ui {
+            println!("   Search page: http://{}/", addr);
+        }
+        if token.is_none() && !addr.ip().is_loopback() {
+            eprintln!("⚠️  Anyone who can reach this address can search the index; pass --token");
+        }
+    })
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve_http(_: &Path, _: &str, _: Option<&str>, _: bool) -> Result<()> {
+    anyhow::bail!("`ss serve` isn't available: sonic-search was built without the `serve` feature")
+}
+
+/// Implements the 'journal' command
+#[cfg(target_os = "linux")]
+fn record_journal(index_dir: &Path) -> Result<()> {
+    let root = Index::load(index_dir)?.meta.root;
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    journal::record(&root, index_dir, &stop, || {
+        println!(
+            "📓 Recording changes under {} (Ctrl-C to stop)",
+            root.display()
+        );
+        println!("   `ss update` reads them from here on, after one more full walk");
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn record_journal(_: &Path) -> Result<()> {
+    if cfg!(windows) {
+        println!("NTFS keeps a USN journal of its own, which `ss update` already reads from.");
+        return Ok(());
+    }
+    anyhow::bail!("`ss journal` needs fanotify, which is only available on Linux")
+}
+
+#[cfg(not(feature = "tui"))]
+fn pick_file(_: &str, _: &Path, _: Option<u64>, _: &SearchOptions) -> Result<()> {
+    anyhow::bail!("--interactive isn't available: sonic-search was built without the `tui` feature")
+}
+
+/// Indexes shown by `tui --dashboard`: the given or workspace index, if
+/// there is one, then every index `ss scan` has registered
+fn dashboard_index_dirs(explicit: Option<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut index_dirs = Vec::new();
+    let first = resolve_index_dir(explicit)?;
+    if Index::exists(&first) {
+        index_dirs.push(dunce::canonicalize(&first)?);
+    }
+    for known in Registry::load().indexes {
+        if !index_dirs.contains(&known.path) {
+            index_dirs.push(known.path);
+        }
+    }
+    Ok(index_dirs)
+}
+
+/// Implements `tui --dashboard`
+#[cfg(feature = "tui")]
+fn show_dashboard(index_dirs: Vec<PathBuf>) -> Result<()> {
+    if !std::io::stderr().is_terminal() {
+        anyhow::bail!("--dashboard needs a terminal");
+    }
+    dashboard::run(&index_dirs)
+}
+
+#[cfg(not(feature = "tui"))]
+fn show_dashboard(_: Vec<PathBuf>) -> Result<()> {
+    anyhow::bail!("`ss tui` isn't available: sonic-search was built without the `tui` feature")
+}
+
+/// Implements the 'init' command
+fn setup(accept_defaults: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let plan = if accept_defaults {
+        init::SetupPlan::defaults(&cwd)
+    } else {
+        init::prompt_plan(&mut std::io::stdin().lock(), &mut std::io::stdout(), &cwd)?
+    };
+
+    println!("\n🔍 Scanning directory: {}", plan.root.display());
+    let generation = init::run_initial_scan(&plan, &mut std::io::stderr())?;
+    println!(
+        "✅ Index saved to: {} (generation {})",
+        plan.index_dir.display(),
+        generation
+    );
+    if let Some(path) = Config::default_path()
+        && init::write_config(&path)?
+    {
+        println!("   Config written to: {}", path.display());
+    }
+    if plan.index_dir != Path::new(workspace::DEFAULT_INDEX_DIR) {
+        println!(
+            "   Search it with: ss find <query> --index-dir {}",
+            plan.index_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The `--index-dir` given, or the index of the workspace around the cwd
+fn resolve_index_dir(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    match explicit.or_else(|| Config::get().index_dir.clone()) {
+        Some(index_dir) => Ok(index_dir),
+        None => Ok(workspace::locate(&std::env::current_dir()?).index_dir),
+    }
+}
+
+/// Like [`resolve_index_dir`], but builds the workspace index on first use
+/// so searches from any subdirectory share it
+fn search_index_dir(explicit: Option<PathBuf>) -> Result<PathBuf> {
+    if explicit.is_some() || Config::get().index_dir.is_some() {
+        return resolve_index_dir(explicit);
+    }
+    let location = workspace::locate(&std::env::current_dir()?);
+    if let Some(root) = &location.workspace_root
+        && !Index::exists(&location.index_dir)
+    {
+        eprintln!("📁 Indexing workspace root: {}", root.display());
+        let mut index = Index::from_scan(scanner::scan_directory(root)?);
+        let generation = index.save(&location.index_dir)?;
+        registry::remember(&location.index_dir, &index.meta.root);
+        eprintln!(
+            "   Index saved to: {} (generation {})",
+            location.index_dir.display(),
+            generation
+        );
+    }
+    Ok(location.index_dir)
+}
+
+/// On the very first interactive run, offer the guided setup before searching
+fn offer_setup(index_dir: &Path) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let config_path = Config::default_path();
+    if !interactive || !init::is_first_run(config_path.as_deref(), index_dir) {
+        return Ok(());
+    }
+    let mut stdin = std::io::stdin().lock();
+    if init::confirm(
+        &mut stdin,
+        &mut std::io::stdout(),
+        "No index yet. Run the guided setup now?",
+    )? {
+        drop(stdin);
+        setup(false)?;
+    } else if let Some(path) = config_path {
+        // Don't ask again on every search
+        init::write_config(&path)?;
+    }
+    Ok(())
+}
+
+/// Index directories a `find` or `grep` reads: the indexes of a profile,
+/// every index `ss scan` has built, those given with `-i`, or the
+/// workspace's index
+fn search_index_dirs(
+    explicit: Vec<PathBuf>,
+    profile: Option<&str>,
+    all: bool,
+) -> Result<Vec<PathBuf>> {
+    if let Some(name) = profile {
+        return Ok(Registry::load().profile(name)?.indexes.clone());
+    }
+    if all {
+        let index_dirs: Vec<PathBuf> = Registry::load()
+            .indexes
+            .into_iter()
+            .map(|known| known.path)
+            .filter(|path| Index::exists(path))
+            .collect();
+        if index_dirs.is_empty() {
+            anyhow::bail!("No indexes have been built yet; create one with `ss scan <path>`");
+        }
+        return Ok(index_dirs);
+    }
+    if explicit.len() > 1 {
+        for index_dir in &explicit {
+            require_index(index_dir)?;
+        }
+        return Ok(explicit);
+    }
+    let index_dir = search_index_dir(explicit.into_iter().next())?;
+    offer_setup(&index_dir)?;
+    Ok(vec![index_dir])
+}
+
+/// Fail with a did-you-mean hint when `index_dir` holds no index
+fn require_index(index_dir: &Path) -> Result<()> {
+    if Index::exists(index_dir) {
+        return Ok(());
+    }
+    match Registry::load().suggest(index_dir) {
+        Some(known) => anyhow::bail!(
+            "No index found at {}. Did you mean {}?",
+            index_dir.display(),
+            known.display()
+        ),
+        None => anyhow::bail!(
+            "No index found at {} (run `ss scan` first)",
+            index_dir.display()
+        ),
+    }
+}
+
+/// Point at a registered index when a search falls back to scanning
+fn warn_index_suggestion(index_dir: &Path) {
+    if let Some(known) = Registry::load().suggest(index_dir) {
+        eprintln!("   Did you mean --index-dir {}?", known.display());
+    }
+}
+
+/// Implements the 'daemon' command, re-indexing on `reindex` beside
+/// serving requests when it's given
+fn run_daemon(index_dir: &Path, reindex: Option<&Schedule>, full: bool) -> Result<()> {
+    let server = daemon::Server::open(index_dir)?;
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        if let Some(schedule) = reindex {
+            scope.spawn(|| {
+                schedule::run(index_dir, schedule, full, &stop, |result| match result {
+                    Ok((report, Some(generation))) => println!(
+                        "🔄 Re-indexed: +{} ~{} -{} (generation {})",
+                        report.added, report.modified, report.removed, generation
+                    ),
+                    Ok((_, None)) => println!("🔄 Re-indexed: no changes"),
+                    Err(e) => eprintln!("⚠️  Re-indexing failed: {:#}", e),
+                })
+            });
+        }
+        let served = daemon::serve(&server, &stop, |socket| {
+            println!(
+                "🛰️  Serving generation {} on {} (Ctrl-C to stop)",
+                server.generation(),
+                socket.display()
+            );
+            if let Some(schedule) = reindex {
+                println!("   Re-indexing {}", describe_schedule(schedule));
+            }
+        });
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        served
+    })
+}
+
+fn describe_schedule(schedule: &Schedule) -> String {
+    match schedule {
+        Schedule::Every(interval) => {
+            format!("every {}", units::format_age(interval.as_secs()))
+        }
+        Schedule::Daily(times) => {
+            let times: Vec<String> = times
+                .iter()
+                .map(|time| time.format("%H:%M").to_string())
+                .collect();
+            format!("daily at {}", times.join(", "))
+        }
+    }
+}
+
+/// Implements the 'update' command (and `scan --incremental`)
+fn update_index(mut index: Index, index_dir: &Path) -> Result<()> {
+    println!("🔄 Updating index for: {}", index.meta.root.display());
+    let (report, saved) = index.update_and_save(index_dir, false)?;
+    println!("✅ Update complete!");
+    if let Some(kind) = report.journal {
+        println!("   Changes read from the {} journal", kind.name());
+    }
+    println!("   Added: {}", report.added);
+    println!("   Modified: {}", report.modified);
+    println!("   Removed: {}", report.removed);
+    println!("   Unchanged: {}", report.unchanged);
+    if report.permissions_changed > 0 {
+        println!("   Permissions changed: {}", report.permissions_changed);
+    }
+    println!(
+        "   Total Size: {}",
+        scanner::format_size(index.total_size())
+    );
+    println!("   Elapsed Time: {} ms", report.elapsed_ms);
+    if let Some(generation) = saved {
+        println!(
+            "   Index saved to: {} (generation {})",
+            index_dir.display(),
+            generation
+        );
+    }
+    Ok(())
+}
+
+/// Implements the 'syntax' command
+fn print_syntax(index_dir: Option<&Path>, limit: usize) -> Result<()> {
+    let snapshot = match index_dir {
+        Some(index_dir) => {
+            require_index(index_dir)?;
+            Some(MappedSnapshot::open(index_dir)?)
+        }
+        None => None,
+    };
+    let width = query::SYNTAX
+        .iter()
+        .flat_map(|section| section.rules)
+        .map(|(rule, _)| rule.chars().count())
+        .max()
+        .unwrap_or(0);
+    for section in query::SYNTAX {
+        println!("{}", section.title);
+        for (rule, meaning) in section.rules {
+            println!("  {:<width$}  {}", rule, meaning);
+        }
+        println!();
+    }
+    println!("Examples");
+    let options = SearchOptions {
+        limit: Some(limit),
+        ..Default::default()
+    };
+    for (example, meaning) in query::EXAMPLES {
+        println!("  ss find '{}'", example);
+        println!("      {}", meaning);
+        let Some(snapshot) = &snapshot else {
+            continue;
+        };
+        let results = snapshot.search(example, &options)?;
+        if results.is_empty() {
+            println!("      (nothing in this index)");
+        }
+        let root = &snapshot.index().meta.root;
+        for (entry, _) in results {
+            let shown = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+            println!("      → {}", shown.display());
+        }
+    }
+    Ok(())
+}
+
+/// Age in days past which `locate` warns the index is stale, as GNU
+/// locate does
+const LOCATE_MAX_AGE_DAYS: u64 = 8;
+
+/// Implements `locate --statistics`, in mlocate's layout
+fn locate_statistics(
+    index_dir: &Path,
+    snapshot: &MappedSnapshot,
+    entries: &[(scanner::FileEntry, i64)],
+) -> Result<()> {
+    let generation = snapshot.generation();
+    let storage = storage::storage(snapshot.index().meta.scan_options.backend);
+    let stored: u64 = [
+        Index::generation_path(index_dir, generation),
+        storage.entries_path(index_dir, generation),
+        Index::filter_path(index_dir, generation),
+        Index::content_path(index_dir, generation),
+    ]
+    .iter()
+    .filter_map(|path| path.metadata().ok())
+    .map(|metadata| metadata.len())
+    .sum();
+    let dirs = entries.iter().filter(|(entry, _)| entry.is_dir).count();
+    let name_bytes: usize = entries.iter().map(|(entry, _)| entry.name.len()).sum();
+    println!("Index {} (generation {}):", index_dir.display(), generation);
+    println!("\t{} directories", dirs);
+    println!("\t{} files", entries.len() - dirs);
+    println!("\t{} bytes in file names", name_bytes);
+    println!("\t{} bytes used to store the index", stored);
+    Ok(())
+}
+
+/// A seed for `--sample` that differs from run to run
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    nanos ^ (u64::from(std::process::id()) << 32)
+}
+
+/// Implements the 'export' command
+fn export_index(
+    index_dir: &Path,
+    format: Option<export::Format>,
+    out: Option<&Path>,
+) -> Result<()> {
+    let format = match (format, out) {
+        (Some(format), _) => format,
+        (None, Some(out)) => export::Format::from_path(out).with_context(|| {
+            format!(
+                "Can't tell the format from {}; pass csv, json, ndjson or parquet",
+                out.display()
+            )
+        })?,
+        (None, None) => anyhow::bail!("Pass a format: csv, json, ndjson or parquet"),
+    };
+    if out.is_none() && format == export::Format::Parquet && std::io::stdout().is_terminal() {
+        anyhow::bail!("Parquet is binary; pass -o FILE or redirect the output");
+    }
+    let index = Index::load(index_dir)?;
+    let rows = export::rows(&index);
+    match out {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            export::write(&mut std::io::BufWriter::new(file), format, &rows)?;
+            eprintln!("📤 Exported {} entries to {}", rows.len(), path.display());
+        }
+        None => export::write(&mut std::io::stdout().lock(), format, &rows)?,
+    }
+    Ok(())
+}
+
+/// Implements the 'index scrub' command
+fn scrub_index(index_dir: &Path, out: &Path) -> Result<()> {
+    if out.exists() && std::fs::read_dir(out)?.next().is_some() {
+        anyhow::bail!(
+            "{} is not empty; pick a new directory for the scrubbed index",
+            out.display()
+        );
+    }
+    let index = Index::load(index_dir)?;
+    let mut scrubbed = scrub::scrub(&index, &scrub::random_salt());
+    scrubbed.save(out)?;
+    println!(
+        "🧹 Scrubbed {} entries into {}",
+        scrubbed.entries.len(),
+        out.display()
+    );
+    println!(
+        "   Names are hashed and contents left out; sizes, times and the tree's shape are kept"
+    );
+    println!("   Try it with: ss find -i {} <query>", out.display());
+    Ok(())
+}
+
+/// The files of `index_dirs` carrying any of `tags`
+fn select_tagged(index_dirs: &[PathBuf], tags: &[String]) -> Result<tags::Tagged> {
+    let mut tagged = tags::Tagged::default();
+    let mut first_error = None;
+    for index_dir in index_dirs {
+        match tags::Tags::load(index_dir)?.select(tags) {
+            Ok(found) => tagged.extend(found),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match (tagged.paths.is_empty(), first_error) {
+        (true, Some(e)) => Err(e),
+        _ => Ok(tagged),
+    }
+}
+
+/// Implements `tag add` and, with `add` unset, `tag remove`
+fn tag_files(index_dir: &Path, tag: &str, paths: &[PathBuf], add: bool) -> Result<()> {
+    require_index(index_dir)?;
+    tags::check_tag(tag)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let entries: HashMap<&Path, &FileEntry> = snapshot
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    let mut tags = tags::Tags::load(index_dir)?;
+    tags.follow(&snapshot.entries);
+    let mut changed = 0;
+    for path in paths {
+        let canonical = dunce::canonicalize(path);
+        // A file already deleted can still have its tags taken off
+        let path = match (canonical, add) {
+            (Ok(path), _) => path,
+            (Err(_), false) => std::path::absolute(path)?,
+            (Err(_), true) => anyhow::bail!("No such file: {}", path.display()),
+        };
+        if add {
+            let Some(entry) = entries.get(path.as_path()) else {
+                anyhow::bail!(
+                    "{} isn't in the index at {}; run `ss update` first",
+                    path.display(),
+                    index_dir.display()
+                );
+            };
+            changed += usize::from(tags.add(tag, &path, entry.hash.as_ref()));
+        } else {
+            changed += usize::from(tags.remove(tag, &path));
+        }
+    }
+    tags.save(index_dir)?;
+    match add {
+        true => println!("🏷️  Tagged {} file(s) '{}'", changed, tag),
+        false => println!("🏷️  Took '{}' off {} file(s)", tag, changed),
+    }
+    Ok(())
+}
+
+/// Implements `tag list`
+fn list_tags(index_dir: &Path, tag: Option<&str>, format: OutputFormat) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let mut tags = tags::Tags::load(index_dir)?;
+    if tags.follow(&snapshot.entries) > 0 {
+        tags.save(index_dir)?;
+    }
+    let Some(tag) = tag else {
+        let counts = tags.counts();
+        if !format.is_text() {
+            let records: Vec<_> = counts
+                .iter()
+                .map(|(&tag, &files)| output::TagRecord { tag, files })
+                .collect();
+            return output::write_records(&mut std::io::stdout().lock(), format, &records);
+        }
+        if counts.is_empty() {
+            println!("No files are tagged; tag some with `ss tag add <tag> <path>...`");
+        }
+        for (tag, files) in counts {
+            println!("{:>8}  {}", files, tag);
+        }
+        return Ok(());
+    };
+    tags.select(&[tag.to_string()])?;
+    let files: Vec<&tags::TaggedFile> = tags
+        .files
+        .iter()
+        .filter(|file| file.tags.contains(tag))
+        .collect();
+    if !format.is_text() {
+        let records: Vec<_> = files
+            .iter()
+            .map(|file| output::TaggedFileRecord {
+                path: &file.path,
+                tags: &file.tags,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    let indexed: HashSet<&Path> = snapshot.entries.iter().map(|e| e.path.as_path()).collect();
+    for file in files {
+        match indexed.contains(file.path.as_path()) {
+            true => println!("{}", file.path.display()),
+            false => println!("{} (not in the index)", file.path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Implements `migrate` and `index migrate`
+fn migrate_index(index_dir: &Path) -> Result<()> {
+    let start = Instant::now();
+    let Some(migrated) = selfcheck::migrate(index_dir)? else {
+        println!(
+            "✅ The index at {} is already in this version's format (version {}); nothing to migrate",
+            index_dir.display(),
+            sonic_search::index::INDEX_FORMAT_VERSION
+        );
+        return Ok(());
+    };
+    let snapshot = Snapshot::open(index_dir)?;
+    println!(
+        "✅ Rebuilt the index at {} in format version {}: {} files (generation {}) in {:.1}s",
+        index_dir.display(),
+        sonic_search::index::INDEX_FORMAT_VERSION,
+        snapshot.entries.len(),
+        migrated.generation,
+        start.elapsed().as_secs_f64()
+    );
+    if migrated.kept_options {
+        return Ok(());
+    }
+    println!(
+        "   The old index didn't record the options it was scanned with, such as --content or --hash; \
+         add them back with `ss {} ...`",
+        searches::command_line(&[
+            "scan".to_string(),
+            snapshot.meta.root.to_string_lossy().into_owned(),
+            "-i".to_string(),
+            index_dir.to_string_lossy().into_owned(),
+        ])
+    );
+    Ok(())
+}
+
+/// Implements the 'import' command
+fn import_database(
+    format: import::Format,
+    database: Option<PathBuf>,
+    index_dir: &Path,
+    root: Option<&Path>,
+    stat: bool,
+) -> Result<()> {
+    let database = database
+        .or_else(|| import::default_path(format))
+        .context("Everything keeps no file list in a known place; pass one exported as .efu")?;
+    let root = root.map(dunce::canonicalize).transpose()?;
+    let mut scan = import::import(format, &database, root.as_deref())?;
+    if stat {
+        import::refresh(&mut scan);
+    }
+    println!("📥 Imported {}", database.display());
+    println!("   Root Directory: {}", scan.root.display());
+    println!("   Files found: {}", scan.file_count);
+    println!("   Directories: {}", scan.dir_count);
+    if stat {
+        println!("   Total Size: {}", scanner::format_size(scan.total_size));
+    }
+    let mut index = Index::from_scan(scan);
+    let generation = index.save(index_dir)?;
+    registry::remember(index_dir, &index.meta.root);
+    println!(
+        "   Index saved to: {} (generation {})",
+        index_dir.display(),
+        generation
+    );
+    if !stat && format != import::Format::Everything {
+        println!("   Sizes and times are filled in by the next `ss update`");
+    }
+    Ok(())
+}
+
+/// Implements the 'updatedb' command: like [`update_index`], or a scan of
+/// `database_root` when that's not what the index covers, but silent
+/// unless `verbose` so it can run from cron
+fn updatedb(
+    database_root: Option<PathBuf>,
+    index_dir: &Path,
+    prunepaths: &[String],
+    prunenames: &[String],
+    verbose: bool,
+) -> Result<()> {
+    let root = database_root.map(dunce::canonicalize).transpose()?;
+    let stored = match Index::exists(index_dir) {
+        true => Some(Index::load(index_dir)?),
+        false => None,
+    };
+    if let Some(mut index) = stored
+        && root.as_ref().is_none_or(|root| *root == index.meta.root)
+    {
+        let excludes = compat::prune_excludes(&index.meta.root, prunepaths, prunenames);
+        for exclude in excludes {
+            if !index.meta.scan_options.excludes.contains(&exclude) {
+                index.meta.scan_options.excludes.push(exclude);
+                // The journal only knows what changed, not what's now pruned
+                index.meta.journal = None;
+            }
+        }
+        limit_threads(index.meta.scan_options.threads);
+        index.load_content(index_dir)?;
+        if verbose {
+            return update_index(index, index_dir);
+        }
+        index.update_and_save(index_dir, false)?;
+        return Ok(());
+    }
+    let Some(root) = root else {
+        anyhow::bail!(
+            "No index found at {} (pass --database-root to build one)",
+            index_dir.display()
+        );
+    };
+    let options = scanner::ScanOptions {
+        excludes: compat::prune_excludes(&root, prunepaths, prunenames),
+        ..Default::default()
+    };
+    if verbose {
+        return scan_root(&root.to_string_lossy(), index_dir, &options, false, None);
+    }
+    let mut index = Index::from_scan(scanner::scan_directory_with(&root, &options, None, None)?);
+    index.save(index_dir)?;
+    registry::remember(index_dir, &index.meta.root);
+    Ok(())
+}
+
+fn prune_index(mut index: Index, index_dir: &Path, dry_run: bool) -> Result<()> {
+    let pruned = index.prune()?;
+    if dry_run {
+        for path in &pruned {
+            println!("{}", path.display());
+        }
+        println!("🧹 Would drop {} entries", pruned.len());
+        return Ok(());
+    }
+    if pruned.is_empty() {
+        println!("✅ Every indexed path still exists; nothing to prune.");
+        return Ok(());
+    }
+    let generation = index.save(index_dir)?;
+    println!(
+        "🧹 Dropped {} entries whose paths no longer exist (generation {})",
+        pruned.len(),
+        generation
+    );
+    Ok(())
+}
+
+fn verify_index(index_dir: &Path, repair: bool) -> Result<()> {
+    let report = verify::verify(index_dir)?;
+    println!("🔍 Verifying index at {}", index_dir.display());
+    if let Some(problem) = &report.pointer_problem {
+        println!("   ❌ Current generation pointer {}", problem);
+    }
+    for check in &report.generations {
+        let current = match Some(check.generation) == report.current {
+            true => " (current)",
+            false => "",
+        };
+        match &check.damage {
+            Some(damage) => println!(
+                "   ❌ Generation {}{}: {}",
+                check.generation, current, damage
+            ),
+            None => println!(
+                "   ✅ Generation {}{}: {} entries",
+                check.generation,
+                current,
+                check.entries.unwrap_or(0)
+            ),
+        }
+    }
+    for path in &report.orphans {
+        println!("   🗑️  Orphaned file: {}", path.display());
+    }
+    let quarantined = quarantine::quarantined(index_dir);
+    if !quarantined.is_empty() {
+        println!(
+            "   🧪 {} damaged file(s) set aside in {}",
+            quarantined.len(),
+            index_dir.join(quarantine::QUARANTINE_DIR).display()
+        );
+    }
+    let problems = report.problems();
+    if problems == 0 {
+        println!("✅ Index is intact");
+        return Ok(());
+    }
+    if !repair {
+        anyhow::bail!(
+            "Found {} problem(s); run `ss verify --repair` to fix them",
+            problems
+        );
+    }
+    for done in verify::repair(index_dir, &report)? {
+        println!("🔧 {}", done);
+    }
+    println!("✅ Repaired {} problem(s)", problems);
+    Ok(())
+}
+
+/// The JSON record of a `find` match, without its source index, last commit
+/// or formatted modification time
+fn find_record(hit: &daemon::Hit, score: i64) -> output::FindRecord<'_> {
+    output::FindRecord {
+        path: &hit.entry.path,
+        name: &hit.entry.name,
+        score,
+        size: hit.entry.size,
+        is_dir: hit.entry.is_dir,
+        is_symlink: hit.entry.is_symlink,
+        mtime: hit.entry.modified.and_then(output::unix_secs),
+        project: hit.project.as_deref(),
+        owners: &hit.owners,
+        index: None,
+        vendored: hit.entry.vendored,
+        hash: hit.entry.hash.as_ref().map(scanner::hash_hex),
+        mime: hit.entry.mime.and_then(filetype::mime_name),
+        user: hit.entry.attributes.uid.map(perms::user_label),
+        group: hit.entry.attributes.gid.map(perms::group_label),
+        mode: hit
+            .entry
+            .attributes
+            .mode
+            .map(|mode| format!("{:03o}", mode)),
+        readonly: hit.entry.attributes.readonly,
+        modified: None,
+        last_commit: None,
+        entry_point: hit.entry_point.as_deref(),
+    }
+}
+
+/// Implements the 'find' command functionality
+fn find_files(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    display: FindDisplay,
+    format: OutputFormat,
+) -> Result<()> {
+    let FindDisplay {
+        order,
+        rerank,
+        git,
+        color,
+        dates,
+        links,
+        listing,
+        template,
+    } = display;
+    let verbose = format.is_text() && listing.is_decorated();
+    let now = SystemTime::now();
+    let show_date = |time: SystemTime| match &dates {
+        Some(dates) => dates.format(time, now),
+        None => units::format_date(time),
+    };
+    let start = Instant::now();
+
+    // Without an index, matches can be printed while the directory is walked
+    let unindexed = generation.is_none()
+        && index_dirs.len() <= 1
+        && !index_dirs.iter().any(|index_dir| Index::exists(index_dir));
+    if order.no_sort && unindexed {
+        if git {
+            anyhow::bail!("--git needs every match found first; leave out --no-sort");
+        }
+        warn_unindexed(index_dirs);
+        let root = scanner::canonical_root(Path::new("."))?;
+        let mut out = std::io::stdout().lock();
+        let mut found = Vec::new();
+        let mut count = 0;
+        search::stream_matches(
+            &root,
+            query,
+            options,
+            &Default::default(),
+            |entry, score| {
+                count += 1;
+                let hit = daemon::Hit {
+                    entry,
+                    project: None,
+                    owners: Vec::new(),
+                    entry_point: None,
+                };
+                let printed = match (format, &template) {
+                    (OutputFormat::Json, _) => {
+                        found.push((hit, score));
+                        Ok(())
+                    }
+                    (OutputFormat::Ndjson, _) => {
+                        output::write_record(&mut out, format, &find_record(&hit, score))
+                    }
+                    (_, Some(template)) => {
+                        let values = template::Values {
+                            path: &hit.entry.path,
+                            entry: Some(&hit.entry),
+                            score: Some(score),
+                            line: None,
+                        };
+                        listing
+                            .write(&mut out, template.render(&values).as_bytes())
+                            .map_err(Into::into)
+                    }
+                    _ if !listing.is_decorated() => listing
+                        .write_path(&mut out, &hit.entry.path)
+                        .map_err(Into::into),
+                    _ => {
+                        let label = find_label(&root, &hit.entry, query, options, color, &links);
+                        match (&dates, hit.entry.modified) {
+                            (Some(_), Some(modified)) => println!(
+                                "  - {} (Score: {}, modified {})",
+                                label,
+                                score,
+                                show_date(modified)
+                            ),
+                            _ => println!("  - {} (Score: {})", label, score),
+                        }
+                        Ok(())
+                    }
+                };
+                // A closed pipe ends the walk
+                match printed {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            },
+        )?;
+        if format == OutputFormat::Json {
+            let records: Vec<_> = found
+                .iter()
+                .map(|(hit, score)| find_record(hit, *score))
+                .collect();
+            output::write_records(&mut out, format, &records)?;
+        } else if verbose {
+            drop(out);
+            println!(
+                "Found {} matches in {} ms",
+                count,
+                start.elapsed().as_millis()
+            );
+        }
+        return match count {
+            0 => Err(NoMatches.into()),
+            _ => Ok(()),
+        };
+    }
+
+    // The limit applies after re-sorting, so rank every match first
+    let search_options = match (&rerank, order.is_natural()) {
+        (Some(rerank), _) => SearchOptions {
+            limit: Some(rerank.candidates),
+            ..options.clone()
+        },
+        (None, true) => options.clone(),
+        (None, false) => SearchOptions {
+            limit: None,
+            ..options.clone()
+        },
+    };
+    // A running `ss daemon` answers without loading the index again
+    let served = match (index_dirs, generation) {
+        ([index_dir], None) => {
+            let request = daemon::Request::Find {
+                query: query.to_string(),
+                options: search_options.clone(),
+            };
+            daemon::request(index_dir, &request)?
+        }
+        _ => None,
+    };
+    let Found {
+        roots,
+        matches: found,
+    } = match served {
+        Some(daemon::Response::Found {
+            root,
+            generation,
+            matches,
+        }) => {
+            if verbose {
+                eprintln!(
+                    "   Index generation: {} (served by `ss daemon`)",
+                    generation
+                );
+            }
+            Found {
+                roots: vec![root],
+                matches: matches
+                    .into_iter()
+                    .map(|(hit, score)| (0, hit, score))
+                    .collect(),
+            }
+        }
+        Some(response) => return Err(daemon_error(response)),
+        None => find_in_indexes(query, index_dirs, generation, &search_options, verbose)?,
+    };
+
+    let mut matches: Vec<(usize, &daemon::Hit, i64)> = found
+        .iter()
+        .map(|(source, hit, score)| (*source, hit, *score))
+        .collect();
+    let several = roots.len() > 1;
+    let commits = if git || order.sort == SortOrder::LastCommit {
+        let mut commits = HashMap::new();
+        for (source, root) in roots.iter().enumerate() {
+            let paths: Vec<&Path> = matches
+                .iter()
+                .filter(|(from, _, _)| *from == source)
+                .map(|(_, hit, _)| hit.entry.path.as_path())
+                .collect();
+            commits.extend(git::last_commits(root, &paths)?);
+        }
+        commits
+    } else {
+        HashMap::new()
+    };
+    if !order.is_natural() {
+        let _span = profile::span("rank");
+        order.sort(&mut matches, &commits, |&(_, hit, _)| &hit.entry)?;
+        if let Some(limit) = order.limit {
+            matches.truncate(limit);
+        }
+    }
+    if let Some(rerank) = &rerank {
+        let _span = profile::span("rerank");
+        let candidates: Vec<output::FindRecord> = matches
+            .iter()
+            .map(|&(_, hit, score)| find_record(hit, score))
+            .collect();
+        let order = rerank::rerank(&rerank.command, query, &candidates)?;
+        matches = order.into_iter().map(|id| matches[id]).collect();
+        if let Some(limit) = options.limit {
+            matches.truncate(limit);
+        }
+    }
+
+    let _span = profile::span("print");
+    if !format.is_text() {
+        let records: Vec<output::FindRecord> = matches
+            .iter()
+            .map(|&(source, hit, score)| output::FindRecord {
+                index: several.then(|| index_dirs[source].as_path()),
+                last_commit: commits
+                    .get(&hit.entry.path)
+                    .map(|commit| output::CommitRecord {
+                        author: &commit.author,
+                        email: &commit.email,
+                        time: output::unix_secs(commit.time),
+                    }),
+                modified: dates
+                    .as_ref()
+                    .and_then(|_| Some(show_date(hit.entry.modified?))),
+                ..find_record(hit, score)
+            })
+            .collect();
+        output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+        return match matches.is_empty() {
+            true => Err(NoMatches.into()),
+            false => Ok(()),
+        };
+    }
+    if !listing.is_decorated() {
+        let mut out = std::io::stdout().lock();
+        for (_, hit, score) in &matches {
+            match &template {
+                Some(template) => {
+                    let values = template::Values {
+                        path: &hit.entry.path,
+                        entry: Some(&hit.entry),
+                        score: Some(*score),
+                        line: None,
+                    };
+                    listing.write(&mut out, template.render(&values).as_bytes())?
+                }
+                None => listing.write_path(&mut out, &hit.entry.path)?,
+            }
+        }
+        return match matches.is_empty() {
+            true => Err(NoMatches.into()),
+            false => Ok(()),
+        };
+    }
+
+    println!(
+        "Found {} potential matches in {} ms:",
+        matches.len(),
+        start.elapsed().as_millis()
+    );
+    if matches.is_empty() {
+        println!("  No files found matching your query.");
+        return Err(NoMatches.into());
+    } else {
+        for (source, hit, score) in matches {
+            let label = find_label(&roots[source], &hit.entry, query, options, color, &links);
+            let label = match several {
+                true => format!("{} [{}]", label, index_dirs[source].display()),
+                false => label,
+            };
+            let score = match (&dates, hit.entry.modified) {
+                (Some(_), Some(modified)) => format!("{}, modified {}", score, show_date(modified)),
+                _ => score.to_string(),
+            };
+            match commits.get(&hit.entry.path) {
+                Some(commit) => println!(
+                    "  - {} (Score: {}) — {}, {}",
+                    label,
+                    score,
+                    commit.author,
+                    show_date(commit.time)
+                ),
+                None if git => println!("  - {} (Score: {}) — uncommitted", label, score),
+                None => println!("  - {} (Score: {})", label, score),
+            }
+            if let Some(entry_point) = &hit.entry_point {
+                let relative = entry_point
+                    .strip_prefix(&hit.entry.path)
+                    .unwrap_or(entry_point);
+                let label = relative.display().to_string();
+                let label = match &links {
+                    Some(links) => links.link(&label, entry_point, None),
+                    None => label,
+                };
+                println!("      ↳ {}", label);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How `find` lists `entry`, found below `root`: its name, or its path
+/// when the query matches paths, highlighted and linked as asked
+fn find_label(
+    root: &Path,
+    entry: &FileEntry,
+    query: &str,
+    options: &SearchOptions,
+    color: bool,
+    links: &Option<HyperlinkFormat>,
+) -> String {
+    let label = match options.matches_full_path(query) {
+        true => search::relative_path(root, entry),
+        false => entry.name.clone(),
+    };
+    let label = match color {
+        true => {
+            let spans = search::match_spans(&label, query, options);
+            grep::highlight(&label, &spans, true)
+        }
+        false => label,
+    };
+    // Like `fd`, directories end in a slash
+    let label = match entry.is_dir {
+        true => format!("{}/", label),
+        false => label,
+    };
+    match links {
+        Some(links) => links.link(&label, &entry.path, None),
+        None => label,
+    }
+}
+
+/// Warn that `index_dirs` has no index, so the current directory is
+/// scanned instead
+fn warn_unindexed(index_dirs: &[PathBuf]) {
+    let index_dir = index_dirs.first().map_or(Path::new("."), PathBuf::as_path);
+    eprintln!(
+        "⚠️  No index found at {}; scanning the current directory instead.",
+        index_dir.display()
+    );
+    warn_index_suggestion(index_dir);
+    eprintln!("   Run `ss scan <path>` to build a persistent index.");
+}
+
+/// The command `find --exec` or `--exec-batch` runs on its matches
+enum ExecRequest {
+    /// Once per match, this many at a time
+    Each {
+        template: String,
+        jobs: NonZeroUsize,
+    },
+    /// Once with every match
+    Batch { template: String },
+}
+
+/// Implements `find --exec` and `--exec-batch`
+fn exec_matches(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    request: ExecRequest,
+) -> Result<()> {
+    let Found { matches, .. } = find_in_indexes(query, index_dirs, generation, options, false)?;
+    let paths: Vec<&Path> = matches
+        .iter()
+        .map(|(_, hit, _)| hit.entry.path.as_path())
+        .collect();
+    if paths.is_empty() {
+        return Err(NoMatches.into());
+    }
+    match request {
+        ExecRequest::Each { template, jobs } => {
+            let failed = exec::run_each(&template, &paths, jobs)?;
+            if failed > 0 {
+                anyhow::bail!("{} of {} commands failed", failed, paths.len());
+            }
+        }
+        ExecRequest::Batch { template } => {
+            let status = exec::run_batch(&template, &paths)?;
+            if !status.success() {
+                anyhow::bail!("`{}` failed with {}", template, status);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What `--open` or `--reveal` asks to do with the results
+fn launch_action(open: bool, reveal: bool) -> Option<launch::Action> {
+    match (open, reveal) {
+        (true, _) => Some(launch::Action::Open),
+        (_, true) => Some(launch::Action::Reveal),
+        _ => None,
+    }
+}
+
+/// How many results `--open` and `--reveal` launch: only the best one
+/// unless `--limit` asks for more
+fn launch_count(limit: Option<usize>) -> usize {
+    limit.unwrap_or(1)
+}
+
+/// Launch `action` on the targets, failing if any launcher did
+fn launch_targets(action: launch::Action, targets: &[launch::Target]) -> Result<()> {
+    let failed = launch::launch(action, targets)?;
+    if failed > 0 {
+        anyhow::bail!("{} of {} could not be opened", failed, targets.len());
+    }
+    Ok(())
+}
+
+/// Implements `find --open` and `--reveal`
+fn launch_matches(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    action: launch::Action,
+) -> Result<()> {
+    let Found { matches, .. } = find_in_indexes(query, index_dirs, generation, options, false)?;
+    let targets: Vec<launch::Target> = matches
+        .iter()
+        .take(launch_count(options.limit))
+        .map(|(_, hit, _)| launch::Target {
+            path: &hit.entry.path,
+            line: None,
+        })
+        .collect();
+    if targets.is_empty() {
+        return Err(NoMatches.into());
+    }
+    launch_targets(action, &targets)
+}
+
+/// Where and how `find --copy-to` or `--move-to` transfers its matches
+struct TransferRequest {
+    mode: transfer::Mode,
+    dest: PathBuf,
+    conflict: transfer::Conflict,
+    dry_run: bool,
+}
+
+/// Implements `find --copy-to` and `--move-to`
+fn transfer_matches(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    request: TransferRequest,
+    format: OutputFormat,
+) -> Result<()> {
+    let Found { matches, .. } =
+        find_in_indexes(query, index_dirs, generation, options, format.is_text())?;
+    let entries: Vec<&FileEntry> = matches.iter().map(|(_, hit, _)| &hit.entry).collect();
+    let transfers = transfer::plan(&entries, &request.dest, request.conflict);
+    if !request.dry_run {
+        transfer::apply(&transfers, request.mode)?;
+    }
+
+    let verb = match request.mode {
+        transfer::Mode::Copy => "copy",
+        transfer::Mode::Move => "move",
+    };
+    let action = |outcome| match outcome {
+        transfer::Outcome::New | transfer::Outcome::Renamed => verb,
+        transfer::Outcome::Overwrite => "overwrite",
+        transfer::Outcome::Skipped => "skip",
+    };
+    if !format.is_text() {
+        let records: Vec<output::TransferRecord> = transfers
+            .iter()
+            .map(|transfer| output::TransferRecord {
+                from: &transfer.from,
+                to: &transfer.to,
+                action: action(transfer.outcome),
+                dry_run: request.dry_run,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    for transfer in &transfers {
+        let (from, to) = (transfer.from.display(), transfer.to.display());
+        match transfer.outcome {
+            transfer::Outcome::New => println!("  {} {} → {}", verb, from, to),
+            transfer::Outcome::Renamed => println!("  {} {} → {} (renamed)", verb, from, to),
+            transfer::Outcome::Overwrite => println!("  {} {} → {} (overwriting)", verb, from, to),
+            transfer::Outcome::Skipped => println!("  skip {} ({} exists)", from, to),
+        }
+    }
+    let done = transfers
+        .iter()
+        .filter(|transfer| transfer.outcome != transfer::Outcome::Skipped)
+        .count();
+    let summary = match (request.dry_run, request.mode) {
+        (true, transfer::Mode::Copy) => "Would copy",
+        (true, transfer::Mode::Move) => "Would move",
+        (false, transfer::Mode::Copy) => "Copied",
+        (false, transfer::Mode::Move) => "Moved",
+    };
+    println!(
+        "📁 {} {} files to {}, skipped {}",
+        summary,
+        done,
+        request.dest.display(),
+        transfers.len() - done
+    );
+    if request.mode == transfer::Mode::Move && !request.dry_run && done > 0 {
+        println!("   Run `ss update` to refresh the index.");
+    }
+    Ok(())
+}
+
+/// Number of matches listed before `find --chmod`, `--touch` and the like
+/// ask to go ahead
+const CHANGE_PREVIEW: usize = 10;
+
+/// Implements `find --chmod`, `--chown`, `--touch`, `--set-readonly` and
+/// `--clear-hidden`: confirm, then change every match
+fn change_matches(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    change: &perms::Change,
+    yes: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let Found { matches, .. } =
+        find_in_indexes(query, index_dirs, generation, options, format.is_text())?;
+    let paths: Vec<&Path> = matches
+        .iter()
+        .map(|(_, hit, _)| hit.entry.path.as_path())
+        .collect();
+    if paths.is_empty() {
+        eprintln!("No files match; nothing to change");
+        return Ok(());
+    }
+
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "Refusing to change {} files without confirmation; pass --yes",
+                paths.len()
+            );
+        }
+        for path in paths.iter().take(CHANGE_PREVIEW) {
+            eprintln!("  {}", path.display());
+        }
+        if paths.len() > CHANGE_PREVIEW {
+            eprintln!("  … and {} more", paths.len() - CHANGE_PREVIEW);
+        }
+        let mut question = format!("{} {} files?", change.describe(), paths.len());
+        question[..1].make_ascii_uppercase();
+        if !init::confirm_default_no(
+            &mut std::io::stdin().lock(),
+            &mut std::io::stderr(),
+            &question,
+        )? {
+            eprintln!("Nothing changed");
+            return Ok(());
+        }
+    }
+
+    let outcomes: Vec<(&Path, Result<bool>)> = paths
+        .iter()
+        .map(|&path| (path, perms::apply(path, change)))
+        .collect();
+    let count = |status: &str| {
+        outcomes
+            .iter()
+            .filter(|(_, outcome)| change_status(outcome) == status)
+            .count()
+    };
+    let failed = count("failed");
+    if format.is_text() {
+        for (path, outcome) in &outcomes {
+            if let Err(e) = outcome {
+                eprintln!("  ⚠️  {}: {:#}", path.display(), e);
+            }
+        }
+        println!(
+            "🔐 Changed {} files, {} already matched, {} failed",
+            count("changed"),
+            count("unchanged"),
+            failed
+        );
+    } else {
+        let records: Vec<output::ChangeRecord> = outcomes
+            .iter()
+            .map(|(path, outcome)| output::ChangeRecord {
+                path,
+                status: change_status(outcome),
+                error: outcome.as_ref().err().map(|e| format!("{:#}", e)),
+            })
+            .collect();
+        output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} files couldn't be changed", failed, outcomes.len());
+    }
+    Ok(())
+}
+
+fn change_status(outcome: &Result<bool>) -> &'static str {
+    match outcome {
+        Ok(true) => "changed",
+        Ok(false) => "unchanged",
+        Err(_) => "failed",
+    }
+}
+
+/// What `find` found, wherever it searched
+struct Found {
+    /// Roots of the searched indexes
+    roots: Vec<PathBuf>,
+    /// Matches and their scores, with the position of the index each came from
+    matches: Vec<(usize, daemon::Hit, i64)>,
+}
+
+/// Implements `compat fd`: lists what fd would with `args`, from the index
+/// of the workspace around the first path searched
+fn fd_compat(index_dir: Option<PathBuf>, args: Vec<OsString>) -> Result<()> {
+    let args = compat::FdArgs::try_parse_from(args).unwrap_or_else(|e| e.exit());
+    let search = compat::FdSearch::new(args, &std::env::current_dir()?)?;
+    let index_dir = match index_dir.or_else(|| Config::get().index_dir.clone()) {
+        Some(index_dir) => index_dir,
+        None => {
+            let first = search.roots().next().unwrap_or(Path::new("."));
+            workspace::locate(first).index_dir
+        }
+    };
+    require_index(&index_dir)?;
+    let snapshot = MappedSnapshot::open(&index_dir)?;
+    let meta = &snapshot.index().meta;
+    if let Some(outside) = search.roots().find(|root| !root.starts_with(&meta.root)) {
+        anyhow::bail!(
+            "{} is outside the index of {}",
+            outside.display(),
+            meta.root.display()
+        );
+    }
+    for note in search.notes(&meta.scan_options) {
+        eprintln!("⚠️  Note: {}", note);
+    }
+    let options = SearchOptions {
+        filters: search.filters.clone(),
+        ..Default::default()
+    };
+    let mut paths: Vec<PathBuf> = snapshot
+        .search("", &options)?
+        .iter()
+        .filter_map(|(entry, _)| search.matches(entry))
+        .collect();
+    paths.sort();
+    paths.truncate(search.limit.unwrap_or(usize::MAX));
+    let listing = match search.print0 {
+        true => output::Listing::Nul,
+        false => output::Listing::Bare,
+    };
+    let mut out = std::io::stdout().lock();
+    for path in paths {
+        listing.write_path(&mut out, &path)?;
+    }
+    Ok(())
+}
+
+/// Search the indexes in `index_dirs` for `find`, printing the generation
+/// of each when `verbose`
+fn find_in_indexes(
+    query: &str,
+    index_dirs: &[PathBuf],
+    generation: Option<u64>,
+    options: &SearchOptions,
+    verbose: bool,
+) -> Result<Found> {
+    // Queries read from pinned snapshots so concurrent updates can't shift
+    // results, and from the mapped entries so only matches get decoded
+    let mut snapshots = Vec::new();
+    for index_dir in index_dirs {
+        let snapshot = match generation {
+            Some(generation) => MappedSnapshot::open_at(index_dir, generation)?,
+            None if Index::exists(index_dir) || index_dirs.len() > 1 => {
+                MappedSnapshot::open(index_dir)?
+            }
+            None => continue,
+        };
+        if verbose {
+            eprintln!("   Index generation: {}", snapshot.generation());
+        }
+        snapshots.push(snapshot);
+    }
+    let mut roots = Vec::new();
+    let mut matches: Vec<(usize, daemon::Hit, i64)> = Vec::new();
+    if snapshots.is_empty() {
+        // Without a stored index, fall back to scanning the current directory
+        warn_unindexed(index_dirs);
+        let index = Index::from_scan(scanner::scan_directory(".")?);
+        let found = index.search(query, options)?;
+        matches.extend(
+            found
+                .into_iter()
+                .map(|m| (0, daemon::Hit::new(&index, m.entry), m.score)),
+        );
+        daemon::Hit::attach_entry_points(
+            matches.iter_mut().map(|(_, hit, _)| hit),
+            index.entries.iter(),
+        );
+        roots.push(index.meta.root);
+    }
+    for (source, snapshot) in snapshots.iter().enumerate() {
+        let found = snapshot.search(query, options)?;
+        let start = matches.len();
+        matches.extend(
+            found
+                .into_iter()
+                .map(|(entry, score)| (source, daemon::Hit::new(snapshot.index(), &entry), score)),
+        );
+        daemon::Hit::attach_entry_points(
+            matches[start..].iter_mut().map(|(_, hit, _)| hit),
+            snapshot.entries().iter(),
+        );
+        roots.push(snapshot.index().meta.root.clone());
+    }
+    // Several indexes are ranked together, keeping the best match of files
+    // that more than one of them covers
+    if roots.len() > 1 {
+        matches.sort_by_key(|&(_, _, score)| std::cmp::Reverse(score));
+        let mut seen = HashSet::new();
+        matches.retain(|(_, hit, _)| seen.insert(hit.entry.path.clone()));
+        if let Some(limit) = options.limit {
+            matches.truncate(limit);
+        }
+    }
+    Ok(Found { roots, matches })
+}
+
+/// The error for a daemon response that doesn't answer the request
+fn daemon_error(response: daemon::Response) -> anyhow::Error {
+    match response {
+        daemon::Response::Error(message) => anyhow::anyhow!(message),
+        _ => anyhow::anyhow!("Unexpected response from the daemon"),
+    }
+}
+
+/// Run rayon's parallel work (content indexing and hashing) on `threads`
+/// threads, if given, to match the walker
+fn limit_threads(threads: Option<usize>) {
+    if let Some(threads) = threads {
+        // This only fails once the pool is running, which then keeps its size
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global();
+    }
+}
+
+/// Scan `path` into `index_dir`, or only apply what changed if `incremental`
+/// and the index already covers it. `content` is the maximum file size for
+/// a content index, if one should be built.
+fn scan_root(
+    path: &str,
+    index_dir: &Path,
+    options: &scanner::ScanOptions,
+    incremental: bool,
+    content: Option<u64>,
+) -> Result<()> {
+    if incremental && Index::exists(index_dir) {
+        let mut index = Index::load(index_dir)?;
+        if index.meta.root == dunce::canonicalize(path)? {
+            index.meta.scan_options.content_memory = options
+                .content_memory
+                .or(index.meta.scan_options.content_memory);
+            if !index.load_content(index_dir)?
+                && let Some(max_content_size) = content
+            {
+                index.build_content(max_content_size)?;
+            }
+            // Once hashed, an index keeps hashing on every update
+            index.meta.scan_options.hash |= options.hash;
+            index.meta.scan_options.compress |= options.compress;
+            index.meta.scan_options.archives |= options.archives;
+            index.meta.scan_options.transliterate |= options.transliterate;
+            index.meta.scan_options.sniff_mime |= options.sniff_mime;
+            index.meta.scan_options.fulltext |= options.fulltext;
+            index.meta.scan_options.strip_comments |= options.strip_comments;
+            if options.backend != storage::Backend::default() {
+                index.meta.scan_options.backend = options.backend;
+            }
+            index.meta.scan_options.threads = options.threads.or(index.meta.scan_options.threads);
+            index.meta.scan_options.keep_generations = options
+                .keep_generations
+                .or(index.meta.scan_options.keep_generations);
+            return update_index(index, index_dir);
+        }
+        println!(
+            "⚠️  Stored index covers {}; performing a full scan instead.",
+            index.meta.root.display()
+        );
+    }
+
+    println!("🔍 Scanning directory: {} (Ctrl-C to stop early)", path);
+    let cursor = dunce::canonicalize(path)
+        .ok()
+        .and_then(|root| journal::current_cursor(&root, index_dir));
+    let scan_result =
+        interruptible(|cancel| scanner::scan_directory_with(path, options, None, Some(cancel)))??;
+    let partial = scan_result.partial;
+    if partial {
+        println!("⚠️  Scan interrupted; keeping what was found so far");
+    } else {
+        println!("✅ Scan complete!");
+    }
+    println!("   Root Directory: {}", scan_result.root.display());
+    println!("   Files found: {}", scan_result.file_count);
+    println!("   Directories: {}", scan_result.dir_count);
+    println!(
+        "   Total Size: {}",
+        scanner::format_size(scan_result.total_size)
+    );
+    println!("   Elapsed Time: {} ms", scan_result.elapsed_ms);
+
+    let mut index = Index::from_scan(scan_result);
+    index.meta.journal = cursor;
+    if let Some(max_content_size) = content
+        && !partial
+    {
+        let start = Instant::now();
+        index.build_content(max_content_size)?;
+        if let Some(content) = &index.content {
+            println!(
+                "   Content Index: {} trigrams in {} ms",
+                content.trigram_count(),
+                start.elapsed().as_millis()
+            );
+        }
+    }
+    let generation = index.save(index_dir)?;
+    registry::remember(index_dir, &index.meta.root);
+    println!(
+        "   Index saved to: {} (generation {})",
+        index_dir.display(),
+        generation
+    );
+    println!(
+        "   Index in memory: {}",
+        scanner::format_size(index.heap_size() as u64)
+    );
+    if let Some(peak) = memory::peak_rss() {
+        println!("   Peak RSS: {}", scanner::format_size(peak));
+    }
+    if partial {
+        anyhow::bail!("Scan was interrupted; the index is partial until `ss update` completes it");
+    }
+    Ok(())
+}
+
+/// Run `work` with Ctrl-C setting the flag it's given instead of ending the
+/// process, so it can stop early and keep its results. A second Ctrl-C
+/// still exits straight away.
+fn interruptible<T>(work: impl FnOnce(&std::sync::atomic::AtomicBool) -> T) -> Result<T> {
+    use signal_hook::consts::SIGINT;
+
+    let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let handlers = [
+        signal_hook::flag::register_conditional_shutdown(
+            SIGINT,
+            130,
+            std::sync::Arc::clone(&cancel),
+        )?,
+        signal_hook::flag::register(SIGINT, std::sync::Arc::clone(&cancel))?,
+    ];
+    let result = work(&cancel);
+    for handler in handlers {
+        signal_hook::low_level::unregister(handler);
+    }
+    Ok(result)
+}
+
+/// Scan every root in `paths` into its own index under `data_dir` and
+/// record them as profile `name`
+fn scan_profile(
+    name: &str,
+    paths: &[String],
+    data_dir: &Path,
+    options: &scanner::ScanOptions,
+    incremental: bool,
+    content: Option<u64>,
+) -> Result<()> {
+    registry::check_profile_name(name)?;
+    let mut indexes = Vec::new();
+    for path in paths {
+        let root =
+            dunce::canonicalize(path).with_context(|| format!("Path does not exist: {}", path))?;
+        let index_dir = registry::profile_index_dir(data_dir, name, &root);
+        scan_root(path, &index_dir, options, incremental, content)?;
+        indexes.push(index_dir);
+    }
+    let registry_path = data_dir.join(registry::REGISTRY_FILE);
+    let mut registry = Registry::load_from(&registry_path)?;
+    registry.set_profile(name, indexes)?;
+    registry.save_to(&registry_path)?;
+    println!(
+        "📚 Profile '{}' covers {} roots; search it with `ss find <query> --index {}`",
+        name,
+        paths.len(),
+        name
+    );
+    Ok(())
+}
+
+/// Implements the 'grep' command functionality
+fn grep_files(
+    query: &str,
+    index_dirs: &[PathBuf],
+    options: &grep::GrepOptions,
+    filters: &Filters,
+    blame: &git::BlameFilter,
+    display: GrepDisplay,
+    format: OutputFormat,
+) -> Result<()> {
+    let GrepDisplay {
+        order,
+        color,
+        lines,
+        listing,
+        template,
+        links,
+        launch,
+    } = display;
+    let pattern = grep::build_pattern(query, options)?;
+    let launch_limit = launch_count(order.limit);
+    let mut launched: Vec<(PathBuf, Option<usize>)> = Vec::new();
+    let start = Instant::now();
+    let mut printer = GrepPrinter {
+        template: template.as_ref(),
+        listing,
+        lines: &lines,
+        links: links.as_ref(),
+        pattern: &pattern,
+        color,
+        format,
+        printed: Printed::default(),
+    };
+
+    // Files are printed as they're searched unless they must all be found
+    // first: for a single JSON document, or to blame their lines
+    if order.no_sort && format != OutputFormat::Json && blame.is_empty() {
+        let options = &grep::GrepOptions {
+            max_total: match (options.max_total, order.limit) {
+                (Some(max), Some(limit)) => Some(max.min(limit)),
+                (max, limit) => max.or(limit),
+            },
+            ..options.clone()
+        };
+        let (mut files, mut line_count) = (0, 0);
+        let mut print = |file: &grep::FileMatches, index: Option<&Path>, hit: &daemon::Hit| {
+            files += 1;
+            line_count += file.matches.len();
+            if launch.is_some() && launched.len() < launch_limit {
+                let line = file.matches.first().map(|m| m.line_number);
+                launched.push((file.path.clone(), line));
+            }
+            // A closed pipe ends the search
+            match printer.print(file, hit, index) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        };
+        let searched = grep_indexes(
+            index_dirs,
+            query,
+            &pattern,
+            options,
+            filters,
+            Some(&mut print),
+        )?
+        .searched;
+        eprintln!(
+            "Found {} matching lines in {} files ({} searched) in {} ms",
+            line_count,
+            files,
+            searched,
+            start.elapsed().as_millis()
+        );
+        if line_count == 0 {
+            return Err(NoMatches.into());
+        }
+        return match launch {
+            Some(action) => launch_targets(action, &grep_targets(&launched)),
+            None => Ok(()),
+        };
+    }
+
+    // A running `ss daemon` answers without loading the index again
+    let served = match index_dirs {
+        [index_dir] => {
+            let request = daemon::Request::Grep {
+                query: query.to_string(),
+                options: options.clone(),
+                filters: filters.clone(),
+            };
+            daemon::request(index_dir, &request)?
+        }
+        _ => None,
+    };
+    let Grepped {
+        roots,
+        hits,
+        mut results,
+        searched,
+    } = match served {
+        Some(daemon::Response::Grepped {
+            root,
+            files,
+            searched,
+            ..
+        }) => {
+            let (hits, results) = files
+                .into_iter()
+                .map(|(hit, file)| ((file.path.clone(), (0, hit)), file))
+                .unzip();
+            Grepped {
+                roots: vec![root],
+                hits,
+                results,
+                searched,
+            }
+        }
+        Some(response) => return Err(daemon_error(response)),
+        None => grep_indexes(index_dirs, query, &pattern, options, filters, None)?,
+    };
+    let several = roots.len() > 1;
+
+    if !blame.is_empty() && roots.iter().any(|root| git::toplevel(root).is_none()) {
+        anyhow::bail!("--changed-by and --since need the index root to be in a git repository");
+    }
+    blame.retain(&mut results);
+    if !order.is_natural() {
+        let mut commits = HashMap::new();
+        if order.sort == SortOrder::LastCommit {
+            for (source, root) in roots.iter().enumerate() {
+                let paths: Vec<&Path> = results
+                    .iter()
+                    .map(|file| file.path.as_path())
+                    .filter(|path| hits[*path].0 == source)
+                    .collect();
+                commits.extend(git::last_commits(root, &paths)?);
+            }
+        }
+        let _span = profile::span("rank");
+        order.sort(&mut results, &commits, |file| &hits[&file.path].1.entry)?;
+    }
+    if let Some(limit) = order.limit {
+        grep::limit_lines(&mut results, limit);
+    }
+    let line_count: usize = results.iter().map(|file| file.matches.len()).sum();
+    let _span = profile::span("print");
+    if format != OutputFormat::Json {
+        for file in &results {
+            let (source, hit) = &hits[&file.path];
+            let index = several.then(|| index_dirs[*source].as_path());
+            printer.print(file, hit, index)?;
+        }
+    } else {
+        let records: Vec<output::GrepRecord> = results
+            .iter()
+            .flat_map(|file| {
+                let (source, hit) = &hits[&file.path];
+                let index = several.then(|| index_dirs[*source].as_path());
+                file.matches.iter().map(move |m| output::GrepRecord {
+                    path: &file.path,
+                    line_number: m.line_number,
+                    column: m.column(),
+                    byte_offset: m.byte_offset(),
+                    line: &m.line,
+                    spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+                    before: &m.before,
+                    after: &m.after,
+                    owners: &hit.owners,
+                    index,
+                })
+            })
+            .collect();
+        output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+    }
+
+    eprintln!(
+        "Found {} matching lines in {} files ({} searched) in {} ms",
+        line_count,
+        results.len(),
+        searched,
+        start.elapsed().as_millis()
+    );
+    if line_count == 0 {
+        return Err(NoMatches.into());
+    }
+    if let Some(action) = launch {
+        launched.extend(results.iter().take(launch_limit).map(|file| {
+            (
+                file.path.clone(),
+                file.matches.first().map(|m| m.line_number),
+            )
+        }));
+        launch_targets(action, &grep_targets(&launched))?;
+    }
+    Ok(())
+}
+
+/// `grep --open` targets: each file at its first matching line
+fn grep_targets(files: &[(PathBuf, Option<usize>)]) -> Vec<launch::Target<'_>> {
+    files
+        .iter()
+        .map(|(path, line)| launch::Target { path, line: *line })
+        .collect()
+}
+
+/// Where `grep` hands each file it searched when printing them as they're
+/// found, with the index it came from when several are searched
+type GrepSink<'a> =
+    &'a mut dyn FnMut(&grep::FileMatches, Option<&Path>, &daemon::Hit) -> ControlFlow<()>;
+
+/// Prints what `grep` found one file at a time, in every format but a
+/// single JSON document
+struct GrepPrinter<'a> {
+    template: Option<&'a template::Template>,
+    listing: output::Listing,
+    lines: &'a LineArgs,
+    links: Option<&'a HyperlinkFormat>,
+    pattern: &'a regex::bytes::Regex,
+    color: bool,
+    format: OutputFormat,
+    printed: Printed,
+}
+
+impl GrepPrinter<'_> {
+    /// Print the matches in `file`, found by `hit`, from the index at
+    /// `index` when several are searched
+    fn print(
+        &mut self,
+        file: &grep::FileMatches,
+        hit: &daemon::Hit,
+        index: Option<&Path>,
+    ) -> Result<()> {
+        let (listing, lines) = (self.listing, self.lines);
+        let mut out = std::io::stdout().lock();
+        if !self.format.is_text() {
+            for m in &file.matches {
+                let record = output::GrepRecord {
+                    path: &file.path,
+                    line_number: m.line_number,
+                    column: m.column(),
+                    byte_offset: m.byte_offset(),
+                    line: &m.line,
+                    spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+                    before: &m.before,
+                    after: &m.after,
+                    owners: &hit.owners,
+                    index,
+                };
+                output::write_record(&mut out, self.format, &record)?;
+            }
+        } else if let Some(template) = self.template {
+            for m in &file.matches {
+                let values = template::Values {
+                    path: &file.path,
+                    entry: Some(&hit.entry),
+                    score: None,
+                    line: Some(m),
+                };
+                listing.write(&mut out, template.render(&values).as_bytes())?;
+            }
+        } else if listing == output::Listing::Nul || lines.files_with_matches {
+            listing.write_path(&mut out, &file.path)?;
+        } else if lines.count {
+            match lines.no_filename {
+                true => println!("{}", file.matches.len()),
+                false => println!("{}:{}", file.path.display(), file.matches.len()),
+            }
+        } else {
+            self.printed.last_line = None;
+            for m in &file.matches {
+                let path = file.path.display().to_string();
+                let path = match self.links {
+                    Some(links) => links.link(&path, &file.path, Some(m.line_number)),
+                    None => path,
+                };
+                lines.print(&path, m, self.pattern, self.color, &mut self.printed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `grep` found, wherever it searched
+struct Grepped {
+    /// Roots of the searched indexes
+    roots: Vec<PathBuf>,
+    /// Every matching file, with the position of the index it came from
+    hits: HashMap<PathBuf, (usize, daemon::Hit)>,
+    results: Vec<grep::FileMatches>,
+    /// Number of files searched
+    searched: usize,
+}
+
+/// Search the contents of the files in the indexes in `index_dirs`
+/// Rank the files of `index_dirs` by their relevance to the full-text `query`
+#[cfg(feature = "fulltext")]
+fn grep_fulltext(
+    query: &str,
+    index_dirs: &[PathBuf],
+    filters: &Filters,
+    limit: usize,
+    listing: output::Listing,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut hits = Vec::new();
+    for (source, index_dir) in index_dirs.iter().enumerate() {
+        require_index(index_dir)?;
+        let snapshot = Snapshot::open(index_dir)?;
+        let path = Index::fulltext_path(index_dir, snapshot.generation());
+        if !path.is_dir() {
+            anyhow::bail!(
+                "{} has no full-text index; run `ss scan --fulltext` first",
+                index_dir.display()
+            );
+        }
+        let filter = snapshot.entry_filter(filters)?;
+        let entries: HashMap<&Path, &FileEntry> = snapshot
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect();
+        let keep = |path: &Path| entries.get(path).is_some_and(|entry| filter.matches(entry));
+        let found = sonic_search::fulltext::search(&path, query, limit, keep)?;
+        hits.extend(found.into_iter().map(|hit| (source, hit)));
+    }
+    hits.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score));
+    hits.truncate(limit);
+
+    let several = index_dirs.len() > 1;
+    if !format.is_text() {
+        let records: Vec<_> = hits
+            .iter()
+            .map(|(source, hit)| output::FulltextRecord {
+                path: &hit.path,
+                score: hit.score,
+                index: several.then(|| index_dirs[*source].as_path()),
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    if hits.is_empty() && listing.is_decorated() {
+        println!("No files match {:?}", query);
+        return Ok(());
+    }
+    for (_, hit) in &hits {
+        if listing.is_decorated() {
+            println!("{:>7.2}  {}", hit.score, hit.path.display());
+        } else {
+            listing.write_path(&mut std::io::stdout().lock(), &hit.path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fulltext"))]
+fn grep_fulltext(
+    _query: &str,
+    _index_dirs: &[PathBuf],
+    _filters: &Filters,
+    _limit: usize,
+    _listing: output::Listing,
+    _format: OutputFormat,
+) -> Result<()> {
+    anyhow::bail!(
+        "`ss grep --fulltext` isn't available: sonic-search was built without the `fulltext` feature"
+    )
+}
+
+/// Search the files of the indexes in `index_dirs`, or of the current
+/// directory without an index. With `on_file`, every file is handed to it
+/// as soon as it's been searched instead of being collected.
+fn grep_indexes(
+    index_dirs: &[PathBuf],
+    query: &str,
+    pattern: &regex::bytes::Regex,
+    options: &grep::GrepOptions,
+    filters: &Filters,
+    on_file: Option<GrepSink>,
+) -> Result<Grepped> {
+    let mut snapshots = Vec::new();
+    for index_dir in index_dirs {
+        if Index::exists(index_dir) || index_dirs.len() > 1 {
+            let mut snapshot = Snapshot::open(index_dir)?;
+            if !snapshot.load_content(index_dir)? && options.doc_lang.is_some() {
+                anyhow::bail!(
+                    "--doc-lang needs a content index; rescan {} with `ss scan --content`",
+                    snapshot.meta.root.display()
+                );
+            }
+            snapshots.push(snapshot);
+        }
+    }
+    let fallback;
+    let indexes: Vec<&Index> = if snapshots.is_empty() {
+        if options.doc_lang.is_some() {
+            anyhow::bail!("--doc-lang needs a content index; build one with `ss scan --content`");
+        }
+        let index_dir = index_dirs.first().map_or(Path::new("."), PathBuf::as_path);
+        eprintln!(
+            "⚠️  No index found at {}; searching the current directory instead.",
+            index_dir.display()
+        );
+        warn_index_suggestion(index_dir);
+        fallback = Index::from_scan(scanner::scan_directory(".")?);
+        vec![&fallback]
+    } else {
+        snapshots.iter().map(|snapshot| &**snapshot).collect()
+    };
+
+    // Candidates remember the index they came from; a file covered by
+    // several indexes is only searched once
+    let mut paths = Vec::new();
+    let mut entries: HashMap<&Path, (usize, &FileEntry)> = HashMap::new();
+    for (source, index) in indexes.iter().enumerate() {
+        let filter = index.entry_filter(filters)?;
+        for entry in grep_candidates(index, query, options, &filter) {
+            if let hash_map::Entry::Vacant(slot) = entries.entry(entry.path.as_path()) {
+                slot.insert((source, entry));
+                paths.push(entry.path.clone());
+            }
+        }
+    }
+
+    let hit = |path: &Path| {
+        let (source, entry) = entries[path];
+        (source, daemon::Hit::new(indexes[source], entry))
+    };
+    let roots = indexes
+        .iter()
+        .map(|index| index.meta.root.clone())
+        .collect();
+    if let Some(on_file) = on_file {
+        let several = indexes.len() > 1;
+        grep::search_files_streaming(&paths, pattern, options, |file| {
+            let (source, hit) = hit(&file.path);
+            let index = several.then(|| index_dirs[source].as_path());
+            on_file(&file, index, &hit)
+        });
+        return Ok(Grepped {
+            roots,
+            hits: HashMap::new(),
+            results: Vec::new(),
+            searched: paths.len(),
+        });
+    }
+    let results = grep::search_files(&paths, pattern, options);
+    let hits = results
+        .iter()
+        .map(|file| (file.path.clone(), hit(&file.path)))
+        .collect();
+    Ok(Grepped {
+        roots,
+        hits,
+        results,
+        searched: paths.len(),
+    })
+}
+
+/// Implements `grep --follow`: print matches in lines written from now on,
+/// until interrupted
+fn grep_follow(
+    query: &str,
+    index_dir: &Path,
+    options: &grep::GrepOptions,
+    filters: &Filters,
+    color: bool,
+    lines: LineArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let index = Index::load(index_dir)?;
+    let filter = index.entry_filter(filters)?;
+    let pattern = grep::build_pattern(query, options)?;
+    let entries: HashMap<&Path, &FileEntry> = index
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    eprintln!(
+        "👀 Following {} for {} (Ctrl-C to stop)",
+        index.meta.root.display(),
+        query
+    );
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    let debounce = Duration::from_millis(100);
+    let mut printed = Printed::default();
+    watch::follow(
+        &index,
+        index_dir,
+        &filter,
+        &pattern,
+        debounce,
+        &stop,
+        |file| {
+            printed.last_line = None;
+            for m in &file.matches {
+                if format.is_text() {
+                    let path = file.path.display().to_string();
+                    lines.print(&path, m, &pattern, color, &mut printed);
+                    continue;
+                }
+                let record = output::GrepRecord {
+                    path: &file.path,
+                    line_number: m.line_number,
+                    column: m.column(),
+                    byte_offset: m.byte_offset(),
+                    line: &m.line,
+                    spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+                    before: &m.before,
+                    after: &m.after,
+                    // Files created while following have no owners recorded yet
+                    owners: entries
+                        .get(file.path.as_path())
+                        .map_or(&[], |entry| index.owners_of(entry)),
+                    index: None,
+                };
+                // An array can't be streamed, so JSON is written one record per line too
+                let _ = output::write_record(
+                    &mut std::io::stdout().lock(),
+                    OutputFormat::Ndjson,
+                    &record,
+                );
+            }
+            ControlFlow::Continue(())
+        },
+    )
+}
+
+/// What `watch-for` does once its pattern appears
+struct Trigger {
+    exec: Option<String>,
+    timeout: Option<Duration>,
+    /// Check the current contents before waiting for new ones
+    existing: bool,
+}
+
+/// Implements the 'watch-for' command
+fn watch_for(
+    query: &str,
+    index_dir: &Path,
+    options: &grep::GrepOptions,
+    filters: &Filters,
+    trigger: Trigger,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let mut index = Index::load(index_dir)?;
+    let filter = index.entry_filter(filters)?;
+    let pattern = grep::build_pattern(query, options)?;
+
+    let mut found = None;
+    if trigger.existing {
+        index.load_content(index_dir)?;
+        let paths: Vec<PathBuf> = grep_candidates(&index, query, options, &filter)
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        found = grep::search_files(&paths, &pattern, options)
+            .into_iter()
+            .next();
+    }
+    if found.is_none() {
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        if let Some(timeout) = trigger.timeout {
+            let stop = std::sync::Arc::clone(&stop);
+            std::thread::spawn(move || {
+                std::thread::sleep(timeout);
+                stop.store(true, std::sync::atomic::Ordering::Relaxed);
+            });
+        }
+        let debounce = Duration::from_millis(100);
+        watch::follow(
+            &index,
+            index_dir,
+            &filter,
+            &pattern,
+            debounce,
+            &stop,
+            |file| {
+                found = Some(file);
+                ControlFlow::Break(())
+            },
+        )?;
+    }
+    let Some(file) = found else {
+        anyhow::bail!("'{}' did not appear within the timeout", query);
+    };
+
+    let m = &file.matches[0];
+    if format.is_text() {
+        println!("{}:{}:{}", file.path.display(), m.line_number, m.line);
+    } else {
+        let record = output::GrepRecord {
+            path: &file.path,
+            line_number: m.line_number,
+            column: m.column(),
+            byte_offset: m.byte_offset(),
+            line: &m.line,
+            spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+            before: &m.before,
+            after: &m.after,
+            owners: &[],
+            index: None,
+        };
+        output::write_record(&mut std::io::stdout().lock(), format, &record)?;
+    }
+    let Some(exec) = trigger.exec else {
+        return Ok(());
+    };
+    let status = exec::shell(&exec)
+        .env("SS_MATCH_PATH", &file.path)
+        .env("SS_MATCH_LINE", m.line_number.to_string())
+        .env("SS_MATCH_TEXT", &m.line)
+        .status()
+        .with_context(|| format!("Failed to run `{}`", exec))?;
+    if !status.success() {
+        anyhow::bail!("`{}` failed with {}", exec, status);
+    }
+    Ok(())
+}
+
+/// Files `grep` has to read for `query`, narrowed down by the content index
+/// when it's loaded
+fn grep_candidates<'i>(
+    index: &'i Index,
+    query: &str,
+    options: &grep::GrepOptions,
+    filter: &EntryFilter,
+) -> Vec<&'i FileEntry> {
+    let candidates: Vec<&FileEntry> = match index.grep_candidates(query, options) {
+        Some(candidates) => {
+            eprintln!(
+                "   Content index narrowed search to {} of {} files",
+                candidates.len(),
+                index.file_count()
+            );
+            candidates
+        }
+        None => {
+            if index.content.is_some() {
+                eprintln!(
+                    "   Content index can't narrow this pattern; searching all {} files",
+                    index.file_count()
+                );
+            }
+            index.files().collect()
+        }
+    };
+    candidates
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect()
+}
+
+/// Implements the 'diff' command: what changed on disk between the
+/// generations current at `from` and `to`
+fn diff_generations(
+    index_dir: &Path,
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+    filters: &Filters,
+    dates: Option<&units::DateStyle>,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let pick = |time: SystemTime| {
+        Index::generation_at(index_dir, time)?.with_context(|| {
+            let times = Index::generation_times(index_dir).unwrap_or_default();
+            match times.first() {
+                Some(&(_, oldest)) => format!(
+                    "No generation of the index is as old as {}; the oldest stored is from {}",
+                    units::format_date(time),
+                    units::format_date(oldest)
+                ),
+                None => "The index has no generation".to_string(),
+            }
+        })
+    };
+    let newer = match to {
+        Some(time) => pick(time)?,
+        None => Index::current_generation(index_dir)?.context("The index has no generation")?,
+    };
+    let older = match from {
+        Some(time) => pick(time)?,
+        None => Index::generations(index_dir)?
+            .into_iter()
+            .rfind(|&generation| generation < newer)
+            .context(
+                "Only one generation of the index is stored; keep more with \
+                 `ss scan --keep-generations <N>` to compare them",
+            )?,
+    };
+
+    let old = Snapshot::open_at(index_dir, older)?;
+    let new = Snapshot::open_at(index_dir, newer)?;
+    let (old_filter, new_filter) = (old.entry_filter(filters)?, new.entry_filter(filters)?);
+    let changes = diff::compare(
+        old.entries.iter().filter(|entry| old_filter.matches(entry)),
+        new.entries.iter().filter(|entry| new_filter.matches(entry)),
+    );
+
+    if !format.is_text() {
+        let records: Vec<output::DiffChangeRecord> = changes
+            .iter()
+            .map(|change| output::DiffChangeRecord {
+                path: &change.path,
+                change: change.kind.as_str(),
+                is_dir: change.is_dir,
+                old_size: change.old_size,
+                new_size: change.new_size,
+                mtime: change.modified.and_then(output::unix_secs),
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    let style = dates.cloned().unwrap_or(units::DateStyle {
+        format: units::DateFormat::Custom("%Y-%m-%d %H:%M".to_string()),
+        utc: false,
+    });
+    let now = SystemTime::now();
+    let show = |snapshot: &Snapshot| {
+        format!(
+            "generation {} ({})",
+            snapshot.generation(),
+            style.format(snapshot.meta.updated_at, now)
+        )
+    };
+    let count = |kind| changes.iter().filter(|change| change.kind == kind).count();
+    let growth: i64 = changes.iter().map(diff::Change::growth).sum();
+    println!("📊 Changes from {} to {}", show(&old), show(&new));
+    println!(
+        "   {} added, {} removed, {} grown, {} shrunk, {} modified ({}{})",
+        count(diff::ChangeKind::Added),
+        count(diff::ChangeKind::Removed),
+        count(diff::ChangeKind::Grown),
+        count(diff::ChangeKind::Shrunk),
+        count(diff::ChangeKind::Modified),
+        if growth < 0 { "-" } else { "+" },
+        scanner::format_size(growth.unsigned_abs())
+    );
+    let root = &new.meta.root;
+    for change in &changes {
+        let marker = match change.kind {
+            diff::ChangeKind::Added => "+",
+            diff::ChangeKind::Removed => "-",
+            diff::ChangeKind::Grown => ">",
+            diff::ChangeKind::Shrunk => "<",
+            diff::ChangeKind::Modified => "~",
+        };
+        let path = change.path.strip_prefix(root).unwrap_or(&change.path);
+        let sizes = match (change.old_size, change.new_size) {
+            _ if change.is_dir => "directory".to_string(),
+            (Some(old), Some(new)) if old != new => format!(
+                "{} → {}",
+                scanner::format_size(old),
+                scanner::format_size(new)
+            ),
+            (Some(size), _) | (_, Some(size)) => scanner::format_size(size),
+            (None, None) => String::new(),
+        };
+        println!("  {} {} ({})", marker, path.display(), sizes);
+    }
+    Ok(())
+}
+
+/// Implements the 'diff-results' command: files matched by one query but
+/// not the other, compared by name like `find` or by contents with `grep`
+fn diff_results(
+    queries: [&str; 2],
+    index_dir: &Path,
+    grep: Option<&grep::GrepOptions>,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    let snapshot = if Index::exists(index_dir) {
+        let mut snapshot = Snapshot::open(index_dir)?;
+        if grep.is_some() {
+            snapshot.load_content(index_dir)?;
+        }
+        Some(snapshot)
+    } else {
+        None
+    };
+    let fallback;
+    let index: &Index = match &snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            eprintln!(
+                "⚠️  No index found at {}; scanning the current directory instead.",
+                index_dir.display()
+            );
+            warn_index_suggestion(index_dir);
+            fallback = Index::from_scan(scanner::scan_directory(".")?);
+            &fallback
+        }
+    };
+
+    let filter = index.entry_filter(filters)?;
+    let matched = |query| matched_paths(index, query, grep, filters, &filter);
+    let [first, second] = [matched(queries[0])?, matched(queries[1])?];
+    let only = [
+        first.difference(&second).collect::<Vec<_>>(),
+        second.difference(&first).collect::<Vec<_>>(),
+    ];
+
+    if !format.is_text() {
+        let records: Vec<output::DiffRecord> = only
+            .iter()
+            .zip(queries)
+            .flat_map(|(paths, query)| {
+                paths.iter().map(move |path| output::DiffRecord {
+                    path,
+                    only_in: query,
+                })
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    for (paths, query) in only.iter().zip(queries) {
+        println!("Only matched by \"{}\" ({}):", query, paths.len());
+        for path in paths {
+            println!(
+                "  {}",
+                path.strip_prefix(&index.meta.root)
+                    .unwrap_or(path)
+                    .display()
+            );
+        }
+    }
+    println!("Matched by both: {}", first.intersection(&second).count());
+    Ok(())
+}
+
+/// Every file matched by `query`, by name or with `grep` by contents
+fn matched_paths(
+    index: &Index,
+    query: &str,
+    grep: Option<&grep::GrepOptions>,
+    filters: &Filters,
+    filter: &EntryFilter,
+) -> Result<BTreeSet<PathBuf>> {
+    Ok(match grep {
+        Some(options) => {
+            let pattern = grep::build_pattern(query, options)?;
+            let paths: Vec<PathBuf> = grep_candidates(index, query, options, filter)
+                .iter()
+                .map(|entry| entry.path.clone())
+                .collect();
+            grep::search_files(&paths, &pattern, options)
+                .into_iter()
+                .map(|file| file.path)
+                .collect()
+        }
+        None => {
+            let options = SearchOptions {
+                filters: filters.clone(),
+                ..Default::default()
+            };
+            index
+                .search(query, &options)?
+                .into_iter()
+                .map(|m| m.entry.path.clone())
+                .collect()
+        }
+    })
+}
+
+/// Implements the 'count' command
+fn disk_usage(
+    index_dir: &Path,
+    path: Option<&Path>,
+    depth: usize,
+    top: Option<usize>,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let start = match path {
+        Some(path) => dunce::canonicalize(path)
+            .with_context(|| format!("Path does not exist: {}", path.display()))?,
+        None => snapshot.meta.root.clone(),
+    };
+    let filter = snapshot.entry_filter(filters)?;
+    let rows = du::usage(
+        snapshot
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry)),
+        &start,
+        depth,
+        top,
+    );
+    if !format.is_text() {
+        return output::write_records(&mut std::io::stdout().lock(), format, &rows);
+    }
+    if rows.is_empty() {
+        println!("No indexed files under {}", start.display());
+        return Ok(());
+    }
+    println!("{:>10} {:>8}  Directory", "Size", "Files");
+    for row in &rows {
+        let name = match row.depth {
+            0 => row.path.display().to_string(),
+            _ => row
+                .path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+        };
+        println!(
+            "{:>10} {:>8}  {}{}/",
+            scanner::format_size(row.size),
+            row.files,
+            "  ".repeat(row.depth),
+            name.trim_end_matches('/')
+        );
+    }
+    Ok(())
+}
+
+fn count_files(
+    index_dir: &Path,
+    lines: bool,
+    by: CountBy,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let rows = count::count(&snapshot, &entries, by.into(), lines);
+    if !format.is_text() {
+        return output::write_records(&mut std::io::stdout().lock(), format, &rows);
+    }
+
+    let label = |key: &str| match (key.is_empty(), by) {
+        (false, CountBy::Ext) => format!(".{}", key),
+        (false, _) => key.to_string(),
+        (true, CountBy::Ext) => "(none)".to_string(),
+        (true, CountBy::Project) => "(no project)".to_string(),
+        (true, CountBy::Dir) => "(root)".to_string(),
+    };
+    let width = rows
+        .iter()
+        .map(|row| label(&row.key).len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let total = rows.iter().fold(
+        count::CountRow {
+            key: String::new(),
+            files: 0,
+            size: 0,
+            lines: lines.then(count::LineCount::default),
+        },
+        |mut total, row| {
+            total.files += row.files;
+            total.size += row.size;
+            if let (Some(sum), Some(lines)) = (&mut total.lines, row.lines) {
+                sum.lines += lines.lines;
+                sum.blank += lines.blank;
+            }
+            total
+        },
+    );
+    match lines {
+        true => println!(
+            "{:<width$} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "", "Files", "Size", "Lines", "Code", "Blank"
+        ),
+        false => println!("{:<width$} {:>8} {:>10}", "", "Files", "Size"),
+    }
+    let print_row = |name: &str, row: &count::CountRow| {
+        print!(
+            "{:<width$} {:>8} {:>10}",
+            name,
+            row.files,
+            scanner::format_size(row.size)
+        );
+        match row.lines {
+            Some(l) => println!(" {:>10} {:>10} {:>10}", l.lines, l.code(), l.blank),
+            None => println!(),
+        }
+    };
+    for row in &rows {
+        print_row(&label(&row.key), row);
+    }
+    print_row("Total", &total);
+    Ok(())
+}
+
+/// Implements the 'dupes' command
+fn find_dupes(
+    index_dir: &Path,
+    min_size: u64,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| entry.size >= min_size && filter.matches(entry))
+        .collect();
+    let start = Instant::now();
+    let groups = dupes::find_duplicates(&entries);
+
+    if !format.is_text() {
+        let records: Vec<output::DupeRecord> = groups
+            .iter()
+            .map(|group| output::DupeRecord {
+                size: group.size,
+                hash: &group.hash,
+                reclaimable: group.reclaimable(),
+                paths: &group.paths,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    let reclaimable: u64 = groups.iter().map(|group| group.reclaimable()).sum();
+    println!(
+        "🧬 {} duplicate groups, {} reclaimable ({} files checked in {} ms)",
+        groups.len(),
+        scanner::format_size(reclaimable),
+        entries.len(),
+        start.elapsed().as_millis()
+    );
+    let root = &snapshot.meta.root;
+    for group in &groups {
+        println!(
+            "\n  {} × {} ({} reclaimable)",
+            group.paths.len(),
+            scanner::format_size(group.size),
+            scanner::format_size(group.reclaimable())
+        );
+        for path in &group.paths {
+            println!("    {}", path.strip_prefix(root).unwrap_or(path).display());
+        }
+    }
+    Ok(())
+}
+
+/// Implements the 'same-as' command
+fn find_same_as(
+    file: &Path,
+    index_dir: &Path,
+    verify: bool,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    if !file.is_file() {
+        anyhow::bail!("Not a file: {}", file.display());
+    }
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let start = Instant::now();
+    let found = dupes::copies_of(file, &entries, verify)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    if !format.is_text() {
+        let records: Vec<output::SameAsRecord> = found
+            .copies
+            .iter()
+            .map(|copy| output::SameAsRecord {
+                path: &copy.path,
+                size: found.size,
+                hash: &found.hash,
+                verified: copy.verified,
+            })
+            .collect();
+        output::write_records(&mut std::io::stdout().lock(), format, &records)?;
+        return match records.is_empty() {
+            true => Err(NoMatches.into()),
+            false => Ok(()),
+        };
+    }
+
+    if found.copies.is_empty() {
+        println!(
+            "🔍 No indexed copies of {} ({})",
+            file.display(),
+            scanner::format_size(found.size)
+        );
+        return Err(NoMatches.into());
+    }
+    let unverified = found.copies.iter().filter(|copy| !copy.verified).count();
+    println!(
+        "🧬 {} copies of {} ({}, BLAKE3 {}) in {} ms",
+        found.copies.len(),
+        file.display(),
+        scanner::format_size(found.size),
+        &found.hash[..16],
+        start.elapsed().as_millis()
+    );
+    let root = &snapshot.meta.root;
+    for copy in &found.copies {
+        println!(
+            "   {}",
+            copy.path.strip_prefix(root).unwrap_or(&copy.path).display()
+        );
+    }
+    if unverified > 0 {
+        println!(
+            "   {} matched by the hash stored at the last scan; --verify reads them again",
+            unverified
+        );
+    }
+    Ok(())
+}
+
+/// Implements the 'name-collisions' command
+fn find_name_collisions(index_dir: &Path, filters: &Filters, format: OutputFormat) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let start = Instant::now();
+    let root = &snapshot.meta.root;
+    let collisions = collisions::find_collisions(root, &entries);
+
+    if !format.is_text() {
+        let records: Vec<output::CollisionRecord> = collisions
+            .iter()
+            .map(|collision| output::CollisionRecord {
+                directory: &collision.dir,
+                kind: collision.kind.as_str(),
+                names: &collision.names,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    println!(
+        "🔠 {} name collisions ({} files checked in {} ms)",
+        collisions.len(),
+        entries.len(),
+        start.elapsed().as_millis()
+    );
+    for collision in &collisions {
+        let dir = collision.dir.strip_prefix(root).unwrap_or(&collision.dir);
+        let dir = match dir.as_os_str().is_empty() {
+            true => ".".to_string(),
+            false => dir.display().to_string(),
+        };
+        println!("\n  {} (differ in {})", dir, collision.kind.as_str());
+        for name in &collision.names {
+            match collision.kind {
+                collisions::CollisionKind::Case => println!("    {}", name),
+                collisions::CollisionKind::Normalization => {
+                    println!("    {}  ({})", name, collisions::normalization_form(name))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Implements the 'audit --portability' command
+fn audit_portability(
+    index_dir: &Path,
+    max_path: usize,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let start = Instant::now();
+    let root = &snapshot.meta.root;
+    let issues = portability::audit(root, &entries, max_path);
+
+    if !format.is_text() {
+        let records: Vec<output::PortabilityRecord> = issues
+            .iter()
+            .map(|issue| output::PortabilityRecord {
+                path: &issue.path,
+                problem: issue.problem.kind(),
+                detail: issue.problem.describe(),
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+
+    println!(
+        "🧳 {} portability problems ({} files checked in {} ms)",
+        issues.len(),
+        entries.len(),
+        start.elapsed().as_millis()
+    );
+    for issue in &issues {
+        let path = issue.path.strip_prefix(root).unwrap_or(&issue.path);
+        println!("  {:?}: {}", path, issue.problem.describe());
+    }
+    Ok(())
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Index a directory for searching
-    Scan {
-        /// Directory path to scan
-        path: String,
-    },
-    /// Find files by name
-    Find {
-        /// Search query
-        query: String,
-        /// Path to the index directory (optional)
-        #[arg(short, long, default_value = ".sonic-search")]
-        index_dir: PathBuf,
-    },
-    /// Show index statistics
-    Stats {
-        /// Path to the index directory (optional)
-        #[arg(short, long, default_value = ".sonic-search")]
-        index_dir: PathBuf,
-    },
-    /// Search inside file contents (Phase 2)
-    Grep {
-        /// Search query
-        query: String,
-        /// Path to the index directory (optional)
-        #[arg(short, long, default_value = ".sonic-search")]
-        index_dir: PathBuf,
-    },
-}
+/// Implements the 'configs' command
+fn list_configs(
+    index_dir: &Path,
+    app: Option<&str>,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    require_index(index_dir)?;
+    let app = match app {
+        Some(name) => {
+            let known = configs::applications().find(|known| known.eq_ignore_ascii_case(name));
+            match (known, suggest::closest(name, configs::applications())) {
+                (Some(known), _) => Some(known),
+                (None, Some(close)) => {
+                    anyhow::bail!("Unknown application '{}'. Did you mean '{}'?", name, close)
+                }
+                (None, None) => anyhow::bail!(
+                    "Unknown application '{}'; known ones are: {}",
+                    name,
+                    configs::applications().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+        None => None,
+    };
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let start = Instant::now();
+    let root = &snapshot.meta.root;
+    let mut groups = configs::group(&entries);
+    if let Some(app) = app {
+        groups.retain(|known, _| *known == app);
+    }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+    if !format.is_text() {
+        let records: Vec<output::ConfigRecord> = groups
+            .iter()
+            .flat_map(|(app, files)| {
+                files.iter().map(|entry| output::ConfigRecord {
+                    application: app,
+                    path: &entry.path,
+                    size: entry.size,
+                })
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
 
-    match cli.command {
-        Commands::Scan { path } => {
-            println!("🔍 Scanning directory: {}", path);
-            let scan_result = scanner::scan_directory(&path)?;
-            println!("✅ Scan complete!");
-            println!("   Root Directory: {}", scan_result.root.display());
-            println!("   Files found: {}", scan_result.file_count);
-            println!("   Directories: {}", scan_result.dir_count);
-            println!("   Total Size: {}", scanner::format_size(scan_result.total_size));
-            println!("   Elapsed Time: {} ms", scan_result.elapsed_ms);
-
-            // TODO: Save scan_result to index_dir
-            // For now, just print
-            Ok(())
+    println!(
+        "⚙️  {} configuration files of {} applications ({} files checked in {} ms)",
+        groups.values().map(Vec::len).sum::<usize>(),
+        groups.len(),
+        entries.len(),
+        start.elapsed().as_millis()
+    );
+    if !snapshot.meta.scan_options.hidden {
+        println!("   Dotfiles aren't indexed; scan with --hidden to include them");
+    }
+    for (app, files) in &groups {
+        let size: u64 = files.iter().map(|entry| entry.size).sum();
+        println!(
+            "\n  {} ({} files, {})",
+            app,
+            files.len(),
+            scanner::format_size(size)
+        );
+        for entry in files {
+            println!(
+                "    {}",
+                entry
+                    .path
+                    .strip_prefix(root)
+                    .unwrap_or(&entry.path)
+                    .display()
+            );
         }
-        Commands::Find { query, index_dir } => {
-            println!("🔎 Searching for: {}", query);
-            find_files(&query, &index_dir)?;
-            Ok(())
+    }
+    Ok(())
+}
+
+/// Implements the 'collect' command: archive every file `find` matches
+fn collect_files(
+    query: &str,
+    index_dir: &Path,
+    options: &SearchOptions,
+    out: &Path,
+    limits: collect::Limits,
+    format: OutputFormat,
+) -> Result<()> {
+    let archive = collect::ArchiveFormat::from_path(out)?;
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let start = Instant::now();
+    let matches = snapshot.search(query, options)?;
+    let entries: Vec<&FileEntry> = matches.iter().map(|m| m.entry).collect();
+    limits.check(&entries)?;
+    let report = collect::write_archive(&snapshot.meta.root, &entries, out, archive)?;
+
+    for path in &report.skipped {
+        eprintln!("⚠️  Skipped {}: it no longer exists", path.display());
+    }
+    if !format.is_text() {
+        let record = output::CollectRecord {
+            archive: out,
+            files: report.files,
+            size: report.size,
+            skipped: &report.skipped,
+        };
+        return output::write_record(&mut std::io::stdout().lock(), format, &record);
+    }
+    println!(
+        "📦 Archived {} files ({}) into {} in {} ms",
+        report.files,
+        scanner::format_size(report.size),
+        out.display(),
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+/// Implements the 'rename' command
+fn rename_files(
+    index_dir: &Path,
+    pattern: &regex::Regex,
+    template: &str,
+    filters: &Filters,
+    dry_run: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let snapshot = Snapshot::open(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let entries: Vec<&FileEntry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let renames = rename::plan(&entries, pattern, template)?;
+    if !dry_run {
+        rename::apply(index_dir, &renames)?;
+    }
+    print_renames(&renames, dry_run, format)?;
+    if format.is_text() {
+        let summary = if dry_run { "Would rename" } else { "Renamed" };
+        println!("✏️  {} {} files", summary, renames.len());
+        if !dry_run && !renames.is_empty() {
+            println!("   Undo with `ss rename --undo`; run `ss update` to refresh the index.");
         }
-        Commands::Stats { index_dir } => {
-            println!("📊 Index Statistics");
-            show_stats(&index_dir)?;
-            Ok(())
+    }
+    Ok(())
+}
+
+/// What `replace` changes
+struct Edit<'a> {
+    pattern: &'a str,
+    replacement: &'a str,
+    eol: Option<replace::LineEnding>,
+    preserve_mtime: bool,
+    backup: Option<&'a str>,
+    dry_run: bool,
+}
+
+/// Implements the 'replace' command. A dry run prints only the diffs on
+/// stdout so they can be piped into `git apply` or a pager.
+fn replace_matches(
+    index_dir: &Path,
+    edit: &Edit,
+    options: &grep::GrepOptions,
+    filters: &Filters,
+    format: OutputFormat,
+) -> Result<()> {
+    let pattern = grep::build_pattern(edit.pattern, options)?;
+    let mut snapshot = Snapshot::open(index_dir)?;
+    snapshot.load_content(index_dir)?;
+    let filter = snapshot.entry_filter(filters)?;
+    let paths: Vec<PathBuf> = grep_candidates(&snapshot, edit.pattern, options, &filter)
+        .into_iter()
+        .map(|entry| entry.path.clone())
+        .collect();
+    let mut replacements = replace::plan(&paths, &pattern, edit.replacement, edit.eol, options);
+    let root = &snapshot.meta.root;
+    if !edit.dry_run {
+        let changed = replace::apply(&replacements, edit.preserve_mtime, edit.backup)?;
+        for path in &changed {
+            let path = path.strip_prefix(root).unwrap_or(path);
+            eprintln!(
+                "⚠️  Skipped {}: it changed since it was read",
+                path.display()
+            );
         }
-        Commands::Grep { query, index_dir } => {
-            println!("grep: Feature not yet implemented. Query: {}", query);
-            Ok(())
+        replacements.retain(|replacement| !changed.contains(&replacement.path));
+    }
+
+    if !format.is_text() {
+        let records: Vec<output::ReplaceRecord> = replacements
+            .iter()
+            .map(|replacement| output::ReplaceRecord {
+                path: &replacement.path,
+                replacements: replacement.count,
+                diff: replacement.unified_diff(root),
+                dry_run: edit.dry_run,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    let count: usize = replacements
+        .iter()
+        .map(|replacement| replacement.count)
+        .sum();
+    if edit.dry_run {
+        for replacement in &replacements {
+            print!("{}", replacement.unified_diff(root));
         }
+        eprintln!(
+            "✏️  Would replace {} matches in {} files",
+            count,
+            replacements.len()
+        );
+        return Ok(());
     }
+    for replacement in &replacements {
+        let path = replacement
+            .path
+            .strip_prefix(root)
+            .unwrap_or(&replacement.path);
+        println!("  {} ({})", path.display(), replacement.count);
+    }
+    println!(
+        "✏️  Replaced {} matches in {} files",
+        count,
+        replacements.len()
+    );
+    if !replacements.is_empty() {
+        println!("   Run `ss update` to refresh the index.");
+    }
+    Ok(())
 }
 
-/// Implements the 'find' command functionality
-fn find_files(query: &str, index_dir: &Path) -> Result<()> {
-    let matcher = SkimMatcherV2::default();
-    let start = Instant::now();
-
-    // In a real scenario, we would load index data from index_dir
-    // For now, we'll simulate by scanning the current directory (not ideal, but for demo)
-    // A proper implementation would read filenames from an index file.
+/// Implements `rename --undo`
+fn undo_rename(index_dir: &Path, format: OutputFormat) -> Result<()> {
+    let Some(renames) = rename::undo(index_dir)? else {
+        anyhow::bail!("Nothing to undo: no renames are recorded for this index");
+    };
+    print_renames(&renames, false, format)?;
+    if format.is_text() {
+        println!("↩️  Renamed {} files back", renames.len());
+        println!("   Run `ss update` to refresh the index.");
+    }
+    Ok(())
+}
 
-    // Attempt to load pre-scanned files if they exist.
-    // This section needs to be integrated with actual indexing and storage.
-    // For now, we acknowledge it won't have a persistent index.
+fn print_renames(renames: &[Rename], dry_run: bool, format: OutputFormat) -> Result<()> {
+    if !format.is_text() {
+        let records: Vec<output::RenameRecord> = renames
+            .iter()
+            .map(|rename| output::RenameRecord {
+                from: &rename.from,
+                to: &rename.to,
+                dry_run,
+            })
+            .collect();
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
+    for rename in renames {
+        println!("  {} → {}", rename.from.display(), rename.to.display());
+    }
+    Ok(())
+}
 
-    println!("⚠️  'Find' command is in early development. Fuzzy matching is applied to current directory files.");
-    println!("   For persistent search, the 'scan' command must be run and results saved.");
+/// Statistics of `index`, listing the `top` largest files, extensions of
+/// `extensions` and deepest paths
+fn stats_record<'a>(
+    index_dir: &'a Path,
+    index: &'a Index,
+    extensions: &'a [sonic_search::ExtensionStats],
+    top: usize,
+) -> output::StatsRecord<'a> {
+    let generation = index.meta.generation;
+    output::StatsRecord {
+        index_dir,
+        root: &index.meta.root,
+        generation,
+        files: index.file_count(),
+        directories: index.meta.dir_count,
+        total_size: index.total_size(),
+        content_index: Index::content_path(index_dir, generation).is_file(),
+        partial: index.meta.partial,
+        created_at: output::unix_secs(index.meta.created_at),
+        updated_at: output::unix_secs(index.meta.updated_at),
+        age_secs: index
+            .meta
+            .updated_at
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs(),
+        largest: index
+            .largest(top)
+            .into_iter()
+            .map(|entry| output::LargeFile {
+                path: &entry.path,
+                size: entry.size,
+            })
+            .collect(),
+        extensions: &extensions[..top.min(extensions.len())],
+        deepest: index
+            .deepest(top)
+            .into_iter()
+            .map(|(entry, depth)| output::DeepPath {
+                path: &entry.path,
+                depth,
+            })
+            .collect(),
+        languages: index
+            .content
+            .iter()
+            .flat_map(|content| content.language_counts())
+            .map(|(language, files)| output::LanguageCount { language, files })
+            .collect(),
+        memory: output::MemoryRecord {
+            index_bytes: index.heap_size(),
+            content_index_bytes: index.content.as_ref().map_or(0, |c| c.heap_size()),
+            rss_bytes: memory::current_rss(),
+            peak_rss_bytes: memory::peak_rss(),
+        },
+        disk: output::DiskRecord {
+            usage_bytes: quota::disk_usage(index_dir),
+            max_bytes: index.meta.scan_options.max_index_size,
+            // A damaged log only loses the history, which stats can go without
+            evictions: quota::evictions(index_dir).unwrap_or_default(),
+        },
+        filesystems: filesystem_records(index),
+    }
+}
 
-    // Simulate finding files by scanning the current directory again (not efficient for large dirs, but for demo)
-    let current_dir_scan = scanner::scan_directory(".")?;
+/// Entries of `index` on each filesystem, most first
+fn filesystem_records(index: &Index) -> Vec<output::FilesystemRecord> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for device in index.entries.iter().filter_map(|entry| entry.device) {
+        *counts.entry(device).or_default() += 1;
+    }
+    let table = MountTable::read();
+    let mut records: Vec<output::FilesystemRecord> = counts
+        .into_iter()
+        .map(|(device, entries)| {
+            let mount = table.of_device(device);
+            output::FilesystemRecord {
+                device,
+                mount_point: mount.map(|mount| mount.point.clone()),
+                fstype: mount.map(|mount| mount.fstype.clone()),
+                remote: mount.is_some_and(Mount::is_remote),
+                entries,
+            }
+        })
+        .collect();
+    records.sort_by_key(|record| (std::cmp::Reverse(record.entries), record.device));
+    records
+}
 
-    // Filter files using fuzzy matching
-    let mut matches: Vec<(String, i64)> = current_dir_scan
-        .files
+/// Implements the 'projects' command
+fn list_projects(index_dir: &Path, format: OutputFormat) -> Result<()> {
+    require_index(index_dir)?;
+    let snapshot = Snapshot::open(index_dir)?;
+    let mut file_counts = vec![0usize; snapshot.projects.len()];
+    for id in snapshot.files().filter_map(|entry| entry.project) {
+        file_counts[id as usize] += 1;
+    }
+    let records: Vec<output::ProjectRecord> = snapshot
+        .projects
         .iter()
-        .filter_map(|file_entry| {
-            matcher
-                .fuzzy_match(&file_entry.name, query)
-                .map(|score| (file_entry.name.clone(), score))
+        .zip(&file_counts)
+        .map(|(project, &files)| output::ProjectRecord {
+            name: &project.name,
+            kind: project.kind.name(),
+            root: &project.root,
+            files,
         })
         .collect();
+    if !format.is_text() {
+        return output::write_records(&mut std::io::stdout().lock(), format, &records);
+    }
 
-    // Sort by score (higher is better)
-    matches.sort_by(|a, b| b.1.cmp(&a.1));
+    println!(
+        "📦 {} projects in {}",
+        records.len(),
+        snapshot.meta.root.display()
+    );
+    for record in &records {
+        let relative = record
+            .root
+            .strip_prefix(&snapshot.meta.root)
+            .unwrap_or(record.root);
+        let relative = match relative.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => relative,
+        };
+        println!(
+            "  - {} ({}, {} files) {}",
+            record.name,
+            record.kind,
+            record.files,
+            relative.display()
+        );
+    }
+    Ok(())
+}
 
-    println!("Found {} potential matches in {} ms:", matches.len(), start.elapsed().as_millis());
-    if matches.is_empty() {
-        println!("  No files found matching your query.");
-    } else {
-        for (name, score) in matches {
-            println!("  - {} (Score: {})", name, score);
+/// Implements the 'stats' command functionality
+/// Print what `bench run` measured
+fn show_bench(report: &bench::Report, format: OutputFormat) -> Result<()> {
+    if !format.is_text() {
+        return output::write_record(&mut std::io::stdout().lock(), format, report);
+    }
+    let timing = |timing: &bench::Timing| {
+        let ms = |us: u64| us as f64 / 1000.0;
+        format!(
+            "median {:.1} ms (fastest {:.1}, slowest {:.1})",
+            ms(timing.median_us),
+            ms(timing.min_us),
+            ms(timing.max_us)
+        )
+    };
+    println!(
+        "⏱️  sonic-search {} on {} {}, threads: {}, filesystem: {}",
+        report.version,
+        report.os,
+        report.arch,
+        report.threads,
+        report.filesystem.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "   Index: {} (generation {}, {} entries)",
+        report.index_dir.display(),
+        report.generation,
+        report.entries
+    );
+    println!("   Root: {}", report.root.display());
+    let runs = report.load.runs;
+    println!("   Runs: {} of each, after one to warm up", runs);
+    if let Some(scan) = &report.scan {
+        println!(
+            "\n📂 Scan of {} files in {} directories: {}, {:.0} files/s",
+            scan.files,
+            scan.directories,
+            timing(&scan.timing),
+            scan.timing.per_second(scan.files)
+        );
+    }
+    println!("\n💽 Load");
+    println!("   Whole index: {}", timing(&report.load));
+    println!("   Mapped entries: {}", timing(&report.open_mapped));
+    for (title, queries) in [("🔍 Search", &report.queries), ("📄 Grep", &report.grep)] {
+        if queries.is_empty() {
+            continue;
+        }
+        println!("\n{}", title);
+        for query in queries {
+            println!(
+                "   {:?}: {} results, {}",
+                query.query,
+                query.results,
+                timing(&query.timing)
+            );
         }
     }
-
     Ok(())
 }
 
-/// Implements the 'stats' command functionality
-fn show_stats(index_dir: &Path) -> Result<()> {
-    println!("⚠️  'Stats' command is in early development.");
-    println!("   Index directory: {}", index_dir.display());
-    println!("   This feature will be fully implemented once indexing is complete and persistent.");
+fn show_stats(index_dir: &Path, top: usize, format: OutputFormat) -> Result<()> {
+    require_index(index_dir)?;
+    let mut snapshot = Snapshot::open(index_dir)?;
+    snapshot.load_content(index_dir)?;
+    let extensions = snapshot.extension_stats();
+    let stats = stats_record(index_dir, &snapshot, &extensions, top);
+    if !format.is_text() {
+        return output::write_record(&mut std::io::stdout().lock(), format, &stats);
+    }
 
-    // In a real scenario, we would load index metadata from index_dir
-    // and display detailed statistics about the indexed data.
-    // For now, we present a placeholder message.
+    let root = &snapshot.meta.root;
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    println!("📊 Index Statistics");
+    println!("   Index directory: {}", index_dir.display());
+    println!("   Root Directory: {}", stats.root.display());
+    println!("   Generation: {}", stats.generation);
+    println!("   Files: {}", stats.files);
+    println!("   Directories: {}", stats.directories);
+    println!("   Total Size: {}", scanner::format_size(stats.total_size));
+    println!(
+        "   Content Index: {}",
+        if stats.content_index { "yes" } else { "no" }
+    );
+    println!("   Last Updated: {} ago", units::format_age(stats.age_secs));
+    if stats.partial {
+        println!("   ⚠️  Partial: the scan was interrupted; run `ss update` to complete it");
+    }
 
+    if !stats.largest.is_empty() {
+        println!("\n📦 Largest Files");
+        for file in &stats.largest {
+            println!(
+                "   {:>10}  {}",
+                scanner::format_size(file.size),
+                relative(file.path)
+            );
+        }
+    }
+    if !stats.extensions.is_empty() {
+        println!("\n🗂  Extensions");
+        for ext in stats.extensions {
+            let name = if ext.extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", ext.extension)
+            };
+            println!(
+                "   {:<12} {:>8} files  {:>10}",
+                name,
+                ext.files,
+                scanner::format_size(ext.size)
+            );
+        }
+    }
+    if !stats.deepest.is_empty() {
+        println!("\n🌲 Deepest Paths");
+        for path in &stats.deepest {
+            println!("   {:>3}  {}", path.depth, relative(path.path));
+        }
+    }
+    if !stats.languages.is_empty() {
+        println!("\n🌐 Languages");
+        for language in &stats.languages {
+            println!("   {:<12} {:>8} files", language.language, language.files);
+        }
+    }
+    let memory = &stats.memory;
+    println!("\n🧠 Memory");
+    println!(
+        "   Index in memory: {}",
+        scanner::format_size(memory.index_bytes as u64)
+    );
+    if memory.content_index_bytes > 0 {
+        println!(
+            "   Content index: {}",
+            scanner::format_size(memory.content_index_bytes as u64)
+        );
+    }
+    if let Some(peak) = memory.peak_rss_bytes {
+        println!("   Peak RSS: {}", scanner::format_size(peak));
+    }
+    if !stats.filesystems.is_empty() {
+        println!("\n🗄️  Filesystems (entries on each)");
+        for filesystem in &stats.filesystems {
+            let remote = if filesystem.remote { ", remote" } else { "" };
+            match (&filesystem.mount_point, &filesystem.fstype) {
+                (Some(point), Some(fstype)) => println!(
+                    "   {:>8}  {} ({}{})",
+                    filesystem.entries,
+                    point.display(),
+                    fstype,
+                    remote
+                ),
+                _ => println!(
+                    "   {:>8}  device {:#x}, no longer mounted",
+                    filesystem.entries, filesystem.device
+                ),
+            }
+        }
+    }
+    let disk = &stats.disk;
+    println!("\n💾 Disk");
+    match disk.max_bytes {
+        Some(max) => println!(
+            "   Index directory: {} of {}",
+            scanner::format_size(disk.usage_bytes),
+            scanner::format_size(max)
+        ),
+        None => println!(
+            "   Index directory: {}",
+            scanner::format_size(disk.usage_bytes)
+        ),
+    }
+    for eviction in disk.evictions.iter().rev().take(top) {
+        println!(
+            "   🧹 {} ago: evicted {} ({})",
+            units::format_age(
+                output::unix_secs(SystemTime::now())
+                    .unwrap_or(0)
+                    .saturating_sub(eviction.at)
+            ),
+            eviction.describe(),
+            scanner::format_size(eviction.bytes)
+        );
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sonic_search::error::ErrorKind;
     use std::fs;
-    use std::io::Write; // Import Write trait
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
 
     #[test]
     fn test_main_scan_command() {
@@ -150,16 +6809,412 @@ mod tests {
         let path = temp_dir.path().to_str().unwrap();
         fs::write(temp_dir.path().join("test.txt"), "content").unwrap();
         fs::create_dir_all(temp_dir.path().join("subdir")).unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
 
-        // Mocking stdout to capture output
-        let mut output = Vec::new();
-        // This part requires a more sophisticated mocking setup for stdout.
-        // For simplicity, we'll assume the CLI parsing works and focus on function logic.
-        // In a real test suite, you'd capture stdout using a library or redirect it.
         println!("Simulating `cargo run -- scan {}`", path);
-        let result = main(); // Directly calling main is not typical for CLI args, but for demonstration.
-                              // A proper test would involve setting up args.
+        let cli = Cli::try_parse_from(["ss", "scan", path, "--index-dir", index_path]).unwrap();
+        let result = run(cli);
         assert!(result.is_ok()); // We expect the program to run without crashing.
+        assert!(Index::exists(index_dir.path()));
+        assert_eq!(Index::load(index_dir.path()).unwrap().file_count(), 1);
+    }
+
+    #[test]
+    fn test_rename_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("IMG_1.jpeg"), "").unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index = index_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index]).unwrap()).unwrap();
+
+        let rename = |extra: &[&str]| {
+            let mut args = vec![
+                "ss",
+                "rename",
+                r"IMG_(\d+)\.jpeg",
+                "photo-$1.jpg",
+                "-i",
+                index,
+            ];
+            args.extend(extra);
+            run(Cli::try_parse_from(args).unwrap())
+        };
+        rename(&["--dry-run"]).unwrap();
+        assert!(temp_dir.path().join("IMG_1.jpeg").exists());
+        rename(&[]).unwrap();
+        assert!(temp_dir.path().join("photo-1.jpg").exists());
+    }
+
+    #[test]
+    fn test_main_update_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        fs::write(temp_dir.path().join("first.txt"), "one").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+
+        run(Cli::try_parse_from(["ss", "scan", path, "--index-dir", index_path]).unwrap()).unwrap();
+        fs::write(temp_dir.path().join("second.txt"), "two").unwrap();
+
+        let cli = Cli::try_parse_from(["ss", "update", "--index-dir", index_path]).unwrap();
+        assert!(run(cli).is_ok());
+        assert_eq!(Index::load(index_dir.path()).unwrap().entries.len(), 2);
+
+        // A fresh index is left alone when --older-than isn't exceeded
+        fs::write(temp_dir.path().join("third.txt"), "three").unwrap();
+        let cli =
+            Cli::try_parse_from(["ss", "update", "-i", index_path, "--older-than", "1h"]).unwrap();
+        assert!(run(cli).is_ok());
+        assert_eq!(Index::load(index_dir.path()).unwrap().entries.len(), 2);
+        fs::remove_file(temp_dir.path().join("third.txt")).unwrap();
+
+        // `scan --incremental` over the same root behaves like `update`
+        fs::remove_file(temp_dir.path().join("first.txt")).unwrap();
+        let cli = Cli::try_parse_from([
+            "ss",
+            "scan",
+            path,
+            "--index-dir",
+            index_path,
+            "--incremental",
+        ])
+        .unwrap();
+        assert!(run(cli).is_ok());
+        assert_eq!(Index::load(index_dir.path()).unwrap().file_count(), 1);
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("old_api.rs"), "call_old()").unwrap();
+        fs::write(temp_dir.path().join("new_api.rs"), "call_new()\ncall_old()").unwrap();
+        let mut index = Index::from_scan(scanner::scan_directory(temp_dir.path()).unwrap());
+        let filters = Filters::default();
+        let filter = index.entry_filter(&filters).unwrap();
+        let names = |paths: BTreeSet<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let by_name = matched_paths(&index, "old_api", None, &filters, &filter).unwrap();
+        assert_eq!(names(by_name), vec!["old_api.rs"]);
+        let options = grep::GrepOptions::default();
+        let by_contents =
+            matched_paths(&index, "call_old", Some(&options), &filters, &filter).unwrap();
+        assert_eq!(names(by_contents), vec!["new_api.rs", "old_api.rs"]);
+
+        assert!(Cli::try_parse_from(["ss", "diff-results", "a", "b", "-F"]).is_err());
+        let index_dir = tempfile::tempdir().unwrap();
+        index.save(index_dir.path()).unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        for args in [
+            vec!["ss", "diff-results", "old", "new", "-i", index_path],
+            vec![
+                "ss",
+                "diff-results",
+                "call_old",
+                "call_new",
+                "-i",
+                index_path,
+                "--grep",
+                "-F",
+            ],
+        ] {
+            assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_watch_for_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            temp_dir.path().join("server.log"),
+            "booting\nready on :8080\n",
+        )
+        .unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+
+        // Content already present only counts with --existing
+        let args = [
+            "ss",
+            "watch-for",
+            "ready",
+            "-i",
+            index_path,
+            "--timeout",
+            "300ms",
+        ];
+        assert!(run(Cli::try_parse_from(args).unwrap()).is_err());
+        if cfg!(unix) {
+            let marker = temp_dir.path().join("triggered");
+            let exec = format!("echo $SS_MATCH_LINE > {}", marker.display());
+            let args = [
+                "ss",
+                "watch-for",
+                "ready",
+                "-i",
+                index_path,
+                "--existing",
+                "--exec",
+                &exec,
+            ];
+            run(Cli::try_parse_from(args).unwrap()).unwrap();
+            assert_eq!(fs::read_to_string(marker).unwrap(), "2\n");
+        }
+    }
+
+    #[test]
+    fn test_dupes_and_count_commands() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("a.txt"), "twin").unwrap();
+        fs::write(temp_dir.path().join("b.txt"), "twin").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+        for args in [
+            vec!["ss", "count", "-i", index_path],
+            vec!["ss", "count", "-i", index_path, "--lines", "--by", "dir"],
+            vec!["ss", "dupes", "-i", index_path],
+            vec![
+                "ss",
+                "dupes",
+                "-i",
+                index_path,
+                "--min-size",
+                "1K",
+                "--ext",
+                "txt",
+            ],
+        ] {
+            assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_collect_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("budget.txt"), "42").unwrap();
+        fs::write(temp_dir.path().join("budget.md"), "# 42").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+
+        let out = index_dir.path().join("budget.tar.zst");
+        let out = out.to_str().unwrap();
+        let args = ["ss", "collect", "budget", "--out", out, "-i", index_path];
+        assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+        assert!(Path::new(out).exists());
+
+        let args = ["ss", "collect", "budget", "--out", out, "-i", index_path];
+        let err = run(Cli::try_parse_from([&args[..], &["--max-files", "1"]].concat()).unwrap());
+        assert!(err.unwrap_err().to_string().contains("--max-files"));
+        let err = run(Cli::try_parse_from([&args[..], &["--max-size", "3"]].concat()).unwrap());
+        assert!(err.unwrap_err().to_string().contains("--max-size"));
+        let args = [
+            "ss", "collect", "budget", "--out", "x.zip", "-i", index_path,
+        ];
+        assert!(run(Cli::try_parse_from(args).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_find_copy_and_move() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("IMG_1.jpg"), "one").unwrap();
+        fs::write(temp_dir.path().join("IMG_2.jpg"), "two").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_path = dest.path().to_str().unwrap();
+
+        let find = ["ss", "find", "img", "-i", index_path];
+        let copy = [&find[..], &["--copy-to", dest_path, "--dry-run"]].concat();
+        run(Cli::try_parse_from(copy).unwrap()).unwrap();
+        assert!(!dest.path().join("IMG_1.jpg").exists());
+        let copy = [&find[..], &["--copy-to", dest_path]].concat();
+        run(Cli::try_parse_from(copy).unwrap()).unwrap();
+        assert!(dest.path().join("IMG_1.jpg").exists());
+        assert!(temp_dir.path().join("IMG_1.jpg").exists());
+
+        let moved = [
+            &find[..],
+            &["--move-to", dest_path, "--on-conflict", "rename"],
+        ]
+        .concat();
+        run(Cli::try_parse_from(moved).unwrap()).unwrap();
+        assert!(dest.path().join("IMG_2 (1).jpg").exists());
+        assert!(!temp_dir.path().join("IMG_2.jpg").exists());
+
+        assert!(Cli::try_parse_from([&find[..], &["--dry-run"]].concat()).is_err());
+        let both = [&find[..], &["--copy-to", "a", "--move-to", "b"]].concat();
+        assert!(Cli::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn test_jump_import_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dunce::canonicalize(dir.path()).unwrap().join("src");
+        fs::create_dir(&src).unwrap();
+        let autojump = dir.path().join("autojump.txt");
+        fs::write(
+            &autojump,
+            format!("4\t{}\n2\t/no/such/dir\n", src.display()),
+        )
+        .unwrap();
+        let path = dir.path().join("data").join(jump::JUMP_FILE);
+
+        import_jumps(&path, jump::Source::Autojump, Some(autojump)).unwrap();
+        let history = JumpHistory::load_from(&path).unwrap();
+        assert_eq!(history.dirs.len(), 2);
+        let keywords = ["src".to_string()];
+        assert_eq!(
+            history.best(&keywords, SystemTime::now()),
+            Some(src.as_path())
+        );
+        jump_to(&path, &keywords, false, OutputFormat::Text).unwrap();
+        jump_to(&path, &[], true, OutputFormat::Json).unwrap();
+        assert!(jump_to(&path, &["such".to_string()], false, OutputFormat::Text).is_err());
+
+        assert!(Cli::try_parse_from(["ss", "jump", "--add", "x", "src"]).is_err());
+        assert!(Cli::try_parse_from(["ss", "jump", "--from", "db"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_chmod() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("app.log"), "log").unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "notes").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+
+        let chmod = [
+            "ss", "find", "log", "-i", index_path, "--chmod", "600", "-y",
+        ];
+        run(Cli::try_parse_from(chmod).unwrap()).unwrap();
+        let mode = |name: &str| {
+            let metadata = fs::metadata(temp_dir.path().join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        assert_eq!(mode("app.log"), 0o600);
+        assert_ne!(mode("notes.txt"), 0o600);
+
+        let readonly = [
+            "ss",
+            "find",
+            "notes",
+            "-i",
+            index_path,
+            "--set-readonly",
+            "-y",
+        ];
+        run(Cli::try_parse_from(readonly).unwrap()).unwrap();
+        let notes = fs::metadata(temp_dir.path().join("notes.txt")).unwrap();
+        assert!(notes.permissions().readonly());
+
+        assert!(Cli::try_parse_from(["ss", "find", "log", "--chmod", "9"]).is_err());
+        let both = ["ss", "find", "log", "--chmod", "600", "--set-readonly"];
+        assert!(Cli::try_parse_from(both).is_err());
+        let both = ["ss", "find", "log", "--chmod", "600", "--copy-to", "a"];
+        assert!(Cli::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn test_scan_excludes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp_dir.path().join("target/debug")).unwrap();
+        fs::write(temp_dir.path().join("target/debug/ss"), "bin").unwrap();
+        fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+
+        let cli =
+            Cli::try_parse_from(["ss", "scan", root, "-i", index_path, "--exclude", "target/"])
+                .unwrap();
+        run(cli).unwrap();
+        let index = Index::load(index_dir.path()).unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.meta.scan_options.excludes, vec!["target/"]);
+    }
+
+    #[test]
+    fn test_size_and_duration_flags_are_validated() {
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--max-filesize", "10M"]).is_ok());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--max-filesize", "ten"]).is_err());
+        assert!(Cli::try_parse_from(["ss", "update", "--older-than", "2h30m"]).is_ok());
+        assert!(Cli::try_parse_from(["ss", "update", "--older-than", "2x"]).is_err());
+    }
+
+    #[test]
+    fn test_short_command_aliases() {
+        assert!(matches!(
+            Cli::try_parse_from(["ss", "f", "query"]).unwrap().command,
+            Commands::Find { .. }
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["ss", "g", "pattern"]).unwrap().command,
+            Commands::Grep { .. }
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["ss", "s", "."]).unwrap().command,
+            Commands::Scan { .. }
+        ));
+
+        let builtins = builtin_commands();
+        for name in ["find", "f", "grep", "g", "scan", "s", "init", "help"] {
+            assert!(builtins.contains(&name.to_string()), "{}", name);
+        }
+
+        // Global options may come before the command, or the default one
+        let config = Config::default();
+        for args in [
+            &["ss", "--output", "json", "find", "main"][..],
+            &["ss", "--no-pager", "find", "main"],
+            &["ss", "--color", "never", "main"],
+            &[
+                "ss",
+                "--config",
+                "x.toml",
+                "--utc",
+                "--date-format",
+                "iso",
+                "main",
+            ],
+        ] {
+            let args = args.iter().map(OsString::from).collect();
+            let args = config
+                .prepare_args(args, &builtins, &global_options())
+                .unwrap();
+            let cli = Cli::try_parse_from(&args).unwrap();
+            assert!(matches!(cli.command, Commands::Find { .. }), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_missing_index_is_an_error() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("nope");
+        let missing = missing.to_str().unwrap();
+        for command in ["update", "watch"] {
+            let cli = Cli::try_parse_from(["ss", command, "-i", missing]).unwrap();
+            let err = run(cli).unwrap_err();
+            assert!(err.to_string().contains("No index found"), "{}", err);
+        }
     }
 
     #[test]
@@ -179,7 +7234,14 @@ mod tests {
         std::env::set_current_dir(temp_dir.path()).unwrap();
 
         let query = "doc"; // Should match "document.txt"
-        let result = find_files(query, &index_path);
+        let result = find_files(
+            query,
+            std::slice::from_ref(&index_path),
+            None,
+            &SearchOptions::default(),
+            FindDisplay::default(),
+            OutputFormat::Text,
+        );
         assert!(result.is_ok());
 
         // Manual check of output is needed here to verify matches.
@@ -192,35 +7254,495 @@ mod tests {
         // Test with a query that might match multiple files if name was different
         let query_archive = "archive"; // Should match archive.zip and archive.tar.gz
         println!("--- Find results for query: '{}' ---", query_archive);
-        let result_archive = find_files(query_archive, &index_path);
+        let result_archive = find_files(
+            query_archive,
+            std::slice::from_ref(&index_path),
+            None,
+            &SearchOptions::default(),
+            FindDisplay::default(),
+            OutputFormat::Text,
+        );
         assert!(result_archive.is_ok());
-        println!("If 'archive.zip' and 'archive.tar.gz' were found and printed above, the basic fuzzy logic works.");
-
+        println!(
+            "If 'archive.zip' and 'archive.tar.gz' were found and printed above, the basic fuzzy logic works."
+        );
 
         // Restore original directory
         std::env::set_current_dir(original_dir).unwrap();
     }
 
+    #[test]
+    fn test_scan_profile_across_roots() {
+        let code = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        fs::write(code.path().join("budget.rs"), "fn main() {}").unwrap();
+        fs::write(docs.path().join("budget.md"), "# Budget").unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let roots = [
+            code.path().to_str().unwrap().to_string(),
+            docs.path().to_str().unwrap().to_string(),
+        ];
+
+        let options = scanner::ScanOptions::default();
+        scan_profile("work", &roots, data_dir.path(), &options, false, None).unwrap();
+        assert!(scan_profile("bad name", &roots, data_dir.path(), &options, false, None).is_err());
+
+        let registry = Registry::load_from(&data_dir.path().join(registry::REGISTRY_FILE)).unwrap();
+        let profile = registry.profile("work").unwrap();
+        assert_eq!(profile.indexes.len(), 2);
+        for index_dir in &profile.indexes {
+            assert!(Index::exists(index_dir));
+        }
+        let result = find_files(
+            "budget",
+            &profile.indexes,
+            None,
+            &SearchOptions::default(),
+            FindDisplay::default(),
+            OutputFormat::Json,
+        );
+        assert!(result.is_ok());
+
+        // Several roots need a profile, and a profile replaces --index-dir
+        let cli = Cli::try_parse_from(["ss", "scan", &roots[0], &roots[1]]).unwrap();
+        assert!(run(cli).unwrap_err().to_string().contains("--name"));
+        let args = ["ss", "find", "x", "--index", "work", "-i", "/tmp"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_search_several_indexes() {
+        let roots = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let indexes = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        fs::write(roots[0].path().join("budget.txt"), "total = 1").unwrap();
+        fs::write(roots[1].path().join("budget.md"), "total = 2").unwrap();
+        let [first, second] = indexes.each_ref().map(|dir| dir.path().to_str().unwrap());
+        for (root, index) in roots.iter().zip([first, second]) {
+            let root = root.path().to_str().unwrap();
+            run(Cli::try_parse_from(["ss", "scan", root, "-i", index]).unwrap()).unwrap();
+        }
+
+        for command in ["find", "grep"] {
+            let query = if command == "find" { "budget" } else { "total" };
+            let args = ["ss", command, query, "-i", first, "-i", second];
+            assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+            // The same index twice reports each file once
+            let args = ["ss", command, query, "-i", first, "-i", first];
+            assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+        }
+
+        let missing = roots[0].path().join("missing");
+        let missing = missing.to_str().unwrap();
+        let cli = Cli::try_parse_from(["ss", "find", "x", "-i", first, "-i", missing]).unwrap();
+        assert!(run(cli).unwrap_err().to_string().contains("No index found"));
+        let args = [
+            "ss",
+            "find",
+            "x",
+            "-i",
+            first,
+            "-i",
+            second,
+            "--generation",
+            "1",
+        ];
+        assert!(run(Cli::try_parse_from(args).unwrap()).is_err());
+        let args = ["ss", "grep", "x", "-i", first, "-i", second, "--follow"];
+        assert!(run(Cli::try_parse_from(args).unwrap()).is_err());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--all-indexes", "-i", first]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_and_grep_through_daemon() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("budget.txt"), "total = 42").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+
+        let server = daemon::Server::open(index_dir.path()).unwrap();
+        let stop = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let (ready, listening) = std::sync::mpsc::channel();
+            scope.spawn(|| daemon::serve(&server, &stop, move |_| ready.send(()).unwrap()));
+            listening.recv_timeout(Duration::from_secs(10)).unwrap();
+            for args in [
+                vec!["ss", "find", "budget", "-i", index_path, "--sort", "size"],
+                vec!["ss", "grep", "total", "-i", index_path, "--output", "json"],
+            ] {
+                assert!(run(Cli::try_parse_from(args).unwrap()).is_ok());
+            }
+            let args = ["ss", "grep", "(", "-i", index_path];
+            assert!(run(Cli::try_parse_from(args).unwrap()).is_err());
+            stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn test_grep_with_content_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("hit.txt"), "target_profit = 42").unwrap();
+        fs::write(temp_dir.path().join("miss.txt"), "nothing here").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+
+        let cli = Cli::try_parse_from(["ss", "scan", root, "-i", index_path, "--content"]).unwrap();
+        run(cli).unwrap();
+        let mut index = Index::load(index_dir.path()).unwrap();
+        assert!(index.load_content(index_dir.path()).unwrap());
+
+        let cli = Cli::try_parse_from(["ss", "grep", "target_profit", "-i", index_path]).unwrap();
+        assert!(run(cli).is_ok());
+
+        // Updates keep the content index in sync
+        fs::write(temp_dir.path().join("new.txt"), "more target_profit").unwrap();
+        run(Cli::try_parse_from(["ss", "update", "-i", index_path]).unwrap()).unwrap();
+        let mut index = Index::load(index_dir.path()).unwrap();
+        assert!(index.load_content(index_dir.path()).unwrap());
+        let ids = index
+            .content
+            .as_ref()
+            .unwrap()
+            .candidates("target_profit", false)
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+    }
+
     #[test]
     fn test_show_stats_logic() {
         let temp_dir = tempfile::tempdir().unwrap();
         let index_path = temp_dir.path().join(".sonic-search");
         fs::create_dir_all(&index_path).unwrap();
 
-        println!("--- Stats output ---");
-        let result = show_stats(&index_path);
-        assert!(result.is_ok());
-        println!("Stats command placeholder output is expected.");
+        // An empty index directory has nothing to report
+        assert!(show_stats(&index_path, 10, OutputFormat::Text).is_err());
+
+        fs::write(temp_dir.path().join("a.txt"), "alpha").unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let index = index_path.to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index]).unwrap()).unwrap();
+        for format in [OutputFormat::Text, OutputFormat::Json, OutputFormat::Ndjson] {
+            assert!(show_stats(&index_path, 10, format).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_order_and_limit_lines() {
+        let entry = |name: &str, size: u64, secs: u64| {
+            let modified = Some(std::time::UNIX_EPOCH + Duration::from_secs(secs));
+            FileEntry::new(format!("/r/{}", name), false, size, modified)
+        };
+        let entries = [
+            entry("b.rs", 10, 3),
+            entry("a.rs", 30, 1),
+            entry("c.rs", 20, 2),
+        ];
+        let names = |sort: SortOrder, reverse: bool| {
+            let order = OrderArgs {
+                sort,
+                reverse,
+                ..Default::default()
+            };
+            let mut items: Vec<&FileEntry> = entries.iter().collect();
+            order.sort(&mut items, &HashMap::new(), |e| e).unwrap();
+            items
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(names(SortOrder::Score, false), "b.rs a.rs c.rs");
+        assert_eq!(names(SortOrder::Name, false), "a.rs b.rs c.rs");
+        assert_eq!(names(SortOrder::Size, false), "a.rs c.rs b.rs");
+        assert_eq!(names(SortOrder::Mtime, false), "b.rs c.rs a.rs");
+        assert_eq!(names(SortOrder::Mtime, true), "a.rs c.rs b.rs");
+
+        let file = |name: &str, lines: usize| grep::FileMatches {
+            path: PathBuf::from(name),
+            matches: (1..=lines)
+                .map(|line_number| grep::LineMatch {
+                    line_number,
+                    line: String::new(),
+                    spans: Vec::new(),
+                    offset: 0,
+                    before: Vec::new(),
+                    after: Vec::new(),
+                })
+                .collect(),
+        };
+        let mut results = vec![file("a", 2), file("b", 3), file("c", 1)];
+        grep::limit_lines(&mut results, 4);
+        let counts: Vec<usize> = results.iter().map(|f| f.matches.len()).collect();
+        assert_eq!(counts, vec![2, 2]);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_grep_command_placeholder() {
+    fn test_find_exec() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let (root_path, index_path) = (
+            root.path().to_str().unwrap(),
+            index_dir.path().to_str().unwrap(),
+        );
+        fs::write(root.path().join("a.log"), "").unwrap();
+        fs::write(root.path().join("b c.log"), "").unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root_path, "-i", index_path]).unwrap()).unwrap();
+
+        let find = |args: &[&str]| {
+            let cli =
+                Cli::try_parse_from([&["ss", "find", "log", "-i", index_path], args].concat());
+            run(cli.unwrap())
+        };
+        find(&["--exec", "cp {path} {path}.bak", "--jobs", "2"]).unwrap();
+        assert!(root.path().join("b c.log.bak").exists());
+        let list = index_dir.path().join("list");
+        let batch = format!("ls {{path}} > {}", list.display());
+        find(&["--exec-batch", &batch]).unwrap();
+        assert_eq!(fs::read_to_string(&list).unwrap().lines().count(), 2);
+        assert!(find(&["--exec", "false"]).is_err());
+        assert!(
+            Cli::try_parse_from(["ss", "find", "x", "--exec", "a", "--exec-batch", "b"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_find_filter_flags() {
+        let cli = Cli::try_parse_from([
+            "ss", "find", "main", "--ext", "rs,toml", "--glob", "src/**", "--glob", "*.md",
+        ])
+        .unwrap();
+        let Commands::Find { filters, .. } = cli.command else {
+            panic!("expected find");
+        };
+        assert_eq!(filters.extensions, vec!["rs", "toml"]);
+        assert_eq!(filters.globs, vec!["src/**", "*.md"]);
+
+        let cli = Cli::try_parse_from([
+            "ss",
+            "grep",
+            "TODO",
+            "--owner",
+            "@acme/web",
+            "--owner",
+            "@acme/docs",
+        ])
+        .unwrap();
+        let Commands::Grep { filters, .. } = cli.command else {
+            panic!("expected grep");
+        };
+        assert_eq!(filters.owners, vec!["@acme/web", "@acme/docs"]);
+
+        let cli = Cli::try_parse_from([
+            "ss",
+            "find",
+            "log",
+            "--size",
+            "-1k",
+            "--size",
+            "+10",
+            "--modified-within",
+            "7d",
+            "--modified-before",
+            "2024-01-01",
+        ])
+        .unwrap();
+        let Commands::Find { filters, .. } = cli.command else {
+            panic!("expected find");
+        };
+        assert_eq!(filters.sizes.len(), 2);
+        assert!(filters.modified_within.is_some() && filters.modified_before.is_some());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--modified-within", "soon"]).is_err());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--history", "--since", "30d"]).is_err());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--follow", "--history"]).is_err());
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "--follow", "--ext", "log"]).is_ok());
+        let cli = Cli::try_parse_from(["ss", "grep", "x", "--changed-by", "ada", "--since", "30d"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Grep {
+                changed_by: Some(_),
+                since: Some(_),
+                ..
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["ss", "find", "x", "--sort", "last-commit"]).unwrap();
+        let Commands::Find { order, git, .. } = cli.command else {
+            panic!("expected find");
+        };
+        assert_eq!(order.sort, SortOrder::LastCommit);
+        assert!(!git && !order.reverse);
+        let cli = Cli::try_parse_from([
+            "ss",
+            "grep",
+            "x",
+            "--sort",
+            "size",
+            "--reverse",
+            "--limit",
+            "5",
+        ])
+        .unwrap();
+        let Commands::Grep { order, .. } = cli.command else {
+            panic!("expected grep");
+        };
+        assert_eq!(order.sort, SortOrder::Size);
+        assert_eq!(order.limit, Some(5));
+        assert!(order.reverse && !order.is_natural());
+
+        assert!(Cli::try_parse_from(["ss", "find"]).is_err());
+        let cli = Cli::try_parse_from(["ss", "find", "--interactive"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Find {
+                query: None,
+                interactive: true,
+                ..
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["ss", "find", "srcscan", "-p"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Find {
+                full_path: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_projects_command() {
         let temp_dir = tempfile::tempdir().unwrap();
-        let index_path = temp_dir.path().join(".sonic-search");
+        fs::create_dir_all(temp_dir.path().join("app/src")).unwrap();
+        fs::write(
+            temp_dir.path().join("app/Cargo.toml"),
+            "[package]\nname = \"app\"\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("app/src/main.rs"), "").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().to_str().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "-i", index_path]).unwrap()).unwrap();
+
+        let cli = Cli::try_parse_from(["ss", "projects", "-i", index_path]).unwrap();
+        assert!(run(cli).is_ok());
+        let cli = Cli::try_parse_from(["ss", "find", "main", "-i", index_path, "--project", "app"])
+            .unwrap();
+        assert!(run(cli).is_ok());
+        let cli =
+            Cli::try_parse_from(["ss", "find", "main", "-i", index_path, "--project", "nope"])
+                .unwrap();
+        assert!(run(cli).is_err());
+    }
+
+    #[test]
+    fn test_output_flag_is_global() {
+        let cli = Cli::try_parse_from(["ss", "find", "x", "--output", "ndjson"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Ndjson);
+        let cli = Cli::try_parse_from(["ss", "--output", "json", "stats"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert!(Cli::try_parse_from(["ss", "find", "x", "--output", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_grep_line_rendering() {
+        let m = grep::LineMatch {
+            line_number: 3,
+            line: "v1.2 and v3.4".to_string(),
+            spans: vec![0..4, 9..13],
+            offset: 20,
+            before: Vec::new(),
+            after: Vec::new(),
+        };
+        let render = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["ss", "grep", "x"], args].concat()).unwrap();
+            let Commands::Grep { lines, .. } = cli.command else {
+                panic!("expected grep");
+            };
+            let pattern = grep::build_pattern(r"v(\d)\.(\d)", &Default::default()).unwrap();
+            lines.render("a.txt", &m, &pattern, false)
+        };
+        assert_eq!(render(&[]), ["a.txt:3:v1.2 and v3.4"]);
+        assert_eq!(render(&["--column", "-b"]), ["a.txt:3:1:20:v1.2 and v3.4"]);
+        assert_eq!(render(&["-o"]), ["a.txt:v1.2", "a.txt:v3.4"]);
+        assert_eq!(render(&["-o", "--no-filename"]), ["v1.2", "v3.4"]);
+        assert_eq!(
+            render(&["-o", "--column", "--no-filename"]),
+            ["1:v1.2", "10:v3.4"]
+        );
+        assert_eq!(render(&["-r", "$2.$1"]), ["a.txt:3:2.1 and 4.3"]);
+        assert_eq!(render(&["-N", "--no-filename"]), ["v1.2 and v3.4"]);
+        assert_eq!(render(&["-o", "-n"]), ["a.txt:3:v1.2", "a.txt:3:v3.4"]);
+        assert_eq!(
+            render(&["-o", "-r", "$1", "--column"]),
+            ["a.txt:1:1", "a.txt:10:3"]
+        );
+    }
+
+    #[test]
+    fn test_grep_context_flags() {
+        let context = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["ss", "grep", "x"], args].concat()).unwrap();
+            let Commands::Grep { lines, .. } = cli.command else {
+                panic!("expected grep");
+            };
+            let context = grep::ContextLines::from(lines.context);
+            (context.before, context.after)
+        };
+        assert_eq!(context(&[]), (0, 0));
+        assert_eq!(context(&["-C", "2"]), (2, 2));
+        assert_eq!(context(&["-C", "2", "-A", "5"]), (2, 5));
+        assert_eq!(context(&["-B", "1"]), (1, 0));
+        assert!(Cli::try_parse_from(["ss", "grep", "x", "-c", "-l"]).is_err());
+    }
+
+    #[test]
+    fn test_grep_command() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "has some_content here").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let index_path = index_dir.path().to_str().unwrap();
         let query = "some_content";
 
-        println!("--- Grep command placeholder output ---");
-        // For demonstration, we just print a message.
-        // A real test would involve ensuring the placeholder message confirms feature is not ready.
+        let root = temp_dir.path().to_str().unwrap();
+        run(Cli::try_parse_from(["ss", "scan", root, "--index-dir", index_path]).unwrap()).unwrap();
+
+        let cli = Cli::try_parse_from(["ss", "grep", query, "--index-dir", index_path]).unwrap();
+        assert!(run(cli).is_ok());
+        let cli =
+            Cli::try_parse_from(["ss", "grep", query, "-b", "--column", "-i", index_path]).unwrap();
+        assert!(run(cli).is_ok());
+
+        // An invalid regex is reported as an error rather than a panic
+        let cli = Cli::try_parse_from(["ss", "grep", "(", "--index-dir", index_path]).unwrap();
+        let error = run(cli).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Query);
+        assert_eq!(exit_code(&error), 2);
+
+        // Finding nothing exits with status 1 rather than reporting an error
+        for command in ["grep", "find"] {
+            let cli = Cli::try_parse_from(["ss", command, "zzqqx", "-i", index_path]).unwrap();
+            let error = run(cli).unwrap_err();
+            assert!(error.is::<NoMatches>());
+            assert_eq!(exit_code(&error), 1);
+        }
+
+        // A damaged index is told apart by its kind, but exits like any error
+        let entries = fs::read_dir(index_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "ent"))
+            .unwrap();
+        fs::write(&entries, "garbage").unwrap();
+        let cli = Cli::try_parse_from(["ss", "find", "main", "-i", index_path]).unwrap();
+        let error = run(cli).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::IndexFormat);
+        assert_eq!(exit_code(&error), 2);
     }
 }
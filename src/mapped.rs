@@ -0,0 +1,550 @@
+use crate::error::Error;
+use crate::git;
+use crate::index::{COMPRESSED, INDEX_FORMAT_VERSION};
+use crate::perms;
+use crate::scanner::FileEntry;
+use crate::search::{EntryFields, EntryTable};
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Magic bytes at the start of every entry table
+const MAGIC: &[u8; 4] = b"SSEN";
+
+/// Magic, format version and flags, never compressed
+const PREFIX_LEN: usize = 16;
+
+/// The prefix and the entry count
+const HEADER_LEN: usize = PREFIX_LEN + 8;
+
+/// Size of the fixed-width record describing one entry:
+///
+/// | offset | field                                         |
+/// |--------|-----------------------------------------------|
+/// | 0      | `u64` start of the path in the string arena   |
+/// | 8      | `u32` length of the path                      |
+/// | 12     | `u32` length of the name, stored after it     |
+/// | 16     | `u64` size                                    |
+/// | 24     | `i64` mtime seconds relative to the epoch     |
+/// | 32     | `u32` mtime nanoseconds                       |
+/// | 36     | `u32` project id, or [`NONE`]                 |
+/// | 40     | `u32` owner list id, or [`NONE`]              |
+/// | 44     | `u32` flags                                   |
+/// | 48     | `[u8; 32]` content hash, if [`HAS_HASH`]      |
+/// | 80     | `u32` length of the folded name, stored next  |
+/// | 84     | `u32` repository id, or [`NONE`]              |
+/// | 88     | `u32` MIME type id, or [`NONE`]               |
+/// | 92     | `u32` owning user id, or [`NONE`]             |
+/// | 96     | `u32` owning group id, or [`NONE`]            |
+/// | 100    | `u32` permission bits, or [`NONE`]            |
+/// | 104    | `u64` device, if [`HAS_DEVICE`]               |
+const RECORD_LEN: usize = 112;
+
+/// Stands for a missing project, owner list, repository or MIME type id,
+/// and for a missing user, group or mode
+const NONE: u32 = u32::MAX;
+
+const IS_DIR: u32 = 1;
+const VENDORED: u32 = 1 << 1;
+const HAS_MODIFIED: u32 = 1 << 2;
+const HAS_HASH: u32 = 1 << 3;
+const IS_SYMLINK: u32 = 1 << 4;
+const GIT_TRACKED: u32 = 1 << 5;
+/// Always set together with [`GIT_TRACKED`]
+const GIT_MODIFIED: u32 = 1 << 6;
+const READONLY: u32 = 1 << 7;
+const HIDDEN: u32 = 1 << 8;
+const HAS_DEVICE: u32 = 1 << 9;
+
+/// Write `entries` to `path` as a table [`MappedEntries`] can search in
+/// place: a header, one fixed-width record per entry, then every path,
+/// name and folded name back to back. With `compress`, everything after the magic, version
+/// and flags is zstd-compressed, trading mapping for a smaller file.
+///
+/// Data goes to a temporary file first so a failed write never clobbers a
+/// good table.
+pub fn write(path: &Path, entries: &[FileEntry], compress: bool) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&INDEX_FORMAT_VERSION.to_le_bytes())?;
+        let flags = if compress { COMPRESSED } else { 0 };
+        writer.write_all(&flags.to_le_bytes())?;
+        writer.write_all(&[0; 4])?;
+        if compress {
+            let mut encoder = zstd::Encoder::new(&mut writer, 0)?;
+            write_body(&mut encoder, entries)?;
+            encoder.finish()?;
+        } else {
+            write_body(&mut writer, entries)?;
+        }
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write index: {}", path.display()))
+}
+
+/// Write the entry count, the records and the string arena
+fn write_body(writer: &mut impl Write, entries: &[FileEntry]) -> Result<()> {
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    let mut offset = 0u64;
+    for entry in entries {
+        let path = path_bytes(&entry.path);
+        let mut record = [0u8; RECORD_LEN];
+        record[0..8].copy_from_slice(&offset.to_le_bytes());
+        record[8..12].copy_from_slice(&(path.len() as u32).to_le_bytes());
+        record[12..16].copy_from_slice(&(entry.name.len() as u32).to_le_bytes());
+        record[16..24].copy_from_slice(&entry.size.to_le_bytes());
+        let mut flags = 0;
+        if let Some(modified) = entry.modified {
+            let (secs, nanos) = epoch_offset(modified);
+            record[24..32].copy_from_slice(&secs.to_le_bytes());
+            record[32..36].copy_from_slice(&nanos.to_le_bytes());
+            flags |= HAS_MODIFIED;
+        }
+        record[36..40].copy_from_slice(&entry.project.unwrap_or(NONE).to_le_bytes());
+        record[40..44].copy_from_slice(&entry.owners.unwrap_or(NONE).to_le_bytes());
+        if let Some(hash) = &entry.hash {
+            record[48..80].copy_from_slice(hash);
+            flags |= HAS_HASH;
+        }
+        if entry.is_dir {
+            flags |= IS_DIR;
+        }
+        if entry.vendored {
+            flags |= VENDORED;
+        }
+        if entry.is_symlink {
+            flags |= IS_SYMLINK;
+        }
+        if entry.attributes.readonly {
+            flags |= READONLY;
+        }
+        if entry.attributes.hidden {
+            flags |= HIDDEN;
+        }
+        if let Some(device) = entry.device {
+            flags |= HAS_DEVICE;
+            record[104..112].copy_from_slice(&device.to_le_bytes());
+        }
+        flags |= match entry.git_status {
+            git::Status::Untracked => 0,
+            git::Status::Tracked => GIT_TRACKED,
+            git::Status::Modified => GIT_TRACKED | GIT_MODIFIED,
+        };
+        record[44..48].copy_from_slice(&flags.to_le_bytes());
+        let folded = entry.folded_name.as_deref().unwrap_or_default();
+        record[80..84].copy_from_slice(&(folded.len() as u32).to_le_bytes());
+        record[84..88].copy_from_slice(&entry.repo.unwrap_or(NONE).to_le_bytes());
+        record[88..92].copy_from_slice(&entry.mime.unwrap_or(NONE).to_le_bytes());
+        let attributes = &entry.attributes;
+        record[92..96].copy_from_slice(&attributes.uid.unwrap_or(NONE).to_le_bytes());
+        record[96..100].copy_from_slice(&attributes.gid.unwrap_or(NONE).to_le_bytes());
+        record[100..104].copy_from_slice(&attributes.mode.unwrap_or(NONE).to_le_bytes());
+        writer.write_all(&record)?;
+        offset += (path.len() + entry.name.len() + folded.len()) as u64;
+    }
+    for entry in entries {
+        writer.write_all(&path_bytes(&entry.path))?;
+        writer.write_all(entry.name.as_bytes())?;
+        writer.write_all(entry.folded_name.as_deref().unwrap_or_default().as_bytes())?;
+    }
+    Ok(())
+}
+
+/// The bytes of a table: mapped, or decompressed into memory
+#[derive(Debug)]
+enum Table {
+    Mapped(Mmap),
+    Decompressed(Vec<u8>),
+}
+
+impl Deref for Table {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Table::Mapped(map) => map,
+            Table::Decompressed(bytes) => bytes,
+        }
+    }
+}
+
+/// The entries of one index generation, read straight from a memory-mapped
+/// table written by [`write`] instead of being decoded up front. Compressed
+/// tables are decompressed into memory, but still not decoded.
+#[derive(Debug)]
+pub struct MappedEntries {
+    map: Table,
+    len: usize,
+}
+
+impl MappedEntries {
+    /// A table of `entries` built in memory, for indexes whose entries
+    /// aren't stored in one
+    pub fn from_entries(entries: &[FileEntry]) -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&INDEX_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        write_body(&mut bytes, entries).expect("writing to memory can't fail");
+        MappedEntries {
+            map: Table::Decompressed(bytes),
+            len: entries.len(),
+        }
+    }
+
+    /// Map the table at `path`, checking that every record fits inside it
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // SAFETY: generation files are never modified after being written;
+        // they're only ever replaced by renaming a new file into place or
+        // deleted once no reader pins them.
+        let map = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("Failed to map {}", path.display()))?;
+        if map.len() < PREFIX_LEN {
+            return Err(Error::index_format("Index file is truncated", path).into());
+        }
+        if &map[0..4] != MAGIC {
+            return Err(Error::index_format("Not a sonic-search index", path).into());
+        }
+        let version = u32::from_le_bytes(map[4..8].try_into().unwrap());
+        if version != INDEX_FORMAT_VERSION {
+            return Err(crate::index::format_mismatch(path, version));
+        }
+        let corrupt = || anyhow::Error::from(Error::index_format("Index file is corrupt", path));
+        let flags = u32::from_le_bytes(map[8..12].try_into().unwrap());
+        let map = if flags & COMPRESSED != 0 {
+            // Keep the prefix so offsets are the same as in a mapped table
+            let mut bytes = map[..PREFIX_LEN].to_vec();
+            zstd::Decoder::new(&map[PREFIX_LEN..])?
+                .read_to_end(&mut bytes)
+                .with_context(|| format!("Failed to decompress {}", path.display()))?;
+            Table::Decompressed(bytes)
+        } else {
+            Table::Mapped(map)
+        };
+        if map.len() < HEADER_LEN {
+            return Err(corrupt());
+        }
+        let len = u64::from_le_bytes(map[PREFIX_LEN..HEADER_LEN].try_into().unwrap()) as usize;
+        let arena_start = len
+            .checked_mul(RECORD_LEN)
+            .and_then(|records| records.checked_add(HEADER_LEN))
+            .filter(|&start| start <= map.len())
+            .ok_or_else(corrupt)?;
+        let entries = Self { map, len };
+        let arena = &entries.map[arena_start..];
+        for i in 0..len {
+            let record = entries.record(i);
+            let start = read_u64(record, 0) as usize;
+            let strings = read_u32(record, 8) as usize
+                + read_u32(record, 12) as usize
+                + read_u32(record, 80) as usize;
+            let valid = start
+                .checked_add(strings)
+                .and_then(|end| arena.get(start..end))
+                .is_some_and(|_| {
+                    let entry = EntryRef { record, arena };
+                    std::str::from_utf8(entry.name_bytes()).is_ok()
+                        && std::str::from_utf8(entry.folded_bytes()).is_ok()
+                        && (cfg!(unix) || std::str::from_utf8(entry.path_bytes()).is_ok())
+                });
+            if !valid {
+                return Err(corrupt());
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The entry at `index`; panics if it's out of bounds
+    pub fn get(&self, index: usize) -> EntryRef<'_> {
+        assert!(index < self.len, "entry {} of {}", index, self.len);
+        EntryRef {
+            record: self.record(index),
+            arena: &self.map[HEADER_LEN + self.len * RECORD_LEN..],
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntryRef<'_>> {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    /// Decode every entry
+    pub fn to_vec(&self) -> Vec<FileEntry> {
+        self.iter().map(|entry| entry.to_entry()).collect()
+    }
+
+    fn record(&self, index: usize) -> &[u8] {
+        let start = HEADER_LEN + index * RECORD_LEN;
+        &self.map[start..start + RECORD_LEN]
+    }
+}
+
+impl EntryTable for MappedEntries {
+    type Entry<'a> = EntryRef<'a>;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> EntryRef<'_> {
+        MappedEntries::get(self, index)
+    }
+}
+
+/// One entry of a [`MappedEntries`] table, borrowing its path and name from
+/// the map
+#[derive(Debug, Clone, Copy)]
+pub struct EntryRef<'a> {
+    record: &'a [u8],
+    arena: &'a [u8],
+}
+
+impl<'a> EntryRef<'a> {
+    fn path_bytes(&self) -> &'a [u8] {
+        let start = read_u64(self.record, 0) as usize;
+        &self.arena[start..start + read_u32(self.record, 8) as usize]
+    }
+
+    fn name_bytes(&self) -> &'a [u8] {
+        let start = read_u64(self.record, 0) as usize + read_u32(self.record, 8) as usize;
+        &self.arena[start..start + read_u32(self.record, 12) as usize]
+    }
+
+    fn folded_bytes(&self) -> &'a [u8] {
+        let start = read_u64(self.record, 0) as usize
+            + read_u32(self.record, 8) as usize
+            + read_u32(self.record, 12) as usize;
+        &self.arena[start..start + read_u32(self.record, 80) as usize]
+    }
+
+    fn flag(&self, flag: u32) -> bool {
+        read_u32(self.record, 44) & flag != 0
+    }
+
+    fn id(&self, offset: usize) -> Option<u32> {
+        Some(read_u32(self.record, offset)).filter(|&id| id != NONE)
+    }
+
+    /// Copy the entry out of the map
+    pub fn to_entry(&self) -> FileEntry {
+        FileEntry {
+            path: EntryFields::path(self).to_path_buf(),
+            name: EntryFields::name(self).to_string(),
+            folded_name: EntryFields::folded_name(self).map(str::to_string),
+            size: EntryFields::size(self),
+            is_dir: EntryFields::is_dir(self),
+            is_symlink: EntryFields::is_symlink(self),
+            modified: EntryFields::modified(self),
+            project: EntryFields::project(self),
+            owners: EntryFields::owners(self),
+            vendored: EntryFields::vendored(self),
+            hash: EntryFields::hash(self).copied(),
+            repo: EntryFields::repo(self),
+            git_status: EntryFields::git_status(self),
+            mime: EntryFields::mime(self),
+            attributes: EntryFields::attributes(self),
+            device: EntryFields::device(self),
+        }
+    }
+}
+
+impl EntryFields for EntryRef<'_> {
+    fn path(&self) -> &Path {
+        path_from_bytes(self.path_bytes())
+    }
+
+    fn name(&self) -> &str {
+        // Checked when the table was opened
+        std::str::from_utf8(self.name_bytes()).unwrap_or_default()
+    }
+
+    fn folded_name(&self) -> Option<&str> {
+        // Checked when the table was opened
+        Some(std::str::from_utf8(self.folded_bytes()).unwrap_or_default())
+            .filter(|name| !name.is_empty())
+    }
+
+    fn size(&self) -> u64 {
+        read_u64(self.record, 16)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.flag(IS_DIR)
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.flag(IS_SYMLINK)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.flag(HAS_MODIFIED)
+            .then(|| from_epoch_offset(read_u64(self.record, 24) as i64, read_u32(self.record, 32)))
+    }
+
+    fn project(&self) -> Option<u32> {
+        self.id(36)
+    }
+
+    fn owners(&self) -> Option<u32> {
+        self.id(40)
+    }
+
+    fn vendored(&self) -> bool {
+        self.flag(VENDORED)
+    }
+
+    fn hash(&self) -> Option<&[u8; 32]> {
+        self.flag(HAS_HASH)
+            .then(|| self.record[48..80].try_into().unwrap())
+    }
+
+    fn repo(&self) -> Option<u32> {
+        self.id(84)
+    }
+
+    fn mime(&self) -> Option<u32> {
+        self.id(88)
+    }
+
+    fn attributes(&self) -> perms::Attributes {
+        perms::Attributes {
+            uid: self.id(92),
+            gid: self.id(96),
+            mode: self.id(100),
+            readonly: self.flag(READONLY),
+            hidden: self.flag(HIDDEN),
+        }
+    }
+
+    fn device(&self) -> Option<u64> {
+        self.flag(HAS_DEVICE).then(|| read_u64(self.record, 104))
+    }
+
+    fn git_status(&self) -> git::Status {
+        match (self.flag(GIT_TRACKED), self.flag(GIT_MODIFIED)) {
+            (false, _) => git::Status::Untracked,
+            (true, false) => git::Status::Tracked,
+            (true, true) => git::Status::Modified,
+        }
+    }
+}
+
+fn read_u32(record: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(record[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(record: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(record[offset..offset + 8].try_into().unwrap())
+}
+
+/// Whole seconds (rounded down) and nanoseconds from the epoch to `time`
+pub(crate) fn epoch_offset(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// The time `secs` seconds and then `nanos` nanoseconds after the epoch
+pub(crate) fn from_epoch_offset(secs: i64, nanos: u32) -> SystemTime {
+    let nanos = Duration::from_nanos(nanos as u64);
+    match secs {
+        0.. => UNIX_EPOCH + Duration::from_secs(secs as u64) + nanos,
+        _ => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nanos,
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().into()
+}
+
+#[cfg(unix)]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> &Path {
+    use std::os::unix::ffi::OsStrExt;
+    Path::new(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Paths that aren't valid Unicode are stored lossily outside Unix
+#[cfg(not(unix))]
+pub(crate) fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        std::borrow::Cow::Borrowed(path) => path.as_bytes().into(),
+        std::borrow::Cow::Owned(path) => path.into_bytes().into(),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> &Path {
+    // Written as Unicode (see `path_bytes`), and checked when a table is
+    // opened
+    Path::new(std::str::from_utf8(bytes).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_entries_roundtrip_through_map() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("budget.txt"), "42").unwrap();
+        fs::create_dir(root.path().join("docs")).unwrap();
+        let mut entries = scanner::scan_directory(root.path()).unwrap().files;
+        entries[0].project = Some(3);
+        entries[0].hash = Some([7; 32]);
+        entries[0].modified = Some(UNIX_EPOCH - Duration::new(5, 250));
+        entries.push(FileEntry {
+            path: root.path().join("ünïcode.md"),
+            name: "ünïcode.md".to_string(),
+            folded_name: Some("unicode.md".to_string()),
+            modified: None,
+            vendored: true,
+            is_symlink: true,
+            owners: Some(0),
+            ..entries[0].clone()
+        });
+
+        let path = root.path().join("entries");
+        for compress in [false, true] {
+            write(&path, &entries, compress).unwrap();
+            let mapped = MappedEntries::open(&path).unwrap();
+            assert_eq!(mapped.len(), entries.len());
+            assert_eq!(mapped.to_vec(), entries);
+            assert_eq!(mapped.get(1).is_dir(), entries[1].is_dir);
+        }
+
+        // A table cut short is rejected rather than read out of bounds
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 3]).unwrap();
+        assert!(MappedEntries::open(&path).is_err());
+        fs::write(&path, b"SSIX").unwrap();
+        assert!(MappedEntries::open(&path).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+/// Highest resident set size this process has reached, in bytes
+#[cfg(unix)]
+pub fn peak_rss() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes to the struct it's given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: initialized by the successful call above
+    let max = unsafe { usage.assume_init() }.ru_maxrss as u64;
+    // macOS reports bytes, everything else kilobytes
+    Some(if cfg!(target_os = "macos") {
+        max
+    } else {
+        max * 1024
+    })
+}
+
+#[cfg(not(unix))]
+pub fn peak_rss() -> Option<u64> {
+    None
+}
+
+/// Resident set size of this process right now, in bytes
+#[cfg(target_os = "linux")]
+pub fn current_rss() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+/// Only Linux reports the current figure cheaply
+#[cfg(not(target_os = "linux"))]
+pub fn current_rss() -> Option<u64> {
+    None
+}
+
+/// Bytes allocated for the elements of `vec`, not counting what they own
+pub(crate) fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+/// Bytes allocated for the slots of `map`, with a control byte each as in
+/// the standard library's table, not counting what keys and values own
+pub(crate) fn map_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_figures() {
+        let vec: Vec<u64> = Vec::with_capacity(10);
+        assert_eq!(vec_bytes(&vec), 80);
+        let map: HashMap<u32, u32> = HashMap::with_capacity(100);
+        assert!(map_bytes(&map) >= 900);
+        if cfg!(unix) {
+            assert!(peak_rss().is_some_and(|peak| peak > 0));
+        }
+        if cfg!(target_os = "linux") {
+            assert!(current_rss().is_some_and(|current| current > 0));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! The filesystems a tree spans, for `scan --one-file-system` and
+//! `--skip-network`: walking into an NFS share or a FUSE mount can stall a
+//! scan for minutes on a slow or unreachable server.
+//!
+//! Mount points and filesystem types come from `/proc/self/mountinfo`, so
+//! only Linux reports them; the device a file is on comes from its metadata
+//! on every Unix.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Filesystems served over the network, whose files may be slow or
+/// unreachable. `autofs` is here because looking inside one mounts a share.
+const NETWORK_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "gpfs",
+    "davfs",
+    "autofs",
+];
+
+/// Filesystems the kernel makes up, with nothing worth indexing in them
+const VIRTUAL_TYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "cgroup",
+    "cgroup2",
+    "debugfs",
+    "tracefs",
+    "securityfs",
+    "pstore",
+    "bpf",
+    "configfs",
+    "fusectl",
+    "mqueue",
+    "hugetlbfs",
+    "binfmt_misc",
+    "efivarfs",
+];
+
+/// One mounted filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub point: PathBuf,
+    /// Type as the kernel names it, e.g. `ext4`, `nfs4` or `fuse.sshfs`
+    pub fstype: String,
+    /// Device number, as [`device`] reports it for files on it
+    pub device: u64,
+}
+
+impl Mount {
+    /// Whether it's served over the network or by a FUSE program, either
+    /// of which can take minutes to answer when something's wrong. Local
+    /// disks mounted through FUSE (ntfs-3g's `fuseblk`) don't count.
+    pub fn is_remote(&self) -> bool {
+        NETWORK_TYPES.contains(&self.fstype.as_str())
+            || self.fstype == "fuse"
+            || self.fstype.starts_with("fuse.")
+    }
+
+    /// Whether the kernel makes its contents up, like `/proc`
+    pub fn is_virtual(&self) -> bool {
+        VIRTUAL_TYPES.contains(&self.fstype.as_str())
+    }
+}
+
+/// The filesystems mounted on this machine
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    /// The mounts the system reports; none where it doesn't
+    pub fn read() -> Self {
+        fs::read_to_string("/proc/self/mountinfo")
+            .map(|mountinfo| Self::parse(&mountinfo))
+            .unwrap_or_default()
+    }
+
+    /// Parse the format of `/proc/self/mountinfo`, skipping lines that
+    /// don't fit it
+    pub fn parse(mountinfo: &str) -> Self {
+        let mounts = mountinfo
+            .lines()
+            .filter_map(|line| {
+                let (mount, filesystem) = line.split_once(" - ")?;
+                let fields: Vec<&str> = mount.split(' ').collect();
+                let (major, minor) = fields.get(2)?.split_once(':')?;
+                Some(Mount {
+                    point: PathBuf::from(unescape(fields.get(4)?)),
+                    fstype: filesystem.split(' ').next()?.to_string(),
+                    device: device_number(major.parse().ok()?, minor.parse().ok()?),
+                })
+            })
+            .collect();
+        MountTable { mounts }
+    }
+
+    /// The mount `path` is on: the one mounted deepest above it
+    pub fn containing(&self, path: &Path) -> Option<&Mount> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.starts_with(&mount.point))
+            .max_by_key(|mount| mount.point.components().count())
+    }
+
+    /// Mounts strictly below `root`
+    pub fn below<'a>(&'a self, root: &'a Path) -> impl Iterator<Item = &'a Mount> + 'a {
+        self.mounts
+            .iter()
+            .filter(move |mount| mount.point != root && mount.point.starts_with(root))
+    }
+
+    /// The most recent mount of `device`
+    pub fn of_device(&self, device: u64) -> Option<&Mount> {
+        self.mounts
+            .iter()
+            .rev()
+            .find(|mount| mount.device == device)
+    }
+}
+
+/// The device a file is on (`st_dev`), where the platform reports it
+pub fn device(metadata: &fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(metadata.dev())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Linux's device number for `major:minor`, as glibc's `makedev` builds it
+fn device_number(major: u64, minor: u64) -> u64 {
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0x0000_0fff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0x0000_00ff)
+}
+
+/// Undo the octal escapes mountinfo writes spaces, tabs, newlines and
+/// backslashes in paths as, e.g. `\040`
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|digits| {
+            bytes[i] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match escape {
+            Some(digits) => {
+                let value = digits
+                    .iter()
+                    .fold(0u32, |value, digit| value * 8 + u32::from(digit - b'0'));
+                out.push(value as u8);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+40 22 0:45 / /home/ana/shared\\040drive rw,relatime shared:30 - nfs4 nas:/export rw
+41 22 0:46 / /home/ana/remote rw,nosuid shared:31 - fuse.sshfs ana@box: rw
+42 22 8:17 / /mnt/windows rw,relatime shared:32 - fuseblk /dev/sdb1 rw
+not a mount line
+";
+
+    #[test]
+    fn test_mount_table() {
+        let table = MountTable::parse(MOUNTINFO);
+        let home = Path::new("/home/ana");
+        let below: Vec<&Path> = table.below(home).map(|m| m.point.as_path()).collect();
+        assert_eq!(
+            below,
+            [
+                Path::new("/home/ana/shared drive"),
+                Path::new("/home/ana/remote")
+            ]
+        );
+        assert!(table.below(home).all(Mount::is_remote));
+
+        let root = table.containing(Path::new("/home/ana/notes.txt")).unwrap();
+        assert_eq!(
+            (root.fstype.as_str(), root.device),
+            ("ext4", (259 << 8) | 2)
+        );
+        assert!(!root.is_remote());
+        let windows = table.containing(Path::new("/mnt/windows/a")).unwrap();
+        assert!(!windows.is_remote(), "fuseblk is a local disk");
+        assert!(table.containing(Path::new("/proc/1")).unwrap().is_virtual());
+        assert_eq!(
+            table.of_device(46).map(|m| m.fstype.as_str()),
+            Some("fuse.sshfs")
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
+
+/// `text` in Unicode normalization form C, so a precomposed `é` and an `e`
+/// followed by a combining accent compare equal
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    match is_nfc_quick(text.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(text),
+        _ => Cow::Owned(text.nfc().collect()),
+    }
+}
+
+/// `text` in normalization form C with the accents of Latin, Greek and
+/// Cyrillic letters removed, so `résumé` reads as `resume`. Letters that
+/// aren't written as a base and an accent, like `ø` or `ß`, are kept.
+pub fn fold_accents(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let folded: String = text.nfd().filter(|&c| !is_accent(c)).nfc().collect();
+    match folded == text {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(folded),
+    }
+}
+
+/// The accent-folded form of `name` stored alongside an entry, or `None`
+/// when that's the name itself
+pub fn folded_name(name: &str) -> Option<String> {
+    match fold_accents(name) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(folded) => Some(folded),
+    }
+}
+
+/// `text` with Cyrillic and Greek letters written in Latin ones, so
+/// `Москва` reads as `Moskva` and `Αθήνα` as `Athina`. Cyrillic follows
+/// ICAO 9303, as on passports, and Greek ISO 843. With the `romanize`
+/// feature Chinese characters are written in pinyin and kana in romaji, as
+/// `cjk::romanize` reads them; other characters are kept.
+pub fn transliterate(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| latin(c).is_some()) {
+        return Cow::Borrowed(text);
+    }
+    let mut output = String::with_capacity(text.len() * 2);
+    for c in nfc(text).chars() {
+        match latin(c) {
+            Some(written) if c.is_uppercase() => {
+                let mut letters = written.chars();
+                output.extend(letters.next().map(|first| first.to_ascii_uppercase()));
+                output.push_str(letters.as_str());
+            }
+            Some(written) => output.push_str(written),
+            None => output.push(c),
+        }
+    }
+    Cow::Owned(output)
+}
+
+/// `text` transliterated with [`transliterate`] and then accent-folded,
+/// as names are compared in an index scanned with `--transliterate`
+pub fn fold_to_latin(text: &str) -> Cow<'_, str> {
+    match transliterate(text) {
+        Cow::Borrowed(text) => fold_accents(text),
+        Cow::Owned(latin) => Cow::Owned(fold_accents(&latin).into_owned()),
+    }
+}
+
+/// The transliterated and folded form of `name` stored alongside an entry
+/// of an index scanned with `--transliterate`, or `None` when it's the
+/// name itself
+pub fn latin_name(name: &str) -> Option<String> {
+    match fold_to_latin(name) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(latin) => Some(latin),
+    }
+}
+
+/// The Latin letters a Cyrillic or Greek letter, or with the `romanize`
+/// feature a CJK character, is written with
+fn latin(c: char) -> Option<&'static str> {
+    #[cfg(feature = "romanize")]
+    if let Some(romanized) = crate::cjk::romanize(c) {
+        return Some(romanized);
+    }
+    let lower = match c {
+        'Ѐ'..='џ' | 'Ґ' | 'ґ' | 'Ά'..='ώ' => c.to_lowercase().next()?,
+        _ => return None,
+    };
+    Some(match lower {
+        // Cyrillic
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' | 'ѓ' => "g",
+        'д' | 'ђ' => "d",
+        'е' | 'ё' | 'э' | 'ѐ' => "e",
+        'є' => "ie",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'й' | 'і' | 'ї' | 'ѝ' => "i",
+        'ј' => "j",
+        'к' | 'ќ' => "k",
+        'л' => "l",
+        'љ' => "lj",
+        'м' => "m",
+        'н' => "n",
+        'њ' => "nj",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'ћ' => "c",
+        'у' | 'ў' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'џ' | 'ѕ' => "dz",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' => "ie",
+        'ы' => "y",
+        'ь' => "",
+        'ю' => "iu",
+        'я' => "ia",
+        // Greek
+        'α' | 'ά' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' | 'έ' => "e",
+        'ζ' => "z",
+        'η' | 'ή' | 'ι' | 'ί' | 'ϊ' | 'ΐ' => "i",
+        'θ' => "th",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ό' | 'ω' | 'ώ' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        _ => return None,
+    })
+}
+
+/// A character of the Combining Diacritical Marks block. Other combining
+/// marks, such as the vowel signs of Indic scripts, change the letter
+/// rather than accent it.
+fn is_accent(c: char) -> bool {
+    ('\u{0300}'..='\u{036F}').contains(&c)
+}
+
+/// `text` passed through `normalize` one character at a time, together
+/// with the byte range of `text` each output character came from. Combining
+/// marks are kept with the character before them, so they can be composed
+/// with or removed from it.
+pub fn mapped<'a>(
+    text: &'a str,
+    normalize: impl Fn(&'a str) -> Cow<'a, str>,
+) -> (String, Vec<Range<usize>>) {
+    let mut output = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, _)) = chars.next() {
+        while chars.next_if(|&(_, c)| is_combining_mark(c)).is_some() {}
+        let end = chars.peek().map_or(text.len(), |&(end, _)| end);
+        for c in normalize(&text[start..end]).chars() {
+            output.push(c);
+            origins.push(start..end);
+        }
+    }
+    (output, origins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folding() {
+        let decomposed = "re\u{301}sume\u{301}.pdf";
+        assert_eq!(nfc(decomposed), "résumé.pdf");
+        assert!(matches!(nfc("résumé.pdf"), Cow::Borrowed(_)));
+        assert_eq!(fold_accents("résumé.pdf"), "resume.pdf");
+        assert_eq!(fold_accents(decomposed), "resume.pdf");
+        assert_eq!(fold_accents("Ærøskøbing Ελλάδα"), "Ærøskøbing Ελλαδα");
+        assert_eq!(fold_accents("हिन्दी"), "हिन्दी");
+        assert_eq!(folded_name("notes.txt"), None);
+        assert_eq!(folded_name("naïve.txt").as_deref(), Some("naive.txt"));
+
+        let (folded, origins) = mapped(decomposed, fold_accents);
+        assert_eq!(folded, "resume.pdf");
+        assert_eq!(origins[1], 1..4);
+        assert_eq!(origins[2], 4..5);
+    }
+
+    #[test]
+    fn test_transliteration() {
+        assert_eq!(transliterate("Москва-2024.pdf"), "Moskva-2024.pdf");
+        assert_eq!(transliterate("Щука и ёж"), "Shchuka i ezh");
+        assert_eq!(transliterate("Київ"), "Kiiv");
+        assert_eq!(fold_to_latin("Αθήνα_σχέδιο.docx"), "Athina_schedio.docx");
+        assert_eq!(fold_to_latin("Café Ω"), "Cafe O");
+        #[cfg(not(feature = "romanize"))]
+        assert!(matches!(transliterate("東京.txt"), Cow::Borrowed(_)));
+        #[cfg(feature = "romanize")]
+        assert_eq!(transliterate("東京.txt"), "dongjing.txt");
+        assert_eq!(latin_name("notes.txt"), None);
+        assert_eq!(latin_name("Отчёт.xlsx").as_deref(), Some("Otchet.xlsx"));
+
+        let (latin, origins) = mapped("Жук", fold_to_latin);
+        assert_eq!(latin, "Zhuk");
+        assert_eq!(origins[..2], [0..2, 0..2]);
+    }
+}
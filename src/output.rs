@@ -0,0 +1,633 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sonic_search::ExtensionStats;
+use sonic_search::quota::Eviction;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How command results are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single JSON document
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// Whether decorated human-readable output should be printed
+    pub fn is_text(self) -> bool {
+        self == OutputFormat::Text
+    }
+}
+
+/// When to highlight matches with ANSI colors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// How text results list their paths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Listing {
+    /// With scores, summaries and decorations, for reading in a terminal
+    #[default]
+    Decorated,
+    /// One bare path per line, for scripts reading a pipe
+    Bare,
+    /// Paths each ended by a NUL byte, for `xargs -0`
+    Nul,
+}
+
+impl Listing {
+    /// NUL-separated with `print0`, otherwise bare unless stdout is a terminal
+    pub fn detect(print0: bool) -> Self {
+        match (print0, std::io::stdout().is_terminal()) {
+            (true, _) => Listing::Nul,
+            (false, false) => Listing::Bare,
+            (false, true) => Listing::Decorated,
+        }
+    }
+
+    pub fn is_decorated(self) -> bool {
+        self == Listing::Decorated
+    }
+
+    /// Write `path` as one entry of the listing
+    pub fn write_path(self, out: &mut impl Write, path: &Path) -> std::io::Result<()> {
+        self.write(out, path.as_os_str().as_encoded_bytes())
+    }
+
+    /// Write `entry`, followed by a NUL byte or a newline
+    pub fn write(self, out: &mut impl Write, entry: &[u8]) -> std::io::Result<()> {
+        out.write_all(entry)?;
+        out.write_all(match self {
+            Listing::Nul => b"\0",
+            _ => b"\n",
+        })
+    }
+}
+
+/// A file matched by `find`
+#[derive(Debug, Serialize)]
+pub struct FindRecord<'a> {
+    pub path: &'a Path,
+    pub name: &'a str,
+    pub score: i64,
+    pub size: u64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: Option<u64>,
+    /// Name of the sub-project containing the file
+    pub project: Option<&'a str>,
+    /// CODEOWNERS owners of the file
+    pub owners: &'a [String],
+    /// Index directory the match came from, when searching several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<&'a Path>,
+    /// Inside a git submodule or vendor directory
+    pub vendored: bool,
+    /// BLAKE3 content hash, for indexes scanned with `--hash`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// MIME type, by extension or sniffed with `scan --sniff-mime`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<&'static str>,
+    /// Owning user and group, by name where they have one (Unix)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Permission bits in octal, such as `644` (Unix)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Nobody may write to it
+    pub readonly: bool,
+    /// Modification time in the `--date-format` asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// Most recent commit touching the file, when requested with `--git`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_commit: Option<CommitRecord<'a>>,
+    /// README or entry point of a matched directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<&'a Path>,
+}
+
+/// The last commit that touched a file
+#[derive(Debug, Serialize)]
+pub struct CommitRecord<'a> {
+    pub author: &'a str,
+    pub email: &'a str,
+    /// Commit time in seconds since the Unix epoch
+    pub time: Option<u64>,
+}
+
+/// The archive written by `collect`
+#[derive(Debug, Serialize)]
+pub struct CollectRecord<'a> {
+    pub archive: &'a Path,
+    pub files: usize,
+    /// Total size in bytes of the archived files before compression
+    pub size: u64,
+    /// Matches that no longer existed
+    pub skipped: &'a [PathBuf],
+}
+
+/// A sub-project listed by `projects`
+#[derive(Debug, Serialize)]
+pub struct ProjectRecord<'a> {
+    pub name: &'a str,
+    pub kind: &'a str,
+    pub root: &'a Path,
+    pub files: usize,
+}
+
+/// A matching line reported by `grep`
+#[derive(Debug, Serialize)]
+pub struct GrepRecord<'a> {
+    pub path: &'a Path,
+    pub line_number: usize,
+    /// 1-based byte column of the first match
+    pub column: usize,
+    /// Byte offset of the first match within the file
+    pub byte_offset: u64,
+    pub line: &'a str,
+    /// Byte offsets `[start, end)` of every match within `line`
+    pub spans: Vec<[usize; 2]>,
+    /// Context lines before and after `line`, with `-B`, `-A` or `-C`
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub before: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub after: &'a [String],
+    /// CODEOWNERS owners of the file
+    pub owners: &'a [String],
+    /// Index directory the match came from, when searching several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<&'a Path>,
+}
+
+/// A file ranked by `grep --fulltext`
+#[cfg(feature = "fulltext")]
+#[derive(Debug, Serialize)]
+pub struct FulltextRecord<'a> {
+    pub path: &'a Path,
+    /// BM25 relevance; higher is better
+    pub score: f32,
+    /// Index directory the match came from, when searching several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<&'a Path>,
+}
+
+/// A matching line in a historical file version, reported by `grep --history`
+#[derive(Debug, Serialize)]
+pub struct HistoryRecord<'a> {
+    /// Object id of the file version
+    pub blob: &'a str,
+    /// Path the version was committed under, relative to the repository root
+    pub path: &'a str,
+    /// Commits that added or removed this version, oldest first
+    pub commits: Vec<HistoryCommit>,
+    pub line_number: usize,
+    pub line: &'a str,
+    pub spans: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryCommit {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub time: Option<u64>,
+    pub summary: String,
+}
+
+/// A search run against an index, listed by `history`
+#[derive(Debug, Serialize)]
+pub struct SearchRecord<'a> {
+    pub command_line: String,
+    /// Arguments after the program name, starting with the subcommand
+    pub args: &'a [String],
+    /// Seconds since the Unix epoch
+    pub time: u64,
+}
+
+/// A search saved with `save-search`, listed by `history --saved`
+#[derive(Debug, Serialize)]
+pub struct SavedSearchRecord<'a> {
+    pub name: &'a str,
+    /// `find` arguments, starting with the query
+    pub args: &'a [String],
+}
+
+/// A tag and how many files carry it, listed by `tag list`
+#[derive(Debug, Serialize)]
+pub struct TagRecord<'a> {
+    pub tag: &'a str,
+    pub files: usize,
+}
+
+/// A file carrying the tag `tag list` was asked for
+#[derive(Debug, Serialize)]
+pub struct TaggedFileRecord<'a> {
+    pub path: &'a Path,
+    /// Every tag of the file
+    pub tags: &'a std::collections::BTreeSet<String>,
+}
+
+/// An entry that changed between two generations, reported by `diff`
+#[derive(Debug, Serialize)]
+pub struct DiffChangeRecord<'a> {
+    pub path: &'a Path,
+    /// `added`, `removed`, `grown`, `shrunk` or `modified`
+    pub change: &'static str,
+    pub is_dir: bool,
+    /// Size in bytes in the older generation, unless added
+    pub old_size: Option<u64>,
+    /// Size in bytes in the newer generation, unless removed
+    pub new_size: Option<u64>,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: Option<u64>,
+}
+
+/// A group of identical files reported by `dupes`
+#[derive(Debug, Serialize)]
+pub struct DupeRecord<'a> {
+    pub size: u64,
+    /// BLAKE3 hash of the contents
+    pub hash: &'a str,
+    /// Bytes freed by keeping only one of the files
+    pub reclaimable: u64,
+    pub paths: &'a [PathBuf],
+}
+
+/// A copy of a file found by `same-as`
+#[derive(Debug, Serialize)]
+pub struct SameAsRecord<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+    /// BLAKE3 hash of the contents
+    pub hash: &'a str,
+    /// Its contents were read again, rather than matched by a stored hash
+    pub verified: bool,
+}
+
+/// Names in one directory that collide, listed by `name-collisions`
+#[derive(Debug, Serialize)]
+pub struct CollisionRecord<'a> {
+    pub directory: &'a Path,
+    /// `case` or `normalization`
+    pub kind: &'static str,
+    pub names: &'a [String],
+}
+
+/// A path flagged by `audit --portability`
+#[derive(Debug, Serialize)]
+pub struct PortabilityRecord<'a> {
+    pub path: &'a Path,
+    /// Kind of problem, e.g. `illegal-character` or `path-too-long`
+    pub problem: &'static str,
+    pub detail: String,
+}
+
+/// A configuration file listed by `configs`
+#[derive(Debug, Serialize)]
+pub struct ConfigRecord<'a> {
+    pub application: &'static str,
+    pub path: &'a Path,
+    pub size: u64,
+}
+
+/// A file copied or moved by `find --copy-to` or `--move-to`
+#[derive(Debug, Serialize)]
+pub struct TransferRecord<'a> {
+    pub from: &'a Path,
+    pub to: &'a Path,
+    /// `copy`, `move`, `overwrite` or `skip`
+    pub action: &'a str,
+    /// Only planned, not carried out
+    pub dry_run: bool,
+}
+
+/// A file renamed by `rename`, or by `rename --undo`
+#[derive(Debug, Serialize)]
+pub struct RenameRecord<'a> {
+    pub from: &'a Path,
+    pub to: &'a Path,
+    /// Only planned, not carried out
+    pub dry_run: bool,
+}
+
+/// A file changed by `replace`, or that `replace --dry-run` would change
+#[derive(Debug, Serialize)]
+pub struct ReplaceRecord<'a> {
+    pub path: &'a Path,
+    /// Number of matches replaced
+    pub replacements: usize,
+    /// Unified diff of the change
+    pub diff: String,
+    /// Only planned, not carried out
+    pub dry_run: bool,
+}
+
+/// A remembered directory listed by `jump --list`
+#[derive(Debug, Serialize)]
+pub struct JumpRecord<'a> {
+    pub path: &'a Path,
+    /// Visits weighted by how recent the last one was
+    pub score: f64,
+    pub visits: f64,
+    /// Seconds since the Unix epoch
+    pub last_visit: Option<u64>,
+}
+
+/// A file whose permissions or owner `find --chmod` or `--chown` changed
+#[derive(Debug, Serialize)]
+pub struct ChangeRecord<'a> {
+    pub path: &'a Path,
+    /// `changed`, `unchanged` (it already matched) or `failed`
+    pub status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A file matched by only one of the queries given to `diff-results`
+#[derive(Debug, Serialize)]
+pub struct DiffRecord<'a> {
+    pub path: &'a Path,
+    /// The query that matched it
+    pub only_in: &'a str,
+}
+
+/// Summary of a stored index reported by `stats`
+#[derive(Debug, Serialize)]
+pub struct StatsRecord<'a> {
+    pub index_dir: &'a Path,
+    pub root: &'a Path,
+    pub generation: u64,
+    pub files: usize,
+    pub directories: usize,
+    pub total_size: u64,
+    pub content_index: bool,
+    /// The scan was interrupted, so files may be missing
+    pub partial: bool,
+    /// Seconds since the Unix epoch
+    pub created_at: Option<u64>,
+    pub updated_at: Option<u64>,
+    /// Seconds since the index was last updated
+    pub age_secs: u64,
+    pub largest: Vec<LargeFile<'a>>,
+    pub extensions: &'a [ExtensionStats],
+    pub deepest: Vec<DeepPath<'a>>,
+    /// Documents per detected language, when the content index is loaded
+    pub languages: Vec<LanguageCount<'a>>,
+    pub memory: MemoryRecord,
+    pub disk: DiskRecord,
+    /// Filesystems the indexed entries are on, most entries first
+    pub filesystems: Vec<FilesystemRecord>,
+}
+
+/// One of the largest files listed by `stats`
+#[derive(Debug, Serialize)]
+pub struct LargeFile<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+}
+
+/// One of the most deeply nested files listed by `stats`
+#[derive(Debug, Serialize)]
+pub struct DeepPath<'a> {
+    pub path: &'a Path,
+    /// Number of path components below the root
+    pub depth: usize,
+}
+
+/// Number of documents written in one language, listed by `stats`
+#[derive(Debug, Serialize)]
+pub struct LanguageCount<'a> {
+    /// ISO 639-1 code
+    pub language: &'a str,
+    pub files: usize,
+}
+
+/// Memory taken up by the loaded index and by the process reporting it,
+/// which for `ss serve` is the daemon
+#[derive(Debug, Serialize)]
+pub struct MemoryRecord {
+    /// Approximate bytes the index takes up once loaded
+    pub index_bytes: usize,
+    /// Part of `index_bytes` taken up by the content index
+    pub content_index_bytes: usize,
+    /// Resident set size, where the platform reports it
+    pub rss_bytes: Option<u64>,
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Disk space taken by the index directory, and what `--max-index-size`
+/// evicted to stay under it
+#[derive(Debug, Serialize)]
+pub struct DiskRecord {
+    pub usage_bytes: u64,
+    pub max_bytes: Option<u64>,
+    /// The most recent evictions, oldest first
+    pub evictions: Vec<Eviction>,
+}
+
+/// A filesystem holding indexed entries, listed by `stats`
+#[derive(Debug, Serialize)]
+pub struct FilesystemRecord {
+    pub device: u64,
+    /// Where it's mounted and its type, if it's still mounted
+    pub mount_point: Option<PathBuf>,
+    pub fstype: Option<String>,
+    /// Served over the network or by a FUSE program
+    pub remote: bool,
+    pub entries: usize,
+}
+
+/// Seconds since the Unix epoch, or `None` for times before it
+pub fn unix_secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Write a list of records: a JSON array, or one line per record for NDJSON.
+///
+/// Text output is formatted by each command itself, so nothing is written.
+pub fn write_records<T: Serialize>(
+    out: &mut impl Write,
+    format: OutputFormat,
+    records: &[T],
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a single record, pretty-printed for JSON and on one line for NDJSON
+pub fn write_record<T: Serialize>(
+    out: &mut impl Write,
+    format: OutputFormat,
+    record: &T,
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, record)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut *out, record)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn records() -> Vec<FindRecord<'static>> {
+        vec![
+            FindRecord {
+                path: Path::new("/tmp/a.rs"),
+                name: "a.rs",
+                score: 42,
+                size: 10,
+                is_dir: false,
+                is_symlink: false,
+                mtime: Some(1_700_000_000),
+                project: Some("app"),
+                owners: &[],
+                index: Some(Path::new("/tmp/.sonic-search")),
+                vendored: false,
+                hash: None,
+                mime: None,
+                user: None,
+                group: None,
+                mode: None,
+                readonly: false,
+                modified: Some("2023-11-14T22:13:20Z".to_string()),
+                last_commit: Some(CommitRecord {
+                    author: "Ada",
+                    email: "ada@example.com",
+                    time: Some(1_700_000_000),
+                }),
+                entry_point: None,
+            },
+            FindRecord {
+                path: Path::new("/tmp/b.rs"),
+                name: "b.rs",
+                score: 7,
+                size: 0,
+                is_dir: false,
+                is_symlink: true,
+                mtime: None,
+                project: None,
+                owners: &[],
+                index: None,
+                vendored: false,
+                hash: None,
+                mime: None,
+                user: None,
+                group: None,
+                mode: None,
+                readonly: false,
+                modified: None,
+                last_commit: None,
+                entry_point: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_json_is_one_array() {
+        let mut out = Vec::new();
+        write_records(&mut out, OutputFormat::Json, &records()).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert_eq!(parsed[0]["path"], "/tmp/a.rs");
+        assert_eq!(parsed[0]["score"], 42);
+        assert_eq!(parsed[1]["mtime"], serde_json::Value::Null);
+        assert_eq!(parsed[0]["last_commit"]["author"], "Ada");
+        assert!(parsed[1].get("last_commit").is_none());
+        assert_eq!(parsed[0]["index"], "/tmp/.sonic-search");
+        assert!(parsed[1].get("index").is_none());
+    }
+
+    #[test]
+    fn test_ndjson_is_one_record_per_line() {
+        let mut out = Vec::new();
+        write_records(&mut out, OutputFormat::Ndjson, &records()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(second["name"], "b.rs");
+
+        let mut out = Vec::new();
+        write_records(&mut out, OutputFormat::Text, &records()).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_listing_paths() {
+        let mut out = Vec::new();
+        for path in ["/tmp/a b.rs", "/tmp/c.rs"] {
+            Listing::Nul.write_path(&mut out, Path::new(path)).unwrap();
+        }
+        assert_eq!(out, b"/tmp/a b.rs\0/tmp/c.rs\0");
+        let mut out = Vec::new();
+        Listing::Bare
+            .write_path(&mut out, Path::new("/tmp/a.rs"))
+            .unwrap();
+        assert_eq!(out, b"/tmp/a.rs\n");
+        assert_eq!(Listing::detect(true), Listing::Nul);
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+    }
+
+    #[test]
+    fn test_unix_secs() {
+        assert_eq!(unix_secs(UNIX_EPOCH + Duration::from_secs(5)), Some(5));
+        assert_eq!(unix_secs(UNIX_EPOCH - Duration::from_secs(5)), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::Write;
+use std::process::Child;
+
+/// Environment variable choosing the pager for sonic-search alone, taking
+/// precedence over `$PAGER`
+pub const PAGER_ENV: &str = "SS_PAGER";
+
+/// Pager run when neither variable is set
+const DEFAULT_PAGER: &str = "less";
+
+/// `$LESS` when it isn't set, as git does: quit if the output fits on one
+/// screen, pass colors through and leave the output on screen afterwards
+const DEFAULT_LESS: &str = "FRX";
+
+/// A running pager that stdout, and stderr if it's a terminal, are sent to
+/// until it's dropped
+pub struct Pager {
+    child: Child,
+    /// The original stdout and stderr, put back once the output is complete
+    #[cfg(unix)]
+    saved: [std::os::fd::OwnedFd; 2],
+}
+
+/// The command to page through, given the `$SS_PAGER` or `$PAGER` setting;
+/// `None` when paging is turned off by setting it empty or to `cat`
+pub fn pager_command(setting: Option<&str>) -> Option<String> {
+    let command = setting.unwrap_or(DEFAULT_PAGER).trim();
+    (!command.is_empty() && command != "cat").then(|| command.to_string())
+}
+
+impl Pager {
+    /// Send the rest of the output through the pager when stdout is a
+    /// terminal. Returns `None` if it isn't, paging is turned off or the
+    /// pager can't be started, in which case output goes where it did.
+    #[cfg(unix)]
+    pub fn start() -> Option<Self> {
+        use std::io::IsTerminal;
+        use std::os::fd::{AsFd, AsRawFd};
+        use std::process::{Command, Stdio};
+
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+        let setting = std::env::var(PAGER_ENV).or_else(|_| std::env::var("PAGER"));
+        let command = pager_command(setting.as_deref().ok())?;
+        let mut pager = Command::new("sh");
+        pager.arg("-c").arg(&command).stdin(Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            pager.env("LESS", DEFAULT_LESS);
+        }
+        let mut child = pager.spawn().ok()?;
+        let stdin = child.stdin.take()?;
+        let saved = [
+            std::io::stdout().as_fd().try_clone_to_owned().ok()?,
+            std::io::stderr().as_fd().try_clone_to_owned().ok()?,
+        ];
+        let targets: &[i32] = match std::io::stderr().is_terminal() {
+            true => &[libc::STDOUT_FILENO, libc::STDERR_FILENO],
+            false => &[libc::STDOUT_FILENO],
+        };
+        for &fd in targets {
+            // SAFETY: both descriptors are open for the duration of the call
+            unsafe { libc::dup2(stdin.as_raw_fd(), fd) };
+        }
+        // Quit quietly once the pager exits rather than failing to print
+        // SAFETY: restores the default disposition, which has no handler
+        unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+        Some(Pager { child, saved })
+    }
+
+    /// Paging relies on redirecting stdout, which is only done on Unix
+    #[cfg(not(unix))]
+    pub fn start() -> Option<Self> {
+        None
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        // Putting the original descriptors back closes the pager's input,
+        // so it sees the end of the output, and lets errors reach the terminal
+        #[cfg(unix)]
+        for (saved, fd) in self
+            .saved
+            .iter()
+            .zip([libc::STDOUT_FILENO, libc::STDERR_FILENO])
+        {
+            use std::os::fd::AsRawFd;
+            // SAFETY: `saved` is an open descriptor owned by the pager
+            unsafe { libc::dup2(saved.as_raw_fd(), fd) };
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(pager_command(None).as_deref(), Some("less"));
+        assert_eq!(pager_command(Some(" most -s ")).as_deref(), Some("most -s"));
+        assert_eq!(pager_command(Some("")), None);
+        assert_eq!(pager_command(Some("cat")), None);
+    }
+}
@@ -0,0 +1,397 @@
+//! A minimal Parquet writer: flat tables of optional strings, integers and
+//! timestamps and of booleans, one zstd-compressed page per column chunk.
+//!
+//! Only what `ss export` needs is covered, which keeps the dependency tree
+//! free of Arrow. Files follow the format spec at
+//! <https://parquet.apache.org/docs/file-format/>.
+
+use anyhow::Result;
+use std::io::Write;
+use std::ops::Range;
+
+/// Rows per row group, so readers can skip or parallelize over parts of a
+/// large table
+const ROW_GROUP_ROWS: usize = 1 << 20;
+
+const MAGIC: &[u8; 4] = b"PAR1";
+
+/// The values of one column, `None` where a row has no value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Bool(Vec<bool>),
+    Int64(Vec<Option<i64>>),
+    /// Milliseconds since the Unix epoch, in UTC
+    Timestamp(Vec<Option<i64>>),
+    Utf8(Vec<Option<String>>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::Bool(values) => values.len(),
+            Values::Int64(values) | Values::Timestamp(values) => values.len(),
+            Values::Utf8(values) => values.len(),
+        }
+    }
+
+    /// The physical type, as numbered by the format
+    fn physical_type(&self) -> i32 {
+        match self {
+            Values::Bool(_) => 0,
+            Values::Int64(_) | Values::Timestamp(_) => 2,
+            Values::Utf8(_) => 6,
+        }
+    }
+
+    fn is_optional(&self) -> bool {
+        !matches!(self, Values::Bool(_))
+    }
+
+    /// Definition levels and PLAIN-encoded values of `rows`
+    fn encode(&self, rows: Range<usize>) -> Vec<u8> {
+        let mut page = Vec::new();
+        match self {
+            Values::Bool(values) => {
+                let mut bits = vec![0u8; rows.len().div_ceil(8)];
+                for (i, &value) in values[rows].iter().enumerate() {
+                    bits[i / 8] |= u8::from(value) << (i % 8);
+                }
+                page.extend_from_slice(&bits);
+            }
+            Values::Int64(values) | Values::Timestamp(values) => {
+                let values = &values[rows];
+                write_levels(&mut page, values.iter().map(Option::is_some));
+                for value in values.iter().flatten() {
+                    page.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Values::Utf8(values) => {
+                let values = &values[rows];
+                write_levels(&mut page, values.iter().map(Option::is_some));
+                for value in values.iter().flatten() {
+                    page.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    page.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+        page
+    }
+}
+
+/// A named column of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: &'static str,
+    pub values: Values,
+}
+
+/// Definition levels of an optional column, 1 where a value is present,
+/// as length-prefixed runs of the RLE/bit-packing hybrid
+fn write_levels(page: &mut Vec<u8>, present: impl Iterator<Item = bool>) {
+    let mut runs = Vec::new();
+    let mut run: Option<(bool, u64)> = None;
+    for value in present {
+        run = match run {
+            Some((current, count)) if current == value => Some((current, count + 1)),
+            Some((current, count)) => {
+                write_varint(&mut runs, count << 1);
+                runs.push(u8::from(current));
+                Some((value, 1))
+            }
+            None => Some((value, 1)),
+        };
+    }
+    if let Some((current, count)) = run {
+        write_varint(&mut runs, count << 1);
+        runs.push(u8::from(current));
+    }
+    page.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    page.extend_from_slice(&runs);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Where a column chunk ended up in the file
+struct ChunkInfo {
+    offset: u64,
+    /// Of the page header and the page, before and after compression
+    uncompressed: u64,
+    compressed: u64,
+    values: usize,
+}
+
+/// Write `columns`, which must all be as long, as a Parquet file
+pub fn write(mut out: impl Write, columns: &[Column]) -> Result<()> {
+    let rows = columns.first().map_or(0, |column| column.values.len());
+    if columns.iter().any(|column| column.values.len() != rows) {
+        anyhow::bail!("Columns of a table must be equally long");
+    }
+    out.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as u64;
+    let mut row_groups = Vec::new();
+    let mut start = 0;
+    while start < rows {
+        let range = start..(start + ROW_GROUP_ROWS).min(rows);
+        let mut chunks = Vec::new();
+        for column in columns {
+            let page = column.values.encode(range.clone());
+            let compressed = zstd::bulk::compress(&page, 3)?;
+            let mut header = Thrift::new();
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, page.len().try_into()?);
+            header.i32(3, compressed.len().try_into()?);
+            header.begin_struct(5);
+            header.i32(1, range.len().try_into()?);
+            header.i32(2, 0); // PLAIN
+            header.i32(3, 3); // RLE
+            header.i32(4, 3);
+            header.end_struct();
+            let header = header.finish();
+            out.write_all(&header)?;
+            out.write_all(&compressed)?;
+            chunks.push(ChunkInfo {
+                offset,
+                uncompressed: (header.len() + page.len()) as u64,
+                compressed: (header.len() + compressed.len()) as u64,
+                values: range.len(),
+            });
+            offset += (header.len() + compressed.len()) as u64;
+        }
+        row_groups.push((range.len(), chunks));
+        start = range.end;
+    }
+
+    let footer = footer(columns, rows, &row_groups);
+    out.write_all(&footer)?;
+    out.write_all(&(footer.len() as u32).to_le_bytes())?;
+    out.write_all(MAGIC)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The file metadata: schema, row groups and where their chunks are
+fn footer(columns: &[Column], rows: usize, row_groups: &[(usize, Vec<ChunkInfo>)]) -> Vec<u8> {
+    let mut meta = Thrift::new();
+    meta.i32(1, 1);
+    meta.list(2, Thrift::STRUCT, columns.len() + 1);
+    meta.begin_element();
+    meta.binary(4, b"schema");
+    meta.i32(5, columns.len() as i32);
+    meta.end_struct();
+    for column in columns {
+        meta.begin_element();
+        meta.i32(1, column.values.physical_type());
+        meta.i32(3, i32::from(column.values.is_optional()));
+        meta.binary(4, column.name.as_bytes());
+        match column.values {
+            Values::Utf8(_) => {
+                meta.i32(6, 0); // UTF8
+                meta.begin_struct(10);
+                meta.begin_struct(1); // STRING
+                meta.end_struct();
+                meta.end_struct();
+            }
+            Values::Timestamp(_) => {
+                meta.i32(6, 9); // TIMESTAMP_MILLIS
+                meta.begin_struct(10);
+                meta.begin_struct(8); // TIMESTAMP
+                meta.bool(1, true);
+                meta.begin_struct(2);
+                meta.begin_struct(1); // MILLIS
+                meta.end_struct();
+                meta.end_struct();
+                meta.end_struct();
+                meta.end_struct();
+            }
+            Values::Bool(_) | Values::Int64(_) => {}
+        }
+        meta.end_struct();
+    }
+    meta.i64(3, rows as i64);
+    meta.list(4, Thrift::STRUCT, row_groups.len());
+    for (group_rows, chunks) in row_groups {
+        meta.begin_element();
+        meta.list(1, Thrift::STRUCT, chunks.len());
+        for (column, chunk) in columns.iter().zip(chunks) {
+            meta.begin_element();
+            meta.i64(2, chunk.offset as i64);
+            meta.begin_struct(3);
+            meta.i32(1, column.values.physical_type());
+            meta.list(2, Thrift::I32, 2);
+            meta.list_i32(0); // PLAIN
+            meta.list_i32(3); // RLE
+            meta.list(3, Thrift::BINARY, 1);
+            meta.list_binary(column.name.as_bytes());
+            meta.i32(4, 6); // ZSTD
+            meta.i64(5, chunk.values as i64);
+            meta.i64(6, chunk.uncompressed as i64);
+            meta.i64(7, chunk.compressed as i64);
+            meta.i64(9, chunk.offset as i64);
+            meta.end_struct();
+            meta.end_struct();
+        }
+        meta.i64(
+            2,
+            chunks.iter().map(|chunk| chunk.uncompressed as i64).sum(),
+        );
+        meta.i64(3, *group_rows as i64);
+        meta.end_struct();
+    }
+    meta.binary(
+        6,
+        concat!("sonic-search version ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    meta.finish()
+}
+
+/// Writes structs in the Thrift compact protocol, which Parquet metadata
+/// is encoded in
+struct Thrift {
+    out: Vec<u8>,
+    /// Id of the last field written in each struct being written
+    last: Vec<i16>,
+}
+
+impl Thrift {
+    const I32: u8 = 5;
+    const BINARY: u8 = 8;
+    const STRUCT: u8 = 12;
+
+    fn new() -> Self {
+        Thrift {
+            out: Vec::new(),
+            last: vec![0],
+        }
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("inside a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.out.push((delta as u8) << 4 | kind);
+        } else {
+            self.out.push(kind);
+            write_varint(&mut self.out, zigzag(id.into()));
+        }
+        *last = id;
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.field(id, if value { 1 } else { 2 });
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, Self::I32);
+        write_varint(&mut self.out, zigzag(value.into()));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, 6);
+        write_varint(&mut self.out, zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.field(id, Self::BINARY);
+        self.list_binary(bytes);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, Self::STRUCT);
+        self.last.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.out.push(0);
+        self.last.pop();
+    }
+
+    /// A list field of `len` elements of `kind`, written next
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, 9);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | kind);
+        } else {
+            self.out.push(0xf0 | kind);
+            write_varint(&mut self.out, len as u64);
+        }
+    }
+
+    /// A struct in a list, ended with [`Thrift::end_struct`]
+    fn begin_element(&mut self) {
+        self.last.push(0);
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        write_varint(&mut self.out, zigzag(value.into()));
+    }
+
+    fn list_binary(&mut self, bytes: &[u8]) {
+        write_varint(&mut self.out, bytes.len() as u64);
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.out.push(0);
+        self.out
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_table() {
+        let columns = [
+            Column {
+                name: "path",
+                values: Values::Utf8(vec![Some("/r/a.txt".into()), None, Some("/r/b".into())]),
+            },
+            Column {
+                name: "size",
+                values: Values::Int64(vec![Some(12), Some(0), None]),
+            },
+            Column {
+                name: "is_dir",
+                values: Values::Bool(vec![false, true, true]),
+            },
+        ];
+        let mut file = Vec::new();
+        write(&mut file, &columns).unwrap();
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let footer_len =
+            u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap());
+        let footer = &file[file.len() - 8 - footer_len as usize..file.len() - 8];
+        let contains = |text: &[u8]| footer.windows(text.len()).any(|window| window == text);
+        assert!(contains(b"schema") && contains(b"path") && contains(b"is_dir"));
+
+        // Two runs of present values around a missing one, then the lengths
+        // and bytes of the strings that are there
+        let page = columns[0].values.encode(0..3);
+        assert_eq!(&page[..10], [6, 0, 0, 0, 2, 1, 2, 0, 2, 1]);
+        assert_eq!(&page[10..14], 8u32.to_le_bytes());
+        assert_eq!(columns[2].values.encode(0..3), [0b110]);
+
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(3), 6);
+        let empty = [Column {
+            name: "x",
+            values: Values::Bool(Vec::new()),
+        }];
+        let mut file = Vec::new();
+        write(&mut file, &empty).unwrap();
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let uneven = [columns[0].clone(), empty[0].clone()];
+        assert!(write(Vec::new(), &uneven).is_err());
+    }
+}
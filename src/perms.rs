@@ -0,0 +1,451 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Ownership, permission bits and attributes of an entry, as its scan found
+/// them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attributes {
+    /// Owning user id (Unix)
+    pub uid: Option<u32>,
+    /// Owning group id (Unix)
+    pub gid: Option<u32>,
+    /// Permission bits, such as `0o644` (Unix)
+    pub mode: Option<u32>,
+    /// Nobody may write to it: no write bits on Unix, the read-only
+    /// attribute on Windows
+    pub readonly: bool,
+    /// The hidden attribute, which Windows and macOS keep apart from the
+    /// file name
+    pub hidden: bool,
+}
+
+impl Attributes {
+    /// The attributes `metadata` reports
+    pub fn of(metadata: &fs::Metadata) -> Self {
+        let mut attributes = Attributes {
+            readonly: metadata.permissions().readonly(),
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            attributes.uid = Some(metadata.uid());
+            attributes.gid = Some(metadata.gid());
+            attributes.mode = Some(metadata.mode() & 0o7777);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+            attributes.hidden = metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+        }
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::macos::fs::MetadataExt;
+            const UF_HIDDEN: u32 = 0x8000;
+            attributes.hidden = metadata.st_flags() & UF_HIDDEN != 0;
+        }
+        attributes
+    }
+}
+
+/// A `--perm` filter on permission bits, written like find's `-perm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PermFilter {
+    /// `644`: exactly these bits
+    Exactly(u32),
+    /// `-022`: at least all of these bits
+    AllOf(u32),
+    /// `/111`: at least one of these bits
+    AnyOf(u32),
+}
+
+impl PermFilter {
+    /// Parse `MODE`, `-MODE` or `/MODE`, with the mode in octal
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(match text.as_bytes().first() {
+            Some(b'-') => PermFilter::AllOf(parse_mode(&text[1..])?),
+            Some(b'/') => PermFilter::AnyOf(parse_mode(&text[1..])?),
+            _ => PermFilter::Exactly(parse_mode(text)?),
+        })
+    }
+
+    pub fn matches(self, mode: u32) -> bool {
+        match self {
+            PermFilter::Exactly(bits) => mode & 0o7777 == bits,
+            PermFilter::AllOf(bits) => mode & bits == bits,
+            PermFilter::AnyOf(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+}
+
+/// Where user and group names are looked up
+const PASSWD: &str = "/etc/passwd";
+const GROUP: &str = "/etc/group";
+
+/// The id of user `name`, or `name` itself when it's numeric
+pub fn user_id(name: &str) -> Result<u32> {
+    resolve_id(name, Path::new(PASSWD), "user")
+}
+
+/// The id of group `name`, or `name` itself when it's numeric
+pub fn group_id(name: &str) -> Result<u32> {
+    resolve_id(name, Path::new(GROUP), "group")
+}
+
+fn resolve_id(name: &str, database: &Path, kind: &str) -> Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    match lookup_id(database, name)? {
+        Some(id) => Ok(id),
+        None => anyhow::bail!("Unknown {} '{}'; give a numeric id instead", kind, name),
+    }
+}
+
+/// The name of the user with id `uid`, if it has one
+pub fn user_name(uid: u32) -> Option<&'static str> {
+    static USERS: OnceLock<HashMap<u32, String>> = OnceLock::new();
+    let users = USERS.get_or_init(|| read_names(Path::new(PASSWD)));
+    users.get(&uid).map(String::as_str)
+}
+
+/// The name of the group with id `gid`, if it has one
+pub fn group_name(gid: u32) -> Option<&'static str> {
+    static GROUPS: OnceLock<HashMap<u32, String>> = OnceLock::new();
+    let groups = GROUPS.get_or_init(|| read_names(Path::new(GROUP)));
+    groups.get(&gid).map(String::as_str)
+}
+
+/// The name of the user with id `uid`, or the id when it has none
+pub fn user_label(uid: u32) -> String {
+    user_name(uid).map_or_else(|| uid.to_string(), str::to_string)
+}
+
+/// The name of the group with id `gid`, or the id when it has none
+pub fn group_label(gid: u32) -> String {
+    group_name(gid).map_or_else(|| gid.to_string(), str::to_string)
+}
+
+/// Names by id in a passwd- or group-style `database`; empty when it can't
+/// be read
+fn read_names(database: &Path) -> HashMap<u32, String> {
+    let contents = fs::read_to_string(database).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// Parse permission bits given in octal, such as `640` or `0755`
+pub fn parse_mode(text: &str) -> Result<u32> {
+    let mode = u32::from_str_radix(text, 8)
+        .ok()
+        .filter(|&mode| mode <= 0o7777)
+        .with_context(|| format!("Invalid mode '{}': expected octal such as 640", text))?;
+    Ok(mode)
+}
+
+/// A new owner and/or group for files, as given to `--chown user:group`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// The text it was parsed from, for messages
+    pub spec: String,
+}
+
+impl Owner {
+    /// Parse `user`, `user:group` or `:group`, where names are looked up in
+    /// `/etc/passwd` and `/etc/group` and numeric ids are taken as they are
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (user, group) = spec.split_once(':').unwrap_or((spec, ""));
+        if user.is_empty() && group.is_empty() {
+            anyhow::bail!(
+                "Invalid owner '{}': expected user, user:group or :group",
+                spec
+            );
+        }
+        let resolve = |name: &str, lookup: fn(&str) -> Result<u32>| -> Result<Option<u32>> {
+            match name.is_empty() {
+                true => Ok(None),
+                false => lookup(name).map(Some),
+            }
+        };
+        Ok(Owner {
+            uid: resolve(user, user_id)?,
+            gid: resolve(group, group_id)?,
+            spec: spec.to_string(),
+        })
+    }
+}
+
+/// The id of `name` in a passwd- or group-style `database`, whose lines
+/// are `name:password:id:...`
+fn lookup_id(database: &Path, name: &str) -> Result<Option<u32>> {
+    let contents = match fs::read_to_string(database) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", database.display())),
+    };
+    Ok(contents.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        (fields.next() == Some(name))
+            .then(|| fields.nth(1)?.parse().ok())
+            .flatten()
+    }))
+}
+
+/// Permission, ownership and attribute changes applied to every matched file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Change {
+    pub mode: Option<u32>,
+    pub owner: Option<Owner>,
+    /// Set the modification time to now
+    pub touch: bool,
+    /// Take away write permission
+    pub readonly: bool,
+    /// Clear the hidden attribute (Windows and macOS)
+    pub clear_hidden: bool,
+}
+
+impl Change {
+    /// Whether the change leaves files as they are
+    pub fn is_empty(&self) -> bool {
+        *self == Change::default()
+    }
+
+    /// What the change does, for confirmation prompts, such as
+    /// `touch and make read-only`
+    pub fn describe(&self) -> String {
+        let mut what = Vec::new();
+        if let Some(mode) = self.mode {
+            what.push(format!("set the mode to {:03o}", mode));
+        }
+        if let Some(owner) = &self.owner {
+            what.push(format!("give to {}", owner.spec));
+        }
+        if self.touch {
+            what.push("touch".to_string());
+        }
+        if self.readonly {
+            what.push("make read-only".to_string());
+        }
+        if self.clear_hidden {
+            what.push("unhide".to_string());
+        }
+        match what.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+            None => "leave alone".to_string(),
+        }
+    }
+}
+
+/// Apply `change` to the file at `path`, returning whether anything had to
+/// be changed. Symbolic links are refused rather than followed.
+pub fn apply(path: &Path, change: &Change) -> Result<bool> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        anyhow::bail!("symbolic links are left alone");
+    }
+    let mut changed = apply_unix(path, &metadata, change)?;
+    if change.touch {
+        // Without opening it for writing, which directories and read-only
+        // files refuse
+        filetime::set_file_mtime(path, filetime::FileTime::now())?;
+        changed = true;
+    }
+    if change.readonly {
+        let mut permissions = fs::metadata(path)?.permissions();
+        if !permissions.readonly() {
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)?;
+            changed = true;
+        }
+    }
+    if change.clear_hidden {
+        changed |= clear_hidden(path, &metadata)?;
+    }
+    Ok(changed)
+}
+
+#[cfg(unix)]
+fn apply_unix(path: &Path, metadata: &fs::Metadata, change: &Change) -> Result<bool> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let mut changed = false;
+    if let Some(owner) = &change.owner {
+        let uid = owner.uid.filter(|&uid| uid != metadata.uid());
+        let gid = owner.gid.filter(|&gid| gid != metadata.gid());
+        if uid.is_some() || gid.is_some() {
+            std::os::unix::fs::chown(path, uid, gid)?;
+            changed = true;
+        }
+    }
+    // Changing the owner can clear setuid bits, so compare the mode afterwards
+    if let Some(mode) = change.mode
+        && fs::metadata(path)?.permissions().mode() & 0o7777 != mode
+    {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        changed = true;
+    }
+    Ok(changed)
+}
+
+#[cfg(not(unix))]
+fn apply_unix(_: &Path, _: &fs::Metadata, change: &Change) -> Result<bool> {
+    if change.mode.is_some() || change.owner.is_some() {
+        anyhow::bail!("changing permissions and owners needs Unix");
+    }
+    Ok(false)
+}
+
+/// Clear the hidden attribute, which Windows and macOS keep apart from the
+/// file name
+#[cfg(windows)]
+fn clear_hidden(path: &Path, metadata: &fs::Metadata) -> Result<bool> {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+    if metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN == 0 {
+        return Ok(false);
+    }
+    run_tool(std::process::Command::new("attrib").arg("-h").arg(path))?;
+    Ok(true)
+}
+
+#[cfg(target_os = "macos")]
+fn clear_hidden(path: &Path, metadata: &fs::Metadata) -> Result<bool> {
+    use std::os::macos::fs::MetadataExt;
+    const UF_HIDDEN: u32 = 0x8000;
+
+    if metadata.st_flags() & UF_HIDDEN == 0 {
+        return Ok(false);
+    }
+    run_tool(
+        std::process::Command::new("chflags")
+            .arg("nohidden")
+            .arg(path),
+    )?;
+    Ok(true)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn clear_hidden(_: &Path, _: &fs::Metadata) -> Result<bool> {
+    anyhow::bail!("this system has no hidden attribute; hidden files start with a dot")
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn run_tool(command: &mut std::process::Command) -> Result<()> {
+    let output = command.output()?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode_and_owner() {
+        assert_eq!(parse_mode("640").unwrap(), 0o640);
+        assert_eq!(parse_mode("0755").unwrap(), 0o755);
+        assert!(parse_mode("648").is_err());
+        assert!(parse_mode("77777").is_err());
+
+        let owner = Owner::parse("1000:50").unwrap();
+        assert_eq!((owner.uid, owner.gid), (Some(1000), Some(50)));
+        assert_eq!(Owner::parse(":7").unwrap().uid, None);
+        assert!(Owner::parse(":").is_err());
+        assert!(Owner::parse("no-such-user-here").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let passwd = dir.path().join("passwd");
+        fs::write(
+            &passwd,
+            "root:x:0:0::/root:/bin/sh\nada:x:1001:1001::/home/ada:/bin/sh\n",
+        )
+        .unwrap();
+        assert_eq!(lookup_id(&passwd, "ada").unwrap(), Some(1001));
+        assert_eq!(lookup_id(&passwd, "bob").unwrap(), None);
+        assert_eq!(read_names(&passwd)[&1001], "ada");
+    }
+
+    #[test]
+    fn test_perm_filters() {
+        assert_eq!(
+            PermFilter::parse("644").unwrap(),
+            PermFilter::Exactly(0o644)
+        );
+        let world_writable = PermFilter::parse("-002").unwrap();
+        assert!(world_writable.matches(0o777));
+        assert!(!world_writable.matches(0o755));
+        let executable = PermFilter::parse("/111").unwrap();
+        assert!(executable.matches(0o744));
+        assert!(!executable.matches(0o644));
+        assert!(PermFilter::parse("-9").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_reports_changes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.txt");
+        fs::write(&path, "x").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let change = Change {
+            mode: Some(0o600),
+            ..Default::default()
+        };
+        assert!(apply(&path, &change).unwrap());
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o7777,
+            0o600
+        );
+        assert!(!apply(&path, &change).unwrap());
+
+        let touch = Change {
+            touch: true,
+            readonly: true,
+            ..Default::default()
+        };
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        assert!(apply(&path, &touch).unwrap());
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.modified().unwrap() > old);
+        assert!(metadata.permissions().readonly());
+        assert_eq!(touch.describe(), "touch and make read-only");
+        let only_touch = Change {
+            touch: true,
+            ..Default::default()
+        };
+        let subdir = dir.path().join("sub");
+        fs::create_dir(&subdir).unwrap();
+        assert!(apply(&subdir, &only_touch).unwrap());
+        assert!(apply(&path, &only_touch).unwrap());
+
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        assert!(apply(&link, &change).is_err());
+    }
+}
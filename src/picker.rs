@@ -0,0 +1,211 @@
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+use sonic_search::{Index, SearchOptions, grep, search};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// What a key press asks the picker to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// The query changed, so results must be recomputed
+    Search,
+    /// Only the selection moved
+    Redraw,
+    Accept,
+    Cancel,
+}
+
+/// Editable query and selection, kept apart from the terminal so key
+/// handling can be tested
+#[derive(Debug, Default)]
+struct PickerState {
+    query: String,
+    selected: usize,
+}
+
+impl PickerState {
+    fn handle_key(&mut self, key: KeyEvent, result_count: usize) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => Action::Accept,
+            KeyCode::Esc => Action::Cancel,
+            KeyCode::Char('c' | 'g') if ctrl => Action::Cancel,
+            KeyCode::Up => self.move_selection(-1, result_count),
+            KeyCode::Char('p' | 'k') if ctrl => self.move_selection(-1, result_count),
+            KeyCode::Down | KeyCode::Tab => self.move_selection(1, result_count),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_selection(1, result_count),
+            KeyCode::Char('u') if ctrl => self.edit(String::clear),
+            KeyCode::Char('w') if ctrl => self.edit(|query| {
+                let kept = query.trim_end().rfind(' ').map_or(0, |i| i + 1);
+                query.truncate(kept);
+            }),
+            KeyCode::Backspace => self.edit(|query| {
+                query.pop();
+            }),
+            KeyCode::Char(c) if !ctrl => self.edit(|query| query.push(c)),
+            _ => Action::Redraw,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize, result_count: usize) -> Action {
+        if result_count > 0 {
+            self.selected = self
+                .selected
+                .saturating_add_signed(delta)
+                .min(result_count - 1);
+        }
+        Action::Redraw
+    }
+
+    fn edit(&mut self, change: impl FnOnce(&mut String)) -> Action {
+        change(&mut self.query);
+        self.selected = 0;
+        Action::Search
+    }
+}
+
+/// Restores the terminal however the picker or dashboard exits
+pub(crate) struct TerminalGuard;
+
+impl TerminalGuard {
+    pub(crate) fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Let the user pick a file from `index`, refining `initial` interactively.
+///
+/// The interface is drawn on stderr so stdout stays free for the chosen
+/// path. Returns `None` if the user cancels.
+pub fn pick(index: &Index, options: &SearchOptions, initial: &str) -> Result<Option<PathBuf>> {
+    let _guard = TerminalGuard::enter()?;
+    let mut state = PickerState {
+        query: initial.to_string(),
+        selected: 0,
+    };
+    let mut results = index.search(&state.query, options)?;
+    loop {
+        draw(index, &state, &results, options)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match state.handle_key(key, results.len()) {
+            // An invalid glob or project was already rejected before the picker opened
+            Action::Search => results = index.search(&state.query, options)?,
+            Action::Redraw => {}
+            Action::Accept => return Ok(results.get(state.selected).map(|r| r.entry.path.clone())),
+            Action::Cancel => return Ok(None),
+        }
+    }
+}
+
+fn draw(
+    index: &Index,
+    state: &PickerState,
+    results: &[search::SearchResult],
+    options: &SearchOptions,
+) -> Result<()> {
+    let mut out = io::stderr().lock();
+    let (width, height) = match terminal::size()? {
+        // Some pseudo-terminals report no size at all
+        (0, _) | (_, 0) => (80, 24),
+        size => size,
+    };
+    let width = width as usize;
+    // One row each for the prompt and the status line
+    let rows = (height as usize).saturating_sub(2);
+    // Scroll so the selection stays visible
+    let first = state.selected.saturating_sub(rows.saturating_sub(1));
+
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        Print(format!("> {}", state.query)),
+        cursor::MoveTo(0, 1),
+        SetAttribute(Attribute::Dim),
+        Print(format!("  {}/{}", results.len(), index.entries.len())),
+        SetAttribute(Attribute::Reset),
+    )?;
+    for (row, result) in results.iter().enumerate().skip(first).take(rows) {
+        let path = search::relative_path(&index.meta.root, result.entry);
+        let line: String = path.chars().take(width.saturating_sub(2)).collect();
+        queue!(out, cursor::MoveTo(0, (row - first + 2) as u16))?;
+        if row == state.selected {
+            // Highlighting would reset the reverse video part-way through
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", line)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            let spans = search::match_spans(&line, &state.query, options);
+            queue!(
+                out,
+                Print(format!("  {}", grep::highlight(&line, &spans, true)))
+            )?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn test_editing_and_navigation() {
+        let mut state = PickerState::default();
+        for c in "src main".chars() {
+            assert_eq!(state.handle_key(key(KeyCode::Char(c)), 0), Action::Search);
+        }
+        assert_eq!(state.query, "src main");
+        state.handle_key(ctrl('w'), 0);
+        assert_eq!(state.query, "src ");
+        state.handle_key(key(KeyCode::Backspace), 0);
+        assert_eq!(state.query, "src");
+
+        // The selection is clamped to the results and reset by edits
+        assert_eq!(state.handle_key(key(KeyCode::Down), 3), Action::Redraw);
+        state.handle_key(ctrl('n'), 3);
+        state.handle_key(ctrl('n'), 3);
+        assert_eq!(state.selected, 2);
+        state.handle_key(key(KeyCode::Up), 3);
+        assert_eq!(state.selected, 1);
+        state.handle_key(key(KeyCode::Char('x')), 3);
+        assert_eq!(state.selected, 0);
+        state.handle_key(key(KeyCode::Up), 3);
+        assert_eq!(state.selected, 0);
+
+        state.handle_key(ctrl('u'), 0);
+        assert!(state.query.is_empty());
+        assert_eq!(state.handle_key(key(KeyCode::Enter), 0), Action::Accept);
+        assert_eq!(state.handle_key(key(KeyCode::Esc), 0), Action::Cancel);
+        assert_eq!(state.handle_key(ctrl('c'), 0), Action::Cancel);
+    }
+}
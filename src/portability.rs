@@ -0,0 +1,184 @@
+use crate::scanner::FileEntry;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Longest path Windows handles without long-path support, in UTF-16 units
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+/// Longest file name NTFS allows, in UTF-16 units
+const MAX_NAME: usize = 255;
+
+/// Characters NTFS doesn't allow in names, besides control characters
+const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Why a path can't be copied to Windows as it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The path below the root is longer than the limit
+    PathTooLong { len: usize, max: usize },
+    /// A name is longer than NTFS allows
+    NameTooLong { len: usize },
+    /// A name contains a character NTFS doesn't allow
+    IllegalCharacter(char),
+    /// A name ends with a dot or space, which Windows strips
+    TrailingDotOrSpace,
+    /// A name is reserved for a device, like `CON` or `aux.txt`
+    ReservedName,
+}
+
+impl Problem {
+    /// Short machine-readable name of the problem
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::PathTooLong { .. } => "path-too-long",
+            Problem::NameTooLong { .. } => "name-too-long",
+            Problem::IllegalCharacter(_) => "illegal-character",
+            Problem::TrailingDotOrSpace => "trailing-dot-or-space",
+            Problem::ReservedName => "reserved-name",
+        }
+    }
+
+    /// What's wrong, for people
+    pub fn describe(&self) -> String {
+        match self {
+            Problem::PathTooLong { len, max } => {
+                format!("path is {} characters, over the limit of {}", len, max)
+            }
+            Problem::NameTooLong { len } => {
+                format!("name is {} characters, over NTFS's {}", len, MAX_NAME)
+            }
+            Problem::IllegalCharacter(c) => format!("contains {:?}, which NTFS doesn't allow", c),
+            Problem::TrailingDotOrSpace => "ends with a dot or space, which Windows strips".into(),
+            Problem::ReservedName => "is a device name Windows reserves".into(),
+        }
+    }
+}
+
+/// A path that needs fixing before it's moved to Windows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The file, or the directory whose name is the problem
+    pub path: PathBuf,
+    pub problem: Problem,
+}
+
+/// Find the paths among `entries` that won't survive a copy to Windows,
+/// sorted by path.
+///
+/// Paths are measured below `root`, since that's what an archive keeps, and
+/// flagged when longer than `max_path`. A badly named directory is reported
+/// once rather than for every file in it.
+pub fn audit(root: &Path, entries: &[&FileEntry], max_path: usize) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut checked: HashSet<&Path> = HashSet::new();
+    for entry in entries {
+        let Ok(relative) = entry.path.strip_prefix(root) else {
+            continue;
+        };
+        let len = utf16_len(&relative.to_string_lossy());
+        if len > max_path {
+            issues.push(Issue {
+                path: entry.path.clone(),
+                problem: Problem::PathTooLong { len, max: max_path },
+            });
+        }
+        for path in entry.path.ancestors().take_while(|path| *path != root) {
+            if !checked.insert(path) {
+                break;
+            }
+            let Some(name) = path.file_name() else {
+                break;
+            };
+            issues.extend(name_problems(&name.to_string_lossy()).map(|problem| Issue {
+                path: path.to_path_buf(),
+                problem,
+            }));
+        }
+    }
+    issues.sort_by(|a, b| a.path.cmp(&b.path));
+    issues
+}
+
+/// Everything wrong with a single file or directory name
+fn name_problems(name: &str) -> impl Iterator<Item = Problem> {
+    let len = utf16_len(name);
+    let illegal = name
+        .chars()
+        .find(|c| c.is_control() || ILLEGAL_CHARS.contains(c));
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    [
+        (len > MAX_NAME).then_some(Problem::NameTooLong { len }),
+        illegal.map(Problem::IllegalCharacter),
+        name.ends_with(['.', ' '])
+            .then_some(Problem::TrailingDotOrSpace),
+        RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+            .then_some(Problem::ReservedName),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Length of `text` in UTF-16 units, as Windows counts it
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: PathBuf) -> FileEntry {
+        FileEntry::new(path, false, 1, None)
+    }
+
+    #[test]
+    fn test_audit() {
+        let root = Path::new("/data");
+        let long = "d".repeat(50);
+        let paths = [
+            "fine/notes.txt".to_string(),
+            "what?/a.txt".to_string(),
+            "what?/b.txt".to_string(),
+            "draft. ".to_string(),
+            "aux.h".to_string(),
+            "auxiliary.h".to_string(),
+            format!("{0}/{0}/{0}.txt", long),
+            "x".repeat(300),
+        ];
+        let entries: Vec<FileEntry> = paths.iter().map(|p| entry(root.join(p))).collect();
+        let entries: Vec<&FileEntry> = entries.iter().collect();
+
+        let issues = audit(root, &entries, 120);
+        let found: Vec<(String, &str)> = issues
+            .iter()
+            .map(|issue| {
+                let path = issue.path.strip_prefix(root).unwrap();
+                (path.display().to_string(), issue.problem.kind())
+            })
+            .collect();
+        let expected = [
+            ("aux.h".to_string(), "reserved-name"),
+            (format!("{0}/{0}/{0}.txt", long), "path-too-long"),
+            ("draft. ".to_string(), "trailing-dot-or-space"),
+            ("what?".to_string(), "illegal-character"),
+            ("x".repeat(300), "path-too-long"),
+            ("x".repeat(300), "name-too-long"),
+        ];
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (path, kind) in expected {
+            assert!(found.contains(&(path.clone(), kind)), "{} {}", path, kind);
+        }
+        assert_eq!(
+            Problem::IllegalCharacter('?').describe(),
+            "contains '?', which NTFS doesn't allow"
+        );
+    }
+}
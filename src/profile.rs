@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Whether spans are being timed at all
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Microseconds spent in each stack of spans, not counting nested spans
+static TOTALS: Mutex<BTreeMap<String, u128>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Spans open on this thread, outermost first
+    static STACK: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+struct Frame {
+    name: &'static str,
+    start: Instant,
+    /// Time spent in spans nested inside this one
+    nested: Duration,
+}
+
+/// Start timing spans from now on, as `--profile` does
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// A timed section of work, which ends when the guard is dropped.
+///
+/// Spans nest on the thread that opens them; work a span hands to other
+/// threads counts towards it but isn't broken down further. Opening one
+/// costs next to nothing unless profiling is enabled.
+#[must_use = "the span ends as soon as the guard is dropped"]
+pub struct Span {
+    active: bool,
+}
+
+/// Open a span called `name`, such as `walk` or `print`
+pub fn span(name: &'static str) -> Span {
+    let active = ENABLED.load(Ordering::Relaxed);
+    if active {
+        STACK.with(|stack| {
+            stack.borrow_mut().push(Frame {
+                name,
+                start: Instant::now(),
+                nested: Duration::ZERO,
+            })
+        });
+    }
+    Span { active }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let Some(frame) = stack.pop() else {
+                return;
+            };
+            let elapsed = frame.start.elapsed();
+            if let Some(parent) = stack.last_mut() {
+                parent.nested += elapsed;
+            }
+            let mut path: Vec<&str> = stack.iter().map(|frame| frame.name).collect();
+            path.push(frame.name);
+            let own = elapsed.saturating_sub(frame.nested).as_micros();
+            let mut totals = TOTALS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            *totals.entry(path.join(";")).or_default() += own;
+        });
+    }
+}
+
+/// The timings recorded so far in folded-stack format, one `outer;inner
+/// microseconds` line per stack, as `flamegraph.pl` and `inferno` read it
+pub fn folded() -> String {
+    let totals = TOTALS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    totals
+        .iter()
+        .filter(|(_, micros)| **micros > 0)
+        .map(|(stack, micros)| format!("{} {}\n", stack, micros))
+        .collect()
+}
+
+/// Write the timings recorded so far to `path` in folded-stack format
+pub fn write(path: &Path) -> Result<()> {
+    std::fs::write(path, folded())
+        .with_context(|| format!("Failed to write profile to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_spans_are_folded() {
+        enable();
+        {
+            let _outer = span("profile-test");
+            std::thread::sleep(Duration::from_millis(2));
+            for _ in 0..2 {
+                let _inner = span("nested");
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        }
+        let folded = folded();
+        let micros = |stack: &str| -> u128 {
+            folded
+                .lines()
+                .find_map(|line| line.strip_prefix(stack)?.strip_prefix(' '))
+                .and_then(|micros| micros.parse().ok())
+                .unwrap_or_default()
+        };
+        // Both stays in the nested span add up under one stack
+        assert!(micros("profile-test;nested") >= 4000, "{}", folded);
+        assert!(micros("profile-test") >= 2000, "{}", folded);
+    }
+}
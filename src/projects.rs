@@ -0,0 +1,172 @@
+use crate::scanner::FileEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Manifest files that mark the root of a sub-project, in order of
+/// preference when a directory holds several
+const MANIFESTS: [(&str, ProjectKind); 4] = [
+    ("Cargo.toml", ProjectKind::Cargo),
+    ("package.json", ProjectKind::Npm),
+    ("BUILD.bazel", ProjectKind::Bazel),
+    ("BUILD", ProjectKind::Bazel),
+];
+
+/// Build system a project was detected from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+    Cargo,
+    Npm,
+    Bazel,
+}
+
+impl ProjectKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectKind::Cargo => "cargo",
+            ProjectKind::Npm => "npm",
+            ProjectKind::Bazel => "bazel",
+        }
+    }
+}
+
+/// A sub-project found inside the indexed tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Project {
+    /// Package name from the manifest, or the directory name
+    pub name: String,
+    /// Directory holding the manifest
+    pub root: PathBuf,
+    pub kind: ProjectKind,
+}
+
+/// Find the sub-projects among `entries` by looking at their manifests.
+///
+/// Cargo manifests without a `[package]` (virtual workspace roots) declare no
+/// project of their own. The result is sorted by root.
+pub fn detect(entries: &[FileEntry]) -> Vec<Project> {
+    let mut by_root: HashMap<&Path, (usize, ProjectKind, &Path)> = HashMap::new();
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        let Some(rank) = MANIFESTS.iter().position(|(name, _)| *name == entry.name) else {
+            continue;
+        };
+        let Some(root) = entry.path.parent() else {
+            continue;
+        };
+        if by_root.get(root).is_none_or(|(best, _, _)| rank < *best) {
+            by_root.insert(root, (rank, MANIFESTS[rank].1, &entry.path));
+        }
+    }
+
+    let mut projects: Vec<Project> = by_root
+        .into_iter()
+        .filter_map(|(root, (_, kind, manifest))| {
+            let name = match kind {
+                ProjectKind::Cargo => cargo_package_name(manifest)?,
+                ProjectKind::Npm => npm_package_name(manifest),
+                ProjectKind::Bazel => None,
+            };
+            let name = name.or_else(|| Some(root.file_name()?.to_string_lossy().into_owned()))?;
+            Some(Project {
+                name,
+                root: root.to_path_buf(),
+                kind,
+            })
+        })
+        .collect();
+    projects.sort_by(|a, b| a.root.cmp(&b.root));
+    projects
+}
+
+/// Tag every entry with the innermost project containing it
+pub fn assign(entries: &mut [FileEntry], projects: &[Project]) {
+    let ids: HashMap<&Path, u32> = projects
+        .iter()
+        .enumerate()
+        .map(|(id, project)| (project.root.as_path(), id as u32))
+        .collect();
+    for entry in entries {
+        entry.project = entry
+            .path
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| ids.get(dir).copied());
+    }
+}
+
+/// `Some(Some(name))` for a Cargo package, `Some(None)` for an unreadable
+/// manifest and `None` for a virtual manifest that isn't a project at all
+fn cargo_package_name(manifest: &Path) -> Option<Option<String>> {
+    let Some(table) = fs::read_to_string(manifest)
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+    else {
+        return Some(None);
+    };
+    let package = table.get("package")?;
+    Some(
+        package
+            .get("name")
+            .and_then(|n| n.as_str())
+            .map(str::to_string),
+    )
+}
+
+fn npm_package_name(manifest: &Path) -> Option<String> {
+    let contents = fs::read(manifest).ok()?;
+    let value: serde_json::Value = serde_json::from_slice(&contents).ok()?;
+    value.get("name")?.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_detect_and_assign() {
+        let root = tempfile::tempdir().unwrap();
+        let files: [(&str, &str); 9] = [
+            ("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n"),
+            ("crates/core/Cargo.toml", "[package]\nname = \"core-lib\"\n"),
+            ("crates/core/src/lib.rs", ""),
+            ("web/package.json", r#"{"name": "@acme/web"}"#),
+            ("web/Cargo.toml", "not toml ["),
+            ("web/src/index.ts", ""),
+            ("tools/gen/BUILD", ""),
+            ("tools/gen/main.py", ""),
+            ("README.md", ""),
+        ];
+        for (name, body) in files {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+        let mut entries = scanner::scan_directory(root.path()).unwrap().files;
+        let projects = detect(&entries);
+        let names: Vec<(&str, ProjectKind)> =
+            projects.iter().map(|p| (p.name.as_str(), p.kind)).collect();
+        // The virtual workspace root isn't a project; Cargo wins in web/ but
+        // its manifest is unreadable, so the directory name is used
+        assert_eq!(
+            names,
+            vec![
+                ("core-lib", ProjectKind::Cargo),
+                ("gen", ProjectKind::Bazel),
+                ("web", ProjectKind::Cargo),
+            ]
+        );
+
+        assign(&mut entries, &projects);
+        let project_of = |name: &str| {
+            let entry = entries.iter().find(|e| e.name == name).unwrap();
+            entry.project.map(|id| projects[id as usize].name.as_str())
+        };
+        assert_eq!(project_of("lib.rs"), Some("core-lib"));
+        assert_eq!(project_of("main.py"), Some("gen"));
+        assert_eq!(project_of("index.ts"), Some("web"));
+        assert_eq!(project_of("README.md"), None);
+    }
+}
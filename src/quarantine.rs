@@ -0,0 +1,226 @@
+//! Keep a damaged index usable: files that fail to open are moved aside,
+//! readers fall back on what's left, and the root is rescanned in the
+//! background to make up for what was lost.
+
+use crate::index::{self, Index, WriteLock};
+use crate::selfcheck::Finding;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory inside the index directory damaged files are moved to
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+static RESCAN: AtomicBool = AtomicBool::new(false);
+
+/// Rescan the root in the background, with `ss update`, whenever a damaged
+/// generation is set aside, as the `ss` binary does. Off by default, so
+/// programs embedding the crate don't start a process they don't know about.
+pub fn enable_rescan() {
+    RESCAN.store(true, Ordering::Relaxed);
+}
+
+/// An error reading an index file that doesn't mean the file is damaged,
+/// such as one written by another version of sonic-search; it's reported
+/// instead of being quarantined
+#[derive(Debug)]
+pub struct NotDamaged(pub String);
+
+impl std::fmt::Display for NotDamaged {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotDamaged {}
+
+/// Whether `error`, from opening an index file that exists, means the file
+/// is damaged, rather than unreadable for now or by this build
+pub fn is_damage(error: &anyhow::Error) -> bool {
+    !error.chain().any(|cause| {
+        cause.is::<NotDamaged>()
+            || cause.is::<Finding>()
+            || cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+                // Missing files are left to races with garbage collection
+                matches!(e.kind(), ErrorKind::PermissionDenied | ErrorKind::NotFound)
+            })
+    })
+}
+
+/// Files set aside in `index_dir`, oldest first
+pub fn quarantined(index_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(index_dir.join(QUARANTINE_DIR)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    paths
+}
+
+/// Move the file at `path`, part of the index in `index_dir`, into
+/// [`QUARANTINE_DIR`], its name prefixed with the time so generations
+/// whose number is reused don't collide
+pub fn quarantine_file(index_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let dir = index_dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&dir)?;
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let name = path.file_name().context("Index file without a name")?;
+    let target = dir.join(format!("{}-{}", secs, name.to_string_lossy()));
+    fs::rename(path, &target)
+        .with_context(|| format!("Failed to quarantine {}", path.display()))?;
+    Ok(target)
+}
+
+/// Set the damaged `generation` of `index_dir` aside after opening it failed
+/// with `error`, point readers at the newest generation before it and start
+/// a rescan.
+///
+/// Fails with `error`, leaving the files in place, when there's no older
+/// generation to fall back on.
+///
+/// Holds the [`WriteLock`], so a save landing meanwhile is never undone by
+/// pointing readers back at an older generation.
+pub(crate) fn recover(index_dir: &Path, generation: u64, error: anyhow::Error) -> Result<()> {
+    let _lock = WriteLock::acquire(index_dir)?;
+    // Another reader may have set it aside while this one waited
+    if !index::generation_files(index_dir, generation)
+        .iter()
+        .any(|path| path.is_file())
+    {
+        return Ok(());
+    }
+    let fallback = Index::generations(index_dir)?
+        .into_iter()
+        .rfind(|&stored| stored < generation);
+    let Some(fallback) = fallback else {
+        return Err(error.context(format!(
+            "Generation {} of the index is damaged and there's none to fall back on; run `ss scan` again",
+            generation
+        )));
+    };
+    for path in index::generation_files(index_dir, generation) {
+        if path.is_file() {
+            quarantine_file(index_dir, &path)?;
+        }
+    }
+    if Index::current_generation(index_dir)? == Some(generation) {
+        Index::set_current(index_dir, fallback)?;
+    }
+    eprintln!(
+        "⚠️  Generation {} of the index at {} is damaged ({:#}); moved it to {} and fell back on generation {}",
+        generation,
+        index_dir.display(),
+        error,
+        QUARANTINE_DIR,
+        fallback
+    );
+    if RESCAN.load(Ordering::Relaxed) {
+        match spawn_rescan(index_dir) {
+            Ok(()) => eprintln!("   Rescanning in the background to catch up"),
+            Err(e) => eprintln!("   Couldn't start a rescan ({}); run `ss update`", e),
+        }
+    }
+    Ok(())
+}
+
+/// Set aside the part of the index at `path` that failed to open with
+/// `error`, which readers can do without
+pub(crate) fn quarantine_part(index_dir: &Path, path: &Path, what: &str, error: &anyhow::Error) {
+    match quarantine_file(index_dir, path) {
+        Ok(_) => eprintln!(
+            "⚠️  The {} at {} is damaged ({:#}); moved it to {} and carried on without it",
+            what,
+            path.display(),
+            error,
+            QUARANTINE_DIR
+        ),
+        Err(e) => eprintln!(
+            "⚠️  The {} at {} is damaged ({:#}); carried on without it, but {:#}",
+            what,
+            path.display(),
+            error,
+            e
+        ),
+    }
+}
+
+/// Start `ss update` on `index_dir`, detached from this process
+fn spawn_rescan(index_dir: &Path) -> std::io::Result<()> {
+    Command::new(std::env::current_exe()?)
+        .arg("update")
+        .arg("--index-dir")
+        .arg(index_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::MappedSnapshot;
+    use crate::search::SearchOptions;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_damaged_generation_is_quarantined() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let dir = index_dir.path();
+        let mut index = IndexBuilder::new("/work").file("notes.txt", 5).build();
+        index.save(dir).unwrap();
+        index.entries.push(index.entries[0].clone());
+        index.entries[1].path = PathBuf::from("/work/later.txt");
+        index.entries[1].name = "later.txt".to_string();
+        index.save(dir).unwrap();
+
+        // Only the name filter of generation 2 is damaged: searches carry on
+        // without it
+        truncate(&Index::filter_path(dir, 2));
+        let snapshot = MappedSnapshot::open(dir).unwrap();
+        assert_eq!(snapshot.generation(), 2);
+        let results = snapshot.search("later", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        drop(snapshot);
+        assert_eq!(quarantined(dir).len(), 1);
+
+        // A damaged entry table takes its generation with it
+        truncate(&Index::entries_path(dir, 2));
+        let loaded = Index::load(dir).unwrap();
+        assert_eq!(loaded.meta.generation, 1);
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(Index::current_generation(dir).unwrap(), Some(1));
+        assert_eq!(Index::generations(dir).unwrap(), [1]);
+        assert_eq!(quarantined(dir).len(), 3);
+
+        // With nothing older left, the error comes through
+        let entries = Index::entries_path(dir, 1);
+        truncate(&entries);
+        assert!(Index::load(dir).is_err());
+        assert!(entries.is_file());
+    }
+
+    /// Cut the file at `path` short, just past its version and flags
+    fn truncate(path: &Path) {
+        let mut file = fs::read(path).unwrap();
+        file.truncate(20);
+        fs::write(path, file).unwrap();
+    }
+
+    #[test]
+    fn test_is_damage() {
+        let corrupt = anyhow::anyhow!("Index file is corrupt");
+        assert!(is_damage(&corrupt));
+        let version = anyhow::Error::new(NotDamaged("format version 3".to_string()));
+        assert!(!is_damage(&version.context("Failed to open")));
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(!is_damage(&anyhow::Error::new(denied)));
+    }
+}
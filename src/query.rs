@@ -0,0 +1,625 @@
+use crate::error::{Error, Result, bail};
+use crate::search::{self, EntryFields, EntryType, SearchOptions};
+use crate::units::{self, SizeFilter};
+use anyhow::Context;
+use fuzzy_matcher::FuzzyMatcher;
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// A parsed search query, such as `report AND 2024 NOT draft` or
+/// `ext:rs path:tests config`.
+///
+/// Words are fuzzy-matched against file names, or against paths relative
+/// to the index root when they contain a `/`, and `field:value` words
+/// restrict some other property of the entry. Everything listed must
+/// match; `OR` matches either side, `NOT` leaves out what follows it and
+/// parentheses group. `NOT` binds tightest and `OR` loosest. A word in
+/// double quotes, like `"NOT"` or `"annual report"`, is matched as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    Field(Field),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+/// A `field:value` restriction within a [`Query`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    /// `ext:rs`: the extension, compared case-insensitively
+    Extension(String),
+    /// `path:tests`: text the path relative to the index root contains
+    Path(String),
+    /// `name:test_`: text the name contains
+    Name(String),
+    /// `type:d`: the kind of entry, as for `find --type`
+    Type(EntryType),
+    /// `size:+10M`: a size comparison, as for `--size`, where `>` and `<`
+    /// mean the same as `+` and `-`
+    Size(SizeFilter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Query {
+    /// Parse `text`; an empty query matches everything
+    pub fn parse(text: &str) -> Result<Query> {
+        let parse = || -> anyhow::Result<Query> {
+            let mut parser = Parser {
+                tokens: tokenize(text)?,
+                pos: 0,
+            };
+            if parser.tokens.is_empty() {
+                return Ok(Query::And(Vec::new()));
+            }
+            let query = parser.parse_or()?;
+            match parser.tokens.get(parser.pos) {
+                None => Ok(query),
+                Some(_) => bail!("unexpected ')'"),
+            }
+        };
+        Ok(parse().with_context(|| Error::query("query", text))?)
+    }
+
+    /// The words that must match for an entry to, as opposed to those
+    /// under a `NOT`, in the order they appear
+    pub fn terms(&self) -> Vec<&str> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms);
+        terms
+    }
+
+    fn collect_terms<'a>(&'a self, terms: &mut Vec<&'a str>) {
+        match self {
+            Query::Term(term) => terms.push(term),
+            Query::And(parts) | Query::Or(parts) => {
+                parts.iter().for_each(|part| part.collect_terms(terms))
+            }
+            Query::Field(_) | Query::Not(_) => {}
+        }
+    }
+}
+
+/// Split `text` into words, quoted words, operators and parentheses. A `(`
+/// opening a word starts a group, and a `)` ending one closes it if a group
+/// is open; elsewhere they're part of the word, as in `report(1).pdf`.
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::Open);
+            depth += 1;
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => bail!("unterminated quote"),
+                }
+            }
+            tokens.push(Token::Quoted(word));
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            let mut closes = 0;
+            while depth > 0 && word.len() > 1 && word.ends_with(')') {
+                word.pop();
+                closes += 1;
+                depth -= 1;
+            }
+            if depth > 0 && word == ")" {
+                word.clear();
+                closes += 1;
+                depth -= 1;
+            }
+            match word.as_str() {
+                "" => {}
+                "AND" => tokens.push(Token::And),
+                "OR" => tokens.push(Token::Or),
+                "NOT" => tokens.push(Token::Not),
+                _ => tokens.push(Token::Word(word)),
+            }
+            tokens.extend(std::iter::repeat_n(Token::Close, closes));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        self.pos += found as usize;
+        found
+    }
+
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut alternatives = vec![self.parse_and()?];
+        while self.eat(&Token::Or) {
+            alternatives.push(self.parse_and()?);
+        }
+        Ok(combine(alternatives, Query::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut parts = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) if parts.is_empty() => bail!("AND needs a term before it"),
+                Some(Token::And) => self.pos += 1,
+                _ => {}
+            }
+            parts.push(self.parse_unary()?);
+        }
+        if parts.is_empty() {
+            bail!("expected a search term");
+        }
+        Ok(combine(parts, Query::And))
+    }
+
+    fn parse_unary(&mut self) -> Result<Query> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Not) => Ok(Query::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let group = self.parse_or()?;
+                if !self.eat(&Token::Close) {
+                    bail!("missing ')'");
+                }
+                Ok(group)
+            }
+            Some(Token::Word(word)) => parse_word(&word),
+            Some(Token::Quoted(text)) => Ok(Query::Term(text)),
+            _ => bail!("expected a search term"),
+        }
+    }
+}
+
+/// One part on its own, or `combined` parts
+fn combine(mut parts: Vec<Query>, combined: fn(Vec<Query>) -> Query) -> Query {
+    match parts.len() {
+        1 => parts.remove(0),
+        _ => combined(parts),
+    }
+}
+
+/// A `field:value` restriction, or a plain term for any other word,
+/// including ones like `notes:draft` whose prefix isn't a field
+fn parse_word(word: &str) -> Result<Query> {
+    let Some((key, value)) = word.split_once(':') else {
+        return Ok(Query::Term(word.to_string()));
+    };
+    let field = match key {
+        "ext" | "path" | "name" | "type" | "size" if value.is_empty() => {
+            bail!("{}: needs a value", key)
+        }
+        "ext" => Field::Extension(value.trim_start_matches('.').to_lowercase()),
+        "path" => Field::Path(value.to_string()),
+        "name" => Field::Name(value.to_string()),
+        "type" => Field::Type(match value {
+            "f" | "file" => EntryType::File,
+            "d" | "dir" | "directory" => EntryType::Directory,
+            "l" | "symlink" => EntryType::Symlink,
+            _ => bail!("unknown type '{}'; expected f, d or l", value),
+        }),
+        "size" => {
+            let value = value.replacen('>', "+", 1).replacen('<', "-", 1);
+            Field::Size(units::parse_size_filter(&value).map_err(anyhow::Error::msg)?)
+        }
+        _ => return Ok(Query::Term(word.to_string())),
+    };
+    Ok(Query::Field(field))
+}
+
+/// A [`Query`] prepared to match entries the way `options` says
+pub(crate) struct Matcher {
+    node: Node,
+    options: SearchOptions,
+    /// When recency is measured from
+    now: SystemTime,
+}
+
+enum Node {
+    Term {
+        /// The term as names and paths are compared with it
+        text: String,
+        fuzzy: Box<dyn FuzzyMatcher>,
+        full_path: bool,
+    },
+    /// Text that names or paths must contain, lowercased if `ignore_case`
+    Contains {
+        text: String,
+        full_path: bool,
+        ignore_case: bool,
+    },
+    Extension(String),
+    Type(EntryType),
+    Size(SizeFilter),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+}
+
+impl fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Matcher")
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Matcher {
+    /// Fails for a term the chosen engine can't match with, such as an
+    /// invalid regex
+    pub(crate) fn new(query: &Query, options: &SearchOptions) -> Result<Self> {
+        Ok(Matcher {
+            node: Self::compile(query, options)?,
+            options: options.clone(),
+            now: SystemTime::now(),
+        })
+    }
+
+    fn compile(query: &Query, options: &SearchOptions) -> Result<Node> {
+        let contains = |text: &str, full_path: bool| {
+            let ignore_case = options.case.ignores_case(text);
+            let text = options.normalize(text);
+            Node::Contains {
+                text: match ignore_case {
+                    true => text.to_lowercase(),
+                    false => text.into_owned(),
+                },
+                full_path,
+                ignore_case,
+            }
+        };
+        let all = |parts: &[Query]| -> Result<Vec<Node>> {
+            parts
+                .iter()
+                .map(|part| Self::compile(part, options))
+                .collect()
+        };
+        Ok(match query {
+            Query::Term(term) => {
+                let text = options.normalize(term).into_owned();
+                Node::Term {
+                    fuzzy: options.engine.matcher(&text, options.case)?,
+                    full_path: options.full_path || text.contains('/'),
+                    text,
+                }
+            }
+            Query::Field(Field::Extension(ext)) => Node::Extension(ext.clone()),
+            Query::Field(Field::Path(text)) => contains(text, true),
+            Query::Field(Field::Name(text)) => contains(text, false),
+            Query::Field(Field::Type(kind)) => Node::Type(*kind),
+            Query::Field(Field::Size(size)) => Node::Size(*size),
+            Query::And(parts) => Node::And(all(parts)?),
+            Query::Or(parts) => Node::Or(all(parts)?),
+            Query::Not(inner) => Node::Not(Box::new(Self::compile(inner, options)?)),
+        })
+    }
+
+    /// How well `entry` matches, higher being better, or `None` if it
+    /// doesn't. Terms that match add up their fuzzy scores and bonuses,
+    /// and the entry's recency and depth are weighed in once.
+    pub(crate) fn score(&self, entry: &impl EntryFields, root: &Path) -> Option<i64> {
+        let name = self.options.match_name(entry);
+        let relative = OnceCell::new();
+        let relative = || {
+            relative.get_or_init(|| {
+                let path = search::relative(root, entry.path());
+                self.options.normalize(&path).into_owned()
+            })
+        };
+        let text = |full_path: bool| match full_path {
+            true => relative().as_str(),
+            false => &*name,
+        };
+        let score = self.eval(&self.node, entry, &text)?;
+        Some(score + self.entry_bonus(entry, root))
+    }
+
+    /// Points for how recently `entry` changed, less its depth below `root`
+    fn entry_bonus(&self, entry: &impl EntryFields, root: &Path) -> i64 {
+        let weights = &self.options.weights;
+        let recency = entry.modified().map_or(0, |modified| {
+            let age = self.now.duration_since(modified).unwrap_or(Duration::ZERO);
+            let half_lives = age.as_secs_f64() / (weights.recency_half_life_days * 86_400.0);
+            (weights.recency as f64 * 0.5f64.powf(half_lives)).round() as i64
+        });
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let depth = relative.components().count().saturating_sub(1) as i64;
+        recency - depth * weights.depth
+    }
+
+    /// Points for `term` naming `name` without its extension, or starting
+    /// one of its words
+    fn term_bonus(&self, name: &str, term: &str) -> i64 {
+        let weights = &self.options.weights;
+        let term = term.to_lowercase();
+        let stem = match name.rfind('.') {
+            Some(dot) if dot > 0 => &name[..dot],
+            _ => name,
+        };
+        if stem.to_lowercase() == term {
+            return weights.stem;
+        }
+        let starts_word =
+            word_starts(name).any(|start| name[start..].to_lowercase().starts_with(term.as_str()));
+        match starts_word {
+            true => weights.word_boundary,
+            false => 0,
+        }
+    }
+
+    fn eval<'a>(
+        &self,
+        node: &Node,
+        entry: &impl EntryFields,
+        text: &impl Fn(bool) -> &'a str,
+    ) -> Option<i64> {
+        let matched = |yes: bool| yes.then_some(0);
+        match node {
+            Node::Term {
+                text: term,
+                fuzzy,
+                full_path,
+            } => fuzzy
+                .fuzzy_match(text(*full_path), term)
+                .map(|score| score + self.term_bonus(text(false), term)),
+            Node::Contains {
+                text: part,
+                full_path,
+                ignore_case,
+            } => {
+                let text = text(*full_path);
+                matched(match ignore_case {
+                    true => text.to_lowercase().contains(part.as_str()),
+                    false => text.contains(part.as_str()),
+                })
+            }
+            Node::Extension(ext) => matched(
+                entry
+                    .path()
+                    .extension()
+                    .is_some_and(|found| found.to_string_lossy().to_lowercase() == *ext),
+            ),
+            Node::Type(kind) => matched(kind.matches(entry)),
+            Node::Size(size) => matched(size.matches(entry.size())),
+            Node::And(parts) => parts
+                .iter()
+                .try_fold(0, |total, part| Some(total + self.eval(part, entry, text)?)),
+            Node::Or(parts) => parts
+                .iter()
+                .filter_map(|part| self.eval(part, entry, text))
+                .max(),
+            Node::Not(inner) => matched(self.eval(inner, entry, text).is_none()),
+        }
+    }
+}
+
+/// Byte offsets in `name` where a word starts: the beginning, after a
+/// separator like `-` or `_`, and at the capital of `camelCase`
+fn word_starts(name: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut previous: Option<char> = None;
+    name.char_indices().filter_map(move |(i, c)| {
+        let starts = match previous {
+            None => true,
+            Some(before) => {
+                c.is_alphanumeric()
+                    && (!before.is_alphanumeric() || (before.is_lowercase() && c.is_uppercase()))
+            }
+        };
+        previous = Some(c);
+        starts.then_some(i)
+    })
+}
+
+/// One part of the query language, as `ss syntax` prints it: a title and
+/// pairs of syntax and what it means
+#[derive(Debug, Clone, Copy)]
+pub struct SyntaxSection {
+    pub title: &'static str,
+    pub rules: &'static [(&'static str, &'static str)],
+}
+
+/// The whole query language, from plain words to the literals that sizes
+/// and times are written in. The rules of every section but the last,
+/// on times, are queries.
+pub const SYNTAX: &[SyntaxSection] = &[
+    SyntaxSection {
+        title: "Terms",
+        rules: &[
+            ("budget", "fuzzy-matched against file names"),
+            (
+                "src/main",
+                "with a `/`, matched against the path from the index root",
+            ),
+            (
+                "\"annual report\"",
+                "in double quotes, matched as it is, spaces and all",
+            ),
+            (
+                "Budget",
+                "smart case: an uppercase letter makes the match case-sensitive",
+            ),
+        ],
+    },
+    SyntaxSection {
+        title: "Operators",
+        rules: &[
+            (
+                "report 2024",
+                "every word must match; `AND` between them says the same",
+            ),
+            ("invoice OR receipt", "either side matches"),
+            ("report NOT draft", "leaves out what follows `NOT`"),
+            ("(invoice OR receipt) 2024", "parentheses group"),
+            ("\"NOT\"", "quoted, an operator is an ordinary word"),
+        ],
+    },
+    SyntaxSection {
+        title: "Fields",
+        rules: &[
+            ("ext:rs", "the extension, ignoring case and a leading dot"),
+            ("path:tests", "text the path from the index root contains"),
+            ("name:test_", "text the name contains"),
+            ("type:d", "f (file), d (directory) or l (symlink)"),
+            ("size:>10M", "a size comparison; see below"),
+        ],
+    },
+    SyntaxSection {
+        title: "Sizes (size:, --size)",
+        rules: &[
+            ("size:4K", "exactly this size"),
+            ("size:+10M", "at least; `>` means the same"),
+            ("size:-1k", "at most; `<` means the same"),
+            (
+                "size:>1.5GiB",
+                "units are case-insensitive binary multiples: K, KB and KiB are 1024",
+            ),
+        ],
+    },
+    SyntaxSection {
+        title: "Times (--modified-within, --modified-before)",
+        rules: &[
+            (
+                "7d",
+                "a duration counted back from now: ms, s, m, h, d or w, as in 2h30m",
+            ),
+            ("2024-01-01", "a date, in UTC"),
+            (
+                "2024-01-01T12:30",
+                "a date and time, in UTC, optionally with seconds",
+            ),
+        ],
+    },
+];
+
+/// Queries showing the language at work, with what each finds
+pub const EXAMPLES: &[(&str, &str)] = &[
+    ("readme", "READMEs anywhere"),
+    ("ext:rs NOT path:tests", "Rust sources outside of tests"),
+    (
+        "(ext:jpg OR ext:png) size:+1M",
+        "images of a megabyte or more",
+    ),
+    (
+        "config type:f NOT ext:bak",
+        "config files, leaving out backups",
+    ),
+    ("src/ main", "names matching main under a src directory"),
+    ("type:d name:build", "build directories"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn term(text: &str) -> Query {
+        Query::Term(text.to_string())
+    }
+
+    #[test]
+    fn test_word_starts() {
+        let starts = |name: &str| word_starts(name).collect::<Vec<_>>();
+        assert_eq!(starts("q3-report.pdf"), vec![0, 3, 10]);
+        assert_eq!(starts("annualReport"), vec![0, 6]);
+        assert_eq!(starts("README"), vec![0]);
+    }
+
+    #[test]
+    fn test_syntax_examples_parse() {
+        let rules = SYNTAX
+            .iter()
+            .take(SYNTAX.len() - 1)
+            .flat_map(|section| section.rules);
+        for (query, _) in rules.chain(EXAMPLES) {
+            assert!(Query::parse(query).is_ok(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Query::parse("budget").unwrap(), term("budget"));
+        assert_eq!(Query::parse("  ").unwrap(), Query::And(Vec::new()));
+        assert_eq!(
+            Query::parse("report AND 2024 NOT draft").unwrap(),
+            Query::And(vec![
+                term("report"),
+                term("2024"),
+                Query::Not(Box::new(term("draft")))
+            ])
+        );
+        assert_eq!(
+            Query::parse("ext:.RS path:tests config").unwrap(),
+            Query::And(vec![
+                Query::Field(Field::Extension("rs".to_string())),
+                Query::Field(Field::Path("tests".to_string())),
+                term("config"),
+            ])
+        );
+        // OR binds loosest, and parentheses group
+        assert_eq!(
+            Query::parse("a b OR c").unwrap(),
+            Query::Or(vec![Query::And(vec![term("a"), term("b")]), term("c")])
+        );
+        assert_eq!(
+            Query::parse("NOT (a OR b) size:>1k").unwrap(),
+            Query::And(vec![
+                Query::Not(Box::new(Query::Or(vec![term("a"), term("b")]))),
+                Query::Field(Field::Size(SizeFilter {
+                    ordering: Ordering::Greater,
+                    bytes: 1024
+                })),
+            ])
+        );
+        // Quotes and parentheses inside words are taken literally
+        assert_eq!(
+            Query::parse("\"NOT\" report(1) notes:draft").unwrap(),
+            Query::And(vec![term("NOT"), term("report(1)"), term("notes:draft")])
+        );
+        assert_eq!(Query::parse("(copy(1))").unwrap(), term("copy(1)"));
+        assert_eq!(
+            Query::parse("a OR NOT b c").unwrap().terms(),
+            vec!["a", "c"]
+        );
+
+        for invalid in [
+            "a OR", "(a", "a)b (", "AND a", "NOT", "\"a", "type:x", "size:big", "ext:",
+        ] {
+            assert!(Query::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! A cap on the disk space an index directory takes, set with `scan
+//! --max-index-size`. Saving a generation that takes it over the cap evicts
+//! what searches can best do without, instead of failing the scan:
+//! quarantined files, then older generations, then the full-text index and
+//! finally the content index of the current generation. Name searches keep
+//! working whatever is evicted, and grep falls back on reading the files.
+//!
+//! Evictions are logged in the index directory for `ss stats` to report.
+
+use crate::index::{self, Index};
+use crate::quarantine;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File logging evictions inside the index directory
+pub const EVICTIONS_FILE: &str = "evictions.json";
+
+/// How many evictions the log keeps, the most recent
+const KEPT_EVICTIONS: usize = 50;
+
+/// What an eviction removed, least valuable first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Part {
+    /// A damaged file set aside by [`crate::quarantine`]
+    Quarantined,
+    /// A whole generation older than the current one
+    Generation,
+    /// The full-text index of a generation
+    Fulltext,
+    /// The content index of a generation, which speeds up grep
+    Content,
+}
+
+/// Something removed to bring the index directory back under its cap
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Eviction {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub part: Part,
+    /// The generation it belonged to; none for quarantined files
+    pub generation: Option<u64>,
+    pub bytes: u64,
+}
+
+impl Eviction {
+    /// What was evicted, e.g. `the content index of generation 4`
+    pub fn describe(&self) -> String {
+        match (self.part, self.generation) {
+            (Part::Generation, Some(generation)) => format!("generation {}", generation),
+            (Part::Fulltext, Some(generation)) => {
+                format!("the full-text index of generation {}", generation)
+            }
+            (Part::Content, Some(generation)) => {
+                format!("the content index of generation {}", generation)
+            }
+            _ => "a quarantined file".to_string(),
+        }
+    }
+}
+
+/// What [`enforce`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Enforced {
+    pub evicted: Vec<Eviction>,
+    /// Bytes the index directory takes afterwards
+    pub usage: u64,
+}
+
+/// Bytes taken by the files below `dir`
+pub fn disk_usage(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => disk_usage(&entry.path()),
+            _ => entry.metadata().map_or(0, |metadata| metadata.len()),
+        })
+        .sum()
+}
+
+/// The evictions logged in `index_dir`, oldest first
+pub fn evictions(index_dir: &Path) -> Result<Vec<Eviction>> {
+    let path = index_dir.join(EVICTIONS_FILE);
+    match fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("Invalid evictions log: {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Whether the `part` of `generation` was evicted, so its absence isn't
+/// damage
+pub fn was_evicted(index_dir: &Path, generation: u64, part: Part) -> bool {
+    evictions(index_dir).is_ok_and(|evictions| {
+        evictions
+            .iter()
+            .any(|eviction| eviction.part == part && eviction.generation == Some(generation))
+    })
+}
+
+/// Evict from `index_dir`, whose current generation is `current`, until it
+/// takes at most `limit` bytes or nothing more can go.
+///
+/// Generations pinned by readers are left alone, as are the entries of the
+/// current generation: an index over its cap with nothing left to evict is
+/// reported by the caller rather than emptied.
+pub fn enforce(index_dir: &Path, current: u64, limit: u64) -> Result<Enforced> {
+    let mut usage = disk_usage(index_dir);
+    let mut evicted = Vec::new();
+    let mut candidates: Vec<(Part, Option<u64>, Vec<PathBuf>)> = quarantine::quarantined(index_dir)
+        .into_iter()
+        .map(|path| (Part::Quarantined, None, vec![path]))
+        .collect();
+    let pinned = index::pinned_generations(index_dir);
+    for generation in Index::generations(index_dir)? {
+        if generation != current && !pinned.contains(&generation) {
+            let mut files = index::generation_files(index_dir, generation).to_vec();
+            files.push(Index::fulltext_path(index_dir, generation));
+            candidates.push((Part::Generation, Some(generation), files));
+        }
+    }
+    candidates.push((
+        Part::Fulltext,
+        Some(current),
+        vec![Index::fulltext_path(index_dir, current)],
+    ));
+    candidates.push((
+        Part::Content,
+        Some(current),
+        vec![Index::content_path(index_dir, current)],
+    ));
+    for (part, generation, paths) in candidates {
+        if usage <= limit {
+            break;
+        }
+        let bytes: u64 = paths.iter().map(|path| size_of(path)).sum();
+        if bytes == 0 {
+            continue;
+        }
+        for path in &paths {
+            remove(path)?;
+        }
+        usage = usage.saturating_sub(bytes);
+        evicted.push(Eviction {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            part,
+            generation,
+            bytes,
+        });
+    }
+    if !evicted.is_empty() {
+        log(index_dir, &evicted)?;
+    }
+    Ok(Enforced { evicted, usage })
+}
+
+/// Bytes taken by the file or directory at `path`, if there's one
+fn size_of(path: &Path) -> u64 {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => disk_usage(path),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+fn remove(path: &Path) -> Result<()> {
+    let removed = match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    };
+    match removed {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to evict {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Add `evicted` to the log, keeping the most recent
+fn log(index_dir: &Path, evicted: &[Eviction]) -> Result<()> {
+    let mut all = evictions(index_dir)?;
+    all.extend_from_slice(evicted);
+    let excess = all.len().saturating_sub(KEPT_EVICTIONS);
+    all.drain(..excess);
+    let path = index_dir.join(EVICTIONS_FILE);
+    let tmp = index_dir.join(format!("{}.{}.tmp", EVICTIONS_FILE, std::process::id()));
+    fs::write(&tmp, serde_json::to_vec_pretty(&all)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to update {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_enforce_evicts_least_valuable_first() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let dir = index_dir.path();
+        let mut index = IndexBuilder::new("/work").file("notes.txt", 5).build();
+        index.save(dir).unwrap();
+        let current = index.save(dir).unwrap();
+        let content = Index::content_path(dir, current);
+        fs::write(&content, vec![0; 4096]).unwrap();
+
+        // Under the cap: nothing goes
+        let enforced = enforce(dir, current, u64::MAX).unwrap();
+        assert!(enforced.evicted.is_empty());
+        assert_eq!(enforced.usage, disk_usage(dir));
+
+        // Just over it: the older generation goes, the content index stays
+        let enforced = enforce(dir, current, disk_usage(dir) - 1).unwrap();
+        let parts: Vec<Part> = enforced.evicted.iter().map(|e| e.part).collect();
+        assert_eq!(parts, [Part::Generation]);
+        assert_eq!(Index::generations(dir).unwrap(), [current]);
+        assert!(content.is_file());
+
+        // Far over it: the content index goes, but never the entries
+        let enforced = enforce(dir, current, 0).unwrap();
+        assert_eq!(enforced.evicted[0].part, Part::Content);
+        assert!(!content.is_file());
+        assert!(was_evicted(dir, current, Part::Content));
+        assert_eq!(Index::load(dir).unwrap().entries.len(), 1);
+        assert_eq!(evictions(dir).unwrap().len(), 2);
+    }
+}
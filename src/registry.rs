@@ -0,0 +1,299 @@
+use crate::index::Index;
+use crate::mapped;
+use crate::suggest;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding where sonic-search keeps its own data
+pub const DATA_ENV: &str = "SONIC_SEARCH_DATA_DIR";
+
+/// Name of the registry file inside the data directory
+pub const REGISTRY_FILE: &str = "indexes.json";
+
+/// Every index directory `ss scan` has written, so mistyped `--index-dir`
+/// values can be matched against indexes that actually exist, plus the
+/// named profiles grouping several of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Registry {
+    pub indexes: Vec<RegisteredIndex>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+}
+
+/// Indexes of several roots scanned together under one name with
+/// `ss scan <root>... --name <name>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// One index directory per root
+    pub indexes: Vec<PathBuf>,
+}
+
+/// One known index directory and the root it covers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredIndex {
+    /// Canonical path of the index directory
+    pub path: PathBuf,
+    /// Directory the index was built from
+    pub root: PathBuf,
+}
+
+/// Where sonic-search keeps its own data, honouring `$SONIC_SEARCH_DATA_DIR`
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(DATA_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    // Keep test runs from writing into the user's data directory
+    if cfg!(test) {
+        return None;
+    }
+    dirs::data_dir().map(|dir| dir.join("sonic-search"))
+}
+
+/// Profile names become directory names, so only allow portable characters
+pub fn check_profile_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!(
+            "Invalid profile name '{}': use letters, digits, '-' and '_'",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Index directory for `root` within profile `name` under `data_dir`: a
+/// readable slug of the root, told apart from other roots slugged alike
+/// (`/tmp/t_s` and `/tmp/t/s`) by a hash of the root itself
+pub fn profile_index_dir(data_dir: &Path, name: &str, root: &Path) -> PathBuf {
+    let slug: String = root
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let hash = blake3::hash(&mapped::path_bytes(root)).to_hex();
+    data_dir
+        .join("profiles")
+        .join(name)
+        .join(format!("{}-{}", slug.trim_matches('_'), &hash[..12]))
+}
+
+impl Registry {
+    /// Default location of the registry file, honouring `$SONIC_SEARCH_DATA_DIR`
+    pub fn default_path() -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join(REGISTRY_FILE))
+    }
+
+    /// Load the registry from its default location.
+    ///
+    /// The registry only feeds suggestions, so a missing or unreadable file
+    /// yields an empty registry rather than an error.
+    pub fn load() -> Self {
+        Self::default_path()
+            .and_then(|path| Self::load_from(&path).ok())
+            .unwrap_or_default()
+    }
+
+    /// Load the registry from a specific file; a missing file is empty
+    pub fn load_from(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid index registry: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the registry to `path`, replacing it atomically
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to update {}", path.display()))
+    }
+
+    /// Record `index_dir` as an index of `root`, replacing any older entry
+    pub fn register(&mut self, index_dir: &Path, root: &Path) {
+        let path = dunce::canonicalize(index_dir).unwrap_or_else(|_| index_dir.to_path_buf());
+        self.indexes.retain(|known| known.path != path);
+        self.indexes.push(RegisteredIndex {
+            path,
+            root: root.to_path_buf(),
+        });
+    }
+
+    /// Create or replace profile `name`
+    pub fn set_profile(&mut self, name: &str, indexes: Vec<PathBuf>) -> Result<()> {
+        check_profile_name(name)?;
+        self.profiles.retain(|profile| profile.name != name);
+        self.profiles.push(Profile {
+            name: name.to_string(),
+            indexes,
+        });
+        Ok(())
+    }
+
+    /// The profile called `name`, suggesting a close match if there's none
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        if let Some(profile) = self.profiles.iter().find(|p| p.name == name) {
+            return Ok(profile);
+        }
+        let names = self.profiles.iter().map(|p| p.name.as_str());
+        match suggest::closest(name, names) {
+            Some(best) => {
+                anyhow::bail!("Unknown index profile '{}'. Did you mean '{}'?", name, best)
+            }
+            None => anyhow::bail!(
+                "Unknown index profile '{}'; create it with `ss scan <root>... --name {}`",
+                name,
+                name
+            ),
+        }
+    }
+
+    /// Drop entries whose index directory no longer holds an index
+    pub fn prune(&mut self) {
+        self.indexes.retain(|known| Index::exists(&known.path));
+    }
+
+    /// The registered index the user most likely meant by `missing`.
+    ///
+    /// Naming an indexed root instead of its index directory counts as an
+    /// exact match; otherwise the closest index path wins.
+    pub fn suggest(&self, missing: &Path) -> Option<&Path> {
+        let missing = std::path::absolute(missing).unwrap_or_else(|_| missing.to_path_buf());
+        if let Some(known) = self.indexes.iter().find(|known| known.root == missing) {
+            return Some(&known.path);
+        }
+        let input = missing.to_string_lossy();
+        let names: Vec<String> = self
+            .indexes
+            .iter()
+            .map(|known| known.path.to_string_lossy().into_owned())
+            .collect();
+        let best = suggest::closest(&input, names.iter().map(String::as_str))?;
+        self.indexes
+            .iter()
+            .find(|known| known.path.to_string_lossy() == best)
+            .map(|known| known.path.as_path())
+    }
+}
+
+/// Record a freshly written index in the default registry.
+///
+/// Failures are ignored: losing a suggestion is better than failing a scan.
+pub fn remember(index_dir: &Path, root: &Path) {
+    let Some(path) = Registry::default_path() else {
+        return;
+    };
+    let mut registry = Registry::load_from(&path).unwrap_or_default();
+    registry.prune();
+    registry.register(index_dir, root);
+    let _ = registry.save_to(&path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    fn build_index(root: &Path, index_dir: &Path) {
+        let mut index = Index::from_scan(scanner::scan_directory(root).unwrap());
+        index.save(index_dir).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_and_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data/indexes.json");
+        assert_eq!(Registry::load_from(&path).unwrap(), Registry::default());
+
+        let mut registry = Registry::default();
+        registry.register(Path::new("/srv/a/.sonic-search"), Path::new("/srv/a"));
+        registry.register(Path::new("/srv/b/.sonic-search"), Path::new("/srv/b"));
+        registry.register(Path::new("/srv/a/.sonic-search"), Path::new("/srv/a2"));
+        registry.save_to(&path).unwrap();
+
+        let loaded = Registry::load_from(&path).unwrap();
+        assert_eq!(loaded.indexes.len(), 2);
+        assert_eq!(loaded.indexes[1].root, Path::new("/srv/a2"));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(Registry::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut registry = Registry::default();
+        let dir = profile_index_dir(Path::new("/data"), "work", Path::new("/home/me/my code"));
+        let slug = dir.strip_prefix("/data/profiles/work").unwrap();
+        assert!(slug.to_string_lossy().starts_with("home_me_my_code-"));
+        // Roots slugged alike still get directories of their own
+        assert_ne!(
+            profile_index_dir(Path::new("/data"), "work", Path::new("/tmp/t_s")),
+            profile_index_dir(Path::new("/data"), "work", Path::new("/tmp/t/s"))
+        );
+        registry.set_profile("work", vec![dir.clone()]).unwrap();
+        registry
+            .set_profile("work", vec![dir.clone(), dir])
+            .unwrap();
+        assert!(registry.set_profile("no/slash", Vec::new()).is_err());
+
+        assert_eq!(registry.profiles.len(), 1);
+        assert_eq!(registry.profile("work").unwrap().indexes.len(), 2);
+        let err = registry.profile("wrok").unwrap_err().to_string();
+        assert!(err.contains("Did you mean 'work'?"), "{}", err);
+
+        // Registries written before profiles existed still load
+        let old: Registry = serde_json::from_str(r#"{"indexes": []}"#).unwrap();
+        assert!(old.profiles.is_empty());
+    }
+
+    #[test]
+    fn test_prune_drops_missing_indexes() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        build_index(root.path(), index_dir.path());
+
+        let mut registry = Registry::default();
+        registry.register(index_dir.path(), root.path());
+        registry.register(&root.path().join("gone"), root.path());
+        registry.prune();
+        assert_eq!(registry.indexes.len(), 1);
+    }
+
+    #[test]
+    fn test_suggest_close_paths_and_roots() {
+        let mut registry = Registry::default();
+        registry.register(
+            Path::new("/home/me/proj/.sonic-search"),
+            Path::new("/home/me/proj"),
+        );
+        registry.register(Path::new("/srv/data/index"), Path::new("/srv/data"));
+
+        assert_eq!(
+            registry.suggest(Path::new("/home/me/proj/.sonic-serach")),
+            Some(Path::new("/home/me/proj/.sonic-search"))
+        );
+        assert_eq!(
+            registry.suggest(Path::new("/srv/data")),
+            Some(Path::new("/srv/data/index"))
+        );
+        assert_eq!(registry.suggest(Path::new("/var/lib/other")), None);
+    }
+}
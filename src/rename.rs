@@ -0,0 +1,193 @@
+use crate::scanner::FileEntry;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Name of the journal of past renames inside the index directory
+pub const JOURNAL_FILE: &str = "renames.json";
+
+/// A file renamed within its directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Plan renaming every file in `entries` whose name matches `pattern`,
+/// replacing the first match with `template`, where `$1` or `${name}`
+/// insert capture groups.
+///
+/// Directories keep their names, so the paths of the files inside them stay
+/// valid. Fails without renaming anything if a new name is invalid or would
+/// clash with an existing file or another rename.
+pub fn plan(entries: &[&FileEntry], pattern: &Regex, template: &str) -> Result<Vec<Rename>> {
+    let mut targets = HashSet::new();
+    let mut renames = Vec::new();
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        if !pattern.is_match(&entry.name) {
+            continue;
+        }
+        let name = pattern.replace(&entry.name, template);
+        if name == entry.name {
+            continue;
+        }
+        if name.is_empty() || name.contains(['/', std::path::MAIN_SEPARATOR]) {
+            anyhow::bail!(
+                "{} would be renamed to {:?}, which isn't a file name",
+                entry.path.display(),
+                name
+            );
+        }
+        let to = entry.path.with_file_name(name.as_ref());
+        if to.exists() {
+            anyhow::bail!(
+                "Can't rename {}: {} already exists",
+                entry.path.display(),
+                to.display()
+            );
+        }
+        if !targets.insert(to.clone()) {
+            anyhow::bail!("Several files would be renamed to {}", to.display());
+        }
+        renames.push(Rename {
+            from: entry.path.clone(),
+            to,
+        });
+    }
+    Ok(renames)
+}
+
+/// One `ss rename` run, as recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Batch {
+    time: SystemTime,
+    renames: Vec<Rename>,
+}
+
+/// Carry out `renames` and record them in the journal in `index_dir`, so
+/// [`undo`] can revert them. If a rename fails, the ones already made are
+/// still recorded.
+pub fn apply(index_dir: &Path, renames: &[Rename]) -> Result<()> {
+    let mut done = Vec::new();
+    let result = renames.iter().try_for_each(|rename| {
+        fs::rename(&rename.from, &rename.to).with_context(|| {
+            format!(
+                "Failed to rename {} to {}",
+                rename.from.display(),
+                rename.to.display()
+            )
+        })?;
+        done.push(rename.clone());
+        Ok(())
+    });
+    if !done.is_empty() {
+        let mut journal = load_journal(index_dir)?;
+        journal.push(Batch {
+            time: SystemTime::now(),
+            renames: done,
+        });
+        save_journal(index_dir, &journal)?;
+    }
+    result
+}
+
+/// Revert the most recent [`apply`], returning the renames undone, or
+/// `None` when the journal is empty
+pub fn undo(index_dir: &Path) -> Result<Option<Vec<Rename>>> {
+    let mut journal = load_journal(index_dir)?;
+    let Some(batch) = journal.last() else {
+        return Ok(None);
+    };
+    let reverted: Vec<Rename> = batch
+        .renames
+        .iter()
+        .rev()
+        .map(|rename| Rename {
+            from: rename.to.clone(),
+            to: rename.from.clone(),
+        })
+        .collect();
+    // Check everything first so a half-done undo can't leave both states mixed
+    for rename in &reverted {
+        if !rename.from.exists() {
+            anyhow::bail!("Can't undo: {} no longer exists", rename.from.display());
+        }
+        if rename.to.exists() {
+            anyhow::bail!("Can't undo: {} exists again", rename.to.display());
+        }
+    }
+    for rename in &reverted {
+        fs::rename(&rename.from, &rename.to).with_context(|| {
+            format!(
+                "Failed to rename {} back to {}",
+                rename.from.display(),
+                rename.to.display()
+            )
+        })?;
+    }
+    journal.pop();
+    save_journal(index_dir, &journal)?;
+    Ok(Some(reverted))
+}
+
+fn load_journal(index_dir: &Path) -> Result<Vec<Batch>> {
+    let path = index_dir.join(JOURNAL_FILE);
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to read {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn save_journal(index_dir: &Path, journal: &[Batch]) -> Result<()> {
+    let path = index_dir.join(JOURNAL_FILE);
+    fs::write(&path, serde_json::to_vec_pretty(journal)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_rename_and_undo() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        for name in ["IMG_001.jpeg", "IMG_002.jpeg", "notes.txt"] {
+            fs::write(root.path().join(name), name).unwrap();
+        }
+        let entries: Vec<FileEntry> = ["IMG_001.jpeg", "IMG_002.jpeg", "notes.txt"]
+            .iter()
+            .map(|name| scanner::entry_for_file(&root.path().join(name)).unwrap())
+            .collect();
+        let entries: Vec<&FileEntry> = entries.iter().collect();
+
+        let pattern = Regex::new(r"^IMG_(\d+)\.jpeg$").unwrap();
+        let renames = plan(&entries, &pattern, "photo-$1.jpg").unwrap();
+        let names: Vec<_> = renames
+            .iter()
+            .map(|rename| rename.to.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["photo-001.jpg", "photo-002.jpg"]);
+
+        assert!(plan(&entries, &pattern, "photo.jpg").is_err());
+        assert!(plan(&entries, &pattern, "a/b").is_err());
+        assert!(plan(&entries, &Regex::new("IMG_001").unwrap(), "IMG_002").is_err());
+
+        apply(index_dir.path(), &renames).unwrap();
+        assert!(root.path().join("photo-001.jpg").exists());
+        assert!(!root.path().join("IMG_001.jpeg").exists());
+
+        let undone = undo(index_dir.path()).unwrap().unwrap();
+        assert_eq!(undone.len(), 2);
+        assert!(root.path().join("IMG_001.jpeg").exists());
+        assert!(!root.path().join("photo-002.jpg").exists());
+        assert!(undo(index_dir.path()).unwrap().is_none());
+    }
+}
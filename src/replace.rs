@@ -0,0 +1,456 @@
+use crate::grep::{self, GrepOptions};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use regex::bytes::{Captures, Regex};
+use similar::TextDiff;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Lines of unchanged context around every hunk, like `diff -u`
+const CONTEXT_LINES: usize = 3;
+
+/// The line ending of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// Parse `lf` or `crlf`, as given to `--eol`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => anyhow::bail!("Invalid line ending '{}': expected lf or crlf", text),
+        }
+    }
+
+    /// The ending most lines of `contents` use, LF when it has none
+    pub fn detect(contents: &[u8]) -> Self {
+        let lines = contents.iter().filter(|&&byte| byte == b'\n').count();
+        let crlf = contents.windows(2).filter(|pair| pair == b"\r\n").count();
+        match crlf * 2 > lines {
+            true => LineEnding::Crlf,
+            false => LineEnding::Lf,
+        }
+    }
+
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+
+    /// `text` with every line ending, LF or CRLF, written as this one
+    fn convert(self, text: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(text.len());
+        for line in text.split_inclusive(|&byte| byte == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    out.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                    out.extend_from_slice(self.as_bytes());
+                }
+                None => out.extend_from_slice(line),
+            }
+        }
+        out
+    }
+}
+
+/// The new contents of a file with matches replaced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub path: PathBuf,
+    pub before: String,
+    pub after: String,
+    /// Number of matches replaced
+    pub count: usize,
+    /// Hash of the contents read, to tell whether the file changed since
+    pub hash: blake3::Hash,
+}
+
+impl Replacement {
+    /// A unified diff of the change with `a/` and `b/` headers naming the
+    /// file below `root`, so it applies with `git apply` or `patch -p1`
+    pub fn unified_diff(&self, root: &Path) -> String {
+        let name = self.path.strip_prefix(root).unwrap_or(&self.path);
+        let name = name.to_string_lossy().replace('\\', "/");
+        TextDiff::from_lines(&self.before, &self.after)
+            .unified_diff()
+            .context_radius(CONTEXT_LINES)
+            .header(&format!("a/{}", name), &format!("b/{}", name))
+            .to_string()
+    }
+}
+
+/// Work out the replacements in every file among `paths` that matches
+/// `pattern`, in parallel and sorted by path.
+///
+/// `replacement` may use `$1` or `${name}` for capture groups, unless
+/// `options.fixed_strings` makes it literal like the pattern. Line breaks
+/// it inserts take each file's line ending, and a file keeps ending with a
+/// newline or not, so only the matches show in a diff; `eol` instead
+/// rewrites every line of a changed file with that ending. Binary files,
+/// files that aren't UTF-8 and files over `options.max_filesize` are left
+/// alone.
+pub fn plan(
+    paths: &[PathBuf],
+    pattern: &Regex,
+    replacement: &str,
+    eol: Option<LineEnding>,
+    options: &GrepOptions,
+) -> Vec<Replacement> {
+    let mut replacements: Vec<Replacement> = paths
+        .par_iter()
+        .filter_map(|path| {
+            replace_file(path, pattern, replacement, eol, options)
+                .ok()
+                .flatten()
+        })
+        .collect();
+    replacements.sort_by(|a, b| a.path.cmp(&b.path));
+    replacements
+}
+
+fn replace_file(
+    path: &Path,
+    pattern: &Regex,
+    replacement: &str,
+    eol: Option<LineEnding>,
+    options: &GrepOptions,
+) -> Result<Option<Replacement>> {
+    let len = fs::metadata(path)?.len();
+    if options.max_filesize.is_some_and(|max| len > max) {
+        return Ok(None);
+    }
+    let contents = fs::read(path)?;
+    if grep::is_binary(&contents) {
+        return Ok(None);
+    }
+    let count = pattern.find_iter(&contents).count();
+    if count == 0 {
+        return Ok(None);
+    }
+    let hash = blake3::hash(&contents);
+    let ending = LineEnding::detect(&contents);
+    let mut after = pattern
+        .replace_all(&contents, |caps: &Captures| {
+            let mut text = Vec::new();
+            match options.fixed_strings {
+                true => text.extend_from_slice(replacement.as_bytes()),
+                false => caps.expand(replacement.as_bytes(), &mut text),
+            }
+            ending.convert(&text)
+        })
+        .into_owned();
+    // Keep the final newline, or its absence, even when a match took or added one
+    let newline = ending.as_bytes();
+    match (contents.ends_with(b"\n"), after.ends_with(b"\n")) {
+        (true, false) if !after.is_empty() => after.extend_from_slice(newline),
+        (false, true) => {
+            let len = match after.ends_with(b"\r\n") {
+                true => after.len() - 2,
+                false => after.len() - 1,
+            };
+            after.truncate(len);
+        }
+        _ => {}
+    }
+    if let Some(eol) = eol {
+        after = eol.convert(&after);
+    }
+    let (Ok(before), Ok(after)) = (String::from_utf8(contents), String::from_utf8(after)) else {
+        return Ok(None);
+    };
+    if before == after {
+        return Ok(None);
+    }
+    Ok(Some(Replacement {
+        path: path.to_path_buf(),
+        before,
+        after,
+        count,
+        hash,
+    }))
+}
+
+/// Write the new contents of every file, each replaced atomically and
+/// keeping its permissions and owner, and its modification time too with
+/// `preserve_mtime`. A file whose owner a new copy can't be given, as when
+/// it belongs to someone else, is overwritten in place instead. With a
+/// `backup` suffix, the contents read are first saved beside each file
+/// under its name with the suffix added, e.g. `main.rs.orig`.
+///
+/// Files whose contents changed since [`plan`] read them are left alone,
+/// so edits made meanwhile aren't lost; their paths are returned.
+pub fn apply(
+    replacements: &[Replacement],
+    preserve_mtime: bool,
+    backup: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for replacement in replacements {
+        let path = &replacement.path;
+        let unchanged = || -> Result<bool> {
+            let contents =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok(blake3::hash(&contents) == replacement.hash)
+        };
+        let metadata = fs::metadata(path)?;
+        // A symlink is left as it is, and the file it points to rewritten
+        let target = dunce::canonicalize(path)
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        let dir = target.parent().unwrap_or(Path::new("."));
+        let mut temp = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("Failed to create a file in {}", dir.display()))?;
+        temp.write_all(replacement.after.as_bytes())?;
+        if !keep_owner(temp.as_file(), &metadata) {
+            drop(temp);
+            if !unchanged()? {
+                changed.push(path.clone());
+                continue;
+            }
+            if let Some(suffix) = backup {
+                back_up(replacement, &metadata, suffix)?;
+            }
+            let mut file = fs::File::options()
+                .write(true)
+                .truncate(true)
+                .open(&target)?;
+            file.write_all(replacement.after.as_bytes())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            if preserve_mtime {
+                file.set_modified(metadata.modified()?)?;
+            }
+            continue;
+        }
+        // After the owner, since changing it can clear setuid bits
+        fs::set_permissions(temp.path(), metadata.permissions())?;
+        if preserve_mtime {
+            temp.as_file().set_modified(metadata.modified()?)?;
+        }
+        // Checked last, to leave as little time as possible for another write
+        if !unchanged()? {
+            changed.push(path.clone());
+            continue;
+        }
+        if let Some(suffix) = backup {
+            back_up(replacement, &metadata, suffix)?;
+        }
+        temp.persist(&target)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(changed)
+}
+
+/// Save the contents `replacement` was planned from beside its file, with
+/// the file's permissions
+fn back_up(replacement: &Replacement, metadata: &fs::Metadata, suffix: &str) -> Result<()> {
+    let mut name = replacement.path.clone().into_os_string();
+    name.push(suffix);
+    let path = PathBuf::from(name);
+    fs::write(&path, &replacement.before)
+        .with_context(|| format!("Failed to write the backup {}", path.display()))?;
+    fs::set_permissions(&path, metadata.permissions())?;
+    Ok(())
+}
+
+/// Give `file` the owner and group in `original`, returning whether it has them
+#[cfg(unix)]
+fn keep_owner(file: &fs::File, original: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(current) = file.metadata() else {
+        return false;
+    };
+    (current.uid(), current.gid()) == (original.uid(), original.gid())
+        || std::os::unix::fs::fchown(file, Some(original.uid()), Some(original.gid())).is_ok()
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_: &fs::File, _: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_and_apply() {
+        let root = tempfile::tempdir().unwrap();
+        let lines: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let main = root.path().join("main.rs");
+        fs::write(&main, format!("{}let old_name = 1;\n", lines)).unwrap();
+        fs::write(root.path().join("other.rs"), "nothing here\n").unwrap();
+        fs::write(root.path().join("blob.bin"), b"old_name\0").unwrap();
+        let paths: Vec<PathBuf> = fs::read_dir(root.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+
+        let options = GrepOptions::default();
+        let pattern = grep::build_pattern(r"old_(\w+)", &options).unwrap();
+        let replacements = plan(&paths, &pattern, "new_$1", None, &options);
+        assert_eq!(replacements.len(), 1);
+        assert_eq!(replacements[0].count, 1);
+        assert_eq!(
+            replacements[0].unified_diff(root.path()),
+            "--- a/main.rs\n+++ b/main.rs\n@@ -8,4 +8,4 @@\n line 8\n line 9\n line 10\n\
+             -let old_name = 1;\n+let new_name = 1;\n"
+        );
+
+        // Fixed strings replace literally, `$` included
+        let fixed = GrepOptions {
+            fixed_strings: true,
+            ..Default::default()
+        };
+        let pattern = grep::build_pattern("old_name", &fixed).unwrap();
+        let replacements = plan(&paths, &pattern, "$cost", None, &fixed);
+        assert!(replacements[0].after.ends_with("let $cost = 1;\n"));
+
+        assert!(
+            apply(&replacements, false, Some(".orig"))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(fs::read_to_string(&main).unwrap(), replacements[0].after);
+        let backup = fs::read_to_string(root.path().join("main.rs.orig")).unwrap();
+        assert_eq!(backup, replacements[0].before);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let root = tempfile::tempdir().unwrap();
+        let dos = root.path().join("dos.txt");
+        let unterminated = root.path().join("unterminated.txt");
+        fs::write(&dos, "one\r\ntwo\r\nthree\r\n").unwrap();
+        fs::write(&unterminated, "one\ntwo").unwrap();
+        let paths = vec![dos, unterminated];
+        let options = GrepOptions::default();
+
+        // Inserted line breaks follow the file, and its last line stays as it was
+        let pattern = grep::build_pattern("two", &options).unwrap();
+        let replacements = plan(&paths, &pattern, "2a\n2b", None, &options);
+        assert_eq!(replacements[0].after, "one\r\n2a\r\n2b\r\nthree\r\n");
+        assert_eq!(replacements[1].after, "one\n2a\n2b");
+        let pattern = grep::build_pattern("three\r\n", &options).unwrap();
+        let replacements = plan(&paths[..1], &pattern, "end", None, &options);
+        assert_eq!(replacements[0].after, "one\r\ntwo\r\nend\r\n");
+        let pattern = grep::build_pattern("two", &options).unwrap();
+        let replacements = plan(&paths[1..], &pattern, "2\n", None, &options);
+        assert_eq!(replacements[0].after, "one\n2");
+
+        // --eol rewrites every line
+        let replacements = plan(&paths, &pattern, "2", Some(LineEnding::Lf), &options);
+        assert_eq!(replacements[0].after, "one\n2\nthree\n");
+        let replacements = plan(&paths, &pattern, "2", Some(LineEnding::Crlf), &options);
+        assert_eq!(replacements[1].after, "one\r\n2");
+
+        assert_eq!(LineEnding::parse("CRLF").unwrap(), LineEnding::Crlf);
+        assert!(LineEnding::parse("cr").is_err());
+    }
+
+    #[test]
+    fn test_anchors_match_every_line() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("list.txt");
+        fs::write(&path, "foo 1\nfoo 2\r\nbar foo\n").unwrap();
+        let options = GrepOptions::default();
+        let paths = vec![path];
+        let pattern = grep::build_pattern("^foo", &options).unwrap();
+        let replacements = plan(&paths, &pattern, "baz", None, &options);
+        assert_eq!(replacements[0].after, "baz 1\nbaz 2\r\nbar foo\n");
+        assert_eq!(replacements[0].count, 2);
+        let pattern = grep::build_pattern(r"\d$", &options).unwrap();
+        let replacements = plan(&paths, &pattern, "n", None, &options);
+        assert_eq!(replacements[0].after, "foo n\nfoo n\r\nbar foo\n");
+    }
+
+    #[test]
+    fn test_apply_keeps_metadata() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("build.sh");
+        fs::write(&path, "echo old\n").unwrap();
+        let year_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(365 * 86400);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(year_ago)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        let options = GrepOptions::default();
+        let paths = vec![path.clone()];
+        let pattern = grep::build_pattern("old", &options).unwrap();
+        apply(&plan(&paths, &pattern, "new", None, &options), true, None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo new\n");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), year_ago);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o7777, 0o750);
+        }
+
+        let pattern = grep::build_pattern("new", &options).unwrap();
+        apply(
+            &plan(&paths, &pattern, "newer", None, &options),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), year_ago);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_keeps_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join("target.txt");
+        let link = root.path().join("link.txt");
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let options = GrepOptions::default();
+        let pattern = grep::build_pattern("old", &options).unwrap();
+        let replacements = plan(std::slice::from_ref(&link), &pattern, "new", None, &options);
+        assert!(apply(&replacements, false, None).unwrap().is_empty());
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn test_apply_skips_changed_files() {
+        let root = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = ["a.txt", "b.txt"]
+            .iter()
+            .map(|name| root.path().join(name))
+            .collect();
+        for path in &paths {
+            fs::write(path, "old\n").unwrap();
+        }
+        let options = GrepOptions::default();
+        let pattern = grep::build_pattern("old", &options).unwrap();
+        let replacements = plan(&paths, &pattern, "new", None, &options);
+        fs::write(&paths[1], "old, edited meanwhile\n").unwrap();
+
+        assert_eq!(
+            apply(&replacements, false, None).unwrap(),
+            [paths[1].clone()]
+        );
+        assert_eq!(fs::read_to_string(&paths[0]).unwrap(), "new\n");
+        assert_eq!(
+            fs::read_to_string(&paths[1]).unwrap(),
+            "old, edited meanwhile\n"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Re-ranking search results with an external program, for `find
+//! --rerank-cmd`: the candidates go to its standard input as JSON lines, and
+//! it prints back their ids in the order it prefers.
+
+use crate::exec;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::process::Stdio;
+
+/// A candidate as the re-ranker reads it: its fields, plus the id to print
+/// back
+#[derive(Serialize)]
+struct Candidate<'a, T> {
+    id: usize,
+    #[serde(flatten)]
+    fields: &'a T,
+}
+
+/// Send `candidates` to the shell command `command` and return their
+/// positions in the order it printed their ids back. Candidates it leaves
+/// out are dropped.
+///
+/// Each candidate is one JSON object per line, with its position as `id`;
+/// the query is in `SS_QUERY`. The ids may be printed one per line, on one
+/// line, or as a JSON array.
+pub fn rerank<T: Serialize>(command: &str, query: &str, candidates: &[T]) -> Result<Vec<usize>> {
+    let mut input = Vec::new();
+    for (id, fields) in candidates.iter().enumerate() {
+        serde_json::to_writer(&mut input, &Candidate { id, fields })?;
+        input.push(b'\n');
+    }
+    let mut child = exec::shell(command)
+        .env("SS_QUERY", query)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", command))?;
+    let mut stdin = child.stdin.take().context("No stdin for the re-ranker")?;
+    let mut stdout = child.stdout.take().context("No stdout for the re-ranker")?;
+    let mut printed = String::new();
+    // Written from another thread so a re-ranker answering as it reads
+    // can't fill its output pipe while we're still writing
+    let written = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(&input));
+        let read = stdout.read_to_string(&mut printed);
+        let written = writer.join().expect("re-ranker input thread panicked");
+        read.with_context(|| format!("Failed to read the output of `{}`", command))?;
+        anyhow::Ok(written)
+    })?;
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("The re-ranker `{}` failed with {}", command, status);
+    }
+    // A re-ranker that's read enough may close its input early
+    if let Err(e) = written
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        return Err(e).with_context(|| format!("Failed to write to `{}`", command));
+    }
+    parse_ids(&printed, candidates.len())
+        .with_context(|| format!("Invalid output from the re-ranker `{}`", command))
+}
+
+/// The ids in a re-ranker's output, each once, checked against the number
+/// of candidates
+fn parse_ids(printed: &str, count: usize) -> Result<Vec<usize>> {
+    let printed = printed.trim();
+    let ids: Vec<usize> = match printed.starts_with('[') {
+        true => serde_json::from_str(printed)?,
+        false => printed
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("'{}' isn't a result id", token))
+            })
+            .collect::<Result<_>>()?,
+    };
+    if let Some(id) = ids.iter().find(|&&id| id >= count) {
+        anyhow::bail!("There's no result {}; ids go from 0 to {}", id, count - 1);
+    }
+    let mut seen = HashSet::new();
+    Ok(ids.into_iter().filter(|&id| seen.insert(id)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!(parse_ids("2\n0\n1\n", 3).unwrap(), [2, 0, 1]);
+        assert_eq!(parse_ids("2 0, 2", 3).unwrap(), [2, 0]);
+        assert_eq!(parse_ids(" [1, 0] ", 3).unwrap(), [1, 0]);
+        assert!(parse_ids("", 3).unwrap().is_empty());
+        assert!(parse_ids("3", 3).is_err());
+        assert!(parse_ids("first", 3).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rerank_with_a_command() {
+        #[derive(Serialize)]
+        struct Hit {
+            name: &'static str,
+        }
+        let hits = [Hit { name: "a" }, Hit { name: "b" }, Hit { name: "c" }];
+        // Reverse the candidates, dropping the one named "b"
+        let command = r#"grep -v '"name":"b"' | sed 's/.*"id":\([0-9]*\).*/\1/' | sort -rn"#;
+        assert_eq!(rerank(command, "x", &hits).unwrap(), [2, 0]);
+        assert_eq!(rerank("echo $SS_QUERY", "1", &hits).unwrap(), [1]);
+        assert!(rerank("exit 3", "x", &hits).is_err());
+    }
+}
@@ -1,10 +1,23 @@
-use anyhow::Result;
-use ignore::WalkBuilder;
-use rayon::prelude::*;
+use crate::archive;
+use crate::buildsys::BuildSystems;
+use crate::error::{Error, Result, bail};
+use crate::git;
+use crate::mounts;
+use crate::normalize;
+use crate::perms;
+use crate::storage::Backend;
+use anyhow::Context;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{
+    DirEntry, ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkParallel, WalkState,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::time::{Instant, SystemTime};
 
 /// Result of a directory scan operation
 #[derive(Debug, Clone)]
@@ -14,88 +27,655 @@ pub struct ScanResult {
     pub dir_count: usize,
     pub total_size: u64,
     pub elapsed_ms: u128,
+    /// Every file and directory found below the root
     pub files: Vec<FileEntry>,
+    /// Options the scan ran with, kept so updates walk the tree the same way
+    pub options: ScanOptions,
+    /// The scan was cancelled, so `files` only holds what it found until then
+    pub partial: bool,
 }
 
-/// A single file entry discovered during scanning
-#[derive(Debug, Clone)]
+/// Options controlling which entries a scan visits
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Gitignore-style patterns, relative to the root, that are never indexed
+    pub excludes: Vec<String>,
+    /// Store a content hash of every file in the index
+    pub hash: bool,
+    /// Store the index zstd-compressed, which makes it smaller on disk but
+    /// slower to open
+    pub compress: bool,
+    /// Number of threads walking the tree and indexing contents; the number
+    /// of CPUs when unset
+    pub threads: Option<usize>,
+    /// Descend at most this many directories below the root
+    pub max_depth: Option<usize>,
+    /// Leave files larger than this many bytes out of the index
+    pub max_filesize: Option<u64>,
+    /// Index hidden files and directories too, except [`ALWAYS_SKIPPED`]
+    pub hidden: bool,
+    /// Don't honour `.ignore` and `.gitignore` files or git's excludes
+    pub no_ignore: bool,
+    /// Don't honour `.gitignore` files or git's excludes, but still `.ignore`
+    pub no_git_ignore: bool,
+    /// Extra gitignore-style files whose patterns are never indexed, as
+    /// absolute paths
+    pub ignore_files: Vec<PathBuf>,
+    /// The global ignore file in the config directory, whose patterns are
+    /// never indexed under any root; ignored while it doesn't exist
+    pub global_ignore: Option<PathBuf>,
+    /// Follow symbolic links to files and directories. Every directory is
+    /// indexed once, so links back up the tree don't loop
+    pub follow_symlinks: bool,
+    /// Stay on the filesystem the root is on, leaving out whatever is
+    /// mounted below it
+    pub one_file_system: bool,
+    /// Leave out network, FUSE and kernel filesystems mounted below the
+    /// root (see [`crate::mounts`])
+    pub skip_network: bool,
+    /// Build the content index within about this many bytes, sorting
+    /// postings in temporary files beyond it; unbounded when unset
+    pub content_memory: Option<u64>,
+    /// Index the files inside zip and tar archives too, with paths like
+    /// `data.zip!/inner/path` (see [`crate::archive`])
+    pub archives: bool,
+    /// Keep this many of the most recent generations of the index on disk,
+    /// for `ss diff` to compare; a few when unset
+    pub keep_generations: Option<usize>,
+    /// Match names written in Cyrillic and Greek by their Latin
+    /// transliteration too (see [`normalize::transliterate`])
+    pub transliterate: bool,
+    /// Tell the MIME type of files whose extension doesn't give it away by
+    /// their first bytes (see [`crate::filetype::sniff`])
+    pub sniff_mime: bool,
+    /// Also keep a full-text index of the contents, for ranked word and
+    /// phrase queries (needs the `fulltext` feature)
+    pub fulltext: bool,
+    /// Leave comments out of the full-text index of source files (see
+    /// [`crate::tokenize`])
+    pub strip_comments: bool,
+    /// Where the entries are stored (see [`crate::storage`])
+    pub backend: Backend,
+    /// Build systems whose output directories, such as `target/` beside a
+    /// `Cargo.toml`, are never indexed (see [`crate::buildsys`])
+    pub skip_build_outputs: BuildSystems,
+    /// Keep the index directory within this many bytes, evicting what
+    /// searches can best do without (see [`crate::quota`])
+    pub max_index_size: Option<u64>,
+}
+
+/// Per-directory ignore file read on top of `.gitignore` and `.ignore`,
+/// for trees that aren't git repositories; its rules win over theirs
+pub const IGNORE_FILE: &str = ".sonic-ignore";
+
+/// Directories left out even of scans that include hidden files: git's
+/// object store and sonic-search's own index
+pub const ALWAYS_SKIPPED: [&str; 2] = [".git", crate::workspace::DEFAULT_INDEX_DIR];
+
+impl ScanOptions {
+    /// Whether a file of `size` bytes is too large to be indexed
+    pub fn too_large(&self, size: u64) -> bool {
+        self.max_filesize.is_some_and(|max| size > max)
+    }
+}
+
+/// A single file or directory discovered during scanning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub name: String,
+    /// The name with accents folded away (see [`normalize::folded_name`]),
+    /// and transliterated in an index that does so, matched against queries
+    /// instead of it; `None` when that's the name itself
+    pub folded_name: Option<String>,
+    /// Size in bytes; always 0 for directories
     pub size: u64,
     pub is_dir: bool,
+    /// The path itself is a symbolic link, found with `--follow-symlinks`
+    pub is_symlink: bool,
+    /// Last modification time, if the platform reports one
+    pub modified: Option<SystemTime>,
+    /// Position of the containing sub-project in [`crate::index::Index::projects`]
+    pub project: Option<u32>,
+    /// Position of the owning team list in [`crate::index::Index::owner_sets`]
+    pub owners: Option<u32>,
+    /// Inside a git submodule or a vendor directory such as `third_party/`
+    pub vendored: bool,
+    /// BLAKE3 hash of the contents, for indexes scanned with `--hash`
+    pub hash: Option<[u8; 32]>,
+    /// Position of the git work tree holding it in [`crate::index::Index::repos`]
+    pub repo: Option<u32>,
+    /// Whether git tracks it and it has uncommitted changes
+    pub git_status: git::Status,
+    /// Position of the file's MIME type in [`crate::filetype::MIME_TYPES`],
+    /// by extension or, in an index that sniffs, by its first bytes
+    pub mime: Option<u32>,
+    /// Owner, permission bits and attributes, where the platform has them
+    pub attributes: perms::Attributes,
+    /// Device of the filesystem it's on, where the platform reports it
+    /// (see [`mounts::device`])
+    pub device: Option<u64>,
+}
+
+impl FileEntry {
+    /// An entry for `path`, named after its last component, with nothing
+    /// known about it beyond its size, kind and modification time
+    pub fn new(
+        path: impl Into<PathBuf>,
+        is_dir: bool,
+        size: u64,
+        modified: Option<SystemTime>,
+    ) -> Self {
+        let path = path.into();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        FileEntry {
+            folded_name: normalize::folded_name(&name),
+            path,
+            name,
+            size,
+            is_dir,
+            is_symlink: false,
+            modified,
+            project: None,
+            owners: None,
+            vendored: false,
+            hash: None,
+            repo: None,
+            git_status: Default::default(),
+            mime: None,
+            attributes: Default::default(),
+            device: None,
+        }
+    }
 }
 
 /// Scan a directory and collect all file entries
-pub fn scan_directory(path: &str) -> Result<ScanResult> {
-    let root = Path::new(path).to_path_buf();
-    if !root.exists() {
-        anyhow::bail!("Path does not exist: {}", path);
+pub fn scan_directory<P: AsRef<Path>>(path: P) -> Result<ScanResult> {
+    scan_directory_with(path, &ScanOptions::default(), None, None)
+}
+
+/// Scan a directory honouring `options`.
+///
+/// When `progress` is given it is incremented for every file found, so a
+/// caller on another thread can display how far the scan has got. Setting
+/// `cancel` stops the walk early, returning a [`ScanResult::partial`] result.
+pub fn scan_directory_with<P: AsRef<Path>>(
+    path: P,
+    options: &ScanOptions,
+    progress: Option<&AtomicUsize>,
+    cancel: Option<&AtomicBool>,
+) -> Result<ScanResult> {
+    let _span = crate::profile::span("walk");
+    let root = canonical_root(path.as_ref())?;
+    let start = Instant::now();
+    // Every walker thread collects into a batch of its own and hands it over
+    // once it's done, so threads never wait on each other to record entries
+    let (sender, batches) = mpsc::channel();
+    let visited = Mutex::new(HashSet::new());
+    walker(&root, options)?.visit(&mut Collectors {
+        sender,
+        stream: None,
+        root: &root,
+        visited: options.follow_symlinks.then_some(&visited),
+        options,
+        progress,
+        cancel,
+    });
+
+    let mut result = ScanResult {
+        root,
+        file_count: 0,
+        dir_count: 0,
+        total_size: 0,
+        elapsed_ms: 0,
+        files: Vec::new(),
+        options: options.clone(),
+        partial: cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)),
+    };
+    for batch in batches {
+        result.dir_count += batch.dir_count;
+        result.files.extend(batch.files);
+    }
+    result.file_count = result.files.iter().filter(|file| !file.is_dir).count();
+    result.total_size = result.files.iter().map(|file| file.size).sum();
+    result.elapsed_ms = start.elapsed().as_millis();
+    Ok(result)
+}
+
+/// Walk a directory honouring `options` like [`scan_directory_with`], but
+/// hand every entry to `on_entry` as soon as it's found, in no particular
+/// order, instead of collecting them. The walk stops once `on_entry`
+/// breaks.
+pub fn scan_streaming<P: AsRef<Path>>(
+    path: P,
+    options: &ScanOptions,
+    mut on_entry: impl FnMut(FileEntry) -> ControlFlow<()>,
+) -> Result<()> {
+    let _span = crate::profile::span("walk");
+    let root = canonical_root(path.as_ref())?;
+    let walker = walker(&root, options)?;
+    let cancel = AtomicBool::new(false);
+    let visited = Mutex::new(HashSet::new());
+    let (stream, entries) = mpsc::channel();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            // Nothing is batched, so nobody listens for the batches
+            let (sender, _) = mpsc::channel();
+            walker.visit(&mut Collectors {
+                sender,
+                stream: Some(stream),
+                root: &root,
+                visited: options.follow_symlinks.then_some(&visited),
+                options,
+                progress: None,
+                cancel: Some(&cancel),
+            });
+        });
+        for entry in entries {
+            if on_entry(entry).is_break() {
+                cancel.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// `path` made absolute and canonical, checking it's a directory, so stored
+/// entries stay valid regardless of the caller's cwd
+pub fn canonical_root(path: &Path) -> Result<PathBuf> {
+    if !path.exists() {
+        bail!("Path does not exist: {}", path.display());
     }
-    if !root.is_dir() {
-        anyhow::bail!("Path is not a directory: {}", path);
+    if !path.is_dir() {
+        bail!("Path is not a directory: {}", path.display());
     }
+    Ok(dunce::canonicalize(path)?)
+}
 
-    let start = Instant::now();
-    let file_count = AtomicUsize::new(0);
-    let dir_count = AtomicUsize::new(0);
-    let total_size = std::sync::atomic::AtomicU64::new(0);
-    let files: Mutex<Vec<FileEntry>> = Mutex::new(Vec::new());
-
-    WalkBuilder::new(path)
-        .hidden(true)
-        .git_ignore(true)
-        .build_parallel()
-        .run(|| {
-            let file_count = &file_count;
-            let dir_count = &dir_count;
-            let total_size = &total_size;
-            let files = &files;
-            Box::new(move |entry| {
-                if let Ok(entry) = entry {
-                    let is_file = entry.file_type().map(|ft| ft.is_file()).unwrap_or(false);
-                    let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
-
-                    if is_file {
-                        file_count.fetch_add(1, Ordering::Relaxed);
-                        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
-                        total_size.fetch_add(size, Ordering::Relaxed);
-
-                        let name = entry
-                            .file_name()
-                            .to_string_lossy()
-                            .to_string();
-
-                        let file_entry = FileEntry {
-                            path: entry.path().to_path_buf(),
-                            name,
-                            size,
-                            is_dir: false,
-                        };
-
-                        if let Ok(mut guard) = files.lock() {
-                            guard.push(file_entry);
-                        }
-                    } else if is_dir {
-                        dir_count.fetch_add(1, Ordering::Relaxed);
-                    }
-                }
-                ignore::WalkState::Continue
-            })
+/// The parallel walk of `root` that `options` asks for
+fn walker(root: &Path, options: &ScanOptions) -> Result<WalkParallel> {
+    let mut builder = WalkBuilder::new(root);
+    let git_ignore = !options.no_ignore && !options.no_git_ignore;
+    builder
+        .hidden(!options.hidden)
+        .ignore(!options.no_ignore)
+        .git_ignore(git_ignore)
+        .git_global(git_ignore)
+        .git_exclude(git_ignore)
+        .follow_links(options.follow_symlinks)
+        .same_file_system(options.one_file_system)
+        .threads(options.threads.unwrap_or(0))
+        .max_depth(options.max_depth);
+    if !options.no_ignore {
+        builder.add_custom_ignore_filename(IGNORE_FILE);
+    }
+    let rules = IgnoreRules::compile(root, options)?;
+    let hidden = options.hidden;
+    let build_outputs = options.skip_build_outputs;
+    let mounts = skipped_mounts(root, options);
+    if !rules.is_empty() || hidden || !build_outputs.is_empty() || !mounts.is_empty() {
+        builder.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            if is_dir && mounts.iter().any(|point| entry.path() == point) {
+                return false;
+            }
+            if hidden && is_dir && ALWAYS_SKIPPED.iter().any(|name| entry.file_name() == *name) {
+                return false;
+            }
+            // Bazel's outputs are links, skipped whether they're followed or not
+            if (is_dir || entry.path_is_symlink())
+                && entry.depth() > 0
+                && build_outputs.output_of(entry.path()).is_some()
+            {
+                return false;
+            }
+            !rules.matched(entry.path(), is_dir)
         });
+    }
+    Ok(builder.build_parallel())
+}
 
-    let elapsed = start.elapsed().as_millis();
+/// What one walker thread found
+#[derive(Default)]
+struct Batch {
+    files: Vec<FileEntry>,
+    dir_count: usize,
+}
 
-    Ok(ScanResult {
-        root,
-        file_count: file_count.load(Ordering::Relaxed),
-        dir_count: dir_count.load(Ordering::Relaxed),
-        total_size: total_size.load(Ordering::Relaxed),
-        elapsed_ms: elapsed,
-        files: files.into_inner().unwrap_or_default(),
+/// Hands every walker thread a [`Collector`]
+struct Collectors<'s> {
+    sender: mpsc::Sender<Batch>,
+    /// Where to send each entry as it's found, instead of batching them
+    stream: Option<mpsc::Sender<FileEntry>>,
+    root: &'s Path,
+    visited: Option<&'s Mutex<HashSet<DirId>>>,
+    options: &'s ScanOptions,
+    progress: Option<&'s AtomicUsize>,
+    cancel: Option<&'s AtomicBool>,
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for Collectors<'s> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(Collector {
+            batch: Batch::default(),
+            sender: self.sender.clone(),
+            stream: self.stream.clone(),
+            root: self.root,
+            visited: self.visited,
+            options: self.options,
+            progress: self.progress,
+            cancel: self.cancel,
+        })
+    }
+}
+
+/// Records the entries one walker thread visits, sending them on when the
+/// thread finishes
+struct Collector<'s> {
+    batch: Batch,
+    sender: mpsc::Sender<Batch>,
+    stream: Option<mpsc::Sender<FileEntry>>,
+    root: &'s Path,
+    /// Directories walked so far, when following symlinks
+    visited: Option<&'s Mutex<HashSet<DirId>>>,
+    options: &'s ScanOptions,
+    progress: Option<&'s AtomicUsize>,
+    cancel: Option<&'s AtomicBool>,
+}
+
+impl ParallelVisitor for Collector<'_> {
+    fn visit(&mut self, entry: Result<DirEntry, ignore::Error>) -> WalkState {
+        if self
+            .cancel
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            return WalkState::Quit;
+        }
+        let Ok(entry) = entry else {
+            return WalkState::Continue;
+        };
+        let is_file = entry.file_type().is_some_and(|ft| ft.is_file());
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        if is_file {
+            let metadata = entry.metadata().ok();
+            let size = metadata.as_ref().map_or(0, |m| m.len());
+            if self.options.too_large(size) {
+                return WalkState::Continue;
+            }
+            if let Some(progress) = self.progress {
+                progress.fetch_add(1, Ordering::Relaxed);
+            }
+            let members = match self.options.archives && archive::is_archive(entry.path()) {
+                // An archive that can't be read is still indexed itself
+                true => archive::members(entry.path(), |size| self.options.too_large(size))
+                    .unwrap_or_default(),
+                false => Vec::new(),
+            };
+            if let Some(progress) = self.progress {
+                progress.fetch_add(members.len(), Ordering::Relaxed);
+            }
+            self.record(walked_entry(entry, metadata, false));
+            members.into_iter().for_each(|member| self.record(member));
+        } else if is_dir {
+            if self.visited.is_some() && !self.first_visit(&entry) {
+                return WalkState::Skip;
+            }
+            self.batch.dir_count += 1;
+            // The root itself isn't an entry of its own index
+            if entry.depth() > 0 {
+                let metadata = entry.metadata().ok();
+                self.record(walked_entry(entry, metadata, true));
+            }
+        }
+        WalkState::Continue
+    }
+}
+
+/// The [`FileEntry`] for a file or directory the walker visited
+fn walked_entry(entry: DirEntry, metadata: Option<std::fs::Metadata>, is_dir: bool) -> FileEntry {
+    let size = match (&metadata, is_dir) {
+        (Some(metadata), false) => metadata.len(),
+        _ => 0,
+    };
+    let name = entry.file_name().to_string_lossy().to_string();
+    FileEntry {
+        folded_name: normalize::folded_name(&name),
+        name,
+        size,
+        modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+        attributes: metadata
+            .as_ref()
+            .map(perms::Attributes::of)
+            .unwrap_or_default(),
+        device: metadata.as_ref().and_then(mounts::device),
+        is_dir,
+        is_symlink: entry.path_is_symlink(),
+        path: entry.into_path(),
+        project: None,
+        owners: None,
+        vendored: false,
+        hash: None,
+        repo: None,
+        git_status: Default::default(),
+        mime: None,
+    }
+}
+
+impl Collector<'_> {
+    fn record(&mut self, entry: FileEntry) {
+        match &self.stream {
+            // The receiver only goes away once it's stopped listening
+            Some(stream) => {
+                let _ = stream.send(entry);
+            }
+            None => self.batch.files.push(entry),
+        }
+    }
+
+    /// Whether a directory reached while following symlinks hasn't been
+    /// walked yet. A link to a directory inside the root is never followed,
+    /// since the directory is indexed under its real path, and a directory
+    /// reached through a link after being walked is a loop or a duplicate.
+    fn first_visit(&self, entry: &DirEntry) -> bool {
+        if entry.path_is_symlink()
+            && dunce::canonicalize(entry.path()).is_ok_and(|target| target.starts_with(self.root))
+        {
+            return false;
+        }
+        let (Some(visited), Some(id)) = (self.visited, dir_id(entry)) else {
+            return true;
+        };
+        let mut visited = visited
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        visited.insert(id) || !entry.path_is_symlink()
+    }
+}
+
+/// Device and inode of a directory, which identify it whatever path it's
+/// reached by
+type DirId = (u64, u64);
+
+#[cfg(unix)]
+fn dir_id(entry: &DirEntry) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = entry.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Elsewhere the walker's own check for links to an ancestor stops loops
+#[cfg(not(unix))]
+fn dir_id(_entry: &DirEntry) -> Option<DirId> {
+    None
+}
+
+impl Drop for Collector<'_> {
+    fn drop(&mut self) {
+        // The receiver outlives the walk, so this only fails if it panicked
+        let _ = self.sender.send(std::mem::take(&mut self.batch));
+    }
+}
+
+/// Compile exclude patterns into a matcher rooted at `root`.
+///
+/// Returns `None` when there are no patterns, so callers can skip matching.
+pub fn exclude_matcher(root: &Path, excludes: &[String]) -> Result<Option<Gitignore>> {
+    if excludes.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in excludes {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| Error::query("exclude pattern", pattern))?;
+    }
+    Ok(Some(builder.build().map_err(anyhow::Error::new)?))
+}
+
+/// Compile extra ignore files into one matcher whose patterns are relative
+/// to `root`, wherever the files are.
+///
+/// Returns `None` when there are none, so callers can skip matching.
+pub fn ignore_file_matcher(root: &Path, files: &[PathBuf]) -> Result<Option<Gitignore>> {
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    for file in files {
+        if let Some(err) = builder.add(file) {
+            let error = anyhow::Error::new(err);
+            return Err(error
+                .context(format!("Invalid ignore file: {}", file.display()))
+                .into());
+        }
+    }
+    Ok(Some(builder.build().map_err(anyhow::Error::new)?))
+}
+
+/// Mount points below `root` that a scan with `options` leaves out. The
+/// walker stays on one filesystem by itself, but only a mount table says
+/// which filesystems are remote, and a watcher reporting paths one at a time
+/// needs the boundaries spelled out.
+pub fn skipped_mounts(root: &Path, options: &ScanOptions) -> Vec<PathBuf> {
+    if !options.one_file_system && !options.skip_network {
+        return Vec::new();
+    }
+    let root_device = std::fs::metadata(root)
+        .ok()
+        .and_then(|m| mounts::device(&m));
+    let table = mounts::MountTable::read();
+    table
+        .below(root)
+        .filter(|mount| {
+            options.one_file_system && root_device != Some(mount.device)
+                || options.skip_network && (mount.is_remote() || mount.is_virtual())
+        })
+        .map(|mount| mount.point.clone())
+        .collect()
+}
+
+/// The ignore rules of a scan that don't live in the tree: its exclude
+/// patterns, `--ignore-file` files and the global ignore file, compiled once
+/// for a walk or a watcher's [`crate::watch::PathFilter`]
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Compile the rules `options` names, relative to `root`
+    pub fn compile(root: &Path, options: &ScanOptions) -> Result<Self> {
+        let global: Vec<PathBuf> = options
+            .global_ignore
+            .iter()
+            .filter(|file| file.is_file())
+            .cloned()
+            .collect();
+        let matchers = exclude_matcher(root, &options.excludes)?
+            .into_iter()
+            .chain(ignore_file_matcher(root, &options.ignore_files)?)
+            .chain(ignore_file_matcher(root, &global)?)
+            .collect();
+        Ok(IgnoreRules { matchers })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matchers.is_empty()
+    }
+
+    /// Whether `path` itself is ignored, as a walk that never descended into
+    /// an ignored directory asks
+    pub fn matched(&self, path: &Path, is_dir: bool) -> bool {
+        self.matchers
+            .iter()
+            .any(|rules| rules.matched(path, is_dir).is_ignore())
+    }
+
+    /// Whether `path` or a directory above it is ignored, for paths reported
+    /// one at a time
+    pub fn matched_with_parents(&self, path: &Path, is_dir: bool) -> bool {
+        self.matchers
+            .iter()
+            .any(|rules| rules.matched_path_or_any_parents(path, is_dir).is_ignore())
+    }
+}
+
+/// Build a [`FileEntry`] for a single file outside of a directory walk.
+///
+/// Returns `None` if the path no longer exists or isn't a regular file.
+pub fn entry_for_file(path: &Path) -> Option<FileEntry> {
+    entry_for_path(path).filter(|entry| !entry.is_dir)
+}
+
+/// Build a [`FileEntry`] for a single file or directory outside of a
+/// directory walk.
+///
+/// Returns `None` if the path no longer exists or is something else, such
+/// as a socket.
+pub fn entry_for_path(path: &Path) -> Option<FileEntry> {
+    let is_symlink = std::fs::symlink_metadata(path).ok()?.is_symlink();
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return None;
+    }
+    let name = path.file_name()?.to_string_lossy().to_string();
+    Some(FileEntry {
+        path: path.to_path_buf(),
+        folded_name: normalize::folded_name(&name),
+        name,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        is_dir: metadata.is_dir(),
+        is_symlink,
+        modified: metadata.modified().ok(),
+        project: None,
+        owners: None,
+        vendored: false,
+        hash: None,
+        repo: None,
+        git_status: Default::default(),
+        mime: None,
+        attributes: perms::Attributes::of(&metadata),
+        device: mounts::device(&metadata),
     })
 }
 
+/// BLAKE3 hash of the contents of `path`
+pub fn content_hash(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(std::fs::File::open(path)?)?;
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// A content hash in lowercase hex
+pub fn hash_hex(hash: &[u8; 32]) -> String {
+    blake3::Hash::from_bytes(*hash).to_hex().to_string()
+}
+
 /// Format bytes into human-readable size
 pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -148,10 +728,261 @@ mod tests {
         assert_eq!(scan.file_count, 3);
         assert!(scan.dir_count >= 2); // root dir + subdir
         assert!(scan.total_size > 0);
+        if cfg!(unix) {
+            assert!(scan.files.iter().all(|entry| entry.device.is_some()));
+        }
+
+        // Nothing is mounted below it, so staying on one filesystem misses nothing
+        let options = ScanOptions {
+            one_file_system: true,
+            skip_network: true,
+            ..Default::default()
+        };
+        let contained = scan_directory_with(&dir, &options, None, None).unwrap();
+        assert_eq!(contained.files.len(), scan.files.len());
 
         let _ = fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn test_scan_with_excludes_and_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("node_modules/pkg")).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("node_modules/pkg/index.js"), "x").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.path().join("src/scratch.tmp"), "tmp").unwrap();
+
+        let options = ScanOptions {
+            excludes: vec!["node_modules/".to_string(), "*.tmp".to_string()],
+            ..Default::default()
+        };
+        let progress = AtomicUsize::new(0);
+        let scan = scan_directory_with(dir.path(), &options, Some(&progress), None).unwrap();
+        let mut names: Vec<&str> = scan.files.iter().map(|f| f.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["main.rs", "src"]);
+        assert_eq!(progress.load(Ordering::Relaxed), 1);
+        assert_eq!(scan.options, options);
+
+        let invalid = ScanOptions {
+            excludes: vec!["[z-a]".to_string()],
+            ..Default::default()
+        };
+        assert!(scan_directory_with(dir.path(), &invalid, None, None).is_err());
+    }
+
+    #[test]
+    fn test_depth_and_size_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("top.txt"), "x").unwrap();
+        fs::write(dir.path().join("a/mid.txt"), "x").unwrap();
+        fs::write(dir.path().join("a/b/deep.txt"), "x").unwrap();
+        fs::write(dir.path().join("a/big.bin"), "x".repeat(1000)).unwrap();
+
+        let options = ScanOptions {
+            threads: Some(2),
+            max_depth: Some(2),
+            max_filesize: Some(100),
+            ..Default::default()
+        };
+        let scan = scan_directory_with(dir.path(), &options, None, None).unwrap();
+        let mut names: Vec<&str> = scan.files.iter().map(|f| f.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b", "mid.txt", "top.txt"]);
+        assert_eq!(scan.file_count, 2);
+        assert_eq!(scan.total_size, 2);
+    }
+
+    #[test]
+    fn test_hidden_and_ignore_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), "ref").unwrap();
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.path().join(".ignore"), "*.tmp\n").unwrap();
+        fs::write(dir.path().join(".env"), "x").unwrap();
+        fs::write(dir.path().join("main.rs"), "x").unwrap();
+        fs::write(dir.path().join("app.log"), "x").unwrap();
+        fs::write(dir.path().join("scratch.tmp"), "x").unwrap();
+        fs::write(dir.path().join("data.csv"), "x").unwrap();
+        let rules = tempfile::NamedTempFile::new().unwrap();
+        fs::write(rules.path(), "/data.csv\n").unwrap();
+
+        let names = |options: ScanOptions| {
+            let scan = scan_directory_with(dir.path(), &options, None, None).unwrap();
+            let mut names: Vec<String> = scan.files.into_iter().map(|f| f.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(ScanOptions::default()), ["data.csv", "main.rs"]);
+        assert_eq!(
+            names(ScanOptions {
+                hidden: true,
+                ..Default::default()
+            }),
+            [".env", ".gitignore", ".ignore", "data.csv", "main.rs"]
+        );
+        assert_eq!(
+            names(ScanOptions {
+                no_git_ignore: true,
+                ..Default::default()
+            }),
+            ["app.log", "data.csv", "main.rs"]
+        );
+        assert_eq!(
+            names(ScanOptions {
+                no_ignore: true,
+                ignore_files: vec![rules.path().to_path_buf()],
+                ..Default::default()
+            }),
+            ["app.log", "main.rs", "scratch.tmp"]
+        );
+
+        let missing = ScanOptions {
+            ignore_files: vec![dir.path().join("missing")],
+            ..Default::default()
+        };
+        assert!(scan_directory_with(dir.path(), &missing, None, None).is_err());
+    }
+
+    #[test]
+    fn test_sonic_ignore_and_global_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("notes")).unwrap();
+        fs::write(dir.path().join(".ignore"), "*.bak\n").unwrap();
+        fs::write(
+            dir.path().join("notes/.sonic-ignore"),
+            "drafts/\n!keep.bak\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("notes/drafts")).unwrap();
+        fs::write(dir.path().join("notes/drafts/idea.md"), "x").unwrap();
+        fs::write(dir.path().join("notes/keep.bak"), "x").unwrap();
+        fs::write(dir.path().join("notes/old.bak"), "x").unwrap();
+        fs::write(dir.path().join("notes/todo.md"), "x").unwrap();
+        let global = tempfile::NamedTempFile::new().unwrap();
+        fs::write(global.path(), "todo.*\n").unwrap();
+
+        let names = |options: ScanOptions| {
+            let scan = scan_directory_with(dir.path(), &options, None, None).unwrap();
+            let mut names: Vec<String> = scan.files.into_iter().map(|f| f.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(ScanOptions::default()),
+            ["keep.bak", "notes", "todo.md"]
+        );
+        assert_eq!(
+            names(ScanOptions {
+                global_ignore: Some(global.path().to_path_buf()),
+                ..Default::default()
+            }),
+            ["keep.bak", "notes"]
+        );
+        // A global ignore file that doesn't exist yet is no error
+        assert_eq!(
+            names(ScanOptions {
+                global_ignore: Some(dir.path().join("missing")),
+                ..Default::default()
+            }),
+            ["keep.bak", "notes", "todo.md"]
+        );
+        assert_eq!(
+            names(ScanOptions {
+                no_ignore: true,
+                ..Default::default()
+            }),
+            [
+                "drafts", "idea.md", "keep.bak", "notes", "old.bak", "todo.md"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks_without_loops() {
+        use std::os::unix::fs::symlink;
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let root = dunce::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("real")).unwrap();
+        fs::write(root.join("real/a.txt"), "a").unwrap();
+        fs::write(outside.path().join("x.txt"), "x").unwrap();
+        symlink(&root, root.join("real/loop")).unwrap();
+        symlink(root.join("real"), root.join("twin")).unwrap();
+        symlink(root.join("real/a.txt"), root.join("link.txt")).unwrap();
+        symlink(outside.path(), root.join("ext1")).unwrap();
+        symlink(outside.path(), root.join("ext2")).unwrap();
+
+        let paths = |options: &ScanOptions| {
+            let scan = scan_directory_with(&root, options, None, None).unwrap();
+            let mut paths: Vec<(String, bool)> = scan
+                .files
+                .iter()
+                .map(|f| {
+                    let path = f.path.strip_prefix(&root).unwrap();
+                    (path.display().to_string(), f.is_symlink)
+                })
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(
+            paths(&ScanOptions::default()),
+            [
+                ("real".to_string(), false),
+                ("real/a.txt".to_string(), false)
+            ]
+        );
+
+        let follow = ScanOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let found = paths(&follow);
+        // The outside tree is indexed once, through whichever link came first
+        assert_eq!(found.len(), 5, "{:?}", found);
+        assert!(found.contains(&("link.txt".to_string(), true)));
+        assert!(
+            found
+                .iter()
+                .any(|(path, link)| path.starts_with("ext") && *link)
+        );
+        assert!(found.contains(&("real/a.txt".to_string(), false)));
+        assert!(found.iter().any(|(path, _)| path.ends_with("/x.txt")));
+    }
+
+    #[test]
+    fn test_cancelled_scan_is_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let options = ScanOptions::default();
+
+        let scan = scan_directory_with(dir.path(), &options, None, None).unwrap();
+        assert!(!scan.partial);
+        let cancel = AtomicBool::new(true);
+        let scan = scan_directory_with(dir.path(), &options, None, Some(&cancel)).unwrap();
+        assert!(scan.partial);
+        assert!(scan.files.is_empty());
+    }
+
+    #[test]
+    fn test_entry_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("single.txt");
+        fs::write(&path, "12345").unwrap();
+
+        let entry = entry_for_file(&path).unwrap();
+        assert_eq!(entry.name, "single.txt");
+        assert_eq!(entry.size, 5);
+        assert!(entry.modified.is_some());
+        assert!(entry_for_file(dir.path()).is_none());
+        assert!(entry_for_file(&dir.path().join("missing")).is_none());
+    }
+
     #[test]
     fn test_format_size() {
         assert_eq!(format_size(0), "0 B");
@@ -0,0 +1,161 @@
+//! When `ss daemon` re-indexes on its own, for `--reindex`: every so often
+//! (`6h`), or at set times of day in the local timezone (`03:00,15:00`),
+//! like the daily crontab line most people would otherwise write.
+
+use crate::index::{Index, UpdateReport};
+use crate::units;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, TimeZone};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often a waiting schedule checks whether it was stopped
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Shortest interval allowed, so a typo like `6` (seconds) can't keep the
+/// daemon rescanning
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When to re-index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// This long after the previous run, starting from the daemon's start
+    Every(Duration),
+    /// At each of these local times every day, in order
+    Daily(Vec<NaiveTime>),
+}
+
+impl Schedule {
+    /// Parse a duration such as `6h` or `90m`, or times of day such as
+    /// `03:00` or `03:00,15:30`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if !text.contains(':') {
+            let interval = units::parse_duration(text)?;
+            if interval < MIN_INTERVAL {
+                return Err(format!(
+                    "invalid schedule '{}': re-index at most once a minute",
+                    text
+                ));
+            }
+            return Ok(Schedule::Every(interval));
+        }
+        let mut times = text
+            .split(',')
+            .map(|time| {
+                NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| {
+                    format!(
+                        "invalid schedule '{}': expected a duration such as 6h or times such as 03:00,15:30",
+                        text
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        times.sort();
+        times.dedup();
+        Ok(Schedule::Daily(times))
+    }
+
+    /// The first time the schedule comes due after `after`. A time of day
+    /// skipped by a daylight saving change waits for the next day's.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> DateTime<Tz> {
+        match self {
+            Schedule::Every(interval) => {
+                after.clone() + TimeDelta::from_std(*interval).unwrap_or(TimeDelta::MAX)
+            }
+            Schedule::Daily(times) => {
+                let timezone = after.timezone();
+                let today = after.date_naive();
+                (0..=2)
+                    .filter_map(|days| today.checked_add_days(chrono::Days::new(days)))
+                    .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+                    .filter_map(|time| timezone.from_local_datetime(&time).earliest())
+                    .find(|time| time > after)
+                    .unwrap_or_else(|| after.clone() + TimeDelta::days(1))
+            }
+        }
+    }
+}
+
+/// Bring the index in `index_dir` up to date, saving a new generation if
+/// anything changed, which a daemon serving it picks up. A `full` re-index
+/// walks the whole root even where a change journal could say what changed.
+pub fn reindex(index_dir: &Path, full: bool) -> Result<(UpdateReport, Option<u64>)> {
+    let mut index = Index::load(index_dir)?;
+    index.load_content(index_dir)?;
+    Ok(index.update_and_save(index_dir, full)?)
+}
+
+/// Re-index `index_dir` whenever `schedule` comes due, until `stop` is set,
+/// passing the outcome of each run to `on_reindex`. A failed run is only
+/// reported; the next one is tried as scheduled.
+pub fn run(
+    index_dir: &Path,
+    schedule: &Schedule,
+    full: bool,
+    stop: &AtomicBool,
+    mut on_reindex: impl FnMut(Result<(UpdateReport, Option<u64>)>),
+) {
+    let mut due = schedule.next_after(&Local::now());
+    while !stop.load(Ordering::Relaxed) {
+        let now = Local::now();
+        if now < due {
+            let left = (due - now).to_std().unwrap_or_default();
+            std::thread::sleep(left.min(STOP_POLL));
+            continue;
+        }
+        on_reindex(reindex(index_dir, full));
+        due = schedule.next_after(&Local::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let start = at("2024-05-01T10:00:00Z");
+        let every = Schedule::parse("6h").unwrap();
+        assert_eq!(every, Schedule::Every(Duration::from_secs(6 * 3600)));
+        assert_eq!(every.next_after(&start), at("2024-05-01T16:00:00Z"));
+
+        let daily = Schedule::parse("15:30, 03:00").unwrap();
+        assert_eq!(daily.next_after(&start), at("2024-05-01T15:30:00Z"));
+        assert_eq!(
+            daily.next_after(&at("2024-05-01T15:30:00Z")),
+            at("2024-05-02T03:00:00Z")
+        );
+        assert_eq!(
+            daily.next_after(&at("2024-12-31T23:59:00Z")),
+            at("2025-01-01T03:00:00Z")
+        );
+
+        assert!(Schedule::parse("10s").is_err());
+        assert!(Schedule::parse("25:00").is_err());
+        assert!(Schedule::parse("soon").is_err());
+    }
+
+    #[test]
+    fn test_reindex_saves_only_changes() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("a.txt"), "a").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let scan = crate::scanner::scan_directory(root.path()).unwrap();
+        Index::from_scan(scan).save(index_dir.path()).unwrap();
+
+        let (report, generation) = reindex(index_dir.path(), false).unwrap();
+        assert!(!report.has_changes());
+        assert_eq!(generation, None);
+
+        std::fs::write(root.path().join("b.txt"), "b").unwrap();
+        let (report, generation) = reindex(index_dir.path(), true).unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(generation, Some(2));
+    }
+}
@@ -0,0 +1,213 @@
+//! Anonymize an index so it can be shared, say to reproduce a slow search,
+//! without giving away what the files are called.
+
+use crate::index::Index;
+use crate::normalize;
+use crate::projects::Project;
+use crate::scanner::FileEntry;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+
+/// Root every path of a scrubbed index is moved under
+pub const SCRUBBED_ROOT: &str = "/scrubbed";
+
+/// Shortest replacement for a name, so short names don't collide
+const MIN_TOKEN_LEN: usize = 8;
+
+const ALPHABET: &[u8; 36] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A copy of `index` with every name replaced by a keyed hash of it, and
+/// the file hashes, content index, exclude patterns and journal position
+/// dropped.
+///
+/// The tree keeps its shape: sizes, times, kinds, permissions, git status
+/// and extensions stay, names keep their length (at least 8 characters),
+/// and equal names anywhere in the tree are replaced by equal tokens.
+/// Without `salt`, which isn't stored, the tokens can't be checked
+/// against guessed names.
+pub fn scrub(index: &Index, salt: &[u8; 32]) -> Index {
+    let mut scrubber = Scrubber {
+        key: *salt,
+        tokens: HashMap::new(),
+        root: &index.meta.root,
+    };
+    let mut meta = index.meta.clone();
+    meta.root = PathBuf::from(SCRUBBED_ROOT);
+    meta.scan_options.hash = false;
+    meta.scan_options.excludes.clear();
+    meta.scan_options.ignore_files.clear();
+    meta.journal = None;
+    let mut entries: Vec<FileEntry> = index
+        .entries
+        .iter()
+        .map(|entry| {
+            let name = scrubber.name(&entry.name);
+            FileEntry {
+                path: scrubber.path(&entry.path),
+                folded_name: normalize::folded_name(&name),
+                name,
+                hash: None,
+                ..entry.clone()
+            }
+        })
+        .collect();
+    // Left in the original order, they'd tell how the real names sort
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let projects = index
+        .projects
+        .iter()
+        .map(|project| Project {
+            name: scrubber.name(&project.name),
+            root: scrubber.path(&project.root),
+            kind: project.kind,
+        })
+        .collect();
+    let owner_sets = index
+        .owner_sets
+        .iter()
+        .map(|owners| {
+            owners
+                .iter()
+                .map(|owner| format!("@{}", scrubber.token(owner.trim_start_matches('@'))))
+                .collect()
+        })
+        .collect();
+    let repos = index.repos.iter().map(|repo| scrubber.path(repo)).collect();
+    Index {
+        meta,
+        entries,
+        projects,
+        owner_sets,
+        repos,
+        content: None,
+    }
+}
+
+/// A salt for [`scrub`] that differs from run to run
+pub fn random_salt() -> [u8; 32] {
+    let mut salt = [0; 32];
+    for chunk in salt.chunks_mut(8) {
+        // Each RandomState is keyed from the OS's random source
+        chunk.copy_from_slice(&RandomState::new().hash_one(0u8).to_le_bytes());
+    }
+    salt
+}
+
+struct Scrubber<'a> {
+    key: [u8; 32],
+    tokens: HashMap<String, String>,
+    root: &'a Path,
+}
+
+impl Scrubber<'_> {
+    /// `name` with its stem replaced, keeping a leading dot and the
+    /// extension
+    fn name(&mut self, name: &str) -> String {
+        let (dot, rest) = match name.strip_prefix('.') {
+            Some(rest) => (".", rest),
+            None => ("", name),
+        };
+        match rest.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                format!("{}{}.{}", dot, self.token(stem), extension)
+            }
+            _ => format!("{}{}", dot, self.token(rest)),
+        }
+    }
+
+    /// `path` moved from the index root to [`SCRUBBED_ROOT`], with every
+    /// component below the root scrubbed
+    fn path(&mut self, path: &Path) -> PathBuf {
+        let relative = path.strip_prefix(self.root).unwrap_or(path);
+        let mut scrubbed = PathBuf::from(SCRUBBED_ROOT);
+        for component in relative.iter() {
+            scrubbed.push(self.name(&component.to_string_lossy()));
+        }
+        scrubbed
+    }
+
+    fn token(&mut self, text: &str) -> String {
+        if let Some(token) = self.tokens.get(text) {
+            return token.clone();
+        }
+        let len = text.chars().count().max(MIN_TOKEN_LEN);
+        let mut bytes = vec![0; len];
+        blake3::Hasher::new_keyed(&self.key)
+            .update(text.as_bytes())
+            .finalize_xof()
+            .fill(&mut bytes);
+        let token: String = bytes
+            .iter()
+            .map(|&byte| ALPHABET[byte as usize % ALPHABET.len()] as char)
+            .collect();
+        self.tokens.insert(text.to_string(), token.clone());
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::ProjectKind;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_scrub_keeps_structure() {
+        let index = IndexBuilder::new("/home/me/work")
+            .spec(
+                "app/src/secret_plan.rs 2K 2024-03-01
+                 lib/src/lib.rs         40
+                 .env                   12",
+            )
+            .unwrap()
+            .project("app", "app", ProjectKind::Cargo)
+            .build();
+        let scrubbed = scrub(&index, &[7; 32]);
+        assert_eq!(scrubbed.meta.root, Path::new(SCRUBBED_ROOT));
+        assert_eq!(scrubbed.entries.len(), index.entries.len());
+        let mut sizes: Vec<u64> = index.entries.iter().map(|entry| entry.size).collect();
+        let mut kept: Vec<u64> = scrubbed.entries.iter().map(|entry| entry.size).collect();
+        sizes.sort();
+        kept.sort();
+        assert_eq!(kept, sizes);
+        for entry in &scrubbed.entries {
+            assert_eq!(entry.path.file_name().unwrap(), entry.name.as_str());
+            assert!(entry.path.starts_with(SCRUBBED_ROOT));
+        }
+        let dated = |index: &Index| {
+            let entry = index.entries.iter().find(|entry| entry.size == 2048);
+            entry.unwrap().modified
+        };
+        assert_eq!(dated(&scrubbed), dated(&index));
+        let names: Vec<&str> = scrubbed.entries.iter().map(|e| e.name.as_str()).collect();
+        assert!(names.iter().all(|name| !name.contains("secret")));
+        let plan = names
+            .iter()
+            .find(|name| name.ends_with(".rs") && name.len() == 14);
+        assert!(plan.is_some(), "{:?}", names);
+        assert!(
+            names
+                .iter()
+                .any(|name| name.starts_with('.') && name.len() == 9)
+        );
+
+        // Both `src` directories get the same token
+        let srcs: Vec<&FileEntry> = scrubbed
+            .entries
+            .iter()
+            .filter(|entry| entry.is_dir && entry.path.components().count() == 4)
+            .collect();
+        assert_eq!(srcs.len(), 2);
+        assert_eq!(srcs[0].name, srcs[1].name);
+        assert_ne!(scrubbed.projects[0].name, "app");
+        assert!(scrubbed.projects[0].root.starts_with(SCRUBBED_ROOT));
+
+        let again = scrub(&index, &[7; 32]);
+        assert_eq!(again.entries, scrubbed.entries);
+        let other = scrub(&index, &[8; 32]);
+        assert_ne!(other.entries, scrubbed.entries);
+        assert_ne!(random_salt(), random_salt());
+    }
+}
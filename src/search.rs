@@ -0,0 +1,2059 @@
+use crate::cjk::{self, BigramMatcher};
+use crate::engine::Engine;
+use crate::error::{Error, Result, bail};
+use crate::filetype::{self, TypeMatcher};
+use crate::fixture::Rng;
+use crate::git;
+use crate::grep::{self, FileMatches, GrepOptions};
+use crate::index::{Index, IndexMeta};
+use crate::normalize;
+use crate::perms::{self, PermFilter};
+use crate::query::{self, Query};
+use crate::scanner::{self, FileEntry, ScanOptions, ScanResult};
+use crate::suggest;
+use crate::tags::Tagged;
+use crate::units::SizeFilter;
+use anyhow::Context;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::ControlFlow;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Options controlling a filename search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchOptions {
+    /// Return at most this many results
+    pub limit: Option<usize>,
+    /// Which entries are considered at all
+    pub filters: Filters,
+    /// Match the query against the path relative to the index root instead
+    /// of the file name. Queries containing a `/` always do.
+    pub full_path: bool,
+    /// How letter case is matched
+    pub case: CaseMode,
+    /// Only match accented letters with the same accents, rather than
+    /// folding them away so `resume` finds `résumé`
+    pub exact_accents: bool,
+    /// Compare queries and names in Latin letters, so `moskva` finds
+    /// `Москва`. Searching an index scanned with `--transliterate` sets it,
+    /// since names are only stored transliterated there.
+    pub transliterate: bool,
+    /// How recency, depth and where terms match weigh against the fuzzy
+    /// score when ranking
+    pub weights: RankWeights,
+    /// Return a uniform random sample of the matches, ranked, instead of
+    /// the best of them
+    pub sample: Option<Sample>,
+    /// Return the first matches in index order rather than the best ones,
+    /// without ranking them
+    #[serde(default)]
+    pub unsorted: bool,
+    /// How query terms match names (see [`crate::engine`])
+    #[serde(default)]
+    pub engine: Engine,
+}
+
+/// How many matches a sampled search keeps, and the seed choosing them so
+/// the same seed over the same index picks the same ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub size: usize,
+    pub seed: u64,
+}
+
+/// Points added to or taken off a match's fuzzy score, which typically
+/// runs from a few dozen to a couple of hundred
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RankWeights {
+    /// Points for an entry modified just now, halving every
+    /// `recency_half_life_days`
+    pub recency: i64,
+    pub recency_half_life_days: f64,
+    /// Points taken off for each directory between the root and the entry
+    pub depth: i64,
+    /// Points for each term found at the start of a word of the name, as
+    /// `rep` is in `q3-report.pdf` and `Report` in `annualReport.pdf`
+    pub word_boundary: i64,
+    /// Points for each term that is the name without its extension
+    pub stem: i64,
+}
+
+impl Default for RankWeights {
+    fn default() -> Self {
+        RankWeights {
+            recency: 40,
+            recency_half_life_days: 30.0,
+            depth: 5,
+            word_boundary: 20,
+            stem: 50,
+        }
+    }
+}
+
+impl RankWeights {
+    /// Weights leaving the fuzzy score alone
+    pub fn none() -> Self {
+        RankWeights {
+            recency: 0,
+            depth: 0,
+            word_boundary: 0,
+            stem: 0,
+            ..Default::default()
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Whether `query` is matched against relative paths rather than names,
+    /// as all of it is with `full_path` and its terms containing a `/` are
+    pub fn matches_full_path(&self, query: &str) -> bool {
+        self.full_path
+            || Query::parse(query).is_ok_and(|query| query.terms().iter().any(|t| t.contains('/')))
+    }
+
+    /// `text` as queries and names are compared: NFC-normalized, and with
+    /// accents folded away, and transliterated if `transliterate` is set,
+    /// unless `exact_accents` is set
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match (self.exact_accents, self.transliterate) {
+            (true, _) => normalize::nfc(text),
+            (false, false) => normalize::fold_accents(text),
+            (false, true) => normalize::fold_to_latin(text),
+        }
+    }
+
+    /// These options as they apply to the index `meta` describes, which
+    /// transliterates names if it was scanned with `--transliterate`
+    pub fn for_index(&self, meta: &IndexMeta) -> Cow<'_, SearchOptions> {
+        match meta.scan_options.transliterate && !self.transliterate {
+            true => Cow::Owned(SearchOptions {
+                transliterate: true,
+                ..self.clone()
+            }),
+            false => Cow::Borrowed(self),
+        }
+    }
+
+    /// The name of `entry` as it's compared with queries, which is the
+    /// folded name recorded at scan time unless accents must match exactly
+    pub(crate) fn match_name<'a>(&self, entry: &'a impl EntryFields) -> Cow<'a, str> {
+        match (self.exact_accents, entry.folded_name()) {
+            (false, Some(folded)) => Cow::Borrowed(folded),
+            (false, None) => Cow::Borrowed(entry.name()),
+            (true, _) => normalize::nfc(entry.name()),
+        }
+    }
+}
+
+/// How `find` and `grep` match letter case
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaseMode {
+    /// Ignore case unless the query contains an uppercase letter
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+    /// Whether `query` is matched ignoring case. Characters escaped with a
+    /// backslash, as in the regex `\W`, don't make a smart-case query
+    /// case-sensitive.
+    pub fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMode::Smart => {
+                let mut escaped = false;
+                !query.chars().any(|c| {
+                    let upper = !escaped && c.is_uppercase();
+                    escaped = !escaped && c == '\\';
+                    upper
+                })
+            }
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+        }
+    }
+
+    /// A fuzzy matcher for `query` honouring this mode: a subsequence
+    /// matcher, or a [`BigramMatcher`] for a query with CJK characters
+    pub(crate) fn matcher(self, query: &str) -> Box<dyn FuzzyMatcher> {
+        let ignore_case = self.ignores_case(query);
+        if cjk::has_cjk(query) {
+            return Box::new(BigramMatcher::new(ignore_case));
+        }
+        match ignore_case {
+            true => Box::new(SkimMatcherV2::default().ignore_case()),
+            false => Box::new(SkimMatcherV2::default().respect_case()),
+        }
+    }
+}
+
+/// Restrictions on which entries `find` and `grep` consider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filters {
+    /// Only match entries matching at least one of these globs.
+    ///
+    /// A glob without a `/` (`*.rs`) is matched against the file name;
+    /// one with a `/` (`src/**/*.rs`) against the path, anchored anywhere
+    /// unless it starts with `/`.
+    pub globs: Vec<String>,
+    /// Only match entries with one of these extensions, compared
+    /// case-insensitively and without the leading dot
+    pub extensions: Vec<String>,
+    /// Only match entries inside one of these sub-projects, given by name or
+    /// by root relative to the index root
+    pub projects: Vec<String>,
+    /// Only match entries owned by one of these CODEOWNERS owners, such as
+    /// `@org/team`, compared case-insensitively
+    pub owners: Vec<String>,
+    /// Only match entries whose size satisfies every one of these
+    pub sizes: Vec<SizeFilter>,
+    /// Only match entries modified at or after this time
+    pub modified_after: Option<SystemTime>,
+    /// Only match entries modified before this time
+    pub modified_before: Option<SystemTime>,
+    /// Also match entries in submodules and vendor directories, which are
+    /// left out by default
+    pub include_vendored: bool,
+    /// Only match files whose content hash starts with this hex digest
+    pub hash: Option<String>,
+    /// Only match entries owned by one of these users, by name or uid (Unix)
+    pub users: Vec<String>,
+    /// Only match entries whose group is one of these, by name or gid (Unix)
+    pub groups: Vec<String>,
+    /// Only match entries whose owner has no name, like find's `-nouser`
+    pub no_user: bool,
+    /// Only match entries whose group has no name, like find's `-nogroup`
+    pub no_group: bool,
+    /// Only match entries whose permission bits pass every one of these
+    pub perms: Vec<PermFilter>,
+    /// Only match entries nobody may write to
+    pub readonly: bool,
+    /// Only match entries of one of these types; every type when empty
+    pub types: Vec<EntryType>,
+    /// Only match files of one of these file types or categories, such as
+    /// `rust` or `image` (see [`filetype::type_names`])
+    pub file_types: Vec<String>,
+    /// Only match files whose MIME type is one of these, such as
+    /// `application/pdf`, or starts with one ending in `*`, such as `image/*`
+    pub mimes: Vec<String>,
+    /// Only match files git tracks
+    pub git_tracked: bool,
+    /// Only match tracked files with uncommitted changes
+    pub git_modified: bool,
+    /// Only match entries inside one of these git work trees, given by
+    /// absolute path, path relative to the index root or directory name
+    pub repos: Vec<String>,
+    /// Only match entries matching this [`Query`], such as
+    /// `ext:rs AND NOT path:tests`
+    pub query: Option<String>,
+    /// Only match files carrying one of the tags asked for, as picked by
+    /// [`Tags::select`](crate::tags::Tags::select)
+    pub tagged: Option<Tagged>,
+}
+
+/// Kinds of entries `find --type` picks, like `fd --type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryType {
+    File,
+    Directory,
+    /// A symbolic link to a file or directory, only indexed by scans with
+    /// `--follow-symlinks`
+    Symlink,
+}
+
+impl EntryType {
+    /// Whether `entry` is of this type. A symbolic link also counts as the
+    /// type of what it points to.
+    pub fn matches(self, entry: &impl EntryFields) -> bool {
+        match self {
+            EntryType::File => !entry.is_dir(),
+            EntryType::Directory => entry.is_dir(),
+            EntryType::Symlink => entry.is_symlink(),
+        }
+    }
+}
+
+/// The fields of an entry that filtering and ranking look at, so entries
+/// can be searched in place as well as after decoding into [`FileEntry`]
+pub trait EntryFields {
+    fn path(&self) -> &Path;
+    fn name(&self) -> &str;
+    fn folded_name(&self) -> Option<&str>;
+    fn size(&self) -> u64;
+    fn is_dir(&self) -> bool;
+    fn is_symlink(&self) -> bool;
+    fn modified(&self) -> Option<SystemTime>;
+    fn project(&self) -> Option<u32>;
+    fn owners(&self) -> Option<u32>;
+    fn vendored(&self) -> bool;
+    fn hash(&self) -> Option<&[u8; 32]>;
+    fn repo(&self) -> Option<u32>;
+    fn git_status(&self) -> git::Status;
+    fn mime(&self) -> Option<u32>;
+    fn attributes(&self) -> perms::Attributes;
+    fn device(&self) -> Option<u64>;
+}
+
+/// Entries that can be looked up by position, so ranking can split them
+/// between threads
+pub(crate) trait EntryTable: Sync {
+    type Entry<'a>: EntryFields + Send
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize;
+    fn get(&self, index: usize) -> Self::Entry<'_>;
+}
+
+impl EntryTable for [FileEntry] {
+    type Entry<'a> = &'a FileEntry;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn get(&self, index: usize) -> &FileEntry {
+        &self[index]
+    }
+}
+
+impl EntryFields for FileEntry {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn folded_name(&self) -> Option<&str> {
+        self.folded_name.as_deref()
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.is_symlink
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    fn project(&self) -> Option<u32> {
+        self.project
+    }
+
+    fn owners(&self) -> Option<u32> {
+        self.owners
+    }
+
+    fn vendored(&self) -> bool {
+        self.vendored
+    }
+
+    fn hash(&self) -> Option<&[u8; 32]> {
+        self.hash.as_ref()
+    }
+
+    fn repo(&self) -> Option<u32> {
+        self.repo
+    }
+
+    fn git_status(&self) -> git::Status {
+        self.git_status
+    }
+
+    fn mime(&self) -> Option<u32> {
+        self.mime
+    }
+
+    fn attributes(&self) -> perms::Attributes {
+        self.attributes
+    }
+
+    fn device(&self) -> Option<u64> {
+        self.device
+    }
+}
+
+impl<T: EntryFields + ?Sized> EntryFields for &T {
+    fn path(&self) -> &Path {
+        (**self).path()
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn folded_name(&self) -> Option<&str> {
+        (**self).folded_name()
+    }
+
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn is_dir(&self) -> bool {
+        (**self).is_dir()
+    }
+
+    fn is_symlink(&self) -> bool {
+        (**self).is_symlink()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        (**self).modified()
+    }
+
+    fn project(&self) -> Option<u32> {
+        (**self).project()
+    }
+
+    fn owners(&self) -> Option<u32> {
+        (**self).owners()
+    }
+
+    fn vendored(&self) -> bool {
+        (**self).vendored()
+    }
+
+    fn hash(&self) -> Option<&[u8; 32]> {
+        (**self).hash()
+    }
+
+    fn repo(&self) -> Option<u32> {
+        (**self).repo()
+    }
+
+    fn git_status(&self) -> git::Status {
+        (**self).git_status()
+    }
+
+    fn mime(&self) -> Option<u32> {
+        (**self).mime()
+    }
+
+    fn attributes(&self) -> perms::Attributes {
+        (**self).attributes()
+    }
+
+    fn device(&self) -> Option<u64> {
+        (**self).device()
+    }
+}
+
+/// [`Filters`] compiled against a particular index
+#[derive(Debug)]
+pub struct EntryFilter {
+    names: Option<GlobSet>,
+    paths: Option<GlobSet>,
+    extensions: Vec<String>,
+    /// Ids of the projects entries must belong to, if restricted
+    projects: Option<Vec<u32>>,
+    /// Ids of the owner lists entries must have, if restricted
+    owner_sets: Option<Vec<u32>>,
+    sizes: Vec<SizeFilter>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    include_vendored: bool,
+    hash: Option<String>,
+    /// Ids of the users and groups entries must belong to, if restricted
+    uids: Option<Vec<u32>>,
+    gids: Option<Vec<u32>>,
+    no_user: bool,
+    no_group: bool,
+    perms: Vec<PermFilter>,
+    readonly: bool,
+    types: Vec<EntryType>,
+    file_types: Option<TypeMatcher>,
+    git_tracked: bool,
+    git_modified: bool,
+    /// Ids of the repositories entries must be in, if restricted
+    repos: Option<Vec<u32>>,
+    /// The query entries must match, and the index root their paths are
+    /// relative to
+    query: Option<(query::Matcher, PathBuf)>,
+    tagged: Option<Tagged>,
+}
+
+impl EntryFilter {
+    /// Whether `entry` passes every filter
+    pub fn matches(&self, entry: &impl EntryFields) -> bool {
+        if entry.vendored() && !self.include_vendored {
+            return false;
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|kind| kind.matches(entry)) {
+            return false;
+        }
+        if let Some(file_types) = &self.file_types
+            && (entry.is_dir()
+                || !file_types.matches(entry.name(), entry.mime().and_then(filetype::mime_name)))
+        {
+            return false;
+        }
+        if let Some(tagged) = &self.tagged
+            && !tagged.contains(entry)
+        {
+            return false;
+        }
+        if let Some(prefix) = &self.hash
+            && !entry
+                .hash()
+                .is_some_and(|hash| scanner::hash_hex(hash).starts_with(prefix.as_str()))
+        {
+            return false;
+        }
+        if !self.matches_attributes(entry.attributes()) {
+            return false;
+        }
+        if let Some(projects) = &self.projects
+            && !entry.project().is_some_and(|id| projects.contains(&id))
+        {
+            return false;
+        }
+        if let Some(owner_sets) = &self.owner_sets
+            && !entry.owners().is_some_and(|id| owner_sets.contains(&id))
+        {
+            return false;
+        }
+        if let Some(repos) = &self.repos
+            && !entry.repo().is_some_and(|id| repos.contains(&id))
+        {
+            return false;
+        }
+        match entry.git_status() {
+            git::Status::Untracked if self.git_tracked || self.git_modified => return false,
+            git::Status::Tracked if self.git_modified => return false,
+            _ => {}
+        }
+        if !self.sizes.iter().all(|size| size.matches(entry.size())) {
+            return false;
+        }
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            // Entries without a modification time can't satisfy a date filter
+            let Some(modified) = entry.modified() else {
+                return false;
+            };
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self
+                    .modified_before
+                    .is_some_and(|before| modified >= before)
+            {
+                return false;
+            }
+        }
+        if !self.extensions.is_empty() {
+            let Some(ext) = entry.path().extension() else {
+                return false;
+            };
+            let ext = ext.to_string_lossy().to_lowercase();
+            if !self.extensions.contains(&ext) {
+                return false;
+            }
+        }
+        if let Some((query, root)) = &self.query
+            && query.score(entry, root).is_none()
+        {
+            return false;
+        }
+        match (&self.names, &self.paths) {
+            (None, None) => true,
+            (names, paths) => {
+                names.as_ref().is_some_and(|set| set.is_match(entry.name()))
+                    || paths.as_ref().is_some_and(|set| set.is_match(entry.path()))
+            }
+        }
+    }
+
+    /// Whether an entry with `attributes` passes the owner and permission
+    /// filters
+    fn matches_attributes(&self, attributes: perms::Attributes) -> bool {
+        let among = |id: Option<u32>, ids: &Option<Vec<u32>>| {
+            ids.as_ref()
+                .is_none_or(|ids| id.is_some_and(|id| ids.contains(&id)))
+        };
+        among(attributes.uid, &self.uids)
+            && among(attributes.gid, &self.gids)
+            && (!self.no_user
+                || attributes
+                    .uid
+                    .is_some_and(|uid| perms::user_name(uid).is_none()))
+            && (!self.no_group
+                || attributes
+                    .gid
+                    .is_some_and(|gid| perms::group_name(gid).is_none()))
+            && (self.perms.is_empty()
+                || attributes
+                    .mode
+                    .is_some_and(|mode| self.perms.iter().all(|perm| perm.matches(mode))))
+            && (!self.readonly || attributes.readonly)
+    }
+}
+
+fn compile_glob(glob: &str, original: &str) -> anyhow::Result<Glob> {
+    Glob::new(glob).with_context(|| Error::query("glob", original))
+}
+
+/// A file whose name matched a search query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult<'a> {
+    pub entry: &'a FileEntry,
+    /// Fuzzy match score; higher is better
+    pub score: i64,
+}
+
+/// How many entries each thread matches at a time when ranking
+const RANK_CHUNK: usize = 4096;
+
+/// Match `query` against the entries passing `filter`, fuzzy-matching its
+/// terms against names or root-relative paths, and return the matches with
+/// their scores best first, ties in the order of the entries.
+///
+/// Entries are matched in chunks across threads, each keeping its own best
+/// `options.limit`, except for samples and unsorted searches, which go
+/// through them in order.
+pub(crate) fn rank<'t, T: EntryTable + ?Sized>(
+    entries: &'t T,
+    root: &Path,
+    query: &Query,
+    options: &SearchOptions,
+    filter: &EntryFilter,
+) -> Result<Vec<(T::Entry<'t>, i64)>> {
+    let matcher = query::Matcher::new(query, options)?;
+    let span = crate::profile::span("match");
+    let matches = |range: Range<usize>| {
+        range
+            .map(|index| entries.get(index))
+            .filter(|entry| filter.matches(entry))
+            .filter_map(|entry| matcher.score(&entry, root).map(|score| (entry, score)))
+    };
+    let all = 0..entries.len();
+    if let Some(sample) = options.sample {
+        let mut results = reservoir(matches(all), sample);
+        drop(span);
+        let _span = crate::profile::span("rank");
+        results.sort_by_key(|&(_, score)| Reverse(score));
+        results.truncate(options.limit.unwrap_or(usize::MAX));
+        return Ok(results);
+    }
+    if options.unsorted {
+        return Ok(matches(all)
+            .take(options.limit.unwrap_or(usize::MAX))
+            .collect());
+    }
+    let chunks: Vec<Range<usize>> = all
+        .step_by(RANK_CHUNK)
+        .map(|start| start..(start + RANK_CHUNK).min(entries.len()))
+        .collect();
+    // Chunks stay in order, so ties still come out in the order of entries
+    let found: Vec<Vec<(T::Entry<'t>, i64)>> = chunks
+        .into_par_iter()
+        .map(|chunk| match options.limit {
+            Some(limit) => top_k(matches(chunk), limit),
+            None => matches(chunk).collect(),
+        })
+        .collect();
+    drop(span);
+    let _span = crate::profile::span("rank");
+    Ok(match options.limit {
+        Some(limit) => top_k(found.into_iter().flatten(), limit),
+        None => {
+            let mut results: Vec<_> = found.into_iter().flatten().collect();
+            results.par_sort_by_key(|&(_, score)| Reverse(score));
+            results
+        }
+    })
+}
+
+/// Walk `root` and hand every entry matching `query` to `on_match`, with its
+/// score, as soon as it's found: `find --no-sort` without an index. Entries
+/// come in no particular order. Stops after `options.limit` matches or once
+/// `on_match` breaks.
+///
+/// Filtering by project, owner or repository needs an index, so it's refused.
+pub fn stream_matches(
+    root: &Path,
+    query: &str,
+    options: &SearchOptions,
+    scan_options: &ScanOptions,
+    mut on_match: impl FnMut(FileEntry, i64) -> ControlFlow<()>,
+) -> Result<()> {
+    let filters = &options.filters;
+    if !filters.projects.is_empty() || !filters.owners.is_empty() || !filters.repos.is_empty() {
+        bail!("Filtering by project, owner or repository needs an index; run `ss scan` first");
+    }
+    // Compiles the filters against the root the way a scan of it would
+    let index = Index::from_scan(ScanResult {
+        root: root.to_path_buf(),
+        file_count: 0,
+        dir_count: 0,
+        total_size: 0,
+        elapsed_ms: 0,
+        files: Vec::new(),
+        options: scan_options.clone(),
+        partial: false,
+    });
+    let options = &*options.for_index(&index.meta);
+    let query = Query::parse(query)?;
+    let filter = index.entry_filter(&options.filters)?;
+    let matcher = query::Matcher::new(&query, options)?;
+    let mut remaining = options.limit.unwrap_or(usize::MAX);
+    if remaining == 0 {
+        return Ok(());
+    }
+    scanner::scan_streaming(root, scan_options, |entry| {
+        if !filter.matches(&entry) {
+            return ControlFlow::Continue(());
+        }
+        let Some(score) = matcher.score(&entry, root) else {
+            return ControlFlow::Continue(());
+        };
+        remaining -= 1;
+        on_match(entry, score)?;
+        match remaining {
+            0 => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        }
+    })
+}
+
+/// The `k` best-scoring of `items`, best first and ties in the order they
+/// came, like sorting them all and keeping the first `k`, but holding no
+/// more than `k` at a time
+fn top_k<T>(items: impl Iterator<Item = (T, i64)>, k: usize) -> Vec<(T, i64)> {
+    /// A kept item, ordered so the heap's greatest is the worst of them
+    struct Kept<T> {
+        score: i64,
+        seq: usize,
+        item: T,
+    }
+    impl<T> Kept<T> {
+        fn key(&self) -> (Reverse<i64>, usize) {
+            (Reverse(self.score), self.seq)
+        }
+    }
+    impl<T> PartialEq for Kept<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.key() == other.key()
+        }
+    }
+    impl<T> Eq for Kept<T> {}
+    impl<T> PartialOrd for Kept<T> {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl<T> Ord for Kept<T> {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.key().cmp(&other.key())
+        }
+    }
+
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(k.min(4096) + 1);
+    for (seq, (item, score)) in items.enumerate() {
+        let kept = Kept { score, seq, item };
+        if heap.len() < k {
+            heap.push(kept);
+        } else if heap.peek().is_some_and(|worst| score > worst.score) {
+            heap.pop();
+            heap.push(kept);
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|kept| (kept.item, kept.score))
+        .collect()
+}
+
+/// A uniform random sample of `sample.size` items, drawn in one pass
+/// without holding the rest (Algorithm R)
+fn reservoir<T>(items: impl Iterator<Item = T>, sample: Sample) -> Vec<T> {
+    let mut rng = Rng::new(sample.seed);
+    let mut kept = Vec::with_capacity(sample.size.min(4096));
+    for (seen, item) in items.enumerate() {
+        if seen < sample.size {
+            kept.push(item);
+        } else {
+            let slot = rng.below(seen as u64 + 1) as usize;
+            if slot < sample.size {
+                kept[slot] = item;
+            }
+        }
+    }
+    kept
+}
+
+/// Path of `entry` relative to `root`, with `/` separators on every platform
+pub fn relative_path(root: &Path, entry: &FileEntry) -> String {
+    relative(root, &entry.path)
+}
+
+pub(crate) fn relative(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let relative = relative.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '/' {
+        relative.into_owned()
+    } else {
+        relative.replace(std::path::MAIN_SEPARATOR, "/")
+    }
+}
+
+/// Byte ranges of `text` that the terms of `query` fuzzy-match under
+/// `options`, with adjacent characters merged into one range. Empty when
+/// none of them match, even transliterated, as names of an index scanned
+/// with `--transliterate` may only match.
+pub fn match_spans(text: &str, query: &str, options: &SearchOptions) -> Vec<Range<usize>> {
+    let spans = spans_under(text, query, options);
+    if spans.is_empty() && !options.transliterate && !options.exact_accents {
+        let latin = SearchOptions {
+            transliterate: true,
+            ..options.clone()
+        };
+        return spans_under(text, query, &latin);
+    }
+    spans
+}
+
+fn spans_under(text: &str, query: &str, options: &SearchOptions) -> Vec<Range<usize>> {
+    let Ok(query) = Query::parse(query) else {
+        return Vec::new();
+    };
+    let mut spans: Vec<Range<usize>> = query
+        .terms()
+        .into_iter()
+        .flat_map(|term| term_spans(text, term, options))
+        .collect();
+    spans.sort_by_key(|span| span.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.end >= span.start => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Byte ranges of `text` that a single term fuzzy-matches
+fn term_spans(text: &str, term: &str, options: &SearchOptions) -> Vec<Range<usize>> {
+    let query = options.normalize(term);
+    // The matcher reports positions of characters in the normalized text
+    let (normalized, origins) = normalize::mapped(text, |part| options.normalize(part));
+    let Ok(matcher) = options.engine.matcher(&query, options.case) else {
+        return Vec::new();
+    };
+    let Some((_, indices)) = matcher.fuzzy_indices(&normalized, &query) else {
+        return Vec::new();
+    };
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for index in indices {
+        let Some(origin) = origins.get(index) else {
+            continue;
+        };
+        match spans.last_mut() {
+            Some(last) if last.end >= origin.start => last.end = last.end.max(origin.end),
+            _ => spans.push(origin.clone()),
+        }
+    }
+    spans
+}
+
+impl Index {
+    /// Find files matching `query`, a [`Query`] whose terms fuzzy-match
+    /// their names, best matches first.
+    ///
+    /// Fails if the query or a glob in `options` is invalid or a project or
+    /// owner is unknown.
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult<'_>>> {
+        let options = &*options.for_index(&self.meta);
+        let query = Query::parse(query)?;
+        let filter = self.entry_filter(&options.filters)?;
+        let results = rank(
+            self.entries.as_slice(),
+            &self.meta.root,
+            &query,
+            options,
+            &filter,
+        )?;
+        Ok(results
+            .into_iter()
+            .map(|(entry, score)| SearchResult { entry, score })
+            .collect())
+    }
+
+    /// Compile `filters`, resolving project and owner names against this index
+    /// Validate a digest given to `--hash`, which may be abbreviated
+    fn hash_prefix(&self, digest: &str) -> Result<String> {
+        let digest = digest.to_lowercase();
+        if digest.len() < 4 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid hash '{}': expected at least 4 hex digits", digest);
+        }
+        if !self.meta.scan_options.hash {
+            bail!("This index has no content hashes; rebuild it with `ss scan --hash`");
+        }
+        Ok(digest)
+    }
+
+    pub fn entry_filter(&self, filters: &Filters) -> Result<EntryFilter> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        let (mut has_names, mut has_paths) = (false, false);
+        for pattern in &filters.globs {
+            if pattern.contains('/') {
+                let anchored = if pattern.starts_with('/') {
+                    pattern.clone()
+                } else {
+                    format!("**/{}", pattern)
+                };
+                paths.add(compile_glob(&anchored, pattern)?);
+                has_paths = true;
+            } else {
+                names.add(compile_glob(pattern, pattern)?);
+                has_names = true;
+            }
+        }
+        Ok(EntryFilter {
+            names: has_names
+                .then(|| names.build())
+                .transpose()
+                .map_err(anyhow::Error::new)?,
+            paths: has_paths
+                .then(|| paths.build())
+                .transpose()
+                .map_err(anyhow::Error::new)?,
+            extensions: filters
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            projects: match filters.projects.is_empty() {
+                true => None,
+                false => Some(self.project_ids(&filters.projects)?),
+            },
+            owner_sets: match filters.owners.is_empty() {
+                true => None,
+                false => Some(self.owner_set_ids(&filters.owners)?),
+            },
+            sizes: filters.sizes.clone(),
+            modified_after: filters.modified_after,
+            modified_before: filters.modified_before,
+            include_vendored: filters.include_vendored,
+            hash: match &filters.hash {
+                Some(digest) => Some(self.hash_prefix(digest)?),
+                None => None,
+            },
+            uids: match filters.users.is_empty() {
+                true => None,
+                false => Some(
+                    filters
+                        .users
+                        .iter()
+                        .map(|user| perms::user_id(user))
+                        .collect::<anyhow::Result<_>>()?,
+                ),
+            },
+            gids: match filters.groups.is_empty() {
+                true => None,
+                false => Some(
+                    filters
+                        .groups
+                        .iter()
+                        .map(|group| perms::group_id(group))
+                        .collect::<anyhow::Result<_>>()?,
+                ),
+            },
+            no_user: filters.no_user,
+            no_group: filters.no_group,
+            perms: filters.perms.clone(),
+            readonly: filters.readonly,
+            types: filters.types.clone(),
+            file_types: TypeMatcher::new(&filters.file_types, &filters.mimes)?,
+            git_tracked: filters.git_tracked,
+            git_modified: filters.git_modified,
+            repos: match filters.repos.is_empty() {
+                true => None,
+                false => Some(self.repo_ids(&filters.repos)?),
+            },
+            query: match &filters.query {
+                Some(query) => Some((
+                    query::Matcher::new(&Query::parse(query)?, &SearchOptions::default())?,
+                    self.meta.root.clone(),
+                )),
+                None => None,
+            },
+            tagged: filters.tagged.clone(),
+        })
+    }
+
+    /// Ids of the projects named by `names`, matching either the project
+    /// name or its root relative to the index root
+    pub fn project_ids(&self, names: &[String]) -> Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for wanted in names {
+            let before = ids.len();
+            for (id, project) in self.projects.iter().enumerate() {
+                let relative = project.root.strip_prefix(&self.meta.root).ok();
+                if project.name == *wanted || relative.is_some_and(|r| r == Path::new(wanted)) {
+                    ids.push(id as u32);
+                }
+            }
+            if ids.len() == before {
+                let known = self.projects.iter().map(|p| p.name.as_str());
+                match suggest::closest(wanted, known) {
+                    Some(name) => {
+                        bail!("Unknown project '{}'. Did you mean '{}'?", wanted, name)
+                    }
+                    None => bail!(
+                        "Unknown project '{}' (run `ss projects` to list them)",
+                        wanted
+                    ),
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Ids of the repositories named by `names`, matching their work tree's
+    /// path, that path relative to the index root or its directory name
+    pub fn repo_ids(&self, names: &[String]) -> Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for wanted in names {
+            let before = ids.len();
+            for (id, repo) in self.repos.iter().enumerate() {
+                let relative = repo.strip_prefix(&self.meta.root).ok();
+                if repo == Path::new(wanted)
+                    || relative.is_some_and(|r| r == Path::new(wanted))
+                    || repo.file_name().is_some_and(|name| name == wanted.as_str())
+                {
+                    ids.push(id as u32);
+                }
+            }
+            if ids.len() == before {
+                match self.repos.is_empty() {
+                    true => bail!(
+                        "Unknown repository '{}': no git repository was indexed",
+                        wanted
+                    ),
+                    false => bail!("Unknown repository '{}'", wanted),
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Ids of the owner lists that include any of `owners`
+    pub fn owner_set_ids(&self, owners: &[String]) -> Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for wanted in owners {
+            let before = ids.len();
+            for (id, set) in self.owner_sets.iter().enumerate() {
+                if set.iter().any(|owner| owner.eq_ignore_ascii_case(wanted)) {
+                    ids.push(id as u32);
+                }
+            }
+            if ids.len() == before {
+                let mut known: Vec<&str> = self
+                    .owner_sets
+                    .iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect();
+                known.sort_unstable();
+                known.dedup();
+                match suggest::closest(wanted, known) {
+                    Some(owner) => {
+                        bail!("Unknown owner '{}'. Did you mean '{}'?", wanted, owner)
+                    }
+                    None if self.owner_sets.is_empty() => {
+                        bail!("Unknown owner '{}': no CODEOWNERS file was indexed", wanted)
+                    }
+                    None => bail!("Unknown owner '{}'", wanted),
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Entries that may contain matches for `query`.
+    ///
+    /// Returns `None` when no content index is loaded or the pattern can't
+    /// be narrowed, in which case every entry has to be searched. With
+    /// [`GrepOptions::doc_lang`] only documents in that language are
+    /// candidates, and none without a content index.
+    pub fn grep_candidates(&self, query: &str, options: &GrepOptions) -> Option<Vec<&FileEntry>> {
+        let source = grep::pattern_source(query, options);
+        let narrowed = self.content.as_ref().and_then(|content| {
+            match grep::identifier_words(query, options) {
+                Some(words) => content.identifier_candidates(&words),
+                None => {
+                    content.selective_candidates(&source, options.ignore_case, self.file_count())
+                }
+            }
+        });
+        let ids = match &options.doc_lang {
+            Some(language) => {
+                let mut ids = self.content.as_ref().map_or(Vec::new(), |content| {
+                    content.with_language(language).to_vec()
+                });
+                if let Some(narrowed) = narrowed {
+                    ids.retain(|id| narrowed.binary_search(id).is_ok());
+                }
+                ids
+            }
+            None => narrowed?,
+        };
+        Some(
+            ids.into_iter()
+                .map(|id| &self.entries[id as usize])
+                .collect(),
+        )
+    }
+
+    /// Search the contents of every indexed file passing `filters` for `query`.
+    ///
+    /// Uses the content index to skip files when one is loaded.
+    pub fn grep(
+        &self,
+        query: &str,
+        options: &GrepOptions,
+        filters: &Filters,
+    ) -> Result<Vec<FileMatches>> {
+        let pattern = grep::build_pattern(query, options)?;
+        let filter = self.entry_filter(filters)?;
+        let candidates = self
+            .grep_candidates(query, options)
+            .unwrap_or_else(|| self.files().collect());
+        let paths: Vec<PathBuf> = candidates
+            .into_iter()
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| entry.path.clone())
+            .collect();
+        Ok(grep::search_files(&paths, &pattern, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::NameStyle;
+    use crate::scanner::{self, ScanOptions};
+    use crate::testing::IndexBuilder;
+    use std::fs;
+
+    fn build_index(files: &[(&str, &str)]) -> (tempfile::TempDir, Index) {
+        let root = tempfile::tempdir().unwrap();
+        for (name, body) in files {
+            fs::write(root.path().join(name), body).unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        (root, index)
+    }
+
+    #[test]
+    fn test_search_ranks_and_limits() {
+        let (_root, index) =
+            build_index(&[("budget.xlsx", ""), ("bud_get.txt", ""), ("notes.md", "")]);
+        let results = index.search("budget", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].entry.name, "budget.xlsx");
+        assert!(results[0].score > results[1].score);
+
+        let options = SearchOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(index.search("budget", &options).unwrap().len(), 1);
+        assert!(
+            index
+                .search("zzz", &SearchOptions::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_search_samples() {
+        let index = IndexBuilder::new("/work")
+            .random_files(500, NameStyle::Mixed)
+            .build();
+        let sampled = |seed| {
+            let options = SearchOptions {
+                sample: Some(Sample { size: 20, seed }),
+                ..Default::default()
+            };
+            let results = index.search("", &options).unwrap();
+            let mut paths: Vec<&Path> = results.iter().map(|r| r.entry.path.as_path()).collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(sampled(1).len(), 20);
+        assert_eq!(sampled(1), sampled(1));
+        assert_ne!(sampled(1), sampled(2));
+
+        // Every match has the same chance of being drawn
+        let mut drawn = vec![0; 10];
+        for seed in 0..2000 {
+            let picked = reservoir(0..10, Sample { size: 3, seed });
+            for item in picked {
+                drawn[item] += 1;
+            }
+        }
+        assert!(
+            drawn.iter().all(|&count| (500..700).contains(&count)),
+            "{:?}",
+            drawn
+        );
+        assert_eq!(reservoir(0..2, Sample { size: 5, seed: 0 }), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_top_k_matches_a_full_sort() {
+        let scores = [5, 1, 9, 5, 7, 9, 0, 5];
+        let items = || scores.iter().copied().enumerate();
+        for k in 0..=scores.len() + 1 {
+            let mut sorted: Vec<(usize, i64)> = items().collect();
+            sorted.sort_by_key(|&(_, score)| Reverse(score));
+            sorted.truncate(k);
+            assert_eq!(top_k(items(), k), sorted, "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn test_rank_in_chunks_matches_a_single_pass() {
+        let index = IndexBuilder::new("/work")
+            .random_files(3 * RANK_CHUNK, NameStyle::Mixed)
+            .build();
+        let query = Query::parse("e").unwrap();
+        let filter = index.entry_filter(&Filters::default()).unwrap();
+        let matcher = query::Matcher::new(&query, &SearchOptions::default()).unwrap();
+        let mut expected: Vec<(&FileEntry, i64)> = index
+            .entries
+            .iter()
+            .filter_map(|entry| Some((entry, matcher.score(entry, &index.meta.root)?)))
+            .collect();
+        expected.sort_by_key(|&(_, score)| Reverse(score));
+        assert!(expected.len() > RANK_CHUNK);
+        for limit in [None, Some(10), Some(expected.len() + 1)] {
+            let options = SearchOptions {
+                limit,
+                ..Default::default()
+            };
+            let ranked = rank(
+                index.entries.as_slice(),
+                &index.meta.root,
+                &query,
+                &options,
+                &filter,
+            )
+            .unwrap();
+            let expected = &expected[..limit.unwrap_or(usize::MAX).min(expected.len())];
+            assert_eq!(ranked, expected, "limit {:?}", limit);
+        }
+    }
+
+    #[test]
+    fn test_stream_matches_without_an_index() {
+        let (root, index) =
+            build_index(&[("budget.xlsx", ""), ("bud_get.txt", ""), ("notes.md", "")]);
+        let stream = |options: &SearchOptions| {
+            let mut found = Vec::new();
+            let scan = ScanOptions::default();
+            stream_matches(
+                &index.meta.root,
+                "budget",
+                options,
+                &scan,
+                |entry, score| {
+                    found.push((entry.name, score));
+                    ControlFlow::Continue(())
+                },
+            )
+            .unwrap();
+            found.sort();
+            found
+        };
+        let ranked = index.search("budget", &SearchOptions::default()).unwrap();
+        let mut expected: Vec<(String, i64)> = ranked
+            .iter()
+            .map(|r| (r.entry.name.clone(), r.score))
+            .collect();
+        expected.sort();
+        assert_eq!(stream(&SearchOptions::default()), expected);
+
+        let limited = SearchOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(stream(&limited).len(), 1);
+        let by_project = SearchOptions {
+            filters: Filters {
+                projects: vec!["app".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let scan = ScanOptions::default();
+        let refused = stream_matches(root.path(), "x", &by_project, &scan, |_, _| {
+            ControlFlow::Continue(())
+        });
+        assert!(refused.is_err());
+    }
+
+    #[test]
+    fn test_project_filter() {
+        let root = tempfile::tempdir().unwrap();
+        for (name, body) in [
+            ("app/Cargo.toml", "[package]\nname = \"app\"\n"),
+            ("app/src/main.rs", ""),
+            ("lib/package.json", r#"{"name": "shared"}"#),
+            ("lib/main.js", ""),
+            ("main.txt", ""),
+        ] {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let search = |projects: &[&str]| {
+            let options = SearchOptions {
+                filters: Filters {
+                    projects: projects.iter().map(|p| p.to_string()).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            index.search("main", &options).map(|results| {
+                let mut names: Vec<String> = results.iter().map(|r| r.entry.name.clone()).collect();
+                names.sort();
+                names
+            })
+        };
+        assert_eq!(search(&[]).unwrap().len(), 3);
+        assert_eq!(search(&["app"]).unwrap(), vec!["main.rs"]);
+        assert_eq!(search(&["lib", "app"]).unwrap(), vec!["main.js", "main.rs"]);
+        let err = search(&["shraed"]).unwrap_err();
+        assert!(
+            err.to_string().contains("Did you mean 'shared'?"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_glob_and_extension_filters() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("src/bin")).unwrap();
+        fs::create_dir_all(root.path().join("docs")).unwrap();
+        for name in [
+            "src/main.rs",
+            "src/bin/tool.rs",
+            "Cargo.toml",
+            "docs/main.md",
+        ] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let names = |filters: Filters| -> Vec<String> {
+            let options = SearchOptions {
+                filters,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("", &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let by_glob = Filters {
+            globs: vec!["*.rs".into()],
+            ..Default::default()
+        };
+        assert_eq!(names(by_glob), vec!["main.rs", "tool.rs"]);
+
+        let by_path = Filters {
+            globs: vec!["src/bin/*".into(), "*.toml".into()],
+            ..Default::default()
+        };
+        assert_eq!(names(by_path), vec!["Cargo.toml", "tool.rs"]);
+
+        let by_ext = Filters {
+            extensions: vec![".MD".into(), "toml".into()],
+            ..Default::default()
+        };
+        assert_eq!(names(by_ext), vec!["Cargo.toml", "main.md"]);
+
+        let invalid = SearchOptions {
+            filters: Filters {
+                globs: vec!["[z-a]".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(index.search("main", &invalid).is_err());
+    }
+
+    #[test]
+    fn test_grep_uses_content_index() {
+        let (_root, mut index) = build_index(&[("hit.txt", "needle here"), ("miss.txt", "hay")]);
+        let options = GrepOptions::default();
+        assert_eq!(index.grep_candidates("needle", &options), None);
+        assert_eq!(
+            index
+                .grep("needle", &options, &Filters::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        index
+            .build_content(crate::trigram::DEFAULT_MAX_CONTENT_SIZE)
+            .unwrap();
+        let candidates = index.grep_candidates("needle", &options).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].name, "hit.txt");
+        let all = Filters::default();
+        assert_eq!(index.grep("needle", &options, &all).unwrap().len(), 1);
+        assert!(index.grep("(", &options, &all).is_err());
+    }
+
+    #[test]
+    fn test_grep_doc_lang() {
+        let english = "The meeting notes are here. It was a bright cold day in April, \
+                       and the clocks were striking thirteen.";
+        let japanese = "会議のメモはここにあります。吾輩は猫である。名前はまだ無い。\
+                        どこで生れたかとんと見当がつかぬ。";
+        let (_root, mut index) = build_index(&[
+            ("notes.md", english),
+            ("memo.txt", japanese),
+            ("main.rs", japanese),
+        ]);
+        let options = GrepOptions {
+            doc_lang: Some("ja".to_string()),
+            ..Default::default()
+        };
+        // Without a content index no document has a language
+        assert_eq!(index.grep_candidates("会議", &options), Some(Vec::new()));
+
+        index
+            .build_content(crate::trigram::DEFAULT_MAX_CONTENT_SIZE)
+            .unwrap();
+        let content = index.content.as_ref().unwrap();
+        assert_eq!(content.language_counts(), vec![("en", 1), ("ja", 1)]);
+        let names = |candidates: Vec<&FileEntry>| -> Vec<String> {
+            candidates.iter().map(|entry| entry.name.clone()).collect()
+        };
+        // Code files aren't documents, even when they're written in prose
+        assert_eq!(
+            names(index.grep_candidates("会議", &options).unwrap()),
+            ["memo.txt"]
+        );
+        assert_eq!(
+            names(index.grep_candidates(".", &options).unwrap()),
+            ["memo.txt"]
+        );
+        assert!(
+            index
+                .grep_candidates("meeting", &options)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_owner_filter() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("infra")).unwrap();
+        for (name, body) in [
+            (
+                "CODEOWNERS",
+                "* @acme/everyone\n/infra/ @acme/Platform-Team\n",
+            ),
+            ("infra/deploy.sh", "needle"),
+            ("deploy.md", "needle"),
+        ] {
+            fs::write(root.path().join(name), body).unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let filters = |owner: &str| Filters {
+            owners: vec![owner.to_string()],
+            ..Default::default()
+        };
+        let options = SearchOptions {
+            filters: filters("@acme/platform-team"),
+            ..Default::default()
+        };
+        let results = index.search("deploy", &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry.name, "deploy.sh");
+        assert_eq!(index.owners_of(results[0].entry), ["@acme/Platform-Team"]);
+
+        let grep_options = GrepOptions::default();
+        let matches = index
+            .grep("needle", &grep_options, &filters("@acme/everyone"))
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].path.ends_with("deploy.md"));
+
+        let err = index.search(
+            "deploy",
+            &SearchOptions {
+                filters: filters("@acme/platfrom-team"),
+                ..Default::default()
+            },
+        );
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("Did you mean '@acme/Platform-Team'?")
+        );
+    }
+
+    #[test]
+    fn test_size_and_date_filters() {
+        use crate::units::{parse_size_filter, parse_time};
+        use std::time::Duration;
+
+        let (_root, mut index) = build_index(&[
+            ("small.log", "x"),
+            ("large.log", &"x".repeat(4096)),
+            ("old.log", "x"),
+        ]);
+        let now = SystemTime::now();
+        for entry in &mut index.entries {
+            entry.modified = Some(match entry.name.as_str() {
+                "old.log" => parse_time("2020-06-01").unwrap(),
+                _ => now - Duration::from_secs(60),
+            });
+        }
+        let names = |filters: Filters| -> Vec<String> {
+            let options = SearchOptions {
+                filters,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("log", &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let larger = Filters {
+            sizes: vec![parse_size_filter("+1k").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(names(larger), vec!["large.log"]);
+        let range = Filters {
+            sizes: vec![
+                parse_size_filter("+1").unwrap(),
+                parse_size_filter("-1k").unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(names(range), vec!["old.log", "small.log"]);
+
+        let recent = Filters {
+            modified_after: Some(parse_time("7d").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(names(recent), vec!["large.log", "small.log"]);
+        let before = Filters {
+            modified_before: Some(parse_time("2024-01-01").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(names(before), vec!["old.log"]);
+    }
+
+    #[test]
+    fn test_vendored_entries_are_opt_in() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("vendor/dep")).unwrap();
+        fs::write(root.path().join("vendor/dep/util.rs"), "").unwrap();
+        fs::write(root.path().join("util.rs"), "").unwrap();
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+
+        let results = index.search("util", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].entry.vendored);
+        let options = SearchOptions {
+            filters: Filters {
+                include_vendored: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(index.search("util", &options).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_directories_and_entry_types() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("reports/2024")).unwrap();
+        fs::write(root.path().join("reports/2024/report.txt"), "report").unwrap();
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        assert_eq!(index.file_count(), 1);
+
+        let names = |types: Vec<EntryType>| -> Vec<String> {
+            let options = SearchOptions {
+                filters: Filters {
+                    types,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("report", &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(Vec::new()), vec!["report.txt", "reports"]);
+        assert_eq!(names(vec![EntryType::Directory]), vec!["reports"]);
+        assert_eq!(names(vec![EntryType::File]), vec!["report.txt"]);
+        assert!(names(vec![EntryType::Symlink]).is_empty());
+
+        // Directories have no contents to grep
+        let matches = index
+            .grep("report", &GrepOptions::default(), &Filters::default())
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_owner_and_permission_filters() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let root = tempfile::tempdir().unwrap();
+        for (name, mode) in [
+            ("open.log", 0o666),
+            ("locked.log", 0o444),
+            ("plain.log", 0o644),
+        ] {
+            let path = root.path().join(name);
+            fs::write(&path, "x").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let uid = fs::metadata(root.path()).unwrap().uid();
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        assert_eq!(index.entries[0].attributes.uid, Some(uid));
+
+        let names = |filters: Filters| -> Vec<String> {
+            let options = SearchOptions {
+                filters,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("log", &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        let world_writable = Filters {
+            perms: vec![PermFilter::AllOf(0o002)],
+            ..Default::default()
+        };
+        assert_eq!(names(world_writable), ["open.log"]);
+        let readonly = Filters {
+            readonly: true,
+            ..Default::default()
+        };
+        assert_eq!(names(readonly), ["locked.log"]);
+        let exact = Filters {
+            perms: vec![PermFilter::Exactly(0o644)],
+            users: vec![uid.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(names(exact), ["plain.log"]);
+        let someone_else = Filters {
+            users: vec![(uid + 1).to_string()],
+            ..Default::default()
+        };
+        assert!(names(someone_else).is_empty());
+    }
+
+    #[test]
+    fn test_file_type_and_mime_filters() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("photo.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        fs::write(
+            root.path().join("photo-export"),
+            b"\xff\xd8\xff\xe0\0\x10JFIF",
+        )
+        .unwrap();
+        fs::write(root.path().join("photo.rs"), "fn main() {}").unwrap();
+        let scan = |sniff_mime| {
+            let options = ScanOptions {
+                sniff_mime,
+                ..Default::default()
+            };
+            let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+            Index::from_scan(scan)
+        };
+        let names = |index: &Index, file_types: &[&str], mimes: &[&str]| -> Vec<String> {
+            let options = SearchOptions {
+                filters: Filters {
+                    file_types: file_types.iter().map(|t| t.to_string()).collect(),
+                    mimes: mimes.iter().map(|m| m.to_string()).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search("photo", &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let index = scan(false);
+        assert_eq!(names(&index, &["code"], &[]), ["photo.rs"]);
+        assert_eq!(names(&index, &["image"], &[]), ["photo.png"]);
+        assert_eq!(names(&index, &[], &["image/*"]), ["photo.png"]);
+        // Only sniffing tells what the file without an extension holds
+        let index = scan(true);
+        assert_eq!(
+            names(&index, &["image"], &[]),
+            ["photo-export", "photo.png"]
+        );
+        assert_eq!(names(&index, &[], &["image/jpeg"]), ["photo-export"]);
+        assert!(
+            index
+                .entry_filter(&Filters {
+                    file_types: vec!["imgae".to_string()],
+                    ..Default::default()
+                })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_match_spans() {
+        let smart = SearchOptions::default();
+        assert_eq!(match_spans("scanner.rs", "scan", &smart), vec![0..4]);
+        assert_eq!(
+            match_spans("src/main.rs", "smr", &smart),
+            vec![0..1, 4..5, 9..10]
+        );
+        // Multi-byte characters are reported as byte ranges
+        assert_eq!(match_spans("résumé.pdf", "sum", &smart), vec![3..6]);
+        assert!(match_spans("notes.md", "xyz", &smart).is_empty());
+        let insensitive = SearchOptions {
+            case: CaseMode::Insensitive,
+            ..Default::default()
+        };
+        assert_eq!(match_spans("readme.md", "README", &insensitive), vec![0..6]);
+        // Folded accents are highlighted whole, combining marks included
+        assert_eq!(match_spans("résumé", "resume", &smart), vec![0..8]);
+        assert_eq!(
+            match_spans("re\u{301}sume\u{301}", "és", &smart),
+            vec![1..5]
+        );
+    }
+
+    #[test]
+    fn test_accent_folding() {
+        let (_root, index) = build_index(&[("résumé.pdf", ""), ("re\u{301}sume\u{301}.txt", "")]);
+        let found = |query: &str, exact_accents: bool| -> usize {
+            let options = SearchOptions {
+                exact_accents,
+                ..Default::default()
+            };
+            index.search(query, &options).unwrap().len()
+        };
+        assert!(index.entries.iter().all(|entry| {
+            entry
+                .folded_name
+                .as_deref()
+                .is_some_and(|name| name.starts_with("resume."))
+        }));
+        assert_eq!(found("resume", false), 2);
+        assert_eq!(found("résumé", false), 2);
+        assert_eq!(found("resume", true), 0);
+        // Both spellings of é are the same letter once normalized
+        assert_eq!(found("re\u{301}sume\u{301}", true), 2);
+        assert_eq!(found("résumé", true), 2);
+    }
+
+    #[test]
+    fn test_transliterated_names() {
+        let root = tempfile::tempdir().unwrap();
+        for name in ["Москва-план.pdf", "moskva.txt", "Αθήνα.doc"] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let options = scanner::ScanOptions {
+            transliterate: true,
+            ..Default::default()
+        };
+        let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+        let index = Index::from_scan(scan);
+        let names = |query: &str| -> Vec<String> {
+            let mut names: Vec<String> = index
+                .search(query, &SearchOptions::default())
+                .unwrap()
+                .iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names("moskva"), ["moskva.txt", "Москва-план.pdf"]);
+        assert_eq!(names("москва"), ["moskva.txt", "Москва-план.pdf"]);
+        assert_eq!(names("athina"), ["Αθήνα.doc"]);
+        // Highlighting finds the letters the Latin query matched
+        let smart = SearchOptions::default();
+        assert_eq!(match_spans("Москва-план.pdf", "mosk", &smart), vec![0..8]);
+
+        let (_root, plain) = build_index(&[("Москва.pdf", "")]);
+        assert!(plain.search("moskva", &smart).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cjk_names() {
+        let (_root, index) = build_index(&[
+            ("东京旅行.jpg", ""),
+            ("东南京路.txt", ""),
+            ("会议_记录.docx", ""),
+        ]);
+        let names = |query: &str| -> Vec<String> {
+            index
+                .search(query, &SearchOptions::default())
+                .unwrap()
+                .iter()
+                .map(|result| result.entry.name.clone())
+                .collect()
+        };
+        // Not every name holding 东 and then 京
+        assert_eq!(names("东京"), ["东京旅行.jpg"]);
+        assert_eq!(names("会议记录"), ["会议_记录.docx"]);
+        assert_eq!(
+            match_spans("会议_记录.docx", "会议记录", &SearchOptions::default()),
+            vec![0..6, 7..13]
+        );
+
+        #[cfg(feature = "romanize")]
+        {
+            let root = tempfile::tempdir().unwrap();
+            for name in ["北京.txt", "すし.md"] {
+                fs::write(root.path().join(name), "").unwrap();
+            }
+            let options = scanner::ScanOptions {
+                transliterate: true,
+                ..Default::default()
+            };
+            let scan = scanner::scan_directory_with(root.path(), &options, None, None).unwrap();
+            let index = Index::from_scan(scan);
+            let found = |query: &str| {
+                index
+                    .search(query, &SearchOptions::default())
+                    .unwrap()
+                    .len()
+            };
+            assert_eq!(found("beijing"), 1);
+            assert_eq!(found("北京"), 1);
+            assert_eq!(found("sushi"), 1);
+        }
+    }
+
+    #[test]
+    fn test_case_modes() {
+        assert!(CaseMode::Smart.ignores_case("budget"));
+        assert!(!CaseMode::Smart.ignores_case("Budget"));
+        assert!(CaseMode::Smart.ignores_case(r"\w+\S"));
+        assert!(!CaseMode::Smart.ignores_case(r"\\Users"));
+        assert!(!CaseMode::Sensitive.ignores_case("budget"));
+        assert!(CaseMode::Insensitive.ignores_case("Budget"));
+
+        let (_root, index) = build_index(&[("Budget.xlsx", ""), ("budget.txt", "")]);
+        let found = |query: &str, case: CaseMode| -> Vec<String> {
+            let options = SearchOptions {
+                case,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = index
+                .search(query, &options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            found("budget", CaseMode::Smart),
+            vec!["Budget.xlsx", "budget.txt"]
+        );
+        assert_eq!(found("Budget", CaseMode::Smart), vec!["Budget.xlsx"]);
+        assert_eq!(found("budget", CaseMode::Sensitive), vec!["budget.txt"]);
+        assert_eq!(found("BUDGET", CaseMode::Insensitive).len(), 2);
+    }
+
+    #[test]
+    fn test_query_language() {
+        let (_root, index) = build_index(&[
+            ("report-2024.pdf", "final"),
+            ("report-2024-draft.pdf", ""),
+            ("report-2023.md", ""),
+            ("config.rs", ""),
+        ]);
+        let found = |query: &str| -> Vec<String> {
+            let mut names: Vec<String> = index
+                .search(query, &SearchOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|result| result.entry.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(found("report AND 2024 NOT draft"), vec!["report-2024.pdf"]);
+        assert_eq!(
+            found("report ext:pdf"),
+            vec!["report-2024-draft.pdf", "report-2024.pdf"]
+        );
+        assert_eq!(found("2023 OR conf"), vec!["config.rs", "report-2023.md"]);
+        assert_eq!(found("path:report size:>1"), vec!["report-2024.pdf"]);
+        assert!(
+            index
+                .search("report OR", &SearchOptions::default())
+                .is_err()
+        );
+
+        // The same language narrows grep through --where
+        let filters = Filters {
+            query: Some("ext:pdf NOT draft".to_string()),
+            ..Default::default()
+        };
+        let filter = index.entry_filter(&filters).unwrap();
+        let passing: Vec<&str> = index
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(passing, vec!["report-2024.pdf"]);
+        assert_eq!(
+            match_spans(
+                "report-2024.pdf",
+                "2024 rep NOT pdf",
+                &SearchOptions::default()
+            ),
+            vec![0..3, 7..11]
+        );
+    }
+
+    #[test]
+    fn test_full_path_matching() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::create_dir_all(root.path().join("docs")).unwrap();
+        for name in ["src/scanner.rs", "docs/scanning.md", "scratch.txt"] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let names = |query: &str, full_path: bool| -> Vec<String> {
+            let options = SearchOptions {
+                full_path,
+                ..Default::default()
+            };
+            index
+                .search(query, &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.entry.name.clone())
+                .collect()
+        };
+        // A `/` switches to path matching on its own
+        assert_eq!(names("src/scan", false), vec!["scanner.rs"]);
+        assert!(names("docsscan", false).is_empty());
+        assert_eq!(names("docsscan", true), vec!["scanning.md"]);
+    }
+
+    #[test]
+    fn test_ranking_weights() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("old/archive/2019")).unwrap();
+        for name in [
+            "old/archive/2019/notes.md",
+            "notes.md",
+            "stale.md",
+            "fresh.md",
+        ] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let year_ago = SystemTime::now() - std::time::Duration::from_secs(365 * 86_400);
+        for name in ["old/archive/2019/notes.md", "stale.md"] {
+            fs::File::options()
+                .write(true)
+                .open(root.path().join(name))
+                .unwrap()
+                .set_modified(year_ago)
+                .unwrap();
+        }
+        let index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        let scores = |query: &str, weights: RankWeights| -> Vec<(String, i64)> {
+            let options = SearchOptions {
+                weights,
+                ..Default::default()
+            };
+            index
+                .search(query, &options)
+                .unwrap()
+                .into_iter()
+                .map(|r| (relative_path(&index.meta.root, r.entry), r.score))
+                .collect()
+        };
+        // Identical names tie on the fuzzy score alone
+        let plain = scores("notes", RankWeights::none());
+        assert_eq!(plain.len(), 2);
+        assert_eq!(plain[0].1, plain[1].1);
+        // The recent, shallow one wins once recency and depth count
+        let weights = RankWeights::default();
+        let ranked = scores("notes", weights);
+        assert_eq!(ranked[0].0, "notes.md");
+        assert_eq!(ranked[0].1, plain[0].1 + weights.stem + weights.recency);
+        let old = plain[0].1 + weights.stem - 3 * weights.depth;
+        assert_eq!(ranked[1], ("old/archive/2019/notes.md".to_string(), old));
+
+        let ranked = scores("md", weights);
+        let position = |name: &str| ranked.iter().position(|(n, _)| n == name).unwrap();
+        assert!(position("fresh.md") < position("stale.md"));
+    }
+}
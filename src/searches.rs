@@ -0,0 +1,196 @@
+//! Searches remembered in the index directory: the latest `find` and `grep`
+//! command lines, for `ss history`, and named `find` arguments saved with
+//! `ss save-search`, for `find --saved`.
+
+use crate::exec;
+use crate::suggest;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File holding the searches inside the index directory
+pub const SEARCHES_FILE: &str = "searches.json";
+
+/// Command lines kept in the history; older ones are forgotten
+pub const MAX_RECENT: usize = 100;
+
+/// A search that was run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recent {
+    /// Arguments after the program name, starting with the subcommand
+    pub args: Vec<String>,
+    pub at: SystemTime,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Searches {
+    /// Most recent first, each command line once
+    #[serde(default)]
+    pub recent: Vec<Recent>,
+    /// `find` arguments, starting with the query, by the name they were
+    /// saved under
+    #[serde(default)]
+    pub saved: BTreeMap<String, Vec<String>>,
+}
+
+impl Searches {
+    pub fn path(index_dir: &Path) -> PathBuf {
+        index_dir.join(SEARCHES_FILE)
+    }
+
+    /// The searches of `index_dir`; none if it has never been searched
+    pub fn load(index_dir: &Path) -> Result<Self> {
+        let path = Self::path(index_dir);
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid search history: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the searches back to `index_dir`, replacing them atomically
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        let path = Self::path(index_dir);
+        // Searches running side by side each write a file of their own
+        let tmp = index_dir.join(format!("{}.{}.tmp", SEARCHES_FILE, std::process::id()));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to update {}", path.display()))
+    }
+
+    /// Remember that `args` ran at `now`; running a command line again
+    /// moves it to the front
+    pub fn record(&mut self, args: Vec<String>, now: SystemTime) {
+        self.recent.retain(|recent| recent.args != args);
+        self.recent.insert(0, Recent { args, at: now });
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    /// The arguments saved as `name`
+    pub fn saved(&self, name: &str) -> Result<&[String]> {
+        if let Some(args) = self.saved.get(name) {
+            return Ok(args);
+        }
+        match suggest::closest(name, self.saved.keys().map(String::as_str)) {
+            Some(known) => anyhow::bail!("No saved search '{}'. Did you mean '{}'?", name, known),
+            None => anyhow::bail!(
+                "No saved search '{}'; save one with `ss save-search {} <query>`",
+                name,
+                name
+            ),
+        }
+    }
+}
+
+/// `args` with `--saved NAME` replaced by the `saved` arguments
+pub fn splice_saved(args: &[OsString], saved: &[String]) -> Vec<OsString> {
+    let saved = saved.iter().map(OsString::from);
+    let mut spliced = Vec::with_capacity(args.len() + saved.len());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--saved" {
+            args.next();
+            spliced.extend(saved.clone());
+        } else if arg.to_string_lossy().starts_with("--saved=") {
+            spliced.extend(saved.clone());
+        } else {
+            spliced.push(arg.clone());
+        }
+    }
+    spliced
+}
+
+/// `args` written out the way a shell would split them again
+pub fn command_line(args: &[String]) -> String {
+    let plain = |arg: &String| {
+        !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_./:=,+@%^".contains(c))
+    };
+    let words: Vec<String> = args
+        .iter()
+        .map(|arg| match plain(arg) {
+            true => arg.clone(),
+            false => exec::quote(Path::new(arg)),
+        })
+        .collect();
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_record_and_save() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let mut searches = Searches::load(index_dir.path()).unwrap();
+        assert_eq!(searches, Searches::default());
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for i in 0..MAX_RECENT + 5 {
+            let query = format!("report{}", i);
+            searches.record(vec!["find".to_string(), query], start);
+        }
+        searches.record(args("find report3"), start + Duration::from_secs(60));
+        assert_eq!(searches.recent.len(), MAX_RECENT);
+        assert_eq!(searches.recent[0].args, args("find report3"));
+        assert_eq!(searches.recent[1].args, args("find report104"));
+        assert!(
+            !searches.recent[1..]
+                .iter()
+                .any(|r| r.args == args("find report3"))
+        );
+
+        searches
+            .saved
+            .insert("logs".to_string(), args("ext:log --newer 7d"));
+        searches.save(index_dir.path()).unwrap();
+        let loaded = Searches::load(index_dir.path()).unwrap();
+        assert_eq!(loaded, searches);
+        assert_eq!(loaded.saved("logs").unwrap(), args("ext:log --newer 7d"));
+        let missing = loaded.saved("log").unwrap_err().to_string();
+        assert!(missing.contains("Did you mean 'logs'"), "{}", missing);
+    }
+
+    #[test]
+    fn test_splice_saved() {
+        let saved = args("ext:log --newer 7d");
+        let line = |text: &str| -> Vec<OsString> { text.split(' ').map(OsString::from).collect() };
+        assert_eq!(
+            splice_saved(&line("ss find --saved logs --limit 5"), &saved),
+            line("ss find ext:log --newer 7d --limit 5")
+        );
+        assert_eq!(
+            splice_saved(&line("ss find -i idx --saved=logs"), &saved),
+            line("ss find -i idx ext:log --newer 7d")
+        );
+    }
+
+    #[test]
+    fn test_command_line() {
+        let args = vec![
+            "find".to_string(),
+            "annual report".to_string(),
+            "--ext".to_string(),
+            "pdf,docx".to_string(),
+            "it's".to_string(),
+        ];
+        #[cfg(unix)]
+        assert_eq!(
+            command_line(&args),
+            r"find 'annual report' --ext pdf,docx 'it'\''s'"
+        );
+    }
+}
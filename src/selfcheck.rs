@@ -0,0 +1,257 @@
+//! A quick look at an index as it's opened, so a problem is reported with
+//! the command that fixes it rather than with whatever error reading the
+//! index ran into, plus `ss migrate` for indexes written by another
+//! version.
+
+use crate::error::Error;
+use crate::index::{self, INDEX_FORMAT_VERSION, Index, StoredOptions};
+use crate::scanner;
+use crate::searches;
+use crate::storage;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// A problem with an index and the command that fixes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: String,
+    /// A full `ss` command line
+    pub fix: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}\n   run: {}", self.problem, self.fix)
+    }
+}
+
+impl std::error::Error for Finding {}
+
+impl Finding {
+    fn new(problem: String, command: &[&str], index_dir: &Path) -> Self {
+        let mut args: Vec<String> = command.iter().map(|word| word.to_string()).collect();
+        args.push("-i".to_string());
+        args.push(index_dir.to_string_lossy().into_owned());
+        Finding {
+            problem,
+            fix: format!("ss {}", searches::command_line(&args)),
+        }
+    }
+
+    /// `index_dir` holds an index in another format version than this build's
+    pub fn format_mismatch(index_dir: &Path, version: u32) -> Self {
+        let writer = match version < INDEX_FORMAT_VERSION {
+            true => "an older",
+            false => "a newer",
+        };
+        let problem = format!(
+            "The index at {} was written by {} sonic-search (format version {}; this one reads {})",
+            index_dir.display(),
+            writer,
+            version,
+            INDEX_FORMAT_VERSION
+        );
+        Self::new(problem, &["migrate"], index_dir)
+    }
+
+    /// `index_dir` has generations, but the pointer to the current one is
+    /// gone or unreadable
+    pub fn pointer(index_dir: &Path) -> Self {
+        let problem = format!(
+            "The index at {} doesn't say which generation is current",
+            index_dir.display()
+        );
+        Self::new(problem, &["verify", "--repair"], index_dir)
+    }
+}
+
+/// Check that `generation` of `index_dir` can be read before reading it:
+/// its files are there and its header was written in this build's format.
+/// Only the first bytes of the header are read.
+pub fn check_generation(index_dir: &Path, generation: u64) -> Result<()> {
+    let path = Index::generation_path(index_dir, generation);
+    if !path.is_file() {
+        anyhow::bail!(
+            "Index generation {} is not available at {}",
+            generation,
+            path.display()
+        );
+    }
+    // A header that isn't one is damage, left to the quarantine
+    if let Some((version, _)) = read_preamble(&path)?
+        && version != INDEX_FORMAT_VERSION
+    {
+        return Err(Finding::format_mismatch(index_dir, version).into());
+    }
+    let entries = Index::entries_path(index_dir, generation);
+    if !entries.is_file() && !index_dir.join(storage::SQLITE_FILE).is_file() {
+        let problem = format!(
+            "The entries of generation {} of the index at {} are missing",
+            generation,
+            index_dir.display()
+        );
+        return Err(Finding::new(problem, &["verify", "--repair"], index_dir).into());
+    }
+    Ok(())
+}
+
+/// The format version and flags at the start of the header at `path`, or
+/// `None` if it doesn't start like a header
+fn read_preamble(path: &Path) -> Result<Option<(u32, u32)>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut preamble = [0u8; 12];
+    let mut reader = BufReader::new(file);
+    if reader.read_exact(&mut preamble).is_err() || &preamble[..4] != index::INDEX_MAGIC {
+        return Ok(None);
+    }
+    let word = |at: usize| u32::from_le_bytes(preamble[at..at + 4].try_into().unwrap());
+    Ok(Some((word(4), word(8))))
+}
+
+/// The root recorded in the header at `path`, whichever format version
+/// wrote it: every version has kept the root first.
+fn stored_root(path: &Path) -> Result<PathBuf> {
+    let Some((_, flags)) = read_preamble(path)? else {
+        return Err(Error::index_format("Not a sonic-search index", path).into());
+    };
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.read_exact(&mut [0u8; 12])?;
+    let root = match flags & index::COMPRESSED != 0 {
+        true => bincode::deserialize_from(zstd::Decoder::with_buffer(reader)?),
+        false => bincode::deserialize_from(reader),
+    };
+    root.with_context(|| format!("Failed to read the root from {}", path.display()))
+}
+
+/// What [`migrate`] rebuilt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migrated {
+    pub generation: u64,
+    /// The old index recorded its scan options, so the new one was scanned
+    /// with them; otherwise it was scanned with the defaults
+    pub kept_options: bool,
+}
+
+/// Rebuild the index in `index_dir` in this build's format by scanning
+/// its root again, and delete the generations in the old one. Returns what
+/// was rebuilt, or `None` if the index was already in this format.
+///
+/// The scan uses the options the old index recorded alongside its
+/// generations, content index included, when there are readable ones.
+pub fn migrate(index_dir: &Path) -> Result<Option<Migrated>> {
+    let generation = match Index::current_generation(index_dir) {
+        Ok(Some(generation)) => generation,
+        Ok(None) => anyhow::bail!("No index found at {}", index_dir.display()),
+        Err(_) => return Err(Finding::pointer(index_dir).into()),
+    };
+    let path = Index::generation_path(index_dir, generation);
+    if let Some((INDEX_FORMAT_VERSION, _)) = read_preamble(&path)? {
+        return Ok(None);
+    }
+    let root = stored_root(&path)?;
+    if !root.is_dir() {
+        anyhow::bail!(
+            "{}, the root of the index at {}, is gone; scan its new location with `ss scan <root> -i {}`",
+            root.display(),
+            index_dir.display(),
+            index_dir.display()
+        );
+    }
+    let old = Index::generations(index_dir)?;
+    let stored = StoredOptions::read(index_dir);
+    let kept_options = stored.is_some();
+    let stored = stored.unwrap_or_default();
+    let scan = scanner::scan_directory_with(&root, &stored.scan_options, None, None)?;
+    let mut index = Index::from_scan(scan);
+    if let Some(max_content_size) = stored.content {
+        index.build_content(max_content_size)?;
+    }
+    let generation = index.save(index_dir)?;
+    for stale in old {
+        index::remove_generation(index_dir, stale);
+    }
+    Ok(Some(Migrated {
+        generation,
+        kept_options,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Rewrite the format version in the header of `generation`
+    fn set_version(index_dir: &Path, generation: u64, version: u32) {
+        let path = Index::generation_path(index_dir, generation);
+        let mut header = fs::read(&path).unwrap();
+        header[4..8].copy_from_slice(&version.to_le_bytes());
+        fs::write(&path, header).unwrap();
+    }
+
+    fn finding(error: crate::error::Error) -> Finding {
+        let crate::error::Error::Other(error) = error else {
+            panic!("Not a finding: {}", error);
+        };
+        error.downcast::<Finding>().unwrap()
+    }
+
+    #[test]
+    fn test_old_format_is_migrated() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("notes.txt"), "n").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let dir = index_dir.path();
+        let mut index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        index.meta.scan_options.compress = true;
+        index.save(dir).unwrap();
+        assert_eq!(migrate(dir).unwrap(), None);
+
+        set_version(dir, 1, INDEX_FORMAT_VERSION - 1);
+        let found = finding(Index::load(dir).unwrap_err());
+        assert!(found.problem.contains("an older sonic-search"), "{}", found);
+        assert_eq!(
+            found.fix,
+            format!("ss migrate -i {}", dir.to_string_lossy())
+        );
+        // Left in place for the migration rather than quarantined
+        assert!(Index::generation_path(dir, 1).is_file());
+
+        fs::write(root.path().join("later.txt"), "l").unwrap();
+        let migrated = migrate(dir).unwrap().unwrap();
+        assert_eq!(migrated.generation, 2);
+        assert!(migrated.kept_options);
+        assert_eq!(Index::generations(dir).unwrap(), [2]);
+        let loaded = Index::load(dir).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert!(loaded.meta.scan_options.compress);
+
+        // Without recorded options, the defaults are used
+        set_version(dir, 2, INDEX_FORMAT_VERSION + 1);
+        fs::remove_file(dir.join("scan-options.json")).unwrap();
+        let migrated = migrate(dir).unwrap().unwrap();
+        assert!(!migrated.kept_options);
+        assert!(!Index::load(dir).unwrap().meta.scan_options.compress);
+    }
+
+    #[test]
+    fn test_missing_files_point_at_repair() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let dir = index_dir.path();
+        let mut index = crate::testing::IndexBuilder::new("/work")
+            .file("notes.txt", 5)
+            .build();
+        index.save(dir).unwrap();
+
+        fs::remove_file(Index::entries_path(dir, 1)).unwrap();
+        let found = finding(Index::load(dir).unwrap_err());
+        assert!(found.problem.contains("are missing"), "{}", found);
+        assert!(found.fix.starts_with("ss verify --repair -i "));
+
+        fs::remove_file(dir.join("CURRENT")).unwrap();
+        let found = finding(Index::load(dir).unwrap_err());
+        assert!(found.to_string().contains("\n   run: ss verify --repair"));
+    }
+}
@@ -0,0 +1,474 @@
+use crate::{CaseArgs, FilterArgs, output};
+use anyhow::Result;
+use clap::Parser;
+use sonic_search::{CaseMode, SearchOptions, daemon, grep};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Requests handled at the same time
+const WORKERS: usize = 4;
+
+/// How often idle workers check whether to stop
+const POLL: Duration = Duration::from_millis(100);
+
+/// The search page served at `/` by `ss serve --ui`
+const UI_PAGE: &str = include_str!("serve.html");
+
+/// Results returned when a request doesn't pass `limit`, so a short query
+/// can't send back the whole index
+const DEFAULT_LIMIT: usize = 100;
+
+/// Query parameters of `/search`
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+struct SearchParams {
+    #[arg(long)]
+    q: String,
+    #[arg(long, default_value_t = DEFAULT_LIMIT)]
+    limit: usize,
+    #[arg(long)]
+    full_path: bool,
+    #[command(flatten)]
+    case: CaseArgs,
+    #[arg(long)]
+    exact_accents: bool,
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// Query parameters of `/grep`
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+struct GrepParams {
+    #[arg(long)]
+    q: String,
+    /// Matching lines to return
+    #[arg(long, default_value_t = DEFAULT_LIMIT)]
+    limit: usize,
+    #[arg(long)]
+    fixed_strings: bool,
+    #[command(flatten)]
+    case: CaseArgs,
+    #[arg(long, value_parser = sonic_search::units::parse_size)]
+    max_filesize: Option<u64>,
+    #[arg(long, value_parser = sonic_search::lang::parse)]
+    doc_lang: Option<&'static str>,
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// Query parameters of `/stats`
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+struct StatsParams {
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+}
+
+/// A failed request: its HTTP status and the message returned as
+/// `{"error": ...}`
+#[derive(Debug)]
+struct Failure {
+    status: u16,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serve `server`'s index over HTTP on `addr` until `stop` is set, calling
+/// `ready` with the bound address once clients can connect. When `token` is
+/// set, every request must present it. With `ui`, a search page is served
+/// at `/` too; it holds no results itself, so it's served without a token
+/// and asks for one when the API does.
+pub fn serve(
+    server: &daemon::Server,
+    addr: &str,
+    token: Option<&str>,
+    ui: bool,
+    stop: &AtomicBool,
+    ready: impl FnOnce(SocketAddr),
+) -> Result<()> {
+    let http = tiny_http::Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?;
+    if let Some(addr) = http.server_addr().to_ip() {
+        ready(addr);
+    }
+    std::thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    match http.recv_timeout(POLL) {
+                        Ok(Some(request)) => {
+                            // A client hanging up early is its own problem
+                            let _ = handle(server, token, ui, request);
+                        }
+                        Ok(None) => {}
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Answer one HTTP request with a JSON body, or the search page
+fn handle(
+    server: &daemon::Server,
+    token: Option<&str>,
+    ui: bool,
+    request: tiny_http::Request,
+) -> std::io::Result<()> {
+    let path = request.url().split('?').next().unwrap_or_default();
+    if ui && path == "/" && *request.method() == tiny_http::Method::Get {
+        let header =
+            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
+                .expect("valid header");
+        return request.respond(tiny_http::Response::from_string(UI_PAGE).with_header(header));
+    }
+    let (status, body) = match route(server, token, &request) {
+        Ok(body) => (200, body),
+        Err(failure) => (
+            failure.status,
+            serde_json::json!({ "error": failure.message }).to_string(),
+        ),
+    };
+    let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("valid header");
+    request.respond(
+        tiny_http::Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header),
+    )
+}
+
+fn route(
+    server: &daemon::Server,
+    token: Option<&str>,
+    request: &tiny_http::Request,
+) -> Result<String, Failure> {
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let mut params = parse_query(query);
+    let given = params
+        .iter()
+        .position(|(key, _)| key == "token")
+        .map(|i| params.remove(i).1);
+    if let Some(token) = token {
+        let bearer = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+            .map(str::to_string);
+        let given = bearer.or(given).unwrap_or_default();
+        if !same_token(given.as_bytes(), token.as_bytes()) {
+            return Err(Failure::new(401, "Missing or wrong token"));
+        }
+    }
+    if *request.method() != tiny_http::Method::Get {
+        return Err(Failure::new(405, "Only GET requests are supported"));
+    }
+    match path {
+        "/search" => search(server, parse_params(&params)?),
+        "/grep" => grep(server, parse_params(&params)?),
+        "/stats" => stats(server, parse_params(&params)?),
+        _ => Err(Failure::new(404, format!("No such endpoint: {}", path))),
+    }
+}
+
+fn search(server: &daemon::Server, params: SearchParams) -> Result<String, Failure> {
+    let request = daemon::Request::Find {
+        query: params.q,
+        options: SearchOptions {
+            limit: Some(params.limit),
+            filters: params.filters.into(),
+            full_path: params.full_path,
+            case: params.case.into(),
+            exact_accents: params.exact_accents,
+            transliterate: false,
+            weights: Default::default(),
+            sample: None,
+            unsorted: false,
+            engine: Default::default(),
+        },
+    };
+    let daemon::Response::Found { matches, .. } = answer(server, &request)? else {
+        unreachable!("find is answered with matches");
+    };
+    let records: Vec<output::FindRecord> = matches
+        .iter()
+        .map(|(hit, score)| crate::find_record(hit, *score))
+        .collect();
+    to_json(&records)
+}
+
+fn grep(server: &daemon::Server, params: GrepParams) -> Result<String, Failure> {
+    let ignore_case = CaseMode::from(params.case).ignores_case(&params.q);
+    let request = daemon::Request::Grep {
+        query: params.q,
+        options: grep::GrepOptions {
+            fixed_strings: params.fixed_strings,
+            ignore_case,
+            max_filesize: params.max_filesize,
+            doc_lang: params.doc_lang.map(str::to_string),
+            spellings: grep::Spellings::Joined,
+            ..Default::default()
+        },
+        filters: params.filters.into(),
+    };
+    let daemon::Response::Grepped { files, .. } = answer(server, &request)? else {
+        unreachable!("grep is answered with matching files");
+    };
+    let (hits, mut results): (Vec<_>, Vec<_>) = files.into_iter().unzip();
+    let owners: std::collections::HashMap<_, _> = hits
+        .iter()
+        .map(|hit| (hit.entry.path.as_path(), hit.owners.as_slice()))
+        .collect();
+    grep::limit_lines(&mut results, params.limit);
+    let records: Vec<output::GrepRecord> = results
+        .iter()
+        .flat_map(|file| {
+            let owners = owners[file.path.as_path()];
+            file.matches.iter().map(move |m| output::GrepRecord {
+                path: &file.path,
+                line_number: m.line_number,
+                column: m.column(),
+                byte_offset: m.byte_offset(),
+                line: &m.line,
+                spans: m.spans.iter().map(|span| [span.start, span.end]).collect(),
+                before: &m.before,
+                after: &m.after,
+                owners,
+                index: None,
+            })
+        })
+        .collect();
+    to_json(&records)
+}
+
+fn stats(server: &daemon::Server, params: StatsParams) -> Result<String, Failure> {
+    let snapshot = server
+        .snapshot()
+        .map_err(|e| Failure::new(500, format!("{:#}", e)))?;
+    let extensions = snapshot.extension_stats();
+    to_json(&crate::stats_record(
+        server.index_dir(),
+        &snapshot,
+        &extensions,
+        params.top,
+    ))
+}
+
+/// Answer `request`, reporting an invalid query as a bad request
+fn answer(server: &daemon::Server, request: &daemon::Request) -> Result<daemon::Response, Failure> {
+    match server.answer(request) {
+        daemon::Response::Error(message) => Err(Failure::new(400, message)),
+        response => Ok(response),
+    }
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<String, Failure> {
+    serde_json::to_string(value).map_err(|e| Failure::new(500, e.to_string()))
+}
+
+/// Parse query parameters as the long options of `P`, so `ext=rs` means
+/// what `--ext rs` does on the command line and flags take `1` or `true`
+fn parse_params<P: Parser>(params: &[(String, String)]) -> Result<P, Failure> {
+    let command = P::command();
+    let mut args = Vec::new();
+    for (key, value) in params {
+        let name = key.replace('_', "-");
+        let takes_value = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()))
+            .is_some_and(|arg| arg.get_action().takes_values());
+        if takes_value {
+            args.push(format!("--{}={}", name, value));
+        } else if matches!(value.as_str(), "" | "1" | "true") {
+            args.push(format!("--{}", name));
+        } else if !matches!(value.as_str(), "0" | "false") {
+            return Err(Failure::new(
+                400,
+                format!("{} is a flag; pass true or false, not {:?}", key, value),
+            ));
+        }
+    }
+    P::try_parse_from(args).map_err(|e| {
+        let message = e.to_string();
+        let first = message.lines().next().unwrap_or_default();
+        Failure::new(400, first.trim_start_matches("error: ").replace("--", ""))
+    })
+}
+
+/// Split `a=1&b=two+words` into decoded key-value pairs
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Undo percent-encoding, reading `+` as a space
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok());
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Compare tokens in time independent of where they differ
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_search::{Index, scanner};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    /// Send a GET for `target` and return the status and parsed body
+    fn get(addr: SocketAddr, target: &str, auth: Option<&str>) -> (u16, serde_json::Value) {
+        let (status, body) = fetch(addr, target, auth);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    /// Send a GET for `target` and return the status and body
+    fn fetch(addr: SocketAddr, target: &str, auth: Option<&str>) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let auth = auth
+            .map(|token| format!("Authorization: Bearer {}\r\n", token))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+            target, auth
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    #[test]
+    fn test_serve_endpoints() {
+        let root = tempfile::tempdir().unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("budget.txt"), "total = 42\nnet = 7").unwrap();
+        std::fs::write(root.path().join("notes.md"), "total = 1").unwrap();
+        Index::from_scan(scanner::scan_directory(root.path()).unwrap())
+            .save(index_dir.path())
+            .unwrap();
+
+        let server = daemon::Server::open(index_dir.path()).unwrap();
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let (ready, listening) = std::sync::mpsc::channel();
+            let serving = scope.spawn(|| {
+                serve(
+                    &server,
+                    "127.0.0.1:0",
+                    Some("secret"),
+                    true,
+                    &stop,
+                    move |addr| ready.send(addr).unwrap(),
+                )
+            });
+            let addr = listening.recv_timeout(Duration::from_secs(10)).unwrap();
+
+            let (status, body) = get(addr, "/search?q=budget", None);
+            assert_eq!(status, 401);
+            assert!(body["error"].is_string());
+
+            let (status, body) = get(addr, "/search?q=budg&token=secret", None);
+            assert_eq!(status, 200);
+            assert_eq!(body[0]["name"], "budget.txt");
+
+            let (status, body) = get(addr, "/grep?q=total+%3D&ext=md", Some("secret"));
+            assert_eq!(status, 200);
+            assert_eq!(body.as_array().unwrap().len(), 1);
+            assert_eq!(body[0]["line"], "total = 1");
+
+            let (status, body) = get(
+                addr,
+                "/grep?q=TOTAL&ignore_case=true&limit=1",
+                Some("secret"),
+            );
+            assert_eq!(status, 200);
+            assert_eq!(body.as_array().unwrap().len(), 1);
+
+            let (status, body) = get(addr, "/stats?top=1", Some("secret"));
+            assert_eq!(status, 200);
+            assert_eq!(body["files"], 2);
+
+            assert_eq!(get(addr, "/grep?q=(", Some("secret")).0, 400);
+            assert_eq!(get(addr, "/search?limit=many&q=a", Some("secret")).0, 400);
+            assert_eq!(get(addr, "/nowhere", Some("secret")).0, 404);
+
+            // The page is served without the token, and asks for it
+            let (status, page) = fetch(addr, "/", None);
+            assert_eq!(status, 200);
+            assert!(page.contains("<input id=\"q\""));
+
+            stop.store(true, Ordering::Relaxed);
+            serving.join().unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("q=two+words&ext=rs%2Cmd&full_path"),
+            vec![
+                ("q".to_string(), "two words".to_string()),
+                ("ext".to_string(), "rs,md".to_string()),
+                ("full_path".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(decode("100%"), "100%");
+        let params: SearchParams = parse_params(&parse_query("q=a&full_path=1&ext=rs,md")).unwrap();
+        assert!(params.full_path);
+        assert_eq!(params.limit, DEFAULT_LIMIT);
+        assert_eq!(params.filters.extensions, ["rs", "md"]);
+        assert!(parse_params::<SearchParams>(&parse_query("full_path")).is_err());
+    }
+}
@@ -0,0 +1,603 @@
+//! Just enough of the SQLite file format to write a database of rowid
+//! tables and read them back, without linking SQLite itself.
+//!
+//! Databases are written whole, in one pass: each table's rows fill leaf
+//! pages in rowid order, with interior pages built above them, so there is
+//! no free space to manage. Reading follows the table b-trees of any
+//! database in the legacy rollback journal mode, such as one SQLite itself
+//! has since changed.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+
+/// Page size of written databases
+const PAGE_SIZE: usize = 4096;
+
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// Length of the database header at the start of page 1
+const HEADER_LEN: usize = 100;
+
+const LEAF_TABLE: u8 = 0x0d;
+const INTERIOR_TABLE: u8 = 0x05;
+
+/// Children of an interior page: as many 13-byte cells, at most, as fit
+/// with their pointers, and the right-most pointer
+const INTERIOR_CHILDREN: usize = (PAGE_SIZE - 12) / 15 + 1;
+
+/// SQLite version the written files claim to come from: 3.40.0
+const SQLITE_VERSION: u32 = 3_040_000;
+
+/// A value in a row
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(value) => Some(value),
+            Value::Real(value) if value.fract() == 0.0 => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// A table to write: its name, the `CREATE TABLE` statement SQLite will
+/// read its columns from, and its rows, each with its rowid
+pub struct Table<'a> {
+    pub name: &'a str,
+    pub sql: &'a str,
+    pub rows: Vec<(i64, Vec<Value>)>,
+}
+
+/// Write `tables` to `out` as a SQLite database. Rows must be in order of
+/// rowid within each table.
+pub fn write(out: &mut impl Write, tables: &[Table]) -> Result<()> {
+    let mut pages = Pages(Vec::new());
+    // Page 1 holds the header and the schema, written once the tables'
+    // root pages are known
+    pages.allocate();
+    let mut schema = Vec::new();
+    for (rowid, table) in (1..).zip(tables) {
+        let root = write_btree(&mut pages, &table.rows);
+        let row = vec![
+            Value::Text("table".to_string()),
+            Value::Text(table.name.to_string()),
+            Value::Text(table.name.to_string()),
+            Value::Integer(root as i64),
+            Value::Text(table.sql.to_string()),
+        ];
+        schema.push((rowid, row));
+    }
+    let mut leaf = Cells::new(HEADER_LEN);
+    for (rowid, row) in &schema {
+        let cell = leaf_cell(&mut pages, *rowid, &encode_record(row));
+        if !leaf.fits(&cell) {
+            anyhow::bail!("The schema doesn't fit on the first page");
+        }
+        leaf.push(cell, *rowid);
+    }
+    pages
+        .page(1)
+        .copy_from_slice(&leaf.finish(LEAF_TABLE, None));
+
+    let page_count = pages.count() as u32;
+    let header = &mut pages.page(1)[..HEADER_LEN];
+    header[..16].copy_from_slice(MAGIC);
+    header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // Legacy rollback journal for writing and reading
+    header[18] = 1;
+    header[19] = 1;
+    // Payload fractions, fixed by the format
+    header[21] = 64;
+    header[22] = 32;
+    header[23] = 32;
+    // File change counter, and the version-valid-for number matching it
+    header[24..28].copy_from_slice(&1u32.to_be_bytes());
+    header[92..96].copy_from_slice(&1u32.to_be_bytes());
+    header[28..32].copy_from_slice(&page_count.to_be_bytes());
+    // Schema cookie and schema format 4
+    header[40..44].copy_from_slice(&1u32.to_be_bytes());
+    header[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // UTF-8
+    header[56..60].copy_from_slice(&1u32.to_be_bytes());
+    header[96..100].copy_from_slice(&SQLITE_VERSION.to_be_bytes());
+    out.write_all(&pages.0)?;
+    Ok(())
+}
+
+/// The rows of the table called `name` in the database `db`, in rowid
+/// order. Columns a row lacks, as rows written before an `ALTER TABLE ...
+/// ADD COLUMN` do, are left off its end.
+pub fn read_table(db: &[u8], name: &str) -> Result<Vec<(i64, Vec<Value>)>> {
+    let reader = Reader::new(db)?;
+    let mut schema = Vec::new();
+    reader.walk(1, &mut schema, 0)?;
+    let root = schema
+        .iter()
+        .find(|(_, row)| {
+            row.first().and_then(Value::as_text) == Some("table")
+                && row.get(1).and_then(Value::as_text) == Some(name)
+        })
+        .and_then(|(_, row)| row.get(3)?.as_integer())
+        .with_context(|| format!("The database has no table `{}`", name))?;
+    let mut rows = Vec::new();
+    reader.walk(root as u32, &mut rows, 0)?;
+    Ok(rows)
+}
+
+/// The pages of a database being written, numbered from 1
+struct Pages(Vec<u8>);
+
+impl Pages {
+    fn allocate(&mut self) -> u32 {
+        self.0.resize(self.0.len() + PAGE_SIZE, 0);
+        self.count() as u32
+    }
+
+    fn count(&self) -> usize {
+        self.0.len() / PAGE_SIZE
+    }
+
+    fn page(&mut self, number: u32) -> &mut [u8] {
+        let start = (number as usize - 1) * PAGE_SIZE;
+        &mut self.0[start..start + PAGE_SIZE]
+    }
+}
+
+/// The cells of a b-tree page being filled
+struct Cells {
+    /// Where the page header starts: after the database header on page 1
+    offset: usize,
+    cells: Vec<Vec<u8>>,
+    used: usize,
+    last_rowid: i64,
+}
+
+impl Cells {
+    fn new(offset: usize) -> Self {
+        Cells {
+            offset,
+            cells: Vec::new(),
+            used: 0,
+            last_rowid: 0,
+        }
+    }
+
+    /// Whether `cell` fits next to the cells already on the page, leaving
+    /// room for an interior page's longer header
+    fn fits(&self, cell: &[u8]) -> bool {
+        self.offset + 12 + 2 * (self.cells.len() + 1) + self.used + cell.len() <= PAGE_SIZE
+    }
+
+    fn push(&mut self, cell: Vec<u8>, rowid: i64) {
+        self.used += cell.len();
+        self.cells.push(cell);
+        self.last_rowid = rowid;
+    }
+
+    /// The page, its cells packed against its end
+    fn finish(&self, kind: u8, right_most: Option<u32>) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        let header_len = if kind == LEAF_TABLE { 8 } else { 12 };
+        let mut end = PAGE_SIZE;
+        let pointers = self.offset + header_len;
+        for (i, cell) in self.cells.iter().enumerate() {
+            end -= cell.len();
+            page[end..end + cell.len()].copy_from_slice(cell);
+            page[pointers + 2 * i..pointers + 2 * i + 2]
+                .copy_from_slice(&(end as u16).to_be_bytes());
+        }
+        let header = &mut page[self.offset..];
+        header[0] = kind;
+        header[3..5].copy_from_slice(&(self.cells.len() as u16).to_be_bytes());
+        // A content area starting at 65536 is written as 0
+        header[5..7].copy_from_slice(&((end % 65536) as u16).to_be_bytes());
+        if let Some(right_most) = right_most {
+            header[8..12].copy_from_slice(&right_most.to_be_bytes());
+        }
+        page
+    }
+}
+
+/// Write `rows` as a table b-tree and return its root page
+fn write_btree(pages: &mut Pages, rows: &[(i64, Vec<Value>)]) -> u32 {
+    // Pages of the level being built, with the largest rowid below each
+    let mut level = Vec::new();
+    let mut leaf = Cells::new(0);
+    for (rowid, row) in rows {
+        let cell = leaf_cell(pages, *rowid, &encode_record(row));
+        if !leaf.cells.is_empty() && !leaf.fits(&cell) {
+            level.push(flush(pages, &leaf, LEAF_TABLE, None));
+            leaf = Cells::new(0);
+        }
+        leaf.push(cell, *rowid);
+    }
+    level.push(flush(pages, &leaf, LEAF_TABLE, None));
+    while level.len() > 1 {
+        let mut groups: Vec<Vec<(u32, i64)>> =
+            level.chunks(INTERIOR_CHILDREN).map(<[_]>::to_vec).collect();
+        // Every interior page needs a cell besides its right-most pointer
+        if let [.., before, last] = groups.as_mut_slice()
+            && last.len() == 1
+        {
+            last.insert(0, before.pop().unwrap());
+        }
+        level = groups
+            .iter()
+            .map(|children| {
+                let (right_most, cells) = children.split_last().unwrap();
+                let mut interior = Cells::new(0);
+                for &(page, rowid) in cells {
+                    let mut cell = page.to_be_bytes().to_vec();
+                    put_varint(&mut cell, rowid as u64);
+                    interior.push(cell, rowid);
+                }
+                flush(pages, &interior, INTERIOR_TABLE, Some(*right_most))
+            })
+            .collect();
+    }
+    level[0].0
+}
+
+/// Write `page` to a new page and return its number and largest rowid.
+/// `right_most` is the last child of an interior page.
+fn flush(pages: &mut Pages, page: &Cells, kind: u8, right_most: Option<(u32, i64)>) -> (u32, i64) {
+    let number = pages.allocate();
+    let bytes = page.finish(kind, right_most.map(|(child, _)| child));
+    pages.page(number).copy_from_slice(&bytes);
+    match right_most {
+        Some((_, rowid)) => (number, rowid),
+        None => (number, page.last_rowid),
+    }
+}
+
+/// The bytes of a payload a table b-tree page keeps itself, the rest
+/// going to overflow pages
+fn local_payload(payload: usize, usable: usize) -> usize {
+    let max_local = usable - 35;
+    if payload <= max_local {
+        return payload;
+    }
+    let min_local = (usable - 12) * 32 / 255 - 23;
+    let local = min_local + (payload - min_local) % (usable - 4);
+    if local <= max_local { local } else { min_local }
+}
+
+/// A table leaf cell holding `record`, spilling what doesn't fit in the
+/// page to a chain of overflow pages
+fn leaf_cell(pages: &mut Pages, rowid: i64, record: &[u8]) -> Vec<u8> {
+    let mut cell = Vec::new();
+    put_varint(&mut cell, record.len() as u64);
+    put_varint(&mut cell, rowid as u64);
+    let local = local_payload(record.len(), PAGE_SIZE);
+    cell.extend_from_slice(&record[..local]);
+    if local < record.len() {
+        let chunks: Vec<&[u8]> = record[local..].chunks(PAGE_SIZE - 4).collect();
+        let first = pages.count() as u32 + 1;
+        cell.extend_from_slice(&first.to_be_bytes());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let number = pages.allocate();
+            let next = if i + 1 < chunks.len() { number + 1 } else { 0 };
+            let page = pages.page(number);
+            page[..4].copy_from_slice(&next.to_be_bytes());
+            page[4..4 + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+    cell
+}
+
+/// `row` in SQLite's record format: a header of serial types, then the
+/// values
+fn encode_record(row: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in row {
+        let serial = match value {
+            Value::Null => 0,
+            Value::Integer(0) => 8,
+            Value::Integer(1) => 9,
+            &Value::Integer(value) => {
+                let (serial, len) = match value {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+                serial
+            }
+            Value::Real(value) => {
+                body.extend_from_slice(&value.to_be_bytes());
+                7
+            }
+            Value::Text(text) => {
+                body.extend_from_slice(text.as_bytes());
+                13 + 2 * text.len() as u64
+            }
+            Value::Blob(bytes) => {
+                body.extend_from_slice(bytes);
+                12 + 2 * bytes.len() as u64
+            }
+        };
+        put_varint(&mut types, serial);
+    }
+    // The header's length counts the varint holding it
+    let mut header_len = types.len() + 1;
+    if header_len > 0x7f {
+        header_len += 1;
+    }
+    let mut record = Vec::with_capacity(header_len + body.len());
+    put_varint(&mut record, header_len as u64);
+    record.extend_from_slice(&types);
+    record.extend_from_slice(&body);
+    record
+}
+
+/// Append `value` as a SQLite varint: big-endian groups of 7 bits, the
+/// ninth byte holding 8
+fn put_varint(out: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        let mut bytes = [0u8; 9];
+        bytes[8] = value as u8;
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
+        }
+        out.extend_from_slice(&bytes);
+        return;
+    }
+    let mut bytes = Vec::with_capacity(8);
+    let mut rest = value;
+    loop {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    bytes[0] &= 0x7f;
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+/// Read the varint at the start of `bytes`, returning it and its length
+fn get_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..8 {
+        let byte = *bytes.get(i)?;
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    Some(((value << 8) | u64::from(*bytes.get(8)?), 9))
+}
+
+/// A database being read
+struct Reader<'a> {
+    db: &'a [u8],
+    page_size: usize,
+    /// Page size less the bytes each page reserves at its end
+    usable: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(db: &'a [u8]) -> Result<Self> {
+        if db.len() < HEADER_LEN || &db[..16] != MAGIC {
+            anyhow::bail!("Not a SQLite database");
+        }
+        let page_size = match u16::from_be_bytes([db[16], db[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            anyhow::bail!("Not a SQLite database: page size {}", page_size);
+        }
+        if db[18] != 1 || db[19] != 1 {
+            anyhow::bail!(
+                "The database is in WAL mode; switch it back with PRAGMA journal_mode=DELETE"
+            );
+        }
+        if u32::from_be_bytes(db[56..60].try_into().unwrap()) > 1 {
+            anyhow::bail!("The database isn't UTF-8 encoded");
+        }
+        Ok(Reader {
+            db,
+            page_size,
+            usable: page_size - db[20] as usize,
+        })
+    }
+
+    fn page(&self, number: u32) -> Result<&'a [u8]> {
+        let start = (number as usize)
+            .checked_sub(1)
+            .map(|index| index * self.page_size)
+            .filter(|&start| number != 0 && start + self.page_size <= self.db.len())
+            .with_context(|| format!("The database is corrupt: no page {}", number))?;
+        Ok(&self.db[start..start + self.page_size])
+    }
+
+    /// Append the rows of the table b-tree rooted at page `number` to `rows`
+    fn walk(&self, number: u32, rows: &mut Vec<(i64, Vec<Value>)>, depth: usize) -> Result<()> {
+        let corrupt = || anyhow::anyhow!("The database is corrupt at page {}", number);
+        if depth > 64 {
+            return Err(corrupt());
+        }
+        let page = self.page(number)?;
+        let header = if number == 1 { HEADER_LEN } else { 0 };
+        let kind = page[header];
+        let cells = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+        let pointers = header + if kind == LEAF_TABLE { 8 } else { 12 };
+        let cell_at = |i: usize| -> Result<&[u8]> {
+            let at = pointers + 2 * i;
+            let offset = u16::from_be_bytes(page.get(at..at + 2).ok_or_else(corrupt)?.try_into()?);
+            page.get(offset as usize..self.usable).ok_or_else(corrupt)
+        };
+        match kind {
+            LEAF_TABLE => {
+                for i in 0..cells {
+                    let cell = cell_at(i)?;
+                    let (len, a) = get_varint(cell).ok_or_else(corrupt)?;
+                    let (rowid, b) = get_varint(&cell[a..]).ok_or_else(corrupt)?;
+                    let payload = self
+                        .payload(&cell[a + b..], len as usize)
+                        .ok_or_else(corrupt)?;
+                    rows.push((rowid as i64, decode_record(&payload).ok_or_else(corrupt)?));
+                }
+            }
+            INTERIOR_TABLE => {
+                for i in 0..cells {
+                    let child = cell_at(i)?.get(..4).ok_or_else(corrupt)?;
+                    self.walk(u32::from_be_bytes(child.try_into()?), rows, depth + 1)?;
+                }
+                let right_most = &page[header + 8..header + 12];
+                self.walk(u32::from_be_bytes(right_most.try_into()?), rows, depth + 1)?;
+            }
+            _ => anyhow::bail!("Page {} isn't part of a table", number),
+        }
+        Ok(())
+    }
+
+    /// A payload of `len` bytes starting in `cell`, followed through its
+    /// overflow pages
+    fn payload(&self, cell: &[u8], len: usize) -> Option<Vec<u8>> {
+        let local = local_payload(len, self.usable);
+        let mut payload = cell.get(..local)?.to_vec();
+        if local < len {
+            let mut next = u32::from_be_bytes(cell.get(local..local + 4)?.try_into().ok()?);
+            while payload.len() < len {
+                let page = self.page(next).ok()?;
+                let take = (len - payload.len()).min(self.usable - 4);
+                payload.extend_from_slice(&page[4..4 + take]);
+                next = u32::from_be_bytes(page[..4].try_into().ok()?);
+                if next == 0 && payload.len() < len {
+                    return None;
+                }
+            }
+        }
+        Some(payload)
+    }
+}
+
+fn decode_record(record: &[u8]) -> Option<Vec<Value>> {
+    let (header_len, mut at) = get_varint(record)?;
+    let header_len = header_len as usize;
+    let mut body = header_len;
+    let mut values = Vec::new();
+    while at < header_len {
+        let (serial, len) = get_varint(record.get(at..header_len)?)?;
+        at += len;
+        let int = |len: usize| -> Option<i64> {
+            let bytes = record.get(body..body + len)?;
+            let mut value = if bytes.first()? & 0x80 != 0 { -1i64 } else { 0 };
+            for &byte in bytes {
+                value = (value << 8) | i64::from(byte);
+            }
+            Some(value)
+        };
+        let (value, len) = match serial {
+            0 => (Value::Null, 0),
+            1 => (Value::Integer(int(1)?), 1),
+            2 => (Value::Integer(int(2)?), 2),
+            3 => (Value::Integer(int(3)?), 3),
+            4 => (Value::Integer(int(4)?), 4),
+            5 => (Value::Integer(int(6)?), 6),
+            6 => (Value::Integer(int(8)?), 8),
+            7 => {
+                let bytes = record.get(body..body + 8)?;
+                (Value::Real(f64::from_be_bytes(bytes.try_into().ok()?)), 8)
+            }
+            8 => (Value::Integer(0), 0),
+            9 => (Value::Integer(1), 0),
+            serial if serial >= 12 => {
+                let len = ((serial - 12) / 2) as usize;
+                let bytes = record.get(body..body + len)?;
+                match serial % 2 {
+                    0 => (Value::Blob(bytes.to_vec()), len),
+                    _ => (
+                        Value::Text(String::from_utf8_lossy(bytes).into_owned()),
+                        len,
+                    ),
+                }
+            }
+            _ => return None,
+        };
+        values.push(value);
+        body += len;
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_tables() {
+        let long = "x".repeat(10_000);
+        let files: Vec<(i64, Vec<Value>)> = (1..=2000)
+            .map(|rowid| {
+                let name = match rowid {
+                    7 => long.clone(),
+                    _ => format!("file-{}.txt", rowid),
+                };
+                let row = vec![
+                    Value::Null,
+                    Value::Text(name),
+                    Value::Integer(rowid * 1_000_003),
+                    Value::Integer(-rowid),
+                    Value::Blob(vec![rowid as u8; 3]),
+                ];
+                (rowid, row)
+            })
+            .collect();
+        let tables = [
+            Table {
+                name: "meta",
+                sql: "CREATE TABLE meta (key TEXT, value)",
+                rows: vec![(1, vec![Value::Text("generation".into()), Value::Integer(3)])],
+            },
+            Table {
+                name: "files",
+                sql: "CREATE TABLE files (id INTEGER PRIMARY KEY, name TEXT, size INTEGER, delta INTEGER, hash BLOB)",
+                rows: files.clone(),
+            },
+            Table {
+                name: "empty",
+                sql: "CREATE TABLE empty (x)",
+                rows: Vec::new(),
+            },
+        ];
+        let mut db = Vec::new();
+        write(&mut db, &tables).unwrap();
+        assert_eq!(db.len() % PAGE_SIZE, 0);
+        assert_eq!(read_table(&db, "files").unwrap(), files);
+        assert_eq!(read_table(&db, "meta").unwrap()[0].1[1], Value::Integer(3));
+        assert!(read_table(&db, "empty").unwrap().is_empty());
+        assert!(read_table(&db, "missing").is_err());
+        assert!(read_table(&db[..200], "files").is_err());
+
+        for value in [0, 1, 127, 128, 300, 1 << 40, u64::MAX] {
+            let mut bytes = Vec::new();
+            put_varint(&mut bytes, value);
+            assert_eq!(get_varint(&bytes), Some((value, bytes.len())));
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Where the entries of an index generation are kept. Every backend shares
+//! the generation headers, name filters and content indexes; they differ in
+//! the file holding the entries.
+
+use crate::filetype;
+use crate::git;
+use crate::index::{INDEX_FORMAT_VERSION, Index};
+use crate::mapped::{self, MappedEntries};
+use crate::perms;
+use crate::projects::{Project, ProjectKind};
+use crate::quarantine::NotDamaged;
+use crate::scanner::FileEntry;
+use crate::sqlite::{self, Value};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How an index stores its entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    /// sonic-search's own table, searched straight from a memory map
+    #[default]
+    Binary,
+    /// A SQLite database other tools can query and change with SQL,
+    /// slower to open
+    Sqlite,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Binary => "binary",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// A way of writing and reading back the entries of a generation
+pub trait Storage {
+    /// The file holding the entries of `generation` in `index_dir`
+    fn entries_path(&self, index_dir: &Path, generation: u64) -> PathBuf;
+
+    /// Write the entries of `index`, which is being saved as generation
+    /// `index.meta.generation`
+    fn write(&self, index_dir: &Path, index: &Index) -> Result<()>;
+
+    /// The entries of the generation whose header `index` was read from,
+    /// updating whatever else of `index` the backend keeps too
+    fn read(&self, index_dir: &Path, index: &mut Index) -> Result<MappedEntries>;
+
+    /// Whether the file at [`Storage::entries_path`] may change after it
+    /// was written, so it can't be held to a checksum
+    fn is_editable(&self) -> bool;
+
+    /// Whether generations older than the current one keep their entries
+    fn keeps_history(&self) -> bool;
+}
+
+/// The storage of `backend`
+pub fn storage(backend: Backend) -> &'static dyn Storage {
+    match backend {
+        Backend::Binary => &BinaryStorage,
+        Backend::Sqlite => &SqliteStorage,
+    }
+}
+
+/// Entries in a memory-mappable table per generation (see [`mapped`])
+pub struct BinaryStorage;
+
+impl Storage for BinaryStorage {
+    fn entries_path(&self, index_dir: &Path, generation: u64) -> PathBuf {
+        Index::entries_path(index_dir, generation)
+    }
+
+    fn write(&self, index_dir: &Path, index: &Index) -> Result<()> {
+        let path = self.entries_path(index_dir, index.meta.generation);
+        mapped::write(&path, &index.entries, index.meta.scan_options.compress)
+    }
+
+    fn read(&self, index_dir: &Path, index: &mut Index) -> Result<MappedEntries> {
+        MappedEntries::open(&self.entries_path(index_dir, index.meta.generation))
+    }
+
+    fn is_editable(&self) -> bool {
+        false
+    }
+
+    fn keeps_history(&self) -> bool {
+        true
+    }
+}
+
+/// The current generation in `index.sqlite`, with its metadata, projects,
+/// owners and repositories next to the entries in tables of their own.
+///
+/// Each save writes a whole new database and renames it over the old one,
+/// so other programs reading it see one generation or the next, never a
+/// mix. Rows changed with SQL are read back by the next search or update.
+///
+/// Only the current generation is kept: opening any other one, with
+/// `--generation` or through a pin a save has since overtaken, is refused,
+/// and the diffs and history between generations need the binary backend.
+pub struct SqliteStorage;
+
+/// Name of the database inside the index directory
+pub const SQLITE_FILE: &str = "index.sqlite";
+
+const META_SQL: &str = "CREATE TABLE meta (key TEXT NOT NULL, value)";
+
+const FILES_SQL: &str = "CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    folded_name TEXT,
+    size INTEGER NOT NULL,
+    is_dir INTEGER NOT NULL,
+    is_symlink INTEGER NOT NULL,
+    modified INTEGER,
+    modified_nsec INTEGER,
+    project INTEGER REFERENCES projects(id),
+    owners INTEGER REFERENCES owner_sets(id),
+    vendored INTEGER NOT NULL,
+    hash BLOB,
+    repo INTEGER REFERENCES repos(id),
+    git_status TEXT NOT NULL,
+    mime TEXT,
+    uid INTEGER,
+    gid INTEGER,
+    mode INTEGER,
+    readonly INTEGER NOT NULL,
+    hidden INTEGER NOT NULL,
+    device INTEGER
+)";
+
+const PROJECTS_SQL: &str = "CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL, root TEXT NOT NULL, kind TEXT NOT NULL)";
+
+const OWNER_SETS_SQL: &str =
+    "CREATE TABLE owner_sets (id INTEGER PRIMARY KEY, owners TEXT NOT NULL)";
+
+const REPOS_SQL: &str = "CREATE TABLE repos (id INTEGER PRIMARY KEY, path TEXT NOT NULL)";
+
+impl Storage for SqliteStorage {
+    fn entries_path(&self, index_dir: &Path, _generation: u64) -> PathBuf {
+        index_dir.join(SQLITE_FILE)
+    }
+
+    fn write(&self, index_dir: &Path, index: &Index) -> Result<()> {
+        let path = index_dir.join(SQLITE_FILE);
+        let tmp_path = index_dir.join(format!("{}.{}.tmp", SQLITE_FILE, std::process::id()));
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            sqlite::write(&mut writer, &tables(index))?;
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write index: {}", path.display()))
+    }
+
+    fn read(&self, index_dir: &Path, index: &mut Index) -> Result<MappedEntries> {
+        if Index::current_generation(index_dir)? != Some(index.meta.generation) {
+            return Err(anyhow::Error::new(NotDamaged(format!(
+                "Generation {} isn't the current one, and the SQLite backend keeps only that; \
+                 scan with `--backend binary` to open older generations",
+                index.meta.generation
+            ))));
+        }
+        // Every generation shares the database, so falling back on an older
+        // one wouldn't get around damage to it
+        let entries = read_database(index_dir, index)
+            .map_err(|e| anyhow::Error::new(NotDamaged(format!("{:#}", e))))?;
+        Ok(MappedEntries::from_entries(&entries))
+    }
+
+    fn is_editable(&self) -> bool {
+        true
+    }
+
+    fn keeps_history(&self) -> bool {
+        false
+    }
+}
+
+/// The entries in `index.sqlite`, filling the rest of `index` in from it
+fn read_database(index_dir: &Path, index: &mut Index) -> Result<Vec<FileEntry>> {
+    let path = index_dir.join(SQLITE_FILE);
+    for suffix in ["-wal", "-journal"] {
+        let journal = index_dir.join(format!("{}{}", SQLITE_FILE, suffix));
+        if journal.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+            anyhow::bail!(
+                "{} has changes a SQLite program hasn't finished writing; close it first",
+                path.display()
+            );
+        }
+    }
+    let db = fs::read(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let has_content = Index::content_path(index_dir, index.meta.generation).is_file();
+    read_index(&db, index, has_content)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(std::mem::take(&mut index.entries))
+}
+
+fn tables(index: &Index) -> Vec<sqlite::Table<'static>> {
+    let text = |text: &str| Value::Text(text.to_string());
+    let int = Value::Integer;
+    let (updated_at, _) = mapped::epoch_offset(index.meta.updated_at);
+    let id = |id: Option<u32>| id.map_or(Value::Null, |id| int(id.into()));
+    let meta = vec![
+        (
+            1,
+            vec![text("format_version"), int(INDEX_FORMAT_VERSION.into())],
+        ),
+        (
+            2,
+            vec![text("generation"), int(index.meta.generation as i64)],
+        ),
+        (3, vec![text("root"), path_value(&index.meta.root)]),
+        (4, vec![text("updated_at"), int(updated_at)]),
+    ];
+    let files = (0..)
+        .zip(&index.entries)
+        .map(|(rowid, entry)| {
+            let (modified, nsec) = match entry.modified.map(mapped::epoch_offset) {
+                Some((secs, nanos)) => (int(secs), int(nanos.into())),
+                None => (Value::Null, Value::Null),
+            };
+            let attributes = &entry.attributes;
+            let row = vec![
+                Value::Null,
+                path_value(&entry.path),
+                text(&entry.name),
+                entry.folded_name.as_deref().map_or(Value::Null, text),
+                int(entry.size as i64),
+                int(entry.is_dir.into()),
+                int(entry.is_symlink.into()),
+                modified,
+                nsec,
+                id(entry.project),
+                id(entry.owners),
+                int(entry.vendored.into()),
+                entry
+                    .hash
+                    .map_or(Value::Null, |hash| Value::Blob(hash.to_vec())),
+                id(entry.repo),
+                text(git_status_name(entry.git_status)),
+                entry
+                    .mime
+                    .and_then(filetype::mime_name)
+                    .map_or(Value::Null, text),
+                id(attributes.uid),
+                id(attributes.gid),
+                id(attributes.mode),
+                int(attributes.readonly.into()),
+                int(attributes.hidden.into()),
+                // Stored as its bits, as SQLite has no unsigned integers
+                entry
+                    .device
+                    .map_or(Value::Null, |device| int(device as i64)),
+            ];
+            (rowid, row)
+        })
+        .collect();
+    let projects = (0..)
+        .zip(&index.projects)
+        .map(|(rowid, project)| {
+            let row = vec![
+                Value::Null,
+                text(&project.name),
+                path_value(&project.root),
+                text(project.kind.name()),
+            ];
+            (rowid, row)
+        })
+        .collect();
+    let owner_sets = (0..)
+        .zip(&index.owner_sets)
+        .map(|(rowid, owners)| (rowid, vec![Value::Null, text(&owners.join(" "))]))
+        .collect();
+    let repos = (0..)
+        .zip(&index.repos)
+        .map(|(rowid, repo)| (rowid, vec![Value::Null, path_value(repo)]))
+        .collect();
+    let table = |name, sql, rows| sqlite::Table { name, sql, rows };
+    vec![
+        table("meta", META_SQL, meta),
+        table("files", FILES_SQL, files),
+        table("projects", PROJECTS_SQL, projects),
+        table("owner_sets", OWNER_SETS_SQL, owner_sets),
+        table("repos", REPOS_SQL, repos),
+    ]
+}
+
+/// Fill the entries, projects, owners and repositories of `index` in from
+/// the tables of `db`
+fn read_index(db: &[u8], index: &mut Index, has_content: bool) -> Result<()> {
+    let meta = sqlite::read_table(db, "meta")?;
+    let setting = |key: &str| {
+        meta.iter()
+            .find(|(_, row)| row.first().and_then(Value::as_text) == Some(key))
+            .and_then(|(_, row)| row.get(1))
+            .with_context(|| format!("The meta table has no `{}`", key))
+    };
+    let version = setting("format_version")?.as_integer();
+    if version != Some(INDEX_FORMAT_VERSION.into()) {
+        anyhow::bail!(
+            "It uses format version {:?} but this build expects {}; run `ss scan` again",
+            version,
+            INDEX_FORMAT_VERSION
+        );
+    }
+    let generation = setting("generation")?.as_integer();
+    if generation != Some(index.meta.generation as i64) {
+        anyhow::bail!(
+            "It holds generation {:?} instead of {}, saved while it was being opened",
+            generation,
+            index.meta.generation
+        );
+    }
+
+    index.projects = rows(db, "projects", |row| {
+        let kind = match row.text(3)? {
+            "cargo" => ProjectKind::Cargo,
+            "npm" => ProjectKind::Npm,
+            "bazel" => ProjectKind::Bazel,
+            kind => anyhow::bail!("unknown project kind `{}`", kind),
+        };
+        Ok(Project {
+            name: row.text(1)?.to_string(),
+            root: row.path(2)?,
+            kind,
+        })
+    })?;
+    index.owner_sets = rows(db, "owner_sets", |row| {
+        Ok(row
+            .text(1)?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect())
+    })?;
+    index.repos = rows(db, "repos", |row| row.path(1))?;
+    let mut ids = Vec::new();
+    index.entries = rows(db, "files", |row| {
+        ids.push(row.rowid);
+        let modified = match row.optional(7)? {
+            Some(secs) => {
+                let nanos = row.optional(8)?.unwrap_or(0);
+                Some(mapped::from_epoch_offset(secs, u32::try_from(nanos)?))
+            }
+            None => None,
+        };
+        let git_status = match row.text(14)? {
+            "untracked" => git::Status::Untracked,
+            "tracked" => git::Status::Tracked,
+            "modified" => git::Status::Modified,
+            status => anyhow::bail!("unknown git status `{}`", status),
+        };
+        let mime = match row.get(15) {
+            Value::Null => None,
+            _ => {
+                let mime = row.text(15)?;
+                let position = filetype::MIME_TYPES
+                    .iter()
+                    .position(|(name, _)| *name == mime);
+                Some(position.with_context(|| format!("unknown MIME type `{}`", mime))? as u32)
+            }
+        };
+        let hash = match row.get(12) {
+            Value::Null => None,
+            Value::Blob(bytes) => Some(
+                bytes
+                    .as_slice()
+                    .try_into()
+                    .context("`hash` isn't 32 bytes long")?,
+            ),
+            _ => anyhow::bail!("`hash` isn't a blob"),
+        };
+        Ok(FileEntry {
+            path: row.path(1)?,
+            name: row.text(2)?.to_string(),
+            folded_name: match row.get(3) {
+                Value::Null => None,
+                _ => Some(row.text(3)?.to_string()),
+            },
+            size: u64::try_from(row.integer(4)?)?,
+            is_dir: row.integer(5)? != 0,
+            is_symlink: row.integer(6)? != 0,
+            modified,
+            project: row.id(9)?,
+            owners: row.id(10)?,
+            vendored: row.integer(11)? != 0,
+            hash,
+            repo: row.id(13)?,
+            git_status,
+            mime,
+            attributes: perms::Attributes {
+                uid: row.id(16)?,
+                gid: row.id(17)?,
+                mode: row.id(18)?,
+                readonly: row.integer(19)? != 0,
+                hidden: row.integer(20)? != 0,
+            },
+            device: row.optional(21)?.map(|device| device as u64),
+        })
+    })?;
+    // The content index refers to entries by position, which rows added or
+    // deleted since would shift
+    let shifted = ids.iter().zip(0..).any(|(&id, position)| id != position);
+    if shifted && has_content {
+        anyhow::bail!(
+            "Rows were added to or deleted from `files` since its content index was built; run `ss scan --content` again"
+        );
+    }
+    Ok(())
+}
+
+/// The rows of table `name` in `db`, each turned into a `T` by `parse`
+fn rows<T>(db: &[u8], name: &str, mut parse: impl FnMut(&Row) -> Result<T>) -> Result<Vec<T>> {
+    sqlite::read_table(db, name)?
+        .into_iter()
+        .map(|(rowid, values)| {
+            let row = Row { rowid, values };
+            parse(&row).with_context(|| format!("Row {} of `{}`", rowid, name))
+        })
+        .collect()
+}
+
+/// A row being read, whose columns added before an `ALTER TABLE` are
+/// read as NULL
+struct Row {
+    rowid: i64,
+    values: Vec<Value>,
+}
+
+impl Row {
+    fn get(&self, column: usize) -> &Value {
+        self.values.get(column).unwrap_or(&Value::Null)
+    }
+
+    fn integer(&self, column: usize) -> Result<i64> {
+        self.optional(column)?
+            .with_context(|| format!("column {} is NULL", column + 1))
+    }
+
+    fn optional(&self, column: usize) -> Result<Option<i64>> {
+        match self.get(column) {
+            Value::Null => Ok(None),
+            value => Ok(Some(value.as_integer().with_context(|| {
+                format!("column {} isn't an integer", column + 1)
+            })?)),
+        }
+    }
+
+    fn id(&self, column: usize) -> Result<Option<u32>> {
+        Ok(self.optional(column)?.map(u32::try_from).transpose()?)
+    }
+
+    fn text(&self, column: usize) -> Result<&str> {
+        self.get(column)
+            .as_text()
+            .with_context(|| format!("column {} isn't text", column + 1))
+    }
+
+    /// A path stored as text or, when it isn't valid Unicode, as a blob
+    fn path(&self, column: usize) -> Result<PathBuf> {
+        match self.get(column) {
+            Value::Text(text) => Ok(PathBuf::from(text)),
+            Value::Blob(bytes) => Ok(mapped::path_from_bytes(bytes).to_path_buf()),
+            _ => anyhow::bail!("column {} isn't a path", column + 1),
+        }
+    }
+}
+
+fn path_value(path: &Path) -> Value {
+    match path.to_str() {
+        Some(path) => Value::Text(path.to_string()),
+        None => Value::Blob(mapped::path_bytes(path).into_owned()),
+    }
+}
+
+fn git_status_name(status: git::Status) -> &'static str {
+    match status {
+        git::Status::Untracked => "untracked",
+        git::Status::Tracked => "tracked",
+        git::Status::Modified => "modified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::NameStyle;
+    use crate::index::MappedSnapshot;
+    use crate::scanner::ScanOptions;
+    use crate::search::SearchOptions;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_sqlite_roundtrip() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let options = ScanOptions {
+            backend: Backend::Sqlite,
+            ..Default::default()
+        };
+        let mut index = IndexBuilder::new("/work")
+            .options(options)
+            .spec(
+                "app/src/main.rs 2K 2024-03-01
+                 app/Cargo.toml  300",
+            )
+            .unwrap()
+            .project("app", "app", ProjectKind::Cargo)
+            .random_files(300, NameStyle::Mixed)
+            .build();
+        index.entries[1].hash = Some([9; 32]);
+        index.save(index_dir.path()).unwrap();
+        index.save(index_dir.path()).unwrap();
+        assert!(index_dir.path().join(SQLITE_FILE).is_file());
+        assert!(!Index::entries_path(index_dir.path(), 2).exists());
+        // Only the current generation is kept
+        assert_eq!(Index::generations(index_dir.path()).unwrap(), [2]);
+
+        let loaded = Index::load(index_dir.path()).unwrap();
+        assert_eq!(loaded.entries, index.entries);
+        assert_eq!(loaded.projects, index.projects);
+        assert_eq!(loaded.meta.generation, 2);
+
+        let snapshot = MappedSnapshot::open(index_dir.path()).unwrap();
+        let results = snapshot
+            .search("main.rs", &SearchOptions::default())
+            .unwrap();
+        assert_eq!(results[0].0.size, 2048);
+        assert_eq!(results[0].0.project, Some(0));
+
+        // The snapshot pins generation 2, but the database moves on
+        index.save(index_dir.path()).unwrap();
+        let error = MappedSnapshot::open_at(index_dir.path(), 2).unwrap_err();
+        assert!(format!("{:#}", error).contains("SQLite backend keeps only"));
+        assert_eq!(Index::load(index_dir.path()).unwrap().meta.generation, 3);
+    }
+}
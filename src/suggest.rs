@@ -0,0 +1,101 @@
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+/// Pick the candidate the user most plausibly meant when typing `input`.
+///
+/// Uses the same skim matcher as `find` to catch dropped characters
+/// (`fnd` -> `find`) and falls back to edit distance for transpositions and
+/// substitutions (`scna` -> `scan`), which subsequence matching can't see.
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let matcher = SkimMatcherV2::default();
+    let needle = input.to_lowercase();
+    let max_distance = (needle.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.is_empty() && *candidate != input)
+        .filter_map(|candidate| {
+            let lowered = candidate.to_lowercase();
+            let distance = edit_distance(&needle, &lowered);
+            // Only trust subsequence matches that cover most of the candidate,
+            // otherwise a single letter would "match" every command
+            let fuzzy = matcher
+                .fuzzy_match(&lowered, &needle)
+                .filter(|_| needle.chars().count() * 3 >= lowered.chars().count() * 2);
+            // Replacing every character isn't a typo, however short the word
+            let plausible = distance <= max_distance && distance < lowered.chars().count();
+            if !plausible && fuzzy.is_none() {
+                return None;
+            }
+            Some((distance, std::cmp::Reverse(fuzzy.unwrap_or(0)), candidate))
+        })
+        .min()
+        .map(|(_, _, candidate)| candidate)
+}
+
+/// Edit distance between two strings, counted in characters.
+///
+/// This is Levenshtein distance extended with adjacent transpositions
+/// (optimal string alignment), since swapped letters are the most common typo.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [&str; 5] = ["scan", "update", "find", "grep", "stats"];
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("scan", "scan"), 0);
+        assert_eq!(edit_distance("scna", "scan"), 1);
+        assert_eq!(edit_distance("gerp", "grep"), 1);
+        assert_eq!(edit_distance("fnd", "find"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_closest_command() {
+        assert_eq!(closest("fnd", COMMANDS), Some("find"));
+        assert_eq!(closest("scna", COMMANDS), Some("scan"));
+        assert_eq!(closest("updte", COMMANDS), Some("update"));
+        assert_eq!(closest("stat", COMMANDS), Some("stats"));
+        assert_eq!(closest("budget", COMMANDS), None);
+        assert_eq!(closest("s", COMMANDS), None);
+        assert_eq!(closest("x", ["s", "f", "g"]), None);
+    }
+
+    #[test]
+    fn test_closest_path() {
+        let known = ["/home/me/.sonic-search", "/data/photos/.sonic-search"];
+        assert_eq!(
+            closest("/home/me/.sonic-serach", known),
+            Some("/home/me/.sonic-search")
+        );
+        assert_eq!(closest("/srv/unrelated/index", known), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Labels attached to indexed files with `ss tag add`, kept in the index
+//! directory apart from the generations so rescans leave them alone.
+//!
+//! A tag belongs to a path, and to the file's content hash when the index
+//! has one, so a tagged file that's moved or renamed keeps its tags.
+
+use crate::scanner::{self, FileEntry};
+use crate::search::EntryFields;
+use crate::suggest;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File holding the tags inside the index directory
+pub const TAGS_FILE: &str = "tags.json";
+
+/// A file and the tags attached to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedFile {
+    pub path: PathBuf,
+    /// BLAKE3 content hash in hex, for indexes scanned with `--hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    pub tags: BTreeSet<String>,
+}
+
+/// Every tagged file of an index, sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tags {
+    pub files: Vec<TaggedFile>,
+}
+
+/// The files carrying any of a set of tags, as `find --tag` filters them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tagged {
+    pub paths: HashSet<PathBuf>,
+    pub hashes: HashSet<[u8; 32]>,
+}
+
+impl Tagged {
+    pub fn contains(&self, entry: &impl EntryFields) -> bool {
+        self.paths.contains(entry.path())
+            || entry.hash().is_some_and(|hash| self.hashes.contains(hash))
+    }
+
+    /// Add the files of `other`, for searches across several indexes
+    pub fn extend(&mut self, other: Tagged) {
+        self.paths.extend(other.paths);
+        self.hashes.extend(other.hashes);
+    }
+}
+
+/// Reject tags that couldn't be told apart on a command line or in a listing
+pub fn check_tag(tag: &str) -> Result<()> {
+    if tag.is_empty() || tag.starts_with('-') || tag.contains(|c: char| c.is_whitespace()) {
+        anyhow::bail!(
+            "Invalid tag '{}': tags can't be empty, start with '-' or contain spaces",
+            tag
+        );
+    }
+    Ok(())
+}
+
+impl Tags {
+    pub fn path(index_dir: &Path) -> PathBuf {
+        index_dir.join(TAGS_FILE)
+    }
+
+    /// The tags of `index_dir`; none if nothing was ever tagged
+    pub fn load(index_dir: &Path) -> Result<Self> {
+        let path = Self::path(index_dir);
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid tags file: {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the tags back to `index_dir`, replacing them atomically
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        let path = Self::path(index_dir);
+        let tmp = index_dir.join(format!("{}.{}.tmp", TAGS_FILE, std::process::id()));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to update {}", path.display()))
+    }
+
+    /// Attach `tag` to the file at `path`. Returns whether it didn't have it.
+    pub fn add(&mut self, tag: &str, path: &Path, hash: Option<&[u8; 32]>) -> bool {
+        let hash = hash.map(scanner::hash_hex);
+        let at = match self
+            .files
+            .binary_search_by(|file| file.path.as_path().cmp(path))
+        {
+            Ok(at) => at,
+            Err(at) => {
+                let file = TaggedFile {
+                    path: path.to_path_buf(),
+                    hash: None,
+                    tags: BTreeSet::new(),
+                };
+                self.files.insert(at, file);
+                at
+            }
+        };
+        let file = &mut self.files[at];
+        file.hash = hash.or(file.hash.take());
+        file.tags.insert(tag.to_string())
+    }
+
+    /// Take `tag` off the file at `path`. Returns whether it had it.
+    pub fn remove(&mut self, tag: &str, path: &Path) -> bool {
+        let Ok(at) = self
+            .files
+            .binary_search_by(|file| file.path.as_path().cmp(path))
+        else {
+            return false;
+        };
+        let removed = self.files[at].tags.remove(tag);
+        if self.files[at].tags.is_empty() {
+            self.files.remove(at);
+        }
+        removed
+    }
+
+    /// Every tag and how many files carry it
+    pub fn counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for tag in self.files.iter().flat_map(|file| &file.tags) {
+            *counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// The files carrying any of `tags`, failing on a tag no file has
+    pub fn select(&self, tags: &[String]) -> Result<Tagged> {
+        let counts = self.counts();
+        for tag in tags {
+            if !counts.contains_key(tag.as_str()) {
+                match suggest::closest(tag, counts.keys().copied()) {
+                    Some(known) => {
+                        anyhow::bail!("Unknown tag '{}'. Did you mean '{}'?", tag, known)
+                    }
+                    None => anyhow::bail!(
+                        "No file is tagged '{}'; tag some with `ss tag add {} <path>...`",
+                        tag,
+                        tag
+                    ),
+                }
+            }
+        }
+        let mut tagged = Tagged::default();
+        for file in &self.files {
+            if tags.iter().any(|tag| file.tags.contains(tag)) {
+                tagged.paths.insert(file.path.clone());
+                let hash = file
+                    .hash
+                    .as_deref()
+                    .and_then(|hex| blake3::Hash::from_hex(hex).ok());
+                tagged.hashes.extend(hash.map(|hash| *hash.as_bytes()));
+            }
+        }
+        Ok(tagged)
+    }
+
+    /// Move the tags of files that are no longer indexed where they were
+    /// tagged to the entry with the same content hash, if there's one.
+    /// Returns how many files were followed.
+    pub fn follow(&mut self, entries: &[FileEntry]) -> usize {
+        let indexed: HashSet<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+        let by_hash: HashMap<String, &Path> = entries
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    scanner::hash_hex(entry.hash.as_ref()?),
+                    entry.path.as_path(),
+                ))
+            })
+            .collect();
+        let mut moved = Vec::new();
+        self.files.retain(|file| {
+            let target = file
+                .hash
+                .as_ref()
+                .filter(|_| !indexed.contains(file.path.as_path()))
+                .and_then(|hash| by_hash.get(hash));
+            match target {
+                Some(path) => {
+                    moved.push((path.to_path_buf(), file.clone()));
+                    false
+                }
+                None => true,
+            }
+        });
+        let count = moved.len();
+        for (path, file) in moved {
+            for tag in &file.tags {
+                self.add(tag, &path, None);
+            }
+            if let Ok(at) = self.files.binary_search_by(|known| known.path.cmp(&path)) {
+                self.files[at].hash = self.files[at].hash.take().or(file.hash.clone());
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::IndexBuilder;
+
+    #[test]
+    fn test_tags_follow_moved_files() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let mut index = IndexBuilder::new("/work")
+            .file("notes.txt", 5)
+            .file("todo.txt", 3)
+            .build();
+        index.entries[0].hash = Some([7; 32]);
+        let (notes, todo) = (Path::new("/work/notes.txt"), Path::new("/work/todo.txt"));
+
+        let mut tags = Tags::load(index_dir.path()).unwrap();
+        assert!(tags.add("urgent", notes, index.entries[0].hash.as_ref()));
+        assert!(!tags.add("urgent", notes, None));
+        assert!(tags.add("later", todo, None));
+        assert!(tags.add("urgent", todo, None));
+        tags.save(index_dir.path()).unwrap();
+        let mut tags = Tags::load(index_dir.path()).unwrap();
+        assert_eq!(tags.counts(), BTreeMap::from([("later", 1), ("urgent", 2)]));
+
+        let urgent = tags.select(&["urgent".to_string()]).unwrap();
+        assert!(index.entries.iter().all(|entry| urgent.contains(entry)));
+        let error = tags.select(&["urgnt".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("Did you mean 'urgent'"));
+
+        // Renamed: found by its hash, then the tags move with it
+        index.entries[0].path = PathBuf::from("/work/done/notes.txt");
+        assert!(urgent.contains(&index.entries[0]));
+        assert_eq!(tags.follow(&index.entries), 1);
+        assert_eq!(tags.files[0].path, Path::new("/work/done/notes.txt"));
+        assert_eq!(tags.follow(&index.entries), 0);
+
+        assert!(tags.remove("later", todo));
+        assert!(!tags.remove("later", todo));
+        assert!(tags.remove("urgent", todo));
+        assert_eq!(tags.files.len(), 1);
+    }
+
+    #[test]
+    fn test_check_tag() {
+        assert!(check_tag("q3-report").is_ok());
+        assert!(check_tag("").is_err());
+        assert!(check_tag("to do").is_err());
+        assert!(check_tag("--all").is_err());
+    }
+}
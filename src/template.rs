@@ -0,0 +1,232 @@
+use anyhow::Result;
+use sonic_search::FileEntry;
+use sonic_search::grep::LineMatch;
+use sonic_search::perms;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// A value `--format` can fill in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Path,
+    Name,
+    Ext,
+    /// Size in bytes
+    Size,
+    /// Modification time in seconds since the Unix epoch
+    Mtime,
+    /// Permission bits in octal (Unix)
+    Mode,
+    /// Owning user and group, by name where they have one (Unix)
+    User,
+    Group,
+    Score,
+    /// 1-based number of the matching line
+    Line,
+    /// 1-based byte column of the first match in the line
+    Column,
+    /// Byte offset of the first match within the file
+    Offset,
+    /// The matching line itself
+    Text,
+    /// The first matched text in the line
+    Match,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("path", Field::Path),
+    ("name", Field::Name),
+    ("ext", Field::Ext),
+    ("size", Field::Size),
+    ("mtime", Field::Mtime),
+    ("mode", Field::Mode),
+    ("user", Field::User),
+    ("group", Field::Group),
+    ("score", Field::Score),
+    ("line", Field::Line),
+    ("column", Field::Column),
+    ("offset", Field::Offset),
+    ("text", Field::Text),
+    ("match", Field::Match),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// A `--format` template such as `{path}\t{size}`, printing one line per
+/// `find` result or `grep` match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// What a template is filled in from
+#[derive(Debug, Clone, Copy)]
+pub struct Values<'a> {
+    pub path: &'a Path,
+    /// The indexed entry, when the file has one
+    pub entry: Option<&'a FileEntry>,
+    pub score: Option<i64>,
+    pub line: Option<&'a LineMatch>,
+}
+
+impl Template {
+    /// Parse `{field}` placeholders, `{{` and `}}` for literal braces and
+    /// the escapes `\t`, `\n`, `\0` and `\\`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('0') => literal.push('\0'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => literal.extend(['\\', other]),
+                    None => literal.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        anyhow::bail!("Invalid format '{}': unclosed {{", text);
+                    };
+                    let name = &rest[..end];
+                    let Some(&(_, field)) = FIELDS.iter().find(|(known, _)| *known == name) else {
+                        let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                        anyhow::bail!(
+                            "Invalid format '{}': unknown field {{{}}}, expected one of {}",
+                            text,
+                            name,
+                            names.join(", ")
+                        );
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// The template filled in from `values`; fields they don't have, such
+    /// as `{line}` for a `find` result, are left empty
+    pub fn render(&self, values: &Values) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Field(field) => out.push_str(&value(*field, values).unwrap_or_default()),
+            }
+        }
+        out
+    }
+}
+
+fn value(field: Field, values: &Values) -> Option<String> {
+    let first_match = || values.line?.spans.first().cloned();
+    Some(match field {
+        Field::Path => values.path.display().to_string(),
+        Field::Name => values.path.file_name()?.to_string_lossy().into_owned(),
+        Field::Ext => values.path.extension()?.to_string_lossy().into_owned(),
+        Field::Size => values.entry?.size.to_string(),
+        Field::Mtime => {
+            let modified = values.entry?.modified?;
+            modified
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_secs()
+                .to_string()
+        }
+        Field::Mode => format!("{:03o}", values.entry?.attributes.mode?),
+        Field::User => perms::user_label(values.entry?.attributes.uid?),
+        Field::Group => perms::group_label(values.entry?.attributes.gid?),
+        Field::Score => values.score?.to_string(),
+        Field::Line => values.line?.line_number.to_string(),
+        Field::Column => values.line?.column().to_string(),
+        Field::Offset => values.line?.byte_offset().to_string(),
+        Field::Text => values.line?.line.clone(),
+        Field::Match => values.line?.line.get(first_match()?)?.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_templates() {
+        let path = Path::new("/src/report.tar.gz");
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let entry = FileEntry {
+            attributes: perms::Attributes {
+                uid: Some(4_000_000),
+                mode: Some(0o640),
+                ..Default::default()
+            },
+            ..FileEntry::new(path, false, 2048, modified)
+        };
+        let found = Values {
+            path,
+            entry: Some(&entry),
+            score: Some(42),
+            line: None,
+        };
+        let template = Template::parse(r"{path}\t{size}\t{mtime} {{{score}}} .{ext}").unwrap();
+        assert_eq!(
+            template.render(&found),
+            "/src/report.tar.gz\t2048\t1700000000 {42} .gz"
+        );
+        assert_eq!(
+            Template::parse("{mode} {user}:{group}")
+                .unwrap()
+                .render(&found),
+            "640 4000000:"
+        );
+        // Line fields are empty for find results
+        assert_eq!(
+            Template::parse("{name}:{line}").unwrap().render(&found),
+            "report.tar.gz:"
+        );
+
+        let m = LineMatch {
+            line_number: 7,
+            line: "version = \"1.2.3\" # was 1.2.2".to_string(),
+            spans: vec![11..16, 24..29],
+            offset: 100,
+            before: Vec::new(),
+            after: Vec::new(),
+        };
+        let matched = Values {
+            path: Path::new("Cargo.toml"),
+            entry: None,
+            score: None,
+            line: Some(&m),
+        };
+        let template = Template::parse("{path}:{line}:{column}:{offset}: {match}").unwrap();
+        assert_eq!(template.render(&matched), "Cargo.toml:7:12:111: 1.2.3");
+
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{path").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Build indexes in memory for tests, without scanning a real tree.
+//!
+//! ```
+//! use sonic_search::SearchOptions;
+//! use sonic_search::testing::IndexBuilder;
+//!
+//! let index = IndexBuilder::new("/work")
+//!     .spec(
+//!         "src/main.rs      2K   2024-03-01
+//!          docs/budget.xlsx 40K
+//!          target/",
+//!     )?
+//!     .build();
+//! let results = index.search("budget", &SearchOptions::default())?;
+//! assert_eq!(results[0].entry.name, "budget.xlsx");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::fixture::{self, NameStyle, Rng};
+use crate::index::{Index, IndexMeta};
+use crate::projects::{self, Project, ProjectKind};
+use crate::scanner::{FileEntry, ScanOptions};
+use crate::{filetype, normalize, units, vendor};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Declares the entries of an [`Index`] one by one, as if a scan of `root`
+/// had found them.
+///
+/// Everything left unspecified, such as the modification time of a file,
+/// is drawn from a seed, so the same calls always build the same index.
+/// Parent directories are added along with their files, and declaring a
+/// path twice keeps the last declaration.
+#[derive(Debug)]
+pub struct IndexBuilder {
+    root: PathBuf,
+    rng: Rng,
+    options: ScanOptions,
+    entries: BTreeMap<PathBuf, FileEntry>,
+    projects: Vec<Project>,
+}
+
+impl IndexBuilder {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        IndexBuilder {
+            root: root.into(),
+            rng: Rng::new(0),
+            options: ScanOptions::default(),
+            entries: BTreeMap::new(),
+            projects: Vec::new(),
+        }
+    }
+
+    /// Draw what's left unspecified from `seed` from here on; 0 until then
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Record `options` as the ones the index was scanned with. Names are
+    /// transliterated with [`ScanOptions::transliterate`]; nothing is hashed,
+    /// since there's nothing to read.
+    pub fn options(mut self, options: ScanOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// A file of `size` bytes at `path`, relative to the root
+    pub fn file(mut self, path: impl AsRef<Path>, size: u64) -> Self {
+        let modified = self.random_mtime();
+        self.add(path.as_ref(), size, false, modified);
+        self
+    }
+
+    /// A file of `size` bytes at `path`, last modified at `modified`
+    pub fn file_modified(
+        mut self,
+        path: impl AsRef<Path>,
+        size: u64,
+        modified: SystemTime,
+    ) -> Self {
+        self.add(path.as_ref(), size, false, modified);
+        self
+    }
+
+    /// A directory at `path`, relative to the root
+    pub fn dir(mut self, path: impl AsRef<Path>) -> Self {
+        let modified = self.random_mtime();
+        self.add(path.as_ref(), 0, true, modified);
+        self
+    }
+
+    /// `count` files named in `style`, spread over directories a few levels
+    /// deep, as [`fixture::generate`] would name them
+    pub fn random_files(mut self, count: usize, style: NameStyle) -> Self {
+        for _ in 0..count {
+            let style = match style {
+                NameStyle::Mixed => [NameStyle::Code, NameStyle::Documents, NameStyle::Media]
+                    [self.rng.below(3) as usize],
+                style => style,
+            };
+            let mut path = PathBuf::new();
+            for _ in 0..self.rng.below(4) {
+                path.push(self.rng.pick(fixture::DIR_WORDS));
+            }
+            let (stem, extension) = fixture::file_name(&mut self.rng, style);
+            path.push(format!("{}.{}", stem, extension));
+            let size = self.rng.below(1 << 20);
+            self = self.file(path, size);
+        }
+        self
+    }
+
+    /// A sub-project named `name` with its manifest in `dir`, relative to
+    /// the root. Unlike a scan, the builder doesn't read manifests, so
+    /// projects are only there when declared.
+    pub fn project(mut self, dir: impl AsRef<Path>, name: &str, kind: ProjectKind) -> Self {
+        let root = self.root.join(dir);
+        self.projects.retain(|project| project.root != root);
+        self.projects.push(Project {
+            name: name.to_string(),
+            root,
+            kind,
+        });
+        self
+    }
+
+    /// The entries listed in `spec`, one per line: a path relative to the
+    /// root, a `/` at its end for a directory, followed by a size such as
+    /// `12K` and a date such as `2024-03-01`, either of which may be left
+    /// out. Blank lines and lines starting with `#` are skipped.
+    ///
+    /// Paths may hold spaces; a last word that reads as a size or a date is
+    /// taken as one.
+    pub fn spec(mut self, spec: &str) -> Result<Self> {
+        for (number, line) in spec.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self = self
+                .spec_line(line)
+                .with_context(|| format!("Invalid fixture line {}: {}", number + 1, line))?;
+        }
+        Ok(self)
+    }
+
+    fn spec_line(mut self, line: &str) -> Result<Self> {
+        let mut path = line;
+        let mut modified = None;
+        let mut size = None;
+        if let Some((rest, last)) = path.rsplit_once(char::is_whitespace)
+            && let Some(time) = units::parse_date(last)
+        {
+            modified = Some(time);
+            path = rest.trim_end();
+        }
+        if let Some((rest, last)) = path.rsplit_once(char::is_whitespace)
+            && let Ok(bytes) = units::parse_size(last)
+        {
+            size = Some(bytes);
+            path = rest.trim_end();
+        }
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            anyhow::bail!("paths must be relative to the root, without `..`");
+        }
+        let is_dir = path.ends_with('/');
+        if is_dir && size.is_some() {
+            anyhow::bail!("directories have no size");
+        }
+        let modified = modified.unwrap_or_else(|| self.random_mtime());
+        self.add(relative, size.unwrap_or(0), is_dir, modified);
+        Ok(self)
+    }
+
+    /// Build the index, tagging entries with their projects, whether
+    /// they're vendored and their MIME type by extension
+    pub fn build(self) -> Index {
+        let created = UNIX_EPOCH + Duration::from_secs(fixture::NEWEST_MTIME);
+        let mut entries: Vec<FileEntry> = self.entries.into_values().collect();
+        let mut projects = self.projects;
+        projects.sort_by(|a, b| a.root.cmp(&b.root));
+        projects::assign(&mut entries, &projects);
+        vendor::assign_with(&self.root, &[], &mut entries);
+        filetype::assign(&mut entries, false);
+        if self.options.transliterate {
+            for entry in entries.iter_mut().filter(|entry| !entry.name.is_ascii()) {
+                entry.folded_name = normalize::latin_name(&entry.name);
+            }
+        }
+        Index {
+            meta: IndexMeta {
+                root: self.root,
+                created_at: created,
+                updated_at: created,
+                dir_count: entries.iter().filter(|entry| entry.is_dir).count() + 1,
+                generation: 0,
+                scan_options: self.options,
+                partial: false,
+                journal: None,
+            },
+            entries,
+            projects,
+            owner_sets: Vec::new(),
+            repos: Vec::new(),
+            content: None,
+        }
+    }
+
+    fn random_mtime(&mut self) -> SystemTime {
+        let secs = fixture::NEWEST_MTIME - self.rng.below(fixture::MTIME_SPREAD);
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// Add an entry along with any of its parents not declared yet
+    fn add(&mut self, relative: &Path, size: u64, is_dir: bool, modified: SystemTime) {
+        let path = self.root.join(relative);
+        for parent in path.ancestors().skip(1) {
+            if parent == self.root || !parent.starts_with(&self.root) {
+                break;
+            }
+            if !self.entries.contains_key(parent) {
+                self.entries
+                    .insert(parent.to_path_buf(), entry(parent, 0, true, modified));
+            }
+        }
+        self.entries
+            .insert(path.clone(), entry(&path, size, is_dir, modified));
+    }
+}
+
+fn entry(path: &Path, size: u64, is_dir: bool, modified: SystemTime) -> FileEntry {
+    FileEntry::new(path, is_dir, size, Some(modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Filters, SearchOptions};
+
+    #[test]
+    fn test_index_builder() {
+        let index = IndexBuilder::new("/work")
+            .spec(
+                "# a small workspace
+                 Cargo.toml                 1K
+                 src/main.rs                2.5K  2024-03-01
+                 vendor/zlib/inflate.c      90K
+                 docs/meeting notes.txt
+                 build/",
+            )
+            .unwrap()
+            .project("", "app", ProjectKind::Cargo)
+            .build();
+
+        let names: Vec<&str> = index.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Cargo.toml",
+                "build",
+                "docs",
+                "meeting notes.txt",
+                "src",
+                "main.rs",
+                "vendor",
+                "zlib",
+                "inflate.c"
+            ]
+        );
+        let main = &index.entries[5];
+        assert_eq!(main.size, 2560);
+        assert_eq!(main.modified, units::parse_date("2024-03-01"));
+        assert_eq!(main.project, Some(0));
+        assert!(index.entries[8].vendored);
+        assert_eq!(index.meta.dir_count, 6);
+
+        let results = index.search("inflate", &SearchOptions::default()).unwrap();
+        assert!(results.is_empty(), "vendored code is left out by default");
+        let options = SearchOptions {
+            filters: Filters {
+                include_vendored: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(index.search("inflate", &options).unwrap().len(), 1);
+
+        assert!(IndexBuilder::new("/work").spec("../escape.txt").is_err());
+        assert!(IndexBuilder::new("/work").spec("build/ 4K").is_err());
+    }
+
+    #[test]
+    fn test_seeded_builds_are_identical() {
+        let build = |seed| {
+            IndexBuilder::new("/fixture")
+                .seed(seed)
+                .random_files(200, NameStyle::Mixed)
+                .file("README.md", 10)
+                .build()
+        };
+        let paths = |index: &Index| -> Vec<(PathBuf, Option<SystemTime>)> {
+            index
+                .entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.modified))
+                .collect()
+        };
+        let first = build(7);
+        assert_eq!(paths(&first), paths(&build(7)));
+        assert_ne!(paths(&first), paths(&build(8)));
+        assert!(first.file_count() > 150, "few names should collide");
+    }
+}
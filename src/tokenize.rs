@@ -0,0 +1,371 @@
+//! How source code is split into words for the full-text index: each
+//! identifier is broken at its `camelCase`, `snake_case` and `kebab-case`
+//! boundaries, so `getUserName`, `get_user_name` and `get-user-name` all
+//! index as the words `get user name`, and comments can be left out.
+//!
+//! Each language is a [`Tokenizer`] picked by file extension from
+//! [`TOKENIZERS`]; a new one only needs adding there. The trigram content
+//! index also keeps every identifier's words run together (see
+//! [`joined_identifiers`]), so a plain `grep getUserName` finds the other
+//! spellings too.
+
+use std::ops::Range;
+use std::path::Path;
+
+/// A lowercase word of a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    /// Where it is in the source, in bytes
+    pub span: Range<usize>,
+}
+
+/// How [`Tokenizer::tokens`] reads a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenOptions {
+    /// Leave comments out
+    pub strip_comments: bool,
+}
+
+/// Splits the source of a language into words
+pub trait Tokenizer: Send + Sync {
+    /// Lowercase extensions of the files it reads
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// What starts a comment that runs to the end of the line, e.g. `//`
+    fn line_comments(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// What starts and ends a block comment, e.g. `/*` and `*/`
+    fn block_comments(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// What opens and closes a string literal, inside which comment
+    /// markers don't count
+    fn quotes(&self) -> &'static [char] {
+        &['"']
+    }
+
+    /// The words of `source`, in order
+    fn tokens(&self, source: &str, options: TokenOptions) -> Vec<Token> {
+        if !options.strip_comments {
+            return words(source);
+        }
+        code_ranges(self, source)
+            .into_iter()
+            .flat_map(|range| {
+                let start = range.start;
+                words(&source[range]).into_iter().map(move |token| Token {
+                    span: token.span.start + start..token.span.end + start,
+                    ..token
+                })
+            })
+            .collect()
+    }
+}
+
+/// A language told apart by its comment syntax alone
+pub struct Syntax {
+    pub extensions: &'static [&'static str],
+    pub line_comments: &'static [&'static str],
+    pub block_comments: &'static [(&'static str, &'static str)],
+    pub quotes: &'static [char],
+}
+
+impl Tokenizer for Syntax {
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn line_comments(&self) -> &'static [&'static str] {
+        self.line_comments
+    }
+
+    fn block_comments(&self) -> &'static [(&'static str, &'static str)] {
+        self.block_comments
+    }
+
+    fn quotes(&self) -> &'static [char] {
+        self.quotes
+    }
+}
+
+/// C and the languages that took its comments
+pub const C_LIKE: Syntax = Syntax {
+    extensions: &[
+        "rs", "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "mm", "java", "kt", "kts", "scala",
+        "swift", "go", "cs", "js", "mjs", "cjs", "jsx", "ts", "tsx", "dart", "zig", "php", "proto",
+        "groovy", "gradle", "scss", "less",
+    ],
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
+/// Shells and scripting languages, commented with `#`
+pub const HASH: Syntax = Syntax {
+    extensions: &[
+        "py", "pyi", "rb", "sh", "bash", "zsh", "fish", "pl", "pm", "r", "toml", "yaml", "yml",
+        "cmake", "nix", "ex", "exs",
+    ],
+    line_comments: &["#"],
+    block_comments: &[],
+    quotes: &['"', '\''],
+};
+
+pub const CSS: Syntax = Syntax {
+    extensions: &["css"],
+    line_comments: &[],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"', '\''],
+};
+
+pub const SQL: Syntax = Syntax {
+    extensions: &["sql"],
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"', '\''],
+};
+
+pub const LUA: Syntax = Syntax {
+    extensions: &["lua"],
+    line_comments: &["--"],
+    block_comments: &[("--[[", "]]")],
+    quotes: &['"', '\''],
+};
+
+pub const HASKELL: Syntax = Syntax {
+    extensions: &["hs", "elm"],
+    line_comments: &["--"],
+    block_comments: &[("{-", "-}")],
+    quotes: &['"'],
+};
+
+/// Lisps, whose `kebab-case` names split like any other
+pub const LISP: Syntax = Syntax {
+    extensions: &["clj", "cljs", "cljc", "edn", "el", "lisp", "scm", "rkt"],
+    line_comments: &[";"],
+    block_comments: &[],
+    quotes: &['"'],
+};
+
+pub const MARKUP: Syntax = Syntax {
+    extensions: &["xml", "svg", "vue", "svelte", "xaml", "plist"],
+    line_comments: &[],
+    block_comments: &[("<!--", "-->")],
+    quotes: &['"'],
+};
+
+/// The tokenizers files are indexed with, by extension
+pub const TOKENIZERS: &[&dyn Tokenizer] =
+    &[&C_LIKE, &CSS, &HASH, &SQL, &LUA, &HASKELL, &LISP, &MARKUP];
+
+/// The tokenizer among [`TOKENIZERS`] for `path`, by its extension
+pub fn for_path(path: &Path) -> Option<&'static dyn Tokenizer> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    TOKENIZERS
+        .iter()
+        .copied()
+        .find(|tokenizer| tokenizer.extensions().contains(&ext.as_str()))
+}
+
+/// The words of `text` in any language, comments and all: what queries are
+/// split into to look them up
+pub fn words(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    let mut offset = 0;
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        let len = rest[start..]
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len() - start);
+        let identifier = &rest[start..start + len];
+        for word in split_identifier(identifier) {
+            let span = offset + start + word.start..offset + start + word.end;
+            tokens.push(Token {
+                text: identifier[word].to_lowercase(),
+                span,
+            });
+        }
+        offset += start + len;
+        rest = &rest[start + len..];
+    }
+    tokens
+}
+
+/// Each identifier among `tokens`, the words of `source`, with its words run
+/// together, one per line: `get_user_name`, `getUserName` and `get-user-name`
+/// all give `getusername`, which is how the content index looks them up
+pub fn joined_identifiers(source: &str, tokens: &[Token]) -> String {
+    let mut joined = String::new();
+    let mut end = None;
+    for token in tokens {
+        let continues = end.is_some_and(|end| {
+            source[end..token.span.start]
+                .bytes()
+                .all(|b| b == b'_' || b == b'-')
+        });
+        if !continues && !joined.is_empty() {
+            joined.push('\n');
+        }
+        joined.push_str(&token.text);
+        end = Some(token.span.end);
+    }
+    joined
+}
+
+/// Where the words of `identifier` are: split at underscores and at each
+/// change to upper case, keeping runs of capitals such as `HTTP` in
+/// `HTTPServer` together and digits with what comes before them
+pub fn split_identifier(identifier: &str) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = identifier.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = None;
+    for (k, &(i, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(start) = start.take() {
+                words.push(start..i);
+            }
+            continue;
+        }
+        let Some(from) = start else {
+            start = Some(i);
+            continue;
+        };
+        let prev = chars[k - 1].1;
+        let next = chars.get(k + 1).map(|&(_, c)| c);
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase()
+                || prev.is_numeric()
+                || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+        if boundary {
+            words.push(from..i);
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        words.push(start..identifier.len());
+    }
+    words
+}
+
+/// The parts of `source` that aren't comments, by `tokenizer`'s syntax
+fn code_ranges<T: Tokenizer + ?Sized>(tokenizer: &T, source: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut code_start = 0;
+    let mut quote = None;
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        let rest = &source[i..];
+        if let Some(open) = quote {
+            let mut len = c.len_utf8();
+            match c {
+                '\\' => len += rest[1..].chars().next().map_or(0, char::len_utf8),
+                // An unterminated string ends with its line
+                '\n' => quote = None,
+                _ if c == open => quote = None,
+                _ => {}
+            }
+            i += len;
+            continue;
+        }
+        if let Some(len) = comment_len(tokenizer, rest) {
+            ranges.push(code_start..i);
+            i += len;
+            code_start = i;
+            continue;
+        }
+        if tokenizer.quotes().contains(&c) {
+            quote = Some(c);
+        }
+        i += c.len_utf8();
+    }
+    ranges.push(code_start..source.len());
+    ranges.retain(|range| !range.is_empty());
+    ranges
+}
+
+/// How long the comment `rest` starts with is, if it starts with one
+fn comment_len<T: Tokenizer + ?Sized>(tokenizer: &T, rest: &str) -> Option<usize> {
+    let block = tokenizer
+        .block_comments()
+        .iter()
+        .find(|(start, _)| rest.starts_with(start));
+    if let Some((start, end)) = block {
+        let len = rest[start.len()..]
+            .find(end)
+            .map_or(rest.len(), |at| start.len() + at + end.len());
+        return Some(len);
+    }
+    tokenizer
+        .line_comments()
+        .iter()
+        .any(|start| rest.starts_with(start))
+        .then(|| rest.find('\n').unwrap_or(rest.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: Vec<Token>) -> Vec<String> {
+        tokens.into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn test_identifiers_split_alike() {
+        for name in [
+            "getUserName",
+            "get_user_name",
+            "GetUserName",
+            "get-user-name",
+        ] {
+            assert_eq!(texts(words(name)), ["get", "user", "name"], "{}", name);
+        }
+        assert_eq!(texts(words("HTTPServer")), ["http", "server"]);
+        assert_eq!(
+            texts(words("parseJSON2 utf8Decoder")),
+            ["parse", "json2", "utf8", "decoder"]
+        );
+        let tokens = words("let ünïcodeName = 1;");
+        assert_eq!(texts(tokens.clone()), ["let", "ünïcode", "name", "1"]);
+        assert_eq!(&"let ünïcodeName = 1;"[tokens[2].span.clone()], "Name");
+    }
+
+    #[test]
+    fn test_joined_identifiers() {
+        let source = "let get_user_name = getUserName(HTTP-server); x";
+        assert_eq!(
+            joined_identifiers(source, &words(source)),
+            "let\ngetusername\ngetusername\nhttpserver\nx"
+        );
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let rust = for_path(Path::new("src/Main.RS")).unwrap();
+        let source = "let url = \"http://x\"; // todo fixMe\n/* old\ncode */ done()";
+        let strip = TokenOptions {
+            strip_comments: true,
+        };
+        assert_eq!(
+            texts(rust.tokens(source, strip)),
+            ["let", "url", "http", "x", "done"]
+        );
+        assert_eq!(rust.tokens(source, TokenOptions::default()).len(), 10);
+
+        let python = for_path(Path::new("app.py")).unwrap();
+        assert_eq!(
+            texts(python.tokens("x = 'a#b'  # isn't\ny = 1", strip)),
+            ["x", "a", "b", "y", "1"]
+        );
+        let lua = for_path(Path::new("init.lua")).unwrap();
+        assert_eq!(
+            texts(lua.tokens("a --[[ b\n c ]] d -- e\nf", strip)),
+            ["a", "d", "f"]
+        );
+        assert!(for_path(Path::new("notes.md")).is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::scanner::FileEntry;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether matched files are copied or moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Copy,
+    Move,
+}
+
+/// What to do when a file of the same name already exists at the destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Conflict {
+    /// Leave the existing file alone and don't transfer the match
+    #[default]
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Transfer under a free name such as `photo (1).jpg`
+    Rename,
+}
+
+/// How one matched file is transferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing is in the way
+    New,
+    /// Replaces an existing file
+    Overwrite,
+    /// Goes to a new name because the original one is taken
+    Renamed,
+    /// Left where it is because the name is taken, or it already is the destination
+    Skipped,
+}
+
+/// A planned transfer of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// Plan transferring `entries` into `dest`, each under its file name.
+///
+/// Matches sharing a name conflict with each other just as with files
+/// already in `dest`, so the plan never writes one destination twice.
+pub fn plan(entries: &[&FileEntry], dest: &Path, conflict: Conflict) -> Vec<Transfer> {
+    let mut taken = HashSet::new();
+    let mut transfers = Vec::new();
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        let to = dest.join(&entry.name);
+        let is_taken = |path: &Path| taken.contains(path) || path.exists();
+        let (to, outcome) = if to == entry.path {
+            (to, Outcome::Skipped)
+        } else if !is_taken(&to) {
+            (to, Outcome::New)
+        } else {
+            match conflict {
+                Conflict::Skip => (to, Outcome::Skipped),
+                // Two matches with the same name must not replace each other
+                Conflict::Overwrite if !taken.contains(&to) => (to, Outcome::Overwrite),
+                Conflict::Overwrite | Conflict::Rename => {
+                    let to = (1..)
+                        .map(|n| dest.join(numbered(&entry.name, n)))
+                        .find(|path| !is_taken(path))
+                        .unwrap();
+                    (to, Outcome::Renamed)
+                }
+            }
+        };
+        if outcome != Outcome::Skipped {
+            taken.insert(to.clone());
+        }
+        transfers.push(Transfer {
+            from: entry.path.clone(),
+            to,
+            outcome,
+        });
+    }
+    transfers
+}
+
+/// `name` with ` (n)` inserted before its extension
+fn numbered(name: &str, n: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, extension),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// Carry out `transfers`, creating the destination directories as needed
+pub fn apply(transfers: &[Transfer], mode: Mode) -> Result<()> {
+    for transfer in transfers {
+        if transfer.outcome == Outcome::Skipped {
+            continue;
+        }
+        if let Some(parent) = transfer.to.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let context = || {
+            format!(
+                "Failed to transfer {} to {}",
+                transfer.from.display(),
+                transfer.to.display()
+            )
+        };
+        match mode {
+            Mode::Copy => {
+                fs::copy(&transfer.from, &transfer.to).with_context(context)?;
+            }
+            // Renaming fails across filesystems, so fall back to copying
+            Mode::Move => {
+                if fs::rename(&transfer.from, &transfer.to).is_err() {
+                    fs::copy(&transfer.from, &transfer.to).with_context(context)?;
+                    fs::remove_file(&transfer.from).with_context(context)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_plan_and_apply_conflicts() {
+        let root = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("a")).unwrap();
+        fs::create_dir_all(root.path().join("b")).unwrap();
+        fs::write(root.path().join("a/photo.jpg"), "first").unwrap();
+        fs::write(root.path().join("b/photo.jpg"), "second").unwrap();
+        fs::write(root.path().join("notes.txt"), "notes").unwrap();
+        fs::write(dest.path().join("notes.txt"), "old").unwrap();
+        let entries: Vec<FileEntry> = ["a/photo.jpg", "b/photo.jpg", "notes.txt"]
+            .iter()
+            .map(|name| scanner::entry_for_file(&root.path().join(name)).unwrap())
+            .collect();
+        let entries: Vec<&FileEntry> = entries.iter().collect();
+
+        let outcomes = |conflict| -> Vec<(String, Outcome)> {
+            plan(&entries, dest.path(), conflict)
+                .into_iter()
+                .map(|t| {
+                    (
+                        t.to.file_name().unwrap().to_string_lossy().into(),
+                        t.outcome,
+                    )
+                })
+                .collect()
+        };
+        let pair = |name: &str, outcome| (name.to_string(), outcome);
+        assert_eq!(
+            outcomes(Conflict::Skip),
+            vec![
+                pair("photo.jpg", Outcome::New),
+                pair("photo.jpg", Outcome::Skipped),
+                pair("notes.txt", Outcome::Skipped),
+            ]
+        );
+        assert_eq!(
+            outcomes(Conflict::Overwrite),
+            vec![
+                pair("photo.jpg", Outcome::New),
+                pair("photo (1).jpg", Outcome::Renamed),
+                pair("notes.txt", Outcome::Overwrite),
+            ]
+        );
+        assert_eq!(numbered("README", 2), "README (2)");
+        assert_eq!(numbered(".env", 1), ".env (1)");
+
+        let transfers = plan(&entries, dest.path(), Conflict::Rename);
+        apply(&transfers, Mode::Move).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.path().join("photo (1).jpg")).unwrap(),
+            "second"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.path().join("notes (1).txt")).unwrap(),
+            "notes"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.path().join("notes.txt")).unwrap(),
+            "old"
+        );
+        assert!(!root.path().join("a/photo.jpg").exists());
+    }
+}
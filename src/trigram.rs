@@ -0,0 +1,740 @@
+use crate::archive;
+use crate::extract;
+use crate::grep;
+use crate::lang;
+use crate::memory;
+use crate::scanner::FileEntry;
+use crate::tokenize::{self, TokenOptions};
+use rayon::prelude::*;
+use regex_syntax::hir::Hir;
+use regex_syntax::hir::literal::{ExtractKind, Extractor, Seq};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+
+/// Files larger than this are not tokenized and are always grep candidates
+pub const DEFAULT_MAX_CONTENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Files each thread tokenizes at a time, which bounds how many trigram
+/// lists are held before they join the postings being sorted
+const FILES_PER_THREAD: usize = 64;
+
+/// Share of the files above which narrowing on a pattern's trigrams isn't
+/// worth it: grep would read nearly as many files as a plain scan, on top of
+/// intersecting long posting lists
+pub const MAX_SELECTIVITY: f64 = 0.5;
+
+/// Indexes with fewer files than this are always narrowed, since either
+/// way takes next to no time
+const MIN_PLANNED_FILES: usize = 256;
+
+/// A trigram and the id of a document containing it
+type Posting = (u32, u32);
+
+/// Bytes a [`Posting`] takes up, in memory and in a run on disk
+const POSTING_LEN: usize = 8;
+
+/// Inverted index from byte trigrams to the entries containing them.
+///
+/// Entry ids are positions in [`crate::index::Index::entries`]. Trigrams are
+/// ASCII-lowercased so the same index serves case-sensitive and
+/// case-insensitive searches; the regex still verifies every candidate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrigramIndex {
+    postings: HashMap<u32, Vec<u32>>,
+    /// Entries that couldn't be tokenized (too large, unreadable) and must
+    /// always be searched
+    unindexed: Vec<u32>,
+    /// Entries whose contents are binary and are never grep results
+    binary: Vec<u32>,
+    /// Documents by the language they're written in, as ISO 639-1 codes
+    languages: HashMap<String, Vec<u32>>,
+    max_content_size: u64,
+}
+
+/// Outcome of tokenizing a single file
+enum FileTrigrams {
+    /// Trigrams of a text file, and its language if it's a document
+    Text(Vec<u32>, Option<&'static str>),
+    Binary,
+    Unindexed,
+}
+
+impl TrigramIndex {
+    /// Tokenize every entry in parallel and build the postings, within
+    /// `memory_budget` bytes as [`TrigramIndex::add`] explains
+    pub fn build(
+        entries: &[FileEntry],
+        max_content_size: u64,
+        memory_budget: Option<u64>,
+    ) -> io::Result<Self> {
+        let mut index = TrigramIndex {
+            max_content_size,
+            ..Default::default()
+        };
+        let ids: Vec<u32> = (0..entries.len() as u32).collect();
+        index.add(entries, &ids, memory_budget)?;
+        Ok(index)
+    }
+
+    /// Tokenize `ids` (positions in `entries`) and merge them into the
+    /// postings. Directories have no contents and are never candidates.
+    ///
+    /// Trigram occurrences are gathered and sorted before joining the
+    /// postings, so each list is allocated at its final size. With a
+    /// `memory_budget`, every budget's worth is sorted into a run in a
+    /// temporary file and the runs are merged at the end, which bounds what
+    /// building takes on top of the finished postings. Fails only if a run
+    /// can't be written or read back.
+    pub fn add(
+        &mut self,
+        entries: &[FileEntry],
+        ids: &[u32],
+        memory_budget: Option<u64>,
+    ) -> io::Result<()> {
+        let max_size = self.max_content_size;
+        let ids: Vec<u32> = ids
+            .iter()
+            .copied()
+            .filter(|&id| !entries[id as usize].is_dir)
+            .collect();
+        let mut runs = Runs::new(memory_budget);
+        for chunk in ids.chunks(FILES_PER_THREAD * rayon::current_num_threads()) {
+            let tokenized: Vec<(u32, FileTrigrams)> = chunk
+                .par_iter()
+                .map(|&id| (id, file_trigrams(&entries[id as usize], max_size)))
+                .collect();
+            for (id, trigrams) in tokenized {
+                match trigrams {
+                    FileTrigrams::Text(trigrams, language) => {
+                        for trigram in trigrams {
+                            runs.push((trigram, id))?;
+                        }
+                        if let Some(language) = language {
+                            self.languages
+                                .entry(language.to_string())
+                                .or_default()
+                                .push(id);
+                        }
+                    }
+                    FileTrigrams::Binary => self.binary.push(id),
+                    FileTrigrams::Unindexed => self.unindexed.push(id),
+                }
+            }
+        }
+        runs.merge_into(&mut self.postings)?;
+        for list in self
+            .postings
+            .values_mut()
+            .chain(self.languages.values_mut())
+        {
+            list.sort_unstable();
+        }
+        self.unindexed.sort_unstable();
+        self.binary.sort_unstable();
+        Ok(())
+    }
+
+    /// Add one document given by its contents rather than an entry on disk.
+    ///
+    /// `id` must be larger than every id added so far; `None` marks contents
+    /// that couldn't be read, which are then always candidates.
+    pub fn push(&mut self, id: u32, contents: Option<&[u8]>) {
+        match contents.map(|contents| classify(contents, None, false)) {
+            Some(FileTrigrams::Text(trigrams, _)) => {
+                for trigram in trigrams {
+                    self.postings.entry(trigram).or_default().push(id);
+                }
+            }
+            Some(FileTrigrams::Binary) => self.binary.push(id),
+            Some(FileTrigrams::Unindexed) | None => self.unindexed.push(id),
+        }
+    }
+
+    /// Renumber entries after the entry list changed.
+    ///
+    /// `mapping[old_id]` is the new id of an entry whose contents are
+    /// unchanged, or `None` if it was removed or must be re-tokenized.
+    pub fn remap(&mut self, mapping: &[Option<u32>]) {
+        let remap_list = |list: &mut Vec<u32>| {
+            let mut remapped: Vec<u32> = list
+                .iter()
+                .filter_map(|&old| mapping.get(old as usize).copied().flatten())
+                .collect();
+            remapped.sort_unstable();
+            *list = remapped;
+        };
+        for list in self
+            .postings
+            .values_mut()
+            .chain(self.languages.values_mut())
+        {
+            remap_list(list);
+        }
+        self.postings.retain(|_, list| !list.is_empty());
+        self.languages.retain(|_, list| !list.is_empty());
+        remap_list(&mut self.unindexed);
+        remap_list(&mut self.binary);
+    }
+
+    /// Size of the largest file whose contents were indexed
+    pub fn max_content_size(&self) -> u64 {
+        self.max_content_size
+    }
+
+    /// Number of distinct trigrams stored
+    pub fn trigram_count(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Approximate bytes of memory the postings and document lists take up
+    pub fn heap_size(&self) -> usize {
+        let postings: usize = self.postings.values().map(memory::vec_bytes).sum();
+        let languages: usize = self
+            .languages
+            .iter()
+            .map(|(language, docs)| language.capacity() + memory::vec_bytes(docs))
+            .sum();
+        memory::map_bytes(&self.postings)
+            + postings
+            + memory::vec_bytes(&self.unindexed)
+            + memory::vec_bytes(&self.binary)
+            + memory::map_bytes(&self.languages)
+            + languages
+    }
+
+    /// Ids of the documents detected to be written in `language`, an ISO
+    /// 639-1 code, in ascending order
+    pub fn with_language(&self, language: &str) -> &[u32] {
+        self.languages.get(language).map_or(&[], Vec::as_slice)
+    }
+
+    /// Number of documents per detected language, most common first
+    pub fn language_counts(&self) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = self
+            .languages
+            .iter()
+            .map(|(language, ids)| (language.as_str(), ids.len()))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+
+    /// Candidate entry ids possibly matching `pattern`, in ascending order.
+    ///
+    /// Returns `None` when the pattern has no literal long enough to filter
+    /// on, in which case every entry must be searched.
+    pub fn candidates(&self, pattern: &str, ignore_case: bool) -> Option<Vec<u32>> {
+        // Literals are extracted case-sensitively: trigrams are ASCII-folded
+        // on both sides, which already covers case-insensitive ASCII matching.
+        let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+
+        // Try both ends of the pattern and keep whichever filters harder
+        let best = [ExtractKind::Prefix, ExtractKind::Suffix]
+            .into_iter()
+            .filter_map(|kind| self.candidates_for(&hir, kind, ignore_case))
+            .min_by_key(Vec::len)?;
+
+        Some(merge_sorted(&best, &self.unindexed))
+    }
+
+    /// Like [`TrigramIndex::candidates`], but also `None` when even the
+    /// rarest trigrams of the pattern appear in more than
+    /// [`MAX_SELECTIVITY`] of the index's `file_count` files, so reading
+    /// every file is the faster plan
+    pub fn selective_candidates(
+        &self,
+        pattern: &str,
+        ignore_case: bool,
+        file_count: usize,
+    ) -> Option<Vec<u32>> {
+        if file_count >= MIN_PLANNED_FILES {
+            let estimate = self.estimate(pattern, ignore_case)?;
+            if estimate as f64 > MAX_SELECTIVITY * file_count as f64 {
+                return None;
+            }
+        }
+        self.candidates(pattern, ignore_case)
+    }
+
+    /// An upper bound on the number of candidates for `pattern`, from the
+    /// lengths of its posting lists without intersecting them, or `None` if
+    /// it has no literal to filter on
+    pub fn estimate(&self, pattern: &str, ignore_case: bool) -> Option<usize> {
+        let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+        let best = [ExtractKind::Prefix, ExtractKind::Suffix]
+            .into_iter()
+            .filter_map(|kind| {
+                let literals = literals_for(&hir, kind, ignore_case)?;
+                Some(literals.iter().map(|lit| self.rarest(lit)).sum::<usize>())
+            })
+            .min()?;
+        Some(best + self.unindexed.len())
+    }
+
+    /// Candidate entry ids for an identifier searched for by its `words`
+    /// (see [`crate::grep::identifier_words`]), in ascending order, found
+    /// by the words run together as every identifier is indexed; `None`
+    /// when that's too short to filter on
+    pub fn identifier_candidates(&self, words: &[String]) -> Option<Vec<u32>> {
+        let joined = words.concat();
+        if joined.len() < 3 {
+            return None;
+        }
+        let mut found = self.entries_containing(joined.as_bytes());
+        found.retain(|id| self.binary.binary_search(id).is_err());
+        Some(merge_sorted(&found, &self.unindexed))
+    }
+
+    fn candidates_for(&self, hir: &Hir, kind: ExtractKind, ignore_case: bool) -> Option<Vec<u32>> {
+        let mut result: Vec<u32> = Vec::new();
+        for literal in literals_for(hir, kind, ignore_case)? {
+            let matching = self.entries_containing(&literal);
+            result = merge_sorted(&result, &matching);
+        }
+        // Binary files never produce grep output, so there's no point visiting them
+        result.retain(|id| self.binary.binary_search(id).is_err());
+        Some(result)
+    }
+
+    /// Length of the shortest posting list among the trigrams of `literal`
+    fn rarest(&self, literal: &[u8]) -> usize {
+        trigrams_of(literal)
+            .iter()
+            .map(|trigram| self.postings.get(trigram).map_or(0, Vec::len))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Entries containing every trigram of `literal`
+    fn entries_containing(&self, literal: &[u8]) -> Vec<u32> {
+        let mut trigrams = trigrams_of(literal);
+        trigrams.sort_unstable();
+        trigrams.dedup();
+
+        let mut lists: Vec<&Vec<u32>> = Vec::with_capacity(trigrams.len());
+        for trigram in &trigrams {
+            match self.postings.get(trigram) {
+                Some(list) => lists.push(list),
+                None => return Vec::new(),
+            }
+        }
+        // Intersect starting from the rarest trigram to keep the working set small
+        lists.sort_by_key(|list| list.len());
+        let Some((first, rest)) = lists.split_first() else {
+            return Vec::new();
+        };
+        let mut result: Vec<u32> = (*first).clone();
+        for list in rest {
+            result.retain(|id| list.binary_search(id).is_ok());
+            if result.is_empty() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// The literals one of which every match of `hir` starts or ends with, as
+/// `kind` says, if they're all long enough to look up
+fn literals_for(hir: &Hir, kind: ExtractKind, ignore_case: bool) -> Option<Vec<Vec<u8>>> {
+    let mut extractor = Extractor::new();
+    extractor.kind(kind);
+    let seq: Seq = extractor.extract(hir);
+    let literals = seq.literals()?;
+    if literals.is_empty() || literals.iter().any(|lit| lit.as_bytes().len() < 3) {
+        return None;
+    }
+    // Non-ASCII case folding isn't captured by ASCII-folded trigrams
+    if ignore_case && literals.iter().any(|lit| !lit.as_bytes().is_ascii()) {
+        return None;
+    }
+    Some(literals.iter().map(|lit| lit.as_bytes().to_vec()).collect())
+}
+
+fn file_trigrams(entry: &FileEntry, max_size: u64) -> FileTrigrams {
+    if entry.size > max_size {
+        return FileTrigrams::Unindexed;
+    }
+    let Ok(contents) = archive::read(&entry.path) else {
+        return FileTrigrams::Unindexed;
+    };
+    match extract::for_path(&entry.path) {
+        // Grep can't search a document whose text can't be extracted either
+        Some(extractor) => match extractor.extract(&contents) {
+            Ok(text) => classify(text.as_bytes(), None, true),
+            Err(_) => FileTrigrams::Binary,
+        },
+        None => classify(
+            &contents,
+            tokenize::for_path(&entry.path),
+            lang::is_document(&entry.path),
+        ),
+    }
+}
+
+/// Tokenize `contents`, with their identifiers split by `tokenizer` (or as
+/// in any language), detecting their language too if `document`
+fn classify(
+    contents: &[u8],
+    tokenizer: Option<&dyn tokenize::Tokenizer>,
+    document: bool,
+) -> FileTrigrams {
+    if grep::is_binary(contents) {
+        return FileTrigrams::Binary;
+    }
+    let mut trigrams = trigrams_of(contents);
+    let text = String::from_utf8_lossy(contents);
+    let tokens = match tokenizer {
+        Some(tokenizer) => tokenizer.tokens(&text, TokenOptions::default()),
+        None => tokenize::words(&text),
+    };
+    trigrams.extend(trigrams_of(
+        tokenize::joined_identifiers(&text, &tokens).as_bytes(),
+    ));
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    let language = document.then(|| lang::detect(contents)).flatten();
+    FileTrigrams::Text(trigrams, language)
+}
+
+/// Packed, ASCII-lowercased trigrams of `bytes`, skipping ones spanning lines
+fn trigrams_of(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .windows(3)
+        .filter(|w| !w.contains(&b'\n'))
+        .map(|w| {
+            (u32::from(w[0].to_ascii_lowercase()) << 16)
+                | (u32::from(w[1].to_ascii_lowercase()) << 8)
+                | u32::from(w[2].to_ascii_lowercase())
+        })
+        .collect()
+}
+
+/// Union of two ascending id lists
+fn merge_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => {
+                out.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                out.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
+
+/// Postings gathered while building, which are sorted and spilled to a
+/// temporary file as a run whenever they fill the memory budget
+struct Runs {
+    buffer: Vec<Posting>,
+    /// Postings the buffer holds before it's spilled; unbounded if `None`
+    capacity: Option<usize>,
+    spilled: Vec<File>,
+}
+
+impl Runs {
+    fn new(memory_budget: Option<u64>) -> Self {
+        Runs {
+            buffer: Vec::new(),
+            capacity: memory_budget.map(|budget| (budget as usize / POSTING_LEN).max(1)),
+            spilled: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, posting: Posting) -> io::Result<()> {
+        self.buffer.push(posting);
+        if self
+            .capacity
+            .is_some_and(|capacity| self.buffer.len() >= capacity)
+        {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the buffer into a run on disk, freeing its memory
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        for (trigram, id) in self.buffer.drain(..) {
+            writer.write_all(&trigram.to_le_bytes())?;
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        self.buffer = Vec::new();
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.rewind()?;
+        self.spilled.push(file);
+        Ok(())
+    }
+
+    /// Merge the runs on disk and what's still buffered into `postings`,
+    /// one trigram at a time in ascending order
+    fn merge_into(mut self, postings: &mut HashMap<u32, Vec<u32>>) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        let mut sources: Vec<Box<dyn Iterator<Item = io::Result<Posting>>>> = self
+            .spilled
+            .into_iter()
+            .map(|file| Box::new(read_run(file)) as Box<dyn Iterator<Item = _>>)
+            .collect();
+        sources.push(Box::new(self.buffer.into_iter().map(Ok)));
+
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, run) in sources.iter_mut().enumerate() {
+            if let Some(posting) = run.next() {
+                heap.push(Reverse((posting?, source)));
+            }
+        }
+        let mut current: Option<(u32, Vec<u32>)> = None;
+        while let Some(Reverse(((trigram, id), source))) = heap.pop() {
+            if let Some(posting) = sources[source].next() {
+                heap.push(Reverse((posting?, source)));
+            }
+            match &mut current {
+                Some((last, ids)) if *last == trigram => ids.push(id),
+                _ => {
+                    if let Some((last, ids)) = current.replace((trigram, vec![id])) {
+                        add_postings(postings, last, ids);
+                    }
+                }
+            }
+        }
+        if let Some((last, ids)) = current {
+            add_postings(postings, last, ids);
+        }
+        Ok(())
+    }
+}
+
+/// Add the ids of documents containing `trigram` to its list, trimming the
+/// spare capacity a new list grew while being collected
+fn add_postings(postings: &mut HashMap<u32, Vec<u32>>, trigram: u32, mut ids: Vec<u32>) {
+    match postings.get_mut(&trigram) {
+        Some(list) => list.extend_from_slice(&ids),
+        None => {
+            ids.shrink_to_fit();
+            postings.insert(trigram, ids);
+        }
+    }
+}
+
+/// The postings of a run written by [`Runs::spill`], in order
+fn read_run(file: File) -> impl Iterator<Item = io::Result<Posting>> {
+    let mut reader = BufReader::new(file);
+    std::iter::from_fn(move || {
+        let mut bytes = [0; POSTING_LEN];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => Some(Ok((
+                u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+                u32::from_le_bytes(bytes[4..].try_into().unwrap()),
+            ))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn entries_for(dir: &Path, files: &[(&str, &[u8])]) -> Vec<FileEntry> {
+        files
+            .iter()
+            .map(|(name, body)| {
+                let path = dir.join(name);
+                fs::write(&path, body).unwrap();
+                FileEntry::new(path, false, body.len() as u64, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_literal_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = entries_for(
+            dir.path(),
+            &[
+                ("a.txt", b"let target_profit = 1;"),
+                ("b.txt", b"nothing to see"),
+                ("c.txt", b"TARGET_PROFIT again"),
+            ],
+        );
+        let index = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, None).unwrap();
+        assert!(index.trigram_count() > 0);
+
+        // Case-folded trigrams give a superset; the regex decides the rest
+        assert_eq!(index.candidates("target_profit", false), Some(vec![0, 2]));
+        assert_eq!(index.candidates("target_profit", true), Some(vec![0, 2]));
+        assert_eq!(index.candidates("missing", false), Some(vec![]));
+    }
+
+    #[test]
+    fn test_regex_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = entries_for(
+            dir.path(),
+            &[
+                ("a.rs", b"fn alpha() {}"),
+                ("b.rs", b"fn beta() {}"),
+                ("c.rs", b"gamma"),
+            ],
+        );
+        let index = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, None).unwrap();
+
+        assert_eq!(index.candidates("alpha|beta", false), Some(vec![0, 1]));
+        assert_eq!(index.candidates(r"fn \w+\(\)", false), Some(vec![0, 1]));
+        // Nothing with three literal bytes to anchor on
+        assert_eq!(index.candidates(r"\w+", false), None);
+        assert_eq!(index.candidates("ab", false), None);
+    }
+
+    #[test]
+    fn test_identifier_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = entries_for(
+            dir.path(),
+            &[
+                ("a.py", b"def get_user_name(): pass"),
+                ("b.js", b"const name = getUserName();"),
+                ("c.txt", b"see get-user-name"),
+                ("d.rs", b"let user = get_name();"),
+            ],
+        );
+        let index = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, None).unwrap();
+        let words =
+            |words: &[&str]| -> Vec<String> { words.iter().map(|word| word.to_string()).collect() };
+        assert_eq!(
+            index.identifier_candidates(&words(&["get", "user", "name"])),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(index.identifier_candidates(&words(&["a", "b"])), None);
+    }
+
+    #[test]
+    fn test_common_trigrams_fall_back_to_scanning() {
+        let dir = tempfile::tempdir().unwrap();
+        let names: Vec<String> = (0..300).map(|i| format!("{}.txt", i)).collect();
+        let bodies: Vec<String> = (0..300)
+            .map(|i| match i {
+                7 => "the rare zebra".to_string(),
+                _ => format!("the common text {}", i),
+            })
+            .collect();
+        let files: Vec<(&str, &[u8])> = names
+            .iter()
+            .zip(&bodies)
+            .map(|(name, body)| (name.as_str(), body.as_bytes()))
+            .collect();
+        let index = TrigramIndex::build(
+            &entries_for(dir.path(), &files),
+            DEFAULT_MAX_CONTENT_SIZE,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(index.estimate("common", false), Some(299));
+        assert_eq!(index.estimate("zebra", false), Some(1));
+        assert_eq!(index.selective_candidates("common", false, 300), None);
+        assert_eq!(
+            index.selective_candidates("zebra", false, 300),
+            Some(vec![7])
+        );
+        // Small indexes are narrowed regardless
+        assert_eq!(
+            index
+                .selective_candidates("common", false, 100)
+                .unwrap()
+                .len(),
+            299
+        );
+    }
+
+    #[test]
+    fn test_unindexed_and_binary_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = entries_for(
+            dir.path(),
+            &[
+                ("small.txt", b"needle"),
+                ("huge.txt", b"needle but over the limit"),
+                ("blob.bin", b"needle\0\0"),
+            ],
+        );
+        let index = TrigramIndex::build(&entries, 10, None).unwrap();
+        // Oversized files are always candidates; binary files never are
+        assert_eq!(index.candidates("needle", false), Some(vec![0, 1]));
+        assert_eq!(index.candidates("zzz", false), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_remap_and_add() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entries = entries_for(
+            dir.path(),
+            &[
+                ("a.txt", b"apple"),
+                ("b.txt", b"banana"),
+                ("c.txt", b"cherry"),
+            ],
+        );
+        let mut index = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, None).unwrap();
+
+        // Drop b.txt, keep a and c (now ids 0 and 1), then append d.txt as id 2
+        entries.remove(1);
+        entries.extend(entries_for(dir.path(), &[("d.txt", b"banana split")]));
+        index.remap(&[Some(0), None, Some(1)]);
+        index.add(&entries, &[2], None).unwrap();
+
+        assert_eq!(index.candidates("cherry", false), Some(vec![1]));
+        assert_eq!(index.candidates("banana", false), Some(vec![2]));
+        assert_eq!(index.candidates("apple", false), Some(vec![0]));
+    }
+
+    #[test]
+    fn test_memory_budget_spills_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<(String, Vec<u8>)> = (0..20)
+            .map(|i| {
+                (
+                    format!("{}.txt", i),
+                    format!("shared text {}", i).into_bytes(),
+                )
+            })
+            .collect();
+        let files: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(name, body)| (name.as_str(), body.as_slice()))
+            .collect();
+        let entries = entries_for(dir.path(), &files);
+        let in_memory = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, None).unwrap();
+        // A few postings per run, so building takes dozens of runs
+        let spilled = TrigramIndex::build(&entries, DEFAULT_MAX_CONTENT_SIZE, Some(64)).unwrap();
+        assert_eq!(spilled.postings, in_memory.postings);
+        assert_eq!(spilled.candidates("shared", false).unwrap().len(), 20);
+        assert_eq!(spilled.candidates("text 7", false), Some(vec![7]));
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        assert_eq!(merge_sorted(&[1, 3, 5], &[2, 3, 6]), vec![1, 2, 3, 5, 6]);
+        assert_eq!(merge_sorted(&[], &[4]), vec![4]);
+    }
+}
@@ -0,0 +1,473 @@
+//! Shared parsers for human-friendly size, duration and date arguments.
+//!
+//! Every CLI flag that takes a size (`10M`, `1.5GiB`) or a duration
+//! (`2h30m`, `3d`) goes through these functions so they all accept the same
+//! syntax and report errors the same way. They are written as clap
+//! `value_parser`s, returning `String` errors that clap prints verbatim.
+//! Timestamps in results are printed here too, in the [`DateFormat`] a
+//! user picked.
+
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SIZE_HINT: &str = "expected a number with an optional unit, e.g. 512, 10K, 1.5GiB";
+const COUNT_HINT: &str = "expected a number with an optional k, M or G suffix, e.g. 5000, 250k, 1M";
+const DURATION_HINT: &str =
+    "expected a number with a unit (ms, s, m, h, d, w), e.g. 90s, 2h30m, 3d";
+const TIME_HINT: &str = "expected a duration such as 7d or a date such as 2024-01-01";
+const DATE_FORMAT_HINT: &str =
+    "expected relative, iso or a strftime format such as '%Y-%m-%d %H:%M'";
+
+/// Parse a size such as `512`, `10M` or `1.5GiB` into bytes.
+///
+/// Units are case-insensitive and use binary multiples, matching how
+/// [`crate::scanner::format_size`] prints sizes: `K`, `KB` and `KiB` all mean
+/// 1024 bytes.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    if number.is_empty() {
+        return Err(format!("invalid size '{}': {}", input, SIZE_HINT));
+    }
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}': {}", input, SIZE_HINT))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        other => {
+            return Err(format!(
+                "invalid size '{}': unknown unit '{}' ({})",
+                input, other, SIZE_HINT
+            ));
+        }
+    };
+
+    let bytes = value * multiplier as f64;
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(format!("invalid size '{}': value is too large", input));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Parse a count such as `5000`, `250k` or `1.5M`. Unlike sizes, counts
+/// use decimal multiples: `1M` is a million.
+pub fn parse_count(input: &str) -> Result<usize, String> {
+    let trimmed = input.trim();
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&trimmed[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&trimmed[..i], 1e6),
+        Some((i, 'g' | 'G')) => (&trimmed[..i], 1e9),
+        _ => (trimmed, 1.0),
+    };
+    let value: f64 = number
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+        .ok_or_else(|| format!("invalid count '{}': {}", input, COUNT_HINT))?;
+    Ok((value * multiplier).round() as usize)
+}
+
+/// Parse a duration such as `90s`, `2h30m` or `3d`.
+///
+/// A duration is one or more `<number><unit>` pairs; a bare number is taken
+/// as seconds. Supported units are `ms`, `s`, `m`, `h`, `d` and `w`.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err(format!("invalid duration '{}': {}", input, DURATION_HINT));
+    }
+    if trimmed.chars().all(|c| c.is_ascii_digit()) {
+        let secs = trimmed
+            .parse()
+            .map_err(|_| format!("invalid duration '{}': value is too large", input))?;
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(digits);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        rest = tail;
+
+        let value: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration '{}': {}", input, DURATION_HINT))?;
+        let unit_secs = match unit {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86_400.0,
+            "w" => 604_800.0,
+            "" => {
+                return Err(format!(
+                    "invalid duration '{}': missing unit after '{}' ({})",
+                    input, number, DURATION_HINT
+                ));
+            }
+            other => {
+                return Err(format!(
+                    "invalid duration '{}': unknown unit '{}' ({})",
+                    input, other, DURATION_HINT
+                ));
+            }
+        };
+        total += Duration::try_from_secs_f64(value * unit_secs)
+            .map_err(|_| format!("invalid duration '{}': value is too large", input))?;
+    }
+    Ok(total)
+}
+
+/// A size comparison such as `+10M` (at least), `-1k` (at most) or `4K`
+/// (exactly)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeFilter {
+    /// How an entry's size must compare to `bytes`; `Greater` includes
+    /// equality and `Less` likewise, as in `fd --size`
+    #[serde(with = "ordering_as_i8")]
+    pub ordering: Ordering,
+    pub bytes: u64,
+}
+
+impl SizeFilter {
+    pub fn matches(&self, size: u64) -> bool {
+        match self.ordering {
+            Ordering::Greater => size >= self.bytes,
+            Ordering::Less => size <= self.bytes,
+            Ordering::Equal => size == self.bytes,
+        }
+    }
+}
+
+/// Serde has no impl for [`Ordering`], so store it as -1, 0 or 1
+mod ordering_as_i8 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(ordering: &Ordering, serializer: S) -> Result<S::Ok, S::Error> {
+        (*ordering as i8).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ordering, D::Error> {
+        Ok(i8::deserialize(deserializer)?.cmp(&0))
+    }
+}
+
+/// Parse a size filter: an optional `+` or `-` followed by a size accepted
+/// by [`parse_size`]
+pub fn parse_size_filter(input: &str) -> Result<SizeFilter, String> {
+    let trimmed = input.trim();
+    let (ordering, size) = match trimmed.as_bytes().first() {
+        Some(b'+') => (Ordering::Greater, &trimmed[1..]),
+        Some(b'-') => (Ordering::Less, &trimmed[1..]),
+        _ => (Ordering::Equal, trimmed),
+    };
+    Ok(SizeFilter {
+        ordering,
+        bytes: parse_size(size)?,
+    })
+}
+
+/// Parse a point in time: a duration such as `7d` counts back from now, and
+/// a date such as `2024-01-01` or `2024-01-01T12:30:00` is taken as UTC
+pub fn parse_time(input: &str) -> Result<SystemTime, String> {
+    parse_time_from(input, SystemTime::now())
+}
+
+fn parse_time_from(input: &str, now: SystemTime) -> Result<SystemTime, String> {
+    let trimmed = input.trim();
+    if trimmed.len() >= 10 && trimmed.as_bytes()[4] == b'-' {
+        return parse_date(trimmed)
+            .ok_or_else(|| format!("invalid date '{}': {}", input, TIME_HINT));
+    }
+    let age =
+        parse_duration(trimmed).map_err(|_| format!("invalid time '{}': {}", input, TIME_HINT))?;
+    Ok(now.checked_sub(age).unwrap_or(UNIX_EPOCH))
+}
+
+/// `YYYY-MM-DD`, optionally followed by `THH:MM[:SS]` or ` HH:MM[:SS]`
+pub(crate) fn parse_date(input: &str) -> Option<SystemTime> {
+    let (date, time) = match input.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut secs_of_day = 0;
+    if let Some(time) = time {
+        let fields: Vec<u64> = time
+            .split(':')
+            .map(|field| field.parse().ok())
+            .collect::<Option<_>>()?;
+        let (hours, minutes, seconds) = match fields[..] {
+            [h, m] => (h, m, 0),
+            [h, m, s] => (h, m, s),
+            _ => return None,
+        };
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        secs_of_day = hours * 3600 + minutes * 60 + seconds;
+    }
+
+    let days = u64::try_from(days_since_epoch(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + secs_of_day))
+}
+
+/// Format `time` as a UTC date, `YYYY-MM-DD`
+pub fn format_date(time: SystemTime) -> String {
+    let days = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() / 86_400) as i64,
+        Err(before) => -(before.duration().as_secs().div_ceil(86_400) as i64),
+    };
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// A duration in seconds as its largest whole unit, e.g. "3 h"
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{} s", secs),
+        60..3_600 => format!("{} min", secs / 60),
+        3_600..86_400 => format!("{} h", secs / 3_600),
+        _ => format!("{} d", secs / 86_400),
+    }
+}
+
+/// How timestamps in results are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// How long ago, e.g. `3 h ago`
+    Relative,
+    /// RFC 3339, e.g. `2024-05-01T14:03:22+02:00`
+    Iso,
+    /// A strftime format such as `%Y-%m-%d %H:%M`
+    Custom(String),
+}
+
+/// Parse `relative`, `iso` or a strftime format into a [`DateFormat`]
+pub fn parse_date_format(input: &str) -> Result<DateFormat, String> {
+    match input {
+        "relative" => Ok(DateFormat::Relative),
+        "iso" => Ok(DateFormat::Iso),
+        custom if custom.contains('%') => match StrftimeItems::new(custom).parse() {
+            Ok(_) => Ok(DateFormat::Custom(custom.to_string())),
+            Err(err) => Err(format!("invalid date format '{}': {}", input, err)),
+        },
+        _ => Err(format!(
+            "invalid date format '{}': {}",
+            input, DATE_FORMAT_HINT
+        )),
+    }
+}
+
+/// A [`DateFormat`] and the timezone to write dates in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateStyle {
+    pub format: DateFormat,
+    /// Write dates in UTC rather than the local timezone
+    pub utc: bool,
+}
+
+impl DateStyle {
+    /// Write `time`, relative to `now` for [`DateFormat::Relative`]
+    pub fn format(&self, time: SystemTime, now: SystemTime) -> String {
+        let pattern = match &self.format {
+            DateFormat::Relative => {
+                return match now.duration_since(time) {
+                    Ok(age) => format!("{} ago", format_age(age.as_secs())),
+                    Err(ahead) => format!("in {}", format_age(ahead.duration().as_secs())),
+                };
+            }
+            DateFormat::Iso => None,
+            DateFormat::Custom(pattern) => Some(pattern.as_str()),
+        };
+        match (self.utc, pattern) {
+            (true, None) => DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true),
+            (false, None) => {
+                DateTime::<Local>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+            }
+            (true, Some(pattern)) => DateTime::<Utc>::from(time).format(pattern).to_string(),
+            (false, Some(pattern)) => DateTime::<Local>::from(time).format(pattern).to_string(),
+        }
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given civil date (Howard Hinnant's algorithm)
+fn days_since_epoch(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of [`days_since_epoch`]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("10kb"), Ok(10 * 1024));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1.5GiB"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_size(" 2 TB "), Ok(2 << 40));
+    }
+
+    #[test]
+    fn test_date_styles() {
+        // 2024-05-01 12:03:22 UTC
+        let time = UNIX_EPOCH + Duration::from_secs(1_714_565_002);
+        let now = time + Duration::from_secs(7_200);
+        let utc = |format| DateStyle { format, utc: true };
+        assert_eq!(utc(DateFormat::Relative).format(time, now), "2 h ago");
+        assert_eq!(utc(DateFormat::Relative).format(now, time), "in 2 h");
+        assert_eq!(
+            utc(DateFormat::Iso).format(time, now),
+            "2024-05-01T12:03:22Z"
+        );
+        let custom = parse_date_format("%d/%m/%Y %H:%M").unwrap();
+        assert_eq!(utc(custom).format(time, now), "01/05/2024 12:03");
+        assert_eq!(format_age(59), "59 s");
+        assert_eq!(format_age(3 * 86_400), "3 d");
+
+        assert_eq!(parse_date_format("iso"), Ok(DateFormat::Iso));
+        assert!(parse_date_format("%Q").is_err());
+        assert!(parse_date_format("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("5000"), Ok(5000));
+        assert_eq!(parse_count("250k"), Ok(250_000));
+        assert_eq!(parse_count("1M"), Ok(1_000_000));
+        assert_eq!(parse_count("1.5m"), Ok(1_500_000));
+        assert!(parse_count("").is_err());
+        assert!(parse_count("-3").is_err());
+        assert!(parse_count("1MB").is_err());
+    }
+
+    #[test]
+    fn test_parse_size_errors() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1.2.3K").is_err());
+        let err = parse_size("10Q").unwrap_err();
+        assert!(err.contains("unknown unit 'q'"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h30m"), Ok(Duration::from_secs(9000)));
+        assert_eq!(parse_duration("3d"), Ok(Duration::from_secs(3 * 86_400)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn test_parse_duration_errors() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2h30").unwrap_err().contains("missing unit"));
+        assert!(
+            parse_duration("3y")
+                .unwrap_err()
+                .contains("unknown unit 'y'")
+        );
+    }
+
+    #[test]
+    fn test_parse_size_filter() {
+        let at_least = parse_size_filter("+10M").unwrap();
+        assert!(at_least.matches(10 << 20) && at_least.matches(11 << 20));
+        assert!(!at_least.matches(1 << 20));
+        let at_most = parse_size_filter("-1k").unwrap();
+        assert!(at_most.matches(0) && at_most.matches(1024));
+        assert!(!at_most.matches(1025));
+        assert!(parse_size_filter("512").unwrap().matches(512));
+        assert!(parse_size_filter("+").is_err());
+        assert!(parse_size_filter("+10Q").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(parse_time("1970-01-02").map(secs), Ok(86_400));
+        assert_eq!(parse_time("2024-01-01").map(secs), Ok(1_704_067_200));
+        assert_eq!(parse_time("2024-02-29T12:30").map(secs), Ok(1_709_209_800));
+        assert!(parse_time("2023-02-29").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("2024-01-01T25:00").is_err());
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(
+            parse_time_from("7d", now).map(secs),
+            Ok(1_000_000 - 7 * 86_400)
+        );
+        assert!(
+            parse_time("soon")
+                .unwrap_err()
+                .contains("expected a duration")
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::scanner::FileEntry;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory names that conventionally hold third-party code
+const VENDOR_DIRS: [&str; 6] = [
+    "vendor",
+    "third_party",
+    "third-party",
+    "node_modules",
+    "bower_components",
+    "Pods",
+];
+
+/// Roots of the git submodules declared in `root/.gitmodules`, including
+/// submodules nested inside them
+pub fn submodules(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(contents) = fs::read_to_string(dir.join(".gitmodules")) else {
+            continue;
+        };
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if key.trim() == "path" {
+                let submodule = dir.join(value.trim());
+                pending.push(submodule.clone());
+                found.push(submodule);
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Mark entries inside a submodule or a vendor directory as vendored
+pub fn assign(root: &Path, entries: &mut [FileEntry]) {
+    assign_with(root, &submodules(root), entries);
+}
+
+/// Mark entries inside one of `submodules` or a vendor directory as vendored
+pub(crate) fn assign_with(root: &Path, submodules: &[PathBuf], entries: &mut [FileEntry]) {
+    for entry in entries {
+        let relative = entry.path.strip_prefix(root).unwrap_or(&entry.path);
+        let in_vendor_dir = relative.parent().is_some_and(|dir| {
+            dir.components().any(|component| {
+                matches!(component, Component::Normal(name)
+                    if VENDOR_DIRS.iter().any(|vendor| name == *vendor))
+            })
+        });
+        entry.vendored = in_vendor_dir || submodules.iter().any(|dir| entry.path.starts_with(dir));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_submodules_and_vendor_dirs() {
+        let root = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                ".gitmodules",
+                "[submodule \"libs/json\"]\n\tpath = libs/json\n\turl = https://example.com/json\n",
+            ),
+            (
+                "libs/json/.gitmodules",
+                "[submodule \"deps\"]\n\tpath = deps\n",
+            ),
+            ("libs/json/json.h", ""),
+            ("libs/own.c", ""),
+            ("src/vendor/lib.go", ""),
+            ("src/vendors.go", ""),
+            ("third_party/zlib/zlib.c", ""),
+            ("vendor.txt", ""),
+        ];
+        for (name, body) in files {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, body).unwrap();
+        }
+        let scan = scanner::scan_directory(root.path()).unwrap();
+        assert_eq!(
+            submodules(&scan.root),
+            vec![
+                scan.root.join("libs/json"),
+                scan.root.join("libs/json/deps")
+            ]
+        );
+
+        let mut entries = scan.files;
+        assign(&scan.root, &mut entries);
+        let mut vendored: Vec<&str> = entries
+            .iter()
+            .filter(|e| e.vendored)
+            .map(|e| e.name.as_str())
+            .collect();
+        vendored.sort();
+        assert_eq!(vendored, vec!["json", "json.h", "lib.go", "zlib", "zlib.c"]);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::error::ErrorKind;
+use crate::filetype;
+use crate::index::{self, Index, WriteLock};
+use crate::quota::{self, Part};
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Stray files younger than this may belong to a save still under way
+const ORPHAN_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// How one stored generation fared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerationCheck {
+    pub generation: u64,
+    /// Number of entries, when the generation is intact
+    pub entries: Option<usize>,
+    /// Why the generation can't be trusted, if it can't
+    pub damage: Option<String>,
+}
+
+/// What [`verify`] found in an index directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Every stored generation, oldest first
+    pub generations: Vec<GenerationCheck>,
+    /// The generation readers are pointed at, if it's stored
+    pub current: Option<u64>,
+    /// Why the pointer to the current generation can't be followed, if it
+    /// can't
+    pub pointer_problem: Option<String>,
+    /// Files no stored generation owns: leftovers of interrupted saves, and
+    /// parts of generations whose header is gone
+    pub orphans: Vec<PathBuf>,
+}
+
+impl Report {
+    /// Number of damaged generations, broken pointers and orphaned files
+    pub fn problems(&self) -> usize {
+        let damaged = self.generations.iter().filter(|g| g.damage.is_some());
+        damaged.count() + usize::from(self.pointer_problem.is_some()) + self.orphans.len()
+    }
+
+    /// The newest generation that is intact
+    pub fn newest_intact(&self) -> Option<u64> {
+        self.generations
+            .iter()
+            .rev()
+            .find(|check| check.damage.is_none())
+            .map(|check| check.generation)
+    }
+
+    fn current_is_damaged(&self) -> bool {
+        self.generations
+            .iter()
+            .any(|check| Some(check.generation) == self.current && check.damage.is_some())
+    }
+}
+
+/// Check every generation stored in `index_dir` against the checksums and
+/// entry count its header recorded, check that entries only refer to
+/// projects, owner lists and repositories that exist, and look for orphaned
+/// files. Fails only if there is no index there at all.
+pub fn verify(index_dir: &Path) -> Result<Report> {
+    let stored = Index::generations(index_dir)?;
+    let pointer = Index::current_generation(index_dir);
+    if stored.is_empty() && matches!(pointer, Ok(None)) {
+        anyhow::bail!(
+            "No index found at {} (run `ss scan` first)",
+            index_dir.display()
+        );
+    }
+    let mut report = Report::default();
+    match pointer {
+        Ok(Some(generation)) if stored.contains(&generation) => report.current = Some(generation),
+        Ok(Some(generation)) => {
+            report.pointer_problem = Some(format!(
+                "points at generation {}, which isn't stored",
+                generation
+            ))
+        }
+        Ok(None) => report.pointer_problem = Some("is missing".to_string()),
+        Err(e) if e.kind() == ErrorKind::IndexFormat => {
+            report.pointer_problem = Some("can't be read".to_string())
+        }
+        Err(e) => report.pointer_problem = Some(format!("{:#}", e)),
+    }
+    for generation in stored {
+        let (entries, damage) = match check_generation(index_dir, generation) {
+            Ok(entries) => (Some(entries), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        report.generations.push(GenerationCheck {
+            generation,
+            entries,
+            damage,
+        });
+    }
+    report.orphans = orphans(index_dir, ORPHAN_AGE)?;
+    Ok(report)
+}
+
+/// The number of entries in `generation`, or why it's damaged
+fn check_generation(index_dir: &Path, generation: u64) -> Result<usize> {
+    let checksums = Index::checksums(index_dir, generation)?;
+    let files = [
+        (
+            "entry table",
+            Index::entries_path(index_dir, generation),
+            checksums.entries,
+        ),
+        (
+            "name filter",
+            Index::filter_path(index_dir, generation),
+            Some(checksums.filter),
+        ),
+        (
+            "content index",
+            Index::content_path(index_dir, generation),
+            checksums.content,
+        ),
+    ];
+    for (what, path, expected) in files {
+        let Some(expected) = expected else {
+            continue;
+        };
+        if !path.is_file() {
+            if what == "content index" && quota::was_evicted(index_dir, generation, Part::Content) {
+                continue;
+            }
+            anyhow::bail!("its {} is missing", what);
+        }
+        if index::file_hash(&path)? != expected {
+            anyhow::bail!("its {} doesn't match its checksum", what);
+        }
+    }
+    let index = Index::load_generation(index_dir, generation)?;
+    if checksums.entries.is_some() && index.entries.len() as u64 != checksums.entry_count {
+        anyhow::bail!(
+            "it holds {} entries but was saved with {}",
+            index.entries.len(),
+            checksums.entry_count
+        );
+    }
+    let dangling = |id: Option<u32>, count: usize| id.is_some_and(|id| id as usize >= count);
+    if let Some(entry) = index.entries.iter().find(|entry| {
+        dangling(entry.project, index.projects.len())
+            || dangling(entry.owners, index.owner_sets.len())
+            || dangling(entry.repo, index.repos.len())
+            || dangling(entry.mime, filetype::MIME_TYPES.len())
+    }) {
+        anyhow::bail!(
+            "{} refers to a project, owner list, repository or MIME type that isn't stored",
+            entry.path.display()
+        );
+    }
+    Ok(index.entries.len())
+}
+
+/// Temporary files and generation files without a header in `index_dir`
+/// that haven't been touched for `min_age`
+fn orphans(index_dir: &Path, min_age: Duration) -> Result<Vec<PathBuf>> {
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(index_dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let headless = name.strip_prefix("gen-").is_some_and(|rest| {
+            let (number, extension) = rest.split_once('.').unwrap_or((rest, ""));
+            matches!(extension, "ent" | "tri" | "blm")
+                && number.parse().is_ok_and(|generation| {
+                    !Index::generation_path(index_dir, generation).is_file()
+                })
+        });
+        let old = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= min_age);
+        if (headless || name.ends_with(".tmp")) && old {
+            orphans.push(entry.path());
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Fix what `report` found in `index_dir`, returning a line for each thing
+/// done: point readers at the newest intact generation if theirs is broken,
+/// then delete damaged generations and orphaned files.
+///
+/// Fails without touching anything when no intact generation is left.
+pub fn repair(index_dir: &Path, report: &Report) -> Result<Vec<String>> {
+    let _lock = WriteLock::acquire(index_dir)?;
+    let mut done = Vec::new();
+    if report.pointer_problem.is_some() || report.current_is_damaged() {
+        let Some(generation) = report.newest_intact() else {
+            anyhow::bail!(
+                "No intact generation is left in {}; run `ss scan` again",
+                index_dir.display()
+            );
+        };
+        Index::set_current(index_dir, generation)?;
+        done.push(format!("Pointed readers at generation {}", generation));
+    }
+    for check in &report.generations {
+        if check.damage.is_some() {
+            index::remove_generation(index_dir, check.generation);
+            done.push(format!("Removed damaged generation {}", check.generation));
+        }
+    }
+    for path in &report.orphans {
+        fs::remove_file(path)?;
+        done.push(format!("Removed orphaned file {}", path.display()));
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+    use std::fs::File;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_verify_and_repair() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("notes.txt"), "hello").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let dir = index_dir.path();
+        let scan = scanner::scan_directory(root.path()).unwrap();
+        let mut index = Index::from_scan(scan);
+        index.save(dir).unwrap();
+        index.save(dir).unwrap();
+
+        let report = verify(dir).unwrap();
+        assert_eq!(report.problems(), 0);
+        assert_eq!(report.current, Some(2));
+        assert_eq!(report.generations[1].entries, Some(index.entries.len()));
+
+        // Damage the current generation and strand a leftover of an old save
+        let mut table = fs::read(Index::entries_path(dir, 2)).unwrap();
+        let last = table.len() - 1;
+        table[last] ^= 0xff;
+        fs::write(Index::entries_path(dir, 2), table).unwrap();
+        let stray = Index::content_path(dir, 9);
+        fs::write(&stray, "").unwrap();
+        let report = verify(dir).unwrap();
+        assert!(
+            report.orphans.is_empty(),
+            "a fresh file may be a save under way"
+        );
+        let long_ago = SystemTime::now() - 2 * ORPHAN_AGE;
+        File::options()
+            .write(true)
+            .open(&stray)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+
+        let report = verify(dir).unwrap();
+        assert_eq!(report.problems(), 2);
+        assert_eq!(
+            report.generations[1].damage.as_deref(),
+            Some("its entry table doesn't match its checksum")
+        );
+        assert_eq!(report.orphans, std::slice::from_ref(&stray));
+
+        let done = repair(dir, &report).unwrap();
+        assert_eq!(done.len(), 3);
+        assert_eq!(Index::current_generation(dir).unwrap(), Some(1));
+        assert_eq!(Index::generations(dir).unwrap(), [1]);
+        assert!(!stray.exists());
+        assert_eq!(verify(dir).unwrap().problems(), 0);
+
+        // With nothing intact left, repair leaves the index alone
+        fs::write(Index::filter_path(dir, 1), "").unwrap();
+        let report = verify(dir).unwrap();
+        assert!(repair(dir, &report).is_err());
+        assert_eq!(Index::generations(dir).unwrap(), [1]);
+    }
+}
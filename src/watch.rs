@@ -0,0 +1,737 @@
+use crate::buildsys::BuildSystems;
+use crate::grep::{self, FileMatches, LineMatch};
+use crate::index::{Index, UpdateReport};
+use crate::scanner::{self, FileEntry, IgnoreRules, ScanOptions};
+use crate::search::EntryFilter;
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{EventKind, RecursiveMode, Watcher};
+use regex::bytes::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How often the watch loop checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Ignore files whose rules the filter mirrors, later ones winning
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", scanner::IGNORE_FILE];
+
+/// Decides which changed paths belong in the index, mirroring the scanner's
+/// hidden-file and ignore-file rules for paths reported one at a time.
+pub struct PathFilter {
+    root: PathBuf,
+    excluded: Vec<PathBuf>,
+    /// Mount points the scan left out, with `--one-file-system` or
+    /// `--skip-network`
+    mounts: Vec<PathBuf>,
+    /// The index's exclude patterns, `--ignore-file` files and global
+    /// ignore file, applied like in the scanner
+    rules: IgnoreRules,
+    /// Hidden paths are indexed too
+    hidden: bool,
+    /// `.ignore` and `.gitignore` files are honoured
+    respect_ignore: bool,
+    /// Deepest level below the root the index was scanned to
+    max_depth: Option<usize>,
+    /// `.gitignore` only applies inside a git repository, as in the scanner
+    respect_gitignore: bool,
+    /// Build systems whose output directories aren't indexed
+    build_outputs: BuildSystems,
+    cache: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path) -> Self {
+        PathFilter {
+            root: root.to_path_buf(),
+            excluded: Vec::new(),
+            mounts: Vec::new(),
+            rules: IgnoreRules::default(),
+            hidden: false,
+            respect_ignore: true,
+            max_depth: None,
+            respect_gitignore: root.ancestors().any(|dir| dir.join(".git").exists()),
+            build_outputs: BuildSystems::default(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Never report changes beneath `dir` (e.g. the index directory itself)
+    pub fn exclude(&mut self, dir: &Path) {
+        self.excluded.push(dir.to_path_buf());
+    }
+
+    /// Apply the exclude patterns the index was scanned with
+    pub fn exclude_patterns(&mut self, excludes: &[String]) -> Result<()> {
+        let options = ScanOptions {
+            excludes: excludes.to_vec(),
+            ..Default::default()
+        };
+        self.rules = IgnoreRules::compile(&self.root, &options)?;
+        Ok(())
+    }
+
+    /// Ignore paths more than `depth` levels below the root, like a scan
+    /// with that maximum depth
+    pub fn max_depth(&mut self, depth: Option<usize>) {
+        self.max_depth = depth;
+    }
+
+    /// Mirror every rule of a scan with `options`: excludes, depth, hidden
+    /// files, build outputs, mounts and which ignore files count
+    pub fn scan_options(&mut self, options: &ScanOptions) -> Result<()> {
+        self.rules = IgnoreRules::compile(&self.root, options)?;
+        self.mounts = scanner::skipped_mounts(&self.root, options);
+        self.max_depth(options.max_depth);
+        self.hidden = options.hidden;
+        self.respect_ignore = !options.no_ignore;
+        self.respect_gitignore &= !options.no_ignore && !options.no_git_ignore;
+        self.build_outputs = options.skip_build_outputs;
+        self.reset();
+        Ok(())
+    }
+
+    /// Forget cached ignore rules after an ignore file changed
+    pub fn reset(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Whether `path` is outside the root, hidden, excluded or ignored
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        let mut excluded = self.excluded.iter().chain(&self.mounts);
+        if excluded.any(|dir| path.starts_with(dir)) {
+            return true;
+        }
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let components: Vec<Component> = relative.components().collect();
+        if self.max_depth.is_some_and(|depth| components.len() > depth) {
+            return true;
+        }
+        if components.iter().any(|c| {
+            let name = c.as_os_str().to_string_lossy();
+            match self.hidden {
+                true => scanner::ALWAYS_SKIPPED.contains(&name.as_ref()),
+                false => name.starts_with('.'),
+            }
+        }) {
+            return true;
+        }
+        let is_dir = path.is_dir();
+        if !self.build_outputs.is_empty() {
+            let mut dir = self.root.clone();
+            for (depth, component) in components.iter().enumerate() {
+                dir.push(component);
+                let below = depth + 1 < components.len();
+                if (below || is_dir) && self.build_outputs.output_of(&dir).is_some() {
+                    return true;
+                }
+            }
+        }
+        if self.rules.matched_with_parents(path, is_dir) {
+            return true;
+        }
+        if !self.respect_ignore {
+            return false;
+        }
+
+        // A path is ignored if it or any directory above it is ignored, with
+        // rules from deeper ignore files taking precedence.
+        let root = self.root.clone();
+        for depth in 1..=components.len() {
+            let candidate: PathBuf = root.join(components[..depth].iter().collect::<PathBuf>());
+            let is_dir = depth < components.len() || candidate.is_dir();
+            for ancestor in (0..depth).rev() {
+                let dir = root.join(components[..ancestor].iter().collect::<PathBuf>());
+                let Some(rules) = self.rules_for(&dir) else {
+                    continue;
+                };
+                let matched = rules.matched(&candidate, is_dir);
+                if matched.is_ignore() {
+                    return true;
+                }
+                if matched.is_whitelist() {
+                    break;
+                }
+            }
+        }
+        false
+    }
+
+    fn rules_for(&mut self, dir: &Path) -> Option<&Gitignore> {
+        let respect_gitignore = self.respect_gitignore;
+        self.cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(dir);
+                let mut found = false;
+                for name in IGNORE_FILES {
+                    if name == ".gitignore" && !respect_gitignore {
+                        continue;
+                    }
+                    let file = dir.join(name);
+                    if file.is_file() && builder.add(&file).is_none() {
+                        found = true;
+                    }
+                }
+                found.then(|| builder.build().ok()).flatten()
+            })
+            .as_ref()
+    }
+}
+
+/// Turn a batch of raw event paths into the paths the index should re-examine.
+///
+/// Newly created directories are expanded into the files and directories
+/// beneath them.
+pub fn expand_paths(filter: &mut PathFilter, raw: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    if raw.iter().any(|path| {
+        path.file_name()
+            .is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f))
+    }) {
+        filter.reset();
+    }
+
+    let mut paths = Vec::new();
+    for path in raw {
+        if filter.is_ignored(&path) {
+            continue;
+        }
+        if path.is_dir()
+            && let Ok(scan) = scanner::scan_directory(&path)
+        {
+            paths.extend(
+                scan.files
+                    .into_iter()
+                    .map(|entry| entry.path)
+                    .filter(|file| !filter.is_ignored(file)),
+            );
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+/// File inside the index directory that a running watcher keeps locked
+pub const LOCK_FILE: &str = "watch.lock";
+
+/// Whether a watcher is keeping the index in `index_dir` up to date
+pub fn is_watched(index_dir: &Path) -> bool {
+    File::open(index_dir.join(LOCK_FILE))
+        .is_ok_and(|file| matches!(file.try_lock(), Err(std::fs::TryLockError::WouldBlock)))
+}
+
+/// Lock the index in `index_dir` for a watcher, failing if another one
+/// holds it; the lock is released when the file is dropped
+fn lock_index(index_dir: &Path) -> Result<File> {
+    let path = index_dir.join(LOCK_FILE);
+    let file =
+        File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => {
+            anyhow::bail!(
+                "Another `ss watch` is already watching {}",
+                index_dir.display()
+            )
+        }
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Watch the root of the index in `index_dir` and apply changes as they happen.
+///
+/// Events are debounced into batches; every batch that changes the index is
+/// saved as a new generation and reported through `on_update`. Runs until
+/// `stop` is set. Only one watcher runs per index at a time.
+pub fn watch(
+    index_dir: &Path,
+    debounce: Duration,
+    stop: &AtomicBool,
+    mut on_update: impl FnMut(&UpdateReport, u64),
+) -> Result<()> {
+    let _lock = lock_index(index_dir)?;
+    let mut index = Index::load(index_dir)?;
+    index.load_content(index_dir)?;
+    let root = index.meta.root.clone();
+    let filter = index_filter(&index, index_dir)?;
+    watch_changes(&root, filter, debounce, stop, |paths| {
+        loop {
+            // A scan or update run meanwhile saved a newer index to build on
+            if Index::current_generation(index_dir)? != Some(index.meta.generation) {
+                index = Index::load(index_dir)?;
+                index.load_content(index_dir)?;
+            }
+            let report = index.apply_changes(&paths)?;
+            if !report.has_changes() {
+                return Ok(ControlFlow::Continue(()));
+            }
+            // Or one saved between that check and this save: apply the batch
+            // again on top of it
+            if let Some(generation) = index.save_if_current(index_dir)? {
+                on_update(&report, generation);
+                return Ok(ControlFlow::Continue(()));
+            }
+        }
+    })
+}
+
+/// Print-as-you-go grep over the files of `index`: every batch of changes
+/// is searched for lines appended since the files were last seen, and the
+/// matches are passed to `on_match`.
+///
+/// Only content written after the call is reported. Runs until `stop` is set
+/// or `on_match` breaks.
+pub fn follow(
+    index: &Index,
+    index_dir: &Path,
+    entries: &EntryFilter,
+    pattern: &Regex,
+    debounce: Duration,
+    stop: &AtomicBool,
+    mut on_match: impl FnMut(FileMatches) -> ControlFlow<()>,
+) -> Result<()> {
+    let known: HashMap<&Path, &FileEntry> = index
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+    // Start from the files' current sizes, which may differ from the indexed ones
+    let current: Vec<FileEntry> = index
+        .entries
+        .iter()
+        .filter_map(|entry| scanner::entry_for_file(&entry.path))
+        .collect();
+    let mut tail = Tail::new(&current);
+    let filter = index_filter(index, index_dir)?;
+    watch_changes(&index.meta.root, filter, debounce, stop, |paths| {
+        for path in paths {
+            let in_scope = match known.get(path.as_path()) {
+                Some(entry) => entries.matches(entry),
+                None => scanner::entry_for_file(&path).is_some_and(|e| entries.matches(&e)),
+            };
+            if !in_scope {
+                continue;
+            }
+            let Ok(Some(appended)) = tail.read_appended(&path) else {
+                continue;
+            };
+            let matches = appended.search(pattern);
+            if !matches.is_empty() && on_match(FileMatches { path, matches }).is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })
+}
+
+/// Filter for changes under the root of `index`, leaving out the index
+/// directory and everything the scan excluded
+pub(crate) fn index_filter(index: &Index, index_dir: &Path) -> Result<PathFilter> {
+    let mut filter = PathFilter::new(&index.meta.root);
+    filter.scan_options(&index.meta.scan_options)?;
+    if let Ok(index_dir) = dunce::canonicalize(index_dir) {
+        filter.exclude(&index_dir);
+    }
+    Ok(filter)
+}
+
+/// Watch `root` and pass every debounced batch of changed paths that
+/// `filter` lets through to `on_batch`, until `stop` is set or `on_batch`
+/// breaks.
+pub fn watch_changes(
+    root: &Path,
+    mut filter: PathFilter,
+    debounce: Duration,
+    stop: &AtomicBool,
+    mut on_batch: impl FnMut(Vec<PathBuf>) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .context("Failed to start the filesystem watcher")?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+    let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut deadline: Option<Instant> = None;
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, EventKind::Access(_)) {
+                    pending.extend(event.paths);
+                    deadline.get_or_insert_with(|| Instant::now() + debounce);
+                }
+            }
+            Ok(Err(e)) => eprintln!("⚠️  Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            deadline = None;
+            let paths = expand_paths(&mut filter, std::mem::take(&mut pending));
+            if !paths.is_empty() && on_batch(paths)?.is_break() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How far into each file has already been read
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    len: u64,
+    /// Lines before `len`, counted the first time the file grows
+    lines: Option<usize>,
+}
+
+/// Tracks how much of each file has been seen so that only appended lines
+/// are searched, like `tail -f`
+#[derive(Debug, Default)]
+pub struct Tail {
+    seen: HashMap<PathBuf, Seen>,
+}
+
+/// Complete lines appended to a file since it was last read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Appended {
+    /// 1-based number of the first appended line
+    pub first_line: usize,
+    /// Byte offset of the first appended line within the file
+    pub offset: u64,
+    pub contents: Vec<u8>,
+}
+
+impl Appended {
+    /// Matching lines, numbered and offset by their position in the whole file
+    pub fn search(&self, pattern: &Regex) -> Vec<LineMatch> {
+        let mut matches = grep::search_bytes(&self.contents, pattern);
+        for m in &mut matches {
+            m.line_number += self.first_line - 1;
+            m.offset += self.offset;
+        }
+        matches
+    }
+}
+
+impl Tail {
+    /// Treat `entries` as read up to their recorded size
+    pub fn new<'e>(entries: impl IntoIterator<Item = &'e FileEntry>) -> Self {
+        let seen = entries
+            .into_iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| {
+                let seen = Seen {
+                    len: entry.size,
+                    lines: None,
+                };
+                (entry.path.clone(), seen)
+            })
+            .collect();
+        Tail { seen }
+    }
+
+    /// Read the complete lines added to `path` since it was last seen, all
+    /// of it for new or truncated files. A trailing partial line is left for
+    /// the next call. Returns `None` if nothing was added.
+    pub fn read_appended(&mut self, path: &Path) -> std::io::Result<Option<Appended>> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let seen = match self.seen.get(path) {
+            Some(seen) if seen.len <= len => *seen,
+            // New, or rewritten shorter than before: read from the start
+            _ => Seen {
+                len: 0,
+                lines: Some(0),
+            },
+        };
+        if seen.len == len {
+            return Ok(None);
+        }
+
+        let lines = match seen.lines {
+            Some(lines) => lines,
+            None => {
+                let mut prefix = Vec::new();
+                (&mut file).take(seen.len).read_to_end(&mut prefix)?;
+                bytecount(&prefix)
+            }
+        };
+        file.seek(SeekFrom::Start(seen.len))?;
+        let mut contents = Vec::new();
+        file.take(len - seen.len).read_to_end(&mut contents)?;
+        let Some(end) = contents.iter().rposition(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        contents.truncate(end + 1);
+        let added_lines = bytecount(&contents);
+        contents.pop();
+
+        self.seen.insert(
+            path.to_path_buf(),
+            Seen {
+                len: seen.len + end as u64 + 1,
+                lines: Some(lines + added_lines),
+            },
+        );
+        Ok(Some(Appended {
+            first_line: lines + 1,
+            offset: seen.len,
+            contents,
+        }))
+    }
+}
+
+/// Number of newlines in `bytes`
+fn bytecount(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_watcher_lock() {
+        let index_dir = tempfile::tempdir().unwrap();
+        assert!(!is_watched(index_dir.path()));
+        let lock = lock_index(index_dir.path()).unwrap();
+        assert!(is_watched(index_dir.path()));
+        assert!(lock_index(index_dir.path()).is_err());
+        drop(lock);
+        assert!(!is_watched(index_dir.path()));
+    }
+
+    #[test]
+    fn test_filter_hidden_and_outside_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let mut filter = PathFilter::new(root.path());
+        assert!(!filter.is_ignored(&root.path().join("src/main.rs")));
+        assert!(filter.is_ignored(&root.path().join(".hidden/file.txt")));
+        assert!(filter.is_ignored(&root.path().join("dir/.env")));
+        assert!(filter.is_ignored(Path::new("/somewhere/else.txt")));
+
+        filter.exclude(&root.path().join("index"));
+        assert!(filter.is_ignored(&root.path().join("index/gen-0000000001.bin")));
+
+        filter.max_depth(Some(1));
+        assert!(!filter.is_ignored(&root.path().join("README.md")));
+        assert!(filter.is_ignored(&root.path().join("src/main.rs")));
+    }
+
+    #[test]
+    fn test_filter_respects_gitignore_in_repos() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join(".git")).unwrap();
+        fs::create_dir_all(root.path().join("target/debug")).unwrap();
+        fs::create_dir_all(root.path().join("app/logs")).unwrap();
+        fs::write(
+            root.path().join(".gitignore"),
+            "target/\n*.log\n!keep.log\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("app/.gitignore"), "logs/\n").unwrap();
+
+        let mut filter = PathFilter::new(root.path());
+        assert!(filter.is_ignored(&root.path().join("target/debug/ss")));
+        assert!(filter.is_ignored(&root.path().join("app/trace.log")));
+        assert!(!filter.is_ignored(&root.path().join("app/keep.log")));
+        assert!(filter.is_ignored(&root.path().join("app/logs/today.txt")));
+        assert!(!filter.is_ignored(&root.path().join("app/main.rs")));
+    }
+
+    #[test]
+    fn test_filter_applies_exclude_patterns() {
+        let root = tempfile::tempdir().unwrap();
+        let mut filter = PathFilter::new(root.path());
+        filter
+            .exclude_patterns(&["node_modules/".to_string(), "*.tmp".to_string()])
+            .unwrap();
+        fs::create_dir_all(root.path().join("web/node_modules")).unwrap();
+        assert!(filter.is_ignored(&root.path().join("web/node_modules/pkg/index.js")));
+        assert!(filter.is_ignored(&root.path().join("scratch.tmp")));
+        assert!(!filter.is_ignored(&root.path().join("web/app.js")));
+    }
+
+    #[test]
+    fn test_filter_follows_hidden_and_ignore_options() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join(".git")).unwrap();
+        fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.path().join(".ignore"), "*.tmp\n").unwrap();
+        let rules = root.path().join("rules");
+        fs::write(&rules, "/data.csv\n").unwrap();
+
+        let mut filter = PathFilter::new(root.path());
+        filter
+            .scan_options(&ScanOptions {
+                hidden: true,
+                no_git_ignore: true,
+                ignore_files: vec![rules],
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(!filter.is_ignored(&root.path().join(".env")));
+        assert!(filter.is_ignored(&root.path().join(".git/HEAD")));
+        assert!(!filter.is_ignored(&root.path().join("trace.log")));
+        assert!(filter.is_ignored(&root.path().join("scratch.tmp")));
+        assert!(filter.is_ignored(&root.path().join("data.csv")));
+        assert!(!filter.is_ignored(&root.path().join("sub/data.csv")));
+
+        filter
+            .scan_options(&ScanOptions {
+                no_ignore: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(!filter.is_ignored(&root.path().join("scratch.tmp")));
+        assert!(filter.is_ignored(&root.path().join(".env")));
+    }
+
+    #[test]
+    fn test_filter_honours_sonic_ignore_and_global_file() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("notes")).unwrap();
+        fs::write(root.path().join(".ignore"), "*.bak\n").unwrap();
+        fs::write(
+            root.path().join("notes/.sonic-ignore"),
+            "drafts/\n!keep.bak\n",
+        )
+        .unwrap();
+        let global = root.path().join("global-ignore");
+        fs::write(&global, "todo.*\n").unwrap();
+
+        let mut filter = PathFilter::new(root.path());
+        filter
+            .scan_options(&ScanOptions {
+                global_ignore: Some(global),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(filter.is_ignored(&root.path().join("notes/drafts/idea.md")));
+        assert!(filter.is_ignored(&root.path().join("notes/old.bak")));
+        assert!(!filter.is_ignored(&root.path().join("notes/keep.bak")));
+        assert!(filter.is_ignored(&root.path().join("notes/todo.md")));
+        assert!(!filter.is_ignored(&root.path().join("notes/plan.md")));
+    }
+
+    #[test]
+    fn test_gitignore_ignored_outside_repos() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.path().join(".ignore"), "*.tmp\n").unwrap();
+
+        let mut filter = PathFilter::new(root.path());
+        assert!(!filter.is_ignored(&root.path().join("trace.log")));
+        assert!(filter.is_ignored(&root.path().join("scratch.tmp")));
+    }
+
+    #[test]
+    fn test_expand_paths_walks_new_directories() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("new/deeper")).unwrap();
+        fs::write(root.path().join("new/a.txt"), "a").unwrap();
+        fs::write(root.path().join("new/deeper/b.txt"), "b").unwrap();
+
+        let root_path = dunce::canonicalize(root.path()).unwrap();
+        let mut filter = PathFilter::new(&root_path);
+        let raw: BTreeSet<PathBuf> = [root_path.join("new"), root_path.join(".git/HEAD")]
+            .into_iter()
+            .collect();
+        let mut paths = expand_paths(&mut filter, raw);
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                root_path.join("new"),
+                root_path.join("new/a.txt"),
+                root_path.join("new/deeper"),
+                root_path.join("new/deeper/b.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_reads_appended_lines() {
+        let root = tempfile::tempdir().unwrap();
+        let log = root.path().join("app.log");
+        fs::write(&log, "boot\nready\n").unwrap();
+        let scan = scanner::scan_directory(root.path()).unwrap();
+        let log = scan.files[0].path.clone();
+        let mut tail = Tail::new(&scan.files);
+        assert_eq!(tail.read_appended(&log).unwrap(), None);
+
+        // Only complete lines are returned, numbered from the end of the old contents
+        fs::write(&log, "boot\nready\nerror: disk\npart").unwrap();
+        let appended = tail.read_appended(&log).unwrap().unwrap();
+        assert_eq!(appended.first_line, 3);
+        assert_eq!(appended.contents, b"error: disk");
+        let pattern = grep::build_pattern("error", &Default::default()).unwrap();
+        assert_eq!(appended.search(&pattern)[0].line_number, 3);
+        assert_eq!(appended.search(&pattern)[0].byte_offset(), 11);
+        fs::write(&log, "boot\nready\nerror: disk\npartial error\n").unwrap();
+        let appended = tail.read_appended(&log).unwrap().unwrap();
+        assert_eq!(
+            (appended.first_line, &appended.contents[..]),
+            (4, &b"partial error"[..])
+        );
+
+        // A truncated file is read again from the start
+        fs::write(&log, "error: fresh\n").unwrap();
+        assert_eq!(tail.read_appended(&log).unwrap().unwrap().first_line, 1);
+    }
+
+    #[test]
+    fn test_watch_applies_events() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("existing.txt"), "x").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let mut index = Index::from_scan(scanner::scan_directory(root.path()).unwrap());
+        index.save(index_dir.path()).unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let index_path = index_dir.path().to_path_buf();
+        let watcher_stop = Arc::clone(&stop);
+        let handle = std::thread::spawn(move || {
+            watch(
+                &index_path,
+                Duration::from_millis(50),
+                &watcher_stop,
+                |_, _| {},
+            )
+        });
+
+        // Give the watcher time to register before touching the tree
+        std::thread::sleep(Duration::from_millis(300));
+        fs::write(root.path().join("created.txt"), "new").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut seen = false;
+        while Instant::now() < deadline {
+            if Index::current_generation(index_dir.path()).unwrap() > Some(1) {
+                seen = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap().unwrap();
+
+        assert!(seen, "watcher never saved a new generation");
+        let index = Index::load(index_dir.path()).unwrap();
+        assert!(index.entries.iter().any(|e| e.name == "created.txt"));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::index::Index;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the index directory used when none is given
+pub const DEFAULT_INDEX_DIR: &str = ".sonic-search";
+
+/// Where commands run from `cwd` should keep their index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexLocation {
+    pub index_dir: PathBuf,
+    /// Root of the enclosing workspace, if `cwd` is inside one
+    pub workspace_root: Option<PathBuf>,
+}
+
+/// Resolve the index for commands run from `cwd` without `--index-dir`.
+///
+/// An existing index in `cwd` or any parent wins; otherwise the index
+/// belongs at the root of the enclosing workspace, so every subdirectory
+/// shares it. Outside a workspace the index lives in `cwd`.
+pub fn locate(cwd: &Path) -> IndexLocation {
+    let workspace_root = find_workspace_root(cwd);
+    if let Some(index_dir) = find_existing_index(cwd) {
+        return IndexLocation {
+            index_dir,
+            workspace_root,
+        };
+    }
+    let index_dir = match &workspace_root {
+        Some(root) if root != cwd => root.join(DEFAULT_INDEX_DIR),
+        _ => PathBuf::from(DEFAULT_INDEX_DIR),
+    };
+    IndexLocation {
+        index_dir,
+        workspace_root,
+    }
+}
+
+/// Nearest index directory in `start` or one of its parents
+pub fn find_existing_index(start: &Path) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let candidate = dir.join(DEFAULT_INDEX_DIR);
+        if Index::exists(&candidate) {
+            return Some(if dir == start {
+                PathBuf::from(DEFAULT_INDEX_DIR)
+            } else {
+                candidate
+            });
+        }
+    }
+    None
+}
+
+/// Root of the git repository, Cargo workspace or npm workspace containing
+/// `start`, whichever is nearest.
+///
+/// Member crates and packages don't count: their `Cargo.toml` or
+/// `package.json` only marks a root when it declares a workspace.
+pub fn find_workspace_root(start: &Path) -> Option<PathBuf> {
+    let home = dirs::home_dir();
+    start
+        .ancestors()
+        // A dotfiles repository in $HOME shouldn't turn it into a workspace
+        .take_while(|dir| home.as_deref() != Some(*dir))
+        .find(|dir| is_workspace_root(dir))
+        .map(Path::to_path_buf)
+}
+
+fn is_workspace_root(dir: &Path) -> bool {
+    dir.join(".git").exists() || is_cargo_workspace(dir) || is_npm_workspace(dir)
+}
+
+fn is_cargo_workspace(dir: &Path) -> bool {
+    fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .is_some_and(|manifest| manifest.contains_key("workspace"))
+}
+
+fn is_npm_workspace(dir: &Path) -> bool {
+    fs::read(dir.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<serde_json::Value>(&contents).ok())
+        .is_some_and(|manifest| manifest.get("workspaces").is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    #[test]
+    fn test_git_and_manifest_roots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = tmp.path().join("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("src/deep")).unwrap();
+        assert_eq!(
+            find_workspace_root(&repo.join("src/deep")),
+            Some(repo.clone())
+        );
+
+        // A Cargo workspace nested in the repo is nearer; its members aren't roots
+        let rust = repo.join("rust");
+        fs::create_dir_all(rust.join("crates/a/src")).unwrap();
+        fs::write(
+            rust.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            rust.join("crates/a/Cargo.toml"),
+            "[package]\nname = \"a\"\n",
+        )
+        .unwrap();
+        assert_eq!(find_workspace_root(&rust.join("crates/a/src")), Some(rust));
+
+        let web = tmp.path().join("web");
+        fs::create_dir_all(web.join("packages/ui")).unwrap();
+        fs::write(
+            web.join("package.json"),
+            r#"{"workspaces": ["packages/*"]}"#,
+        )
+        .unwrap();
+        fs::write(web.join("packages/ui/package.json"), r#"{"name": "ui"}"#).unwrap();
+        assert_eq!(find_workspace_root(&web.join("packages/ui")), Some(web));
+
+        let loose = tmp.path().join("loose");
+        fs::create_dir_all(&loose).unwrap();
+        assert_eq!(find_workspace_root(&loose), None);
+    }
+
+    #[test]
+    fn test_locate_prefers_existing_index() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = tmp.path().join("repo");
+        let sub = repo.join("src/module");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+
+        // No index yet: it belongs at the workspace root
+        let location = locate(&sub);
+        assert_eq!(location.index_dir, repo.join(DEFAULT_INDEX_DIR));
+        assert_eq!(location.workspace_root, Some(repo.clone()));
+        assert_eq!(locate(&repo).index_dir, PathBuf::from(DEFAULT_INDEX_DIR));
+
+        // An index in a parent is found from any subdirectory
+        let src = repo.join("src");
+        let mut index = Index::from_scan(scanner::scan_directory(&src).unwrap());
+        index.save(&src.join(DEFAULT_INDEX_DIR)).unwrap();
+        assert_eq!(locate(&sub).index_dir, src.join(DEFAULT_INDEX_DIR));
+        assert_eq!(locate(&src).index_dir, PathBuf::from(DEFAULT_INDEX_DIR));
+    }
+}